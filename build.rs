@@ -14,6 +14,7 @@ fn main() {
         [
             "pin",
             "menu",
+            "paper",
             "go-next",
             "clock-alt",
             "speaker-0",
@@ -22,7 +23,10 @@ fn main() {
             "speaker-3",
             "speaker-4",
             "view-more",
+            "user-trash",
             "down-small",
+            "chain-link",
+            "go-previous",
             "image-round",
             "paper-plane",
             "info-outline",
@@ -1,12 +1,18 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use adw::{NavigationSplitView, prelude::*};
-use chrono::{DateTime, Utc};
-use gtk::{gio, glib, pango};
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use gtk::{gdk, gio, glib, pango};
 use indexmap::IndexMap;
 use relm4::{
     abstractions::Toaster,
-    actions::{AccelsPlus, RelmAction, RelmActionGroup},
+    actions::{ActionName, RelmAction, RelmActionGroup},
     main_application,
     prelude::*,
 };
@@ -14,23 +20,96 @@ use strum::{AsRefStr, EnumString};
 use tokio::time;
 use uuid::Uuid;
 use wacore::types::{message::MessageInfo, presence::ReceiptType};
-use waproto::whatsapp::Message;
+use waproto::whatsapp::{
+    Message,
+    message::{PollUpdateMessage, ReactionMessage, pin_in_chat_message, protocol_message},
+};
 
+#[cfg(feature = "message-injector")]
+use crate::modals::message_injector::{MessageInjector, MessageInjectorOutput, Sample};
 use crate::{
-    DATA_DIR,
+    DATA_DIR, background, cli,
     components::{
-        ChatList, ChatListInput, ChatListOutput, ChatView, ChatViewInput, ChatViewOutput, Login,
-        LoginInput, LoginOutput,
+        ChatList, ChatListInput, ChatListOutput, ChatView, ChatViewInput, ChatViewOutput,
+        ConnectionState, Login, LoginInput, LoginOutput, StatusList, StatusListInput,
+        StatusListOutput,
     },
     config::{APP_ID, PROFILE},
-    i18n,
-    modals::{about::AboutDialog, shortcuts::ShortcutsDialog},
-    session::{Client, ClientInput, ClientOutput, SyncedMessage},
-    state::{Chat, ChatMessage, MessageStatus},
-    store::{Contact, Database},
-    utils::format_lid_as_number,
+    i18n, i18n_f, launcher,
+    modals::{
+        about::AboutDialog,
+        attachment_warning::open_attachment,
+        bookmarks::{BookmarksDialog, BookmarksInit, BookmarksOutput},
+        call::{CallDialog, CallDialogInit, CallDialogInput, CallDialogOutput},
+        chat_gallery::{ChatGallery, ChatGalleryInit, ChatGalleryOutput},
+        chat_switcher::{
+            ChatSwitcher, ChatSwitcherInit, ChatSwitcherInput, ChatSwitcherOutput,
+            parse_phone_number,
+        },
+        image_viewer::{ImageViewer, ImageViewerInit, ImageViewerOutput},
+        join_requests::{
+            JoinRequestsDialog, JoinRequestsInit, JoinRequestsInput, JoinRequestsOutput,
+        },
+        preferences::PreferencesDialog,
+        shortcuts::{ShortcutsDialog, shortcut_sections},
+    },
+    ni18n, report, screencast,
+    session::{
+        AvatarCache, Client, ClientError, ClientInput, ClientOutput, IngestionTicket, ReceiptError,
+        SyncedMessage,
+    },
+    sound::{self, Sound},
+    state::{
+        AutoDownload, Chat, ChatKind, ChatMessage, GroupEvent, LinkPreview, Media, Membership,
+        MessageActor, MessageContent, MessageKind, MessageStatus, Quote, STATUS_JID, StatusUpdate,
+        TextContent, is_status_jid,
+    },
+    store::{Contact, Database, JoinRequest, PendingReceipt},
+    thumbnails,
+    utils::{
+        MAX_UNREAD_COUNT, OnboardingStep, TimestampMode, format_badge_count, format_date_label,
+        format_export_timestamp, format_jid, format_lid_as_number, icon_or_fallback,
+        is_low_on_space, media_cache_dir, remove_stale_files, skip_onboarding_if_linked,
+    },
 };
 
+/// ID of the incoming call notification.
+const CALL_NOTIFICATION_ID: &str = "incoming-call";
+/// ID of the notification posted from the onboarding.
+const TEST_NOTIFICATION_ID: &str = "test";
+/// Prefix of the notification IDs for messages, followed by the chat JID.
+const MESSAGE_NOTIFICATION_PREFIX: &str = "message-";
+
+/// How long media written for other apps to open is kept.
+const MEDIA_CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Longest time to ring for an incoming call, in case its end is missed.
+const RING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a change to a message that isn't stored yet is kept around.
+const PENDING_REFERENCE_TTL: Duration = Duration::from_secs(2 * 60);
+/// Maximum number of messages to keep early changes for, dropping the oldest.
+const MAX_PENDING_REFERENCES: usize = 512;
+
+/// How long to wait before marking a chat as read, so messages arriving in
+/// a burst are marked in one batch.
+const MARK_READ_DELAY: Duration = Duration::from_millis(500);
+
+/// How long to wait before sending read receipts that failed again, doubled
+/// after every failure.
+const RECEIPT_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Failures after which read receipts are given up on, as they'd likely
+/// never go through (like for a JID that can't be parsed).
+const MAX_RECEIPT_ATTEMPTS: u32 = 8;
+
+/// How long unread messages must stay unchanged before their total is shown,
+/// so bursts (like during sync) update the title once.
+const UNREAD_TOTAL_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest wait for a sync to complete before showing the chats anyway,
+/// as the phone may stop sending history halfway.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(3 * 60);
+
 pub struct Application {
     /// Papo's own database.
     db: Arc<Database>,
@@ -46,18 +125,318 @@ pub struct Application {
     client: AsyncController<Client>,
     /// Toaster overlay.
     toaster: Toaster,
+    /// Settings of the app, kept to follow changes to them.
+    settings: gio::Settings,
     /// JID from the connected user.
     user_jid: Option<String>,
     /// Chat list component.
     chat_list: AsyncController<ChatList>,
     /// Chat view component.
     chat_view: AsyncController<ChatView>,
+    /// JID of the open chat.
+    open_chat: Option<String>,
+    /// Text of the messages to forward to the chat picked in the chat switcher.
+    forwarding: Option<Vec<String>>,
+    /// Watcher of screen sharing, kept alive while the app runs.
+    screencast: Option<gio::DBusProxy>,
     /// The `SplitView` widget from the session page.
     split_view: NavigationSplitView,
+    /// Call in progress, placed or answered, while its dialog is open.
+    call_dialog: Option<Controller<CallDialog>>,
+    /// IDs of the messages whose media is being downloaded.
+    downloading: HashSet<String>,
+    /// Alternate (PN or LID) JIDs mapped to the JID of their chat.
+    jid_aliases: JidAliases,
+    /// Status list component.
+    status_list: AsyncController<StatusList>,
+    /// Media, links and documents of a chat, while they're shown.
+    chat_gallery: Option<Controller<ChatGallery>>,
+    /// Image viewer, while it's open.
+    image_viewer: Option<Controller<ImageViewer>>,
+    /// Whether the disk with the app data is low on free space.
+    low_on_space: bool,
+    /// Chat to open once the chats are loaded, as asked from the command line.
+    pending_chat: Option<String>,
+    /// Chat to mark as read once the window is focused with it open.
+    pending_read: Option<String>,
     /// Page session view is displaying.
     session_page: AppSessionPage,
+    /// Total of unread messages, as shown in the window title.
+    unread_total: UnreadTotal,
+    /// What contacts see of the connected user, besides the push name.
+    user_profile: UserProfile,
+    /// Chat switcher, while it's open.
+    chat_switcher: Option<Controller<ChatSwitcher>>,
+    /// Incoming call awaiting an answer.
+    incoming_call: Option<IncomingCall>,
+    /// Highest sort key given to a chat, to move the next one above it.
+    last_sort_key: i64,
+    /// Deadline of the sync in progress.
+    sync_deadline: SyncDeadline,
+    /// Whether Do Not Disturb is on.
+    do_not_disturb: bool,
+    /// Phone number asked from the command line, while it's being checked.
+    opening_number: Option<String>,
     /// Push name from the connected user.
     user_push_name: Option<String>,
+    /// Read receipts being sent or waiting to be retried.
+    receipt_retries: ReceiptRetries,
+    /// Bookmarks dialog, while it's open.
+    bookmarks_dialog: Option<Controller<BookmarksDialog>>,
+    /// When focus mode turns itself off, if it was turned on for some time.
+    focus_mode_until: Option<Instant>,
+    /// Message injector, while it's open.
+    #[cfg(feature = "message-injector")]
+    message_injector: Option<Controller<MessageInjector>>,
+    /// Action toggling focus mode, hiding unread counts and holding the chats
+    /// and notifications still, for this session only. Its state is whether
+    /// focus mode is on.
+    focus_mode_action: gio::SimpleAction,
+    /// Whether the screen is being shared or recorded.
+    screencast_active: bool,
+    /// Edits and receipts for messages that aren't stored yet.
+    pending_references: PendingReferences,
+    /// People asking to join a group, with the group JID, while they're shown.
+    join_requests_dialog: Option<(String, Controller<JoinRequestsDialog>)>,
+}
+
+/// Coalesces changes to the unread messages into a single update of their
+/// total, once the changes have settled.
+#[derive(Debug, Default)]
+struct UnreadTotal {
+    /// Count shown on the launcher badge, of the chats that aren't muted.
+    badge: Option<usize>,
+    /// Total currently shown.
+    shown: Option<usize>,
+    /// Whether a refresh of the total is scheduled.
+    scheduled: bool,
+    /// When the last change not shown yet happened.
+    changed_at: Option<Instant>,
+}
+
+impl UnreadTotal {
+    /// Note a change at `now`. Returns whether a refresh needs to be scheduled.
+    fn change(&mut self, now: Instant) -> bool {
+        self.changed_at = Some(now);
+        !std::mem::replace(&mut self.scheduled, true)
+    }
+
+    /// Check a scheduled refresh at `now`. Returns how much longer to wait
+    /// for the changes to settle, if they haven't yet.
+    fn settle(&mut self, now: Instant) -> Option<Duration> {
+        let wait = self
+            .changed_at
+            .map(|t| UNREAD_TOTAL_DELAY.saturating_sub(now.saturating_duration_since(t)))
+            .filter(|wait| !wait.is_zero());
+        self.scheduled = wait.is_some();
+        wait
+    }
+
+    /// Store the recomputed total. Returns it if it differs from the one shown.
+    fn update(&mut self, total: usize) -> Option<usize> {
+        self.changed_at = None;
+        (self.shown.replace(total) != Some(total)).then_some(total)
+    }
+
+    /// Store the recomputed badge count. Returns it if it differs from the one shown.
+    fn update_badge(&mut self, count: usize) -> Option<usize> {
+        (self.badge.replace(count) != Some(count)).then_some(count)
+    }
+}
+
+/// Deadline of the sync in progress, telling apart those of earlier syncs.
+#[derive(Debug, Default)]
+struct SyncDeadline(u32);
+
+impl SyncDeadline {
+    /// Start the deadline of a new sync. Returns the attempt to check once
+    /// it elapses.
+    fn start(&mut self) -> u32 {
+        self.0 = self.0.wrapping_add(1);
+        self.0
+    }
+
+    /// Checks if the deadline of `attempt` is the one of the sync in progress.
+    fn is_current(&self, attempt: u32) -> bool {
+        self.0 == attempt
+    }
+}
+
+/// Changes that arrived before the message they're for, like while syncing,
+/// replayed once it's stored.
+#[derive(Debug, Default)]
+struct PendingReferences(HashMap<String, PendingReference>);
+
+/// Changes to a message that isn't stored yet.
+#[derive(Debug)]
+struct PendingReference {
+    /// Content from the latest edit, with who made it.
+    edit: Option<(String, MessageActor)>,
+    /// Furthest status from the receipts.
+    status: Option<MessageStatus>,
+    /// When the first change arrived.
+    received: Instant,
+}
+
+impl PendingReferences {
+    /// Get the changes kept for a message, dropping expired ones and making
+    /// room for it if there are too many.
+    fn entry(&mut self, msg_id: String) -> &mut PendingReference {
+        self.0
+            .retain(|_, pending| pending.received.elapsed() < PENDING_REFERENCE_TTL);
+        if self.0.len() >= MAX_PENDING_REFERENCES
+            && !self.0.contains_key(&msg_id)
+            && let Some(oldest) = self
+                .0
+                .iter()
+                .min_by_key(|(_, pending)| pending.received)
+                .map(|(id, _)| id.clone())
+        {
+            self.0.remove(&oldest);
+        }
+
+        self.0.entry(msg_id).or_insert_with(|| PendingReference {
+            edit: None,
+            status: None,
+            received: Instant::now(),
+        })
+    }
+
+    /// Take the changes kept for a message, if they haven't expired.
+    fn take(&mut self, msg_id: &str) -> Option<PendingReference> {
+        self.0
+            .remove(msg_id)
+            .filter(|pending| pending.received.elapsed() < PENDING_REFERENCE_TTL)
+    }
+}
+
+impl PendingReference {
+    /// Keep the status of a receipt, unless a further one arrived first.
+    fn receipt(&mut self, status: MessageStatus) {
+        self.status = Some(self.status.map_or(status, |kept| kept.max(status)));
+    }
+
+    /// Apply the changes to the message they were kept for, skipping edits
+    /// by someone other than its sender.
+    fn apply(self, message: &mut ChatMessage) {
+        if let Some((content, actor)) = self.edit
+            && actor.sent(message)
+        {
+            message.content = content;
+            message.edited = true;
+        }
+        // Receipts are only sent for the user's own messages.
+        if let Some(status) = self.status
+            && message.outgoing
+        {
+            message.status = message.status.max(status);
+        }
+    }
+}
+
+/// Alternate (PN or LID) JIDs mapped to the JID of their chat.
+#[derive(Debug, Default)]
+struct JidAliases(HashMap<String, String>);
+
+impl JidAliases {
+    /// Resolve the JID of the chat a message belongs to, preferring
+    /// existing `chats` known by an alternate (PN or LID) JID.
+    fn resolve(&self, chats: &[Chat], chat_jid: &str, alt_jid: Option<&str>) -> String {
+        if chats.iter().any(|c| c.jid == chat_jid) {
+            return chat_jid.to_string();
+        }
+
+        if let Some(jid) = self.0.get(chat_jid) {
+            return jid.clone();
+        }
+
+        if let Some(alt_jid) = alt_jid {
+            let alt_jid = self.0.get(alt_jid).map_or(alt_jid, String::as_str);
+            if chats.iter().any(|c| c.jid == alt_jid) {
+                return alt_jid.to_string();
+            }
+        }
+
+        chat_jid.to_string()
+    }
+
+    /// Remember that messages addressed to `alias` belong to the chat `jid`,
+    /// returning whether it wasn't known yet.
+    fn insert(&mut self, alias: String, jid: String) -> bool {
+        if self.0.get(&alias) == Some(&jid) {
+            return false;
+        }
+
+        self.0.insert(alias, jid);
+        true
+    }
+}
+
+/// Read receipts being sent or waiting to be retried, so that each is only
+/// sent once at a time.
+#[derive(Debug, Default)]
+struct ReceiptRetries {
+    /// Receipts sent, until the client reports on them.
+    in_flight: HashSet<Uuid>,
+    /// Receipts waiting for their retry after failing.
+    backing_off: HashSet<Uuid>,
+}
+
+impl ReceiptRetries {
+    /// Start sending a receipt, unless it's being sent already. Sending it
+    /// cancels the retry it was waiting for.
+    fn start(&mut self, id: Uuid) -> bool {
+        self.backing_off.remove(&id);
+        self.in_flight.insert(id)
+    }
+
+    /// Stop tracking a receipt the client reported on.
+    fn finish(&mut self, id: Uuid) {
+        self.in_flight.remove(&id);
+    }
+
+    /// Wait to retry a receipt that failed.
+    fn back_off(&mut self, id: Uuid) {
+        self.backing_off.insert(id);
+    }
+
+    /// Whether the retry of a receipt is still due, and not cancelled by
+    /// sending it earlier.
+    fn retry_due(&mut self, id: Uuid) -> bool {
+        self.backing_off.remove(&id)
+    }
+}
+
+/// Profile of the connected user, as previewed from local data.
+#[derive(Debug, Default)]
+struct UserProfile {
+    /// About text.
+    about: Option<String>,
+    /// Avatar, if cached.
+    avatar: Option<gdk::Texture>,
+    /// Phone number JID.
+    phone_jid: Option<String>,
+}
+
+impl UserProfile {
+    /// Phone number, formatted to be shown.
+    fn phone_number(&self) -> Option<String> {
+        self.phone_jid.as_deref().map(format_lid_as_number)
+    }
+}
+
+/// An incoming call awaiting an answer.
+struct IncomingCall {
+    /// Dialog asking to answer the call.
+    dialog: adw::AlertDialog,
+    /// Call ID.
+    call_id: String,
+    /// JID of the caller.
+    from_jid: String,
+    /// Whether it's a video call.
+    is_video: bool,
+    /// When the call was offered.
+    timestamp: DateTime<Utc>,
 }
 
 #[derive(Clone, Copy, Debug, AsRefStr, PartialEq, EnumString)]
@@ -69,6 +448,8 @@ enum AppPage {
     Session,
     /// Loading/fetching page.
     Fetching,
+    /// First sync after pairing, until chats show up.
+    Syncing,
     /// Error page.
     Error,
 }
@@ -111,8 +492,19 @@ pub enum AppMsg {
     LoggedOut,
     /// Reset the client session.
     ResetSession,
+    /// Copy the current error and debug info to the clipboard.
+    CopyErrorDetails,
     /// Client has been disconnected.
     Disconnected,
+    /// Try to connect again after being disconnected.
+    Reconnect,
+    /// Profile of the connected user fetched.
+    OwnProfile {
+        phone_jid: Option<String>,
+        about: Option<String>,
+    },
+    /// Copy the phone number of the connected user.
+    CopyOwnNumber,
     /// Self push name updated.
     SelfPushNameUpdated {
         push_name: String,
@@ -139,6 +531,36 @@ pub enum AppMsg {
     ChatSelected(String),
     /// Mark a chat as read.
     MarkChatRead(String),
+    /// Mark a chat as read right away, as asked from the chat list.
+    MarkChatReadNow(String),
+    /// Read receipts have been sent, or failed to.
+    MarkedRead {
+        id: Uuid,
+        result: Result<(), ReceiptError>,
+    },
+    /// Archive a chat, or unarchive it to undo archiving.
+    ArchiveChat {
+        jid: String,
+        archived: bool,
+    },
+    /// Ask to delete a chat from this device.
+    DeleteChat(String),
+    /// Delete a chat from this device, keeping the data kept only here if asked.
+    ConfirmDeleteChat {
+        jid: String,
+        keep_local_data: bool,
+    },
+    /// The window has been focused.
+    WindowFocused,
+    /// Show a notification in the window.
+    ShowToast(String),
+    /// A status update has been viewed.
+    StatusViewed {
+        id: String,
+        sender_jid: String,
+    },
+    /// Status updates posted before this time have expired.
+    StatusesExpired(DateTime<Utc>),
 
     /// Avatar updated for a chat.
     AvatarUpdate {
@@ -169,12 +591,33 @@ pub enum AppMsg {
         chat_jid: String,
         msg_id: Uuid,
         status: MessageStatus,
+        /// Preview of its link attached while sending.
+        preview: Option<LinkPreview>,
     },
 
     /// New message received.
     MessageReceived {
         info: Box<MessageInfo>,
         message: Box<Message>,
+        /// Holds background work back until the message is handled.
+        ticket: IngestionTicket,
+    },
+    /// Message received that couldn't be decrypted.
+    UndecryptableMessage {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// A group changed while connected.
+    GroupChanged {
+        chat_jid: String,
+        id: String,
+        timestamp: DateTime<Utc>,
+        event: GroupEvent,
+    },
+    /// All the participants of a group have been fetched.
+    ParticipantsFetched {
+        jid: String,
+        participants: Vec<String>,
     },
 
     /// Send a text message.
@@ -183,18 +626,295 @@ pub enum AppMsg {
         text: String,
         /// Message recipient.
         recipient: String,
+        /// Message replied to.
+        quote: Option<Quote>,
+    },
+    /// Open a chat with a shared contact, creating it if needed.
+    MessageContact {
+        jid: String,
+        name: String,
+    },
+    /// Save a shared contact.
+    SaveContact {
+        jid: String,
+        name: String,
+        phone_number: Option<String>,
+    },
+    /// Show the chat switcher.
+    ShowChatSwitcher,
+    /// Open a chat picked in the chat switcher.
+    ChatSwitched(String),
+    /// The chat switcher has been closed.
+    ChatSwitcherClosed,
+    /// Pick a chat to send the text of messages to.
+    ForwardMessages(Vec<String>),
+    /// Open a chat with a phone number from the chat switcher, if it's on `WhatsApp`.
+    MessageNumber(String),
+    /// A phone number has been checked.
+    NumberChecked {
+        phone_number: String,
+        result: Result<Option<String>, String>,
+    },
+    /// Open a chat by JID or phone number, as asked from the command line.
+    OpenChat(String),
+    /// Show when a message was sent and how far it got.
+    ShowMessageInfo {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Copy the image of a message to the clipboard.
+    CopyImage {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Ask where to save the media of a message.
+    SaveMedia {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Download the full media of a message.
+    DownloadMedia {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// The full media of a message has been downloaded.
+    MediaDownloaded {
+        chat_jid: String,
+        msg_id: String,
+        data: Vec<u8>,
+    },
+    /// The full media of a message couldn't be downloaded.
+    MediaDownloadFailed {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Open the media of a message with an app picked for it.
+    OpenMediaWith {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Media has been saved to a file, or failed to.
+    MediaSaved(Result<(), String>),
+    /// View the image of a message, among the other images in its chat.
+    ViewImage {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// The image viewer has been closed.
+    ImageViewerClosed,
+    /// Show the media, links and documents sent in a chat.
+    ShowChatGallery {
+        jid: String,
+        name: String,
+    },
+    /// The chat gallery has been closed.
+    ChatGalleryClosed,
+    /// Show the people asking to join a group.
+    ShowJoinRequests {
+        jid: String,
+        name: String,
+    },
+    /// People asked to join a group.
+    JoinRequested {
+        chat_jid: String,
+        participants: Vec<String>,
+        timestamp: DateTime<Utc>,
+    },
+    /// Requests to join a group were withdrawn, or resolved by another admin.
+    JoinRequestsWithdrawn {
+        chat_jid: String,
+        participants: Vec<String>,
+    },
+    /// The people asking to join a group have been fetched.
+    JoinRequestsFetched {
+        chat_jid: String,
+        requests: Vec<JoinRequest>,
+    },
+    /// Approve or reject requests to join a group.
+    ResolveJoinRequests {
+        chat_jid: String,
+        participants: Vec<String>,
+        approve: bool,
+    },
+    /// Requests to join a group have been approved or rejected.
+    JoinRequestsResolved {
+        chat_jid: String,
+        participants: Vec<String>,
+        approved: bool,
+    },
+    /// Requests to join a group couldn't be approved or rejected.
+    JoinRequestsFailed(String),
+    /// The join requests dialog has been closed.
+    JoinRequestsClosed,
+    /// Call the other side of a private chat.
+    StartCall {
+        jid: String,
+        is_video: bool,
+    },
+    /// Outgoing call placed, ringing on the other side.
+    CallStarted {
+        call_id: String,
+        jid: String,
+        is_video: bool,
+    },
+    /// Outgoing call couldn't be placed.
+    CallFailed(String),
+    /// Call answered, by the other side or by us.
+    CallAccepted(String),
+    /// Call ended, declined or cancelled.
+    CallEnded(String),
+    /// Hang up a call from its dialog.
+    HangUp(String),
+    /// The call dialog has been closed.
+    CallDialogClosed,
+    /// Ask to clear the history of a chat.
+    ClearHistory {
+        jid: String,
+        name: String,
+    },
+    /// Delete the messages of a chat, keeping them from syncing again.
+    ConfirmClearHistory(String),
+    /// Let the cleared messages of a chat sync again.
+    RestoreHistory(String),
+    /// Ask for a note and bookmark a message on this device.
+    BookmarkMessage {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Bookmark a message on this device.
+    SaveBookmark {
+        chat_jid: String,
+        msg_id: String,
+        note: Option<String>,
+    },
+    /// Reply to a message in the open chat.
+    ReplyToMessage {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Jump to the message quoted by a reply in the open chat.
+    GoToQuoted {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Select a message in the open chat, or unselect it.
+    SelectMessage {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Bookmark messages on this device, without a note.
+    BookmarkMessages {
+        chat_jid: String,
+        msg_ids: Vec<String>,
+    },
+    /// Ask to delete messages from this device.
+    DeleteMessages {
+        chat_jid: String,
+        msg_ids: Vec<String>,
+    },
+    /// Delete messages from this device.
+    ConfirmDeleteMessages {
+        chat_jid: String,
+        msg_ids: Vec<String>,
+    },
+    /// Show the messages bookmarked on this device.
+    ShowBookmarks,
+    /// Jump to a bookmarked message.
+    OpenBookmark {
+        chat_jid: String,
+        msg_id: String,
+    },
+    /// Ask how to export the bookmarks.
+    ExportBookmarks,
+    /// Ask where to export the bookmarks.
+    ChooseBookmarksFile,
+    /// Export the bookmarks to a path.
+    SaveBookmarks(PathBuf),
+    /// The bookmarks dialog has been closed.
+    BookmarksClosed,
+    /// Show the dialog injecting synthetic messages.
+    #[cfg(feature = "message-injector")]
+    ShowMessageInjector,
+    /// Inject a synthetic message into the open chat.
+    #[cfg(feature = "message-injector")]
+    InjectMessage(Sample),
+    /// The message injector has been closed.
+    #[cfg(feature = "message-injector")]
+    MessageInjectorClosed,
+    /// Move a chat to where another is, (un)pinning it to match.
+    MoveChat {
+        jid: String,
+        target_jid: String,
+    },
+    /// The screen started or stopped being shared or recorded.
+    ScreencastChanged(bool),
+    /// Turn Do Not Disturb on until turned off, or off.
+    SetDoNotDisturb(bool),
+    /// Turn Do Not Disturb on for some minutes.
+    DoNotDisturbFor(u32),
+    /// Do Not Disturb has been turned on or off.
+    DoNotDisturbChanged,
+    /// Turn focus mode on until turned off, or off.
+    SetFocusMode(bool),
+    /// Turn focus mode on for some minutes.
+    FocusModeFor(u32),
+    /// Post a notification from the onboarding, asking for the permission to.
+    TestNotification,
+    /// The onboarding notification has been seen and clicked.
+    NotificationsShown,
+    /// Ask to keep running in the background, if sandboxed.
+    RequestBackground,
+    /// Running in the background has been allowed or not.
+    BackgroundAllowed(bool),
+    /// Ask where to save a problem report.
+    ReportProblem,
+    /// Save a problem report to a path.
+    SaveReport(PathBuf),
+    /// Pin or unpin a message for everyone in its chat.
+    SetMessagePinned {
+        chat_jid: String,
+        msg_id: String,
+        pinned: bool,
+    },
+    /// Toggle our vote for a poll option.
+    VotePoll {
+        chat_jid: String,
+        msg_id: String,
+        option: String,
+    },
+    /// React to a message, or remove our reaction with an empty `emoji`.
+    React {
+        chat_jid: String,
+        msg_id: String,
+        emoji: String,
+    },
+
+    /// Incoming call offer.
+    CallOffer {
+        call_id: String,
+        from_jid: String,
+        is_video: bool,
+    },
+    /// Answer an incoming call.
+    AnswerCall {
+        call_id: String,
+        accept: bool,
     },
 
     /// Chat synced from history.
     ChatSynced {
         jid: String,
         name: Option<String>,
+        alt_jid: Option<String>,
         pinned: bool,
         archived: bool,
         unread_count: Option<u32>,
         participants: Vec<(String, Option<String>)>,
+        admins: Vec<String>,
         mute_end_time: Option<u64>,
         last_message_time: Option<u64>,
+        participant_count: Option<u32>,
+        modified_at: Option<u64>,
     },
     /// Sync completed, fetch avatars for chats.
     SyncCompleted {
@@ -207,6 +927,8 @@ pub enum AppMsg {
         chat_jid: String,
         /// Synced messages.
         messages: Vec<SyncedMessage>,
+        /// Holds background work back until the messages are handled.
+        ticket: IngestionTicket,
     },
 
     /// Chat property updated (pin, mute, archive).
@@ -217,19 +939,27 @@ pub enum AppMsg {
         pinned: Option<bool>,
         /// Whether the chat is muted.
         muted: Option<bool>,
+        /// When the mute ends, as sent by WhatsApp.
+        mute_end: Option<i64>,
         /// Whether the chat is archived.
         archived: Option<bool>,
     },
+    /// Chats started syncing.
+    Syncing,
     /// History sync completed.
     HistorySyncCompleted,
     /// Offline sync completed.
     OfflineSyncCompleted,
+    /// The launcher badge has been turned on or off.
+    LauncherBadgeChanged,
+    /// Check whether the disk with the app data is low on free space.
+    CheckDiskSpace,
 
     Unknown,
-    /// Error occurred.
-    Error {
-        message: String,
-    },
+    /// The client failed.
+    Error(ClientError),
+    /// The window has been closed, hide it or quit.
+    CloseWindow,
     /// Quit the application.
     Quit,
 }
@@ -245,941 +975,4617 @@ pub enum AppCmd {
         pinned: bool,
         archived: bool,
         participants: Vec<(String, Option<String>)>,
+        mute_end_time: Option<u64>,
         last_message_time: Option<u64>,
+        participant_count: Option<u32>,
     },
     /// Process messages sync from history (background task).
     ProcessMessagesSync {
         chat_jid: String,
         is_group: bool,
         messages: Vec<SyncedMessage>,
+        ticket: IngestionTicket,
     },
+    /// Problem report has been saved (or failed to).
+    ReportSaved(io::Result<()>),
+    /// Bookmarks have been exported (or failed to).
+    BookmarksExported(io::Result<()>),
+    /// Mark the pending chat as read, if it can be seen.
+    FlushPendingRead,
+    /// Show the total of unread messages, once changes to them settle.
+    RefreshUnreadTotal,
+    /// Stop ringing for a call that has been ringing for too long.
+    StopRinging { call_id: String },
+    /// Turn Do Not Disturb off if the time it was on for has elapsed.
+    DoNotDisturbExpired,
+    /// A sync didn't complete in time.
+    SyncTimedOut(u32),
+    /// Turn focus mode off if the time it was on for has elapsed.
+    FocusModeExpired,
+    /// Send read receipts kept from before, like on reconnecting.
+    RetryReceipts(Vec<PendingReceipt>),
+    /// The wait to retry read receipts that failed is over.
+    ReceiptRetryDue(PendingReceipt),
 }
 
 impl Application {
-    fn add_chat(&mut self, chat: Chat) {
-        // Insert the chat into our cached list.
-        self.chats.push(chat.clone());
-
-        // Sort all our chats.
+    /// Sort the chats as shown in the list: pinned first, in their manual order.
+    fn sort_chats(&mut self) {
         self.chats.sort_by(|a, b| {
             b.pinned
                 .cmp(&a.pinned)
-                .then_with(|| b.last_message_time.cmp(&a.last_message_time))
+                .then_with(|| a.pin_order.cmp(&b.pin_order))
+                .then_with(|| b.sort_key.cmp(&a.sort_key))
+                .then_with(|| a.jid.cmp(&b.jid))
         });
+    }
 
-        // Save the chat in the database.
-        let chat_clone = chat.clone();
+    /// Give a chat a sort key above all others, moving it to the top.
+    fn bump_chat(&mut self, jid: &str) {
+        // Follow the clock, so chats added with their last message time stay below.
+        self.last_sort_key = (self.last_sort_key + 1).max(Utc::now().timestamp_millis());
+        let sort_key = self.last_sort_key;
+
+        if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) {
+            chat.sort_key = sort_key;
+        }
+        self.sort_chats();
+
+        let db = Arc::clone(&self.db);
+        let jid = jid.to_string();
         relm4::spawn(async move {
-            if let Err(e) = chat_clone.save().await {
-                tracing::error!("Failed to save chat: {}", e);
+            if let Err(e) = db.raise_chat_sort_key(&jid, sort_key).await {
+                tracing::error!("Failed to move chat {jid} to the top: {e}");
             }
         });
-
-        // Add the chat in the chat list.
-        self.chat_list
-            .emit(ChatListInput::AddChat { chat, at_top: true });
     }
 
-    fn add_message(&mut self, chat_jid: &str, message: ChatMessage) {
-        // Check if the message's chat is a group.
-        let is_group = chat_jid.ends_with("@g.us");
+    /// Get the display name of a JID from chats or contacts.
+    async fn get_display_name(&self, jid: &str) -> String {
+        if let Some(chat) = self.chats.iter().find(|c| c.jid == jid) {
+            return chat.get_name_or_number();
+        }
 
-        // Create a new chat if it doesn't exists.
-        if !self.chats.iter().any(|c| c.jid == chat_jid) {
-            let name = if is_group {
-                format!("{} {}", i18n!("Group"), &chat_jid[..8])
-            } else if self.user_jid.as_ref().is_some_and(|u_j| chat_jid == u_j) {
-                i18n!("You")
-            } else {
-                message
-                    .sender_name
-                    .clone()
-                    .unwrap_or_else(|| format_lid_as_number(chat_jid))
-            };
+        match self.db.get_contact(jid).await {
+            Ok(Some(contact)) => contact
+                .name
+                .or(contact.push_name)
+                .unwrap_or_else(|| format_lid_as_number(jid)),
+            _ => format_lid_as_number(jid),
+        }
+    }
 
-            self.add_chat(Chat {
-                jid: chat_jid.to_string(),
-                name,
-                muted: false,
-                pinned: false,
-                archived: false,
-                available: None,
-                last_seen: None,
-                avatar_path: None,
-                participants: HashMap::new(),
-                last_message_time: message.timestamp,
+    /// Check if the user is an admin of a group.
+    async fn is_group_admin(&self, jid: &str) -> bool {
+        self.db
+            .is_group_admin(jid, &self.own_jids())
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Failed to check the admins of {jid}: {e}");
+                false
+            })
+    }
 
-                db: Arc::clone(&self.db),
-            });
+    /// Get the names of the people asking to join a group, by their JIDs.
+    async fn requester_names(&self, requests: &[JoinRequest]) -> HashMap<String, String> {
+        let mut names = HashMap::new();
+        for request in requests {
+            let name = self.get_display_name(&request.jid).await;
+            names.insert(request.jid.clone(), name);
+        }
 
-            self.client.emit(ClientInput::FetchAvatar {
-                jid: chat_jid.to_string(),
+        names
+    }
+
+    /// Show a change in the people asking to join a group on its row and,
+    /// if it's open, in its history.
+    fn join_requests_changed(&self, jid: &str) {
+        self.chat_view.emit(ChatViewInput::JoinRequestsChanged {
+            chat_jid: jid.to_string(),
+        });
+        if let Some(chat) = self.chats.iter().find(|c| c.jid == jid).cloned() {
+            self.chat_list.emit(ChatListInput::UpdateChat {
+                chat,
+                move_to_top: false,
             });
         }
+    }
 
-        // Get the chat.
-        let Some(chat) = self.chats.iter_mut().find(|c| c.jid == chat_jid) else {
+    /// Show the dialog of a call placed or answered, with its elapsed time
+    /// counting from now if it's `answered`.
+    fn show_call(
+        &mut self,
+        call_id: String,
+        name: String,
+        is_video: bool,
+        answered: bool,
+        sender: &AsyncComponentSender<Self>,
+    ) {
+        self.call_dialog = Some(
+            CallDialog::builder()
+                .launch(CallDialogInit {
+                    name,
+                    call_id,
+                    answered,
+                    is_video,
+                })
+                .forward(sender.input_sender(), |output| match output {
+                    CallDialogOutput::HangUp(call_id) => AppMsg::HangUp(call_id),
+                    CallDialogOutput::Closed => AppMsg::CallDialogClosed,
+                }),
+        );
+    }
+
+    /// Show the history of a chat again, after messages were cleared or restored.
+    fn history_changed(&self, jid: &str) {
+        let Some(chat) = self.chats.iter().find(|c| c.jid == jid).cloned() else {
             return;
         };
 
-        // Check if the message was sent by the connected user.
-        if !message.outgoing && is_group && !chat.participants.contains_key(&message.sender_jid) {
-            chat.participants.insert(
-                message.sender_jid.clone(),
-                message
-                    .sender_name
-                    .clone()
-                    .unwrap_or_else(|| i18n!("Unknown")),
-            );
+        if self.open_chat.as_deref() == Some(jid) {
+            self.chat_view.emit(ChatViewInput::Open(chat.clone()));
         }
+        self.chat_list.emit(ChatListInput::UpdateChat {
+            chat,
+            move_to_top: false,
+        });
+    }
+
+    fn add_chat(&mut self, chat: Chat) {
+        // Insert the chat into our cached list.
+        self.chats.push(chat.clone());
+
+        // Sort all our chats.
+        self.sort_chats();
 
         // Save the chat in the database.
         let chat_clone = chat.clone();
         relm4::spawn(async move {
             if let Err(e) = chat_clone.save().await {
-                tracing::error!("Failed to update chat: {}", e);
-            }
-        });
-
-        // Save the message in the database.
-        relm4::spawn(async move {
-            if let Err(e) = message.save().await {
-                tracing::error!("Failed to save message: {}", e);
+                tracing::error!("Failed to save chat: {}", e);
             }
         });
 
-        // Update the chat in the chat list.
-        self.chat_list.emit(ChatListInput::UpdateChat {
-            chat: chat.clone(),
-            move_to_top: true,
-        });
+        // Add the chat in the chat list.
+        self.chat_list
+            .emit(ChatListInput::AddChat { chat, at_top: true });
     }
 
-    /// Mark a chat as read.
-    async fn mark_chat_read(&mut self, chat_jid: &str) {
-        // Find the chat.
-        if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == chat_jid) {
-            // Collect unread messages before marking them as read locally.
-            let messages = chat.get_unread_messages().await.unwrap_or_default();
-
-            // Separate messages by sender.
-            let mut sender_messages: IndexMap<String, Vec<String>> = IndexMap::new();
-            for message in messages {
-                let sender_jid = message.sender_jid;
+    /// Check if focus mode is on.
+    fn focus_mode(&self) -> bool {
+        self.focus_mode_action
+            .state()
+            .and_then(|state| state.get())
+            .unwrap_or_default()
+    }
 
-                sender_messages
-                    .entry(sender_jid)
-                    .or_default()
-                    .push(message.server_id);
-            }
+    /// Check if notifications shouldn't pop up, as Do Not Disturb is on or
+    /// the screen is being shared.
+    fn notifications_muted(&self) -> bool {
+        self.do_not_disturb
+            || (self.screencast_active && self.settings.boolean("mute-during-screencast"))
+    }
 
-            // Send read receipts to WhatsApp.
-            for (sender_jid, message_ids) in sender_messages {
-                self.client.emit(ClientInput::MarkRead {
-                    chat_jid: chat_jid.to_string(),
-                    sender_jid: Some(sender_jid),
-                    message_ids,
-                });
-            }
+    /// Show the chats anyway if the sync starting now doesn't complete in time.
+    fn start_sync_deadline(&mut self, sender: &AsyncComponentSender<Self>) {
+        let attempt = self.sync_deadline.start();
+        sender.oneshot_command(async move {
+            time::sleep(SYNC_TIMEOUT).await;
+            AppCmd::SyncTimedOut(attempt)
+        });
+    }
 
-            // Mark chat as read locally.
-            let chat_clone = chat.clone();
-            relm4::spawn(async move {
-                if let Err(e) = chat_clone.mark_read().await {
-                    tracing::error!("Failed to mark a chat as read: {e}");
-                }
-            });
+    /// Show the chats once a sync is over, with the unread count held back
+    /// during it.
+    fn finish_syncing(&mut self, sender: &AsyncComponentSender<Self>) {
+        if self.page == AppPage::Syncing {
+            self.page = AppPage::Session;
+        }
+        if self.state == AppState::Syncing {
+            self.state = AppState::Ready;
+            self.chat_list.emit(ChatListInput::SetConnectionState(
+                ConnectionState::Connected,
+            ));
+            self.unread_changed(sender);
+        }
+    }
 
-            // Update the chat in the chat list.
-            self.chat_list.emit(ChatListInput::UpdateChat {
-                chat: chat.clone(),
-                move_to_top: false,
+    /// Turn focus mode on, for some time if given, or off.
+    fn set_focus_mode(
+        &mut self,
+        enabled: bool,
+        duration: Option<Duration>,
+        sender: &AsyncComponentSender<Self>,
+    ) {
+        tracing::debug!("Focus mode: {enabled}");
+        let duration = duration.filter(|_| enabled);
+        self.focus_mode_until = duration.map(|duration| Instant::now() + duration);
+        self.focus_mode_action.set_state(&enabled.to_variant());
+        self.chat_list.emit(ChatListInput::SetFocusMode(enabled));
+        self.unread_changed(sender);
+
+        if let Some(duration) = duration {
+            sender.oneshot_command(async move {
+                time::sleep(duration).await;
+                AppCmd::FocusModeExpired
             });
         }
     }
-}
-
-relm4::new_action_group!(pub(super) WindowActionGroup, "win");
-relm4::new_stateless_action!(ContactsAction, WindowActionGroup, "show-contacts");
-relm4::new_stateless_action!(PreferencesAction, WindowActionGroup, "show-preferences");
-relm4::new_stateless_action!(pub(super) ShortcutsAction, WindowActionGroup, "show-help-overlay");
-relm4::new_stateless_action!(AboutAction, WindowActionGroup, "about");
-relm4::new_stateless_action!(QuitAction, WindowActionGroup, "quit");
 
-#[relm4::component(async, pub)]
-impl AsyncComponent for Application {
-    type Init = ();
-    type Input = AppMsg;
-    type Output = ();
-    type Widgets = AppWidgets;
-    type CommandOutput = AppCmd;
+    /// Whether a message received in a chat should be notified and sounded,
+    /// going by Do Not Disturb, the chat's mute and whether it's in view.
+    fn should_notify(
+        &self,
+        chat: &Chat,
+        message: &ChatMessage,
+        mentions: &[String],
+        focused: bool,
+    ) -> bool {
+        let user_jids = [
+            self.user_jid.as_deref(),
+            self.user_profile.phone_jid.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        let mut check = NotifyCheck::empty();
+        check.set(NotifyCheck::OUTGOING, message.outgoing);
+        check.set(NotifyCheck::LEFT, chat.has_left());
+        check.set(NotifyCheck::NOTIFICATIONS_MUTED, self.notifications_muted());
+        check.set(
+            NotifyCheck::IN_VIEW,
+            focused && self.open_chat.as_deref() == Some(chat.jid.as_str()),
+        );
+        check.set(NotifyCheck::CHAT_MUTED, chat.is_muted());
+        check.set(NotifyCheck::GROUP, chat.kind() == ChatKind::Group);
+        check.set(
+            NotifyCheck::MENTIONS_IN_MUTED_GROUPS,
+            self.settings.boolean("muted-group-mentions"),
+        );
+        check.set(
+            NotifyCheck::MENTIONED,
+            mentions_user(&message.content, mentions, &user_jids),
+        );
+        check.should_notify()
+    }
 
-    menu! {
-        primary_menu: {
-            section! {
-                &i18n!("_Contacts") => ContactsAction,
-            },
-            section! {
-                &i18n!("_Preferences") => PreferencesAction,
-                &i18n!("_Keyboard Shortcuts") => ShortcutsAction,
-                &i18n!("_About Papo") => AboutAction,
+    /// Pop up a notification for a message received in a chat, replacing
+    /// the one shown for the chat before.
+    fn notify_message(chat: &Chat, message: &ChatMessage) {
+        let body = match message.sender_name.as_deref() {
+            Some(sender) if chat.kind() == ChatKind::Group => {
+                format!("{sender}: {}", message.content)
             }
-        }
+            _ => message.content.clone(),
+        };
+
+        let notification = gio::Notification::new(&chat.name);
+        notification.set_body(Some(&body));
+        notification
+            .set_default_action_and_target_value("app.open-chat", Some(&chat.jid.to_variant()));
+        main_application().send_notification(
+            Some(&format!("{MESSAGE_NOTIFICATION_PREFIX}{}", chat.jid)),
+            &notification,
+        );
     }
 
-    view! {
-        #[root]
-        main_window = adw::ApplicationWindow::new(&main_application()) {
-            set_title: Some(&i18n!("Papo")),
-            set_visible: true,
-            set_width_request: 360,
-            set_height_request: 440,
-            set_default_width: 900,
-            set_default_height: 850,
+    /// Sound a message received in a chat, where a notification would
+    /// pop up for it.
+    fn sound_received_message(kind: MessageKind, focused: bool) {
+        let settings = gio::Settings::new(APP_ID);
+        if !settings.boolean("message-received-sound")
+            || (focused && !settings.boolean("sounds-while-focused"))
+            || (kind == MessageKind::Automated && !settings.boolean("automated-message-sounds"))
+        {
+            return;
+        }
 
-            connect_close_request[sender] => move |_| {
-                sender.input(AppMsg::Quit);
-                glib::Propagation::Stop
-            },
+        sound::play(Sound::MessageReceived);
+    }
 
-            add_css_class?: (PROFILE == "Devel").then_some("devel"),
+    /// Dismiss the incoming call dialog and notification.
+    fn dismiss_incoming_call(&mut self, call_id: &str) -> Option<IncomingCall> {
+        if self
+            .incoming_call
+            .as_ref()
+            .is_none_or(|call| call.call_id != call_id)
+        {
+            return None;
+        }
 
-            #[local_ref]
-            toast_overlay -> adw::ToastOverlay {
-                #[name = "main_stack"]
-                gtk::Stack {
-                    set_transition_type: gtk::StackTransitionType::Crossfade,
+        let call = self.incoming_call.take()?;
+        call.dialog.force_close();
+        main_application().withdraw_notification(CALL_NOTIFICATION_ID);
+        sound::stop(Sound::Ringtone);
 
-                    add_named[Some("fetching")] = &adw::ToolbarView {
-                        add_top_bar = &adw::HeaderBar {
-                            pack_end = &gtk::Button {
-                                set_icon_name: "info-outline-symbolic",
-                                set_action_name: Some("win.about"),
-                                set_tooltip_text: Some(&i18n!("About Papo")),
-                            }
-                        },
+        Some(call)
+    }
 
-                        #[wrap(Some)]
-                        set_content = &gtk::Box {
-                            set_halign: gtk::Align::Center,
-                            set_valign: gtk::Align::Center,
-                            set_vexpand: true,
-                            set_spacing: 24,
-                            set_orientation: gtk::Orientation::Vertical,
+    /// Keep a call that wasn't answered in the history of the caller's chat.
+    fn log_missed_call(
+        &mut self,
+        from_jid: &str,
+        call_id: &str,
+        is_video: bool,
+        timestamp: DateTime<Utc>,
+    ) {
+        let chat_jid = self.resolve_chat_jid(from_jid, None);
+        let content = if is_video {
+            i18n!("Missed video call")
+        } else {
+            i18n!("Missed voice call")
+        };
+        let message = ChatMessage {
+            local_id: Uuid::new_v4(),
+            server_id: format!("call-{call_id}"),
+            chat_jid: chat_jid.clone(),
+            outgoing: false,
+            sender_jid: from_jid.to_string(),
+            sender_name: None,
+
+            kind: MessageKind::Service,
+            poll: None,
+            media: None,
+            edited: false,
+            status: MessageStatus::Read,
+            content,
+            revoked: false,
+            contacts: Vec::new(),
+            location: None,
+            quote: None,
+            preview: None,
+            reactions: IndexMap::new(),
+            timestamp,
+
+            db: Arc::clone(&self.db),
+        };
 
-                            gtk::Label {
-                                set_label: &i18n!("Fetching account data..."),
-                                set_halign: gtk::Align::Center,
-                                set_justify: gtk::Justification::Center,
-                                set_css_classes: &["title-2"],
+        self.add_message(&chat_jid, message.clone());
+        if self.open_chat.as_deref() == Some(chat_jid.as_str()) {
+            self.chat_view
+                .emit(ChatViewInput::MessageReceived(Box::new(message)));
+        }
+    }
 
-                                set_wrap: true,
-                                set_wrap_mode: pango::WrapMode::WordChar
-                            },
+    /// Resolve the JID of the chat a message belongs to, preferring
+    /// existing chats known by an alternate (PN or LID) JID.
+    fn resolve_chat_jid(&mut self, chat_jid: &str, alt_jid: Option<&str>) -> String {
+        let jid = self.jid_aliases.resolve(&self.chats, chat_jid, alt_jid);
+        if jid != chat_jid {
+            self.add_jid_alias(chat_jid.to_string(), jid.clone());
+        }
 
-                            adw::Spinner {
-                                set_width_request: 48,
-                                set_height_request: 48
-                            }
-                        }
-                    },
+        jid
+    }
 
-                    #[local_ref]
-                    add_named[Some("login")] = login_widget -> adw::ToolbarView {},
+    /// Remember that messages addressed to `alias` belong to the chat `jid`,
+    /// across restarts.
+    fn add_jid_alias(&mut self, alias: String, jid: String) {
+        if !self.jid_aliases.insert(alias.clone(), jid.clone()) {
+            return;
+        }
 
-                    add_named[Some("session")] = &adw::BreakpointBin {
-                        set_width_request: main_window.width_request(),
-                        set_height_request: main_window.height_request(),
+        let db = Arc::clone(&self.db);
+        relm4::spawn(async move {
+            if let Err(e) = db.save_jid_alias(&alias, &jid).await {
+                tracing::error!("Failed to save the alias {alias} of {jid}: {e}");
+            }
+        });
+    }
 
-                        #[local_ref]
-                        #[wrap(Some)]
-                        set_child = split_view -> adw::NavigationSplitView {
-                            set_min_sidebar_width: 280.0,
-                            set_max_sidebar_width: 360.0,
+    fn add_message(&mut self, chat_jid: &str, mut message: ChatMessage) {
+        // Apply changes that arrived before the message itself.
+        if let Some(pending) = self.pending_references.take(&message.server_id) {
+            pending.apply(&mut message);
+        }
 
-                            #[name = "sidebar"]
-                            #[wrap(Some)]
-                            set_sidebar = &adw::NavigationPage {
-                                set_title: &i18n!("Papo"),
-                                set_css_classes: &["background"],
+        // Check if the message's chat is a group.
+        let is_group = ChatKind::from_jid(chat_jid) == ChatKind::Group;
 
-                                #[wrap(Some)]
-                                set_child = &adw::ToolbarView {
-                                    add_top_bar = &adw::HeaderBar {
-                                        set_show_title: false,
+        // Create a new chat if it doesn't exists.
+        if !self.chats.iter().any(|c| c.jid == chat_jid) {
+            let name = if is_group {
+                format!("{} {}", i18n!("Group"), &chat_jid[..8])
+            } else if self.user_jid.as_ref().is_some_and(|u_j| chat_jid == u_j) {
+                i18n!("You")
+            } else {
+                message
+                    .sender_name
+                    .clone()
+                    .unwrap_or_else(|| format_jid(chat_jid))
+            };
 
-                                        pack_start = &gtk::ToggleButton {
-                                            set_css_classes: &["flat", "circular"],
-                                            set_tooltip_text: Some(&i18n!("Your profile")),
+            self.add_chat(Chat {
+                jid: chat_jid.to_string(),
+                name,
+                muted: false,
+                muted_until: None,
+                pinned: false,
+                archived: false,
+                membership: Membership::Member,
+                available: None,
+                last_seen: None,
+                avatar_path: None,
+                participants: HashMap::new(),
+                last_message_time: message.timestamp,
+                participant_count: None,
+                name_modified_at: None,
+                flags_modified_at: None,
+                pin_order: None,
+                sort_key: message.timestamp.timestamp_millis(),
 
-                                            adw::Avatar {
-                                                #[watch]
-                                                set_text: Some(&model.user_push_name.clone().unwrap_or_else(|| i18n!("You"))),
-                                                set_size: 30,
-                                                set_show_initials: true,
-                                            }
-                                        },
-                                        pack_end = &gtk::MenuButton {
-                                            set_icon_name: "menu-symbolic",
-                                            set_menu_model: Some(&primary_menu),
-                                            set_tooltip_text: Some(&i18n!("Menu")),
-                                        },
-                                    },
+                db: Arc::clone(&self.db),
+            });
 
-                                    #[name = "view_stack"]
-                                    #[wrap(Some)]
-                                    set_content = &adw::ViewStack {
-                                        #[local_ref]
-                                        add_titled[Some("chats"), &i18n!("Chats")] = chat_list_widget -> gtk::Box {} -> {
-                                            set_icon_name: Some("chat-bubbles-text-symbolic")
-                                        },
+            self.client.emit(ClientInput::FetchAvatar {
+                jid: chat_jid.to_string(),
+            });
+        }
 
-                                        /* add_titled[Some("status"), &i18n!("Status")] = &gtk::ScrolledWindow {} -> {
-                                            set_icon_name: Some("image-round-symbolic")
-                                        } */
-                                    },
+        // Groups the user left stay where they are.
+        let left = self.chats.iter().any(|c| c.jid == chat_jid && c.has_left());
+        if !left {
+            self.bump_chat(chat_jid);
+        }
 
-                                    add_bottom_bar = &adw::ViewSwitcherBar {
-                                        set_stack: Some(&view_stack),
-                                        set_reveal: true
-                                    },
-                                },
-                            },
+        // Get the chat.
+        let Some(chat) = self.chats.iter_mut().find(|c| c.jid == chat_jid) else {
+            return;
+        };
 
-                            #[name = "content"]
-                            #[wrap(Some)]
-                            set_content = &adw::NavigationPage {
-                                set_title: &i18n!("Chat"),
-                                set_css_classes: &["view"],
+        // Check if the message was sent by the connected user.
+        if !message.outgoing && is_group && !chat.participants.contains_key(&message.sender_jid) {
+            chat.participants.insert(
+                message.sender_jid.clone(),
+                message
+                    .sender_name
+                    .clone()
+                    .unwrap_or_else(|| i18n!("Unknown")),
+            );
+        }
 
-                                #[wrap(Some)]
-                                set_child = &gtk::Stack {
-                                    set_transition_type: gtk::StackTransitionType::Crossfade,
+        // Save the chat in the database.
+        let chat_clone = chat.clone();
+        relm4::spawn(async move {
+            if let Err(e) = chat_clone.save().await {
+                tracing::error!("Failed to update chat: {}", e);
+            }
+        });
 
-                                    add_named[Some("empty")] = &adw::StatusPage {
-                                        set_title: &i18n!("No Chat Selected"),
-                                        set_hexpand: true,
-                                        set_vexpand: true,
-                                        set_can_focus: false,
-                                        set_icon_name: Some("chat-bubbles-empty-symbolic"),
-                                        set_description: Some(&i18n!("Select a chat to start chatting"))
-                                    },
+        // Save the message in the database.
+        relm4::spawn(async move {
+            if let Err(e) = message.save().await {
+                tracing::error!("Failed to save message: {}", e);
+            }
+        });
 
-                                    #[local_ref]
-                                    add_named[Some("chat-history")] = chat_view_widget -> adw::ToolbarView {},
+        // Update the chat in the chat list.
+        self.chat_list.emit(ChatListInput::UpdateChat {
+            chat: chat.clone(),
+            move_to_top: !left,
+        });
+    }
 
-                                    #[watch]
-                                    set_visible_child_name: model.session_page.as_ref(),
-                                }
-                            }
-                        },
+    /// Gets the JIDs the user is known by.
+    fn own_jids(&self) -> Vec<String> {
+        [self.user_jid.clone(), self.user_profile.phone_jid.clone()]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
 
-                        add_breakpoint = bp_with_setters(
-                            adw::Breakpoint::new(
-                                adw::BreakpointCondition::new_length(
-                                    adw::BreakpointConditionLengthType::MaxWidth,
-                                    600.0,
-                                    adw::LengthUnit::Sp,
-                                )
-                            ),
-                            &[(split_view, "collapsed", true)]
-                        ),
-                    },
+    /// Mark the user as out of a group, or back in it, which makes it read-only
+    /// or lets them write in it again.
+    fn set_chat_left(&mut self, jid: &str, left: bool) {
+        let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) else {
+            return;
+        };
+        if chat.has_left() == left {
+            return;
+        }
 
-                    #[watch]
-                    set_visible_child_name: model.page.as_ref(),
-                },
-            },
+        tracing::info!("Chat {jid} left: {left}");
+        chat.membership = Membership::from_left(left);
+        let chat_clone = chat.clone();
+        relm4::spawn(async move {
+            if let Err(e) = chat_clone.save().await {
+                tracing::error!("Failed to save chat: {}", e);
+            }
+        });
+
+        if self.open_chat.as_deref() == Some(jid) {
+            self.chat_view
+                .emit(ChatViewInput::ChatChanged(chat.clone()));
         }
     }
 
-    #[allow(clippy::too_many_lines)]
-    async fn init(
-        _init: Self::Init,
-        root: Self::Root,
-        sender: AsyncComponentSender<Self>,
-    ) -> AsyncComponentParts<Self> {
-        let db = Arc::new(
-            Database::new()
-                .await
-                .expect("Failed to initialize database"),
-        );
-        let login =
-            Login::builder()
-                .launch(())
-                .forward(sender.input_sender(), |output| match output {
-                    LoginOutput::ResetSession => AppMsg::ResetSession,
+    /// Download the full media of a message, unless it's being downloaded.
+    fn download_media(&mut self, chat_jid: &str, msg_id: &str, media: &Media) {
+        let Some(downloadable) = media.downloadable.clone() else {
+            return;
+        };
+        if !self.downloading.insert(msg_id.to_string()) {
+            return;
+        }
 
-                    LoginOutput::PairWithPhoneNumber { phone_number } => {
-                        AppMsg::PairWithPhoneNumber { phone_number }
-                    }
-                });
+        self.client.emit(ClientInput::DownloadMedia {
+            chat_jid: chat_jid.to_string(),
+            message_id: msg_id.to_string(),
+            media: downloadable,
+        });
+    }
 
-        let client = Client::builder()
-            .launch(())
-            .forward(sender.input_sender(), |output| match output {
-                ClientOutput::Connected { jid, push_name } => AppMsg::Connected { jid, push_name },
-                ClientOutput::LoggedOut => AppMsg::LoggedOut,
-                ClientOutput::Disconnected => AppMsg::Disconnected,
-                ClientOutput::SelfPushNameUpdated { push_name } => {
-                    AppMsg::SelfPushNameUpdated { push_name }
-                }
+    /// Load the media of a message, telling why if there's none to use.
+    async fn load_media(&self, chat_jid: &str, msg_id: &str) -> Option<(ChatMessage, Media)> {
+        let message = match self.db.load_message_by_server_id(chat_jid, msg_id).await {
+            Ok(Some(message)) => message,
+            Ok(None) => return None,
+            Err(e) => {
+                tracing::error!("Failed to load message {msg_id}: {e}");
+                return None;
+            }
+        };
 
-                ClientOutput::PairCode {
-                    code,
-                    qr_code,
-                    timeout,
-                } => AppMsg::PairDevice {
-                    code,
-                    qr_code,
-                    timeout,
-                },
-                ClientOutput::PairSuccess => AppMsg::DevicePaired,
+        match message.media.clone() {
+            Some(media) if media.has_data() => Some((message, media)),
+            _ => {
+                self.show_toast(
+                    &i18n!("The media isn't downloaded yet"),
+                    adw::ToastPriority::Normal,
+                );
+                None
+            }
+        }
+    }
 
-                ClientOutput::ReceiptUpdate {
-                    chat_jid,
-                    message_ids,
-                    receipt_type,
-                } => AppMsg::ReceiptUpdate {
-                    chat_jid,
-                    message_ids,
-                    receipt_type,
-                },
-                ClientOutput::PresenceUpdate {
-                    jid,
-                    available,
-                    last_seen,
-                } => AppMsg::PresenceUpdate {
-                    jid,
-                    available,
-                    last_seen,
-                },
+    /// Tell that there's too little free space to export to `path`, if so.
+    fn refuse_low_space_export(&self, path: &Path) -> bool {
+        let low_on_space = is_low_on_space(path.parent().unwrap_or(path));
+        if low_on_space {
+            self.show_toast(
+                &i18n!("There isn't enough free disk space to export"),
+                adw::ToastPriority::High,
+            );
+        }
+        low_on_space
+    }
 
-                ClientOutput::MessageReceived { info, message } => {
-                    AppMsg::MessageReceived { info, message }
-                }
-                ClientOutput::MessageSent { chat_jid, msg_id } => AppMsg::MessageStatusUpdate {
-                    chat_jid,
-                    msg_id,
-                    status: MessageStatus::Sent,
-                },
-                ClientOutput::MessageFailed { chat_jid, msg_id } => AppMsg::MessageStatusUpdate {
-                    chat_jid,
-                    msg_id,
-                    status: MessageStatus::Failed,
-                },
+    /// Get a file name for the media of a message, from its chat and time.
+    fn media_file_name(&self, message: &ChatMessage, media: &Media) -> String {
+        let chat_name = self
+            .chats
+            .iter()
+            .find(|c| c.jid == message.chat_jid)
+            .map_or_else(|| format_jid(&message.chat_jid), Chat::get_name_or_number);
+
+        format!(
+            "{} {}.{}",
+            chat_name.replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_"),
+            message
+                .timestamp
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H.%M.%S"),
+            media.extension()
+        )
+    }
 
-                ClientOutput::ChatSynced {
-                    jid,
-                    name,
-                    pinned,
-                    archived,
-                    unread_count,
-                    participants,
-                    mute_end_time,
-                    last_message_time,
-                } => AppMsg::ChatSynced {
-                    jid,
-                    name,
-                    pinned,
-                    archived,
-                    unread_count,
-                    participants,
-                    mute_end_time,
-                    last_message_time,
-                },
+    /// Replace a message deleted for everyone with a tombstone, if `actor`
+    /// may delete it.
+    async fn revoke_message(&mut self, chat_jid: &str, msg_id: String, actor: &MessageActor) {
+        match self.db.revoke_message(chat_jid, &msg_id, actor).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::debug!(
+                    "Revoked message {msg_id} isn't stored or wasn't sent by the revoker, ignoring"
+                );
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Failed to revoke message {msg_id}: {e}");
+                return;
+            }
+        }
 
-                ClientOutput::MessagesSynced { chat_jid, messages } => {
-                    AppMsg::MessagesSynced { chat_jid, messages }
-                }
+        self.chat_view
+            .emit(ChatViewInput::MessageRevoked { msg_id });
 
-                ClientOutput::ChatPropertyUpdate {
-                    jid,
-                    pinned,
-                    muted,
-                    archived,
-                } => AppMsg::ChatPropertyUpdate {
-                    jid,
-                    pinned,
-                    muted,
-                    archived,
-                },
+        // Refresh the chat subtitle in case the revoked message was the latest one.
+        self.refresh_chat(chat_jid);
+    }
 
-                ClientOutput::HistorySyncCompleted => AppMsg::HistorySyncCompleted,
-                ClientOutput::OfflineSyncCompleted => AppMsg::OfflineSyncCompleted,
+    /// Replace the content of a message edited by its sender.
+    async fn edit_message(
+        &mut self,
+        chat_jid: &str,
+        msg_id: String,
+        actor: MessageActor,
+        content: String,
+    ) {
+        match self
+            .db
+            .edit_message(chat_jid, &msg_id, &actor, &content)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                // The original message may still arrive later through sync,
+                // when the edit is checked against its sender again.
+                tracing::debug!("Edited message {msg_id} isn't stored, keeping the edit");
+                self.pending_references.entry(msg_id).edit = Some((content, actor));
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Failed to edit message {msg_id}: {e}");
+                return;
+            }
+        }
 
-                ClientOutput::ContactUpdate {
-                    jid,
-                    name,
-                    push_name,
-                    phone_number,
-                } => AppMsg::ContactUpdate {
-                    jid,
-                    name,
-                    push_name,
-                    phone_number,
-                },
+        self.chat_view
+            .emit(ChatViewInput::MessageEdited { msg_id, content });
 
-                ClientOutput::AvatarUpdate { jid, path } => AppMsg::AvatarUpdate { jid, path },
+        // Refresh the chat subtitle in case the edited message is the latest one.
+        self.refresh_chat(chat_jid);
+    }
 
-                ClientOutput::Error { message } => AppMsg::Error { message },
-                _ => AppMsg::Unknown,
-            });
+    /// Name the chat of a contact saved with `name`.
+    async fn rename_contact_chat(&mut self, jid: String, name: String) {
+        if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) {
+            chat.name.clone_from(&name);
+            chat.name_modified_at = Some(Utc::now());
+            if let Err(e) = chat.save().await {
+                tracing::error!("Failed to update chat name for {jid}: {e}");
+            }
 
-        let chat_list = ChatList::builder()
-            .launch(())
-            .forward(sender.input_sender(), |output| match output {
-                ChatListOutput::ChatSelected(jid) => AppMsg::ChatSelected(jid),
+            self.chat_list.emit(ChatListInput::UpdateChat {
+                chat: chat.clone(),
+                move_to_top: false,
             });
-        let chat_view = ChatView::builder()
-            .launch(())
-            .forward(sender.input_sender(), |output| match output {
-                ChatViewOutput::ChatOpen => AppMsg::ChatOpen,
-                ChatViewOutput::ChatClosed => AppMsg::ChatClosed,
-                ChatViewOutput::MarkChatRead(jid) => AppMsg::MarkChatRead(jid),
+        }
 
-                ChatViewOutput::SendTextMessage { text, recipient } => {
-                    AppMsg::SendTextMessage { text, recipient }
-                }
-            });
+        self.chat_view
+            .emit(ChatViewInput::ContactSaved { jid, name });
+    }
 
-        let model = Self {
-            db,
-            page: AppPage::Fetching,
-            chats: Vec::new(),
-            login,
-            state: AppState::Loading,
-            client,
-            toaster: Toaster::default(),
-            user_jid: None,
-            chat_list,
-            chat_view,
-            split_view: NavigationSplitView::new(),
-            session_page: AppSessionPage::Empty,
-            user_push_name: None,
+    /// Pin a message in its chat, until `expires_at` if set, or unpin it,
+    /// updating the pinned bar.
+    async fn set_message_pinned(
+        &self,
+        chat_jid: &str,
+        msg_id: &str,
+        pinned: bool,
+        at: DateTime<Utc>,
+        expires_at: Option<DateTime<Utc>>,
+    ) {
+        let result = if pinned {
+            self.db.pin_message(chat_jid, msg_id, at, expires_at).await
+        } else {
+            self.db.unpin_message(chat_jid, msg_id).await
         };
+        if let Err(e) = result {
+            tracing::error!("Failed to update pin of message {msg_id}: {e}");
+            return;
+        }
 
-        let split_view = &model.split_view;
-        let login_widget = model.login.widget();
-        let toast_overlay = model.toaster.overlay_widget();
-        let chat_list_widget = model.chat_list.widget();
-        let chat_view_widget = model.chat_view.widget();
-
-        let app = root.application().unwrap();
-        let mut actions = RelmActionGroup::<WindowActionGroup>::new();
+        self.chat_view.emit(ChatViewInput::PinnedMessagesChanged {
+            chat_jid: chat_jid.to_string(),
+        });
+    }
 
-        let shortcuts_action = {
-            RelmAction::<ShortcutsAction>::new_stateless(move |_| {
-                ShortcutsDialog::builder().launch(()).detach();
-            })
+    /// Toggle our vote for a poll option, updating the poll right away.
+    async fn vote_poll(&self, chat_jid: &str, msg_id: String, option: &str) {
+        let Some(ref user_jid) = self.user_jid else {
+            return;
         };
 
-        let about_action = {
-            RelmAction::<AboutAction>::new_stateless(move |_| {
-                AboutDialog::builder().launch(()).detach();
-            })
+        let mut message = match self.db.load_message_by_server_id(chat_jid, &msg_id).await {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                tracing::warn!("Poll {msg_id} isn't stored, can't vote");
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Failed to load poll {msg_id}: {e}");
+                return;
+            }
         };
-
-        let quit_action = {
-            let sender = sender.clone();
-            RelmAction::<QuitAction>::new_stateless(move |_| {
-                sender.input(AppMsg::Quit);
-            })
+        let Some(poll) = message.poll.as_mut() else {
+            return;
         };
 
-        // Connect actions with hotkeys
-        app.set_accelerators_for_action::<QuitAction>(&["<Control>q"]);
-        // app.set_accelerators_for_action::<QuitAction>(&["<Control>w"]);
+        // Votes are encrypted with the poll's secret, so they can't be sent without it.
+        if poll.secret.is_none() {
+            self.show_toast(
+                &i18n!("Can't vote in this poll from here"),
+                adw::ToastPriority::Normal,
+            );
+            return;
+        }
+
+        let options = poll.toggle_vote(user_jid, option);
+        if let Err(e) = self.db.save_poll_vote(&msg_id, user_jid, &options).await {
+            tracing::error!("Failed to save vote for poll {msg_id}: {e}");
+            return;
+        }
+
+        self.chat_view.emit(ChatViewInput::PollVoted {
+            msg_id: msg_id.clone(),
+            voter_jid: user_jid.clone(),
+            options: options.clone(),
+        });
+        let voter_jid = self
+            .own_jid_like(&message.sender_jid)
+            .unwrap_or_else(|| user_jid.clone());
+        self.client.emit(ClientInput::VotePoll {
+            poll: Box::new(message),
+            voter_jid,
+            options,
+        });
+    }
+
+    /// Decrypt a vote in a poll and show it, replacing the voter's previous one.
+    async fn receive_poll_vote(
+        &self,
+        chat_jid: &str,
+        voter_jid: &str,
+        outgoing: bool,
+        update: &PollUpdateMessage,
+    ) {
+        let Some(key) = update.poll_creation_message_key.as_ref() else {
+            return;
+        };
+        let Some(msg_id) = key.id.clone() else {
+            return;
+        };
+
+        let message = match self.db.load_message_by_server_id(chat_jid, &msg_id).await {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                tracing::debug!("Ignoring vote for poll {msg_id}, which isn't stored");
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Failed to load poll {msg_id}: {e}");
+                return;
+            }
+        };
+        let (Some(poll), Some(vote)) = (message.poll.as_ref(), update.vote.as_ref()) else {
+            return;
+        };
+
+        // The key is from the voter's side: `from_me` means they created
+        // the poll, otherwise it names its creator.
+        let creator_jid = if key.from_me.unwrap_or_default() {
+            Some(voter_jid.to_string())
+        } else if ChatKind::from_jid(chat_jid) == ChatKind::Group {
+            key.participant.clone()
+        } else {
+            key.remote_jid.clone()
+        }
+        .filter(|jid| !jid.is_empty())
+        .unwrap_or_else(|| message.sender_jid.clone());
+
+        let Some(options) = poll.decrypt_vote(&msg_id, &creator_jid, voter_jid, vote) else {
+            tracing::warn!("Failed to decrypt vote for poll {msg_id} from {voter_jid}");
+            return;
+        };
+
+        // Our own votes are kept under the JID we vote from here.
+        let voter_jid = match self.user_jid.as_ref() {
+            Some(user_jid) if outgoing => user_jid.clone(),
+            _ => voter_jid.to_string(),
+        };
+        if let Err(e) = self.db.save_poll_vote(&msg_id, &voter_jid, &options).await {
+            tracing::error!("Failed to save vote for poll {msg_id}: {e}");
+            return;
+        }
+
+        self.chat_view.emit(ChatViewInput::PollVoted {
+            msg_id,
+            voter_jid,
+            options,
+        });
+    }
+
+    /// React to a message, or remove our reaction with an empty `emoji`,
+    /// updating it right away.
+    async fn react(&self, chat_jid: &str, msg_id: String, emoji: String) {
+        let Some(ref user_jid) = self.user_jid else {
+            return;
+        };
+
+        let message = match self.db.load_message_by_server_id(chat_jid, &msg_id).await {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                tracing::warn!("Message {msg_id} isn't stored, can't react");
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Failed to load message {msg_id}: {e}");
+                return;
+            }
+        };
+
+        let reaction = Some(emoji.as_str()).filter(|emoji| !emoji.is_empty());
+        if let Err(e) = self
+            .db
+            .save_reaction(&msg_id, user_jid, reaction, Utc::now())
+            .await
+        {
+            tracing::error!("Failed to save reaction to message {msg_id}: {e}");
+            return;
+        }
+
+        self.chat_view.emit(ChatViewInput::ReactionChanged {
+            msg_id,
+            sender_jid: user_jid.clone(),
+            emoji: reaction.map(ToString::to_string),
+        });
+        self.client.emit(ClientInput::React {
+            message: Box::new(message),
+            emoji,
+        });
+    }
+
+    /// Show a reaction to a message, replacing the sender's previous one.
+    async fn receive_reaction(
+        &self,
+        sender_jid: &str,
+        outgoing: bool,
+        reaction: &ReactionMessage,
+        timestamp: DateTime<Utc>,
+    ) {
+        let Some(msg_id) = reaction.key.as_ref().and_then(|k| k.id.clone()) else {
+            return;
+        };
+
+        // Our own reactions are kept under the JID we react from here.
+        let sender_jid = match self.user_jid.as_ref() {
+            Some(user_jid) if outgoing => user_jid.clone(),
+            _ => sender_jid.to_string(),
+        };
+        let emoji = reaction.text.clone().filter(|emoji| !emoji.is_empty());
+        if let Err(e) = self
+            .db
+            .save_reaction(&msg_id, &sender_jid, emoji.as_deref(), timestamp)
+            .await
+        {
+            tracing::error!("Failed to save reaction to message {msg_id}: {e}");
+            return;
+        }
+
+        self.chat_view.emit(ChatViewInput::ReactionChanged {
+            msg_id,
+            sender_jid,
+            emoji,
+        });
+    }
+
+    /// Get who revokes or edits a message with a change received as `info`.
+    fn message_actor(&self, info: &MessageInfo, outgoing: bool) -> MessageActor {
+        if outgoing {
+            let own_jids = [self.user_jid.clone(), self.user_profile.phone_jid.clone()];
+            MessageActor::User(own_jids.into_iter().flatten().collect())
+        } else {
+            let alt_jid = info.source.sender_alt.as_ref().map(ToString::to_string);
+            MessageActor::contact(&info.source.sender.to_string(), alt_jid.as_deref())
+        }
+    }
+
+    /// Get the connected user's JID addressed like `jid`, by LID or phone number.
+    fn own_jid_like(&self, jid: &str) -> Option<String> {
+        let server = |jid: &str| jid.rsplit_once('@').map(|(_, server)| server.to_string());
+        let own_jids = [self.user_jid.as_ref(), self.user_profile.phone_jid.as_ref()];
+
+        own_jids
+            .into_iter()
+            .flatten()
+            .find(|own| server(own) == server(jid))
+            .or(self.user_jid.as_ref())
+            .cloned()
+    }
+
+    /// Move a chat to where another is in the list, (un)pinning it to match,
+    /// and renumber the manual order of the pinned chats.
+    fn move_chat(&mut self, jid: &str, target_jid: &str) {
+        self.sort_chats();
+
+        let Some(pin) = self
+            .chats
+            .iter()
+            .find(|c| c.jid == target_jid)
+            .map(|c| c.pinned)
+        else {
+            return;
+        };
+        let Some(was_pinned) = self.chats.iter().find(|c| c.jid == jid).map(|c| c.pinned) else {
+            return;
+        };
+
+        // Unpinned chats are always ordered by time.
+        if !pin && !was_pinned {
+            return;
+        }
+
+        // Chats are sorted, so the pinned ones are already in their manual order.
+        let pinned = self
+            .chats
+            .iter()
+            .filter(|c| c.pinned)
+            .map(|c| c.jid.clone())
+            .collect::<Vec<_>>();
+        let source = pinned.iter().position(|j| j == jid);
+        let mut order = pinned.into_iter().filter(|j| j != jid).collect::<Vec<_>>();
+
+        let mut before = None;
+        if pin && let Some(target) = order.iter().position(|j| j == target_jid) {
+            // Dropping a chat further down places it after the target.
+            let position = if source.is_some_and(|s| s <= target) {
+                target + 1
+            } else {
+                target
+            };
+            before = order.get(position).cloned();
+            order.insert(position, jid.to_string());
+        }
+
+        let now = Utc::now();
+        for chat in &mut self.chats {
+            let pin_order = order
+                .iter()
+                .position(|j| *j == chat.jid)
+                .and_then(|p| u32::try_from(p).ok());
+            let pinned = pin_order.is_some();
+            if chat.pinned == pinned && chat.pin_order == pin_order {
+                continue;
+            }
+
+            if chat.pinned != pinned {
+                chat.flags_modified_at = Some(now);
+            }
+            chat.pinned = pinned;
+            chat.pin_order = pin_order;
+
+            let chat = chat.clone();
+            relm4::spawn(async move {
+                if let Err(e) = chat.save().await {
+                    tracing::error!("Failed to save chat order: {}", e);
+                }
+            });
+        }
+        self.sort_chats();
+
+        if let Some(chat) = self.chats.iter().find(|c| c.jid == jid) {
+            self.chat_list.emit(ChatListInput::MoveChat {
+                chat: chat.clone(),
+                before,
+            });
+        }
+        // Pins made here aren't synced to the account, so tell them apart
+        // from the ones made on the phone.
+        if pin != was_pinned {
+            let text = if pin {
+                i18n!("Chat pinned on this device only")
+            } else {
+                i18n!("Chat unpinned on this device only")
+            };
+            self.show_toast(&text, adw::ToastPriority::Normal);
+        }
+    }
+
+    /// Note that unread messages changed, to show their new total once
+    /// changes settle.
+    fn unread_changed(&mut self, sender: &AsyncComponentSender<Self>) {
+        if self.unread_total.change(Instant::now()) {
+            schedule_unread_total(sender, UNREAD_TOTAL_DELAY);
+        }
+    }
+
+    /// Check if a JID (LID or phone number) is the connected user's.
+    fn is_own_jid(&self, jid: &str) -> bool {
+        self.user_jid.as_deref() == Some(jid) || self.user_profile.phone_jid.as_deref() == Some(jid)
+    }
+
+    /// Fill who sent a quoted message from its original, if it was synced.
+    async fn resolve_quote(&self, chat_jid: &str, quote: Quote) -> Quote {
+        match self.db.load_message_by_server_id(chat_jid, &quote.id).await {
+            Ok(Some(original)) => Quote::from(&original),
+            _ => Quote {
+                outgoing: self.is_own_jid(&quote.sender_jid),
+                ..quote
+            },
+        }
+    }
+
+    /// Load the avatar of the connected user from the cache, if it's there.
+    async fn load_own_avatar(&mut self) {
+        let Ok(cache) = AvatarCache::new() else {
+            return;
+        };
+        let path = [&self.user_jid, &self.user_profile.phone_jid]
+            .into_iter()
+            .flatten()
+            .find_map(|jid| cache.get_cached_path(jid));
+        let Some(path) = path else {
+            return;
+        };
+
+        match gio::File::for_path(&path).load_bytes_future().await {
+            Ok((bytes, _)) => self.user_profile.avatar = gdk::Texture::from_bytes(&bytes).ok(),
+            Err(e) => tracing::error!("Failed to load own avatar from {path}: {e}"),
+        }
+    }
+
+    /// Show a toast in the window. High priority ones replace the one shown.
+    fn show_toast(&self, text: &str, priority: adw::ToastPriority) {
+        let toast = adw::Toast::new(text);
+        toast.set_priority(priority);
+        self.toaster.add_toast(toast);
+    }
+
+    /// Refresh a chat in the chat list without moving it.
+    fn refresh_chat(&self, chat_jid: &str) {
+        if let Some(chat) = self.chats.iter().find(|c| c.jid == chat_jid) {
+            self.chat_list.emit(ChatListInput::UpdateChat {
+                chat: chat.clone(),
+                move_to_top: false,
+            });
+        }
+    }
+
+    /// Mark a chat as read.
+    async fn mark_chat_read(&mut self, chat_jid: &str) {
+        main_application()
+            .withdraw_notification(&format!("{MESSAGE_NOTIFICATION_PREFIX}{chat_jid}"));
+
+        // Find the chat.
+        if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == chat_jid) {
+            // Collect unread messages before marking them as read locally.
+            let messages = chat.get_unread_messages().await.unwrap_or_default();
+
+            // Separate messages by sender.
+            let mut sender_messages: IndexMap<String, Vec<String>> = IndexMap::new();
+            for message in messages {
+                let sender_jid = message.sender_jid;
+
+                sender_messages
+                    .entry(sender_jid)
+                    .or_default()
+                    .push(message.server_id);
+            }
+
+            // Send read receipts to WhatsApp. They can only be turned off in
+            // private chats, groups and channels always get them.
+            let receipts: Vec<PendingReceipt> = if chat
+                .kind()
+                .sends_read_receipts(self.settings.boolean("send-read-receipts"))
+            {
+                sender_messages
+                    .into_iter()
+                    .map(|(sender_jid, message_ids)| PendingReceipt {
+                        id: Uuid::new_v4(),
+                        attempts: 0,
+                        chat_jid: chat_jid.to_string(),
+                        sender_jid: Some(sender_jid),
+                        message_ids,
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            // Mark chat as read locally, whether the receipts go through or
+            // not: they're retried on their own.
+            let chat_clone = chat.clone();
+            relm4::spawn(async move {
+                if let Err(e) = chat_clone.mark_read().await {
+                    tracing::error!("Failed to mark a chat as read: {e}");
+                }
+            });
+
+            // Update the chat in the chat list.
+            self.chat_list.emit(ChatListInput::UpdateChat {
+                chat: chat.clone(),
+                move_to_top: false,
+            });
+
+            for receipt in receipts {
+                self.send_receipt(receipt).await;
+            }
+        }
+    }
+
+    /// Send read receipts, keeping them until the client reports they've
+    /// been sent so they can be retried.
+    async fn send_receipt(&mut self, receipt: PendingReceipt) {
+        if let Err(e) = self.db.add_pending_receipt(&receipt).await {
+            tracing::error!("Failed to save pending read receipts: {e}");
+        }
+
+        self.resend_receipt(receipt).await;
+    }
+
+    /// Send read receipts kept from before, unless they're being sent already
+    /// or shouldn't be sent anymore.
+    async fn resend_receipt(&mut self, receipt: PendingReceipt) {
+        // Receipts of private chats queued before the preference was
+        // turned off aren't sent anymore.
+        let enabled = self.settings.boolean("send-read-receipts");
+        if !ChatKind::from_jid(&receipt.chat_jid).sends_read_receipts(enabled) {
+            if let Err(e) = self.db.remove_pending_receipt(receipt.id).await {
+                tracing::error!("Failed to drop pending read receipts: {e}");
+            }
+            return;
+        }
+
+        if self.receipt_retries.start(receipt.id) {
+            self.client.emit(ClientInput::MarkRead {
+                id: receipt.id,
+                chat_jid: receipt.chat_jid,
+                sender_jid: receipt.sender_jid,
+                message_ids: receipt.message_ids,
+            });
+        }
+    }
+}
+
+relm4::new_action_group!(pub(super) WindowActionGroup, "win");
+relm4::new_stateless_action!(ContactsAction, WindowActionGroup, "show-contacts");
+relm4::new_stateless_action!(BookmarksAction, WindowActionGroup, "show-bookmarks");
+relm4::new_stateless_action!(pub(super) PreferencesAction, WindowActionGroup, "show-preferences");
+relm4::new_stateless_action!(pub(super) ShortcutsAction, WindowActionGroup, "show-help-overlay");
+relm4::new_stateless_action!(AboutAction, WindowActionGroup, "about");
+relm4::new_stateless_action!(ReportProblemAction, WindowActionGroup, "report-problem");
+relm4::new_stateless_action!(pub(super) QuitAction, WindowActionGroup, "quit");
+relm4::new_stateless_action!(pub(super) ChatSwitcherAction, WindowActionGroup, "show-chat-switcher");
+relm4::new_stateless_action!(pub(super) NextChatAction, WindowActionGroup, "next-chat");
+relm4::new_stateless_action!(pub(super) PreviousChatAction, WindowActionGroup, "previous-chat");
+#[cfg(feature = "message-injector")]
+relm4::new_stateless_action!(InjectMessageAction, WindowActionGroup, "inject-message");
+relm4::new_stateful_action!(
+    pub(super) DoNotDisturbAction,
+    WindowActionGroup,
+    "do-not-disturb",
+    (),
+    bool
+);
+relm4::new_stateful_action!(
+    DoNotDisturbForAction,
+    WindowActionGroup,
+    "do-not-disturb-for",
+    u32,
+    ()
+);
+relm4::new_stateful_action!(
+    pub(super) FocusModeAction,
+    WindowActionGroup,
+    "focus-mode",
+    (),
+    bool
+);
+relm4::new_stateful_action!(
+    FocusModeForAction,
+    WindowActionGroup,
+    "focus-mode-for",
+    u32,
+    ()
+);
+
+#[relm4::component(async, pub)]
+impl AsyncComponent for Application {
+    type Init = ();
+    type Input = AppMsg;
+    type Output = ();
+    type Widgets = AppWidgets;
+    type CommandOutput = AppCmd;
+
+    menu! {
+        primary_menu: {
+            section! {
+                &i18n!("_Contacts") => ContactsAction,
+                &i18n!("_Bookmarks") => BookmarksAction,
+            },
+            section! {
+                &i18n!("_Do Not Disturb") => DoNotDisturbAction,
+                &i18n!("Do Not Disturb _For") {
+                    &i18n!("_30 Minutes") => DoNotDisturbForAction(30),
+                    &i18n!("_1 Hour") => DoNotDisturbForAction(60),
+                    &i18n!("_8 Hours") => DoNotDisturbForAction(8 * 60),
+                },
+                &i18n!("_Focus Mode") => FocusModeAction,
+                &i18n!("Focus Mode F_or") {
+                    &i18n!("_30 Minutes") => FocusModeForAction(30),
+                    &i18n!("_1 Hour") => FocusModeForAction(60),
+                    &i18n!("_2 Hours") => FocusModeForAction(2 * 60),
+                }
+            },
+            section! {
+                &i18n!("_Preferences") => PreferencesAction,
+                &i18n!("_Keyboard Shortcuts") => ShortcutsAction,
+                &i18n!("_Report a Problem") => ReportProblemAction,
+                &i18n!("_About Papo") => AboutAction,
+                &i18n!("_Quit") => QuitAction,
+            }
+        }
+    }
+
+    view! {
+        #[root]
+        main_window = adw::ApplicationWindow::new(&main_application()) {
+            set_title: Some(&i18n!("Papo")),
+            set_visible: true,
+            set_width_request: 360,
+            set_height_request: 440,
+            set_default_width: 900,
+            set_default_height: 850,
+
+            connect_close_request[sender] => move |_| {
+                sender.input(AppMsg::CloseWindow);
+                glib::Propagation::Stop
+            },
+            connect_is_active_notify[sender] => move |window| {
+                if window.is_active() {
+                    sender.input(AppMsg::WindowFocused);
+                }
+            },
+
+            add_css_class?: (PROFILE == "Devel").then_some("devel"),
+
+            #[local_ref]
+            toast_overlay -> adw::ToastOverlay {
+                #[name = "main_stack"]
+                gtk::Stack {
+                    set_transition_type: gtk::StackTransitionType::Crossfade,
+
+                    add_named[Some("fetching")] = &adw::ToolbarView {
+                        add_top_bar = &adw::HeaderBar {
+                            pack_end = &gtk::Button {
+                                set_icon_name: icon_or_fallback("info-outline-symbolic", "help-about-symbolic"),
+                                set_action_name: Some("win.about"),
+                                set_tooltip_text: Some(&i18n!("About Papo")),
+                            }
+                        },
+
+                        #[wrap(Some)]
+                        set_content = &gtk::Box {
+                            set_halign: gtk::Align::Center,
+                            set_valign: gtk::Align::Center,
+                            set_vexpand: true,
+                            set_spacing: 24,
+                            set_orientation: gtk::Orientation::Vertical,
+
+                            gtk::Label {
+                                set_label: &i18n!("Fetching account data..."),
+                                set_halign: gtk::Align::Center,
+                                set_justify: gtk::Justification::Center,
+                                set_css_classes: &["title-2"],
+
+                                set_wrap: true,
+                                set_wrap_mode: pango::WrapMode::WordChar
+                            },
+
+                            adw::Spinner {
+                                set_width_request: 48,
+                                set_height_request: 48
+                            }
+                        }
+                    },
+
+                    #[local_ref]
+                    add_named[Some("login")] = login_widget -> adw::ToolbarView {},
+
+                    add_named[Some("syncing")] = &adw::ToolbarView {
+                        add_top_bar = &adw::HeaderBar {
+                            pack_end = &gtk::Button {
+                                set_icon_name: icon_or_fallback("info-outline-symbolic", "help-about-symbolic"),
+                                set_action_name: Some("win.about"),
+                                set_tooltip_text: Some(&i18n!("About Papo")),
+                            }
+                        },
+
+                        #[wrap(Some)]
+                        set_content = &adw::StatusPage {
+                            set_title: &i18n!("Setting Up Your Chats"),
+                            set_description: Some(&i18n!("This can take a few minutes")),
+
+                            #[wrap(Some)]
+                            set_child = &adw::Spinner {
+                                set_halign: gtk::Align::Center,
+                                set_width_request: 48,
+                                set_height_request: 48,
+                            },
+                        },
+                    },
+
+                    add_named[Some("session")] = &adw::BreakpointBin {
+                        set_width_request: main_window.width_request(),
+                        set_height_request: main_window.height_request(),
+
+                        #[local_ref]
+                        #[wrap(Some)]
+                        set_child = split_view -> adw::NavigationSplitView {
+                            set_min_sidebar_width: 280.0,
+                            set_max_sidebar_width: 360.0,
+
+                            #[name = "sidebar"]
+                            #[wrap(Some)]
+                            set_sidebar = &adw::NavigationPage {
+                                set_title: &i18n!("Papo"),
+                                set_css_classes: &["background"],
+
+                                #[wrap(Some)]
+                                set_child = &adw::ToolbarView {
+                                    add_top_bar = &adw::HeaderBar {
+                                        set_show_title: false,
+
+                                        pack_start = &gtk::MenuButton {
+                                            set_css_classes: &["flat", "circular"],
+                                            set_tooltip_text: Some(&i18n!("Your profile")),
+
+                                            #[wrap(Some)]
+                                            set_child = &adw::Avatar {
+                                                #[watch]
+                                                set_text: Some(&model.user_push_name.clone().unwrap_or_else(|| i18n!("You"))),
+                                                #[watch]
+                                                set_custom_image: model.user_profile.avatar.as_ref(),
+                                                set_size: 30,
+                                                set_show_initials: true,
+                                            },
+
+                                            // How contacts see the account, from what's known locally.
+                                            #[wrap(Some)]
+                                            set_popover = &gtk::Popover {
+                                                gtk::Box {
+                                                    set_width_request: 260,
+                                                    set_spacing: 12,
+                                                    set_margin_all: 6,
+                                                    set_orientation: gtk::Orientation::Vertical,
+
+                                                    gtk::Label {
+                                                        set_label: &i18n!("Preview"),
+                                                        set_xalign: 0.0,
+                                                        set_css_classes: &["heading"],
+                                                    },
+
+                                                    gtk::Box {
+                                                        set_spacing: 6,
+                                                        set_orientation: gtk::Orientation::Vertical,
+
+                                                        adw::Avatar {
+                                                            #[watch]
+                                                            set_text: Some(&model.user_push_name.clone().unwrap_or_else(|| i18n!("You"))),
+                                                            #[watch]
+                                                            set_custom_image: model.user_profile.avatar.as_ref(),
+                                                            set_size: 80,
+                                                            set_show_initials: true,
+                                                        },
+
+                                                        gtk::Label {
+                                                            #[watch]
+                                                            set_label: &model.user_push_name.clone().unwrap_or_default(),
+                                                            set_css_classes: &["title-3"],
+                                                            set_wrap: true,
+                                                            set_justify: gtk::Justification::Center,
+                                                        },
+
+                                                        gtk::Label {
+                                                            #[watch]
+                                                            set_visible: model.user_profile.about.is_some(),
+                                                            #[watch]
+                                                            set_label: model.user_profile.about.as_deref().unwrap_or_default(),
+                                                            set_wrap: true,
+                                                            set_wrap_mode: pango::WrapMode::WordChar,
+                                                            set_justify: gtk::Justification::Center,
+                                                        },
+
+                                                        gtk::Label {
+                                                            #[watch]
+                                                            set_visible: model.user_profile.phone_jid.is_some(),
+                                                            #[watch]
+                                                            set_label: &model.user_profile.phone_number().unwrap_or_default(),
+                                                            set_css_classes: &["dimmed", "numeric"],
+                                                        },
+                                                    },
+
+                                                    gtk::Button {
+                                                        set_label: &i18n!("_Copy My Number"),
+                                                        set_use_underline: true,
+                                                        #[watch]
+                                                        set_sensitive: model.user_profile.phone_jid.is_some(),
+                                                        connect_clicked => AppMsg::CopyOwnNumber,
+                                                    },
+                                                },
+                                            },
+                                        },
+                                        #[name = "screencast_indicator"]
+                                        pack_start = &gtk::Box {
+                                            gtk::Image {
+                                                #[watch]
+                                                set_visible: model.screencast_active,
+                                                set_icon_name: Some(icon_or_fallback("speaker-0-symbolic", "audio-volume-muted-symbolic")),
+                                                set_tooltip_text: Some(&i18n!("Notifications are muted while the screen is shared")),
+                                                set_css_classes: &["dimmed"],
+                                            },
+                                        },
+                                        pack_start = &gtk::Image {
+                                            #[watch]
+                                            set_visible: model.do_not_disturb,
+                                            set_icon_name: Some(icon_or_fallback("notifications-disabled-symbolic", "preferences-system-notifications-symbolic")),
+                                            set_tooltip_text: Some(&i18n!("Do Not Disturb is on")),
+                                            set_css_classes: &["dimmed"],
+                                        },
+                                        pack_start = &gtk::Button {
+                                            #[watch]
+                                            set_visible: model.focus_mode(),
+                                            set_icon_name: icon_or_fallback("eye-not-looking-symbolic", "view-conceal-symbolic"),
+                                            set_tooltip_text: Some(&i18n!("Turn Off Focus Mode")),
+                                            set_css_classes: &["flat", "circular"],
+                                            connect_clicked => AppMsg::SetFocusMode(false),
+                                        },
+                                        pack_end = &gtk::MenuButton {
+                                            set_icon_name: icon_or_fallback("menu-symbolic", "open-menu-symbolic"),
+                                            set_menu_model: Some(&primary_menu),
+                                            set_tooltip_text: Some(&i18n!("Menu")),
+                                        },
+                                    },
+
+                                    add_top_bar = &adw::Banner {
+                                        set_title: &i18n!("Low disk space. Free some up to sync messages and download avatars."),
+                                        set_button_label: Some(&i18n!("_Preferences")),
+                                        set_action_name: Some("win.show-preferences"),
+                                        #[watch]
+                                        set_revealed: model.low_on_space,
+                                    },
+
+                                    #[name = "view_stack"]
+                                    #[wrap(Some)]
+                                    set_content = &adw::ViewStack {
+                                        #[local_ref]
+                                        add_titled[Some("chats"), &i18n!("Chats")] = chat_list_widget -> gtk::Box {} -> {
+                                            set_icon_name: Some(icon_or_fallback("chat-bubbles-text-symbolic", "mail-unread-symbolic"))
+                                        },
+
+                                        #[local_ref]
+                                        add_titled[Some("status"), &i18n!("Status")] = status_list_widget -> gtk::Stack {} -> {
+                                            set_icon_name: Some(icon_or_fallback("image-round-symbolic", "image-x-generic-symbolic"))
+                                        },
+                                    },
+
+                                    add_bottom_bar = &adw::ViewSwitcherBar {
+                                        set_stack: Some(&view_stack),
+                                        set_reveal: true
+                                    },
+                                },
+                            },
+
+                            #[name = "content"]
+                            #[wrap(Some)]
+                            set_content = &adw::NavigationPage {
+                                set_title: &i18n!("Chat"),
+                                set_css_classes: &["view"],
+
+                                #[wrap(Some)]
+                                set_child = &gtk::Stack {
+                                    set_transition_type: gtk::StackTransitionType::Crossfade,
+
+                                    add_named[Some("empty")] = &adw::StatusPage {
+                                        set_title: &i18n!("No Chat Selected"),
+                                        set_hexpand: true,
+                                        set_vexpand: true,
+                                        set_can_focus: false,
+                                        set_icon_name: Some(icon_or_fallback("chat-bubbles-empty-symbolic", "mail-read-symbolic")),
+                                        set_description: Some(&i18n!("Select a chat to start chatting"))
+                                    },
+
+                                    #[local_ref]
+                                    add_named[Some("chat-history")] = chat_view_widget -> adw::ToolbarView {},
+
+                                    #[watch]
+                                    set_visible_child_name: model.session_page.as_ref(),
+                                }
+                            }
+                        },
+
+                        add_breakpoint = bp_with_setters(
+                            adw::Breakpoint::new(
+                                adw::BreakpointCondition::new_length(
+                                    adw::BreakpointConditionLengthType::MaxWidth,
+                                    600.0,
+                                    adw::LengthUnit::Sp,
+                                )
+                            ),
+                            &[(split_view, "collapsed", true)]
+                        ),
+                    },
+
+                    add_named[Some("error")] = &adw::ToolbarView {
+                        add_top_bar = &adw::HeaderBar {
+                            pack_end = &gtk::Button {
+                                set_icon_name: icon_or_fallback("info-outline-symbolic", "help-about-symbolic"),
+                                set_action_name: Some("win.about"),
+                                set_tooltip_text: Some(&i18n!("About Papo")),
+                            }
+                        },
+
+                        #[wrap(Some)]
+                        set_content = &adw::StatusPage {
+                            set_title: &i18n!("Something Went Wrong"),
+                            set_icon_name: Some(icon_or_fallback("exclamation-mark-symbolic", "dialog-error-symbolic")),
+                            #[watch]
+                            set_description: match &model.state {
+                                AppState::Error(message) => Some(message.as_str()),
+                                _ => None,
+                            },
+
+                            #[wrap(Some)]
+                            set_child = &gtk::Box {
+                                set_halign: gtk::Align::Center,
+                                set_spacing: 12,
+                                set_orientation: gtk::Orientation::Vertical,
+
+                                gtk::Button {
+                                    set_label: &i18n!("_Retry"),
+                                    set_use_underline: true,
+                                    set_css_classes: &["pill", "suggested-action"],
+                                    connect_clicked => AppMsg::ResetSession,
+                                },
+                                gtk::Button {
+                                    set_label: &i18n!("_Copy Details"),
+                                    set_use_underline: true,
+                                    set_css_classes: &["pill"],
+                                    connect_clicked => AppMsg::CopyErrorDetails,
+                                },
+                            }
+                        }
+                    },
+
+                    #[watch]
+                    set_visible_child_name: model.page.as_ref(),
+                },
+            },
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    async fn init(
+        _init: Self::Init,
+        root: Self::Root,
+        sender: AsyncComponentSender<Self>,
+    ) -> AsyncComponentParts<Self> {
+        // Keep working without saving anything if the database can't be
+        // opened, telling why once the window is up.
+        let (db, db_failed) = match Database::shared().await {
+            Ok(db) => (db, false),
+            Err(e) => {
+                tracing::error!("Failed to open the database: {e}");
+                let db = Database::in_memory()
+                    .await
+                    .expect("Failed to open an in-memory database");
+                (Arc::new(db), true)
+            }
+        };
+        // Users who linked their phone before the checklist existed don't need it.
+        skip_onboarding_if_linked(
+            &gio::Settings::new(APP_ID),
+            DATA_DIR.join("whatsapp.db").exists(),
+        );
+
+        let login =
+            Login::builder()
+                .launch(())
+                .forward(sender.input_sender(), |output| match output {
+                    LoginOutput::ResetSession => AppMsg::ResetSession,
+
+                    LoginOutput::PairWithPhoneNumber { phone_number } => {
+                        AppMsg::PairWithPhoneNumber { phone_number }
+                    }
+                    LoginOutput::ShowToast(message) => AppMsg::ShowToast(message),
+                });
+
+        let client = Client::builder()
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                ClientOutput::Connected { jid, push_name } => AppMsg::Connected { jid, push_name },
+                ClientOutput::LoggedOut => AppMsg::LoggedOut,
+                ClientOutput::Disconnected => AppMsg::Disconnected,
+                ClientOutput::SelfPushNameUpdated { push_name } => {
+                    AppMsg::SelfPushNameUpdated { push_name }
+                }
+
+                ClientOutput::PairCode {
+                    code,
+                    qr_code,
+                    timeout,
+                } => AppMsg::PairDevice {
+                    code,
+                    qr_code,
+                    timeout,
+                },
+                ClientOutput::PairSuccess => AppMsg::DevicePaired,
+
+                ClientOutput::ReceiptUpdate {
+                    chat_jid,
+                    message_ids,
+                    receipt_type,
+                } => AppMsg::ReceiptUpdate {
+                    chat_jid,
+                    message_ids,
+                    receipt_type,
+                },
+                ClientOutput::PresenceUpdate {
+                    jid,
+                    available,
+                    last_seen,
+                } => AppMsg::PresenceUpdate {
+                    jid,
+                    available,
+                    last_seen,
+                },
+
+                ClientOutput::MessageReceived {
+                    info,
+                    message,
+                    ticket,
+                } => AppMsg::MessageReceived {
+                    info,
+                    message,
+                    ticket,
+                },
+                ClientOutput::MarkedRead { id, result } => AppMsg::MarkedRead { id, result },
+                ClientOutput::OwnProfile { phone_jid, about } => {
+                    AppMsg::OwnProfile { phone_jid, about }
+                }
+                ClientOutput::NumberChecked {
+                    phone_number,
+                    result,
+                } => AppMsg::NumberChecked {
+                    phone_number,
+                    result,
+                },
+                ClientOutput::UndecryptableMessage { chat_jid, msg_id } => {
+                    AppMsg::UndecryptableMessage { chat_jid, msg_id }
+                }
+                ClientOutput::GroupChanged {
+                    chat_jid,
+                    id,
+                    timestamp,
+                    event,
+                } => AppMsg::GroupChanged {
+                    chat_jid,
+                    id,
+                    timestamp,
+                    event,
+                },
+                ClientOutput::ParticipantsFetched { jid, participants } => {
+                    AppMsg::ParticipantsFetched { jid, participants }
+                }
+                ClientOutput::MessageSent {
+                    chat_jid,
+                    msg_id,
+                    preview,
+                } => AppMsg::MessageStatusUpdate {
+                    chat_jid,
+                    msg_id,
+                    status: MessageStatus::Sent,
+                    preview,
+                },
+                ClientOutput::MessageFailed { chat_jid, msg_id } => AppMsg::MessageStatusUpdate {
+                    chat_jid,
+                    msg_id,
+                    status: MessageStatus::Failed,
+                    preview: None,
+                },
+
+                ClientOutput::ChatSynced {
+                    jid,
+                    name,
+                    alt_jid,
+                    pinned,
+                    archived,
+                    unread_count,
+                    participants,
+                    admins,
+                    mute_end_time,
+                    last_message_time,
+                    participant_count,
+                    modified_at,
+                } => AppMsg::ChatSynced {
+                    jid,
+                    name,
+                    alt_jid,
+                    pinned,
+                    archived,
+                    unread_count,
+                    participants,
+                    admins,
+                    mute_end_time,
+                    last_message_time,
+                    participant_count,
+                    modified_at,
+                },
+
+                ClientOutput::MessagesSynced {
+                    chat_jid,
+                    messages,
+                    ticket,
+                } => AppMsg::MessagesSynced {
+                    chat_jid,
+                    messages,
+                    ticket,
+                },
+
+                ClientOutput::ChatPropertyUpdate {
+                    jid,
+                    pinned,
+                    muted,
+                    mute_end,
+                    archived,
+                } => AppMsg::ChatPropertyUpdate {
+                    jid,
+                    pinned,
+                    muted,
+                    mute_end,
+                    archived,
+                },
+
+                ClientOutput::Syncing => AppMsg::Syncing,
+                ClientOutput::HistorySyncCompleted => AppMsg::HistorySyncCompleted,
+                ClientOutput::OfflineSyncCompleted => AppMsg::OfflineSyncCompleted,
+
+                ClientOutput::ContactUpdate {
+                    jid,
+                    name,
+                    push_name,
+                    phone_number,
+                } => AppMsg::ContactUpdate {
+                    jid,
+                    name,
+                    push_name,
+                    phone_number,
+                },
+
+                ClientOutput::AvatarUpdate { jid, path } => AppMsg::AvatarUpdate { jid, path },
+
+                ClientOutput::CallStarted {
+                    call_id,
+                    jid,
+                    is_video,
+                } => AppMsg::CallStarted {
+                    call_id,
+                    jid,
+                    is_video,
+                },
+                ClientOutput::JoinRequested {
+                    chat_jid,
+                    participants,
+                    timestamp,
+                } => AppMsg::JoinRequested {
+                    chat_jid,
+                    participants,
+                    timestamp,
+                },
+                ClientOutput::JoinRequestsWithdrawn {
+                    chat_jid,
+                    participants,
+                } => AppMsg::JoinRequestsWithdrawn {
+                    chat_jid,
+                    participants,
+                },
+                ClientOutput::JoinRequestsFetched { chat_jid, requests } => {
+                    AppMsg::JoinRequestsFetched { chat_jid, requests }
+                }
+                ClientOutput::JoinRequestsResolved {
+                    chat_jid,
+                    participants,
+                    approved,
+                } => AppMsg::JoinRequestsResolved {
+                    chat_jid,
+                    participants,
+                    approved,
+                },
+                ClientOutput::JoinRequestsFailed { chat_jid } => {
+                    AppMsg::JoinRequestsFailed(chat_jid)
+                }
+                ClientOutput::MediaDownloaded {
+                    chat_jid,
+                    message_id,
+                    data,
+                } => AppMsg::MediaDownloaded {
+                    chat_jid,
+                    msg_id: message_id,
+                    data,
+                },
+                ClientOutput::MediaDownloadFailed {
+                    chat_jid,
+                    message_id,
+                } => AppMsg::MediaDownloadFailed {
+                    chat_jid,
+                    msg_id: message_id,
+                },
+
+                ClientOutput::CallFailed { jid } => AppMsg::CallFailed(jid),
+                ClientOutput::CallAccepted { call_id } => AppMsg::CallAccepted(call_id),
+                ClientOutput::CallOffer {
+                    call_id,
+                    from_jid,
+                    is_video,
+                } => AppMsg::CallOffer {
+                    call_id,
+                    from_jid,
+                    is_video,
+                },
+                ClientOutput::CallEnded { call_id } => AppMsg::CallEnded(call_id),
+
+                ClientOutput::Error(error) => AppMsg::Error(error),
+                _ => AppMsg::Unknown,
+            });
+
+        let chat_list = ChatList::builder()
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                ChatListOutput::ChatSelected(jid) => AppMsg::ChatSelected(jid),
+                ChatListOutput::Reconnect => AppMsg::Reconnect,
+                ChatListOutput::TestNotification => AppMsg::TestNotification,
+                ChatListOutput::StartChat => AppMsg::ShowChatSwitcher,
+            });
+        let status_list = StatusList::builder().launch(()).forward(
+            sender.input_sender(),
+            |output| match output {
+                StatusListOutput::Viewed { id, sender_jid } => {
+                    AppMsg::StatusViewed { id, sender_jid }
+                }
+                StatusListOutput::Expired(before) => AppMsg::StatusesExpired(before),
+            },
+        );
+        let chat_view = ChatView::builder()
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                ChatViewOutput::ChatOpen => AppMsg::ChatOpen,
+                ChatViewOutput::ChatClosed => AppMsg::ChatClosed,
+                ChatViewOutput::MarkChatRead(jid) => AppMsg::MarkChatRead(jid),
+                ChatViewOutput::SaveContact {
+                    jid,
+                    name,
+                    phone_number,
+                } => AppMsg::SaveContact {
+                    jid,
+                    name,
+                    phone_number: Some(phone_number),
+                },
+                ChatViewOutput::UnpinMessage { chat_jid, msg_id } => AppMsg::SetMessagePinned {
+                    chat_jid,
+                    msg_id,
+                    pinned: false,
+                },
+                ChatViewOutput::ShowGallery { jid, name } => AppMsg::ShowChatGallery { jid, name },
+                ChatViewOutput::StartCall { jid, is_video } => AppMsg::StartCall { jid, is_video },
+                ChatViewOutput::ShowJoinRequests { jid, name } => {
+                    AppMsg::ShowJoinRequests { jid, name }
+                }
+                ChatViewOutput::ClearHistory { jid, name } => AppMsg::ClearHistory { jid, name },
+                ChatViewOutput::RestoreHistory(jid) => AppMsg::RestoreHistory(jid),
+                ChatViewOutput::ForwardMessages(texts) => AppMsg::ForwardMessages(texts),
+                ChatViewOutput::BookmarkMessages { chat_jid, msg_ids } => {
+                    AppMsg::BookmarkMessages { chat_jid, msg_ids }
+                }
+                ChatViewOutput::DeleteMessages { chat_jid, msg_ids } => {
+                    AppMsg::DeleteMessages { chat_jid, msg_ids }
+                }
+                ChatViewOutput::ShowToast(message) => AppMsg::ShowToast(message),
+
+                ChatViewOutput::SendTextMessage {
+                    text,
+                    recipient,
+                    quote,
+                } => AppMsg::SendTextMessage {
+                    text,
+                    recipient,
+                    quote,
+                },
+            });
+
+        let focus_mode_action = {
+            let sender = sender.clone();
+            RelmAction::<FocusModeAction>::new_stateful(&false, move |_, enabled| {
+                // The state follows the mode, once it's changed.
+                sender.input(AppMsg::SetFocusMode(!*enabled));
+            })
+        };
+        let focus_mode_for_action = {
+            let sender = sender.clone();
+            RelmAction::<FocusModeForAction>::new_with_target_value(move |_, minutes| {
+                sender.input(AppMsg::FocusModeFor(minutes));
+            })
+        };
+
+        let mut model = Self {
+            db,
+            page: AppPage::Fetching,
+            chats: Vec::new(),
+            login,
+            state: AppState::Loading,
+            client,
+            toaster: Toaster::default(),
+            settings: gio::Settings::new(APP_ID),
+            user_jid: None,
+            open_chat: None,
+            chat_list,
+            chat_view,
+            screencast: None,
+            split_view: NavigationSplitView::new(),
+            jid_aliases: JidAliases::default(),
+            status_list,
+            pending_read: None,
+            pending_chat: None,
+            unread_total: UnreadTotal::default(),
+            sync_deadline: SyncDeadline::default(),
+            session_page: AppSessionPage::Empty,
+            chat_switcher: None,
+            forwarding: None,
+            opening_number: None,
+            bookmarks_dialog: None,
+            #[cfg(feature = "message-injector")]
+            message_injector: None,
+            image_viewer: None,
+            chat_gallery: None,
+            call_dialog: None,
+            join_requests_dialog: None,
+            incoming_call: None,
+            pending_references: PendingReferences::default(),
+            downloading: HashSet::new(),
+            receipt_retries: ReceiptRetries::default(),
+            user_push_name: None,
+            user_profile: UserProfile::default(),
+            screencast_active: false,
+            do_not_disturb: false,
+            focus_mode_until: None,
+            focus_mode_action: focus_mode_action.gio_action().clone(),
+            low_on_space: false,
+            last_sort_key: 0,
+        };
+
+        let split_view = &model.split_view;
+        let login_widget = model.login.widget();
+        let toast_overlay = model.toaster.overlay_widget();
+        let chat_list_widget = model.chat_list.widget();
+        let status_list_widget = model.status_list.widget();
+        let chat_view_widget = model.chat_view.widget();
+
+        let app = root.application().unwrap();
+        let mut actions = RelmActionGroup::<WindowActionGroup>::new();
+
+        let preferences_action = {
+            let db = Arc::clone(&model.db);
+            RelmAction::<PreferencesAction>::new_stateless(move |_| {
+                PreferencesDialog::builder()
+                    .launch(Arc::clone(&db))
+                    .detach();
+            })
+        };
+
+        let shortcuts_action = {
+            RelmAction::<ShortcutsAction>::new_stateless(move |_| {
+                ShortcutsDialog::builder().launch(()).detach();
+            })
+        };
+
+        let about_action = {
+            RelmAction::<AboutAction>::new_stateless(move |_| {
+                AboutDialog::builder().launch(()).detach();
+            })
+        };
+
+        let bookmarks_action = {
+            let sender = sender.clone();
+            RelmAction::<BookmarksAction>::new_stateless(move |_| {
+                sender.input(AppMsg::ShowBookmarks);
+            })
+        };
+
+        let report_problem_action = {
+            let sender = sender.clone();
+            RelmAction::<ReportProblemAction>::new_stateless(move |_| {
+                sender.input(AppMsg::ReportProblem);
+            })
+        };
+
+        let quit_action = {
+            let sender = sender.clone();
+            RelmAction::<QuitAction>::new_stateless(move |_| {
+                sender.input(AppMsg::Quit);
+            })
+        };
+
+        let chat_switcher_action = {
+            let sender = sender.clone();
+            RelmAction::<ChatSwitcherAction>::new_stateless(move |_| {
+                sender.input(AppMsg::ShowChatSwitcher);
+            })
+        };
+
+        let next_chat_action = {
+            let chat_list_sender = model.chat_list.sender().clone();
+            RelmAction::<NextChatAction>::new_stateless(move |_| {
+                chat_list_sender.emit(ChatListInput::SelectNext);
+            })
+        };
+
+        let previous_chat_action = {
+            let chat_list_sender = model.chat_list.sender().clone();
+            RelmAction::<PreviousChatAction>::new_stateless(move |_| {
+                chat_list_sender.emit(ChatListInput::SelectPrevious);
+            })
+        };
+
+        let do_not_disturb_action = {
+            let sender = sender.clone();
+            RelmAction::<DoNotDisturbAction>::new_stateful(&false, move |_, enabled| {
+                // The state follows the setting, once it's changed.
+                sender.input(AppMsg::SetDoNotDisturb(!*enabled));
+            })
+        };
+
+        let do_not_disturb_for_action = {
+            let sender = sender.clone();
+            RelmAction::<DoNotDisturbForAction>::new_with_target_value(move |_, minutes| {
+                sender.input(AppMsg::DoNotDisturbFor(minutes));
+            })
+        };
+
+        // Follow Do Not Disturb from the setting, so it survives restarts.
+        let gio_action = do_not_disturb_action.gio_action().clone();
+        let input_sender = sender.input_sender().clone();
+        model
+            .settings
+            .connect_changed(Some("do-not-disturb-until"), move |settings, _| {
+                gio_action.set_state(&do_not_disturb_active(settings).to_variant());
+                input_sender.emit(AppMsg::DoNotDisturbChanged);
+            });
+        do_not_disturb_action
+            .gio_action()
+            .set_state(&do_not_disturb_active(&model.settings).to_variant());
+        sender.input(AppMsg::DoNotDisturbChanged);
+
+        // Ask again when running in the background is turned on, and on every
+        // start as the portal expects.
+        let input_sender = sender.input_sender().clone();
+        model
+            .settings
+            .connect_changed(Some("run-in-background"), move |settings, key| {
+                if settings.boolean(key) {
+                    input_sender.emit(AppMsg::RequestBackground);
+                }
+            });
+        if model.settings.boolean("run-in-background") {
+            sender.input(AppMsg::RequestBackground);
+        }
+
+        let input_sender = sender.input_sender().clone();
+        model
+            .settings
+            .connect_changed(Some("launcher-badge"), move |_, _| {
+                input_sender.emit(AppMsg::LauncherBadgeChanged);
+            });
+
+        // Warn about a disk about to fill up before syncing writes to it.
+        let input_sender = sender.input_sender().clone();
+        model
+            .settings
+            .connect_changed(Some("low-disk-space-threshold"), move |_, _| {
+                input_sender.emit(AppMsg::CheckDiskSpace);
+            });
+        sender.input(AppMsg::CheckDiskSpace);
+
+        // Bring the window back when activated while running in the background.
+        let window = root.clone();
+        app.connect_activate(move |_| window.present());
+
+        // Vote in polls from the chat history.
+        let vote_action = gio::SimpleAction::new(
+            "vote-poll",
+            Some(&<(String, String, String)>::static_variant_type()),
+        );
+        let vote_sender = sender.clone();
+        vote_action.connect_activate(move |_, parameter| {
+            if let Some((chat_jid, msg_id, option)) =
+                parameter.and_then(glib::Variant::get::<(String, String, String)>)
+            {
+                vote_sender.input(AppMsg::VotePoll {
+                    chat_jid,
+                    msg_id,
+                    option,
+                });
+            }
+        });
+        app.add_action(&vote_action);
+
+        // React to messages from the chat history.
+        let react_action = gio::SimpleAction::new(
+            "react",
+            Some(&<(String, String, String)>::static_variant_type()),
+        );
+        let react_sender = sender.clone();
+        react_action.connect_activate(move |_, parameter| {
+            if let Some((chat_jid, msg_id, emoji)) =
+                parameter.and_then(glib::Variant::get::<(String, String, String)>)
+            {
+                react_sender.input(AppMsg::React {
+                    chat_jid,
+                    msg_id,
+                    emoji,
+                });
+            }
+        });
+        app.add_action(&react_action);
+
+        // Pin messages from the chat history.
+        let pin_action = gio::SimpleAction::new(
+            "pin-message",
+            Some(&<(String, String)>::static_variant_type()),
+        );
+        let pin_sender = sender.clone();
+        pin_action.connect_activate(move |_, parameter| {
+            if let Some((chat_jid, msg_id)) =
+                parameter.and_then(glib::Variant::get::<(String, String)>)
+            {
+                pin_sender.input(AppMsg::SetMessagePinned {
+                    chat_jid,
+                    msg_id,
+                    pinned: true,
+                });
+            }
+        });
+        app.add_action(&pin_action);
+
+        // Bookmark messages from the chat history on this device.
+        let bookmark_action = gio::SimpleAction::new(
+            "bookmark-message",
+            Some(&<(String, String)>::static_variant_type()),
+        );
+        let bookmark_sender = sender.clone();
+        bookmark_action.connect_activate(move |_, parameter| {
+            if let Some((chat_jid, msg_id)) =
+                parameter.and_then(glib::Variant::get::<(String, String)>)
+            {
+                bookmark_sender.input(AppMsg::BookmarkMessage { chat_jid, msg_id });
+            }
+        });
+        app.add_action(&bookmark_action);
+
+        // Reply to messages from the chat history.
+        let reply_action = gio::SimpleAction::new(
+            "reply-message",
+            Some(&<(String, String)>::static_variant_type()),
+        );
+        let reply_sender = sender.clone();
+        reply_action.connect_activate(move |_, parameter| {
+            if let Some((chat_jid, msg_id)) =
+                parameter.and_then(glib::Variant::get::<(String, String)>)
+            {
+                reply_sender.input(AppMsg::ReplyToMessage { chat_jid, msg_id });
+            }
+        });
+        app.add_action(&reply_action);
+
+        // Jump to the original of a quoted message.
+        let quoted_action = gio::SimpleAction::new(
+            "go-to-quoted",
+            Some(&<(String, String)>::static_variant_type()),
+        );
+        let quoted_sender = sender.clone();
+        quoted_action.connect_activate(move |_, parameter| {
+            if let Some((chat_jid, msg_id)) =
+                parameter.and_then(glib::Variant::get::<(String, String)>)
+            {
+                quoted_sender.input(AppMsg::GoToQuoted { chat_jid, msg_id });
+            }
+        });
+        app.add_action(&quoted_action);
+
+        // Select messages from the chat history for bulk actions.
+        let select_action = gio::SimpleAction::new(
+            "select-message",
+            Some(&<(String, String)>::static_variant_type()),
+        );
+        let select_sender = sender.clone();
+        select_action.connect_activate(move |_, parameter| {
+            if let Some((chat_jid, msg_id)) =
+                parameter.and_then(glib::Variant::get::<(String, String)>)
+            {
+                select_sender.input(AppMsg::SelectMessage { chat_jid, msg_id });
+            }
+        });
+        app.add_action(&select_action);
+
+        // Show the details of a message from the chat history.
+        let message_info_action = gio::SimpleAction::new(
+            "message-info",
+            Some(&<(String, String)>::static_variant_type()),
+        );
+        let message_info_sender = sender.clone();
+        message_info_action.connect_activate(move |_, parameter| {
+            if let Some((chat_jid, msg_id)) =
+                parameter.and_then(glib::Variant::get::<(String, String)>)
+            {
+                message_info_sender.input(AppMsg::ShowMessageInfo { chat_jid, msg_id });
+            }
+        });
+        app.add_action(&message_info_action);
+
+        // View, copy, save and open media from the chat history.
+        let media_actions: [(&str, fn(String, String) -> AppMsg); 5] = [
+            ("view-image", |chat_jid, msg_id| AppMsg::ViewImage {
+                chat_jid,
+                msg_id,
+            }),
+            ("copy-image", |chat_jid, msg_id| AppMsg::CopyImage {
+                chat_jid,
+                msg_id,
+            }),
+            ("save-media", |chat_jid, msg_id| AppMsg::SaveMedia {
+                chat_jid,
+                msg_id,
+            }),
+            ("open-media-with", |chat_jid, msg_id| {
+                AppMsg::OpenMediaWith { chat_jid, msg_id }
+            }),
+            ("download-media", |chat_jid, msg_id| AppMsg::DownloadMedia {
+                chat_jid,
+                msg_id,
+            }),
+        ];
+        for (name, msg) in media_actions {
+            let action =
+                gio::SimpleAction::new(name, Some(&<(String, String)>::static_variant_type()));
+            let sender = sender.clone();
+            action.connect_activate(move |_, parameter| {
+                if let Some((chat_jid, msg_id)) =
+                    parameter.and_then(glib::Variant::get::<(String, String)>)
+                {
+                    sender.input(msg(chat_jid, msg_id));
+                }
+            });
+            app.add_action(&action);
+        }
+
+        // Message or save contacts shared in the chat history.
+        let message_contact_action = gio::SimpleAction::new(
+            "message-contact",
+            Some(&<(String, String)>::static_variant_type()),
+        );
+        let message_contact_sender = sender.clone();
+        message_contact_action.connect_activate(move |_, parameter| {
+            if let Some((jid, name)) = parameter.and_then(glib::Variant::get::<(String, String)>) {
+                message_contact_sender.input(AppMsg::MessageContact { jid, name });
+            }
+        });
+        app.add_action(&message_contact_action);
+
+        // Mark as read and archive chats from the chat list.
+        let mark_chat_read_action =
+            gio::SimpleAction::new("mark-chat-read", Some(glib::VariantTy::STRING));
+        let mark_chat_read_sender = sender.clone();
+        mark_chat_read_action.connect_activate(move |_, parameter| {
+            if let Some(jid) = parameter.and_then(glib::Variant::get::<String>) {
+                mark_chat_read_sender.input(AppMsg::MarkChatReadNow(jid));
+            }
+        });
+        app.add_action(&mark_chat_read_action);
+
+        let archive_chat_action =
+            gio::SimpleAction::new("archive-chat", Some(glib::VariantTy::STRING));
+        let archive_chat_sender = sender.clone();
+        archive_chat_action.connect_activate(move |_, parameter| {
+            if let Some(jid) = parameter.and_then(glib::Variant::get::<String>) {
+                archive_chat_sender.input(AppMsg::ArchiveChat {
+                    jid,
+                    archived: true,
+                });
+            }
+        });
+        app.add_action(&archive_chat_action);
+
+        let delete_chat_action =
+            gio::SimpleAction::new("delete-chat", Some(glib::VariantTy::STRING));
+        let delete_chat_sender = sender.clone();
+        delete_chat_action.connect_activate(move |_, parameter| {
+            if let Some(jid) = parameter.and_then(glib::Variant::get::<String>) {
+                delete_chat_sender.input(AppMsg::DeleteChat(jid));
+            }
+        });
+        app.add_action(&delete_chat_action);
+
+        let save_contact_action = gio::SimpleAction::new(
+            "save-contact",
+            Some(&<(String, String, String)>::static_variant_type()),
+        );
+        let save_contact_sender = sender.clone();
+        save_contact_action.connect_activate(move |_, parameter| {
+            if let Some((jid, name, phone_number)) =
+                parameter.and_then(glib::Variant::get::<(String, String, String)>)
+            {
+                save_contact_sender.input(AppMsg::SaveContact {
+                    jid,
+                    name,
+                    phone_number: Some(phone_number).filter(|n| !n.is_empty()),
+                });
+            }
+        });
+        app.add_action(&save_contact_action);
+
+        // Reorder chats dragged in the chat list.
+        let move_chat_action = gio::SimpleAction::new(
+            "move-chat",
+            Some(&<(String, String)>::static_variant_type()),
+        );
+        let move_chat_sender = sender.clone();
+        move_chat_action.connect_activate(move |_, parameter| {
+            if let Some((jid, target_jid)) =
+                parameter.and_then(glib::Variant::get::<(String, String)>)
+            {
+                move_chat_sender.input(AppMsg::MoveChat { jid, target_jid });
+            }
+        });
+        app.add_action(&move_chat_action);
+
+        // Open locations in the default maps app and links in the browser.
+        for name in ["open-location", "open-link"] {
+            let action = gio::SimpleAction::new(name, Some(glib::VariantTy::STRING));
+            action.connect_activate(|_, parameter| {
+                if let Some(uri) = parameter.and_then(glib::Variant::get::<String>) {
+                    let window = main_application().active_window();
+                    gtk::UriLauncher::new(&uri).launch(
+                        window.as_ref(),
+                        gio::Cancellable::NONE,
+                        move |result| {
+                            if let Err(e) = result {
+                                tracing::error!("Failed to open {uri}: {e}");
+                            }
+                        },
+                    );
+                }
+            });
+            app.add_action(&action);
+        }
+
+        // Open chats from the command line of other instances.
+        let open_chat_action = gio::SimpleAction::new("open-chat", Some(glib::VariantTy::STRING));
+        let open_chat_sender = sender.clone();
+        open_chat_action.connect_activate(move |_, parameter| {
+            if let Some(chat) = parameter.and_then(glib::Variant::get::<String>) {
+                open_chat_sender.input(AppMsg::OpenChat(chat));
+            }
+        });
+        app.add_action(&open_chat_action);
+
+        // Check off the notifications step from the test notification.
+        let notifications_shown_action = gio::SimpleAction::new("notifications-shown", None);
+        let notifications_shown_sender = sender.clone();
+        notifications_shown_action.connect_activate(move |_, _| {
+            notifications_shown_sender.input(AppMsg::NotificationsShown);
+        });
+        app.add_action(&notifications_shown_action);
+        // Answer calls from the notification buttons.
+        for (name, accept) in [("accept-call", true), ("decline-call", false)] {
+            let sender = sender.clone();
+            let action = gio::SimpleAction::new(name, Some(glib::VariantTy::STRING));
+            action.connect_activate(move |_, parameter| {
+                if let Some(call_id) = parameter.and_then(glib::Variant::get::<String>) {
+                    sender.input(AppMsg::AnswerCall { call_id, accept });
+                }
+            });
+            app.add_action(&action);
+        }
+
+        // Connect actions with hotkeys, as listed in the shortcuts dialog.
+        for shortcut in shortcut_sections().into_iter().flat_map(|(_, s)| s) {
+            if let Some(action) = &shortcut.action {
+                app.set_accels_for_action(action, shortcut.accelerators);
+            }
+        }
+
+        let widgets = view_output!();
+
+        // Apply the options from the command line that launched the app.
+        let launch = cli::take_pending();
+        if launch.hidden {
+            root.set_visible(false);
+        }
+        model.pending_chat = launch.chat;
+
+        actions.add_action(preferences_action);
+        actions.add_action(shortcuts_action);
+        actions.add_action(about_action);
+        actions.add_action(report_problem_action);
+        actions.add_action(quit_action);
+        actions.add_action(chat_switcher_action);
+        actions.add_action(bookmarks_action);
+        actions.add_action(next_chat_action);
+        actions.add_action(previous_chat_action);
+        actions.add_action(do_not_disturb_action);
+        actions.add_action(do_not_disturb_for_action);
+        actions.add_action(focus_mode_action);
+        actions.add_action(focus_mode_for_action);
+
+        // Testing tool, left out of release builds.
+        #[cfg(feature = "message-injector")]
+        {
+            let sender = sender.clone();
+            actions.add_action(RelmAction::<InjectMessageAction>::new_stateless(
+                move |_| sender.input(AppMsg::ShowMessageInjector),
+            ));
+            app.set_accels_for_action(&InjectMessageAction::action_name(), &["<Control><Shift>i"]);
+        }
+        actions.register_for_widget(&widgets.main_window);
+
+        widgets.load_window_size();
+
+        if db_failed {
+            let toast = adw::Toast::builder()
+                .title(i18n!(
+                    "Couldn't open the message database, messages won't be kept after closing Papo"
+                ))
+                .priority(adw::ToastPriority::High)
+                .timeout(0)
+                .build();
+            model.toaster.add_toast(toast);
+        }
+
+        // Show that notifications are muted only if muting them is enabled.
+        gio::Settings::new(APP_ID)
+            .bind(
+                "mute-during-screencast",
+                &widgets.screencast_indicator,
+                "visible",
+            )
+            .get()
+            .build();
+
+        let input_sender = sender.input_sender().clone();
+        model.screencast = screencast::watch(move |active| {
+            input_sender.emit(AppMsg::ScreencastChanged(active));
+        })
+        .await
+        .inspect_err(|e| tracing::warn!("Failed to watch screen sharing: {e}"))
+        .ok();
+
+        // Media written for other apps is only needed while they open it.
+        relm4::spawn_blocking(|| {
+            remove_stale_files(&media_cache_dir(), MEDIA_CACHE_MAX_AGE)
+                .inspect_err(|e| tracing::warn!("Failed to clean up the media cache: {e}"))
+        });
+
+        AsyncComponentParts { model, widgets }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    async fn update(
+        &mut self,
+        message: Self::Input,
+        sender: AsyncComponentSender<Self>,
+        root: &Self::Root,
+    ) {
+        match message {
+            AppMsg::Connected { jid, push_name } => {
+                self.chat_view
+                    .emit(ChatViewInput::UserJidChanged(jid.clone()));
+                self.user_jid = jid;
+                self.user_push_name = Some(push_name);
+                self.chat_list.emit(ChatListInput::SetConnectionState(
+                    ConnectionState::Connected,
+                ));
+
+                // Sync in background.
+                sender.oneshot_command(async { AppCmd::Sync });
+                self.client.emit(ClientInput::FetchOwnProfile);
+
+                // Send the read receipts left from before, now that they can be.
+                let db = Arc::clone(&self.db);
+                sender.oneshot_command(async move {
+                    let receipts = db.load_pending_receipts().await.unwrap_or_else(|e| {
+                        tracing::error!("Failed to load pending read receipts: {e}");
+                        Vec::new()
+                    });
+                    AppCmd::RetryReceipts(receipts)
+                });
+
+                // The first sync keeps its page until chats show up.
+                if !matches!(self.page, AppPage::Session | AppPage::Syncing) {
+                    self.page = AppPage::Session;
+                }
+            }
+
+            AppMsg::SyncCompleted {
+                chats_needing_avatars,
+            } => {
+                // Fetch avatars for chats that don't have them.
+                for jid in chats_needing_avatars {
+                    self.client.emit(ClientInput::FetchAvatar { jid });
+                }
+            }
+            AppMsg::LoggedOut => {
+                self.page = AppPage::Fetching;
+                self.state = AppState::Pairing;
+
+                // Start a fresh client — the old credentials have been cleared
+                // by the ClientCommand::LoggedOut handler.
+                self.client.emit(ClientInput::Start);
+
+                let db = self.db.clone();
+                let chats = std::mem::take(&mut self.chats);
+                relm4::spawn(async move {
+                    for chat in chats {
+                        let _ = db.delete_chat(&chat.jid).await;
+                    }
+                });
+            }
+            AppMsg::Disconnected => {
+                self.state = AppState::Disconnected;
+                self.chat_list
+                    .emit(ChatListInput::SetConnectionState(ConnectionState::Offline));
+            }
+            AppMsg::Reconnect => {
+                tracing::info!("Reconnecting");
+                self.chat_list.emit(ChatListInput::SetConnectionState(
+                    ConnectionState::Connecting,
+                ));
+                self.client.emit(ClientInput::Start);
+            }
+            AppMsg::OwnProfile { phone_jid, about } => {
+                self.user_profile.about = about;
+                self.user_profile.phone_jid = phone_jid;
+                self.load_own_avatar().await;
+            }
+            AppMsg::CopyOwnNumber => {
+                if let Some(phone_number) = self.user_profile.phone_number() {
+                    root.clipboard().set_text(&phone_number);
+                    self.show_toast(&i18n!("Number copied"), adw::ToastPriority::Normal);
+                }
+            }
+            AppMsg::SelfPushNameUpdated { push_name } => {
+                self.user_push_name = Some(push_name);
+            }
+            AppMsg::ResetSession => {
+                if self.page == AppPage::Error {
+                    self.page = AppPage::Fetching;
+                }
+
+                self.client.emit(ClientInput::Restart);
+            }
+            AppMsg::CopyErrorDetails => {
+                if let AppState::Error(message) = &self.state {
+                    root.clipboard()
+                        .set_text(&format!("{message}\n\n{}", report::debug_info()));
+                    self.show_toast(&i18n!("Details copied"), adw::ToastPriority::Normal);
+                }
+            }
+
+            AppMsg::PairDevice {
+                code,
+                qr_code,
+                timeout,
+            } => {
+                if self.page == AppPage::Fetching {
+                    self.page = AppPage::Login;
+                }
+
+                self.login.emit(LoginInput::PairCode {
+                    code,
+                    qr_code,
+                    timeout,
+                });
+            }
+            AppMsg::DevicePaired => {
+                self.login.emit(LoginInput::PairSuccess);
+                time::sleep(Duration::from_secs(2)).await;
+
+                self.page = AppPage::Syncing;
+                self.state = AppState::Syncing;
+                self.start_sync_deadline(&sender);
+            }
+            AppMsg::PairWithPhoneNumber { phone_number } => {
+                self.client
+                    .emit(ClientInput::PairWithPhoneNumber { phone_number });
+            }
+
+            AppMsg::ChatOpen => {
+                self.split_view.set_show_content(true);
+                self.session_page = AppSessionPage::ChatHistory;
+            }
+            AppMsg::ChatClosed => {
+                self.pending_read = None;
+                self.open_chat = None;
+                self.chat_list.emit(ChatListInput::ClearSelection);
+                self.split_view.set_show_content(false);
+                self.session_page = AppSessionPage::Empty;
+            }
+            AppMsg::ChatSelected(jid) => {
+                self.pending_read.take_if(|pending| *pending != jid);
+                if let Some(chat) = self.chats.iter().find(|c| c.jid == jid).cloned() {
+                    if chat.is_private() {
+                        self.client
+                            .emit(ClientInput::SubscribePresence { jid: jid.clone() });
+                    } else if chat.has_missing_participants() {
+                        self.client
+                            .emit(ClientInput::FetchParticipants { jid: jid.clone() });
+                    }
+                    self.chat_view.emit(ChatViewInput::Open(chat));
+                    self.open_chat = Some(jid);
+                }
+            }
+            AppMsg::MarkChatRead(jid) => {
+                // Wait for the burst to settle, unless a read is already scheduled.
+                if self.pending_read.replace(jid).is_none() {
+                    sender.oneshot_command(async {
+                        time::sleep(MARK_READ_DELAY).await;
+                        AppCmd::FlushPendingRead
+                    });
+                }
+            }
+            AppMsg::MarkChatReadNow(jid) => {
+                self.pending_read.take_if(|pending| *pending == jid);
+                self.mark_chat_read(&jid).await;
+                self.unread_changed(&sender);
+                self.show_toast(&i18n!("Marked as read"), adw::ToastPriority::Normal);
+            }
+            AppMsg::ArchiveChat { jid, archived } => {
+                sender.input(AppMsg::ChatPropertyUpdate {
+                    jid: jid.clone(),
+                    pinned: None,
+                    muted: None,
+                    mute_end: None,
+                    archived: Some(archived),
+                });
+
+                if archived {
+                    let toast = adw::Toast::builder()
+                        .title(i18n!("Chat archived"))
+                        .button_label(i18n!("_Undo"))
+                        .build();
+                    let sender = sender.clone();
+                    toast.connect_button_clicked(move |_| {
+                        sender.input(AppMsg::ArchiveChat {
+                            jid: jid.clone(),
+                            archived: false,
+                        });
+                    });
+                    self.toaster.add_toast(toast);
+                }
+            }
+            AppMsg::DeleteChat(jid) => {
+                let Some(chat) = self.chats.iter().find(|c| c.jid == jid) else {
+                    return;
+                };
+                let mut body = i18n_f!(
+                    "The messages with {0} will be deleted from this device. Syncing with your phone can bring them back",
+                    chat.get_name_or_number()
+                );
+
+                // Settings and bookmarks outlive the chat, so list them to
+                // choose whether to keep them.
+                let mut local_data = Vec::new();
+                match self.db.count_chat_bookmarks(&jid).await {
+                    Ok(0) => {}
+                    Ok(count) => {
+                        let count = u32::try_from(count).unwrap_or(u32::MAX);
+                        local_data.push(ni18n!("{0} bookmark", "{0} bookmarks", count).replacen(
+                            "{0}",
+                            &count.to_string(),
+                            1,
+                        ));
+                    }
+                    Err(e) => tracing::error!("Failed to count bookmarks in {jid}: {e}"),
+                }
+                if self
+                    .db
+                    .load_chat_settings(&jid)
+                    .await
+                    .is_ok_and(|settings| settings.wallpaper.is_some())
+                {
+                    local_data.push(i18n!("the chat wallpaper"));
+                }
+
+                let keep_button = (!local_data.is_empty()).then(|| {
+                    body = format!(
+                        "{body}\n\n{}",
+                        i18n_f!(
+                            "This device also keeps {0} for the chat",
+                            local_data.join(", ")
+                        )
+                    );
+                    gtk::CheckButton::builder()
+                        .label(i18n!("_Keep Them for When the Chat Comes Back"))
+                        .use_underline(true)
+                        .active(true)
+                        .build()
+                });
+
+                let dialog = adw::AlertDialog::builder()
+                    .heading(i18n!("Delete Chat?"))
+                    .body(body)
+                    .default_response("cancel")
+                    .close_response("cancel")
+                    .build();
+                dialog.set_extra_child(keep_button.as_ref());
+                dialog
+                    .add_responses(&[("cancel", &i18n!("_Cancel")), ("delete", &i18n!("_Delete"))]);
+                dialog.set_response_appearance("delete", adw::ResponseAppearance::Destructive);
+
+                let sender = sender.clone();
+                dialog.connect_response(Some("delete"), move |_, _| {
+                    sender.input(AppMsg::ConfirmDeleteChat {
+                        jid: jid.clone(),
+                        keep_local_data: keep_button
+                            .as_ref()
+                            .is_some_and(CheckButtonExt::is_active),
+                    });
+                });
+                dialog.present(Some(root));
+            }
+            AppMsg::ConfirmDeleteChat {
+                jid,
+                keep_local_data,
+            } => {
+                if self.open_chat.as_ref() == Some(&jid) {
+                    self.chat_view.emit(ChatViewInput::Close);
+                }
+
+                if let Err(e) = self.db.delete_chat(&jid).await {
+                    tracing::error!("Failed to delete chat {jid}: {e}");
+                    self.show_toast(&i18n!("Couldn't delete the chat"), adw::ToastPriority::High);
+                    return;
+                }
+                // Otherwise kept, to be there again when a message recreates the chat.
+                if !keep_local_data && let Err(e) = self.db.delete_chat_local_data(&jid).await {
+                    tracing::error!("Failed to delete local data of chat {jid}: {e}");
+                }
+
+                self.chats.retain(|c| c.jid != jid);
+                self.chat_list.emit(ChatListInput::RemoveChat { jid });
+                self.unread_changed(&sender);
+                self.show_toast(&i18n!("Chat deleted"), adw::ToastPriority::Normal);
+            }
+            AppMsg::ShowToast(message) => self.show_toast(&message, adw::ToastPriority::Normal),
+            AppMsg::WindowFocused => {
+                if self.pending_read.is_some() {
+                    sender.oneshot_command(async {
+                        time::sleep(MARK_READ_DELAY).await;
+                        AppCmd::FlushPendingRead
+                    });
+                }
+            }
+
+            AppMsg::AvatarUpdate { jid, path } => {
+                if self.is_own_jid(&jid) {
+                    self.load_own_avatar().await;
+                }
+
+                // Update the chat's avatar path.
+                if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) {
+                    chat.avatar_path = Some(path);
+
+                    // Update in chat list.
+                    self.chat_list.emit(ChatListInput::UpdateChat {
+                        chat: chat.clone(),
+                        move_to_top: false,
+                    });
+
+                    tracing::info!("Updated avatar for chat: {}", jid);
+                }
+            }
+            AppMsg::ContactUpdate {
+                jid,
+                name,
+                push_name,
+                phone_number,
+            } => {
+                // Save contact to database in background.
+                let db = Arc::clone(&self.db);
+                let jid_for_contact = jid.clone();
+                let name_for_contact = name.clone();
+
+                let contact = Contact {
+                    jid: jid.clone(),
+                    name: name.clone(),
+                    push_name,
+                    phone_number: Some(phone_number),
+                    is_registered: true,
+                };
+
+                relm4::spawn(async move {
+                    if let Err(e) = db.save_contact(&contact).await {
+                        tracing::error!("Failed to save contact {}: {}", jid_for_contact, e);
+                    } else {
+                        tracing::debug!(
+                            "{}",
+                            report::redact(&format!(
+                                "Saved contact: {jid_for_contact} (name: {name_for_contact:?})"
+                            ))
+                        );
+                    }
+                });
+
+                // Update chat name if this contact has a chat and we got a name.
+                if let Some(contact_name) = name
+                    && let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid)
+                {
+                    // Only update if current name is generic (phone number or "Unknown").
+                    let current_name = chat.get_name_or_number();
+                    let is_generic = current_name == contact_name
+                        || current_name == i18n!("Unknown")
+                        || current_name == format_jid(&jid);
+
+                    if is_generic {
+                        chat.name.clone_from(&contact_name);
+                        chat.name_modified_at = Some(Utc::now());
+
+                        // Save updated chat in background.
+                        let jid_clone = jid.clone();
+                        let chat_clone = chat.clone();
+
+                        relm4::spawn(async move {
+                            if let Err(e) = chat_clone.save().await {
+                                tracing::error!(
+                                    "Failed to update chat name for {}: {}",
+                                    jid_clone,
+                                    e
+                                );
+                            }
+                        });
+
+                        // Update in chat list immediately.
+                        self.chat_list.emit(ChatListInput::UpdateChat {
+                            chat: chat.clone(),
+                            move_to_top: false,
+                        });
+
+                        tracing::info!(
+                            "{}",
+                            report::redact(&format!(
+                                "Updated chat name for {jid} to: {contact_name:?}"
+                            ))
+                        );
+                    }
+                }
+            }
+            AppMsg::ReceiptUpdate {
+                chat_jid,
+                message_ids,
+                receipt_type,
+            } => {
+                if let Some(chat) = self.chats.iter().find(|c| c.jid == chat_jid).cloned() {
+                    match MessageStatus::try_from(receipt_type) {
+                        Ok(status) => {
+                            for msg_id in message_ids {
+                                match chat.find_message(&msg_id).await {
+                                    Ok(Some(mut message)) => {
+                                        // Update message status, as receipts may
+                                        // arrive out of order.
+                                        message.status = message.status.max(status);
+
+                                        // Update the message in the database.
+                                        let msg_clone = message.clone();
+                                        relm4::spawn(async move {
+                                            if let Err(e) = msg_clone.save().await {
+                                                tracing::error!("Failed to update message: {}", e);
+                                            }
+                                        });
+                                    }
+                                    // The message may still arrive later through sync.
+                                    Ok(None) => {
+                                        tracing::debug!(
+                                            "Message {msg_id} with a receipt isn't stored, keeping it"
+                                        );
+                                        self.pending_references.entry(msg_id).receipt(status);
+                                    }
+                                    Err(e) => {
+                                        tracing::error!("Failed to find message {msg_id}: {e}");
+                                    }
+                                }
+                            }
+
+                            self.chat_list.emit(ChatListInput::UpdateChat {
+                                chat,
+                                move_to_top: false,
+                            });
+                        }
+                        Err(e) => tracing::error!(
+                            "Failed to convert `ReceiptType` to `MessageStatus`: {e}"
+                        ),
+                    }
+                }
+            }
+            AppMsg::PresenceUpdate {
+                jid,
+                available,
+                last_seen,
+            } => {
+                if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) {
+                    // Only private chats have a presence.
+                    if chat.is_private() {
+                        chat.available = Some(available);
+                        chat.last_seen = last_seen;
+                    }
+
+                    // Update the online dot in the chat list.
+                    self.chat_list.emit(ChatListInput::UpdateChat {
+                        chat: chat.clone(),
+                        move_to_top: false,
+                    });
+                }
+
+                self.chat_view.emit(ChatViewInput::PresenceUpdate {
+                    jid,
+                    available,
+                    last_seen,
+                });
+            }
+            AppMsg::MessageStatusUpdate {
+                chat_jid,
+                msg_id,
+                status,
+                preview,
+            } => {
+                // Get the chat and message altogether.
+                if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == chat_jid)
+                    && let Ok(Some(mut message)) = chat.find_message_by_local_id(&msg_id).await
+                {
+                    // Update the message status in-place.
+                    message.status = status;
+                    if preview.is_some() {
+                        message.preview = preview;
+                    }
+
+                    // Update the message in the database.
+                    let msg_clone = message.clone();
+                    relm4::spawn(async move {
+                        if let Err(e) = msg_clone.save().await {
+                            tracing::error!("Failed to update message: {}", e);
+                        }
+                    });
+
+                    self.chat_view
+                        .emit(ChatViewInput::MessageStatusUpdate(Box::new(message)));
+                }
+
+                if status == MessageStatus::Failed {
+                    self.show_toast(
+                        &i18n!("Couldn't send the message"),
+                        adw::ToastPriority::High,
+                    );
+                }
+
+                // Sound the outcome of sending, if enabled, in the focused window only.
+                if let Some((sound, key)) =
+                    send_outcome_sound(status, root.is_active(), self.notifications_muted())
+                    && gio::Settings::new(APP_ID).boolean(key)
+                {
+                    sound::play(sound);
+                }
+            }
+
+            AppMsg::MessageReceived {
+                info,
+                mut message,
+                ticket: _ticket,
+            } => {
+                // Messages sent from the phone come wrapped with the chat they
+                // were sent to, which is missing for the user's own chat.
+                let mut sent_to = None;
+                if let Some(sent) = message.device_sent_message.take() {
+                    let Some(inner) = sent.message else {
+                        tracing::debug!("Ignoring empty message {} sent from the phone", info.id);
+                        return;
+                    };
+                    message = inner;
+                    sent_to = Some(
+                        sent.destination_jid
+                            .filter(|jid| !jid.is_empty())
+                            .or_else(|| self.user_jid.clone())
+                            .unwrap_or_else(|| info.source.chat.to_string()),
+                    );
+                }
+                let source_chat = sent_to
+                    .clone()
+                    .unwrap_or_else(|| info.source.chat.to_string());
+                let outgoing = info.source.is_from_me || sent_to.is_some();
+
+                // Status updates are addressed to a dedicated broadcast chat.
+                if is_status_jid(&source_chat) {
+                    if let Some(status) = StatusUpdate::from_message(
+                        info.id.clone(),
+                        &message,
+                        info.source.sender.to_string(),
+                        Some(info.push_name.clone()),
+                        info.timestamp,
+                    )
+                    .filter(|status| !outgoing && !status.is_expired(Utc::now()))
+                    {
+                        let db = Arc::clone(&self.db);
+                        let status_clone = status.clone();
+                        relm4::spawn(async move {
+                            if let Err(e) = db.save_status_update(&status_clone).await {
+                                tracing::error!("Failed to save status update: {e}");
+                            }
+                        });
+
+                        self.status_list.emit(StatusListInput::Add(status));
+                    } else {
+                        tracing::debug!(
+                            "Ignoring status update {} from {}",
+                            info.id,
+                            info.source.sender
+                        );
+                    }
+
+                    return;
+                }
+
+                // A message that failed to decrypt has been retried successfully.
+                match self.db.resolve_undecryptable_message(&info.id).await {
+                    Ok(Some(chat_jid)) => self
+                        .chat_view
+                        .emit(ChatViewInput::UndecryptableCountChanged { chat_jid }),
+                    Ok(None) => {}
+                    Err(e) => tracing::error!("Failed to resolve undecryptable message: {e}"),
+                }
+
+                // A message was deleted for everyone.
+                if let Some(ref protocol) = message.protocol_message
+                    && protocol.r#type == Some(protocol_message::Type::Revoke as i32)
+                    && let Some(msg_id) = protocol.key.as_ref().and_then(|k| k.id.clone())
+                {
+                    let chat_jid = self.resolve_chat_jid(&source_chat, None);
+                    let actor = self.message_actor(&info, outgoing);
+                    self.revoke_message(&chat_jid, msg_id, &actor).await;
+                    return;
+                }
+
+                // A message was pinned or unpinned, possibly from another device.
+                if let Some(ref pin) = message.pin_in_chat_message
+                    && let Some(msg_id) = pin.key.as_ref().and_then(|k| k.id.clone())
+                {
+                    let chat_jid = self.resolve_chat_jid(&source_chat, None);
+                    let pinned = pin.r#type == Some(pin_in_chat_message::Type::PinForAll as i32);
+                    let expires_at = message
+                        .message_context_info
+                        .as_ref()
+                        .and_then(|context| context.message_add_on_duration_in_secs)
+                        .filter(|secs| *secs > 0)
+                        .map(|secs| info.timestamp + TimeDelta::seconds(secs.into()));
+                    self.set_message_pinned(&chat_jid, &msg_id, pinned, info.timestamp, expires_at)
+                        .await;
+                    return;
+                }
+
+                // A message was edited by its sender.
+                if let Some(protocol) = message.protocol_message.as_ref().or_else(|| {
+                    message
+                        .edited_message
+                        .as_ref()
+                        .and_then(|future| future.message.as_ref())
+                        .and_then(|m| m.protocol_message.as_ref())
+                }) && protocol.r#type == Some(protocol_message::Type::MessageEdit as i32)
+                    && let Some(msg_id) = protocol.key.as_ref().and_then(|k| k.id.clone())
+                {
+                    let content = protocol
+                        .edited_message
+                        .as_deref()
+                        .and_then(TextContent::from_message)
+                        .map(|content| content.text);
+
+                    if let Some(content) = content {
+                        let chat_jid = self.resolve_chat_jid(&source_chat, None);
+                        let actor = self.message_actor(&info, outgoing);
+                        self.edit_message(&chat_jid, msg_id, actor, content).await;
+                    } else {
+                        tracing::debug!("Ignoring edit without text for message {msg_id}");
+                    }
+                    return;
+                }
+
+                // A reaction to a message, or its removal.
+                if let Some(ref reaction) = message.reaction_message {
+                    let sender_jid = info.source.sender.to_string();
+                    self.receive_reaction(&sender_jid, outgoing, reaction, info.timestamp)
+                        .await;
+                    return;
+                }
+
+                // A vote in a poll, encrypted with the poll's secret.
+                if let Some(ref update) = message.poll_update_message {
+                    let chat_jid = self.resolve_chat_jid(&source_chat, None);
+                    let voter_jid = info.source.sender.to_string();
+                    self.receive_poll_vote(&chat_jid, &voter_jid, outgoing, update)
+                        .await;
+                    return;
+                }
+
+                let content = MessageContent::from_message(&message);
+                let mentions = content
+                    .text
+                    .as_ref()
+                    .map(|text| text.mentions.clone())
+                    .unwrap_or_default();
+
+                if let Some(text) = content.display_text() {
+                    // The other side of a direct chat may be addressed by its
+                    // alternate (PN or LID) JID.
+                    let alt_jid = if info.source.is_group || sent_to.is_some() {
+                        None
+                    } else if outgoing {
+                        info.source.recipient_alt.as_ref()
+                    } else {
+                        info.source.sender_alt.as_ref()
+                    };
+                    let chat_jid = self.resolve_chat_jid(
+                        &source_chat,
+                        alt_jid.map(ToString::to_string).as_deref(),
+                    );
+
+                    let left = self.chats.iter().any(|c| c.jid == chat_jid && c.has_left());
+                    let status = MessageStatus::received(outgoing, left);
+                    let mut chat_message = ChatMessage {
+                        local_id: Uuid::new_v4(),
+                        server_id: info.id.clone(),
+                        chat_jid: chat_jid.clone(),
+                        sender_jid: sent_to
+                            .as_ref()
+                            .and(self.user_jid.clone())
+                            .unwrap_or_else(|| info.source.sender.to_string()),
+                        sender_name: Some(info.push_name.clone()),
+
+                        kind: MessageKind::Regular,
+                        poll: None,
+                        media: None,
+                        edited: false,
+                        status,
+                        content: text,
+                        revoked: false,
+                        contacts: Vec::new(),
+                        location: None,
+                        quote: None,
+                        preview: None,
+                        outgoing,
+                        reactions: IndexMap::new(),
+                        timestamp: info.timestamp,
+
+                        db: Arc::clone(&self.db),
+                    };
+                    content.apply(&mut chat_message);
+                    if let Some(quote) = chat_message.quote.take() {
+                        chat_message.quote = Some(self.resolve_quote(&chat_jid, quote).await);
+                    }
+                    let kind = chat_message.kind;
+
+                    self.add_message(&chat_jid, chat_message.clone());
+                    self.unread_changed(&sender);
+
+                    // Fetch the full media without asking when the
+                    // preferences allow it, otherwise its bubble offers to.
+                    if let Some(ref media) = chat_message.media
+                        && media.should_auto_download(&AutoDownload::from_settings(&self.settings))
+                    {
+                        self.download_media(&chat_jid, &chat_message.server_id, media);
+                    }
+
+                    let focused = root.is_active();
+                    if let Some(chat) = self.chats.iter().find(|c| c.jid == chat_jid)
+                        && self.should_notify(chat, &chat_message, &mentions, focused)
+                    {
+                        Self::sound_received_message(kind, focused);
+                        if !focused && !self.focus_mode() {
+                            Self::notify_message(chat, &chat_message);
+                        }
+                    }
+                } else {
+                    tracing::trace!(
+                        "Message without content received: info = {:#?}, message = {:#?}",
+                        info,
+                        message
+                    );
+                }
+            }
+
+            AppMsg::SendTextMessage {
+                text,
+                recipient,
+                quote,
+            } => {
+                // Get the chat if it exists and is loaded.
+                if let Some(chat) = self.chats.iter().find(|c| c.jid == recipient).cloned() {
+                    let timestamp = Utc::now();
+                    let message = ChatMessage {
+                        local_id: Uuid::new_v4(),
+                        server_id: String::new(), // will be replaced later by the client.
+                        chat_jid: recipient,
+                        sender_jid: self.user_jid.clone().unwrap_or_default(),
+                        sender_name: self.user_push_name.clone(),
+
+                        kind: MessageKind::Regular,
+                        poll: None,
+                        media: None,
+                        edited: false,
+                        status: MessageStatus::Sending,
+                        content: text,
+                        revoked: false,
+                        contacts: Vec::new(),
+                        location: None,
+                        quote,
+                        preview: None,
+                        outgoing: true,
+                        reactions: IndexMap::new(),
+                        timestamp,
+
+                        db: self.db.clone(),
+                    };
+
+                    // Save the message in the database.
+                    let msg_clone = message.clone();
+                    relm4::spawn(async move {
+                        if let Err(e) = msg_clone.save().await {
+                            tracing::error!("Failed to save message: {}", e);
+                        }
+                    });
+
+                    self.client.emit(ClientInput::SendMessage {
+                        message: Box::new(message.clone()),
+                        fetch_preview: self.settings.boolean("link-previews"),
+                    });
+                    // Forwarded messages can be sent to other chats than the open one.
+                    if self.open_chat.as_deref() == Some(chat.jid.as_str()) {
+                        self.chat_view
+                            .emit(ChatViewInput::MessageReceived(Box::new(message)));
+                    }
+
+                    OnboardingStep::StartChat.complete(&self.settings);
+                    self.bump_chat(&chat.jid);
+                    self.chat_list.emit(ChatListInput::UpdateChat {
+                        chat,
+                        move_to_top: true,
+                    });
+                }
+            }
+            AppMsg::MessageContact { jid, name } => {
+                if !self.chats.iter().any(|c| c.jid == jid) {
+                    self.add_chat(Chat {
+                        jid: jid.clone(),
+                        name,
+                        muted: false,
+                        muted_until: None,
+                        pinned: false,
+                        archived: false,
+                        membership: Membership::Member,
+                        available: None,
+                        last_seen: None,
+                        avatar_path: None,
+                        participants: HashMap::new(),
+                        last_message_time: Utc::now(),
+                        participant_count: None,
+                        name_modified_at: None,
+                        flags_modified_at: None,
+                        pin_order: None,
+                        sort_key: Utc::now().timestamp_millis(),
+
+                        db: Arc::clone(&self.db),
+                    });
+
+                    self.client
+                        .emit(ClientInput::FetchAvatar { jid: jid.clone() });
+                }
+
+                OnboardingStep::StartChat.complete(&self.settings);
+                self.chat_list.emit(ChatListInput::Select(jid));
+            }
+            AppMsg::SaveContact {
+                jid,
+                name,
+                phone_number,
+            } => {
+                let contact = Contact {
+                    jid,
+                    name: Some(name),
+                    push_name: None,
+                    phone_number,
+                    is_registered: true,
+                };
+
+                match self.db.save_contact(&contact).await {
+                    Ok(()) => {
+                        self.show_toast(&i18n!("Contact saved"), adw::ToastPriority::Normal);
+                        self.rename_contact_chat(contact.jid, contact.name.unwrap_or_default())
+                            .await;
+                    }
+                    Err(e) => tracing::error!("Failed to save contact {}: {e}", contact.jid),
+                }
+            }
+            AppMsg::VotePoll {
+                chat_jid,
+                msg_id,
+                option,
+            } => {
+                self.vote_poll(&chat_jid, msg_id, &option).await;
+            }
+            AppMsg::React {
+                chat_jid,
+                msg_id,
+                emoji,
+            } => self.react(&chat_jid, msg_id, emoji).await,
+            AppMsg::MoveChat { jid, target_jid } => self.move_chat(&jid, &target_jid),
+            AppMsg::SetMessagePinned {
+                chat_jid,
+                msg_id,
+                pinned,
+            } => {
+                let now = Utc::now();
+                let expires_at = ChatMessage::pin_expiry(now);
+                self.set_message_pinned(&chat_jid, &msg_id, pinned, now, Some(expires_at))
+                    .await;
+
+                match self.db.load_message_by_server_id(&chat_jid, &msg_id).await {
+                    Ok(Some(message)) => self.client.emit(ClientInput::PinMessage {
+                        message: Box::new(message),
+                        pinned,
+                    }),
+                    Ok(None) => tracing::warn!("Pinned message {msg_id} isn't stored"),
+                    Err(e) => tracing::error!("Failed to load pinned message {msg_id}: {e}"),
+                }
+            }
+            AppMsg::GroupChanged {
+                chat_jid,
+                id,
+                timestamp,
+                event,
+            } => {
+                if let Some(joined) = event.membership_change(&self.own_jids()) {
+                    self.set_chat_left(&chat_jid, !joined);
+                }
+
+                // Notifications come again after reconnecting, and changes
+                // without an ID can't be told apart from the ones already kept.
+                let kept = id.is_empty()
+                    || matches!(
+                        self.db.load_message_by_server_id(&chat_jid, &id).await,
+                        Ok(Some(_))
+                    );
+                if kept {
+                    return;
+                }
+
+                // Keep the change in the history, as the synced ones are.
+                let participants = self
+                    .chats
+                    .iter()
+                    .find(|c| c.jid == chat_jid)
+                    .map(|c| c.participants.clone())
+                    .unwrap_or_default();
+                let sender_jid = event.actor().unwrap_or_default().to_string();
+                let message = ChatMessage {
+                    local_id: Uuid::new_v4(),
+                    server_id: id,
+                    chat_jid: chat_jid.clone(),
+                    outgoing: self.is_own_jid(&sender_jid),
+                    sender_jid,
+                    sender_name: None,
+
+                    kind: MessageKind::Service,
+                    poll: None,
+                    media: None,
+                    edited: false,
+                    status: MessageStatus::Read,
+                    content: event.display_text(self.user_jid.as_deref(), &participants),
+                    revoked: false,
+                    contacts: Vec::new(),
+                    location: None,
+                    quote: None,
+                    preview: None,
+                    reactions: IndexMap::new(),
+                    timestamp,
 
-        let widgets = view_output!();
+                    db: Arc::clone(&self.db),
+                };
 
-        actions.add_action(shortcuts_action);
-        actions.add_action(about_action);
-        actions.add_action(quit_action);
-        actions.register_for_widget(&widgets.main_window);
+                self.add_message(&chat_jid, message.clone());
+                if self.open_chat.as_deref() == Some(chat_jid.as_str()) {
+                    self.chat_view
+                        .emit(ChatViewInput::MessageReceived(Box::new(message)));
+                }
+            }
+            AppMsg::ParticipantsFetched { jid, participants } => {
+                if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) {
+                    chat.add_participants(participants);
+                    let chat = chat.clone();
+                    self.chat_view.emit(ChatViewInput::ChatChanged(chat));
+                }
+            }
+            AppMsg::UndecryptableMessage { chat_jid, msg_id } => {
+                let chat_jid = self.resolve_chat_jid(&chat_jid, None);
+                match self.db.add_undecryptable_message(&chat_jid, &msg_id).await {
+                    Ok(true) => self
+                        .chat_view
+                        .emit(ChatViewInput::UndecryptableCountChanged { chat_jid }),
+                    Ok(false) => {}
+                    Err(e) => tracing::error!("Failed to count undecryptable message: {e}"),
+                }
+            }
 
-        widgets.load_window_size();
+            AppMsg::ScreencastChanged(active) => {
+                tracing::debug!("Screen sharing active: {active}");
+                self.screencast_active = active;
+            }
+            AppMsg::SetDoNotDisturb(enabled) => {
+                let until = if enabled { i64::MAX } else { 0 };
+                if let Err(e) = self.settings.set_int64("do-not-disturb-until", until) {
+                    tracing::error!("Failed to save Do Not Disturb: {e}");
+                }
+            }
+            AppMsg::DoNotDisturbFor(minutes) => {
+                let until = Utc::now().timestamp() + i64::from(minutes) * 60;
+                if let Err(e) = self.settings.set_int64("do-not-disturb-until", until) {
+                    tracing::error!("Failed to save Do Not Disturb: {e}");
+                }
+            }
+            AppMsg::DoNotDisturbChanged => {
+                self.do_not_disturb = do_not_disturb_active(&self.settings);
+                tracing::debug!("Do Not Disturb: {}", self.do_not_disturb);
+
+                // Turn it off once its time elapses, even if that was while
+                // the app wasn't running.
+                let until = self.settings.int64("do-not-disturb-until");
+                if until != 0 && until != i64::MAX {
+                    let remaining = u64::try_from(until - Utc::now().timestamp()).unwrap_or(0);
+                    sender.oneshot_command(async move {
+                        time::sleep(Duration::from_secs(remaining)).await;
+                        AppCmd::DoNotDisturbExpired
+                    });
+                }
+            }
+            AppMsg::SetFocusMode(enabled) => self.set_focus_mode(enabled, None, &sender),
+            AppMsg::FocusModeFor(minutes) => {
+                let duration = Duration::from_secs(u64::from(minutes) * 60);
+                self.set_focus_mode(true, Some(duration), &sender);
+            }
+            AppMsg::ReportProblem => {
+                let dialog = gtk::FileDialog::builder()
+                    .title(i18n!("Save Problem Report"))
+                    .initial_name(format!(
+                        "papo-report-{}.zip",
+                        Utc::now().format("%Y%m%d-%H%M%S")
+                    ))
+                    .build();
+
+                let sender = sender.clone();
+                dialog.save(Some(root), gio::Cancellable::NONE, move |result| {
+                    if let Some(path) = result.ok().and_then(|file| file.path()) {
+                        sender.input(AppMsg::SaveReport(path));
+                    }
+                });
+            }
+            AppMsg::SaveReport(path) => {
+                if self.refuse_low_space_export(&path) {
+                    return;
+                }
 
-        AsyncComponentParts { model, widgets }
-    }
+                let schema_version = self.db.get_schema_version().await.unwrap_or_else(|e| {
+                    tracing::error!("Failed to get the database schema version: {e}");
+                    0
+                });
 
-    #[allow(clippy::too_many_lines)]
-    async fn update(
-        &mut self,
-        message: Self::Input,
-        sender: AsyncComponentSender<Self>,
-        _root: &Self::Root,
-    ) {
-        match message {
-            AppMsg::Connected { jid, push_name } => {
-                self.user_jid = jid;
-                self.user_push_name = Some(push_name);
+                sender.oneshot_command(async move {
+                    let result =
+                        relm4::spawn_blocking(move || report::write_bundle(&path, schema_version))
+                            .await
+                            .unwrap_or_else(|e| Err(io::Error::other(e)));
+                    AppCmd::ReportSaved(result)
+                });
+            }
 
-                // Sync in background.
-                sender.oneshot_command(async { AppCmd::Sync });
+            AppMsg::ShowChatSwitcher => {
+                if self.page != AppPage::Session || self.chat_switcher.is_some() {
+                    return;
+                }
 
-                if self.page != AppPage::Session {
-                    self.page = AppPage::Session;
+                let mut chats = self
+                    .chats
+                    .iter()
+                    .filter(|c| !c.archived)
+                    .collect::<Vec<_>>();
+                chats.sort_by(|a, b| b.last_message_time.cmp(&a.last_message_time));
+
+                self.chat_switcher = Some(
+                    ChatSwitcher::builder()
+                        .launch(ChatSwitcherInit {
+                            parent: self.split_view.clone().upcast(),
+                            chats: chats
+                                .into_iter()
+                                .map(|c| (c.jid.clone(), c.get_name_or_number()))
+                                .collect(),
+                        })
+                        .forward(sender.input_sender(), |output| match output {
+                            ChatSwitcherOutput::Selected(jid) => AppMsg::ChatSwitched(jid),
+                            ChatSwitcherOutput::MessageNumber(phone_number) => {
+                                AppMsg::MessageNumber(phone_number)
+                            }
+                            ChatSwitcherOutput::Closed => AppMsg::ChatSwitcherClosed,
+                        }),
+                );
+            }
+            AppMsg::ChatSwitched(jid) => {
+                for text in self.forwarding.take().unwrap_or_default() {
+                    sender.input(AppMsg::SendTextMessage {
+                        text,
+                        recipient: jid.clone(),
+                        quote: None,
+                    });
                 }
+                self.chat_list.emit(ChatListInput::Select(jid));
+            }
+            AppMsg::ChatSwitcherClosed => {
+                self.chat_switcher = None;
+                self.forwarding = None;
             }
+            AppMsg::ForwardMessages(texts) => {
+                if self.chat_switcher.is_some() {
+                    return;
+                }
 
-            AppMsg::SyncCompleted {
-                chats_needing_avatars,
-            } => {
-                // Fetch avatars for chats that don't have them.
-                for jid in chats_needing_avatars {
-                    self.client.emit(ClientInput::FetchAvatar { jid });
+                self.forwarding = Some(texts);
+                sender.input(AppMsg::ShowChatSwitcher);
+            }
+            AppMsg::MarkedRead { id, result: Ok(()) } => {
+                self.receipt_retries.finish(id);
+                if let Err(e) = self.db.remove_pending_receipt(id).await {
+                    tracing::error!("Failed to remove sent read receipts: {e}");
                 }
             }
-            AppMsg::LoggedOut => {
-                self.page = AppPage::Fetching;
-                self.state = AppState::Pairing;
-
-                // Start a fresh client — the old credentials have been cleared
-                // by the ClientCommand::LoggedOut handler.
-                self.client.emit(ClientInput::Start);
+            // Kept as they are, to be sent on reconnecting.
+            AppMsg::MarkedRead {
+                id,
+                result: Err(ReceiptError::NotConnected),
+            } => {
+                self.receipt_retries.finish(id);
+            }
+            AppMsg::MarkedRead {
+                id,
+                result: Err(ReceiptError::Failed(message)),
+            } => {
+                self.receipt_retries.finish(id);
+                let receipt = match self.db.fail_pending_receipt(id).await {
+                    Ok(Some(receipt)) => receipt,
+                    Ok(None) => return,
+                    Err(e) => {
+                        tracing::error!("Failed to count read receipts failure: {e}");
+                        return;
+                    }
+                };
 
-                let db = self.db.clone();
-                let chats = std::mem::take(&mut self.chats);
-                relm4::spawn(async move {
-                    for chat in chats {
-                        let _ = db.delete_chat(&chat.jid).await;
+                if receipt.attempts >= MAX_RECEIPT_ATTEMPTS {
+                    tracing::warn!(
+                        "Giving up on read receipts for {} after {} attempts: {message}",
+                        receipt.chat_jid,
+                        receipt.attempts
+                    );
+                    if let Err(e) = self.db.remove_pending_receipt(id).await {
+                        tracing::error!("Failed to remove read receipts: {e}");
                     }
+                    return;
+                }
+
+                let delay = RECEIPT_RETRY_DELAY * 2u32.pow(receipt.attempts - 1);
+                tracing::info!(
+                    "Retrying read receipts for {} in {delay:?}",
+                    receipt.chat_jid
+                );
+                self.receipt_retries.back_off(id);
+                sender.oneshot_command(async move {
+                    time::sleep(delay).await;
+                    AppCmd::ReceiptRetryDue(receipt)
                 });
             }
-            AppMsg::Disconnected => {
-                self.state = AppState::Disconnected;
+            AppMsg::MessageNumber(phone_number) => {
+                self.client.emit(ClientInput::CheckNumber { phone_number });
             }
-            AppMsg::SelfPushNameUpdated { push_name } => {
-                self.user_push_name = Some(push_name);
+            AppMsg::NumberChecked {
+                phone_number,
+                result,
+            } if self.opening_number.as_ref() == Some(&phone_number) => {
+                self.opening_number = None;
+                match result {
+                    Ok(Some(jid)) => {
+                        let jid = self.resolve_chat_jid(&jid, None);
+                        sender.input(AppMsg::MessageContact {
+                            jid,
+                            name: format!("+{phone_number}"),
+                        });
+                    }
+                    Ok(None) => self.show_toast(
+                        &i18n_f!("+{0} isn't on WhatsApp", phone_number),
+                        adw::ToastPriority::High,
+                    ),
+                    Err(message) => self.show_toast(&message, adw::ToastPriority::High),
+                }
             }
-            AppMsg::ResetSession => {
-                self.client.emit(ClientInput::Restart);
+            AppMsg::NumberChecked {
+                phone_number,
+                result,
+            } => {
+                // Nothing to do if the switcher was closed meanwhile.
+                let Some(chat_switcher) = &self.chat_switcher else {
+                    return;
+                };
+
+                let jid = match result {
+                    Ok(Some(jid)) => jid,
+                    Ok(None) => {
+                        chat_switcher.emit(ChatSwitcherInput::NumberFailed(i18n_f!(
+                            "+{0} isn't on WhatsApp",
+                            phone_number
+                        )));
+                        return;
+                    }
+                    Err(message) => {
+                        chat_switcher.emit(ChatSwitcherInput::NumberFailed(message));
+                        return;
+                    }
+                };
+                chat_switcher.widget().popdown();
+
+                let jid = self.resolve_chat_jid(&jid, None);
+                sender.input(AppMsg::MessageContact {
+                    jid,
+                    name: format!("+{phone_number}"),
+                });
             }
 
-            AppMsg::PairDevice {
-                code,
-                qr_code,
-                timeout,
-            } => {
-                if self.page == AppPage::Fetching {
-                    self.page = AppPage::Login;
+            AppMsg::OpenChat(target) => {
+                // It may come from a notification while the window is hidden.
+                root.present();
+
+                // Wait for the chats, opening it once they're loaded.
+                if self.page != AppPage::Session
+                    || matches!(self.state, AppState::Loading | AppState::Pairing)
+                {
+                    self.pending_chat = Some(target);
+                    return;
                 }
 
-                self.login.emit(LoginInput::PairCode {
-                    code,
-                    qr_code,
-                    timeout,
-                });
+                let target = target.trim();
+                if target.contains('@') {
+                    let jid = self.resolve_chat_jid(target, None);
+                    if self.chats.iter().any(|c| c.jid == jid) {
+                        self.chat_list.emit(ChatListInput::Select(jid));
+                    } else if ChatKind::from_jid(&jid).is_private()
+                        && let Ok(Some(contact)) = self.db.get_contact(&jid).await
+                    {
+                        // A contact without a chat yet, as found from GNOME Shell.
+                        sender.input(AppMsg::MessageContact {
+                            jid,
+                            name: contact.name.or(contact.push_name).unwrap_or_default(),
+                        });
+                    } else {
+                        self.show_toast(
+                            &i18n_f!("No chat with {0}", target),
+                            adw::ToastPriority::High,
+                        );
+                    }
+                } else if let Some(phone_number) = parse_phone_number(target) {
+                    let jid =
+                        self.resolve_chat_jid(&format!("{phone_number}@s.whatsapp.net"), None);
+                    if self.chats.iter().any(|c| c.jid == jid) {
+                        self.chat_list.emit(ChatListInput::Select(jid));
+                    } else {
+                        self.client.emit(ClientInput::CheckNumber {
+                            phone_number: phone_number.clone(),
+                        });
+                        self.opening_number = Some(phone_number);
+                    }
+                } else {
+                    self.show_toast(
+                        &i18n_f!("{0} isn't a valid chat or phone number", target),
+                        adw::ToastPriority::High,
+                    );
+                }
             }
-            AppMsg::DevicePaired => {
-                self.login.emit(LoginInput::PairSuccess);
-                time::sleep(Duration::from_secs(2)).await;
 
-                self.page = AppPage::Session;
-                self.state = AppState::Syncing;
+            AppMsg::ShowMessageInfo { chat_jid, msg_id } => {
+                let message = match self.db.load_message_by_server_id(&chat_jid, &msg_id).await {
+                    Ok(Some(message)) => message,
+                    Ok(None) => return,
+                    Err(e) => {
+                        tracing::error!("Failed to load message {msg_id}: {e}");
+                        return;
+                    }
+                };
+
+                // Only a private chat has a single recipient to tell about.
+                let private = self
+                    .chats
+                    .iter()
+                    .any(|c| c.jid == chat_jid && c.is_private());
+                let refused =
+                    private && AvatarCache::new().is_ok_and(|cache| cache.was_refused(&chat_jid));
+
+                let timestamp = message.timestamp.with_timezone(&Local);
+                let mut body = format!(
+                    "{}\n{}",
+                    i18n_f!(
+                        "Sent {0}, {1}",
+                        format_date_label(timestamp.date_naive()),
+                        timestamp.format("%H:%M")
+                    ),
+                    message.status.display_label()
+                );
+                if message.delivery_unconfirmed(refused, Utc::now()) {
+                    body.push_str("\n\n");
+                    body.push_str(&i18n!(
+                        "Delivery not confirmed — the recipient may be unreachable"
+                    ));
+                }
+
+                let dialog = adw::AlertDialog::builder()
+                    .heading(i18n!("Message Info"))
+                    .body(body)
+                    .default_response("close")
+                    .close_response("close")
+                    .build();
+                dialog.add_response("close", &i18n!("_Close"));
+                dialog.present(Some(root));
             }
-            AppMsg::PairWithPhoneNumber { phone_number } => {
-                self.client
-                    .emit(ClientInput::PairWithPhoneNumber { phone_number });
+            AppMsg::CopyImage { chat_jid, msg_id } => {
+                let Some((message, media)) = self.load_media(&chat_jid, &msg_id).await else {
+                    return;
+                };
+
+                // Reuses the texture shown in the bubble when it's still cached.
+                let key = thumbnails::key("image", &message, &media.data);
+                if let Some(texture) = thumbnails::load(key, media.data).await {
+                    root.clipboard().set_texture(&texture);
+                    self.show_toast(&i18n!("Image copied"), adw::ToastPriority::Normal);
+                } else {
+                    tracing::error!("Failed to decode image {msg_id}");
+                    self.show_toast(&i18n!("Couldn't copy the image"), adw::ToastPriority::High);
+                }
             }
+            AppMsg::SaveMedia { chat_jid, msg_id } => {
+                let Some((message, media)) = self.load_media(&chat_jid, &msg_id).await else {
+                    return;
+                };
 
-            AppMsg::ChatOpen => {
-                self.split_view.set_show_content(true);
-                self.session_page = AppSessionPage::ChatHistory;
+                let dialog = gtk::FileDialog::builder()
+                    .title(i18n!("Save As"))
+                    .initial_name(self.media_file_name(&message, &media))
+                    .build();
+
+                let sender = sender.clone();
+                dialog.save(Some(root), gio::Cancellable::NONE, move |result| {
+                    let Ok(file) = result else { return };
+                    file.replace_contents_async(
+                        media.data.to_vec(),
+                        None,
+                        false,
+                        gio::FileCreateFlags::REPLACE_DESTINATION,
+                        gio::Cancellable::NONE,
+                        move |result| {
+                            sender.input(AppMsg::MediaSaved(
+                                result.map(|_| ()).map_err(|(_, e)| e.to_string()),
+                            ));
+                        },
+                    );
+                });
             }
-            AppMsg::ChatClosed => {
-                self.chat_list.emit(ChatListInput::ClearSelection);
-                self.split_view.set_show_content(false);
-                self.session_page = AppSessionPage::Empty;
+            AppMsg::MediaSaved(Ok(())) => {
+                self.show_toast(&i18n!("Saved"), adw::ToastPriority::Normal);
             }
-            AppMsg::ChatSelected(jid) => {
-                if let Some(chat) = self.chats.iter().find(|c| c.jid == jid).cloned() {
-                    self.chat_view.emit(ChatViewInput::Open(chat));
-                }
+            AppMsg::MediaSaved(Err(message)) => {
+                tracing::error!("Failed to save media: {message}");
+                self.show_toast(
+                    &i18n_f!("Couldn't save the file: {0}", message),
+                    adw::ToastPriority::High,
+                );
             }
-            AppMsg::MarkChatRead(jid) => {
-                self.mark_chat_read(&jid).await;
+            AppMsg::DownloadMedia { chat_jid, msg_id } => {
+                match self.db.load_downloadable_media(&chat_jid, &msg_id).await {
+                    Ok(Some(media)) => self.download_media(&chat_jid, &msg_id, &media),
+                    Ok(None) => {}
+                    Err(e) => tracing::error!("Failed to load media of {msg_id}: {e}"),
+                }
             }
+            AppMsg::MediaDownloaded {
+                chat_jid,
+                msg_id,
+                data,
+            } => {
+                self.downloading.remove(&msg_id);
+                if let Err(e) = self
+                    .db
+                    .save_downloaded_media(&chat_jid, &msg_id, &data)
+                    .await
+                {
+                    tracing::error!("Failed to save media of {msg_id}: {e}");
+                    return;
+                }
 
-            AppMsg::AvatarUpdate { jid, path } => {
-                // Update the chat's avatar path.
-                if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) {
-                    chat.avatar_path = Some(path);
-
-                    // Update in chat list.
-                    self.chat_list.emit(ChatListInput::UpdateChat {
-                        chat: chat.clone(),
-                        move_to_top: false,
+                if self.open_chat.as_deref() == Some(chat_jid.as_str()) {
+                    self.chat_view.emit(ChatViewInput::MediaDownloaded {
+                        msg_id,
+                        data: Arc::new(data),
                     });
-
-                    tracing::info!("Updated avatar for chat: {}", jid);
                 }
             }
-            AppMsg::ContactUpdate {
-                jid,
-                name,
-                push_name,
-                phone_number,
-            } => {
-                // Save contact to database in background.
-                let db = Arc::clone(&self.db);
-                let jid_for_contact = jid.clone();
-                let name_for_contact = name.clone();
-
-                let contact = Contact {
-                    jid: jid.clone(),
-                    name: name.clone(),
-                    push_name,
-                    phone_number: Some(phone_number),
-                    is_registered: true,
+            AppMsg::MediaDownloadFailed { msg_id, .. } => {
+                self.downloading.remove(&msg_id);
+                self.show_toast(
+                    &i18n!("Couldn't download the media"),
+                    adw::ToastPriority::High,
+                );
+            }
+            AppMsg::OpenMediaWith { chat_jid, msg_id } => {
+                let Some((message, media)) = self.load_media(&chat_jid, &msg_id).await else {
+                    return;
                 };
 
-                relm4::spawn(async move {
-                    if let Err(e) = db.save_contact(&contact).await {
-                        tracing::error!("Failed to save contact {}: {}", jid_for_contact, e);
-                    } else {
-                        tracing::debug!(
-                            "Saved contact: {} (name: {:?})",
-                            jid_for_contact,
-                            name_for_contact
+                // Apps open files, so write the media to one first.
+                let dir = media_cache_dir();
+                let path = dir.join(self.media_file_name(&message, &media));
+                let data = Arc::clone(&media.data);
+                let result = relm4::spawn_blocking(move || {
+                    remove_stale_files(&dir, MEDIA_CACHE_MAX_AGE)?;
+                    std::fs::create_dir_all(&dir)?;
+                    std::fs::write(&path, data.as_slice())?;
+                    Ok(path)
+                })
+                .await
+                .unwrap_or_else(|e| Err(io::Error::other(e)));
+
+                match result {
+                    Ok(path) => {
+                        open_attachment(&gio::File::for_path(path), &media.mime_type, true);
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to write media {msg_id}: {e}");
+                        self.show_toast(
+                            &i18n_f!("Couldn't open the file: {0}", e),
+                            adw::ToastPriority::High,
                         );
                     }
-                });
+                }
+            }
+            AppMsg::ViewImage { chat_jid, msg_id } => {
+                if self.image_viewer.is_some() {
+                    return;
+                }
 
-                // Update chat name if this contact has a chat and we got a name.
-                if let Some(contact_name) = name
-                    && let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid)
-                {
-                    // Only update if current name is generic (phone number or "Unknown").
-                    let current_name = chat.get_name_or_number();
-                    let is_generic = current_name == contact_name
-                        || current_name == i18n!("Unknown")
-                        || current_name == format_lid_as_number(&jid);
+                let images = match self.db.load_media_messages(&chat_jid).await {
+                    Ok(images) if !images.is_empty() => images,
+                    Ok(_) => {
+                        self.show_toast(
+                            &i18n!("The media isn't downloaded yet"),
+                            adw::ToastPriority::Normal,
+                        );
+                        return;
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to load images of {chat_jid}: {e}");
+                        return;
+                    }
+                };
 
-                    if is_generic {
-                        chat.name.clone_from(&contact_name);
+                // Start from the last image if this one isn't stored yet.
+                let start = images
+                    .iter()
+                    .position(|m| m.server_id == msg_id)
+                    .unwrap_or(images.len() - 1);
+
+                self.image_viewer = Some(
+                    ImageViewer::builder()
+                        .launch(ImageViewerInit { start, images })
+                        .forward(sender.input_sender(), |output| match output {
+                            ImageViewerOutput::Closed => AppMsg::ImageViewerClosed,
+                        }),
+                );
+            }
+            AppMsg::ImageViewerClosed => self.image_viewer = None,
+            AppMsg::ShowChatGallery { jid, name } => {
+                if self.chat_gallery.is_some() {
+                    return;
+                }
 
-                        // Save updated chat in background.
-                        let jid_clone = jid.clone();
-                        let chat_clone = chat.clone();
+                self.chat_gallery = Some(
+                    ChatGallery::builder()
+                        .launch(ChatGalleryInit {
+                            db: Arc::clone(&self.db),
+                            chat_jid: jid,
+                            chat_name: name,
+                        })
+                        .forward(sender.input_sender(), |output| match output {
+                            ChatGalleryOutput::Closed => AppMsg::ChatGalleryClosed,
+                        }),
+                );
+            }
+            AppMsg::ChatGalleryClosed => self.chat_gallery = None,
+            AppMsg::ShowJoinRequests { jid, name } => {
+                if self.join_requests_dialog.is_some() || !self.is_group_admin(&jid).await {
+                    return;
+                }
 
-                        relm4::spawn(async move {
-                            if let Err(e) = chat_clone.save().await {
-                                tracing::error!(
-                                    "Failed to update chat name for {}: {}",
-                                    jid_clone,
-                                    e
-                                );
-                            }
-                        });
+                let requests = self.db.load_join_requests(&jid).await.unwrap_or_else(|e| {
+                    tracing::error!("Failed to load join requests of {jid}: {e}");
+                    Vec::new()
+                });
+                let names = self.requester_names(&requests).await;
+                let dialog = JoinRequestsDialog::builder()
+                    .launch(JoinRequestsInit {
+                        name,
+                        names,
+                        requests,
+                    })
+                    .forward(sender.input_sender(), {
+                        let chat_jid = jid.clone();
+                        move |output| match output {
+                            JoinRequestsOutput::Resolve {
+                                participants,
+                                approve,
+                            } => AppMsg::ResolveJoinRequests {
+                                chat_jid: chat_jid.clone(),
+                                participants,
+                                approve,
+                            },
+                            JoinRequestsOutput::Closed => AppMsg::JoinRequestsClosed,
+                        }
+                    });
+                self.join_requests_dialog = Some((jid.clone(), dialog));
 
-                        // Update in chat list immediately.
-                        self.chat_list.emit(ChatListInput::UpdateChat {
-                            chat: chat.clone(),
-                            move_to_top: false,
-                        });
+                // The stored requests may have missed some while offline.
+                self.client.emit(ClientInput::FetchJoinRequests { jid });
+            }
+            AppMsg::JoinRequested {
+                chat_jid,
+                participants,
+                timestamp,
+            } => {
+                // Only admins can approve requests.
+                if !self.is_group_admin(&chat_jid).await {
+                    return;
+                }
 
-                        tracing::info!("Updated chat name for {} to: {}", jid, contact_name);
-                    }
+                let requests = participants
+                    .into_iter()
+                    .map(|jid| JoinRequest {
+                        jid,
+                        requested_at: timestamp,
+                    })
+                    .collect::<Vec<_>>();
+                if let Err(e) = self.db.add_join_requests(&chat_jid, &requests).await {
+                    tracing::error!("Failed to save join requests of {chat_jid}: {e}");
+                    return;
                 }
+                if let Some((jid, dialog)) = &self.join_requests_dialog
+                    && *jid == chat_jid
+                    && let Ok(requests) = self.db.load_join_requests(&chat_jid).await
+                {
+                    let names = self.requester_names(&requests).await;
+                    dialog.emit(JoinRequestsInput::SetRequests(requests, names));
+                }
+                self.join_requests_changed(&chat_jid);
             }
-            AppMsg::ReceiptUpdate {
+            AppMsg::JoinRequestsWithdrawn {
                 chat_jid,
-                message_ids,
-                receipt_type,
+                participants,
             } => {
-                if let Some(chat) = self.chats.iter().find(|c| c.jid == chat_jid).cloned() {
-                    match MessageStatus::try_from(receipt_type) {
-                        Ok(status) => {
-                            for msg_id in message_ids {
-                                if let Ok(Some(mut message)) = chat.find_message(&msg_id).await {
-                                    // Update message status.
-                                    message.status = status;
-
-                                    // Update the message in the database.
-                                    let msg_clone = message.clone();
-                                    relm4::spawn(async move {
-                                        if let Err(e) = msg_clone.save().await {
-                                            tracing::error!("Failed to update message: {}", e);
-                                        }
-                                    });
-                                }
-                            }
+                if let Err(e) = self.db.remove_join_requests(&chat_jid, &participants).await {
+                    tracing::error!("Failed to forget join requests of {chat_jid}: {e}");
+                }
+                if let Some((jid, dialog)) = &self.join_requests_dialog
+                    && *jid == chat_jid
+                {
+                    dialog.emit(JoinRequestsInput::Remove(participants));
+                }
+                self.join_requests_changed(&chat_jid);
+            }
+            AppMsg::JoinRequestsFetched { chat_jid, requests } => {
+                if let Err(e) = self.db.set_join_requests(&chat_jid, &requests).await {
+                    tracing::error!("Failed to save join requests of {chat_jid}: {e}");
+                }
+                if let Some((jid, dialog)) = &self.join_requests_dialog
+                    && *jid == chat_jid
+                {
+                    let names = self.requester_names(&requests).await;
+                    dialog.emit(JoinRequestsInput::SetRequests(requests, names));
+                }
+                self.join_requests_changed(&chat_jid);
+            }
+            AppMsg::ResolveJoinRequests {
+                chat_jid,
+                participants,
+                approve,
+            } => self.client.emit(ClientInput::ResolveJoinRequests {
+                jid: chat_jid,
+                participants,
+                approve,
+            }),
+            AppMsg::JoinRequestsResolved {
+                chat_jid,
+                participants,
+                approved,
+            } => {
+                if let Err(e) = self.db.remove_join_requests(&chat_jid, &participants).await {
+                    tracing::error!("Failed to forget join requests of {chat_jid}: {e}");
+                }
+                if let Some((jid, dialog)) = &self.join_requests_dialog
+                    && *jid == chat_jid
+                {
+                    dialog.emit(JoinRequestsInput::Remove(participants.clone()));
+                }
+                self.join_requests_changed(&chat_jid);
 
-                            self.chat_list.emit(ChatListInput::UpdateChat {
-                                chat,
-                                move_to_top: false,
-                            });
-                        }
-                        Err(e) => tracing::error!(
-                            "Failed to convert `ReceiptType` to `MessageStatus`: {e}"
-                        ),
+                // Keep who was let in or turned away in the history.
+                let actor = self.user_jid.clone().unwrap_or_default();
+                let event = if approved {
+                    GroupEvent::JoinRequestsApproved {
+                        actor,
+                        participants,
+                    }
+                } else {
+                    GroupEvent::JoinRequestsRejected {
+                        actor,
+                        participants,
                     }
+                };
+                sender.input(AppMsg::GroupChanged {
+                    chat_jid,
+                    id: format!("join-requests-{}", Uuid::new_v4()),
+                    timestamp: Utc::now(),
+                    event,
+                });
+            }
+            AppMsg::JoinRequestsFailed(chat_jid) => {
+                if let Some((jid, dialog)) = &self.join_requests_dialog
+                    && *jid == chat_jid
+                {
+                    dialog.emit(JoinRequestsInput::Failed);
                 }
+                self.show_toast(
+                    &i18n!("Couldn't answer the join request"),
+                    adw::ToastPriority::High,
+                );
             }
-            AppMsg::PresenceUpdate {
+            AppMsg::JoinRequestsClosed => self.join_requests_dialog = None,
+            AppMsg::StartCall { jid, is_video } => {
+                if self.call_dialog.is_some() {
+                    self.show_toast(&i18n!("Already in a call"), adw::ToastPriority::Normal);
+                    return;
+                }
+
+                self.client.emit(ClientInput::StartCall { jid, is_video });
+            }
+            AppMsg::CallStarted {
+                call_id,
                 jid,
-                available,
-                last_seen,
+                is_video,
             } => {
-                if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) {
-                    if !chat.is_group() {
-                        chat.available = Some(available);
-                    }
-
-                    chat.last_seen = last_seen;
+                let name = self.get_display_name(&jid).await;
+                self.show_call(call_id, name, is_video, false, &sender);
+            }
+            AppMsg::CallFailed(jid) => {
+                let name = self.get_display_name(&jid).await;
+                self.show_toast(
+                    &i18n_f!("Couldn't call {0}", name),
+                    adw::ToastPriority::High,
+                );
+            }
+            AppMsg::CallAccepted(call_id) => {
+                if let Some(dialog) = &self.call_dialog
+                    && dialog.model().call_id() == call_id
+                {
+                    dialog.emit(CallDialogInput::Answered);
+                }
+            }
+            AppMsg::CallEnded(call_id) => {
+                // The call ended before it was answered.
+                if let Some(call) = self.dismiss_incoming_call(&call_id) {
+                    self.log_missed_call(
+                        &call.from_jid,
+                        &call.call_id,
+                        call.is_video,
+                        call.timestamp,
+                    );
                 }
 
-                self.chat_view.emit(ChatViewInput::PresenceUpdate {
-                    jid,
-                    available,
-                    last_seen,
+                if let Some(dialog) = &self.call_dialog
+                    && dialog.model().call_id() == call_id
+                {
+                    dialog.emit(CallDialogInput::Ended);
+                }
+            }
+            AppMsg::HangUp(call_id) => self.client.emit(ClientInput::EndCall { call_id }),
+            AppMsg::CallDialogClosed => self.call_dialog = None,
+            AppMsg::ClearHistory { jid, name } => {
+                let dialog = adw::AlertDialog::builder()
+                    .heading(i18n!("Clear History?"))
+                    .body(i18n_f!(
+                        "The messages of {} will be deleted from this device, and won't come back when syncing",
+                        name
+                    ))
+                    .default_response("cancel")
+                    .close_response("cancel")
+                    .build();
+                dialog.add_responses(&[("cancel", &i18n!("_Cancel")), ("clear", &i18n!("_Clear"))]);
+                dialog.set_response_appearance("clear", adw::ResponseAppearance::Destructive);
+
+                let sender = sender.clone();
+                dialog.connect_response(Some("clear"), move |_, _| {
+                    sender.input(AppMsg::ConfirmClearHistory(jid.clone()));
                 });
+                dialog.present(Some(root));
             }
-            AppMsg::MessageStatusUpdate {
-                chat_jid,
-                msg_id,
-                status,
-            } => {
-                // Get the chat and message altogether.
-                if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == chat_jid)
-                    && let Ok(Some(mut message)) = chat.find_message_by_local_id(&msg_id).await
-                {
-                    // Update the message status in-place.
-                    message.status = status;
+            AppMsg::ConfirmClearHistory(jid) => {
+                if let Err(e) = self.db.clear_chat_history(&jid, Utc::now()).await {
+                    tracing::error!("Failed to clear the history of {jid}: {e}");
+                    self.show_toast(
+                        &i18n!("Couldn't clear the history"),
+                        adw::ToastPriority::High,
+                    );
+                    return;
+                }
 
-                    // Update the message in the database.
-                    let msg_clone = message.clone();
-                    relm4::spawn(async move {
-                        if let Err(e) = msg_clone.save().await {
-                            tracing::error!("Failed to update message: {}", e);
-                        }
+                self.history_changed(&jid);
+            }
+            AppMsg::RestoreHistory(jid) => {
+                if let Err(e) = self.db.forget_cleared_history(&jid).await {
+                    tracing::error!("Failed to restore the history of {jid}: {e}");
+                    return;
+                }
+
+                self.history_changed(&jid);
+
+                // Ask the phone for what came before the messages kept here,
+                // or wait for the next history sync if none were.
+                let oldest = self
+                    .db
+                    .load_messages_after(&jid, i64::MIN, 1)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::error!("Failed to load the oldest message of {jid}: {e}");
+                        Vec::new()
+                    })
+                    .into_iter()
+                    .next();
+                let toast = if let Some(oldest) = oldest {
+                    self.client.emit(ClientInput::RequestHistory {
+                        oldest: Box::new(oldest),
                     });
+                    i18n!("Downloading cleared messages from your phone")
+                } else {
+                    i18n!("Cleared messages will be downloaded with the next history sync")
+                };
+                self.show_toast(&toast, adw::ToastPriority::Normal);
+            }
 
-                    self.chat_view.emit(ChatViewInput::MessageStatusUpdate {
-                        msg_id: message.server_id,
-                        status: message.status,
+            AppMsg::BookmarkMessage { chat_jid, msg_id } => {
+                let entry = gtk::Entry::builder()
+                    .activates_default(true)
+                    .placeholder_text(i18n!("Note (optional)"))
+                    .build();
+                let dialog = adw::AlertDialog::builder()
+                    .heading(i18n!("Bookmark Message"))
+                    .body(i18n!(
+                        "The bookmark is kept on this device only and isn't synced to your phone"
+                    ))
+                    .extra_child(&entry)
+                    .default_response("bookmark")
+                    .close_response("cancel")
+                    .build();
+                dialog.add_responses(&[
+                    ("cancel", &i18n!("_Cancel")),
+                    ("bookmark", &i18n!("_Bookmark")),
+                ]);
+                dialog.set_response_appearance("bookmark", adw::ResponseAppearance::Suggested);
+
+                let sender = sender.clone();
+                dialog.connect_response(Some("bookmark"), move |_, _| {
+                    let note = entry.text().trim().to_string();
+                    sender.input(AppMsg::SaveBookmark {
+                        chat_jid: chat_jid.clone(),
+                        msg_id: msg_id.clone(),
+                        note: Some(note).filter(|n| !n.is_empty()),
                     });
+                });
+                dialog.present(Some(root));
+            }
+            AppMsg::SaveBookmark {
+                chat_jid,
+                msg_id,
+                note,
+            } => match self
+                .db
+                .add_bookmark(&chat_jid, &msg_id, note.as_deref())
+                .await
+            {
+                Ok(()) => {
+                    self.show_toast(
+                        &i18n!("Bookmarked on this device"),
+                        adw::ToastPriority::Normal,
+                    );
+                }
+                Err(e) => {
+                    tracing::error!("Failed to bookmark message {msg_id}: {e}");
+                    self.show_toast(
+                        &i18n!("Couldn't bookmark the message"),
+                        adw::ToastPriority::High,
+                    );
+                }
+            },
+            AppMsg::ReplyToMessage { chat_jid, msg_id } => {
+                if self.open_chat.as_deref() == Some(chat_jid.as_str()) {
+                    self.chat_view.emit(ChatViewInput::Reply(msg_id));
+                }
+            }
+            AppMsg::GoToQuoted { chat_jid, msg_id } => {
+                if self.open_chat.as_deref() == Some(chat_jid.as_str()) {
+                    self.chat_view.emit(ChatViewInput::GoToQuoted(msg_id));
+                }
+            }
+            AppMsg::SelectMessage { chat_jid, msg_id } => {
+                if self.open_chat.as_deref() == Some(chat_jid.as_str()) {
+                    self.chat_view.emit(ChatViewInput::ToggleSelected(msg_id));
                 }
             }
+            AppMsg::BookmarkMessages { chat_jid, msg_ids } => {
+                let mut count = 0_u32;
+                for msg_id in &msg_ids {
+                    match self.db.add_bookmark(&chat_jid, msg_id, None).await {
+                        Ok(()) => count += 1,
+                        Err(e) => tracing::error!("Failed to bookmark message {msg_id}: {e}"),
+                    }
+                }
 
-            AppMsg::MessageReceived { info, message } => {
-                let content = message
-                    .conversation
-                    .clone()
-                    .filter(|c| !c.is_empty())
-                    .or_else(|| {
-                        message
-                            .extended_text_message
-                            .as_ref()
-                            .and_then(|e| e.text.clone().filter(|t| !t.is_empty()))
+                if count > 0 {
+                    self.show_toast(
+                        &ni18n!(
+                            "{0} message bookmarked on this device",
+                            "{0} messages bookmarked on this device",
+                            count
+                        )
+                        .replacen("{0}", &count.to_string(), 1),
+                        adw::ToastPriority::Normal,
+                    );
+                } else {
+                    self.show_toast(
+                        &i18n!("Couldn't bookmark the messages"),
+                        adw::ToastPriority::High,
+                    );
+                }
+            }
+            AppMsg::DeleteMessages { chat_jid, msg_ids } => {
+                let count = u32::try_from(msg_ids.len()).unwrap_or(u32::MAX);
+                let dialog = adw::AlertDialog::builder()
+                    .heading(ni18n!("Delete Message?", "Delete Messages?", count))
+                    .body(i18n!(
+                        "They'll only be deleted from this device, and stay in the chat for everyone else"
+                    ))
+                    .default_response("cancel")
+                    .close_response("cancel")
+                    .build();
+                dialog
+                    .add_responses(&[("cancel", &i18n!("_Cancel")), ("delete", &i18n!("_Delete"))]);
+                dialog.set_response_appearance("delete", adw::ResponseAppearance::Destructive);
+
+                let sender = sender.clone();
+                dialog.connect_response(Some("delete"), move |_, _| {
+                    sender.input(AppMsg::ConfirmDeleteMessages {
+                        chat_jid: chat_jid.clone(),
+                        msg_ids: msg_ids.clone(),
                     });
+                });
+                dialog.present(Some(root));
+            }
+            AppMsg::ConfirmDeleteMessages { chat_jid, msg_ids } => {
+                let mut deleted = Vec::with_capacity(msg_ids.len());
+                for msg_id in msg_ids {
+                    match self.db.delete_message(&msg_id).await {
+                        Ok(()) => deleted.push(msg_id),
+                        Err(e) => tracing::error!("Failed to delete message {msg_id}: {e}"),
+                    }
+                }
 
-                if let Some(content) = content {
-                    if content == "status@broadcast" {
-                        // TODO: handle status events
-                    } else {
-                        let chat_jid = info.source.chat.to_string();
-                        let outgoing = info.source.is_from_me;
+                if self.open_chat.as_deref() == Some(chat_jid.as_str()) {
+                    self.chat_view
+                        .emit(ChatViewInput::MessagesDeleted(deleted.clone()));
+                }
 
-                        let status = if outgoing {
-                            MessageStatus::Read
-                        } else {
-                            MessageStatus::Sent
-                        };
-                        let chat_message = ChatMessage {
-                            local_id: Uuid::new_v4(),
-                            server_id: info.id.clone(),
-                            chat_jid: chat_jid.clone(),
-                            sender_jid: info.source.sender.to_string(),
-                            sender_name: Some(info.push_name.clone()),
+                let count = u32::try_from(deleted.len()).unwrap_or(u32::MAX);
+                if count > 0 {
+                    self.show_toast(
+                        &ni18n!("{0} message deleted", "{0} messages deleted", count).replacen(
+                            "{0}",
+                            &count.to_string(),
+                            1,
+                        ),
+                        adw::ToastPriority::Normal,
+                    );
+                } else {
+                    self.show_toast(
+                        &i18n!("Couldn't delete the messages"),
+                        adw::ToastPriority::High,
+                    );
+                }
+            }
+            AppMsg::ShowBookmarks => {
+                if self.page != AppPage::Session || self.bookmarks_dialog.is_some() {
+                    return;
+                }
 
-                            media: None,
-                            status,
-                            content,
-                            outgoing,
-                            reactions: IndexMap::new(),
-                            timestamp: info.timestamp,
+                let bookmarks = match self.db.load_bookmarks().await {
+                    Ok(bookmarks) => bookmarks,
+                    Err(e) => {
+                        tracing::error!("Failed to load bookmarks: {e}");
+                        return;
+                    }
+                };
 
+                self.bookmarks_dialog = Some(
+                    BookmarksDialog::builder()
+                        .launch(BookmarksInit {
                             db: Arc::clone(&self.db),
-                        };
-
-                        self.add_message(&chat_jid, chat_message);
-                    }
-                } else if let Some(sent_message) = message.device_sent_message {
-                    if let Some(_chat_jid) = sent_message.destination_jid {
-                        if let Some(msg) = sent_message.message {
-                            if let Some(_reaction) = msg.reaction_message {
-                                // TODO: handle
-                            } else if let Some(_sticker) = msg.sticker_message {
-                                // TODO: handle
+                            bookmarks,
+                            chat_names: self
+                                .chats
+                                .iter()
+                                .map(|c| (c.jid.clone(), c.get_name_or_number()))
+                                .collect(),
+                        })
+                        .forward(sender.input_sender(), |output| match output {
+                            BookmarksOutput::Open { chat_jid, msg_id } => {
+                                AppMsg::OpenBookmark { chat_jid, msg_id }
                             }
-                        }
+                            BookmarksOutput::Export => AppMsg::ExportBookmarks,
+                            BookmarksOutput::Closed => AppMsg::BookmarksClosed,
+                        }),
+                );
+            }
+            AppMsg::OpenBookmark { chat_jid, msg_id } => {
+                if let Some(dialog) = &self.bookmarks_dialog {
+                    dialog.widget().close();
+                }
+
+                self.chat_view.emit(ChatViewInput::GoToMessage {
+                    chat_jid: chat_jid.clone(),
+                    msg_id,
+                });
+                self.chat_list.emit(ChatListInput::Select(chat_jid));
+            }
+            AppMsg::ExportBookmarks => {
+                let settings = gio::Settings::new(APP_ID);
+                let mode = settings
+                    .string("export-timestamps")
+                    .parse::<TimestampMode>()
+                    .unwrap_or_default();
+
+                let timestamps_row = adw::ComboRow::builder()
+                    .title(i18n!("Timestamps"))
+                    .model(&gtk::StringList::new(&[
+                        &i18n!("Local Time"),
+                        &i18n!("UTC (ISO 8601)"),
+                    ]))
+                    .selected(u32::from(mode == TimestampMode::Utc))
+                    .build();
+                let group = adw::PreferencesGroup::new();
+                group.add(&timestamps_row);
+
+                let dialog = adw::AlertDialog::builder()
+                    .heading(i18n!("Export Bookmarks"))
+                    .extra_child(&group)
+                    .default_response("export")
+                    .close_response("cancel")
+                    .build();
+                dialog.add_responses(&[
+                    ("cancel", &i18n!("_Cancel")),
+                    ("export", &i18n!("_Export…")),
+                ]);
+                dialog.set_response_appearance("export", adw::ResponseAppearance::Suggested);
+
+                let sender = sender.clone();
+                dialog.connect_response(Some("export"), move |_, _| {
+                    // Remember the choice for the next exports and copies.
+                    let mode = if timestamps_row.selected() == 1 {
+                        TimestampMode::Utc
                     } else {
-                        // TODO: maybe add message to "You" chat?
+                        TimestampMode::Local
+                    };
+                    if let Err(e) = settings.set_string("export-timestamps", mode.as_ref()) {
+                        tracing::error!("Failed to save the export timestamps setting: {e}");
                     }
-                } else {
-                    tracing::trace!(
-                        "Message without content received: info = {:#?}, message = {:#?}",
-                        info,
-                        message
-                    );
-                }
+
+                    sender.input(AppMsg::ChooseBookmarksFile);
+                });
+                dialog.present(Some(root));
+            }
+            AppMsg::ChooseBookmarksFile => {
+                let dialog = gtk::FileDialog::builder()
+                    .title(i18n!("Export Bookmarks"))
+                    .initial_name("papo-bookmarks.json")
+                    .build();
+
+                let sender = sender.clone();
+                dialog.save(Some(root), gio::Cancellable::NONE, move |result| {
+                    if let Some(path) = result.ok().and_then(|file| file.path()) {
+                        sender.input(AppMsg::SaveBookmarks(path));
+                    }
+                });
             }
+            AppMsg::SaveBookmarks(path) => {
+                if self.refuse_low_space_export(&path) {
+                    return;
+                }
 
-            AppMsg::SendTextMessage { text, recipient } => {
-                // Get the chat if it exists and is loaded.
-                if let Some(chat) = self.chats.iter().find(|c| c.jid == recipient).cloned() {
-                    let timestamp = Utc::now();
-                    let message = ChatMessage {
-                        local_id: Uuid::new_v4(),
-                        server_id: String::new(), // will be replaced later by the client.
-                        chat_jid: recipient,
-                        sender_jid: self.user_jid.clone().unwrap_or_default(),
-                        sender_name: self.user_push_name.clone(),
+                let bookmarks = match self.db.load_bookmarks().await {
+                    Ok(bookmarks) => bookmarks,
+                    Err(e) => {
+                        tracing::error!("Failed to load bookmarks: {e}");
+                        return;
+                    }
+                };
 
-                        media: None,
-                        status: MessageStatus::Sending,
-                        content: text,
-                        outgoing: true,
-                        reactions: IndexMap::new(),
-                        timestamp,
+                let mode = gio::Settings::new(APP_ID)
+                    .string("export-timestamps")
+                    .parse::<TimestampMode>()
+                    .unwrap_or_default();
+
+                // Raw epoch milliseconds are always included, whatever the display mode.
+                let entries = bookmarks
+                    .into_iter()
+                    .map(|b| {
+                        let chat_name = self
+                            .chats
+                            .iter()
+                            .find(|c| c.jid == b.chat_jid)
+                            .map(Chat::get_name_or_number);
+                        serde_json::json!({
+                            "chat_jid": b.chat_jid,
+                            "chat_name": chat_name,
+                            "message_id": b.message_id,
+                            "note": b.note,
+                            "content": b.content,
+                            "timestamp": b.timestamp.map(|t| format_export_timestamp(t, mode)),
+                            "timestamp_ms": b.timestamp.map(|t| t.timestamp_millis()),
+                            "created_at": format_export_timestamp(b.created_at, mode),
+                            "created_at_ms": b.created_at.timestamp_millis(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
 
-                        db: self.db.clone(),
-                    };
+                sender.oneshot_command(async move {
+                    let result = relm4::spawn_blocking(move || {
+                        let json = serde_json::to_string_pretty(&entries)?;
+                        std::fs::write(&path, json)
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(io::Error::other(e)));
+                    AppCmd::BookmarksExported(result)
+                });
+            }
+            AppMsg::BookmarksClosed => self.bookmarks_dialog = None,
+            #[cfg(feature = "message-injector")]
+            AppMsg::ShowMessageInjector => {
+                if self.message_injector.is_some() {
+                    return;
+                }
 
-                    // Save the message in the database.
-                    let msg_clone = message.clone();
-                    relm4::spawn(async move {
-                        if let Err(e) = msg_clone.save().await {
-                            tracing::error!("Failed to save message: {}", e);
-                        }
-                    });
+                self.message_injector = Some(MessageInjector::builder().launch(()).forward(
+                    sender.input_sender(),
+                    |output| match output {
+                        MessageInjectorOutput::Inject(sample) => AppMsg::InjectMessage(sample),
+                        MessageInjectorOutput::Closed => AppMsg::MessageInjectorClosed,
+                    },
+                ));
+            }
+            #[cfg(feature = "message-injector")]
+            AppMsg::InjectMessage(sample) => {
+                let Some(chat_jid) = self.open_chat.clone() else {
+                    self.show_toast(
+                        "Open a chat to inject messages into",
+                        adw::ToastPriority::Normal,
+                    );
+                    return;
+                };
 
-                    self.client.emit(ClientInput::SendMessage {
-                        message: Box::new(message.clone()),
-                    });
-                    self.chat_view
-                        .emit(ChatViewInput::MessageReceived(Box::new(message)));
-                    self.chat_list.emit(ChatListInput::UpdateChat {
-                        chat,
-                        move_to_top: true,
-                    });
+                // Straight into the history and the view: going through
+                // add_message would send receipts for messages no one sent.
+                let message = sample.to_message(
+                    &chat_jid,
+                    self.user_jid.as_deref().unwrap_or_default(),
+                    Arc::clone(&self.db),
+                );
+                if let Err(e) = message.save().await {
+                    tracing::error!("Failed to save injected message: {e}");
+                    return;
                 }
+                for (emoji, senders) in &message.reactions {
+                    for sender_jid in senders {
+                        if let Err(e) = self
+                            .db
+                            .save_reaction(
+                                &message.server_id,
+                                sender_jid,
+                                Some(emoji),
+                                message.timestamp,
+                            )
+                            .await
+                        {
+                            tracing::error!("Failed to save injected reaction: {e}");
+                        }
+                    }
+                }
+                self.chat_view
+                    .emit(ChatViewInput::MessageReceived(Box::new(message)));
             }
+            #[cfg(feature = "message-injector")]
+            AppMsg::MessageInjectorClosed => self.message_injector = None,
 
             AppMsg::ChatSynced {
                 jid,
                 name,
+                alt_jid,
                 pinned,
                 archived,
                 participants,
+                admins,
+                mute_end_time,
                 last_message_time,
+                participant_count,
+                modified_at,
                 ..
             } => {
-                // Skip if chat already exists (quick check, non-blocking).
-                if self.chats.iter().any(|c| c.jid == jid) {
+                // Remember the alternate JID so live messages addressed to it
+                // land in this chat.
+                if let Some(alt_jid) = alt_jid
+                    && alt_jid != jid
+                {
+                    self.add_jid_alias(alt_jid, jid.clone());
+                }
+
+                // Admins may delete messages of others in the group.
+                if ChatKind::from_jid(&jid) == ChatKind::Group {
+                    let db = Arc::clone(&self.db);
+                    let jid = jid.clone();
+                    relm4::spawn(async move {
+                        if let Err(e) = db.set_group_admins(&jid, &admins).await {
+                            tracing::error!("Failed to save admins of {jid}: {e}");
+                        }
+                    });
+                }
+
+                // Merge into the chat if it already exists, so local changes
+                // made while syncing aren't overwritten.
+                if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) {
+                    let was_archived = chat.archived;
+                    let synced_at = modified_at
+                        .or(last_message_time)
+                        .and_then(|ts| DateTime::from_timestamp(ts.cast_signed(), 0))
+                        .unwrap_or(DateTime::UNIX_EPOCH);
+
+                    if chat.merge_synced(name, pinned, archived, mute_end_time, synced_at) {
+                        let chat_clone = chat.clone();
+                        relm4::spawn(async move {
+                            if let Err(e) = chat_clone.save().await {
+                                tracing::error!("Failed to save merged chat: {}", e);
+                            }
+                        });
+
+                        if chat.archived == was_archived {
+                            self.chat_list.emit(ChatListInput::UpdateChat {
+                                chat: chat.clone(),
+                                move_to_top: false,
+                            });
+                        } else if chat.archived {
+                            self.chat_list.emit(ChatListInput::RemoveChat { jid });
+                        } else {
+                            self.chat_list.emit(ChatListInput::AddChat {
+                                chat: chat.clone(),
+                                at_top: false,
+                            });
+                        }
+                    }
                     return;
                 }
 
@@ -1194,7 +5600,9 @@ impl AsyncComponent for Application {
                         pinned,
                         archived,
                         participants,
+                        mute_end_time,
                         last_message_time,
+                        participant_count,
                     }
                 });
 
@@ -1203,8 +5611,12 @@ impl AsyncComponent for Application {
                 });
             }
 
-            AppMsg::MessagesSynced { chat_jid, messages } => {
-                let is_group = chat_jid.ends_with("@g.us");
+            AppMsg::MessagesSynced {
+                chat_jid,
+                messages,
+                ticket,
+            } => {
+                let is_group = ChatKind::from_jid(&chat_jid) == ChatKind::Group;
 
                 // Update chat in the list (lightweight UI update) before moving values.
                 if let Some(chat) = self.chats.iter().find(|c| c.jid == chat_jid).cloned() {
@@ -1220,6 +5632,7 @@ impl AsyncComponent for Application {
                         chat_jid,
                         is_group,
                         messages,
+                        ticket,
                     }
                 });
             }
@@ -1228,14 +5641,25 @@ impl AsyncComponent for Application {
                 jid,
                 pinned,
                 muted,
+                mute_end,
                 archived,
             } => {
                 if let Some(chat) = self.chats.iter_mut().find(|c| c.jid == jid) {
+                    if pinned.is_some() || muted.is_some() || archived.is_some() {
+                        chat.flags_modified_at = Some(Utc::now());
+                    }
                     if let Some(pinned) = pinned {
                         chat.pinned = pinned;
+                        chat.pin_order = None;
                     }
                     if let Some(muted) = muted {
-                        chat.muted = muted;
+                        match mute_end.filter(|end| muted && *end != 0) {
+                            Some(end) => chat.set_mute_end(end),
+                            None => {
+                                chat.muted = muted;
+                                chat.muted_until = None;
+                            }
+                        }
                     }
                     if let Some(archived) = archived {
                         chat.archived = archived;
@@ -1271,36 +5695,263 @@ impl AsyncComponent for Application {
                         });
                     }
                 }
+
+                // Muted chats don't count on the launcher badge.
+                if muted.is_some() {
+                    self.unread_changed(&sender);
+                }
+            }
+            AppMsg::LauncherBadgeChanged => self.unread_changed(&sender),
+            AppMsg::CheckDiskSpace => {
+                self.low_on_space = is_low_on_space(&DATA_DIR);
+                if self.low_on_space {
+                    tracing::warn!("Low on disk space in {}", DATA_DIR.display());
+                }
+            }
+            AppMsg::Syncing => {
+                tracing::info!("Syncing chats");
+                self.state = AppState::Syncing;
+                self.start_sync_deadline(&sender);
+
+                // Only the first sync takes the whole window.
+                if self.page != AppPage::Syncing {
+                    self.chat_list
+                        .emit(ChatListInput::SetConnectionState(ConnectionState::Syncing));
+                }
             }
             AppMsg::HistorySyncCompleted => {
                 tracing::info!("History sync completed");
-                if self.state == AppState::Syncing {
-                    self.state = AppState::Ready;
-                }
+                self.finish_syncing(&sender);
+                sender.input(AppMsg::CheckDiskSpace);
             }
             AppMsg::OfflineSyncCompleted => {
                 tracing::info!("Offline sync completed");
-                if self.state == AppState::Syncing {
-                    self.state = AppState::Ready;
+                self.finish_syncing(&sender);
+                sender.input(AppMsg::CheckDiskSpace);
+            }
+
+            AppMsg::StatusViewed { id, sender_jid } => {
+                let db = Arc::clone(&self.db);
+                let status_id = id.clone();
+                relm4::spawn(async move {
+                    if let Err(e) = db.mark_status_update_viewed(&status_id).await {
+                        tracing::error!("Failed to mark status update as viewed: {e}");
+                    }
+                });
+
+                self.send_receipt(PendingReceipt {
+                    id: Uuid::new_v4(),
+                    attempts: 0,
+                    chat_jid: STATUS_JID.to_string(),
+                    sender_jid: Some(sender_jid),
+                    message_ids: vec![id],
+                })
+                .await;
+            }
+            AppMsg::StatusesExpired(before) => {
+                if let Err(e) = self.db.delete_status_updates(before).await {
+                    tracing::error!("Failed to delete expired status updates: {e}");
                 }
             }
 
-            AppMsg::Unknown => {}
-            AppMsg::Error { message } => {
-                self.state = AppState::Error(message.clone());
+            AppMsg::CallOffer {
+                call_id,
+                from_jid,
+                is_video,
+            } => {
+                let caller = self.get_display_name(&from_jid).await;
+                let heading = if is_video {
+                    i18n!("Incoming Video Call")
+                } else {
+                    i18n!("Incoming Voice Call")
+                };
+
+                // Only one call can be answered at a time.
+                if self.call_dialog.is_some() {
+                    self.client.emit(ClientInput::DeclineCall {
+                        call_id: call_id.clone(),
+                    });
+                    self.log_missed_call(&from_jid, &call_id, is_video, Utc::now());
+                    return;
+                }
+
+                // Only one call can ring at a time.
+                if let Some(call) = self.incoming_call.take() {
+                    call.dialog.force_close();
+                    sound::stop(Sound::Ringtone);
+                    self.log_missed_call(
+                        &call.from_jid,
+                        &call.call_id,
+                        call.is_video,
+                        call.timestamp,
+                    );
+                }
+
+                let dialog = adw::AlertDialog::builder()
+                    .heading(&heading)
+                    .body(&caller)
+                    .default_response("accept")
+                    .close_response("decline")
+                    .build();
+                dialog.add_responses(&[
+                    ("decline", &i18n!("_Decline")),
+                    ("accept", &i18n!("_Accept")),
+                ]);
+                dialog.set_response_appearance("decline", adw::ResponseAppearance::Destructive);
+                dialog.set_response_appearance("accept", adw::ResponseAppearance::Suggested);
+
+                let sender_clone = sender.clone();
+                let call_id_clone = call_id.clone();
+                dialog.connect_response(None, move |_, response| {
+                    sender_clone.input(AppMsg::AnswerCall {
+                        call_id: call_id_clone.clone(),
+                        accept: response == "accept",
+                    });
+                });
+                dialog.present(Some(root));
+
+                // Ring even when the window is not focused.
+                let notification = gio::Notification::new(&heading);
+                notification.set_body(Some(&caller));
+                notification.set_priority(gio::NotificationPriority::Urgent);
+                notification.add_button_with_target_value(
+                    &i18n!("Decline"),
+                    "app.decline-call",
+                    Some(&call_id.to_variant()),
+                );
+                notification.add_button_with_target_value(
+                    &i18n!("Accept"),
+                    "app.accept-call",
+                    Some(&call_id.to_variant()),
+                );
+                if self.notifications_muted() {
+                    tracing::debug!("Not notifying call {call_id} while notifications are muted");
+                } else {
+                    main_application().send_notification(Some(CALL_NOTIFICATION_ID), &notification);
 
-                #[allow(clippy::match_same_arms)] // FIXME: remove when `Error` page is added
-                match self.page {
-                    AppPage::Login => {
-                        self.login.emit(LoginInput::Error { message });
+                    if gio::Settings::new(APP_ID).boolean("call-ringtone") {
+                        sound::play_looping(Sound::Ringtone);
+
+                        let call_id = call_id.clone();
+                        sender.oneshot_command(async move {
+                            time::sleep(RING_TIMEOUT).await;
+                            AppCmd::StopRinging { call_id }
+                        });
                     }
-                    AppPage::Session => {
-                        // TODO: display error
+                }
+
+                self.incoming_call = Some(IncomingCall {
+                    dialog,
+                    call_id,
+                    from_jid,
+                    is_video,
+                    timestamp: Utc::now(),
+                });
+            }
+            AppMsg::AnswerCall { call_id, accept } => {
+                let Some(call) = self.dismiss_incoming_call(&call_id) else {
+                    return;
+                };
+
+                if accept {
+                    let name = self.get_display_name(&call.from_jid).await;
+                    self.client.emit(ClientInput::AcceptCall {
+                        call_id: call_id.clone(),
+                    });
+                    self.show_call(call_id, name, call.is_video, true, &sender);
+                } else {
+                    self.client.emit(ClientInput::DeclineCall { call_id });
+                }
+            }
+
+            AppMsg::Unknown => {}
+            AppMsg::Error(error) => {
+                let message = error.message();
+                match error {
+                    ClientError::Pairing(failure) => {
+                        self.login.emit(LoginInput::PairFailed(failure));
                     }
-                    AppPage::Fetching => {
+                    // Nothing works without the session storage.
+                    ClientError::Database(_) => {
+                        self.state = AppState::Error(message);
                         self.page = AppPage::Error;
                     }
-                    AppPage::Error => {}
+                    ClientError::Network(_) => {
+                        self.chat_list
+                            .emit(ChatListInput::SetConnectionState(ConnectionState::Offline));
+                        self.state = AppState::Error(message.clone());
+
+                        match self.page {
+                            AppPage::Login => self.login.emit(LoginInput::Error(error)),
+                            AppPage::Session | AppPage::Syncing => {
+                                let toast = adw::Toast::builder()
+                                    .title(message)
+                                    .button_label(i18n!("_Retry"))
+                                    .priority(adw::ToastPriority::High)
+                                    .build();
+                                let sender = sender.clone();
+                                toast.connect_button_clicked(move |_| {
+                                    sender.input(AppMsg::ResetSession);
+                                });
+                                self.toaster.add_toast(toast);
+                            }
+                            AppPage::Fetching => self.page = AppPage::Error,
+                            AppPage::Error => {}
+                        }
+                    }
+                }
+            }
+            AppMsg::TestNotification => {
+                // Sandboxed apps get asked by the portal the first time they notify,
+                // and notifications can be turned off, so the step is only checked
+                // off once the notification is seen and clicked.
+                let notification = gio::Notification::new(&i18n!("Notifications Are On"));
+                notification.set_body(Some(&i18n!(
+                    "New messages will show up like this. Click to finish this step."
+                )));
+                notification.set_default_action("app.notifications-shown");
+                notification.add_button(&i18n!("Done"), "app.notifications-shown");
+                main_application().send_notification(Some(TEST_NOTIFICATION_ID), &notification);
+            }
+            AppMsg::NotificationsShown => {
+                main_application().withdraw_notification(TEST_NOTIFICATION_ID);
+                OnboardingStep::AllowNotifications.complete(&self.settings);
+            }
+            AppMsg::RequestBackground => {
+                if !background::is_sandboxed() {
+                    OnboardingStep::EnableBackground.complete(&self.settings);
+                    return;
+                }
+
+                let sender = sender.clone();
+                relm4::spawn_local(async move {
+                    let reason = i18n!("Receive messages and calls with the window closed");
+                    match background::request(&reason).await {
+                        Ok(allowed) => sender.input(AppMsg::BackgroundAllowed(allowed)),
+                        Err(e) => tracing::warn!("Failed to ask to run in the background: {e}"),
+                    }
+                });
+            }
+            AppMsg::BackgroundAllowed(allowed) => {
+                tracing::info!("Running in the background allowed: {allowed}");
+                if allowed {
+                    OnboardingStep::EnableBackground.complete(&self.settings);
+                } else {
+                    if let Err(e) = self.settings.set_boolean("run-in-background", false) {
+                        tracing::error!("Failed to turn running in the background off: {e}");
+                    }
+                    self.show_toast(
+                        &i18n!("Papo isn't allowed to run in the background"),
+                        adw::ToastPriority::High,
+                    );
+                }
+            }
+            AppMsg::CloseWindow => {
+                // Stay connected with the window hidden, until activated again.
+                if self.settings.boolean("run-in-background") {
+                    root.set_visible(false);
+                } else {
+                    sender.input(AppMsg::Quit);
                 }
             }
             AppMsg::Quit => main_application().quit(),
@@ -1312,13 +5963,163 @@ impl AsyncComponent for Application {
         &mut self,
         command: Self::CommandOutput,
         sender: AsyncComponentSender<Self>,
-        _root: &Self::Root,
+        root: &Self::Root,
     ) {
         match command {
+            AppCmd::ReportSaved(Ok(())) => {
+                let toast = adw::Toast::builder()
+                    .title(i18n!("Problem report saved"))
+                    .button_label(i18n!("Open Issue"))
+                    .build();
+                toast.connect_button_clicked(|_| {
+                    let window = main_application().active_window();
+                    gtk::UriLauncher::new(&report::issue_url()).launch(
+                        window.as_ref(),
+                        gio::Cancellable::NONE,
+                        |result| {
+                            if let Err(e) = result {
+                                tracing::error!("Failed to open the issue page: {e}");
+                            }
+                        },
+                    );
+                });
+                self.toaster.add_toast(toast);
+            }
+            AppCmd::ReportSaved(Err(e)) => {
+                tracing::error!("Failed to save problem report: {e}");
+                self.show_toast(
+                    &i18n!("Couldn't save the problem report"),
+                    adw::ToastPriority::High,
+                );
+            }
+            AppCmd::BookmarksExported(Ok(())) => {
+                self.show_toast(&i18n!("Bookmarks exported"), adw::ToastPriority::Normal);
+            }
+            AppCmd::BookmarksExported(Err(e)) => {
+                tracing::error!("Failed to export bookmarks: {e}");
+                self.show_toast(
+                    &i18n!("Couldn't export the bookmarks"),
+                    adw::ToastPriority::High,
+                );
+            }
+            AppCmd::StopRinging { call_id } => {
+                if self
+                    .incoming_call
+                    .as_ref()
+                    .is_some_and(|call| call.call_id == call_id)
+                {
+                    sound::stop(Sound::Ringtone);
+                }
+            }
+            AppCmd::RetryReceipts(receipts) => {
+                for receipt in receipts {
+                    self.resend_receipt(receipt).await;
+                }
+            }
+            AppCmd::ReceiptRetryDue(receipt) => {
+                // Reconnecting may have sent it since.
+                if self.receipt_retries.retry_due(receipt.id) {
+                    self.resend_receipt(receipt).await;
+                }
+            }
+            AppCmd::DoNotDisturbExpired => {
+                let until = self.settings.int64("do-not-disturb-until");
+                if until != 0 && !do_not_disturb_active(&self.settings) {
+                    tracing::debug!("Do Not Disturb time elapsed");
+                    if let Err(e) = self.settings.set_int64("do-not-disturb-until", 0) {
+                        tracing::error!("Failed to turn Do Not Disturb off: {e}");
+                    }
+                }
+            }
+            AppCmd::FocusModeExpired => {
+                // Turned off or on again for longer in between.
+                if self
+                    .focus_mode_until
+                    .is_some_and(|until| until <= Instant::now())
+                {
+                    tracing::debug!("Focus mode time elapsed");
+                    self.set_focus_mode(false, None, &sender);
+                }
+            }
+            AppCmd::FlushPendingRead => {
+                // Only tell senders their messages were read if they can be seen,
+                // keeping the read for when the window is focused again.
+                if self.session_page != AppSessionPage::ChatHistory {
+                    self.pending_read = None;
+                } else if root.is_active()
+                    && let Some(jid) = self.pending_read.take()
+                {
+                    self.mark_chat_read(&jid).await;
+                    self.unread_changed(&sender);
+                }
+            }
+            AppCmd::SyncTimedOut(attempt) => {
+                if self.sync_deadline.is_current(attempt)
+                    && (self.state == AppState::Syncing || self.page == AppPage::Syncing)
+                {
+                    tracing::warn!("Sync didn't complete in time, showing the chats");
+                    self.finish_syncing(&sender);
+                }
+            }
+            AppCmd::RefreshUnreadTotal => {
+                if let Some(wait) = self.unread_total.settle(Instant::now()) {
+                    schedule_unread_total(&sender, wait);
+                    return;
+                }
+
+                // Shown once the initial sync is over instead.
+                if self.state == AppState::Syncing {
+                    return;
+                }
+
+                // Focus mode hides the unread counts.
+                let total = if self.focus_mode() {
+                    Ok(0)
+                } else {
+                    self.db.get_total_unread_count(true).await
+                };
+                match total {
+                    Ok(total) => {
+                        if let Some(total) = self.unread_total.update(total) {
+                            let title = if total > 0 {
+                                let total = u32::try_from(total).unwrap_or(u32::MAX);
+                                format!(
+                                    "{} ({})",
+                                    i18n!("Papo"),
+                                    format_badge_count(total, MAX_UNREAD_COUNT)
+                                )
+                            } else {
+                                i18n!("Papo")
+                            };
+                            root.set_title(Some(&title));
+                        }
+                    }
+                    Err(e) => tracing::error!("Failed to count unread messages: {e}"),
+                }
+
+                let badge = if self.settings.boolean("launcher-badge") && !self.focus_mode() {
+                    self.db.get_total_unread_count(false).await
+                } else {
+                    Ok(0)
+                };
+                match badge {
+                    Ok(count) => {
+                        if let Some(count) = self.unread_total.update_badge(count) {
+                            launcher::set_badge(count);
+                        }
+                    }
+                    Err(e) => tracing::error!("Failed to count unread messages for the badge: {e}"),
+                }
+            }
             AppCmd::Sync => {
                 self.state = AppState::Syncing;
                 let mut chats_needing_avatars = Vec::new();
 
+                self.last_sort_key = self.db.max_chat_sort_key().await.unwrap_or_else(|e| {
+                    tracing::error!("Failed to get the highest chat sort key: {e}");
+                    0
+                });
+
                 match self.db.load_chats().await {
                     Ok(mut chats) => {
                         tracing::info!("Loaded {} chats from own database", chats.len());
@@ -1338,10 +6139,11 @@ impl AsyncComponent for Application {
                             }
                         }
 
-                        // Insert all chats into our cached list.
+                        // Insert all chats into our cached list, archived ones
+                        // included so syncs and property updates find them.
                         self.chats.extend(chats);
 
-                        for chat in &self.chats {
+                        for chat in self.chats.iter().filter(|c| !c.archived) {
                             // Add the chat to the chat list.
                             self.chat_list.emit(ChatListInput::AddChat {
                                 chat: chat.clone(),
@@ -1352,7 +6154,32 @@ impl AsyncComponent for Application {
                     Err(e) => tracing::error!("Failed to load chats from own database: {}", e),
                 }
 
+                // Route messages addressed by alternate JIDs seen before.
+                match self.db.load_jid_aliases().await {
+                    Ok(aliases) => self.jid_aliases.0.extend(aliases),
+                    Err(e) => tracing::error!("Failed to load JID aliases: {e}"),
+                }
+
+                // Load the status updates still shown, dropping older ones.
+                let since = Utc::now() - StatusUpdate::LIFETIME;
+                if let Err(e) = self.db.delete_status_updates(since).await {
+                    tracing::error!("Failed to delete expired status updates: {}", e);
+                }
+                match self.db.load_status_updates(since).await {
+                    Ok(statuses) => {
+                        for status in statuses {
+                            self.status_list.emit(StatusListInput::Add(status));
+                        }
+                    }
+                    Err(e) => tracing::error!("Failed to load status updates: {}", e),
+                }
+
                 self.state = AppState::Ready;
+                self.unread_changed(&sender);
+
+                if let Some(chat) = self.pending_chat.take() {
+                    sender.input(AppMsg::OpenChat(chat));
+                }
 
                 // Emit `SyncCompleted` to fetch avatars in the regular update cycle.
                 if !chats_needing_avatars.is_empty() {
@@ -1368,7 +6195,9 @@ impl AsyncComponent for Application {
                 pinned,
                 archived,
                 participants,
+                mute_end_time,
                 last_message_time,
+                participant_count,
             } => {
                 // Skip if chat already exists (double-check in background).
                 if self.chats.iter().any(|c| c.jid == jid) {
@@ -1377,12 +6206,12 @@ impl AsyncComponent for Application {
 
                 // Determine chat name.
                 let chat_name = name.unwrap_or_else(|| {
-                    if jid.ends_with("@g.us") {
+                    if ChatKind::from_jid(&jid) == ChatKind::Group {
                         format!("{} {}", i18n!("Group"), &jid[..8.min(jid.len())])
                     } else if self.user_jid.as_ref().is_some_and(|u_j| jid == *u_j) {
                         i18n!("You")
                     } else {
-                        format_lid_as_number(&jid)
+                        format_jid(&jid)
                     }
                 });
 
@@ -1397,30 +6226,36 @@ impl AsyncComponent for Application {
                     participants_map.insert(pjid, pname.unwrap_or_else(|| i18n!("Unknown")));
                 }
 
-                let chat = Chat {
+                let mut chat = Chat {
                     jid,
                     name: chat_name,
-                    muted: false, // TODO: handle mute_end_time
+                    muted: false,
+                    muted_until: None,
                     pinned,
                     archived,
+                    membership: Membership::Member,
                     available: None,
                     last_seen: None,
                     avatar_path: None,
                     participants: participants_map,
                     last_message_time,
+                    participant_count,
+                    name_modified_at: None,
+                    flags_modified_at: None,
+                    pin_order: None,
+                    sort_key: last_message_time.timestamp_millis(),
 
                     db: Arc::clone(&self.db),
                 };
+                if let Some(end) = mute_end_time {
+                    chat.set_mute_end(end.cast_signed());
+                }
 
                 // Add to cached list (keep in memory for property updates even if archived).
                 self.chats.push(chat.clone());
 
                 // Sort chats.
-                self.chats.sort_by(|a, b| {
-                    b.pinned
-                        .cmp(&a.pinned)
-                        .then_with(|| b.last_message_time.cmp(&a.last_message_time))
-                });
+                self.sort_chats();
 
                 // Add to chat list UI only if not archived.
                 if !archived {
@@ -1449,6 +6284,7 @@ impl AsyncComponent for Application {
                 chat_jid,
                 is_group,
                 messages,
+                ticket,
             } => {
                 let db = Arc::clone(&self.db);
 
@@ -1456,7 +6292,9 @@ impl AsyncComponent for Application {
                 let sender_info: Vec<(String, Option<String>)> = if is_group {
                     messages
                         .iter()
-                        .filter(|m| !m.outgoing && !m.sender_jid.is_empty())
+                        .filter(|m| {
+                            !m.outgoing && !m.sender_jid.is_empty() && m.group_event.is_none()
+                        })
                         .map(|m| (m.sender_jid.clone(), m.sender_name.clone()))
                         .collect()
                 } else {
@@ -1475,22 +6313,80 @@ impl AsyncComponent for Application {
                     }
                 }
 
-                // Spawn database operations in background task.
+                // The latest change to the user's membership tells if they're
+                // still in the group.
+                if let Some(joined) = latest_membership(&messages, &self.own_jids()) {
+                    self.set_chat_left(&chat_jid, !joined);
+                }
+
+                // Take changes that arrived before the messages themselves.
+                let mut pending: HashMap<String, PendingReference> = messages
+                    .iter()
+                    .filter_map(|m| Some((m.id.clone(), self.pending_references.take(&m.id)?)))
+                    .collect();
+
+                // Names used to describe group changes.
+                let user_jid = self.user_jid.clone();
+                let participants = self
+                    .chats
+                    .iter()
+                    .find(|c| c.jid == chat_jid)
+                    .map(|c| c.participants.clone())
+                    .unwrap_or_default();
+
+                // Spawn database operations in background task, holding
+                // avatar downloads back until the messages are saved.
                 relm4::spawn(async move {
+                    let _ticket = ticket;
                     let mut saved_count = 0;
                     let mut dup_count = 0;
                     let mut skip_count = 0;
                     let total = messages.len();
 
+                    // Messages from before the history was cleared stay deleted.
+                    let cleared_before = match db.load_chat_settings(&chat_jid).await {
+                        Ok(settings) => settings.cleared_before.map(|t| t.timestamp()),
+                        Err(e) => {
+                            tracing::error!("Failed to load chat settings: {}", e);
+                            None
+                        }
+                    };
+                    let mut cleared_count = 0;
+
+                    // Messages up to the last one read here were already seen.
+                    let last_read = db.get_last_read_time(&chat_jid).await.unwrap_or_else(|e| {
+                        tracing::error!("Failed to load last read time: {}", e);
+                        None
+                    });
+
+                    // Synced messages only update the unread state, they never notify.
                     for synced_msg in messages {
-                        // Skip messages without content for now.
-                        let Some(content) = synced_msg.content else {
-                            skip_count += 1;
+                        if cleared_before.is_some_and(|t| synced_msg.timestamp.cast_signed() <= t) {
+                            cleared_count += 1;
                             continue;
-                        };
+                        }
+
+                        // Group changes are kept as service events.
+                        let (text, kind) =
+                            match (synced_msg.content.display_text(), &synced_msg.group_event) {
+                                (_, Some(event)) => (
+                                    event.display_text(user_jid.as_deref(), &participants),
+                                    MessageKind::Service,
+                                ),
+                                (Some(text), None) => (text, synced_msg.content.kind),
+                                // Skip messages without content for now.
+                                (None, None) => {
+                                    skip_count += 1;
+                                    continue;
+                                }
+                            };
+
+                        let unread = synced_msg.unread
+                            && last_read.is_none_or(|t| synced_msg.timestamp.cast_signed() > t);
 
                         // Select message status based on `unread` and `outgoing` fields.
-                        let status = match (synced_msg.unread, synced_msg.outgoing) {
+                        let status = match (unread, synced_msg.outgoing) {
+                            _ if kind == MessageKind::Service => MessageStatus::Read,
                             (true, false) => MessageStatus::Delivered,
                             (true, true) => MessageStatus::Sent,
                             (false, _) => MessageStatus::Read,
@@ -1501,22 +6397,36 @@ impl AsyncComponent for Application {
                             DateTime::from_timestamp(synced_msg.timestamp.cast_signed(), 0)
                                 .unwrap_or_else(Utc::now);
 
-                        let message = ChatMessage {
+                        let mut message = ChatMessage {
                             local_id: Uuid::new_v4(),
                             server_id: synced_msg.id,
                             chat_jid: chat_jid.clone(),
                             sender_jid: synced_msg.sender_jid.clone(),
                             sender_name: synced_msg.sender_name.clone(),
 
+                            kind,
+                            poll: None,
                             media: None,
+                            edited: false,
                             status,
-                            content,
+                            content: text,
+                            revoked: false,
+                            contacts: Vec::new(),
+                            location: None,
+                            quote: None,
+                            preview: None,
                             outgoing: synced_msg.outgoing,
                             reactions: IndexMap::new(),
                             timestamp,
 
                             db: Arc::clone(&db),
                         };
+                        if synced_msg.group_event.is_none() {
+                            synced_msg.content.apply(&mut message);
+                        }
+                        if let Some(pending) = pending.remove(&message.server_id) {
+                            pending.apply(&mut message);
+                        }
 
                         // Save the message, skipping duplicates on server_id.
                         match message.save_or_ignore().await {
@@ -1527,14 +6437,16 @@ impl AsyncComponent for Application {
                     }
 
                     tracing::info!(
-                        "Synced {} messages for chat: {} (of {} received, {} duplicates, {} without content)",
+                        "Synced {} messages for chat: {} (of {} received, {} duplicates, {} without content, {} cleared)",
                         saved_count,
                         chat_jid,
                         total,
                         dup_count,
-                        skip_count
+                        skip_count,
+                        cleared_count
                     );
                 });
+                self.unread_changed(&sender);
             }
         }
     }
@@ -1579,3 +6491,474 @@ fn bp_with_setters(
     bp.add_setters(additions);
     bp
 }
+
+/// Schedule a refresh of the unread messages total after `delay`.
+fn schedule_unread_total(sender: &AsyncComponentSender<Application>, delay: Duration) {
+    sender.oneshot_command(async move {
+        time::sleep(delay).await;
+        AppCmd::RefreshUnreadTotal
+    });
+}
+
+/// Tell if the user is in a group after the latest change to their
+/// membership among synced messages, if any changed it.
+fn latest_membership(messages: &[SyncedMessage], user_jids: &[String]) -> Option<bool> {
+    messages
+        .iter()
+        .filter_map(|m| {
+            let joined = m.group_event.as_ref()?.membership_change(user_jids)?;
+            Some((m.timestamp, joined))
+        })
+        .max_by_key(|(timestamp, _)| *timestamp)
+        .map(|(_, joined)| joined)
+}
+
+glib::bitflags::bitflags! {
+    /// What decides if a received message is notified.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct NotifyCheck: u8 {
+        /// The user sent the message, from another device.
+        const OUTGOING = 1;
+        /// The user left the group, stray messages still reaching it.
+        const LEFT = 1 << 1;
+        /// Notifications are muted, by Do Not Disturb or screen sharing.
+        const NOTIFICATIONS_MUTED = 1 << 2;
+        /// The chat is open in the focused window, being read already.
+        const IN_VIEW = 1 << 3;
+        /// The chat is muted right now.
+        const CHAT_MUTED = 1 << 4;
+        /// The chat is a group.
+        const GROUP = 1 << 5;
+        /// Mentions are still notified in muted groups.
+        const MENTIONS_IN_MUTED_GROUPS = 1 << 6;
+        /// The message mentions the user.
+        const MENTIONED = 1 << 7;
+    }
+}
+
+impl NotifyCheck {
+    /// Whether the message is notified and sounded.
+    fn should_notify(self) -> bool {
+        if self.intersects(Self::OUTGOING | Self::LEFT | Self::NOTIFICATIONS_MUTED | Self::IN_VIEW)
+        {
+            return false;
+        }
+
+        if self.contains(Self::CHAT_MUTED) {
+            return self.contains(Self::GROUP | Self::MENTIONS_IN_MUTED_GROUPS | Self::MENTIONED);
+        }
+
+        true
+    }
+}
+
+/// Whether a message mentions the user, listed among its mentioned JIDs or
+/// written as `@` and their number or LID in its text.
+fn mentions_user(text: &str, mentioned_jids: &[String], user_jids: &[&str]) -> bool {
+    let users = user_jids
+        .iter()
+        .filter_map(|jid| jid.split(['@', ':']).next())
+        .filter(|user| !user.is_empty())
+        .collect::<Vec<_>>();
+
+    let listed = mentioned_jids
+        .iter()
+        .filter_map(|jid| jid.split(['@', ':']).next())
+        .any(|mentioned| users.contains(&mentioned));
+
+    // `@123` mustn't match the start of `@1234`, nor the end of `a@123`.
+    listed
+        || users.iter().any(|user| {
+            let mention = format!("@{user}");
+            text.match_indices(&mention).any(|(start, _)| {
+                let before = text[..start].chars().next_back();
+                let after = text[start + mention.len()..].chars().next();
+                before.is_none_or(|c| !c.is_alphanumeric())
+                    && after.is_none_or(|c| !c.is_alphanumeric())
+            })
+        })
+}
+
+/// Get the sound of a message reaching `status` and the setting turning it
+/// on. Only the focused window sounds, and not while notifications are muted.
+fn send_outcome_sound(
+    status: MessageStatus,
+    focused: bool,
+    muted: bool,
+) -> Option<(Sound, &'static str)> {
+    if !focused || muted {
+        return None;
+    }
+
+    match status {
+        MessageStatus::Sent => Some((Sound::MessageSent, "message-sent-sound")),
+        MessageStatus::Failed => Some((Sound::MessageFailed, "message-failed-sound")),
+        _ => None,
+    }
+}
+
+/// Check if Do Not Disturb is on, as saved in `do-not-disturb-until`: the
+/// Unix time it ends at, `i64::MAX` until turned off, or 0 when off.
+fn do_not_disturb_active(settings: &gio::Settings) -> bool {
+    settings.int64("do-not-disturb-until") > Utc::now().timestamp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{block_on, chat, database, message};
+
+    const USER: &[&str] = &["5511999990000@s.whatsapp.net", "12345678901234@lid"];
+
+    fn mentions(text: &str, mentioned_jids: &[&str]) -> bool {
+        let mentioned_jids = mentioned_jids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        mentions_user(text, &mentioned_jids, USER)
+    }
+
+    #[test]
+    fn live_lid_messages_land_in_the_synced_pn_chat() {
+        const PN: &str = "5511988887777@s.whatsapp.net";
+        const LID: &str = "98765432109876@lid";
+
+        let db = block_on(database());
+        // The chat was created by the history sync, with its PN.
+        let chats = vec![chat(&db, PN)];
+        let mut aliases = JidAliases::default();
+
+        // A live message addressed to the LID, with the PN as the alternate.
+        let jid = aliases.resolve(&chats, LID, Some(PN));
+        assert_eq!(jid, PN);
+        assert!(aliases.insert(LID.to_string(), jid));
+        assert!(!aliases.insert(LID.to_string(), PN.to_string()));
+
+        // Later ones may only come with the LID.
+        assert_eq!(aliases.resolve(&chats, LID, None), PN);
+        assert_eq!(aliases.resolve(&chats, PN, Some(LID)), PN);
+        // Unknown chats are still created under their own JID.
+        assert_eq!(aliases.resolve(&chats, "1@lid", None), "1@lid");
+    }
+
+    #[test]
+    fn only_the_latest_sync_deadline_counts() {
+        let mut deadline = SyncDeadline::default();
+        let first = deadline.start();
+        assert!(deadline.is_current(first));
+
+        let second = deadline.start();
+        assert!(!deadline.is_current(first));
+        assert!(deadline.is_current(second));
+    }
+
+    #[test]
+    fn mention_in_text_matches_whole_number() {
+        assert!(mentions("@5511999990000", &[]));
+        assert!(mentions("hi @5511999990000!", &[]));
+        assert!(mentions("@12345678901234, look", &[]));
+        assert!(!mentions("@55119999900001", &[]));
+        assert!(!mentions("@551199999000", &[]));
+        assert!(!mentions("mail a@5511999990000", &[]));
+        assert!(!mentions("5511999990000", &[]));
+    }
+
+    #[test]
+    fn mention_listed_in_context_info_matches() {
+        assert!(mentions("@Someone", &["5511999990000@s.whatsapp.net"]));
+        assert!(mentions("@Someone", &["12345678901234:3@lid"]));
+        assert!(!mentions("@Someone", &["5511999990001@s.whatsapp.net"]));
+    }
+
+    #[test]
+    fn no_user_jid_never_matches() {
+        assert!(!mentions_user("@", &[String::from("@lid")], &[]));
+        assert!(!mentions_user("@", &[], &["@s.whatsapp.net"]));
+    }
+
+    #[test]
+    fn notify_in_unmuted_chats() {
+        assert!(NotifyCheck::empty().should_notify());
+        assert!(NotifyCheck::GROUP.should_notify());
+    }
+
+    #[test]
+    fn skip_outgoing_left_muted_and_read() {
+        for check in [
+            NotifyCheck::OUTGOING,
+            NotifyCheck::LEFT,
+            NotifyCheck::NOTIFICATIONS_MUTED,
+            NotifyCheck::IN_VIEW,
+        ] {
+            assert!(!check.should_notify(), "{check:?}");
+        }
+    }
+
+    #[test]
+    fn muted_group_notifies_mentions_only_when_enabled() {
+        let muted_group = NotifyCheck::CHAT_MUTED | NotifyCheck::GROUP;
+
+        assert!(!muted_group.should_notify());
+        assert!(!(muted_group | NotifyCheck::MENTIONED).should_notify());
+        assert!(!(muted_group | NotifyCheck::MENTIONS_IN_MUTED_GROUPS).should_notify());
+        let mentions = muted_group | NotifyCheck::MENTIONED | NotifyCheck::MENTIONS_IN_MUTED_GROUPS;
+        assert!(mentions.should_notify());
+        assert!(!(mentions | NotifyCheck::NOTIFICATIONS_MUTED).should_notify());
+    }
+
+    #[test]
+    fn muted_private_chat_ignores_mentions() {
+        let check = NotifyCheck::CHAT_MUTED
+            | NotifyCheck::MENTIONED
+            | NotifyCheck::MENTIONS_IN_MUTED_GROUPS;
+        assert!(!check.should_notify());
+    }
+
+    fn outgoing_message() -> ChatMessage {
+        let db = block_on(database());
+        let mut message = message(&db, "5511999990000@s.whatsapp.net", "", "3EB0");
+        message.outgoing = true;
+        message
+    }
+
+    #[test]
+    fn receipt_before_message_is_replayed() {
+        let mut pending = PendingReferences::default();
+        pending
+            .entry(String::from("3EB0"))
+            .receipt(MessageStatus::Delivered);
+
+        let mut message = outgoing_message();
+        pending
+            .take("3EB0")
+            .expect("Receipt wasn't kept")
+            .apply(&mut message);
+        assert_eq!(message.status, MessageStatus::Delivered);
+        assert!(pending.take("3EB0").is_none());
+    }
+
+    #[test]
+    fn read_before_delivered_stays_read() {
+        let mut pending = PendingReferences::default();
+        let entry = pending.entry(String::from("3EB0"));
+        entry.receipt(MessageStatus::Read);
+        entry.receipt(MessageStatus::Delivered);
+
+        let mut message = outgoing_message();
+        pending
+            .take("3EB0")
+            .expect("Receipt wasn't kept")
+            .apply(&mut message);
+        assert_eq!(message.status, MessageStatus::Read);
+    }
+
+    #[test]
+    fn replayed_receipt_never_lowers_status() {
+        let mut pending = PendingReferences::default();
+        pending
+            .entry(String::from("3EB0"))
+            .receipt(MessageStatus::Delivered);
+
+        let mut message = outgoing_message();
+        message.status = MessageStatus::Read;
+        pending
+            .take("3EB0")
+            .expect("Receipt wasn't kept")
+            .apply(&mut message);
+        assert_eq!(message.status, MessageStatus::Read);
+    }
+
+    #[test]
+    fn receipt_skips_incoming_messages() {
+        let mut pending = PendingReferences::default();
+        let entry = pending.entry(String::from("3EB0"));
+        entry.receipt(MessageStatus::Read);
+        entry.edit = Some((
+            String::from("Edited"),
+            MessageActor::contact("5511999990000@s.whatsapp.net", None),
+        ));
+
+        let mut message = outgoing_message();
+        message.outgoing = false;
+        pending
+            .take("3EB0")
+            .expect("Changes weren't kept")
+            .apply(&mut message);
+        assert_eq!(message.status, MessageStatus::Sent);
+        assert_eq!(message.content, "Edited");
+        assert!(message.edited);
+    }
+
+    #[test]
+    fn pending_edit_only_applies_from_the_sender() {
+        let db = block_on(database());
+        let mut pending = PendingReferences::default();
+        for (id, editor) in [("1", "bob@lid"), ("2", "alice:5@lid")] {
+            pending.entry(String::from(id)).edit =
+                Some((String::from("Edited"), MessageActor::contact(editor, None)));
+        }
+
+        for id in ["1", "2"] {
+            let mut message = message(&db, "group@g.us", "alice@lid", id);
+            pending
+                .take(id)
+                .expect("Edit wasn't kept")
+                .apply(&mut message);
+            assert_eq!(message.edited, id == "2");
+        }
+    }
+
+    #[test]
+    fn pending_references_are_capped() {
+        let mut pending = PendingReferences::default();
+        for id in 0..=MAX_PENDING_REFERENCES {
+            pending
+                .entry(id.to_string())
+                .receipt(MessageStatus::Delivered);
+        }
+
+        assert_eq!(pending.0.len(), MAX_PENDING_REFERENCES);
+        assert!(pending.take(&MAX_PENDING_REFERENCES.to_string()).is_some());
+    }
+
+    fn membership_event(timestamp: u64, event: Option<GroupEvent>) -> SyncedMessage {
+        SyncedMessage {
+            id: timestamp.to_string(),
+            unread: false,
+            content: MessageContent::default(),
+            outgoing: false,
+            timestamp,
+            sender_jid: String::from("admin@lid"),
+            group_event: event,
+            sender_name: None,
+        }
+    }
+
+    #[test]
+    fn latest_membership_change_wins() {
+        let user_jids = USER.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let removed = || {
+            Some(GroupEvent::ParticipantsRemoved {
+                actor: String::from("admin@lid"),
+                participants: vec![String::from("12345678901234:2@lid")],
+            })
+        };
+        let added = || {
+            Some(GroupEvent::ParticipantsAdded {
+                actor: String::from("admin@lid"),
+                participants: vec![String::from(USER[0])],
+            })
+        };
+
+        // Rejoining after being removed puts the user back in.
+        let rejoined = [
+            membership_event(3, added()),
+            membership_event(1, removed()),
+            membership_event(4, None),
+        ];
+        assert_eq!(latest_membership(&rejoined, &user_jids), Some(true));
+
+        // Messages after leaving keep them out.
+        let left = [
+            membership_event(1, added()),
+            membership_event(2, removed()),
+            membership_event(3, None),
+        ];
+        assert_eq!(latest_membership(&left, &user_jids), Some(false));
+
+        assert_eq!(
+            latest_membership(&[membership_event(1, None)], &user_jids),
+            None
+        );
+    }
+
+    #[test]
+    fn receipts_in_flight_are_sent_once() {
+        let mut retries = ReceiptRetries::default();
+        let id = Uuid::new_v4();
+
+        assert!(retries.start(id));
+        // Reconnecting while it's being sent doesn't send it again.
+        assert!(!retries.start(id));
+
+        // Not being connected leaves it to the flush on reconnecting.
+        retries.finish(id);
+        assert!(retries.start(id));
+    }
+
+    #[test]
+    fn reconnecting_cancels_the_pending_retry() {
+        let mut retries = ReceiptRetries::default();
+        let id = Uuid::new_v4();
+        retries.start(id);
+        retries.finish(id);
+        retries.back_off(id);
+
+        // The flush on reconnecting sends it before its retry is due.
+        assert!(retries.start(id));
+        assert!(!retries.retry_due(id));
+    }
+
+    #[test]
+    fn due_retry_sends_the_receipt_again() {
+        let mut retries = ReceiptRetries::default();
+        let id = Uuid::new_v4();
+        retries.start(id);
+        retries.finish(id);
+        retries.back_off(id);
+
+        assert!(retries.retry_due(id));
+        assert!(retries.start(id));
+        assert!(!retries.retry_due(id));
+    }
+
+    #[test]
+    fn send_outcome_sounds_respect_focus_and_muting() {
+        assert_eq!(
+            send_outcome_sound(MessageStatus::Sent, true, false),
+            Some((Sound::MessageSent, "message-sent-sound"))
+        );
+        assert_eq!(
+            send_outcome_sound(MessageStatus::Failed, true, false),
+            Some((Sound::MessageFailed, "message-failed-sound"))
+        );
+        assert_eq!(
+            send_outcome_sound(MessageStatus::Delivered, true, false),
+            None
+        );
+        assert_eq!(send_outcome_sound(MessageStatus::Sent, false, false), None);
+        // Do Not Disturb, or muted during a screencast.
+        assert_eq!(send_outcome_sound(MessageStatus::Sent, true, true), None);
+        assert_eq!(send_outcome_sound(MessageStatus::Failed, true, true), None);
+    }
+
+    #[test]
+    fn unread_total_waits_for_changes_to_settle() {
+        let start = Instant::now();
+        let mut total = UnreadTotal::default();
+
+        // A burst of changes schedules a single refresh.
+        assert!(total.change(start));
+        assert!(!total.change(start + Duration::from_millis(100)));
+        assert!(!total.change(start + Duration::from_millis(300)));
+
+        // The refresh waits until the last change has settled.
+        assert_eq!(
+            total.settle(start + UNREAD_TOTAL_DELAY),
+            Some(Duration::from_millis(300))
+        );
+        assert!(!total.change(start + Duration::from_millis(600)));
+        assert_eq!(
+            total.settle(start + Duration::from_millis(800)),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(total.settle(start + Duration::from_millis(1100)), None);
+
+        // Only a different total is shown.
+        assert_eq!(total.update(3), Some(3));
+        assert!(total.change(start + Duration::from_millis(1200)));
+        assert_eq!(total.settle(start + Duration::from_secs(2)), None);
+        assert_eq!(total.update(3), None);
+        assert_eq!(total.update(0), Some(0));
+    }
+}
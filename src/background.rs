@@ -0,0 +1,81 @@
+use std::{cell::Cell, collections::HashMap, path::Path};
+
+use gtk::{gio, glib, prelude::*};
+use relm4::gtk;
+use tokio::sync::oneshot;
+
+/// Desktop portal, which grants sandboxed apps permissions.
+const PORTAL_BUS_NAME: &str = "org.freedesktop.portal.Desktop";
+/// Object of the desktop portal.
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+/// Portal interface to run in the background.
+const BACKGROUND_INTERFACE: &str = "org.freedesktop.portal.Background";
+/// Interface of the requests the portal answers asynchronously.
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+
+/// Check if the app runs sandboxed, where running in the background needs
+/// to be allowed through the portal.
+pub fn is_sandboxed() -> bool {
+    Path::new("/.flatpak-info").exists()
+}
+
+/// Ask the portal to let the app keep running with its window closed,
+/// showing `reason` to the user. Returns whether it's allowed.
+pub async fn request(reason: &str) -> Result<bool, glib::Error> {
+    let connection = gio::bus_get_future(gio::BusType::Session).await?;
+
+    // Listen for the answer before asking, at the path the request will have.
+    let token = format!("papo{}", glib::random_int());
+    let unique_name = connection
+        .unique_name()
+        .map(|name| name.trim_start_matches(':').replace('.', "_"))
+        .unwrap_or_default();
+    let handle = format!("{PORTAL_PATH}/request/{unique_name}/{token}");
+
+    let (response_sender, response) = oneshot::channel();
+    let response_sender = Cell::new(Some(response_sender));
+    let _subscription = connection.subscribe_to_signal(
+        Some(PORTAL_BUS_NAME),
+        Some(REQUEST_INTERFACE),
+        Some("Response"),
+        Some(&handle),
+        None,
+        gio::DBusSignalFlags::NONE,
+        move |signal| {
+            let allowed = signal
+                .parameters
+                .get::<(u32, HashMap<String, glib::Variant>)>()
+                .is_some_and(|(response, results)| {
+                    response == 0
+                        && results
+                            .get("background")
+                            .and_then(glib::Variant::get::<bool>)
+                            .unwrap_or_default()
+                });
+            if let Some(sender) = response_sender.take() {
+                let _ = sender.send(allowed);
+            }
+        },
+    );
+
+    let options = glib::VariantDict::new(None);
+    options.insert("handle_token", token.as_str());
+    options.insert("reason", reason);
+    connection
+        .call_future(
+            Some(PORTAL_BUS_NAME),
+            PORTAL_PATH,
+            BACKGROUND_INTERFACE,
+            "RequestBackground",
+            Some(&glib::Variant::tuple_from_iter([
+                "".to_variant(),
+                options.end(),
+            ])),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+        )
+        .await?;
+
+    Ok(response.await.unwrap_or_default())
+}
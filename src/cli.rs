@@ -0,0 +1,124 @@
+use std::{io::Write, ops::ControlFlow, sync::Mutex};
+
+use gtk::{glib, prelude::*};
+use relm4::gtk;
+
+use crate::{
+    config::{PROFILE, VERSION},
+    i18n,
+};
+
+/// Options from the command line of the first launch, for the window to
+/// apply once it's up.
+static PENDING: Mutex<LaunchOptions> = Mutex::new(LaunchOptions {
+    hidden: false,
+    chat: None,
+});
+
+/// What to do on launch, as asked from the command line.
+#[derive(Debug, Default)]
+pub struct LaunchOptions {
+    /// Chat to open once connected, by JID or phone number.
+    pub chat: Option<String>,
+    /// Keep the window hidden, running in the background.
+    pub hidden: bool,
+}
+
+/// Register the command line options and their handlers.
+///
+/// `--version` is handled by the process it's given to; the rest are
+/// forwarded to the running instance, if any, instead of starting another.
+pub fn setup(app: &gtk::Application) {
+    app.add_main_option(
+        "version",
+        glib::Char::from(b'v'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        &i18n!("Print the version and exit"),
+        None,
+    );
+    app.add_main_option(
+        "quit",
+        glib::Char::from(b'q'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        &i18n!("Quit the running instance"),
+        None,
+    );
+    app.add_main_option(
+        "hidden",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        &i18n!("Start in the background, with the window hidden"),
+        None,
+    );
+    app.add_main_option(
+        "chat",
+        glib::Char::from(b'c'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        &i18n!("Open a chat once connected"),
+        Some(&i18n!("JID or phone number")),
+    );
+
+    app.connect_handle_local_options(|_, options| {
+        if options.contains("version") {
+            let written = writeln!(std::io::stdout().lock(), "Papo {VERSION} ({PROFILE})");
+            return ControlFlow::Break(if written.is_ok() {
+                glib::ExitCode::SUCCESS
+            } else {
+                glib::ExitCode::FAILURE
+            });
+        }
+
+        ControlFlow::Continue(())
+    });
+
+    app.connect_command_line(|app, command_line| {
+        handle_command_line(app, &command_line.options_dict());
+        glib::ExitCode::SUCCESS
+    });
+}
+
+/// Take the options the window should apply on launch.
+pub fn take_pending() -> LaunchOptions {
+    std::mem::take(&mut *PENDING.lock().unwrap())
+}
+
+/// Act on the options of a command line, in the running instance.
+fn handle_command_line(app: &gtk::Application, options: &glib::VariantDict) {
+    if options.contains("quit") {
+        app.quit();
+        return;
+    }
+
+    let hidden = options.contains("hidden");
+    let chat = options.lookup::<String>("chat").ok().flatten();
+
+    // Before the window is up, leave the options for it to apply.
+    if app.windows().is_empty() {
+        *PENDING.lock().unwrap() = LaunchOptions { hidden, chat };
+        app.activate();
+        return;
+    }
+
+    if let Some(chat) = chat {
+        app.activate_action("open-chat", Some(&chat.to_variant()));
+    }
+    if !hidden {
+        app.activate();
+    }
+}
+
+/// Open a chat by JID or phone number, bringing the window up first if it
+/// isn't, like when started as a D-Bus service.
+pub fn open_chat(app: &gtk::Application, chat: String) {
+    if app.windows().is_empty() {
+        PENDING.lock().unwrap().chat = Some(chat);
+        app.activate();
+    } else {
+        app.activate_action("open-chat", Some(&chat.to_variant()));
+        app.activate();
+    }
+}
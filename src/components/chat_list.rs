@@ -1,25 +1,45 @@
 use std::path::Path;
 
 use adw::prelude::*;
-use chrono::Local;
-use gtk::{gdk::Texture, gio, glib, pango};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use gtk::{
+    gdk::{self, Texture},
+    gio, glib, pango,
+};
 use relm4::{
     prelude::*,
     typed_view::list::{RelmListItem, TypedListView},
 };
 
 use crate::{
-    i18n,
+    config::APP_ID,
+    i18n, ni18n,
     state::{Chat, ChatMessage, MessageStatus},
-    utils::{format_lid_as_number, get_first_name},
+    utils::{
+        MAX_UNREAD_COUNT, OnboardingStep, format_badge_count, format_jid, get_first_name,
+        icon_or_fallback, text_direction,
+    },
 };
 
 #[derive(Debug)]
 pub struct ChatList {
+    /// Date the time labels were last rendered for.
+    today: NaiveDate,
     /// Currently selected chat JID.
     chat_jid: Option<String>,
+    /// Application settings, holding the onboarding progress.
+    settings: gio::Settings,
+    /// Whether focus mode is on, hiding unread counts and keeping the chats
+    /// in place.
+    focus_mode: bool,
+    /// Onboarding steps done so far.
+    completed_steps: Vec<OnboardingStep>,
+    /// State of the connection, shown above the chats until connected.
+    connection_state: ConnectionState,
     /// `ListView` widget wrapper containing all chat rows.
     list_view_wrapper: TypedListView<ChatRow, gtk::SingleSelection>,
+    /// Whether the onboarding checklist is shown.
+    onboarding_visible: bool,
 }
 
 #[derive(Debug, Default)]
@@ -33,6 +53,33 @@ pub enum ChatListFilter {
     Unreads,
 }
 
+/// State of the connection shown in the chat list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Connected, nothing is shown.
+    Connected,
+    /// Connecting, the chats shown may be outdated. The client always
+    /// starts here.
+    #[default]
+    Connecting,
+    /// Connected and catching up with the chats.
+    Syncing,
+    /// Offline, only the cached chats are shown.
+    Offline,
+}
+
+impl ConnectionState {
+    /// Text shown in the row for the state.
+    fn label(self) -> String {
+        match self {
+            Self::Connected => String::new(),
+            Self::Connecting => i18n!("Connecting…"),
+            Self::Syncing => i18n!("Syncing chats…"),
+            Self::Offline => i18n!("Offline — showing cached chats"),
+        }
+    }
+}
+
 impl From<&str> for ChatListFilter {
     fn from(value: &str) -> Self {
         match value {
@@ -65,6 +112,16 @@ pub enum ChatListInput {
     Select(String),
     /// Select a chat by its position.
     SelectPosition(u32),
+    /// Select the next chat.
+    SelectNext,
+    /// Select the previous chat.
+    SelectPrevious,
+    /// Move a chat to its new place after being (un)pinned or reordered.
+    MoveChat {
+        chat: Chat,
+        /// The pinned chat to place it before.
+        before: Option<String>,
+    },
     /// Remove a chat from the list.
     RemoveChat {
         /// Chat JID.
@@ -72,12 +129,35 @@ pub enum ChatListInput {
     },
     /// Clear the chat selection.
     ClearSelection,
+
+    /// Show the state of the connection.
+    SetConnectionState(ConnectionState),
+    /// Try to connect again, if offline.
+    Reconnect,
+    /// Turn focus mode on or off.
+    SetFocusMode(bool),
+
+    /// Do an onboarding step.
+    StartOnboardingStep(OnboardingStep),
+    /// Hide the onboarding checklist for good.
+    DismissOnboarding,
+    /// The onboarding progress changed in the settings.
+    OnboardingChanged,
+
+    /// Periodic tick to keep the time labels current.
+    Tick,
 }
 
 #[derive(Debug)]
 pub enum ChatListOutput {
     /// A chat has been selected.
     ChatSelected(String),
+    /// Try to connect again.
+    Reconnect,
+    /// Post a notification, asking for the permission to if needed.
+    TestNotification,
+    /// Pick someone to start a chat with.
+    StartChat,
 }
 
 #[relm4::component(async, pub)]
@@ -145,6 +225,128 @@ impl SimpleAsyncComponent for ChatList {
                 }
             },
 
+            // Connection state, as a row that stays put above the chats.
+            gtk::ListBox {
+                #[watch]
+                set_visible: model.connection_state != ConnectionState::Connected,
+                set_selection_mode: gtk::SelectionMode::None,
+                set_css_classes: &["navigation-sidebar"],
+                connect_row_activated[sender] => move |_, _| {
+                    sender.input(ChatListInput::Reconnect);
+                },
+
+                gtk::ListBoxRow {
+                    #[watch]
+                    set_activatable: model.connection_state == ConnectionState::Offline,
+                    #[watch]
+                    set_tooltip_text: (model.connection_state == ConnectionState::Offline)
+                        .then(|| i18n!("Connect Again"))
+                        .as_deref(),
+
+                    gtk::Box {
+                        set_spacing: 12,
+                        set_margin_all: 6,
+
+                        adw::Spinner {
+                            #[watch]
+                            set_visible: matches!(
+                                model.connection_state,
+                                ConnectionState::Connecting | ConnectionState::Syncing
+                            ),
+                        },
+
+                        gtk::Label {
+                            #[watch]
+                            set_label: &model.connection_state.label(),
+                            set_xalign: 0.0,
+                            set_hexpand: true,
+                            set_css_classes: &["dimmed"],
+
+                            set_wrap: true,
+                            set_wrap_mode: pango::WrapMode::WordChar,
+                        },
+                    },
+                },
+            },
+
+            // Checklist for new users, until it's done or dismissed.
+            gtk::Box {
+                #[watch]
+                set_visible: model.onboarding_visible,
+                set_margin_start: 8,
+                set_margin_end: 8,
+                set_margin_top: 4,
+                set_orientation: gtk::Orientation::Vertical,
+                set_css_classes: &["card", "onboarding-card"],
+
+                gtk::Box {
+                    set_spacing: 6,
+                    set_margin_start: 12,
+                    set_margin_end: 6,
+                    set_margin_top: 6,
+
+                    gtk::Label {
+                        set_label: &i18n!("Get Started"),
+                        set_xalign: 0.0,
+                        set_hexpand: true,
+                        set_css_classes: &["heading"],
+                    },
+
+                    gtk::Button {
+                        set_icon_name: icon_or_fallback("window-close-symbolic", "window-close"),
+                        set_tooltip_text: Some(&i18n!("Dismiss")),
+                        set_css_classes: &["flat", "circular"],
+                        connect_clicked => ChatListInput::DismissOnboarding,
+                    },
+                },
+
+                gtk::ListBox {
+                    set_selection_mode: gtk::SelectionMode::None,
+                    set_css_classes: &["navigation-sidebar"],
+                    connect_row_activated[sender] => move |_, row| {
+                        if let Some(step) = usize::try_from(row.index())
+                            .ok()
+                            .and_then(|index| OnboardingStep::ALL.get(index))
+                        {
+                            sender.input(ChatListInput::StartOnboardingStep(*step));
+                        }
+                    },
+
+                    adw::ActionRow {
+                        set_title: &i18n!("Allow Notifications"),
+                        #[watch]
+                        set_activatable: !model.is_completed(OnboardingStep::AllowNotifications),
+                        add_prefix = &gtk::CheckButton {
+                            set_can_target: false,
+                            #[watch]
+                            set_active: model.is_completed(OnboardingStep::AllowNotifications),
+                        },
+                    },
+
+                    adw::ActionRow {
+                        set_title: &i18n!("Enable Background Mode"),
+                        #[watch]
+                        set_activatable: !model.is_completed(OnboardingStep::EnableBackground),
+                        add_prefix = &gtk::CheckButton {
+                            set_can_target: false,
+                            #[watch]
+                            set_active: model.is_completed(OnboardingStep::EnableBackground),
+                        },
+                    },
+
+                    adw::ActionRow {
+                        set_title: &i18n!("Start Your First Chat"),
+                        #[watch]
+                        set_activatable: !model.is_completed(OnboardingStep::StartChat),
+                        add_prefix = &gtk::CheckButton {
+                            set_can_target: false,
+                            #[watch]
+                            set_active: model.is_completed(OnboardingStep::StartChat),
+                        },
+                    },
+                },
+            },
+
             gtk::ScrolledWindow {
                 set_vexpand: true,
                 set_hscrollbar_policy: gtk::PolicyType::Never,
@@ -163,10 +365,24 @@ impl SimpleAsyncComponent for ChatList {
         root: Self::Root,
         sender: AsyncComponentSender<Self>,
     ) -> AsyncComponentParts<Self> {
-        let model = Self {
+        let mut model = Self {
+            today: Local::now().date_naive(),
             chat_jid: None,
+            settings: gio::Settings::new(APP_ID),
+            completed_steps: Vec::new(),
+            onboarding_visible: false,
+            connection_state: ConnectionState::default(),
+            focus_mode: false,
             list_view_wrapper: TypedListView::new(),
         };
+        model.update_onboarding();
+
+        for key in ["onboarding-completed", "onboarding-dismissed"] {
+            let input_sender = sender.input_sender().clone();
+            model.settings.connect_changed(Some(key), move |_, _| {
+                input_sender.emit(ChatListInput::OnboardingChanged);
+            });
+        }
 
         let selection_model = &model.list_view_wrapper.selection_model;
 
@@ -184,6 +400,13 @@ impl SimpleAsyncComponent for ChatList {
             input_sender.emit(ChatListInput::SelectPosition(position));
         });
 
+        // Times are shown as "14:30" today and "23/02" before, re-check every minute.
+        let input_sender = sender.input_sender().clone();
+        glib::timeout_add_seconds_local(60, move || {
+            input_sender.emit(ChatListInput::Tick);
+            glib::ControlFlow::Continue
+        });
+
         AsyncComponentParts { model, widgets }
     }
 
@@ -210,6 +433,7 @@ impl SimpleAsyncComponent for ChatList {
                         .get_unread_count()
                         .await
                         .map_or(0, |c| u32::try_from(c).unwrap());
+                    let join_request_count = chat.get_join_request_count().await.unwrap_or(0);
                     let avatar_texture = if let Some(ref path) = chat.avatar_path {
                         load_avatar(path).await
                     } else {
@@ -221,11 +445,18 @@ impl SimpleAsyncComponent for ChatList {
                         chat,
                         last_message,
                         unread_count,
+                        hide_unread: self.focus_mode,
+                        join_request_count,
                         avatar_texture,
                     };
 
                     if at_top {
-                        self.list_view_wrapper.insert(0, row);
+                        let position = if row.chat.pinned {
+                            0
+                        } else {
+                            self.pinned_count()
+                        };
+                        self.list_view_wrapper.insert(position, row);
                     } else {
                         self.list_view_wrapper.append(row);
                     }
@@ -241,6 +472,7 @@ impl SimpleAsyncComponent for ChatList {
                         .get_unread_count()
                         .await
                         .map_or(0, |c| u32::try_from(c).unwrap());
+                    let join_request_count = chat.get_join_request_count().await.unwrap_or(0);
                     let avatar_texture = if let Some(ref path) = chat.avatar_path {
                         load_avatar(path).await
                     } else {
@@ -252,22 +484,26 @@ impl SimpleAsyncComponent for ChatList {
                         chat: chat.clone(),
                         last_message,
                         unread_count,
+                        hide_unread: self.focus_mode,
+                        join_request_count,
                         avatar_texture,
                     };
 
-                    let adj = self.list_view_wrapper.view.vadjustment();
-                    let saved_scroll = adj.as_ref().map(AdjustmentExt::value);
-
-                    if move_to_top {
-                        // Insert the new updated row.
-                        self.list_view_wrapper.insert(0, updated_row);
-                        let old_index = index + 1;
+                    // Focus mode keeps the chats in place.
+                    if move_to_top && !chat.pinned && !self.focus_mode {
+                        // Insert the new updated row below the pinned chats, which keep
+                        // their manual order.
+                        let top = self.pinned_count();
+                        self.list_view_wrapper.insert(top, updated_row);
+                        let old_index = if index >= top { index + 1 } else { index };
 
                         // Re-select the row and scroll to the top if it's the selected chat.
                         if self.chat_jid.as_deref() == Some(&chat.jid) {
-                            self.list_view_wrapper.selection_model.select_item(0, true);
+                            self.list_view_wrapper
+                                .selection_model
+                                .select_item(top, true);
 
-                            if let Some(adj) = adj {
+                            if let Some(adj) = self.list_view_wrapper.view.vadjustment() {
                                 glib::idle_add_local_once(move || adj.set_value(adj.lower()));
                             }
                         }
@@ -276,20 +512,7 @@ impl SimpleAsyncComponent for ChatList {
                         self.list_view_wrapper.remove(old_index);
                     } else {
                         // Update the row in-place.
-                        self.list_view_wrapper.remove(index);
-                        self.list_view_wrapper.insert(index, updated_row);
-
-                        // Re-select the row.
-                        if self.chat_jid.as_deref() == Some(&chat.jid) {
-                            self.list_view_wrapper
-                                .selection_model
-                                .select_item(index, true);
-                        }
-
-                        // Scroll back to where it was before.
-                        if let (Some(adj), Some(value)) = (adj, saved_scroll) {
-                            glib::idle_add_local_once(move || adj.set_value(value));
-                        }
+                        self.replace_row(index, updated_row);
                     }
                 }
             }
@@ -323,6 +546,14 @@ impl SimpleAsyncComponent for ChatList {
                 // Check if the selected chat isn't already selected.
                 if self.chat_jid.as_deref() != Some(&jid) {
                     self.chat_jid = Some(jid.clone());
+
+                    // Highlight the row when selected from elsewhere, e.g. the chat switcher.
+                    if let Some(position) = self.list_view_wrapper.find(|row| row.chat.jid == jid) {
+                        self.list_view_wrapper
+                            .selection_model
+                            .select_item(position, true);
+                    }
+
                     let _ = sender.output(ChatListOutput::ChatSelected(jid));
                 }
             }
@@ -335,6 +566,48 @@ impl SimpleAsyncComponent for ChatList {
                     sender.input(ChatListInput::Select(jid));
                 }
             }
+            ChatListInput::SelectNext => self.select_adjacent(true),
+            ChatListInput::SelectPrevious => self.select_adjacent(false),
+            ChatListInput::MoveChat { chat, before } => {
+                let Some(index) = self.get_index_by_jid(&chat.jid) else {
+                    return;
+                };
+                let Some(mut row) = self
+                    .list_view_wrapper
+                    .get(index)
+                    .map(|item| item.borrow().clone())
+                else {
+                    return;
+                };
+                row.chat = chat;
+                self.list_view_wrapper.remove(index);
+
+                let position = match before.and_then(|jid| self.get_index_by_jid(&jid)) {
+                    Some(position) => position,
+                    None if row.chat.pinned => self.pinned_count(),
+                    None => {
+                        // Unpinned chats are ordered by their last message.
+                        let time = row.last_message.as_ref().map(|m| m.timestamp);
+                        self.list_view_wrapper
+                            .iter()
+                            .position(|r| {
+                                let r = r.borrow();
+                                !r.chat.pinned
+                                    && r.last_message.as_ref().map(|m| m.timestamp) < time
+                            })
+                            .and_then(|p| u32::try_from(p).ok())
+                            .unwrap_or_else(|| self.list_view_wrapper.len())
+                    }
+                };
+
+                let selected = self.chat_jid.as_deref() == Some(&row.chat.jid);
+                self.list_view_wrapper.insert(position, row);
+                if selected {
+                    self.list_view_wrapper
+                        .selection_model
+                        .select_item(position, true);
+                }
+            }
             ChatListInput::RemoveChat { jid } => {
                 if let Some(index) = self.get_index_by_jid(&jid) {
                     self.list_view_wrapper.remove(index);
@@ -355,11 +628,108 @@ impl SimpleAsyncComponent for ChatList {
                     self.list_view_wrapper.selection_model.unselect_all();
                 }
             }
+
+            ChatListInput::SetConnectionState(state) => {
+                self.connection_state = state;
+            }
+            ChatListInput::Reconnect => {
+                if self.connection_state == ConnectionState::Offline {
+                    self.connection_state = ConnectionState::Connecting;
+                    let _ = sender.output(ChatListOutput::Reconnect);
+                }
+            }
+
+            ChatListInput::StartOnboardingStep(step) => match step {
+                OnboardingStep::AllowNotifications => {
+                    let _ = sender.output(ChatListOutput::TestNotification);
+                }
+                // Asking the portal is left to the application, as on every start.
+                OnboardingStep::EnableBackground => {
+                    if let Err(e) = self.settings.set_boolean("run-in-background", true) {
+                        tracing::error!("Failed to turn running in the background on: {e}");
+                    }
+                }
+                OnboardingStep::StartChat => {
+                    let _ = sender.output(ChatListOutput::StartChat);
+                }
+            },
+            ChatListInput::DismissOnboarding => {
+                if let Err(e) = self.settings.set_boolean("onboarding-dismissed", true) {
+                    tracing::error!("Failed to dismiss the onboarding: {e}");
+                }
+            }
+            ChatListInput::OnboardingChanged => self.update_onboarding(),
+
+            ChatListInput::SetFocusMode(enabled) => {
+                if self.focus_mode == enabled {
+                    return;
+                }
+                self.focus_mode = enabled;
+
+                // Only rows with unread messages show a badge to hide or bring back.
+                let unread = self
+                    .list_view_wrapper
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, row)| {
+                        let mut row = row.borrow().clone();
+                        row.hide_unread = enabled;
+                        (row.unread_count > 0).then_some((index, row))
+                    })
+                    .collect::<Vec<_>>();
+
+                for (index, row) in unread {
+                    if let Ok(index) = u32::try_from(index) {
+                        self.replace_row(index, row);
+                    }
+                }
+            }
+
+            ChatListInput::Tick => {
+                let today = Local::now().date_naive();
+                if today == self.today {
+                    return;
+                }
+                let previous = std::mem::replace(&mut self.today, today);
+
+                // Rows from the day before now show a date instead of a time.
+                let stale = self
+                    .list_view_wrapper
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, row)| {
+                        let row = row.borrow();
+                        let date = row.last_message.as_ref()?.timestamp.with_timezone(&Local);
+                        (date.date_naive() == previous).then(|| (index, row.clone()))
+                    })
+                    .collect::<Vec<_>>();
+
+                for (index, row) in stale {
+                    if let Ok(index) = u32::try_from(index) {
+                        self.replace_row(index, row);
+                    }
+                }
+            }
         }
     }
 }
 
 impl ChatList {
+    /// Read the onboarding progress, hiding the checklist once it's all done.
+    fn update_onboarding(&mut self) {
+        self.completed_steps = OnboardingStep::ALL
+            .into_iter()
+            .filter(|step| step.is_completed(&self.settings))
+            .collect();
+        self.onboarding_visible = !self.settings.boolean("onboarding-dismissed")
+            && self.completed_steps.len() < OnboardingStep::ALL.len();
+    }
+
+    /// Check if an onboarding step has been done.
+    fn is_completed(&self, step: OnboardingStep) -> bool {
+        self.completed_steps.contains(&step)
+    }
+
     /// Find the index by its chat JID.
     fn get_index_by_jid(&self, jid: &str) -> Option<u32> {
         for (i, row) in self.list_view_wrapper.iter().enumerate() {
@@ -370,24 +740,86 @@ impl ChatList {
 
         None
     }
+
+    /// Select the next or previous visible chat, wrapping around.
+    fn select_adjacent(&self, forward: bool) {
+        let selection_model = &self.list_view_wrapper.selection_model;
+        let count = selection_model.n_items();
+        if count == 0 {
+            return;
+        }
+
+        let selected = selection_model.selected();
+        let position = if selected == gtk::INVALID_LIST_POSITION {
+            if forward { 0 } else { count - 1 }
+        } else if forward {
+            (selected + 1) % count
+        } else {
+            (selected + count - 1) % count
+        };
+
+        // Selecting the row opens the chat.
+        selection_model.select_item(position, true);
+    }
+
+    /// Count the pinned chats, which are always at the top.
+    fn pinned_count(&self) -> u32 {
+        let count = self
+            .list_view_wrapper
+            .iter()
+            .take_while(|row| row.borrow().chat.pinned)
+            .count();
+
+        u32::try_from(count).unwrap_or(u32::MAX)
+    }
+
+    /// Replace a row in place, keeping the selection and scroll position.
+    fn replace_row(&mut self, index: u32, row: ChatRow) {
+        let adj = self.list_view_wrapper.view.vadjustment();
+        let saved_scroll = adj.as_ref().map(AdjustmentExt::value);
+        let selected = self.chat_jid.as_deref() == Some(&row.chat.jid);
+
+        self.list_view_wrapper.remove(index);
+        self.list_view_wrapper.insert(index, row);
+
+        // Re-select the row.
+        if selected {
+            self.list_view_wrapper
+                .selection_model
+                .select_item(index, true);
+        }
+
+        // Scroll back to where it was before.
+        if let (Some(adj), Some(value)) = (adj, saved_scroll) {
+            glib::idle_add_local_once(move || adj.set_value(value));
+        }
+    }
 }
 
 /// A single row in the chat history list.
 #[derive(Clone, Debug)]
 pub struct ChatRow {
     chat: Chat,
+    /// Whether to hide the unread count, in focus mode.
+    hide_unread: bool,
     /// The last sent message in the chat.
     last_message: Option<ChatMessage>,
     /// How many messages are unread.
     unread_count: u32,
     avatar_texture: Option<Texture>,
+    /// How many people are asking to join the group, shown to its admins.
+    join_request_count: usize,
 }
 
 pub struct ChatRowWidgets {
     /// Chat avatar.
     avatar: adw::Avatar,
+    /// Chat actions, shown on right click or long press.
+    chat_menu: gio::Menu,
     /// Muted icon.
     muted_icon: gtk::Image,
+    /// Online indicator dot over the avatar.
+    online_dot: gtk::Box,
     /// Pinned icon.
     pinned_icon: gtk::Image,
     /// Message status icon (e.g. "Sending", "Sent").
@@ -400,6 +832,8 @@ pub struct ChatRowWidgets {
     timestamp_label: gtk::Label,
     /// Unread count badge.
     unread_count_badge: gtk::Label,
+    /// Badge shown while people are asking to join the group.
+    join_requests_badge: gtk::Image,
 }
 
 impl RelmListItem for ChatRow {
@@ -407,7 +841,9 @@ impl RelmListItem for ChatRow {
     type Widgets = ChatRowWidgets;
 
     fn setup(_list_item: &gtk::ListItem) -> (Self::Root, Self::Widgets) {
+        // Labeled on bind, for screen readers to announce the row as a whole.
         let root = gtk::Box::builder()
+            .accessible_role(gtk::AccessibleRole::Group)
             .spacing(12)
             .orientation(gtk::Orientation::Horizontal)
             .margin_start(4)
@@ -422,7 +858,16 @@ impl RelmListItem for ChatRow {
         avatar_overlay.set_child(Some(&avatar));
         root.append(&avatar_overlay);
 
-        // TODO: online dot
+        // Online indicator dot.
+        let online_dot = gtk::Box::builder()
+            .accessible_role(gtk::AccessibleRole::Img)
+            .halign(gtk::Align::End)
+            .valign(gtk::Align::End)
+            .css_classes(["online-dot"])
+            .visible(false)
+            .build();
+        online_dot.update_property(&[gtk::accessible::Property::Label(&i18n!("Online"))]);
+        avatar_overlay.add_overlay(&online_dot);
 
         // Middle text box (title and subtitle).
         let text_box = gtk::Box::builder()
@@ -489,7 +934,10 @@ impl RelmListItem for ChatRow {
 
         let muted_icon = gtk::Image::builder()
             .halign(gtk::Align::End)
-            .icon_name("speaker-0-symbolic")
+            .icon_name(icon_or_fallback(
+                "speaker-0-symbolic",
+                "audio-volume-muted-symbolic",
+            ))
             .pixel_size(12)
             .css_classes(["dimmed"])
             .build();
@@ -497,27 +945,84 @@ impl RelmListItem for ChatRow {
 
         let pinned_icon = gtk::Image::builder()
             .halign(gtk::Align::End)
-            .icon_name("pin-symbolic")
+            .icon_name(icon_or_fallback("pin-symbolic", "view-pin-symbolic"))
             .pixel_size(12)
             .css_classes(["dimmed"])
             .build();
         suffix_bottom_box.append(&pinned_icon);
 
+        let join_requests_badge = gtk::Image::builder()
+            .halign(gtk::Align::End)
+            .icon_name(icon_or_fallback(
+                "person-add-symbolic",
+                "contact-new-symbolic",
+            ))
+            .pixel_size(12)
+            .css_classes(["accent"])
+            .build();
+        suffix_bottom_box.append(&join_requests_badge);
+
         let unread_count_badge = gtk::Label::builder()
             .justify(gtk::Justification::Center)
             .css_classes(["badge", "numeric"])
             .build();
         suffix_bottom_box.append(&unread_count_badge);
 
+        // Chats are dragged onto others to reorder (and pin) them.
+        let drag_source = gtk::DragSource::builder()
+            .actions(gdk::DragAction::MOVE)
+            .build();
+        drag_source.connect_prepare(|source, _, _| {
+            let jid = source.widget()?.widget_name();
+            Some(gdk::ContentProvider::for_value(&jid.to_value()))
+        });
+        root.add_controller(drag_source);
+
+        let drop_target = gtk::DropTarget::new(String::static_type(), gdk::DragAction::MOVE);
+        drop_target.connect_drop(|target, value, _, _| {
+            let (Some(widget), Ok(jid)) = (target.widget(), value.get::<String>()) else {
+                return false;
+            };
+            let target_jid = widget.widget_name().to_string();
+            if jid == target_jid {
+                return false;
+            }
+
+            widget
+                .activate_action("app.move-chat", Some(&(jid, target_jid).to_variant()))
+                .is_ok()
+        });
+        root.add_controller(drop_target);
+
+        // Chat actions, on right click or long press.
+        let chat_menu = gio::Menu::new();
+        let popover = gtk::PopoverMenu::from_model(Some(&chat_menu));
+        popover.set_has_arrow(false);
+        popover.set_parent(&root);
+
+        let click_gesture = gtk::GestureClick::builder()
+            .button(gdk::BUTTON_SECONDARY)
+            .build();
+        let show = popover.clone();
+        click_gesture.connect_pressed(move |_, _, _, _| show.popup());
+        root.add_controller(click_gesture);
+
+        let long_press_gesture = gtk::GestureLongPress::new();
+        long_press_gesture.connect_pressed(move |_, _, _| popover.popup());
+        root.add_controller(long_press_gesture);
+
         let widgets = ChatRowWidgets {
             avatar,
+            chat_menu,
             muted_icon,
+            online_dot,
             pinned_icon,
             status_icon,
             title_label,
             subtitle_label,
             timestamp_label,
             unread_count_badge,
+            join_requests_badge,
         };
 
         (root, widgets)
@@ -525,16 +1030,33 @@ impl RelmListItem for ChatRow {
 
     fn bind(&mut self, widgets: &mut Self::Widgets, root: &mut Self::Root) {
         let name = if self.chat.name.trim().is_empty() {
-            format_lid_as_number(&self.chat.jid)
+            format_jid(&self.chat.jid)
         } else {
             self.chat.name.trim().to_string()
         };
         widgets.title_label.set_label(&name);
+        widgets.title_label.set_direction(text_direction(&name));
         root.set_widget_name(&self.chat.jid);
 
         widgets.avatar.set_text(Some(&self.chat.name));
-        widgets.muted_icon.set_visible(self.chat.muted);
+
+        widgets.chat_menu.remove_all();
+        let target = self.chat.jid.to_variant();
+        if self.unread_count > 0 {
+            let item = gio::MenuItem::new(Some(&i18n!("Mark as _Read")), None);
+            item.set_action_and_target_value(Some("app.mark-chat-read"), Some(&target));
+            widgets.chat_menu.append_item(&item);
+        }
+        let item = gio::MenuItem::new(Some(&i18n!("_Archive")), None);
+        item.set_action_and_target_value(Some("app.archive-chat"), Some(&target));
+        widgets.chat_menu.append_item(&item);
+        let item = gio::MenuItem::new(Some(&i18n!("_Delete Chat…")), None);
+        item.set_action_and_target_value(Some("app.delete-chat"), Some(&target));
+        widgets.chat_menu.append_item(&item);
+        widgets.muted_icon.set_visible(self.chat.is_muted());
         widgets.pinned_icon.set_visible(self.chat.pinned);
+        let online = self.chat.is_private() && self.chat.available == Some(true);
+        widgets.online_dot.set_visible(online);
 
         // Load avatar image if available.
         if let texture @ Some(_) = self.avatar_texture.as_ref() {
@@ -543,10 +1065,38 @@ impl RelmListItem for ChatRow {
             widgets.avatar.set_custom_image(None::<&Texture>);
         }
 
-        if self.unread_count > 0 {
+        let join_requests = (self.join_request_count > 0).then(|| {
+            ni18n!(
+                "{0} join request",
+                "{0} join requests",
+                u32::try_from(self.join_request_count).unwrap_or(u32::MAX)
+            )
+            .replacen("{0}", &self.join_request_count.to_string(), 1)
+        });
+        widgets
+            .join_requests_badge
+            .set_tooltip_text(join_requests.as_deref());
+        widgets
+            .join_requests_badge
+            .set_visible(join_requests.is_some());
+
+        let unread = (self.unread_count > 0 && !self.hide_unread).then(|| {
+            ni18n!(
+                "{0} unread message",
+                "{0} unread messages",
+                self.unread_count
+            )
+            .replacen("{0}", &self.unread_count.to_string(), 1)
+        });
+        if let Some(ref unread) = unread {
+            widgets
+                .unread_count_badge
+                .set_label(&format_badge_count(self.unread_count, MAX_UNREAD_COUNT));
+            widgets.unread_count_badge.set_tooltip_text(Some(unread));
+            // The badge caps the count, so announce the full one.
             widgets
                 .unread_count_badge
-                .set_label(self.unread_count.to_string().as_str());
+                .update_property(&[gtk::accessible::Property::Label(unread)]);
             widgets.unread_count_badge.set_visible(true);
         } else {
             widgets.unread_count_badge.set_visible(false);
@@ -555,7 +1105,7 @@ impl RelmListItem for ChatRow {
         root.remove_css_class("dimmed");
         widgets.unread_count_badge.remove_css_class("dimmed");
 
-        if self.chat.muted {
+        if self.chat.is_muted() {
             root.add_css_class("dimmed");
             widgets.unread_count_badge.add_css_class("dimmed");
         }
@@ -566,7 +1116,14 @@ impl RelmListItem for ChatRow {
 
         if let Some(msg) = &self.last_message {
             // Get last message's content.
-            let mut content = msg.content.clone();
+            let mut content = if msg.revoked {
+                i18n!("This message was deleted")
+            } else {
+                msg.content.clone()
+            };
+            // Follow the message, not the sender name prefixed in groups, so
+            // right-to-left text is aligned and truncated from its end.
+            let direction = text_direction(&content);
             let mut first_line = if content.contains('\n') {
                 content
                     .split_once('\n')
@@ -595,7 +1152,9 @@ impl RelmListItem for ChatRow {
             }
 
             widgets.subtitle_label.set_label(&first_line);
+            widgets.subtitle_label.set_direction(direction);
             root.set_tooltip_text(Some(&content));
+            root.update_property(&[gtk::accessible::Property::Description(&content)]);
 
             // Get last message's status.
             if msg.outgoing {
@@ -617,21 +1176,46 @@ impl RelmListItem for ChatRow {
             }
 
             // Get last message's timestamp.
-            let now = Local::now();
-            let timestamp = msg.timestamp.with_timezone(&Local);
-
-            let sent_today = (now - timestamp).num_days() == 0;
-            let time = if sent_today {
-                timestamp.format("%H:%M").to_string()
-            } else {
-                timestamp.format("%d/%m").to_string()
-            };
-            widgets.timestamp_label.set_label(&time);
+            widgets
+                .timestamp_label
+                .set_label(&format_row_time(msg.timestamp));
         } else {
             widgets.subtitle_label.set_label("");
+            widgets
+                .subtitle_label
+                .set_direction(gtk::TextDirection::None);
             widgets.timestamp_label.set_label("");
             root.set_tooltip_text(None);
+            root.reset_property(gtk::AccessibleProperty::Description);
         }
+
+        // Announce the row as "name, online, 2 unread messages, 14:30".
+        let states = [
+            online.then(|| i18n!("Online")),
+            self.chat.pinned.then(|| i18n!("Pinned")),
+            self.chat.is_muted().then(|| i18n!("Muted")),
+            join_requests,
+            unread,
+            self.last_message
+                .as_ref()
+                .map(|msg| format_row_time(msg.timestamp)),
+        ];
+        let label = std::iter::once(name)
+            .chain(states.into_iter().flatten())
+            .collect::<Vec<_>>()
+            .join(", ");
+        root.update_property(&[gtk::accessible::Property::Label(&label)]);
+    }
+}
+
+/// Format the time of a row, as a time for today and as a date before.
+fn format_row_time(timestamp: DateTime<Utc>) -> String {
+    let timestamp = timestamp.with_timezone(&Local);
+
+    if timestamp.date_naive() == Local::now().date_naive() {
+        timestamp.format("%H:%M").to_string()
+    } else {
+        timestamp.format("%d/%m").to_string()
     }
 }
 
@@ -650,3 +1234,14 @@ async fn load_avatar<P: AsRef<Path>>(path: P) -> Option<Texture> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_is_shown_as_connecting_until_told_otherwise() {
+        assert_eq!(ConnectionState::default(), ConnectionState::Connecting);
+        assert!(!ConnectionState::default().label().is_empty());
+    }
+}
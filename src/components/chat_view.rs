@@ -1,18 +1,36 @@
-use std::{cell::Cell, collections::VecDeque, ops::Deref, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet, VecDeque},
+    ops::Deref,
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
 
 use adw::prelude::*;
-use chrono::{DateTime, Local, NaiveDate, Utc};
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, Utc};
 use gtk::pango;
-use gtk::{gdk, glib};
+use gtk::{gdk, gio, glib};
 use relm4::{
+    actions::{RelmAction, RelmActionGroup},
     prelude::*,
     typed_view::list::{RelmListItem, TypedListView},
 };
 
 use crate::{
-    i18n,
-    state::{Chat, ChatMessage, MessageStatus},
-    utils::format_date_label,
+    config::APP_ID,
+    i18n, i18n_f, ni18n,
+    state::{
+        Chat, ChatKind, ChatMessage, ContactCard, LinkPreview, Location, Media, MediaType,
+        MessageKind, MessageStatus, Poll, Quote,
+    },
+    store::{ChatSettings, ComposerHints, Snippet},
+    thumbnails,
+    utils::{
+        ComposerPurpose, SendShortcut, TimestampMode, Wallpaper, expand_snippet,
+        format_badge_count, format_date, format_date_label, format_export_timestamp, format_jid,
+        format_lid_as_number, icon_or_fallback, is_jumbo_emoji, text_direction,
+    },
 };
 
 /// Number of messages to load when scrolling.
@@ -21,6 +39,33 @@ const LOAD_MORE_COUNT: u32 = 70;
 const MAX_LOADED_ROWS: u32 = 600;
 /// Number of messages to load on initial chat open.
 const INITIAL_LOAD_COUNT: u32 = 120;
+/// Number of undecryptable messages from which to suggest a resync.
+const UNDECRYPTABLE_BANNER_THRESHOLD: u32 = 3;
+/// Highest count of new messages shown in full on the scroll button badge.
+const MAX_BADGE_COUNT: u32 = 99;
+/// How long the date pill stays after scrolling stops.
+const DATE_PILL_TIMEOUT: Duration = Duration::from_secs(1);
+/// Horizontal speed a swipe on a message needs to reply to it, in pixels per second.
+const SWIPE_REPLY_VELOCITY: f64 = 400.0;
+/// How long a message jumped to from a quote stays highlighted.
+const HIGHLIGHT_DURATION: Duration = Duration::from_millis(1500);
+/// Number of messages nearest to the view whose thumbnails are decoded
+/// before they're scrolled to, about two screens.
+const PREFETCH_COUNT: usize = 40;
+
+relm4::new_action_group!(ChatViewActionGroup, "chat");
+relm4::new_stateless_action!(GoToDateAction, ChatViewActionGroup, "go-to-date");
+relm4::new_stateless_action!(AddContactAction, ChatViewActionGroup, "add-contact");
+relm4::new_stateless_action!(GalleryAction, ChatViewActionGroup, "gallery");
+relm4::new_stateless_action!(ClearHistoryAction, ChatViewActionGroup, "clear-history");
+relm4::new_stateless_action!(RestoreHistoryAction, ChatViewActionGroup, "restore-history");
+relm4::new_stateful_action!(
+    WallpaperAction,
+    ChatViewActionGroup,
+    "wallpaper",
+    String,
+    String
+);
 
 #[derive(Debug)]
 pub struct ChatView {
@@ -28,13 +73,35 @@ pub struct ChatView {
     chat: Option<Chat>,
     /// Current chat view state.
     state: ChatViewState,
+    /// Calendar to pick a date to jump to.
+    calendar: gtk::Calendar,
+    /// App settings, kept to follow wallpaper and send shortcut changes.
+    settings: gio::Settings,
+    /// Snippets expanded in the composer, if enabled.
+    snippets: Rc<RefCell<Vec<Snippet>>>,
+    /// Popover holding the `calendar`.
+    date_picker: gtk::Popover,
+    /// Button opening the emoji picker of the `message_entry`.
+    emoji_button: gtk::MenuButton,
     /// Metadata tracking for each row, mirrors `list_view_wrapper` order.
     /// Used to update pagination cursors when trimming rows.
     row_metadata: VecDeque<RowMetadata>,
     /// Text input for sending messages.
     message_entry: gtk::Entry,
+    /// Shortcut sending the message, as set in the preferences.
+    send_shortcut: Rc<Cell<SendShortcut>>,
+    /// Action to pick the wallpaper of the open chat.
+    wallpaper_action: gio::SimpleAction,
+    /// Button jumping to the date picked in the `calendar`.
+    go_to_date_button: gtk::Button,
     /// `ListView` widget wrapper containing all chat rows.
     list_view_wrapper: TypedListView<ChatRow, gtk::NoSelection>,
+    /// Action to save the chat to the contacts, enabled for unknown contacts.
+    add_contact_action: gio::SimpleAction,
+    /// Style of the wallpaper image picked in the preferences.
+    wallpaper_provider: gtk::CssProvider,
+    /// Action to sync the cleared history again, enabled for cleared chats.
+    restore_history_action: gio::SimpleAction,
 }
 
 #[derive(Debug)]
@@ -42,20 +109,58 @@ pub struct ChatView {
 pub struct ChatViewState {
     /// User presence.
     presence: Option<String>,
+    /// Wallpaper shown behind the chat history.
+    wallpaper: Wallpaper,
     /// Whether a load operation is currently in progress.
     is_loading: bool,
     /// Whether messages at the top have been trimmed due to exceeding `MAX_LOADED_ROWS`.
     top_trimmed: bool,
     /// Whether the scroll is at the bottom.
     is_at_bottom: bool,
+    /// Whether messages are being selected for bulk actions.
+    selecting: bool,
+    /// IDs of the messages selected for bulk actions.
+    selected: HashSet<String>,
+    /// Message the composer is replying to.
+    replying_to: Option<ChatMessage>,
+    /// Message to jump to once its chat is open (chat JID, message ID).
+    pending_jump: Option<(String, String)>,
+    /// Dates of the oldest and newest messages, while picking a date.
+    date_range: Option<(NaiveDate, NaiveDate)>,
+    /// Month shown in the date picker with its days with messages marked.
+    marked_month: Option<(i32, i32)>,
+    /// Phone number of the chat, if it isn't in the contacts.
+    phone_number: Option<String>,
     /// Whether messages at the bottom have been trimmed due to exceeding `MAX_LOADED_ROWS`.
     bottom_trimmed: bool,
+    /// Wallpaper picked for the open chat, instead of the preferences one.
+    chat_wallpaper: Option<Wallpaper>,
+    /// When the history of the open chat was cleared.
+    cleared_before: Option<DateTime<Utc>>,
+    /// Input hints the composer was last used with.
+    composer_hints: ComposerHints,
+    /// Whether the emoji picker was used for the message being composed.
+    emoji_picked: bool,
     /// Whether there might be more messages to load.
     has_more_messages: bool,
     /// Date of the first displayed message (top).
     first_message_date: Option<NaiveDate>,
+    /// Date the separators were last labeled for.
+    today: NaiveDate,
     /// Date of the last appended message (bottom).
     last_message_date: Option<NaiveDate>,
+    /// Number of messages received while scrolled up.
+    new_message_count: u32,
+    /// Pinned message shown in the pinned bar.
+    pinned_index: usize,
+    /// Number of messages in the chat that couldn't be decrypted.
+    undecryptable_count: u32,
+    /// Number of people asking to join the group, kept for its admins only.
+    join_request_count: usize,
+    /// Pinned messages of the chat, latest pin first.
+    pinned_messages: Vec<ChatMessage>,
+    /// When the first of the shown pins ends, to drop it then.
+    pins_expire_at: Option<DateTime<Utc>>,
     /// Timestamp of the newest loaded message.
     newest_loaded_timestamp: Option<i64>,
     /// Timestamp of the oldest loaded message.
@@ -66,6 +171,8 @@ pub struct ChatViewState {
 pub enum ChatViewInput {
     /// Open a chat.
     Open(Chat),
+    /// The open chat changed, e.g. the user left it.
+    ChatChanged(Chat),
     /// Close the open chat.
     Close,
 
@@ -80,14 +187,99 @@ pub enum ChatViewInput {
         available: bool,
         last_seen: Option<DateTime<Utc>>,
     },
-    /// Message status updated.
-    MessageStatusUpdate {
+    /// A message being sent was sent or failed, with its server ID and
+    /// link preview filled in.
+    MessageStatusUpdate(Box<ChatMessage>),
+    /// Message deleted for everyone.
+    MessageRevoked { msg_id: String },
+    /// Message edited by its sender.
+    MessageEdited { msg_id: String, content: String },
+    /// The full media of a message has been downloaded.
+    MediaDownloaded { msg_id: String, data: Arc<Vec<u8>> },
+    /// Someone voted in a poll.
+    PollVoted {
+        msg_id: String,
+        voter_jid: String,
+        options: Vec<String>,
+    },
+    /// Someone reacted to a message, or removed their reaction.
+    ReactionChanged {
         msg_id: String,
-        status: MessageStatus,
+        sender_jid: String,
+        emoji: Option<String>,
     },
+    /// The connected user's JID is known, to tell their reactions apart.
+    UserJidChanged(Option<String>),
 
     /// Scroll to the bottom of the chat.
     ScrollToBottom,
+    /// Show the calendar to pick a date to jump to.
+    ShowDatePicker,
+    /// The month or day shown in the date picker changed.
+    DatePickerChanged,
+    /// Jump to the first message of a date, or the nearest earlier one.
+    GoToDate(NaiveDate),
+    /// Jump to a message, once its chat is open.
+    GoToMessage { chat_jid: String, msg_id: String },
+    /// Jump to the message quoted by a reply, highlighting it.
+    GoToQuoted(String),
+
+    /// Periodic tick to keep the date separators and pins current.
+    Tick,
+    /// The number of undecryptable messages in a chat changed.
+    UndecryptableCountChanged { chat_jid: String },
+    /// The number of people asking to join a group changed.
+    JoinRequestsChanged { chat_jid: String },
+    /// Show the people asking to join the open group.
+    ShowJoinRequests,
+    /// Ask for a name to save the chat to the contacts with.
+    ShowAddContact,
+    /// Show the media, links and documents sent in the open chat.
+    ShowGallery,
+    /// Call the other side of the open chat, with video if `true`.
+    StartCall(bool),
+    /// Ask to clear the history of the open chat.
+    ClearHistory,
+    /// Sync the cleared history of the open chat again.
+    RestoreHistory,
+    /// A contact has been saved with a name.
+    ContactSaved { jid: String, name: String },
+    /// The pinned messages of a chat changed.
+    PinnedMessagesChanged { chat_jid: String },
+    /// Jump to the pinned message shown in the pinned bar, then show the next one.
+    ShowPinnedMessage,
+    /// Unpin the message shown in the pinned bar.
+    UnpinShownMessage,
+    /// Use a wallpaper for the open chat, or the preferences one if `None`.
+    SetChatWallpaper(Option<Wallpaper>),
+    /// The wallpaper set in the preferences changed.
+    WallpaperChanged,
+    /// The send shortcut set in the preferences changed.
+    SendShortcutChanged,
+
+    /// Reply to a loaded message.
+    Reply(String),
+    /// Stop replying to a message.
+    CancelReply,
+    /// `Esc` has been pressed in the composer.
+    ComposerEscaped,
+    /// An emoji was picked to insert in the composer.
+    EmojiPicked(String),
+
+    /// Select a message, or unselect it, starting to select if needed.
+    ToggleSelected(String),
+    /// Stop selecting messages.
+    CancelSelection,
+    /// Copy the text of the selected messages.
+    CopySelected,
+    /// Forward the text of the selected messages to another chat.
+    ForwardSelected,
+    /// Bookmark the selected messages on this device.
+    BookmarkSelected,
+    /// Delete the selected messages from this device.
+    DeleteSelected,
+    /// Messages have been deleted from this device.
+    MessagesDeleted(Vec<String>),
 }
 
 #[derive(Debug)]
@@ -105,7 +297,41 @@ pub enum ChatViewOutput {
         text: String,
         /// Message recipient.
         recipient: String,
+        /// Message replied to.
+        quote: Option<Quote>,
+    },
+    /// Save a contact with a name.
+    SaveContact {
+        jid: String,
+        name: String,
+        phone_number: String,
+    },
+    /// Unpin a message for everyone in the chat.
+    UnpinMessage { chat_jid: String, msg_id: String },
+    /// Show the media, links and documents sent in a chat.
+    ShowGallery { jid: String, name: String },
+    /// Call the other side of a private chat.
+    StartCall { jid: String, is_video: bool },
+    /// Show the people asking to join a group.
+    ShowJoinRequests { jid: String, name: String },
+    /// Ask to clear the history of a chat.
+    ClearHistory { jid: String, name: String },
+    /// Sync the cleared history of a chat again.
+    RestoreHistory(String),
+    /// Send the text of messages to a chat picked for it.
+    ForwardMessages(Vec<String>),
+    /// Bookmark messages on this device.
+    BookmarkMessages {
+        chat_jid: String,
+        msg_ids: Vec<String>,
     },
+    /// Ask to delete messages from this device.
+    DeleteMessages {
+        chat_jid: String,
+        msg_ids: Vec<String>,
+    },
+    /// Show a notification.
+    ShowToast(String),
 }
 
 #[derive(Debug)]
@@ -126,51 +352,242 @@ impl AsyncComponent for ChatView {
     type Output = ChatViewOutput;
     type CommandOutput = ChatViewCommand;
 
+    menu! {
+        chat_menu: {
+            section! {
+                &i18n!("_Go to Date…") => GoToDateAction,
+                &i18n!("_Media, Links and Docs") => GalleryAction,
+                &i18n!("_Add to Contacts…") => AddContactAction,
+            },
+            section! {
+                &i18n!("_Clear History…") => ClearHistoryAction,
+                &i18n!("_Re-download Cleared History") => RestoreHistoryAction,
+            },
+            section! {
+                &i18n!("_Wallpaper") {
+                    &i18n!("_Same as Preferences") => WallpaperAction(String::new()),
+                    &i18n!("_None") => WallpaperAction(Wallpaper::Default.as_ref().to_string()),
+                    &i18n!("_Pattern") => WallpaperAction(Wallpaper::Pattern.as_ref().to_string()),
+                    &i18n!("_Image") => WallpaperAction(Wallpaper::Image.as_ref().to_string()),
+                }
+            }
+        }
+    }
+
     view! {
         adw::ToolbarView {
-            set_css_classes: &["chat-view"],
+            #[watch]
+            set_css_classes: &["chat-view", model.state.wallpaper.css_class()],
 
             add_top_bar = &adw::HeaderBar {
                 set_css_classes: &["flat"],
 
+                pack_start = &gtk::Button {
+                    set_label: &i18n!("_Cancel"),
+                    set_use_underline: true,
+                    #[watch]
+                    set_visible: model.state.selecting,
+
+                    connect_clicked => ChatViewInput::CancelSelection,
+                },
+
+                #[name = "menu_button"]
+                pack_end = &gtk::MenuButton {
+                    set_icon_name: icon_or_fallback("view-more-symbolic", "open-menu-symbolic"),
+                    set_tooltip_text: Some(&i18n!("Chat Menu")),
+                    set_menu_model: Some(&chat_menu),
+                    #[watch]
+                    set_visible: model.chat.is_some() && !model.state.selecting,
+                },
+
+                // Calls, only with a single user.
+                pack_end = &gtk::Box {
+                    set_spacing: 6,
+                    #[watch]
+                    set_visible: model.chat.as_ref().is_some_and(Chat::is_private) && !model.state.selecting,
+
+                    gtk::Button {
+                        set_icon_name: icon_or_fallback("camera-video-symbolic", "camera-web-symbolic"),
+                        set_tooltip_text: Some(&i18n!("Video Call")),
+
+                        connect_clicked => ChatViewInput::StartCall(true),
+                    },
+
+                    gtk::Button {
+                        set_icon_name: icon_or_fallback("call-start-symbolic", "call-start"),
+                        set_tooltip_text: Some(&i18n!("Voice Call")),
+
+                        connect_clicked => ChatViewInput::StartCall(false),
+                    },
+                },
+
+                // Bulk actions on the selected messages.
+                pack_end = &gtk::Box {
+                    set_spacing: 6,
+                    #[watch]
+                    set_visible: model.state.selecting,
+                    #[watch]
+                    set_sensitive: !model.state.selected.is_empty(),
+
+                    gtk::Button {
+                        set_icon_name: icon_or_fallback("user-trash-symbolic", "edit-delete-symbolic"),
+                        set_tooltip_text: Some(&i18n!("Delete for Me")),
+
+                        connect_clicked => ChatViewInput::DeleteSelected,
+                    },
+
+                    gtk::Button {
+                        set_icon_name: icon_or_fallback("starred-symbolic", "emblem-favorite-symbolic"),
+                        set_tooltip_text: Some(&i18n!("Bookmark Locally")),
+
+                        connect_clicked => ChatViewInput::BookmarkSelected,
+                    },
+
+                    gtk::Button {
+                        set_icon_name: icon_or_fallback("mail-forward-symbolic", "go-next-symbolic"),
+                        set_tooltip_text: Some(&i18n!("Forward")),
+
+                        connect_clicked => ChatViewInput::ForwardSelected,
+                    },
+
+                    gtk::Button {
+                        set_icon_name: icon_or_fallback("edit-copy-symbolic", "edit-copy"),
+                        set_tooltip_text: Some(&i18n!("Copy")),
+
+                        connect_clicked => ChatViewInput::CopySelected,
+                    },
+                },
+
                 #[wrap(Some)]
-                set_title_widget = &gtk::Button {
-                    set_halign: gtk::Align::Center,
-                    set_valign: gtk::Align::Center,
+                set_title_widget = &gtk::Stack {
+                    set_hhomogeneous: false,
                     #[watch]
-                    set_css_classes: &["chat-title", "flat", if model.state.presence.is_some() { "with-subtitle" } else { "" }],
+                    set_visible_child_name: if model.state.selecting { "selection" } else { "chat" },
 
-                    gtk::Box {
+                    add_named[Some("selection")] = &gtk::Label {
+                        set_css_classes: &["title"],
+                        #[watch]
+                        set_label: &model.selection_title(),
+                    },
+
+                    add_named[Some("chat")] = &gtk::Button {
                         set_halign: gtk::Align::Center,
                         set_valign: gtk::Align::Center,
+                        #[watch]
+                        set_css_classes: &["chat-title", "flat", if model.state.presence.is_some() || model.shows_phone_number() { "with-subtitle" } else { "" }],
+
+                        gtk::Box {
+                            set_halign: gtk::Align::Center,
+                            set_valign: gtk::Align::Center,
+                            set_orientation: gtk::Orientation::Vertical,
+
+                            gtk::Label {
+                                #[watch]
+                                set_label?: model.chat.as_ref().map(Chat::get_name_or_number).as_ref(),
+                                #[watch]
+                                set_visible: model.chat.is_some(),
+                                set_selectable: false,
+                                set_css_classes: &["title"],
+                            },
+
+                            gtk::Label {
+                                #[watch]
+                                set_label?: model.state.phone_number.as_ref(),
+                                #[watch]
+                                set_visible: model.shows_phone_number(),
+                                set_selectable: false,
+                                set_css_classes: &["subtitle", "numeric"],
+                            },
+
+                            gtk::Label {
+                                #[watch]
+                                set_label?: model.state.presence.as_ref(),
+                                #[watch]
+                                set_visible: model.state.presence.is_some(),
+                                set_selectable: false,
+                                set_css_classes: &["subtitle"],
+                            },
+                        },
+                    },
+                },
+            },
+
+            add_top_bar = &gtk::Box {
+                set_spacing: 6,
+                set_css_classes: &["pinned-bar", "toolbar"],
+                #[watch]
+                set_visible: model.chat.is_some() && !model.state.pinned_messages.is_empty(),
+
+                gtk::Button {
+                    set_hexpand: true,
+                    set_css_classes: &["flat"],
+                    set_tooltip_text: Some(&i18n!("Show Pinned Message")),
+
+                    gtk::Box {
                         set_orientation: gtk::Orientation::Vertical,
 
                         gtk::Label {
+                            set_xalign: 0.0,
+                            set_css_classes: &["caption-heading", "accent"],
                             #[watch]
-                            set_label?: model.chat.as_ref().map(Chat::get_name_or_number).as_ref(),
-                            #[watch]
-                            set_visible: model.chat.is_some(),
-                            set_selectable: false,
-                            set_css_classes: &["title"],
+                            set_label: &model.pinned_title(),
                         },
 
                         gtk::Label {
+                            set_xalign: 0.0,
+                            set_single_line_mode: true,
+                            set_ellipsize: pango::EllipsizeMode::End,
+                            set_css_classes: &["caption"],
                             #[watch]
-                            set_label?: model.state.presence.as_ref(),
-                            #[watch]
-                            set_visible: model.state.presence.is_some(),
-                            set_selectable: false,
-                            set_css_classes: &["subtitle"],
+                            set_label: &model
+                                .shown_pinned_message()
+                                .map(message_preview)
+                                .unwrap_or_default(),
                         },
                     },
+
+                    connect_clicked => ChatViewInput::ShowPinnedMessage,
+                },
+
+                gtk::Button {
+                    set_valign: gtk::Align::Center,
+                    set_icon_name: icon_or_fallback("pin-symbolic", "view-pin-symbolic"),
+                    set_css_classes: &["flat", "circular"],
+                    set_tooltip_text: Some(&i18n!("Unpin Message")),
+
+                    connect_clicked => ChatViewInput::UnpinShownMessage,
                 },
             },
 
+            add_top_bar = &adw::Banner {
+                set_title: &i18n!("Some messages couldn't be decrypted. Open WhatsApp on your phone to resync them."),
+                #[watch]
+                set_revealed: model.chat.is_some()
+                    && model.state.undecryptable_count >= UNDECRYPTABLE_BANNER_THRESHOLD,
+            },
+
+            add_top_bar = &adw::Banner {
+                #[watch]
+                set_title: &ni18n!(
+                    "{0} person asked to join",
+                    "{0} people asked to join",
+                    u32::try_from(model.state.join_request_count).unwrap_or(u32::MAX)
+                ).replacen("{0}", &model.state.join_request_count.to_string(), 1),
+                set_button_label: Some(&i18n!("_Review")),
+                #[watch]
+                set_revealed: model.chat.as_ref().is_some_and(Chat::is_group)
+                    && model.state.join_request_count > 0,
+
+                connect_button_clicked => ChatViewInput::ShowJoinRequests,
+            },
+
             #[wrap(Some)]
             set_content = &gtk::Overlay {
                 #[wrap(Some)]
                 #[local_ref]
                 set_child = &scroll_window -> gtk::ScrolledWindow {
+                    // The wallpaper is drawn once behind the rows, not per row.
+                    add_css_class: "chat-background",
                     set_hscrollbar_policy: gtk::PolicyType::Never,
                     set_overlay_scrolling: true,
 
@@ -212,6 +629,23 @@ impl AsyncComponent for ChatView {
                     }
                 },
 
+                #[name = "date_pill"]
+                add_overlay = &gtk::Revealer {
+                    set_halign: gtk::Align::Center,
+                    set_valign: gtk::Align::Start,
+                    set_can_target: false,
+                    #[watch]
+                    set_visible: !model.state.is_loading,
+                    set_transition_type: gtk::RevealerTransitionType::Crossfade,
+                    set_transition_duration: 250,
+
+                    #[name = "date_pill_label"]
+                    gtk::Label {
+                        set_margin_top: 12,
+                        set_css_classes: &["service-message", "caption", "card"],
+                    },
+                },
+
                 add_overlay = &gtk::Revealer {
                     set_halign: gtk::Align::Center,
                     set_valign: gtk::Align::End,
@@ -220,14 +654,83 @@ impl AsyncComponent for ChatView {
                     set_transition_type: gtk::RevealerTransitionType::Crossfade,
                     set_transition_duration: 350,
 
-                    gtk::Button {
-                        set_icon_name: "down-small-symbolic",
-                        set_css_classes: &["circular", "osd"],
+                    gtk::Overlay {
                         set_margin_bottom: 12,
 
-                        connect_clicked => ChatViewInput::ScrollToBottom
+                        #[wrap(Some)]
+                        set_child = &gtk::Button {
+                            set_icon_name: icon_or_fallback("down-small-symbolic", "pan-down-symbolic"),
+                            set_css_classes: &["circular", "osd"],
+                            #[watch]
+                            update_property: &[gtk::accessible::Property::Label(&model.scroll_button_label())],
+
+                            connect_clicked => ChatViewInput::ScrollToBottom
+                        },
+
+                        add_overlay = &gtk::Label {
+                            set_halign: gtk::Align::End,
+                            set_valign: gtk::Align::Start,
+                            set_can_target: false,
+                            set_css_classes: &["badge", "numeric"],
+                            #[watch]
+                            set_visible: model.state.new_message_count > 0,
+                            #[watch]
+                            set_label: &format_badge_count(model.state.new_message_count, MAX_BADGE_COUNT),
+                        },
+                    },
+                },
+            },
+
+            add_bottom_bar = &gtk::Box {
+                set_spacing: 6,
+                set_css_classes: &["reply-bar", "toolbar"],
+                #[watch]
+                set_visible: model.state.replying_to.is_some(),
+
+                gtk::Image {
+                    set_icon_name: Some(icon_or_fallback("reply-symbolic", "mail-reply-sender-symbolic")),
+                    set_margin_start: 6,
+                },
+
+                gtk::Box {
+                    set_hexpand: true,
+                    set_orientation: gtk::Orientation::Vertical,
+
+                    gtk::Label {
+                        set_xalign: 0.0,
+                        set_css_classes: &["caption-heading", "accent"],
+                        #[watch]
+                        set_label: &model
+                            .state
+                            .replying_to
+                            .as_ref()
+                            .map(|message| i18n_f!("Replying to {}", sender_name(message)))
+                            .unwrap_or_default(),
+                    },
+
+                    gtk::Label {
+                        set_xalign: 0.0,
+                        set_single_line_mode: true,
+                        set_ellipsize: pango::EllipsizeMode::End,
+                        set_css_classes: &["caption"],
+                        #[watch]
+                        set_label: &model
+                            .state
+                            .replying_to
+                            .as_ref()
+                            .map(message_preview)
+                            .unwrap_or_default(),
                     },
                 },
+
+                gtk::Button {
+                    set_valign: gtk::Align::Center,
+                    set_icon_name: icon_or_fallback("window-close-symbolic", "window-close"),
+                    set_css_classes: &["flat", "circular"],
+                    set_tooltip_text: Some(&i18n!("Cancel Reply")),
+
+                    connect_clicked => ChatViewInput::CancelReply,
+                },
             },
 
             add_bottom_bar = &gtk::Box {
@@ -235,20 +738,56 @@ impl AsyncComponent for ChatView {
                 set_margin_all: 6,
                 set_orientation: gtk::Orientation::Horizontal,
 
+                #[local_ref]
+                emoji_button -> gtk::MenuButton {
+                    #[watch]
+                    set_visible: !model.chat.as_ref().is_some_and(Chat::is_read_only),
+                    set_valign: gtk::Align::Center,
+                    set_direction: gtk::ArrowType::Up,
+                    set_icon_name: icon_or_fallback("face-smile-symbolic", "face-smile"),
+                    set_css_classes: &["flat", "circular"],
+                    set_tooltip_text: Some(&i18n!("Insert Emoji")),
+                },
+
                 #[local_ref]
                 message_entry -> gtk::Entry {
                     set_hexpand: true,
+                    #[watch]
+                    set_visible: !model.chat.as_ref().is_some_and(Chat::is_read_only),
                     set_placeholder_text: Some(&i18n!("Type a message...")),
-
-                    connect_activate => ChatViewInput::SendMessage,
                 },
 
                 gtk::Button {
-                    set_icon_name: "paper-plane-symbolic",
+                    #[watch]
+                    set_visible: !model.chat.as_ref().is_some_and(Chat::is_read_only),
+                    set_icon_name: icon_or_fallback("paper-plane-symbolic", "document-send-symbolic"),
                     set_css_classes: &["circular", "suggested-action"],
+                    #[watch]
+                    set_tooltip_text: Some(&model.send_shortcut.get().tooltip()),
 
                     connect_clicked => ChatViewInput::SendMessage,
                 },
+
+                // The client can't post to channels, so they can only be read.
+                gtk::Label {
+                    set_hexpand: true,
+                    set_margin_all: 6,
+                    #[watch]
+                    set_visible: model.chat.as_ref().is_some_and(Chat::is_newsletter),
+                    set_label: &i18n!("Posting to channels is not supported yet"),
+                    set_css_classes: &["dimmed"],
+                },
+
+                gtk::Label {
+                    set_hexpand: true,
+                    set_margin_all: 6,
+                    set_wrap: true,
+                    set_justify: gtk::Justification::Center,
+                    #[watch]
+                    set_visible: model.chat.as_ref().is_some_and(Chat::has_left),
+                    set_label: &i18n!("You can't send messages to this group because you're no longer a participant"),
+                    set_css_classes: &["dimmed"],
+                },
             },
         }
     }
@@ -260,30 +799,128 @@ impl AsyncComponent for ChatView {
     ) -> AsyncComponentParts<Self> {
         let list_view_wrapper = TypedListView::new();
 
-        let model = Self {
+        let input_sender = sender.input_sender().clone();
+        let add_contact_action = RelmAction::<AddContactAction>::new_stateless(move |_| {
+            input_sender.emit(ChatViewInput::ShowAddContact);
+        });
+
+        let input_sender = sender.input_sender().clone();
+        let gallery_action = RelmAction::<GalleryAction>::new_stateless(move |_| {
+            input_sender.emit(ChatViewInput::ShowGallery);
+        });
+
+        let input_sender = sender.input_sender().clone();
+        let clear_history_action = RelmAction::<ClearHistoryAction>::new_stateless(move |_| {
+            input_sender.emit(ChatViewInput::ClearHistory);
+        });
+
+        let input_sender = sender.input_sender().clone();
+        let restore_history_action = RelmAction::<RestoreHistoryAction>::new_stateless(move |_| {
+            input_sender.emit(ChatViewInput::RestoreHistory);
+        });
+
+        let input_sender = sender.input_sender().clone();
+        let wallpaper_action = RelmAction::<WallpaperAction>::new_stateful_with_target_value(
+            &String::new(),
+            move |_, state, value: String| {
+                input_sender.emit(ChatViewInput::SetChatWallpaper(value.parse().ok()));
+                *state = value;
+            },
+        );
+
+        // Follow the wallpaper and send shortcut set in the preferences.
+        let settings = gio::Settings::new(APP_ID);
+        let input_sender = sender.input_sender().clone();
+        settings.connect_changed(None, move |_, key| {
+            if key.starts_with("chat-wallpaper") {
+                input_sender.emit(ChatViewInput::WallpaperChanged);
+            } else if key == "send-shortcut" {
+                input_sender.emit(ChatViewInput::SendShortcutChanged);
+            }
+        });
+        let send_shortcut = settings
+            .string("send-shortcut")
+            .parse::<SendShortcut>()
+            .unwrap_or_default();
+
+        let wallpaper_provider = gtk::CssProvider::new();
+        if let Some(display) = gdk::Display::default() {
+            gtk::style_context_add_provider_for_display(
+                &display,
+                &wallpaper_provider,
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+            );
+        }
+
+        let mut model = Self {
             chat: None,
             state: ChatViewState {
                 presence: None,
+                wallpaper: Wallpaper::Default,
                 is_loading: true,
                 top_trimmed: true,
                 is_at_bottom: true,
+                selecting: false,
+                selected: HashSet::new(),
+                replying_to: None,
+                pending_jump: None,
+                date_range: None,
+                marked_month: None,
+                phone_number: None,
                 bottom_trimmed: false,
+                chat_wallpaper: None,
+                cleared_before: None,
+                composer_hints: ComposerHints::default(),
+                emoji_picked: false,
                 has_more_messages: false,
                 first_message_date: None,
+                today: Local::now().date_naive(),
                 last_message_date: None,
+                new_message_count: 0,
+                pinned_index: 0,
+                undecryptable_count: 0,
+                join_request_count: 0,
+                pinned_messages: Vec::new(),
+                pins_expire_at: None,
                 newest_loaded_timestamp: None,
                 oldest_loaded_timestamp: None,
             },
+            calendar: gtk::Calendar::new(),
+            settings,
+            snippets: Rc::new(RefCell::new(Vec::new())),
+            send_shortcut: Rc::new(Cell::new(send_shortcut)),
+            date_picker: gtk::Popover::new(),
+            go_to_date_button: gtk::Button::builder()
+                .label(i18n!("Go to Date"))
+                .css_classes(["suggested-action"])
+                .build(),
             row_metadata: VecDeque::new(),
             message_entry: gtk::Entry::new(),
+            emoji_button: gtk::MenuButton::new(),
+            wallpaper_action: wallpaper_action.gio_action().clone(),
             list_view_wrapper,
+            add_contact_action: add_contact_action.gio_action().clone(),
+            restore_history_action: restore_history_action.gio_action().clone(),
+            wallpaper_provider,
         };
 
+        model.load_wallpaper_image();
+        model.update_wallpaper();
+
         let list_view = &model.list_view_wrapper.view;
         let scroll_window = gtk::ScrolledWindow::new();
         let message_entry = &model.message_entry;
+        let emoji_button = &model.emoji_button;
         let widgets = view_output!();
 
+        // Insert picked emoji in the composer.
+        let emoji_chooser = gtk::EmojiChooser::new();
+        let input_sender = sender.input_sender().clone();
+        emoji_chooser.connect_emoji_picked(move |_, emoji| {
+            input_sender.emit(ChatViewInput::EmojiPicked(emoji.to_string()));
+        });
+        emoji_button.set_popover(Some(&emoji_chooser));
+
         // Focus the scroll window when clicked within.
         let scroll = scroll_window.clone();
         let click_gesture = gtk::GestureClick::new();
@@ -304,18 +941,72 @@ impl AsyncComponent for ChatView {
         });
         scroll_window.add_controller(key_event_controller);
 
-        // Close the chat when `Esc` is pressed and message entry is focused.
+        // Stop replying, or close the chat, when `Esc` is pressed and message
+        // entry is focused, and expand the snippet before the cursor on `Tab`.
+        let entry = message_entry.clone();
+        let snippets = Rc::clone(&model.snippets);
         let input_sender = sender.input_sender().clone();
         let key_event_controller = gtk::EventControllerKey::new();
         key_event_controller.connect_key_pressed(move |_, key, _, _| match key {
             gdk::Key::Escape => {
-                input_sender.emit(ChatViewInput::Close);
+                input_sender.emit(ChatViewInput::ComposerEscaped);
                 glib::Propagation::Stop
             }
+            gdk::Key::Tab => {
+                let cursor = usize::try_from(entry.position()).unwrap_or_default();
+                match expand_snippet(&entry.text(), cursor, &snippets.borrow()) {
+                    Some((text, cursor)) => {
+                        entry.set_text(&text);
+                        entry.set_position(i32::try_from(cursor).unwrap_or(-1));
+                        glib::Propagation::Stop
+                    }
+                    None => glib::Propagation::Proceed,
+                }
+            }
             _ => glib::Propagation::Proceed,
         });
         message_entry.add_controller(key_event_controller);
 
+        // Track text being composed with an input method, which `Enter`
+        // commits instead of sending.
+        let composing = Rc::new(Cell::new(false));
+        if let Some(text) = message_entry.delegate().and_downcast::<gtk::Text>() {
+            let composing = Rc::clone(&composing);
+            text.connect_preedit_changed(move |_, preedit| composing.set(!preedit.is_empty()));
+        }
+
+        // Send on the shortcut set in the preferences. This runs before the
+        // entry handles `Enter`, leaving the other shortcut for a new line.
+        let send_shortcut = Rc::clone(&model.send_shortcut);
+        let input_sender = sender.input_sender().clone();
+        let key_event_controller = gtk::EventControllerKey::builder()
+            .propagation_phase(gtk::PropagationPhase::Capture)
+            .build();
+        key_event_controller.connect_key_pressed(move |_, key, _, modifiers| {
+            if sends_message(send_shortcut.get(), key, modifiers, composing.get()) {
+                input_sender.emit(ChatViewInput::SendMessage);
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        });
+        message_entry.add_controller(key_event_controller);
+
+        // Stop selecting messages when `Esc` is pressed, before it closes the chat.
+        let input_sender = sender.input_sender().clone();
+        let key_event_controller = gtk::EventControllerKey::builder()
+            .propagation_phase(gtk::PropagationPhase::Capture)
+            .build();
+        key_event_controller.connect_key_pressed(move |_, key, _, _| {
+            if key == gdk::Key::Escape && ROW_SELECTION.with_borrow(|selection| selection.active) {
+                input_sender.emit(ChatViewInput::CancelSelection);
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        });
+        root.add_controller(key_event_controller);
+
         // Track scroll position and notify the model when it changes.
         let adj = widgets.scroll_window.vadjustment();
         let command_sender = sender.command_sender().clone();
@@ -349,6 +1040,105 @@ impl AsyncComponent for ChatView {
             }
         });
 
+        // Show the date of the topmost messages while scrolling, fading out
+        // shortly after. Only scroll input reveals it, so the adjustment changes
+        // caused by loading and trimming pages just keep its label current.
+        let list_view = model.list_view_wrapper.view.clone();
+        let date_pill = widgets.date_pill.clone();
+        let date_pill_label = widgets.date_pill_label.clone();
+        let hide_source: Rc<RefCell<Option<glib::SourceId>>> = Rc::default();
+        let show_date_pill = Rc::new(move |reveal: bool| {
+            if !reveal && !date_pill.reveals_child() {
+                return;
+            }
+            if let Some(date) = top_visible_date(&list_view) {
+                date_pill_label.set_label(&format_date_label(date));
+            }
+            date_pill.set_reveal_child(true);
+
+            if let Some(source) = hide_source.take() {
+                source.remove();
+            }
+            let pill = date_pill.clone();
+            let source = Rc::clone(&hide_source);
+            hide_source.replace(Some(glib::timeout_add_local_once(
+                DATE_PILL_TIMEOUT,
+                move || {
+                    source.take();
+                    pill.set_reveal_child(false);
+                },
+            )));
+        });
+
+        let show = Rc::clone(&show_date_pill);
+        adj.connect_value_changed(move |_| show(false));
+
+        let scroll_controller = gtk::EventControllerScroll::builder()
+            .flags(
+                gtk::EventControllerScrollFlags::VERTICAL
+                    | gtk::EventControllerScrollFlags::KINETIC,
+            )
+            .propagation_phase(gtk::PropagationPhase::Capture)
+            .build();
+        let show = Rc::clone(&show_date_pill);
+        scroll_controller.connect_scroll(move |_, _, _| {
+            show(true);
+            glib::Propagation::Proceed
+        });
+        scroll_controller.connect_decelerate(move |_, _, _| show_date_pill(true));
+        widgets.scroll_window.add_controller(scroll_controller);
+
+        // Pick a date to jump to from the chat menu. The calendar starts
+        // weeks on the first weekday of the locale on its own.
+        let go_button = model.go_to_date_button.clone();
+        let calendar = model.calendar.clone();
+        let date_picker = model.date_picker.clone();
+        let input_sender = sender.input_sender().clone();
+        go_button.connect_clicked(move |_| {
+            date_picker.popdown();
+            if let Some(date) = calendar_date(&calendar) {
+                input_sender.emit(ChatViewInput::GoToDate(date));
+            }
+        });
+
+        let date_picker_content = gtk::Box::builder()
+            .spacing(6)
+            .orientation(gtk::Orientation::Vertical)
+            .build();
+        date_picker_content.append(&model.calendar);
+
+        let input_sender = sender.input_sender().clone();
+        let changed = move |_: &gtk::Calendar| input_sender.emit(ChatViewInput::DatePickerChanged);
+        model.calendar.connect_day_selected(changed.clone());
+        model.calendar.connect_next_month(changed.clone());
+        model.calendar.connect_prev_month(changed.clone());
+        model.calendar.connect_next_year(changed.clone());
+        model.calendar.connect_prev_year(changed);
+        date_picker_content.append(&go_button);
+        model.date_picker.set_child(Some(&date_picker_content));
+        model.date_picker.set_parent(&widgets.menu_button);
+
+        let input_sender = sender.input_sender().clone();
+        let go_to_date_action = RelmAction::<GoToDateAction>::new_stateless(move |_| {
+            input_sender.emit(ChatViewInput::ShowDatePicker);
+        });
+        let mut actions = RelmActionGroup::<ChatViewActionGroup>::new();
+        actions.add_action(go_to_date_action);
+        actions.add_action(add_contact_action);
+        actions.add_action(gallery_action);
+        actions.add_action(clear_history_action);
+        actions.add_action(restore_history_action);
+        actions.add_action(wallpaper_action);
+        actions.register_for_widget(&root);
+
+        // Re-label "Today" and "Yesterday" separators when the date changes,
+        // and drop pins once they end.
+        let input_sender = sender.input_sender().clone();
+        glib::timeout_add_seconds_local(60, move || {
+            input_sender.emit(ChatViewInput::Tick);
+            glib::ControlFlow::Continue
+        });
+
         AsyncComponentParts { model, widgets }
     }
 
@@ -357,10 +1147,13 @@ impl AsyncComponent for ChatView {
         &mut self,
         input: Self::Input,
         sender: AsyncComponentSender<Self>,
-        _root: &Self::Root,
+        root: &Self::Root,
     ) {
         match input {
             ChatViewInput::Open(chat) => {
+                // Remember the composer state of the previous chat.
+                self.store_composer_state().await;
+
                 self.row_metadata.clear();
                 self.list_view_wrapper.clear();
 
@@ -371,13 +1164,39 @@ impl AsyncComponent for ChatView {
                 self.state.bottom_trimmed = false;
                 self.state.first_message_date = None;
                 self.state.last_message_date = None;
+                self.state.new_message_count = 0;
                 self.state.oldest_loaded_timestamp = None;
                 self.state.newest_loaded_timestamp = None;
+                self.state.replying_to = None;
+                self.set_selecting(false);
 
                 let jid = chat.jid.clone();
+                let settings = chat.load_settings().await.unwrap_or_else(|e| {
+                    tracing::error!("Failed to load chat settings for {jid}: {e}");
+                    ChatSettings::default()
+                });
+                self.state.cleared_before = settings.cleared_before;
+                self.restore_history_action
+                    .set_enabled(settings.cleared_before.is_some());
+
+                // Open at the first unread message if they don't all fit in the
+                // first page, loading newer ones while scrolling down.
+                let unread_count = chat.get_unread_count().await.unwrap_or_default();
+                let first_unread = if self.settings.boolean("open-at-first-unread")
+                    && unread_count > usize::try_from(INITIAL_LOAD_COUNT).unwrap_or(usize::MAX)
+                {
+                    chat.get_first_unread_time().await.unwrap_or_else(|e| {
+                        tracing::error!("Failed to find the first unread message in {jid}: {e}");
+                        None
+                    })
+                } else {
+                    None
+                };
 
-                // Load the initial batch of messages.
-                if let Ok(messages) = chat.load_messages(INITIAL_LOAD_COUNT).await {
+                if let Some(anchor) = first_unread {
+                    self.show_chat_messages_around(&chat, anchor).await;
+                    self.state.is_at_bottom = false;
+                } else if let Ok(messages) = chat.load_messages(INITIAL_LOAD_COUNT).await {
                     self.state.has_more_messages = messages.len() == usize::try_from(INITIAL_LOAD_COUNT).unwrap();
 
                     // Track the oldest loaded timestamp for pagination.
@@ -408,10 +1227,11 @@ impl AsyncComponent for ChatView {
                             self.state.first_message_date = Some(msg_date);
                         }
 
-                        self.list_view_wrapper.append(ChatRow::Message(msg.clone()));
+                        self.list_view_wrapper.append(ChatRow::from(msg.clone()));
                         self.row_metadata
-                            .push_back(RowMetadata::Message(msg.timestamp.timestamp()));
+                            .push_back(RowMetadata::from(&msg));
                     }
+                    self.show_cleared_boundary();
 
                     // Scroll to the last message.
                     let count = self.list_view_wrapper.len();
@@ -426,54 +1246,103 @@ impl AsyncComponent for ChatView {
 
                         self.state.is_at_bottom = true;
                     }
+
+                    // Decode the thumbnails on and above the screen, newest first.
+                    prefetch_thumbnails(&messages);
                 }
 
                 // Mark chat as read if it has unread messages.
-                if chat.get_unread_count().await.is_ok_and(|count| count > 0) {
+                if unread_count > 0 {
                     let _ = sender.output(ChatViewOutput::MarkChatRead(jid));
                 }
 
-                // Update the user presence label.
-                self.update_presence();
+                // Restore the composer state last used in this chat.
+                self.restore_composer_state(&chat).await;
+                self.load_snippets(&chat).await;
+                self.restore_wallpaper(&chat).await;
+
+                // Suggest a resync if many messages couldn't be decrypted.
+                self.state.undecryptable_count = settings.undecryptable_count;
+                self.load_join_request_count(&chat).await;
 
-                // Grab message entry focus as convenience.
+                self.load_pinned_messages(&chat).await;
+
+                // Show who is behind the chat if they aren't in the contacts.
+                self.state.phone_number = unknown_phone_number(&chat).await;
+                self.add_contact_action
+                    .set_enabled(self.state.phone_number.is_some());
+
+                // Grab message entry focus as convenience, along with the
+                // emoji picker if it was used for the last message.
                 self.message_entry.grab_focus();
+                if self.state.composer_hints.picker && !chat.is_read_only() {
+                    self.emoji_button.popup();
+                }
 
                 self.chat = Some(chat);
                 self.state.is_loading = false;
 
+                // Update the user presence label.
+                self.update_presence();
+
                 let _ = sender.output(ChatViewOutput::ChatOpen);
+
+                // Jump to the message this chat was opened for, if any.
+                if let Some((chat_jid, msg_id)) = self.state.pending_jump.take().filter(|(jid, _)| {
+                    self.chat.as_ref().is_some_and(|chat| chat.jid == *jid)
+                }) {
+                    sender.input(ChatViewInput::GoToMessage { chat_jid, msg_id });
+                }
+            }
+            ChatViewInput::ChatChanged(chat) => {
+                if self.chat.as_ref().is_some_and(|open| open.jid == chat.jid) {
+                    self.chat = Some(chat);
+                }
             }
             ChatViewInput::Close => {
+                self.store_composer_state().await;
+                thumbnails::cancel_prefetch();
+
                 self.row_metadata.clear();
                 self.list_view_wrapper.clear();
 
                 // Reset state.
                 self.chat = None;
                 self.state.presence = None;
+                self.state.replying_to = None;
+                self.state.phone_number = None;
+                self.state.cleared_before = None;
                 self.state.is_loading = false;
                 self.state.top_trimmed = false;
                 self.state.is_at_bottom = false;
                 self.state.bottom_trimmed = false;
                 self.state.first_message_date = None;
                 self.state.last_message_date = None;
+                self.state.new_message_count = 0;
                 self.state.oldest_loaded_timestamp = None;
                 self.state.newest_loaded_timestamp = None;
+                self.set_selecting(false);
 
                 let _ = sender.output(ChatViewOutput::ChatClosed);
             }
 
             ChatViewInput::SendMessage => {
                 if let Some(ref chat) = self.chat
+                    && !chat.is_newsletter()
                     && self.message_entry.text_length() > 0
                 {
                     let text = self.message_entry.text().to_string();
                     self.message_entry.set_text("");
+                    self.state.composer_hints = ComposerHints {
+                        picker: std::mem::take(&mut self.state.emoji_picked),
+                        purpose: ComposerPurpose::from_text(&text),
+                    };
 
                     // Send a plain text message.
                     let _ = sender.output(ChatViewOutput::SendTextMessage {
                         text,
                         recipient: chat.jid.clone(),
+                        quote: self.state.replying_to.take().as_ref().map(Quote::from),
                     });
 
                     // TODO: implements media sending
@@ -484,36 +1353,17 @@ impl AsyncComponent for ChatView {
                 }
             }
             ChatViewInput::MessageReceived(message) => {
-                // If the bottom has been trimmed, skip appending — the message will
-                // appear when the user scrolls back to bottom and triggers a reload.
-                if self.state.bottom_trimmed {
-                    return;
-                }
-
-                // Convert to local date for separator comparison.
-                let msg_date = message.timestamp.with_timezone(&Local).date_naive();
-
-                // Insert a date separator if the date changed.
-                if self.state.last_message_date != Some(msg_date) {
-                    self.list_view_wrapper
-                        .append(ChatRow::DateSeparator(msg_date));
-                    self.row_metadata
-                        .push_back(RowMetadata::Separator(msg_date));
-                    self.state.last_message_date = Some(msg_date);
-                }
-
-                // Update newest loaded timestamp to this message.
-                let ts = message.timestamp.timestamp();
-                self.state.newest_loaded_timestamp = Some(ts);
-
-                self.list_view_wrapper.append(ChatRow::Message(*message));
-                self.row_metadata.push_back(RowMetadata::Message(ts));
-
-                // If the user is at the bottom, they're seeing this message — mark read.
-                if self.state.is_at_bottom
-                    && let Some(ref chat) = self.chat
-                {
-                    let _ = sender.output(ChatViewOutput::MarkChatRead(chat.jid.clone()));
+                // A message saved while the chat was opening may already be
+                // in its first page, and one delivered late goes among the
+                // loaded ones, by its time.
+                let date = message.timestamp.with_timezone(&Local).date_naive();
+                let has_older = self.state.has_more_messages || self.state.top_trimmed;
+                match place_received(&self.row_metadata, &message, date, has_older) {
+                    Some(Placement::End) => self.append_message(*message, &sender),
+                    Some(Placement::Before(position, separator)) => {
+                        self.insert_message(position, separator, *message);
+                    }
+                    None => {}
                 }
             }
 
@@ -525,27 +1375,76 @@ impl AsyncComponent for ChatView {
                 if let Some(ref mut chat) = self.chat
                     && jid == chat.jid
                 {
-                    if !chat.is_group() {
+                    // Only private chats have a presence.
+                    if chat.is_private() {
                         chat.available = Some(available);
+                        chat.last_seen = last_seen;
                     }
-                    chat.last_seen = last_seen;
 
                     // Update the user presence label.
                     self.update_presence();
                 }
             }
-            ChatViewInput::MessageStatusUpdate { msg_id, status } => {
-                if let Some(item) = self.list_view_wrapper.iter().find(
-                    |item| matches!(item.borrow().deref(), ChatRow::Message(message) if message.server_id == msg_id),
-                    ) {
-                        let mut row = item.borrow_mut();
-                        if let ChatRow::Message(message) = &mut *row {
-                            message.status = status;
-                        }
+            ChatViewInput::MessageStatusUpdate(message) => {
+                // The row was added before sending, so match its local ID.
+                let position = self.list_view_wrapper.iter().position(
+                    |item| matches!(item.borrow().deref(), ChatRow::Message(row) if row.local_id == message.local_id),
+                );
+                if let Some(position) = position.and_then(|p| u32::try_from(p).ok()) {
+                    // Bind the row again, for the preview attached while sending.
+                    self.list_view_wrapper.remove(position);
+                    self.list_view_wrapper
+                        .insert(position, ChatRow::Message(*message));
+                }
+            }
+
+            ChatViewInput::MessageRevoked { msg_id } => {
+                // Bind the row again as a tombstone.
+                self.replace_message(&msg_id, |message| {
+                    message.media = None;
+                    message.revoked = true;
+                    message.content.clear();
+                });
+            }
+            ChatViewInput::MessageEdited { msg_id, content } => {
+                self.replace_message(&msg_id, |message| {
+                    message.edited = true;
+                    message.content = content;
+                });
+            }
+            ChatViewInput::MediaDownloaded { msg_id, data } => {
+                self.replace_message(&msg_id, |message| {
+                    if let Some(ref mut media) = message.media {
+                        media.data = data;
+                        media.downloadable = None;
+                    }
+                });
+            }
+            ChatViewInput::PollVoted {
+                msg_id,
+                voter_jid,
+                options,
+            } => {
+                self.replace_message(&msg_id, |message| {
+                    if let Some(ref mut poll) = message.poll {
+                        poll.votes.insert(voter_jid, options);
                     }
+                });
+            }
+            ChatViewInput::ReactionChanged {
+                msg_id,
+                sender_jid,
+                emoji,
+            } => {
+                self.replace_message(&msg_id, |message| {
+                    message.set_reaction(&sender_jid, emoji.as_deref());
+                });
             }
+            ChatViewInput::UserJidChanged(jid) => USER_JID.set(jid),
 
             ChatViewInput::ScrollToBottom => {
+                self.state.new_message_count = 0;
+
                 // If either end has been trimmed, the view is a "window" into the
                 // message history — reload from scratch to jump to the real latest.
                 if self.state.bottom_trimmed || self.state.top_trimmed {
@@ -590,10 +1489,11 @@ impl AsyncComponent for ChatView {
                                 self.state.first_message_date = Some(msg_date);
                             }
 
-                            self.list_view_wrapper.append(ChatRow::Message(msg.clone()));
+                            self.list_view_wrapper.append(ChatRow::from(msg.clone()));
                             self.row_metadata
-                                .push_back(RowMetadata::Message(msg.timestamp.timestamp()));
+                                .push_back(RowMetadata::from(&msg));
                         }
+                        self.show_cleared_boundary();
                     }
                 }
 
@@ -611,15 +1511,439 @@ impl AsyncComponent for ChatView {
                     self.state.is_at_bottom = true;
                 }
             }
-        }
-    }
 
-    #[allow(clippy::too_many_lines)]
-    async fn update_cmd(
-        &mut self,
-        command: Self::CommandOutput,
-        sender: AsyncComponentSender<Self>,
-        _root: &Self::Root,
+            ChatViewInput::ShowDatePicker => {
+                let Some(ref chat) = self.chat else { return };
+                let Some((oldest, newest)) = chat
+                    .get_date_range()
+                    .await
+                    .ok()
+                    .flatten()
+                    .and_then(|(oldest, newest)| local_date(oldest).zip(local_date(newest)))
+                else {
+                    return;
+                };
+                self.state.date_range = Some((oldest, newest));
+                self.state.marked_month = None;
+
+                // Start from the month of the newest message.
+                self.calendar.set_year(newest.year());
+                self.calendar
+                    .set_month(i32::try_from(newest.month0()).unwrap_or_default());
+                self.calendar
+                    .set_day(i32::try_from(newest.day()).unwrap_or(1));
+                sender.input(ChatViewInput::DatePickerChanged);
+                self.date_picker.popup();
+            }
+            ChatViewInput::DatePickerChanged => {
+                let Some(ref chat) = self.chat else { return };
+
+                // Days outside the chat history can't be jumped to.
+                let in_range = calendar_date(&self.calendar)
+                    .zip(self.state.date_range)
+                    .is_some_and(|(date, (oldest, newest))| (oldest..=newest).contains(&date));
+                self.go_to_date_button.set_sensitive(in_range);
+
+                // Mark the days with messages, once per month shown.
+                let month = (self.calendar.year(), self.calendar.month());
+                if self.state.marked_month == Some(month) {
+                    return;
+                }
+                self.state.marked_month = Some(month);
+                self.calendar.clear_marks();
+
+                let Some((start, end)) = month_bounds(month.0, month.1) else {
+                    return;
+                };
+                match chat.get_message_days(start, end).await {
+                    Ok(days) => {
+                        for day in days {
+                            self.calendar.mark_day(day.day());
+                        }
+                    }
+                    Err(e) => tracing::error!("Failed to load the days with messages: {e}"),
+                }
+            }
+            ChatViewInput::GoToDate(date) => {
+                let Some(ref chat) = self.chat else { return };
+                let Ok(Some((oldest, newest))) = chat.get_date_range().await else {
+                    return;
+                };
+
+                // Keep the date within the chat history.
+                let date = match (local_date(oldest), local_date(newest)) {
+                    (Some(first), Some(last)) => date.clamp(first, last),
+                    _ => date,
+                };
+                let (Some(start), Some(end)) = (
+                    local_day_start(date),
+                    date.succ_opt().and_then(local_day_start),
+                ) else {
+                    return;
+                };
+
+                let Ok(Some(anchor)) = chat.find_message_time_between(start, end).await else {
+                    return;
+                };
+                self.show_messages_around(anchor).await;
+            }
+
+            ChatViewInput::GoToMessage { chat_jid, msg_id } => {
+                let Some(ref chat) = self.chat.clone().filter(|chat| chat.jid == chat_jid) else {
+                    self.state.pending_jump = Some((chat_jid, msg_id));
+                    return;
+                };
+
+                match chat.find_message(&msg_id).await {
+                    Ok(Some(message)) => {
+                        self.show_messages_around(message.timestamp.timestamp())
+                            .await;
+                    }
+                    // Deleted or cleared since it was listed.
+                    Ok(None) => {
+                        tracing::warn!("Message {msg_id} to jump to isn't stored");
+                        let _ = sender.output(ChatViewOutput::ShowToast(i18n!(
+                            "Message no longer available"
+                        )));
+                    }
+                    Err(e) => tracing::error!("Failed to load message {msg_id}: {e}"),
+                }
+            }
+            ChatViewInput::GoToQuoted(msg_id) => {
+                let Some(chat) = self.chat.clone() else { return };
+
+                // Load the history around the original if it's not in the window.
+                if self.message_position(&msg_id).is_none() {
+                    match chat.find_message(&msg_id).await {
+                        Ok(Some(message)) => {
+                            self.show_messages_around(message.timestamp.timestamp())
+                                .await;
+                        }
+                        Ok(None) => {
+                            let _ = sender.output(ChatViewOutput::ShowToast(i18n!(
+                                "Original message not found"
+                            )));
+                            return;
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to load message {msg_id}: {e}");
+                            return;
+                        }
+                    }
+                }
+
+                self.highlight_message(&msg_id);
+            }
+
+            ChatViewInput::Tick => {
+                // Pins last for the time they were made for.
+                if self.state.pins_expire_at.is_some_and(|t| t <= Utc::now())
+                    && let Some(chat) = self.chat.clone()
+                {
+                    self.load_pinned_messages(&chat).await;
+                }
+
+                let today = Local::now().date_naive();
+                if today == self.state.today {
+                    return;
+                }
+                let previous = std::mem::replace(&mut self.state.today, today);
+
+                // "Today" became "Yesterday", and "Yesterday" became a date.
+                let stale = self
+                    .list_view_wrapper
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(position, item)| match *item.borrow() {
+                        ChatRow::DateSeparator(date)
+                            if previous.pred_opt().is_some_and(|d| date >= d) =>
+                        {
+                            Some((position, date))
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+
+                for (position, date) in stale {
+                    if let Ok(position) = u32::try_from(position) {
+                        self.list_view_wrapper.remove(position);
+                        self.list_view_wrapper
+                            .insert(position, ChatRow::DateSeparator(date));
+                    }
+                }
+            }
+            ChatViewInput::UndecryptableCountChanged { chat_jid } => {
+                let Some(ref chat) = self.chat else { return };
+                if chat.jid != chat_jid {
+                    return;
+                }
+
+                match chat.load_settings().await {
+                    Ok(settings) => self.state.undecryptable_count = settings.undecryptable_count,
+                    Err(e) => tracing::error!("Failed to load chat settings for {chat_jid}: {e}"),
+                }
+            }
+            ChatViewInput::JoinRequestsChanged { chat_jid } => {
+                if let Some(chat) = self.chat.clone().filter(|c| c.jid == chat_jid) {
+                    self.load_join_request_count(&chat).await;
+                }
+            }
+            ChatViewInput::ShowJoinRequests => {
+                if let Some(chat) = self.chat.as_ref().filter(|c| c.is_group()) {
+                    let _ = sender.output(ChatViewOutput::ShowJoinRequests {
+                        jid: chat.jid.clone(),
+                        name: chat.get_name_or_number(),
+                    });
+                }
+            }
+            ChatViewInput::ShowGallery => {
+                if let Some(chat) = &self.chat {
+                    let _ = sender.output(ChatViewOutput::ShowGallery {
+                        jid: chat.jid.clone(),
+                        name: chat.name.clone(),
+                    });
+                }
+            }
+            ChatViewInput::StartCall(is_video) => {
+                if let Some(chat) = self.chat.as_ref().filter(|c| c.is_private()) {
+                    let _ = sender.output(ChatViewOutput::StartCall {
+                        jid: chat.jid.clone(),
+                        is_video,
+                    });
+                }
+            }
+            ChatViewInput::ClearHistory => {
+                if let Some(chat) = &self.chat {
+                    let _ = sender.output(ChatViewOutput::ClearHistory {
+                        jid: chat.jid.clone(),
+                        name: chat.get_name_or_number(),
+                    });
+                }
+            }
+            ChatViewInput::RestoreHistory => {
+                if let Some(chat) = &self.chat {
+                    let _ = sender.output(ChatViewOutput::RestoreHistory(chat.jid.clone()));
+                }
+            }
+            ChatViewInput::ShowAddContact => {
+                let (Some(chat), Some(phone_number)) =
+                    (self.chat.as_ref(), self.state.phone_number.clone())
+                else {
+                    return;
+                };
+
+                let entry = gtk::Entry::builder()
+                    .text(&chat.name)
+                    .activates_default(true)
+                    .placeholder_text(i18n!("Name"))
+                    .build();
+                let dialog = adw::AlertDialog::builder()
+                    .heading(i18n!("Add to Contacts"))
+                    .body(&phone_number)
+                    .extra_child(&entry)
+                    .default_response("add")
+                    .close_response("cancel")
+                    .build();
+                dialog.add_responses(&[("cancel", &i18n!("_Cancel")), ("add", &i18n!("_Add"))]);
+                dialog.set_response_appearance("add", adw::ResponseAppearance::Suggested);
+
+                let jid = chat.jid.clone();
+                dialog.connect_response(Some("add"), move |_, _| {
+                    let name = entry.text().trim().to_string();
+                    if !name.is_empty() {
+                        let _ = sender.output(ChatViewOutput::SaveContact {
+                            jid: jid.clone(),
+                            name,
+                            phone_number: phone_number.clone(),
+                        });
+                    }
+                });
+                dialog.present(Some(root));
+            }
+            ChatViewInput::ContactSaved { jid, name } => {
+                let Some(ref mut chat) = self.chat else { return };
+                if chat.jid == jid {
+                    chat.name = name;
+                    self.state.phone_number = None;
+                    self.add_contact_action.set_enabled(false);
+                }
+            }
+            ChatViewInput::PinnedMessagesChanged { chat_jid } => {
+                let Some(chat) = self.chat.clone() else { return };
+                if chat.jid == chat_jid {
+                    self.load_pinned_messages(&chat).await;
+                }
+            }
+            ChatViewInput::ShowPinnedMessage => {
+                let Some(anchor) = self
+                    .shown_pinned_message()
+                    .map(|message| message.timestamp.timestamp())
+                else {
+                    return;
+                };
+                self.show_messages_around(anchor).await;
+
+                // Cycle through the pins, like the official apps do.
+                self.state.pinned_index =
+                    (self.state.pinned_index + 1) % self.state.pinned_messages.len();
+            }
+            ChatViewInput::UnpinShownMessage => {
+                if let Some(message) = self.shown_pinned_message() {
+                    let _ = sender.output(ChatViewOutput::UnpinMessage {
+                        chat_jid: message.chat_jid.clone(),
+                        msg_id: message.server_id.clone(),
+                    });
+                }
+            }
+            ChatViewInput::SetChatWallpaper(wallpaper) => {
+                let Some(ref chat) = self.chat else { return };
+
+                self.state.chat_wallpaper = wallpaper;
+                self.update_wallpaper();
+
+                let mut settings = chat.load_settings().await.unwrap_or_default();
+                settings.wallpaper = wallpaper;
+                if let Err(e) = chat.save_settings(&settings).await {
+                    tracing::error!("Failed to save chat settings for {}: {e}", chat.jid);
+                }
+            }
+            ChatViewInput::WallpaperChanged => {
+                self.load_wallpaper_image();
+                self.update_wallpaper();
+            }
+            ChatViewInput::ToggleSelected(msg_id) => {
+                if !self.state.selected.remove(&msg_id) {
+                    self.state.selected.insert(msg_id);
+                }
+                self.set_selecting(true);
+            }
+            ChatViewInput::Reply(msg_id) => {
+                let message = self.list_view_wrapper.iter().find_map(|item| match &*item.borrow() {
+                    ChatRow::Message(message) if message.server_id == msg_id => Some(message.clone()),
+                    _ => None,
+                });
+                if message.is_some() {
+                    self.state.replying_to = message;
+                    self.message_entry.grab_focus();
+                }
+            }
+            ChatViewInput::CancelReply => self.state.replying_to = None,
+            ChatViewInput::EmojiPicked(emoji) => {
+                self.message_entry.delete_selection();
+                let mut position = self.message_entry.position();
+                self.message_entry.insert_text(&emoji, &mut position);
+                self.message_entry.set_position(position);
+                self.state.emoji_picked = true;
+            }
+            ChatViewInput::ComposerEscaped => {
+                if self.state.replying_to.is_some() {
+                    self.state.replying_to = None;
+                } else {
+                    sender.input(ChatViewInput::Close);
+                }
+            }
+            ChatViewInput::CancelSelection => self.set_selecting(false),
+            ChatViewInput::CopySelected => {
+                let mode = self
+                    .settings
+                    .string("export-timestamps")
+                    .parse::<TimestampMode>()
+                    .unwrap_or_default();
+                let text = self
+                    .selected_messages()
+                    .iter()
+                    .map(|message| {
+                        format!(
+                            "[{}] {}: {}",
+                            format_export_timestamp(message.timestamp, mode),
+                            sender_name(message),
+                            message.content
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                root.clipboard().set_text(&text);
+
+                let count = u32::try_from(self.state.selected.len()).unwrap_or(u32::MAX);
+                let _ = sender.output(ChatViewOutput::ShowToast(
+                    ni18n!("{0} message copied", "{0} messages copied", count).replacen(
+                        "{0}",
+                        &count.to_string(),
+                        1,
+                    ),
+                ));
+                self.set_selecting(false);
+            }
+            ChatViewInput::ForwardSelected => {
+                // Only text can be sent, so media, locations and such are left out.
+                let texts = self
+                    .selected_messages()
+                    .into_iter()
+                    .filter(|message| {
+                        message.media.is_none()
+                            && message.location.is_none()
+                            && message.poll.is_none()
+                            && message.contacts.is_empty()
+                            && !message.content.is_empty()
+                    })
+                    .map(|message| message.content)
+                    .collect::<Vec<_>>();
+                if texts.is_empty() {
+                    let _ = sender.output(ChatViewOutput::ShowToast(i18n!(
+                        "Only text messages can be forwarded"
+                    )));
+                    return;
+                }
+
+                let _ = sender.output(ChatViewOutput::ForwardMessages(texts));
+                self.set_selecting(false);
+            }
+            ChatViewInput::BookmarkSelected => {
+                if let Some(chat) = &self.chat {
+                    let _ = sender.output(ChatViewOutput::BookmarkMessages {
+                        chat_jid: chat.jid.clone(),
+                        msg_ids: self.state.selected.iter().cloned().collect(),
+                    });
+                }
+                self.set_selecting(false);
+            }
+            ChatViewInput::DeleteSelected => {
+                if let Some(chat) = &self.chat {
+                    let _ = sender.output(ChatViewOutput::DeleteMessages {
+                        chat_jid: chat.jid.clone(),
+                        msg_ids: self.state.selected.iter().cloned().collect(),
+                    });
+                }
+            }
+            ChatViewInput::MessagesDeleted(msg_ids) => {
+                if self
+                    .state
+                    .replying_to
+                    .as_ref()
+                    .is_some_and(|message| msg_ids.contains(&message.server_id))
+                {
+                    self.state.replying_to = None;
+                }
+                self.remove_messages(&msg_ids);
+                self.set_selecting(false);
+            }
+            ChatViewInput::SendShortcutChanged => {
+                let shortcut = self
+                    .settings
+                    .string("send-shortcut")
+                    .parse::<SendShortcut>()
+                    .unwrap_or_default();
+                self.send_shortcut.set(shortcut);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    async fn update_cmd(
+        &mut self,
+        command: Self::CommandOutput,
+        sender: AsyncComponentSender<Self>,
+        _root: &Self::Root,
     ) {
         match command {
             ChatViewCommand::LoadOlderMessages => {
@@ -662,11 +1986,9 @@ impl AsyncComponent for ChatView {
                         }
 
                         self.list_view_wrapper
-                            .insert(insert_pos, ChatRow::Message(msg.clone()));
-                        self.row_metadata.insert(
-                            insert_pos as usize,
-                            RowMetadata::Message(msg.timestamp.timestamp()),
-                        );
+                            .insert(insert_pos, ChatRow::from(msg.clone()));
+                        self.row_metadata
+                            .insert(insert_pos as usize, RowMetadata::from(&msg));
 
                         insert_pos += 1;
                     }
@@ -681,11 +2003,15 @@ impl AsyncComponent for ChatView {
                         self.row_metadata.remove(insert_pos as usize);
                     }
 
+                    // Decode the thumbnails further up, nearest first.
+                    prefetch_thumbnails(&messages);
+
                     // Update first_message_date to the oldest prepended message's date.
                     if let Some(oldest_msg) = messages.last() {
                         self.state.first_message_date =
                             Some(oldest_msg.timestamp.with_timezone(&Local).date_naive());
                     }
+                    self.show_cleared_boundary();
 
                     // Trim excess rows from the bottom to stay within MAX_LOADED_ROWS.
                     let total = self.list_view_wrapper.len();
@@ -742,11 +2068,13 @@ impl AsyncComponent for ChatView {
                             self.state.last_message_date = Some(msg_date);
                         }
 
-                        self.list_view_wrapper.append(ChatRow::Message(msg.clone()));
-                        self.row_metadata
-                            .push_back(RowMetadata::Message(msg.timestamp.timestamp()));
+                        self.list_view_wrapper.append(ChatRow::from(msg.clone()));
+                        self.row_metadata.push_back(RowMetadata::from(&msg));
                     }
 
+                    // Decode the thumbnails further down, nearest first.
+                    prefetch_thumbnails(&messages);
+
                     // Trim excess rows from the top to stay within MAX_LOADED_ROWS.
                     let total = self.list_view_wrapper.len();
                     if total > MAX_LOADED_ROWS {
@@ -776,127 +2104,879 @@ impl AsyncComponent for ChatView {
                 if at_bottom != self.state.is_at_bottom {
                     self.state.is_at_bottom = at_bottom;
                 }
+
+                // Only the real bottom shows the messages received meanwhile.
+                if at_bottom && !self.state.bottom_trimmed {
+                    self.state.new_message_count = 0;
+                }
             }
         }
     }
 }
 
 impl ChatView {
-    /// Update the user presence.
-    fn update_presence(&mut self) {
-        if let Some(ref mut chat) = self.chat {
-            if chat.available.unwrap_or_default() {
-                self.state.presence = Some(i18n!("online"));
-            } else if let Some(last_seen) = chat.last_seen {
-                let today = Local::now().date_naive();
-                let last_date = last_seen.with_timezone(&Local).date_naive();
+    /// Save the composer state of the open chat, if it's to be remembered.
+    async fn store_composer_state(&self) {
+        let Some(ref chat) = self.chat else {
+            return;
+        };
+        if !self.settings.boolean("remember-composer-hints") {
+            return;
+        }
 
-                let presence = if last_date == today {
-                    format!(
-                        "{} {} {} {}",
-                        i18n!("Last seen"),
-                        i18n!("today"),
-                        i18n!("at"),
-                        last_date.format("%H:%M")
-                    )
-                } else if let Some(yesterday) = today.pred_opt()
-                    && last_date == yesterday
-                {
-                    format!(
-                        "{} {} {} {}",
-                        i18n!("Last seen"),
-                        i18n!("yesterday"),
-                        i18n!("at"),
-                        last_date.format("%H:%M")
-                    )
-                } else {
-                    format!(
-                        "{} {} {} {}",
-                        i18n!("Last seen"),
-                        last_date.format("%d/%m"),
-                        i18n!("at"),
-                        last_date.format("%H:%M")
-                    )
-                };
-                self.state.presence = Some(presence);
-            }
+        let mut settings = chat.load_settings().await.unwrap_or_default();
+        if settings.composer_hints == self.state.composer_hints {
+            return;
+        }
+
+        settings.composer_hints = self.state.composer_hints;
+        if let Err(e) = chat.save_settings(&settings).await {
+            tracing::error!("Failed to save chat settings for {}: {e}", chat.jid);
         }
     }
 
-    /// Update bottom cursors (`newest_loaded_timestamp`, `last_message_date`)
-    /// from the `row_metadata` after trimming rows from the bottom.
-    fn update_bottom_cursors(&mut self) {
-        self.state.last_message_date = None;
-        self.state.newest_loaded_timestamp = None;
+    /// Restore the composer state last used in `chat`.
+    async fn restore_composer_state(&mut self, chat: &Chat) {
+        self.state.emoji_picked = false;
+        self.state.composer_hints = if self.settings.boolean("remember-composer-hints") {
+            chat.load_settings()
+                .await
+                .map(|settings| settings.composer_hints)
+                .unwrap_or_default()
+        } else {
+            ComposerHints::default()
+        };
 
-        // Walk backward through metadata to find the newest message and last date.
-        for meta in self.row_metadata.iter().rev() {
-            match meta {
-                RowMetadata::Message(ts) => {
-                    if self.state.newest_loaded_timestamp.is_none() {
-                        self.state.newest_loaded_timestamp = Some(*ts);
-                    }
-                }
-                RowMetadata::Separator(date) => {
-                    if self.state.last_message_date.is_none() {
-                        self.state.last_message_date = Some(*date);
-                    }
-                }
-            }
+        let (purpose, hints) = self.state.composer_hints.purpose.input_hints();
+        self.message_entry.set_input_purpose(purpose);
+        self.message_entry.set_input_hints(hints);
+    }
 
-            // Stop once both cursors are found.
-            if self.state.newest_loaded_timestamp.is_some()
-                && self.state.last_message_date.is_some()
-            {
-                break;
-            }
+    /// Load the number of people asking to join `chat`, if it's a group.
+    async fn load_join_request_count(&mut self, chat: &Chat) {
+        if !chat.is_group() {
+            self.state.join_request_count = 0;
+            return;
+        }
+
+        match chat.get_join_request_count().await {
+            Ok(count) => self.state.join_request_count = count,
+            Err(e) => tracing::error!("Failed to count join requests of {}: {e}", chat.jid),
         }
     }
 
-    /// Update top cursors (`oldest_loaded_timestamp`, `first_message_date`)
-    /// from the `row_metadata` after trimming rows from the top.
-    fn update_top_cursors(&mut self) {
+    /// Restore the wallpaper picked for `chat`, if any.
+    async fn restore_wallpaper(&mut self, chat: &Chat) {
+        self.state.chat_wallpaper = chat
+            .load_settings()
+            .await
+            .ok()
+            .and_then(|settings| settings.wallpaper);
+        self.wallpaper_action.set_state(
+            &self
+                .state
+                .chat_wallpaper
+                .map_or_else(String::new, |wallpaper| wallpaper.as_ref().to_string())
+                .to_variant(),
+        );
+        self.update_wallpaper();
+    }
+
+    /// Show the wallpaper of the open chat, falling back to the preferences
+    /// one, and to none if it's an image that isn't set.
+    fn update_wallpaper(&mut self) {
+        let wallpaper = self.state.chat_wallpaper.unwrap_or_else(|| {
+            self.settings
+                .string("chat-wallpaper")
+                .parse()
+                .unwrap_or_default()
+        });
+
+        self.state.wallpaper = if wallpaper == Wallpaper::Image
+            && self.settings.string("chat-wallpaper-image").is_empty()
+        {
+            Wallpaper::Default
+        } else {
+            wallpaper
+        };
+    }
+
+    /// Load the wallpaper image picked in the preferences, dimmed in dark
+    /// mode so messages stay legible over it.
+    fn load_wallpaper_image(&self) {
+        let path = self.settings.string("chat-wallpaper-image");
+        if path.is_empty() {
+            self.wallpaper_provider.load_from_string("");
+            return;
+        }
+
+        let uri = gio::File::for_path(path.as_str()).uri();
+        self.wallpaper_provider.load_from_string(&format!(
+            r#"
+            .chat-view.wallpaper-image .chat-background {{
+              background-image: url("{uri}");
+              background-position: center;
+              background-size: cover;
+              background-repeat: no-repeat;
+            }}
+
+            @media (prefers-color-scheme: dark) {{
+              .chat-view.wallpaper-image .chat-background {{
+                background-image: linear-gradient(alpha(black, 0.5), alpha(black, 0.5)), url("{uri}");
+              }}
+            }}
+            "#
+        ));
+    }
+
+    /// Load the messages around `anchor` and scroll to the first one sent
+    /// then, showing its date separator if it starts the day.
+    async fn show_messages_around(&mut self, anchor: i64) {
+        let Some(chat) = self.chat.clone() else {
+            return;
+        };
+        self.show_chat_messages_around(&chat, anchor).await;
+    }
+
+    /// Like `show_messages_around`, for a chat that may still be opening.
+    async fn show_chat_messages_around(&mut self, chat: &Chat, anchor: i64) {
+        let Ok(Some((oldest, newest))) = chat.get_date_range().await else {
+            return;
+        };
+        let Ok(messages) = chat.load_messages_around(anchor, INITIAL_LOAD_COUNT).await else {
+            return;
+        };
+
+        self.row_metadata.clear();
+        self.list_view_wrapper.clear();
         self.state.first_message_date = None;
-        self.state.oldest_loaded_timestamp = None;
+        self.state.last_message_date = None;
 
-        // Walk forward through metadata to find the oldest message and first date.
-        for meta in &self.row_metadata {
-            match meta {
-                RowMetadata::Message(ts) => {
-                    if self.state.oldest_loaded_timestamp.is_none() {
-                        self.state.oldest_loaded_timestamp = Some(*ts);
-                    }
-                }
-                RowMetadata::Separator(date) => {
-                    if self.state.first_message_date.is_none() {
-                        self.state.first_message_date = Some(*date);
-                    }
-                }
+        // The view is now a window into the history, loading more at both ends.
+        self.state.oldest_loaded_timestamp = messages.first().map(|m| m.timestamp.timestamp());
+        self.state.newest_loaded_timestamp = messages.last().map(|m| m.timestamp.timestamp());
+        self.state.has_more_messages = self
+            .state
+            .oldest_loaded_timestamp
+            .is_some_and(|t| t > oldest);
+        self.state.top_trimmed = self.state.has_more_messages;
+        self.state.bottom_trimmed = self
+            .state
+            .newest_loaded_timestamp
+            .is_some_and(|t| t < newest);
+
+        // Decode the thumbnails nearest to the message first.
+        let mut nearest = messages.iter().collect::<Vec<_>>();
+        nearest.sort_by_key(|m| m.timestamp.timestamp().abs_diff(anchor));
+        prefetch_thumbnails(nearest);
+
+        for msg in messages {
+            let msg_date = msg.timestamp.with_timezone(&Local).date_naive();
+
+            // Insert a date separator if the date changed.
+            if self.state.last_message_date != Some(msg_date) {
+                self.list_view_wrapper
+                    .append(ChatRow::DateSeparator(msg_date));
+                self.row_metadata
+                    .push_back(RowMetadata::Separator(msg_date));
+                self.state.last_message_date = Some(msg_date);
             }
 
-            // Stop once both cursors are found.
-            if self.state.oldest_loaded_timestamp.is_some()
-                && self.state.first_message_date.is_some()
-            {
-                break;
+            // Track the first message date for prepend separators.
+            if self.state.first_message_date.is_none() {
+                self.state.first_message_date = Some(msg_date);
             }
-        }
 
-        // We trimmed from top, so there are definitely older messages to load.
-        self.state.has_more_messages = true;
+            self.row_metadata.push_back(RowMetadata::from(&msg));
+            self.list_view_wrapper.append(ChatRow::from(msg));
+        }
+        self.show_cleared_boundary();
+
+        // Scroll to the message, showing its date separator if it starts the day.
+        let position = self
+            .row_metadata
+            .iter()
+            .position(|meta| matches!(meta, RowMetadata::Message { timestamp, .. } if *timestamp == anchor))
+            .map(|position| match position.checked_sub(1) {
+                Some(previous)
+                    if matches!(
+                        self.row_metadata.get(previous),
+                        Some(RowMetadata::Separator(_))
+                    ) =>
+                {
+                    previous
+                }
+                _ => position,
+            });
+        if let Some(position) = position.and_then(|p| u32::try_from(p).ok()) {
+            let info = gtk::ScrollInfo::new();
+            info.set_enable_vertical(true);
+            self.list_view_wrapper.view.scroll_to(
+                position,
+                gtk::ListScrollFlags::FOCUS,
+                Some(info),
+            );
+        }
     }
-}
 
-/// Metadata for a single row in the message list, used for cursor tracking
+    /// Load the pinned messages of `chat`, showing the latest pin first.
+    async fn load_pinned_messages(&mut self, chat: &Chat) {
+        let pinned = chat.load_pinned_messages().await.unwrap_or_else(|e| {
+            tracing::error!("Failed to load pinned messages for {}: {e}", chat.jid);
+            Vec::new()
+        });
+
+        self.state.pinned_index = 0;
+        self.state.pins_expire_at = pinned
+            .iter()
+            .filter_map(|(_, expires_at)| *expires_at)
+            .min();
+        self.state.pinned_messages = pinned.into_iter().map(|(message, _)| message).collect();
+    }
+
+    /// Get the accessible label of the scroll to bottom button, with the
+    /// number of new messages.
+    fn scroll_button_label(&self) -> String {
+        let count = self.state.new_message_count;
+        if count > 0 {
+            ni18n!(
+                "Scroll to Bottom, {0} new message",
+                "Scroll to Bottom, {0} new messages",
+                count
+            )
+            .replacen("{0}", &count.to_string(), 1)
+        } else {
+            i18n!("Scroll to Bottom")
+        }
+    }
+
+    /// Check if the phone number is shown under the chat name, which is
+    /// the number itself when there's no name.
+    fn shows_phone_number(&self) -> bool {
+        self.state.phone_number.is_some()
+            && self.chat.as_ref().is_some_and(|chat| !chat.name.is_empty())
+    }
+
+    /// Get the pinned message shown in the pinned bar.
+    fn shown_pinned_message(&self) -> Option<&ChatMessage> {
+        self.state.pinned_messages.get(self.state.pinned_index)
+    }
+
+    /// Get the title of the pinned bar, with the position of the shown pin.
+    fn pinned_title(&self) -> String {
+        let count = self.state.pinned_messages.len();
+        if count > 1 {
+            i18n_f!(
+                "Pinned Message {0} of {1}",
+                self.state.pinned_index + 1,
+                count
+            )
+        } else {
+            i18n!("Pinned Message")
+        }
+    }
+
+    /// Load the snippets to expand in the composer, if enabled.
+    async fn load_snippets(&self, chat: &Chat) {
+        let snippets = if gio::Settings::new(APP_ID).boolean("expand-snippets") {
+            chat.db.load_snippets().await.unwrap_or_else(|e| {
+                tracing::error!("Failed to load snippets: {e}");
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+
+        *self.snippets.borrow_mut() = snippets;
+    }
+
+    /// Append a message received for the open chat.
+    fn append_message(&mut self, message: ChatMessage, sender: &AsyncComponentSender<Self>) {
+        // Count the messages the user is missing while scrolled up.
+        if !self.state.is_at_bottom && !message.outgoing {
+            self.state.new_message_count = self.state.new_message_count.saturating_add(1);
+        }
+
+        // If the bottom has been trimmed, skip appending — the message will
+        // appear when the user scrolls back to bottom and triggers a reload.
+        if self.state.bottom_trimmed {
+            return;
+        }
+
+        // Convert to local date for separator comparison.
+        let msg_date = message.timestamp.with_timezone(&Local).date_naive();
+
+        // Insert a date separator if the date changed.
+        if self.state.last_message_date != Some(msg_date) {
+            self.list_view_wrapper
+                .append(ChatRow::DateSeparator(msg_date));
+            self.row_metadata
+                .push_back(RowMetadata::Separator(msg_date));
+            self.state.last_message_date = Some(msg_date);
+        }
+
+        // Update newest loaded timestamp to this message.
+        let ts = message.timestamp.timestamp();
+        self.state.newest_loaded_timestamp = Some(ts);
+
+        self.row_metadata.push_back(RowMetadata::from(&message));
+        self.list_view_wrapper.append(ChatRow::from(message));
+
+        // If the user is at the bottom, they're seeing this message — mark read.
+        if self.state.is_at_bottom
+            && let Some(ref chat) = self.chat
+        {
+            let _ = sender.output(ChatViewOutput::MarkChatRead(chat.jid.clone()));
+        }
+    }
+
+    /// Insert a message received late before the row at `position`, after
+    /// a new date separator if `separator` is set.
+    fn insert_message(&mut self, position: usize, separator: bool, message: ChatMessage) {
+        let date = message.timestamp.with_timezone(&Local).date_naive();
+        let Ok(mut index) = u32::try_from(position) else {
+            return;
+        };
+
+        if separator {
+            self.list_view_wrapper
+                .insert(index, ChatRow::DateSeparator(date));
+            self.row_metadata
+                .insert(index as usize, RowMetadata::Separator(date));
+            index += 1;
+        }
+
+        // It may be the oldest message loaded.
+        if !self
+            .row_metadata
+            .range(..index as usize)
+            .any(|row| matches!(row, RowMetadata::Message { .. }))
+        {
+            self.state.first_message_date = Some(date);
+            self.state.oldest_loaded_timestamp = Some(message.timestamp.timestamp());
+        }
+
+        self.row_metadata
+            .insert(index as usize, RowMetadata::from(&message));
+        self.list_view_wrapper
+            .insert(index, ChatRow::from(message));
+    }
+
+    /// Start or stop selecting messages, clearing the selection when stopping.
+    fn set_selecting(&mut self, selecting: bool) {
+        self.state.selecting = selecting;
+        if !selecting {
+            self.state.selected.clear();
+        }
+        update_row_selection(selecting, &self.state.selected);
+    }
+
+    /// Get the title shown while selecting messages.
+    fn selection_title(&self) -> String {
+        if self.state.selected.is_empty() {
+            return i18n!("Select Messages");
+        }
+
+        let count = u32::try_from(self.state.selected.len()).unwrap_or(u32::MAX);
+        ni18n!("{0} Selected", "{0} Selected", count).replacen("{0}", &count.to_string(), 1)
+    }
+
+    /// Get the selected messages, oldest first.
+    fn selected_messages(&self) -> Vec<ChatMessage> {
+        self.list_view_wrapper
+            .iter()
+            .filter_map(|item| match &*item.borrow() {
+                ChatRow::Message(message) if self.state.selected.contains(&message.server_id) => {
+                    Some(message.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Remove loaded messages, along with the date separators left without any.
+    fn remove_messages(&mut self, msg_ids: &[String]) {
+        let positions = self
+            .list_view_wrapper
+            .iter()
+            .enumerate()
+            .filter_map(|(position, item)| {
+                matches!(&*item.borrow(), ChatRow::Message(message) if msg_ids.contains(&message.server_id))
+                    .then_some(position)
+            })
+            .collect::<Vec<_>>();
+        for position in positions.into_iter().rev() {
+            self.remove_row(position);
+        }
+
+        // A separator followed by another one has no messages left. So does the
+        // last one, unless the messages after it are trimmed.
+        let mut next_is_separator = !self.state.bottom_trimmed;
+        for position in (0..self.row_metadata.len()).rev() {
+            let is_separator = matches!(self.row_metadata[position], RowMetadata::Separator(_));
+            if is_separator && next_is_separator {
+                self.remove_row(position);
+            } else {
+                next_is_separator = is_separator;
+            }
+        }
+
+        if !self.state.bottom_trimmed {
+            self.state.last_message_date =
+                self.row_metadata.iter().rev().find_map(|row| match row {
+                    RowMetadata::Separator(date) => Some(*date),
+                    RowMetadata::Message { .. } => None,
+                });
+        }
+    }
+
+    /// Remove a row, keeping its metadata in step.
+    fn remove_row(&mut self, position: usize) {
+        if let Ok(index) = u32::try_from(position) {
+            self.list_view_wrapper.remove(index);
+            self.row_metadata.remove(position);
+        }
+    }
+
+    /// Show when the history was cleared above the oldest message, once it's loaded.
+    fn show_cleared_boundary(&mut self) {
+        let Some(cleared_before) = self.state.cleared_before else {
+            return;
+        };
+        if self.state.has_more_messages {
+            return;
+        }
+
+        let date = format_date(cleared_before.with_timezone(&Local).date_naive());
+        let text = i18n_f!("History cleared on {}", date);
+        let shown = self.list_view_wrapper.get(0).is_some_and(
+            |item| matches!(item.borrow().deref(), ChatRow::ServiceEvent { text: shown } if *shown == text),
+        );
+        if !shown {
+            self.list_view_wrapper
+                .insert(0, ChatRow::ServiceEvent { text });
+            self.row_metadata.push_front(RowMetadata::Message {
+                timestamp: cleared_before.timestamp(),
+                id: String::new(),
+            });
+        }
+    }
+
+    /// Get the position of a loaded message's row.
+    fn message_position(&self, msg_id: &str) -> Option<usize> {
+        self.row_metadata
+            .iter()
+            .position(|meta| matches!(meta, RowMetadata::Message { id, .. } if id == msg_id))
+    }
+
+    /// Scroll to a loaded message and flash its bubble.
+    fn highlight_message(&mut self, msg_id: &str) {
+        let Some(position) = self.message_position(msg_id) else {
+            return;
+        };
+
+        HIGHLIGHTED_MESSAGE.set(Some(msg_id.to_owned()));
+        self.replace_message(msg_id, |_| {});
+
+        if let Ok(position) = u32::try_from(position) {
+            let info = gtk::ScrollInfo::new();
+            info.set_enable_vertical(true);
+            self.list_view_wrapper.view.scroll_to(
+                position,
+                gtk::ListScrollFlags::FOCUS,
+                Some(info),
+            );
+        }
+
+        // Rows bound after the flash ended show the message as usual.
+        let msg_id = msg_id.to_owned();
+        glib::timeout_add_local_once(HIGHLIGHT_DURATION, move || {
+            HIGHLIGHTED_MESSAGE.with_borrow_mut(|highlighted| {
+                if highlighted.as_ref() == Some(&msg_id) {
+                    *highlighted = None;
+                }
+            });
+        });
+    }
+
+    /// Checks if a message is loaded.
+    fn has_message(&self, msg_id: &str) -> bool {
+        self.list_view_wrapper.iter().any(
+            |item| matches!(item.borrow().deref(), ChatRow::Message(message) if message.server_id == msg_id),
+        )
+    }
+
+    /// Update a loaded message and replace its row so it's bound again.
+    fn replace_message(&mut self, msg_id: &str, update: impl FnOnce(&mut ChatMessage)) {
+        let position = self.list_view_wrapper.iter().position(
+            |item| matches!(item.borrow().deref(), ChatRow::Message(message) if message.server_id == msg_id),
+        );
+
+        if let Some(position) = position.and_then(|p| u32::try_from(p).ok())
+            && let Some(item) = self.list_view_wrapper.get(position)
+        {
+            let row = item.borrow().clone();
+            if let ChatRow::Message(mut message) = row {
+                update(&mut message);
+
+                self.list_view_wrapper.remove(position);
+                self.list_view_wrapper
+                    .insert(position, ChatRow::Message(message));
+            }
+        }
+    }
+
+    /// Update the user presence.
+    fn update_presence(&mut self) {
+        if let Some(ref mut chat) = self.chat {
+            if chat.is_group() {
+                // Only some participants are loaded in large groups, prefer the synced count.
+                let count = chat
+                    .participant_count
+                    .unwrap_or_else(|| u32::try_from(chat.participants.len()).unwrap_or(u32::MAX));
+                self.state.presence = (count > 0).then(|| {
+                    ni18n!("{0} participant", "{0} participants", count).replacen(
+                        "{0}",
+                        &count.to_string(),
+                        1,
+                    )
+                });
+            } else if chat.available.unwrap_or_default() {
+                self.state.presence = Some(i18n!("online"));
+            } else if let Some(last_seen) = chat.last_seen {
+                let today = Local::now().date_naive();
+                let last_date = last_seen.with_timezone(&Local).date_naive();
+
+                let presence = if last_date == today {
+                    format!(
+                        "{} {} {} {}",
+                        i18n!("Last seen"),
+                        i18n!("today"),
+                        i18n!("at"),
+                        last_date.format("%H:%M")
+                    )
+                } else if let Some(yesterday) = today.pred_opt()
+                    && last_date == yesterday
+                {
+                    format!(
+                        "{} {} {} {}",
+                        i18n!("Last seen"),
+                        i18n!("yesterday"),
+                        i18n!("at"),
+                        last_date.format("%H:%M")
+                    )
+                } else {
+                    format!(
+                        "{} {} {} {}",
+                        i18n!("Last seen"),
+                        last_date.format("%d/%m"),
+                        i18n!("at"),
+                        last_date.format("%H:%M")
+                    )
+                };
+                self.state.presence = Some(presence);
+            }
+        }
+    }
+
+    /// Update bottom cursors (`newest_loaded_timestamp`, `last_message_date`)
+    /// from the `row_metadata` after trimming rows from the bottom.
+    fn update_bottom_cursors(&mut self) {
+        self.state.last_message_date = None;
+        self.state.newest_loaded_timestamp = None;
+
+        // Walk backward through metadata to find the newest message and last date.
+        for meta in self.row_metadata.iter().rev() {
+            match meta {
+                RowMetadata::Message { timestamp: ts, .. } => {
+                    if self.state.newest_loaded_timestamp.is_none() {
+                        self.state.newest_loaded_timestamp = Some(*ts);
+                    }
+                }
+                RowMetadata::Separator(date) => {
+                    if self.state.last_message_date.is_none() {
+                        self.state.last_message_date = Some(*date);
+                    }
+                }
+            }
+
+            // Stop once both cursors are found.
+            if self.state.newest_loaded_timestamp.is_some()
+                && self.state.last_message_date.is_some()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Update top cursors (`oldest_loaded_timestamp`, `first_message_date`)
+    /// from the `row_metadata` after trimming rows from the top.
+    fn update_top_cursors(&mut self) {
+        self.state.first_message_date = None;
+        self.state.oldest_loaded_timestamp = None;
+
+        // Walk forward through metadata to find the oldest message and first date.
+        for meta in &self.row_metadata {
+            match meta {
+                RowMetadata::Message { timestamp: ts, .. } => {
+                    if self.state.oldest_loaded_timestamp.is_none() {
+                        self.state.oldest_loaded_timestamp = Some(*ts);
+                    }
+                }
+                RowMetadata::Separator(date) => {
+                    if self.state.first_message_date.is_none() {
+                        self.state.first_message_date = Some(*date);
+                    }
+                }
+            }
+
+            // Stop once both cursors are found.
+            if self.state.oldest_loaded_timestamp.is_some()
+                && self.state.first_message_date.is_some()
+            {
+                break;
+            }
+        }
+
+        // We trimmed from top, so there are definitely older messages to load.
+        self.state.has_more_messages = true;
+    }
+}
+
+/// Get the local date of a Unix timestamp.
+fn local_date(timestamp: i64) -> Option<NaiveDate> {
+    DateTime::from_timestamp(timestamp, 0).map(|t| t.with_timezone(&Local).date_naive())
+}
+
+/// Get the Unix timestamp of the start of a local day.
+fn local_day_start(date: NaiveDate) -> Option<i64> {
+    date.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|t| t.timestamp())
+}
+
+/// Get the Unix timestamps of the start of a local month and of the next
+/// one, with `month0` counted from 0 like in `gtk::Calendar`.
+fn month_bounds(year: i32, month0: i32) -> Option<(i64, i64)> {
+    let first = NaiveDate::from_ymd_opt(year, u32::try_from(month0).ok()? + 1, 1)?;
+    let next = first.checked_add_months(Months::new(1))?;
+
+    local_day_start(first).zip(local_day_start(next))
+}
+
+/// Get the date of the topmost visible row, from the name of its widget.
+fn top_visible_date(list_view: &gtk::ListView) -> Option<NaiveDate> {
+    let widget = list_view.pick(
+        f64::from(list_view.width()) / 2.0,
+        1.0,
+        gtk::PickFlags::DEFAULT,
+    )?;
+
+    std::iter::successors(Some(widget), gtk::Widget::parent)
+        .take_while(|widget| widget != list_view.upcast_ref::<gtk::Widget>())
+        .find_map(|widget| widget.widget_name().parse().ok())
+}
+
+/// Get the date selected in a calendar.
+fn calendar_date(calendar: &gtk::Calendar) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(
+        calendar.year(),
+        u32::try_from(calendar.month() + 1).ok()?,
+        u32::try_from(calendar.day()).ok()?,
+    )
+}
+
+/// Get the formatted phone number of a private chat whose contact has no
+/// name in the address book.
+async fn unknown_phone_number(chat: &Chat) -> Option<String> {
+    if !chat.is_private() {
+        return None;
+    }
+
+    match chat.db.get_contact(&chat.jid).await {
+        Ok(Some(contact)) if contact.name.is_some_and(|name| !name.is_empty()) => None,
+        Ok(_) => Some(format_lid_as_number(&chat.jid)),
+        Err(e) => {
+            tracing::error!("Failed to load contact {}: {e}", chat.jid);
+            None
+        }
+    }
+}
+
+/// Get the name of who sent a message, as shown to the user.
+fn sender_name(message: &ChatMessage) -> String {
+    if message.outgoing {
+        i18n!("You")
+    } else {
+        message
+            .sender_name
+            .clone()
+            .unwrap_or_else(|| format_jid(&message.sender_jid))
+    }
+}
+
+/// Get the label screen readers announce for a message, like "Maria, 14:32: Hello".
+fn accessible_label(message: &ChatMessage) -> String {
+    let sender = sender_name(message);
+    let time = message
+        .timestamp
+        .with_timezone(&Local)
+        .format("%H:%M")
+        .to_string();
+
+    let content = if message.revoked {
+        i18n!("This message was deleted")
+    } else if let Some(ref location) = message.location {
+        location.name.clone().unwrap_or_else(|| i18n!("Location"))
+    } else {
+        match message.media {
+            Some(ref media) if message.content.is_empty() => media.r#type.display_label(),
+            Some(ref media) => format!("{}, {}", media.r#type.display_label(), message.content),
+            None => message.content.clone(),
+        }
+    };
+
+    // Translators: a message read by screen readers, as "sender, time: content".
+    i18n!("{0}, {1}: {2}")
+        .replacen("{0}", &sender, 1)
+        .replacen("{1}", &time, 1)
+        .replacen("{2}", &content, 1)
+}
+
+/// Get the name of who sent a quoted message, as shown to the user.
+fn quote_sender_name(quote: &Quote) -> String {
+    if quote.outgoing {
+        i18n!("You")
+    } else {
+        quote
+            .sender_name
+            .clone()
+            .unwrap_or_else(|| format_jid(&quote.sender_jid))
+    }
+}
+
+/// Get a one-line preview of a message, for the pinned and reply bars.
+fn message_preview(message: &ChatMessage) -> String {
+    match message.media {
+        Some(ref media) if message.content.is_empty() => media.r#type.display_label(),
+        _ => message.content.replace('\n', " "),
+    }
+}
+
+/// Metadata for a single row in the message list, used for cursor tracking
 /// when trimming rows during bidirectional pagination.
 #[derive(Clone, Debug)]
 enum RowMetadata {
-    /// A message row, with its Unix timestamp.
-    Message(i64),
+    /// A message row, with its Unix timestamp and server ID.
+    Message { timestamp: i64, id: String },
     /// A date separator row.
     Separator(NaiveDate),
 }
 
+impl From<&ChatMessage> for RowMetadata {
+    fn from(message: &ChatMessage) -> Self {
+        Self::Message {
+            timestamp: message.timestamp.timestamp(),
+            id: message.server_id.clone(),
+        }
+    }
+}
+
+/// Where a received message goes among the loaded rows.
+#[derive(Debug, PartialEq, Eq)]
+enum Placement {
+    /// After every loaded message.
+    End,
+    /// Before the row at the index, after a new date separator if set.
+    Before(usize, bool),
+}
+
+/// Find where a received message dated `date` goes among the loaded `rows`,
+/// after the last message that isn't newer. `None` if it's already loaded,
+/// or older than them all while `has_older` ones will be loaded with it.
+fn place_received(
+    rows: &VecDeque<RowMetadata>,
+    message: &ChatMessage,
+    date: NaiveDate,
+    has_older: bool,
+) -> Option<Placement> {
+    let timestamp = message.timestamp.timestamp();
+    if !message.server_id.is_empty()
+        && rows
+            .iter()
+            .any(|row| matches!(row, RowMetadata::Message { id, .. } if *id == message.server_id))
+    {
+        return None;
+    }
+
+    let position = rows
+        .iter()
+        .rposition(
+            |row| matches!(row, RowMetadata::Message { timestamp: t, .. } if *t <= timestamp),
+        )
+        .map_or(0, |i| i + 1);
+    if position == rows.len() {
+        return Some(Placement::End);
+    }
+    if position == 0 && has_older {
+        return None;
+    }
+
+    // Join the day of the rows around it, or start its own.
+    let day = rows.range(..position).rev().find_map(|row| match row {
+        RowMetadata::Separator(day) => Some(*day),
+        RowMetadata::Message { .. } => None,
+    });
+    if day == Some(date) {
+        Some(Placement::Before(position, false))
+    } else if matches!(rows.get(position), Some(RowMetadata::Separator(next)) if *next == date) {
+        Some(Placement::Before(position + 1, false))
+    } else {
+        Some(Placement::Before(position, true))
+    }
+}
+
+/// Selection of messages as seen by the rows, which are recycled between
+/// messages and can't reach the chat view.
+#[derive(Debug, Default)]
+struct RowSelection {
+    /// IDs of the selected messages.
+    ids: HashSet<String>,
+    /// Whether messages are being selected.
+    active: bool,
+    /// Check boxes of the rows created so far, with the message they show.
+    checks: Vec<(
+        glib::WeakRef<gtk::CheckButton>,
+        Rc<RefCell<Option<(String, String)>>>,
+    )>,
+}
+
+thread_local! {
+    static ROW_SELECTION: RefCell<RowSelection> = RefCell::new(RowSelection::default());
+    /// Message jumped to from a quote, flashed when its row is bound.
+    static HIGHLIGHTED_MESSAGE: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// Connected user's JID, marking their own reactions.
+    static USER_JID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Show the selection on the rows, as it changed.
+fn update_row_selection(active: bool, ids: &HashSet<String>) {
+    ROW_SELECTION.with_borrow_mut(|selection| {
+        selection.active = active;
+        selection.ids.clone_from(ids);
+        selection.checks.retain(|(check, message)| {
+            let Some(check) = check.upgrade() else {
+                return false;
+            };
+            let message = message.borrow();
+            check.set_visible(active && message.is_some());
+            check.set_active(message.as_ref().is_some_and(|(_, id)| ids.contains(id)));
+            true
+        });
+    });
+}
+
 /// A single row in the chat history list.
 #[derive(Clone, Debug)]
 #[allow(clippy::large_enum_variant)]
@@ -909,23 +2989,127 @@ pub enum ChatRow {
     ServiceEvent { text: String },
 }
 
+impl From<ChatMessage> for ChatRow {
+    fn from(message: ChatMessage) -> Self {
+        if message.kind == MessageKind::Service {
+            Self::ServiceEvent {
+                text: message.content,
+            }
+        } else {
+            Self::Message(message)
+        }
+    }
+}
+
 pub struct ChatRowWidgets {
+    /// Poll options, with their votes.
+    poll_box: gtk::Box,
+    /// Voice message player, with its play button, progress and duration.
+    voice_box: gtk::Box,
     /// The message bubble itself.
     bubble_box: gtk::Box,
+    /// "Live location" badge.
+    live_label: gtk::Label,
+    /// Text content next to its time and status.
+    content_box: gtk::Box,
     /// Outer container for message bubbles.
     message_box: gtk::Box,
+    /// Text of the quoted message.
+    quote_label: gtk::Label,
     /// Message status icon (e.g. "Sending", "Sent").
     status_icon: gtk::Image,
+    /// Shared contact cards.
+    contacts_box: gtk::Box,
+    /// Label marking edited messages.
+    edited_label: gtk::Label,
+    /// Image preview, opening the image viewer when clicked.
+    image_button: gtk::Button,
+    /// Actions on the message, shown on right click or long press.
+    message_menu: gio::Menu,
+    /// Message quoted by a reply, jumping to it when clicked.
+    quote_button: gtk::Button,
+    /// Check box showing if the message is selected, while selecting.
+    select_check: gtk::CheckButton,
     /// Sender name label (visible in group chats for incoming messages).
     sender_label: gtk::Label,
+    /// Button playing or pausing the voice message.
+    voice_button: gtk::Button,
+    /// Location address.
+    address_label: gtk::Label,
     /// Message text content.
     content_label: gtk::Label,
+    /// Image preview picture.
+    image_picture: gtk::Picture,
+    /// Reaction pills, one per emoji.
+    reactions_box: gtk::FlowBox,
     /// Service event label (e.g. "someone added xxx").
     service_label: gtk::Label,
+    /// Loudness bars of the voice message, filled as it plays.
+    waveform_area: gtk::DrawingArea,
+    /// Location place name.
+    location_label: gtk::Label,
+    /// Link preview card, opening the link when clicked.
+    preview_button: gtk::Button,
+    /// Thumbnails being decoded for the message shown.
+    thumbnail_keys: Rc<RefCell<Vec<String>>>,
+    /// Voice message shown, and its playback.
+    voice_playback: Rc<RefCell<VoicePlayback>>,
+    /// Progress shown for voice messages without a waveform.
+    voice_progress: gtk::ProgressBar,
+    /// Caption marking messages sent automatically by businesses.
+    automated_label: gtk::Label,
+    /// Downloads the media of the message, showing its size.
+    download_button: gtk::Button,
+    /// Location card, opening the maps app when clicked.
+    location_button: gtk::Button,
+    /// Thumbnail of the linked page.
+    preview_picture: gtk::Picture,
     /// Date separator label (e.g. "Today", "Yesterday").
     separator_label: gtk::Label,
     /// Timestamp label (e.g. "14:30").
     timestamp_label: gtk::Label,
+    /// Location map thumbnail.
+    location_picture: gtk::Picture,
+    /// Popover listing who reacted, while open.
+    reactions_popover: Rc<RefCell<Option<gtk::Popover>>>,
+    /// Who sent the quoted message.
+    quote_sender_label: gtk::Label,
+    /// Chat and server ID of the message shown, if it can be selected.
+    selectable_message: Rc<RefCell<Option<(String, String)>>>,
+    /// Title of the linked page.
+    preview_title_label: gtk::Label,
+    /// Domain of the link.
+    preview_domain_label: gtk::Label,
+    /// Duration of the voice message.
+    voice_duration_label: gtk::Label,
+    /// Description of the linked page.
+    preview_description_label: gtk::Label,
+}
+
+/// Voice message shown in a row, and its playback.
+#[derive(Debug, Default)]
+struct VoicePlayback {
+    /// Audio of the message, if it has been downloaded.
+    data: Option<Arc<Vec<u8>>>,
+    /// Stream playing the audio, once started.
+    stream: Option<gtk::MediaFile>,
+    /// Loudness samples, from 0 to 100.
+    waveform: Vec<u8>,
+}
+
+impl VoicePlayback {
+    /// Get how much of the audio has been played, from 0 to 1.
+    fn progress(&self) -> f64 {
+        self.stream
+            .as_ref()
+            .filter(|stream| stream.duration() > 0)
+            .map_or(0.0, |stream| {
+                // Count in ten-thousandths, which fit losslessly in a float.
+                let played = (stream.timestamp().saturating_mul(10_000) / stream.duration())
+                    .clamp(0, 10_000);
+                f64::from(u16::try_from(played).unwrap_or_default()) / 10_000.0
+            })
+    }
 }
 
 impl RelmListItem for ChatRow {
@@ -934,7 +3118,9 @@ impl RelmListItem for ChatRow {
 
     fn setup(_list_item: &gtk::ListItem) -> (Self::Root, Self::Widgets) {
         // Root container stacks all row variants vertically.
+        // Labeled on bind, for screen readers to announce the row as a whole.
         let root = gtk::Box::builder()
+            .accessible_role(gtk::AccessibleRole::Group)
             .orientation(gtk::Orientation::Vertical)
             .build();
 
@@ -965,6 +3151,15 @@ impl RelmListItem for ChatRow {
             .orientation(gtk::Orientation::Horizontal)
             .build();
 
+        // Shown while selecting messages, clicks on the row toggle it.
+        let select_check = gtk::CheckButton::builder()
+            .valign(gtk::Align::Center)
+            .margin_start(6)
+            .can_target(false)
+            .visible(false)
+            .build();
+        message_box.append(&select_check);
+
         let bubble_box = gtk::Box::builder()
             .spacing(2)
             .orientation(gtk::Orientation::Vertical)
@@ -978,6 +3173,176 @@ impl RelmListItem for ChatRow {
             .build();
         bubble_box.append(&sender_label);
 
+        let automated_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .label(i18n!("Automated message"))
+            .css_classes(["dimmed", "caption"])
+            .visible(false)
+            .build();
+        bubble_box.append(&automated_label);
+
+        // Quoted message of a reply.
+        let quote_box = gtk::Box::builder()
+            .spacing(2)
+            .orientation(gtk::Orientation::Vertical)
+            .build();
+
+        let quote_sender_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .ellipsize(pango::EllipsizeMode::End)
+            .css_classes(["accent", "caption-heading"])
+            .build();
+        quote_box.append(&quote_sender_label);
+
+        let quote_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .xalign(0.0)
+            .lines(2)
+            .wrap(true)
+            .wrap_mode(pango::WrapMode::WordChar)
+            .ellipsize(pango::EllipsizeMode::End)
+            .css_classes(["caption"])
+            .build();
+        quote_box.append(&quote_label);
+
+        let quote_button = gtk::Button::builder()
+            .child(&quote_box)
+            .action_name("app.go-to-quoted")
+            .tooltip_text(i18n!("Go to Original Message"))
+            .css_classes(["flat", "quote-card"])
+            .visible(false)
+            .build();
+        bubble_box.append(&quote_button);
+
+        // Location card.
+        let location_box = gtk::Box::builder()
+            .spacing(2)
+            .orientation(gtk::Orientation::Vertical)
+            .build();
+
+        let location_picture = gtk::Picture::builder()
+            .width_request(240)
+            .height_request(120)
+            .content_fit(gtk::ContentFit::Cover)
+            .css_classes(["location-thumbnail"])
+            .build();
+        location_box.append(&location_picture);
+
+        let location_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .xalign(0.0)
+            .wrap(true)
+            .wrap_mode(pango::WrapMode::WordChar)
+            .css_classes(["heading"])
+            .build();
+        location_box.append(&location_label);
+
+        let address_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .xalign(0.0)
+            .wrap(true)
+            .wrap_mode(pango::WrapMode::WordChar)
+            .css_classes(["dimmed", "caption"])
+            .build();
+        location_box.append(&address_label);
+
+        let live_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .label(i18n!("Live location"))
+            .css_classes(["accent", "caption-heading"])
+            .build();
+        location_box.append(&live_label);
+
+        let location_button = gtk::Button::builder()
+            .child(&location_box)
+            .action_name("app.open-location")
+            .tooltip_text(i18n!("Open in Maps"))
+            .css_classes(["flat", "location-card"])
+            .visible(false)
+            .build();
+        bubble_box.append(&location_button);
+
+        let image_picture = gtk::Picture::builder()
+            .width_request(240)
+            .height_request(180)
+            .content_fit(gtk::ContentFit::Cover)
+            .css_classes(["image-thumbnail"])
+            .build();
+
+        let image_button = gtk::Button::builder()
+            .child(&image_picture)
+            .action_name("app.view-image")
+            .tooltip_text(i18n!("View Image"))
+            .css_classes(["flat", "image-card"])
+            .visible(false)
+            .build();
+        bubble_box.append(&image_button);
+
+        // Voice message player.
+        let voice_playback = Rc::new(RefCell::new(VoicePlayback::default()));
+        let voice_box = gtk::Box::builder()
+            .spacing(6)
+            .orientation(gtk::Orientation::Horizontal)
+            .css_classes(["voice-player"])
+            .visible(false)
+            .build();
+        let voice_button = gtk::Button::builder()
+            .valign(gtk::Align::Center)
+            .icon_name(icon_or_fallback("media-playback-start-symbolic", "media-playback-start"))
+            .tooltip_text(i18n!("Play"))
+            .css_classes(["circular", "flat"])
+            .build();
+        voice_box.append(&voice_button);
+
+        let waveform_area = gtk::DrawingArea::builder()
+            .hexpand(true)
+            .content_width(160)
+            .content_height(28)
+            .valign(gtk::Align::Center)
+            .build();
+        let playback = Rc::clone(&voice_playback);
+        waveform_area.set_draw_func(move |area, cr, width, height| {
+            let playback = playback.borrow();
+            draw_waveform(
+                area,
+                cr,
+                width,
+                height,
+                &playback.waveform,
+                playback.progress(),
+            );
+        });
+        voice_box.append(&waveform_area);
+
+        let voice_progress = gtk::ProgressBar::builder()
+            .hexpand(true)
+            .width_request(160)
+            .valign(gtk::Align::Center)
+            .build();
+        voice_box.append(&voice_progress);
+
+        let voice_duration_label = gtk::Label::builder()
+            .css_classes(["caption", "numeric", "dimmed"])
+            .build();
+        voice_box.append(&voice_duration_label);
+        bubble_box.append(&voice_box);
+
+        // Media not fetched yet, downloaded on request.
+        let download_button = gtk::Button::builder()
+            .action_name("app.download-media")
+            .halign(gtk::Align::Start)
+            .css_classes(["pill"])
+            .visible(false)
+            .build();
+        bubble_box.append(&download_button);
+
+        let playback = Rc::clone(&voice_playback);
+        let area = waveform_area.downgrade();
+        let progress = voice_progress.downgrade();
+        voice_button.connect_clicked(move |button| {
+            toggle_voice_playback(&playback, button, &area, &progress);
+        });
+
         let content_box = gtk::Box::builder()
             .spacing(12)
             .orientation(gtk::Orientation::Horizontal)
@@ -1003,57 +3368,467 @@ impl RelmListItem for ChatRow {
             .orientation(gtk::Orientation::Horizontal)
             .build();
 
-        let timestamp_label = gtk::Label::builder()
-            .css_classes(["dimmed", "caption", "numeric"])
+        let edited_label = gtk::Label::builder()
+            .label(i18n!("Edited"))
+            .css_classes(["dimmed", "caption"])
+            .visible(false)
+            .build();
+        time_status_box.append(&edited_label);
+
+        let timestamp_label = gtk::Label::builder()
+            .css_classes(["dimmed", "caption", "numeric"])
+            .build();
+        time_status_box.append(&timestamp_label);
+
+        let status_icon = gtk::Image::builder()
+            .pixel_size(12)
+            .css_classes(["dimmed", "status-icon"])
+            .build();
+        time_status_box.append(&status_icon);
+
+        content_box.append(&time_status_box);
+        bubble_box.append(&content_box);
+
+        // Link preview card.
+        let preview_box = gtk::Box::builder()
+            .spacing(8)
+            .orientation(gtk::Orientation::Horizontal)
+            .build();
+
+        let preview_picture = gtk::Picture::builder()
+            .width_request(56)
+            .height_request(56)
+            .valign(gtk::Align::Center)
+            .content_fit(gtk::ContentFit::Cover)
+            .css_classes(["link-preview-thumbnail"])
+            .build();
+        preview_box.append(&preview_picture);
+
+        let preview_text_box = gtk::Box::builder()
+            .spacing(2)
+            .valign(gtk::Align::Center)
+            .orientation(gtk::Orientation::Vertical)
+            .build();
+
+        let preview_title_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .xalign(0.0)
+            .lines(2)
+            .wrap(true)
+            .wrap_mode(pango::WrapMode::WordChar)
+            .ellipsize(pango::EllipsizeMode::End)
+            .css_classes(["heading"])
+            .build();
+        preview_text_box.append(&preview_title_label);
+
+        let preview_description_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .xalign(0.0)
+            .lines(2)
+            .wrap(true)
+            .wrap_mode(pango::WrapMode::WordChar)
+            .ellipsize(pango::EllipsizeMode::End)
+            .css_classes(["caption"])
+            .build();
+        preview_text_box.append(&preview_description_label);
+
+        let preview_domain_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .ellipsize(pango::EllipsizeMode::End)
+            .css_classes(["dimmed", "caption"])
+            .build();
+        preview_text_box.append(&preview_domain_label);
+        preview_box.append(&preview_text_box);
+
+        let preview_button = gtk::Button::builder()
+            .child(&preview_box)
+            .action_name("app.open-link")
+            .css_classes(["flat", "link-preview-card"])
+            .visible(false)
             .build();
-        time_status_box.append(&timestamp_label);
+        bubble_box.append(&preview_button);
 
-        let status_icon = gtk::Image::builder()
-            .pixel_size(12)
-            .css_classes(["dimmed", "status-icon"])
+        let contacts_box = gtk::Box::builder()
+            .spacing(6)
+            .orientation(gtk::Orientation::Vertical)
+            .css_classes(["contact-cards"])
+            .visible(false)
             .build();
-        time_status_box.append(&status_icon);
+        bubble_box.append(&contacts_box);
+
+        let poll_box = gtk::Box::builder()
+            .spacing(6)
+            .orientation(gtk::Orientation::Vertical)
+            .css_classes(["poll"])
+            .visible(false)
+            .build();
+        bubble_box.append(&poll_box);
+
+        let reactions_box = gtk::FlowBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .column_spacing(4)
+            .row_spacing(4)
+            .max_children_per_line(8)
+            .halign(gtk::Align::Start)
+            .css_classes(["reactions"])
+            .visible(false)
+            .build();
+        bubble_box.append(&reactions_box);
 
-        content_box.append(&time_status_box);
-        bubble_box.append(&content_box);
         message_box.append(&bubble_box);
         root.append(&message_box);
 
+        // Message actions, on right click or long press.
+        let message_menu = gio::Menu::new();
+        let popover = gtk::PopoverMenu::from_model(Some(&message_menu));
+        popover.set_has_arrow(false);
+        popover.set_parent(&bubble_box);
+
+        let menu = message_menu.clone();
+        let show_menu = move || {
+            if menu.n_items() > 0 {
+                popover.popup();
+            }
+        };
+
+        let click_gesture = gtk::GestureClick::builder()
+            .button(gdk::BUTTON_SECONDARY)
+            .build();
+        let show = show_menu.clone();
+        click_gesture.connect_pressed(move |_, _, _, _| show());
+        bubble_box.add_controller(click_gesture);
+
+        let long_press_gesture = gtk::GestureLongPress::new();
+        long_press_gesture.connect_pressed(move |_, _, _| show_menu());
+        bubble_box.add_controller(long_press_gesture);
+
+        // Select messages with Ctrl+click, or with any click while selecting.
+        let selectable_message = Rc::new(RefCell::new(None::<(String, String)>));
+        let select_gesture = gtk::GestureClick::builder()
+            .button(gdk::BUTTON_PRIMARY)
+            .propagation_phase(gtk::PropagationPhase::Capture)
+            .build();
+        let message = Rc::clone(&selectable_message);
+        select_gesture.connect_pressed(move |gesture, _, _, _| {
+            let selecting = ROW_SELECTION.with_borrow(|selection| selection.active);
+            let control = gesture
+                .current_event_state()
+                .contains(gdk::ModifierType::CONTROL_MASK);
+            let Some(target) = message.borrow().clone().filter(|_| selecting || control) else {
+                return;
+            };
+
+            gesture.set_state(gtk::EventSequenceState::Claimed);
+            if let Some(widget) = gesture.widget() {
+                let _ = widget.activate_action("app.select-message", Some(&target.to_variant()));
+            }
+        });
+        message_box.add_controller(select_gesture);
+
+        // Reply on double click. Bubbling up from the bubble's children lets
+        // the text handle it first, selecting a word when clicked on.
+        let reply_gesture = gtk::GestureClick::builder()
+            .button(gdk::BUTTON_PRIMARY)
+            .propagation_phase(gtk::PropagationPhase::Bubble)
+            .build();
+        let message = Rc::clone(&selectable_message);
+        let label = content_label.clone();
+        reply_gesture.connect_pressed(move |gesture, n_press, _, _| {
+            let selecting = ROW_SELECTION.with_borrow(|selection| selection.active);
+            if n_press != 2 || selecting || label.selection_bounds().is_some() {
+                return;
+            }
+            let Some(target) = message.borrow().clone() else {
+                return;
+            };
+
+            gesture.set_state(gtk::EventSequenceState::Claimed);
+            if let Some(widget) = gesture.widget() {
+                let _ = widget.activate_action("app.reply-message", Some(&target.to_variant()));
+            }
+        });
+        bubble_box.add_controller(reply_gesture);
+
+        // Reply on a short horizontal swipe on touchscreens.
+        let swipe_gesture = gtk::GestureSwipe::builder().touch_only(true).build();
+        let message = Rc::clone(&selectable_message);
+        swipe_gesture.connect_swipe(move |gesture, velocity_x, velocity_y| {
+            if velocity_x.abs() < SWIPE_REPLY_VELOCITY || velocity_x.abs() < velocity_y.abs() * 2.0
+            {
+                return;
+            }
+
+            if let Some(target) = message.borrow().clone()
+                && let Some(widget) = gesture.widget()
+            {
+                let _ = widget.activate_action("app.reply-message", Some(&target.to_variant()));
+            }
+        });
+        bubble_box.add_controller(swipe_gesture);
+
+        ROW_SELECTION.with_borrow_mut(|selection| {
+            selection
+                .checks
+                .push((select_check.downgrade(), Rc::clone(&selectable_message)));
+        });
+
         let widgets = ChatRowWidgets {
+            poll_box,
             bubble_box,
+            live_label,
+            content_box,
             message_box,
             status_icon,
+            contacts_box,
+            reactions_box,
+            image_button,
+            select_check,
+            edited_label,
+            message_menu,
             sender_label,
+            address_label,
+            image_picture,
             content_label,
             service_label,
+            location_label,
+            automated_label,
+            quote_button,
+            quote_label,
+            quote_sender_label,
+            location_button,
             separator_label,
             timestamp_label,
+            location_picture,
+            preview_button,
+            preview_picture,
+            preview_title_label,
+            preview_description_label,
+            preview_domain_label,
+            selectable_message,
+            reactions_popover: Rc::default(),
+            thumbnail_keys: Rc::default(),
+            voice_box,
+            voice_button,
+            download_button,
+            voice_progress,
+            waveform_area,
+            voice_duration_label,
+            voice_playback,
         };
 
         (root, widgets)
     }
 
-    fn bind(&mut self, widgets: &mut Self::Widgets, _root: &mut Self::Root) {
+    fn bind(&mut self, widgets: &mut Self::Widgets, root: &mut Self::Root) {
+        // Name the row after its date, to show it in the date pill.
+        root.set_widget_name(&match self {
+            Self::DateSeparator(date) => date.to_string(),
+            Self::Message(msg) => msg.timestamp.with_timezone(&Local).date_naive().to_string(),
+            Self::ServiceEvent { .. } => String::new(),
+        });
+
         // Hide all variants first, then show the active one.
+        *widgets.selectable_message.borrow_mut() = None;
+        widgets.thumbnail_keys.borrow_mut().clear();
+        widgets.select_check.set_visible(false);
         widgets.separator_label.set_visible(false);
         widgets.service_label.set_visible(false);
         widgets.message_box.set_visible(false);
 
+        root.reset_property(gtk::AccessibleProperty::Description);
+
         match self {
             Self::DateSeparator(date) => {
-                widgets.separator_label.set_label(&format_date_label(*date));
+                let label = format_date_label(*date);
+                root.update_property(&[gtk::accessible::Property::Label(&label)]);
+                widgets.separator_label.set_label(&label);
                 widgets.separator_label.set_visible(true);
                 widgets.separator_label.set_focusable(false);
             }
             Self::ServiceEvent { text } => {
+                root.update_property(&[gtk::accessible::Property::Label(text)]);
                 widgets.service_label.set_label(text);
                 widgets.service_label.set_visible(true);
                 widgets.service_label.set_focusable(false);
             }
             Self::Message(msg) => {
+                root.update_property(&[gtk::accessible::Property::Label(&accessible_label(msg))]);
+                if msg.edited && !msg.revoked {
+                    root.update_property(&[gtk::accessible::Property::Description(&i18n!(
+                        "Edited"
+                    ))]);
+                }
+
                 widgets.message_box.set_visible(true);
                 widgets.message_box.set_focusable(false);
-                widgets.content_label.set_label(&msg.content);
+
+                if msg.revoked {
+                    widgets
+                        .content_label
+                        .set_label(&i18n!("This message was deleted"));
+                    widgets.content_label.add_css_class("revoked");
+                    widgets.content_label.add_css_class("dimmed");
+                } else {
+                    widgets.content_label.set_label(&msg.content);
+                    widgets.content_label.remove_css_class("revoked");
+                    widgets.content_label.remove_css_class("dimmed");
+                }
+                widgets.edited_label.set_visible(msg.edited && !msg.revoked);
+
+                // Lay the text and its time out in the direction it's written in,
+                // keeping the time from overlapping right-to-left text.
+                let direction = text_direction(&widgets.content_label.label());
+                widgets.content_box.set_direction(direction);
+                widgets.content_label.set_direction(direction);
+                widgets
+                    .automated_label
+                    .set_visible(msg.kind == MessageKind::Automated);
+
+                widgets.quote_button.set_visible(false);
+                if let Some(ref quote) = msg.quote
+                    && !msg.revoked
+                {
+                    bind_quote(widgets, msg, quote);
+                }
+
+                let highlighted = HIGHLIGHTED_MESSAGE.with_borrow(|highlighted| {
+                    highlighted.as_ref() == Some(&msg.server_id) && !msg.server_id.is_empty()
+                });
+                if highlighted {
+                    widgets.bubble_box.add_css_class("highlighted");
+                } else {
+                    widgets.bubble_box.remove_css_class("highlighted");
+                }
+
+                // Messages not yet acknowledged by the server can't be referenced.
+                widgets.message_menu.remove_all();
+                if !msg.revoked && !msg.server_id.is_empty() {
+                    *widgets.selectable_message.borrow_mut() =
+                        Some((msg.chat_jid.clone(), msg.server_id.clone()));
+                    ROW_SELECTION.with_borrow(|selection| {
+                        widgets.select_check.set_visible(selection.active);
+                        widgets
+                            .select_check
+                            .set_active(selection.ids.contains(&msg.server_id));
+                    });
+
+                    let target = (msg.chat_jid.clone(), msg.server_id.clone()).to_variant();
+                    let mut items = vec![
+                        (i18n!("_Reply"), "app.reply-message"),
+                        (i18n!("_Select"), "app.select-message"),
+                        (i18n!("_Pin"), "app.pin-message"),
+                        (i18n!("_Bookmark Locally…"), "app.bookmark-message"),
+                    ];
+                    if msg.outgoing {
+                        items.push((i18n!("Message _Info"), "app.message-info"));
+                    }
+                    if let Some(media) = msg.media.as_ref().filter(|media| media.has_data()) {
+                        if matches!(media.r#type, MediaType::Image | MediaType::Sticker) {
+                            items.push((i18n!("_Copy Image"), "app.copy-image"));
+                        }
+                        items.push((i18n!("_Save As…"), "app.save-media"));
+                        if matches!(media.r#type, MediaType::Video | MediaType::Document) {
+                            items.push((i18n!("_Open With…"), "app.open-media-with"));
+                        }
+                    }
+
+                    for (label, action) in items {
+                        let item = gio::MenuItem::new(Some(&label), None);
+                        item.set_action_and_target_value(Some(action), Some(&target));
+                        widgets.message_menu.append_item(&item);
+                    }
+                }
+
+                // The location card shows the place name instead of the text.
+                widgets.location_button.set_visible(false);
+                widgets.content_label.set_visible(true);
+                if let Some(ref location) = msg.location
+                    && !msg.revoked
+                {
+                    bind_location(widgets, msg, location);
+                    widgets.content_label.set_visible(false);
+                    widgets.location_button.set_visible(true);
+                }
+
+                widgets.preview_button.set_visible(false);
+                if let Some(ref preview) = msg.preview
+                    && !msg.revoked
+                {
+                    bind_link_preview(widgets, msg, preview);
+                }
+
+                // Downloaded images and stickers show above their caption.
+                widgets.image_button.set_visible(false);
+                if let Some(ref media) = msg.media
+                    && matches!(media.r#type, MediaType::Image | MediaType::Sticker)
+                    && media.has_data()
+                    && !msg.revoked
+                {
+                    bind_image(widgets, msg, media);
+                    widgets.content_label.set_visible(!msg.content.is_empty());
+                }
+
+                // Voice messages show a player instead of their text.
+                bind_voice(widgets, msg.media.as_ref().filter(|_| !msg.revoked));
+                if widgets.voice_box.is_visible() {
+                    widgets.content_label.set_visible(!msg.content.is_empty());
+                }
+                bind_download(widgets, msg);
+
+                // Rebuild the contact cards, as their number varies between messages.
+                while let Some(child) = widgets.contacts_box.first_child() {
+                    widgets.contacts_box.remove(&child);
+                }
+                widgets.contacts_box.set_visible(false);
+                if let [first, rest @ ..] = msg.contacts.as_slice()
+                    && !msg.revoked
+                {
+                    widgets.contacts_box.append(&contact_card(first));
+                    if !rest.is_empty() {
+                        let rest_box = gtk::Box::builder()
+                            .spacing(6)
+                            .orientation(gtk::Orientation::Vertical)
+                            .build();
+                        for card in rest {
+                            rest_box.append(&contact_card(card));
+                        }
+
+                        let count = u32::try_from(rest.len()).unwrap_or(u32::MAX);
+                        let label = ni18n!("{0} more contact", "{0} more contacts", count)
+                            .replacen("{0}", &count.to_string(), 1);
+                        widgets.contacts_box.append(
+                            &gtk::Expander::builder()
+                                .label(label)
+                                .child(&rest_box)
+                                .build(),
+                        );
+                    }
+
+                    widgets.content_label.set_visible(false);
+                    widgets.contacts_box.set_visible(true);
+                }
+
+                // Rebuild the poll options, as their number varies between polls.
+                while let Some(child) = widgets.poll_box.first_child() {
+                    widgets.poll_box.remove(&child);
+                }
+                widgets.poll_box.set_visible(false);
+                if let Some(ref poll) = msg.poll
+                    && !msg.revoked
+                {
+                    fill_poll_box(&widgets.poll_box, msg, poll);
+                    widgets.poll_box.set_visible(true);
+                }
+
+                // Rebuild the reaction pills, closing the list of the previous message.
+                close_reactions_popover(&widgets.reactions_popover);
+                widgets.reactions_box.remove_all();
+                widgets.reactions_box.set_visible(false);
+                if !msg.reactions.is_empty() && !msg.revoked {
+                    fill_reactions_box(widgets, msg);
+                    widgets.reactions_box.set_visible(true);
+                }
+
                 // Convert UTC timestamp to local time for display
                 let local_time = msg.timestamp.with_timezone(&Local);
                 widgets
@@ -1096,7 +3871,7 @@ impl RelmListItem for ChatRow {
                     widgets.bubble_box.set_margin_start(6);
                     widgets.bubble_box.set_margin_end(60);
 
-                    if msg.chat_jid.ends_with("@g.us") {
+                    if ChatKind::from_jid(&msg.chat_jid) == ChatKind::Group {
                         if let Some(ref name) = msg.sender_name {
                             widgets.sender_label.set_label(name);
                             widgets.sender_label.set_visible(true);
@@ -1110,9 +3885,934 @@ impl RelmListItem for ChatRow {
                     widgets.status_icon.set_visible(false);
                 }
 
+                // A few emoji on their own show large, without the bubble.
+                let jumbo = !msg.revoked
+                    && msg.media.is_none()
+                    && msg.quote.is_none()
+                    && msg.preview.is_none()
+                    && is_jumbo_emoji(&msg.content);
+                if jumbo {
+                    widgets.bubble_box.remove_css_class("card");
+                    widgets.bubble_box.remove_css_class("incoming");
+                    widgets.bubble_box.remove_css_class("outgoing");
+                    widgets.bubble_box.add_css_class("jumbo-emoji");
+                    widgets.status_icon.remove_css_class("white");
+                } else {
+                    widgets.bubble_box.add_css_class("card");
+                    widgets.bubble_box.remove_css_class("jumbo-emoji");
+                }
+
                 widgets.bubble_box.set_margin_top(2);
                 widgets.bubble_box.set_margin_bottom(2);
             }
         }
     }
 }
+
+/// Show the player of a voice message, or hide it for other messages.
+fn bind_voice(widgets: &ChatRowWidgets, media: Option<&Media>) {
+    // The row is now showing another message, stop playing the previous one.
+    let mut playback = widgets.voice_playback.borrow_mut();
+    if let Some(stream) = playback.stream.take() {
+        stream.set_playing(false);
+    }
+    widgets
+        .voice_button
+        .set_icon_name(icon_or_fallback("media-playback-start-symbolic", "media-playback-start"));
+    widgets.voice_progress.set_fraction(0.0);
+
+    let Some(media) = media.filter(|media| matches!(media.r#type, MediaType::Audio)) else {
+        widgets.voice_box.set_visible(false);
+        return;
+    };
+
+    playback.data = media.has_data().then(|| Arc::clone(&media.data));
+    playback.waveform = media.waveform.clone().unwrap_or_default();
+    widgets.voice_box.set_visible(true);
+    widgets.voice_button.set_sensitive(playback.data.is_some());
+    widgets
+        .voice_button
+        .set_tooltip_text(Some(&if playback.data.is_some() {
+            i18n!("Play")
+        } else {
+            i18n!("Not Downloaded")
+        }));
+
+    // Messages without a waveform fall back to a plain progress bar.
+    widgets
+        .waveform_area
+        .set_visible(!playback.waveform.is_empty());
+    widgets
+        .voice_progress
+        .set_visible(playback.waveform.is_empty());
+    widgets.waveform_area.queue_draw();
+
+    let secs = media.durations_secs.unwrap_or_default();
+    widgets
+        .voice_duration_label
+        .set_label(&format!("{}:{:02}", secs / 60, secs % 60));
+    widgets
+        .voice_duration_label
+        .set_visible(media.durations_secs.is_some());
+}
+
+/// Play or pause the voice message of a row, starting its stream if needed.
+fn toggle_voice_playback(
+    playback: &Rc<RefCell<VoicePlayback>>,
+    button: &gtk::Button,
+    area: &glib::WeakRef<gtk::DrawingArea>,
+    progress: &glib::WeakRef<gtk::ProgressBar>,
+) {
+    let mut state = playback.borrow_mut();
+    if state.stream.is_none() {
+        let Some(data) = state.data.clone() else {
+            return;
+        };
+        let input = gio::MemoryInputStream::from_bytes(&glib::Bytes::from(data.as_slice()));
+        let stream = gtk::MediaFile::for_input_stream(&input);
+
+        // Fill the waveform, or the progress bar, as the audio plays.
+        let (area, progress, weak_playback) =
+            (area.clone(), progress.clone(), Rc::downgrade(playback));
+        stream.connect_timestamp_notify(move |_| {
+            if let Some(area) = area.upgrade() {
+                area.queue_draw();
+            }
+            if let (Some(progress), Some(playback)) = (progress.upgrade(), weak_playback.upgrade())
+                && let Ok(playback) = playback.try_borrow()
+            {
+                progress.set_fraction(playback.progress());
+            }
+        });
+
+        let button = button.downgrade();
+        stream.connect_playing_notify(move |stream| {
+            if let Some(button) = button.upgrade() {
+                let (icon, tooltip) = if stream.is_playing() {
+                    (
+                        icon_or_fallback("media-playback-pause-symbolic", "media-playback-pause"),
+                        i18n!("Pause"),
+                    )
+                } else {
+                    (
+                        icon_or_fallback("media-playback-start-symbolic", "media-playback-start"),
+                        i18n!("Play"),
+                    )
+                };
+                button.set_icon_name(icon);
+                button.set_tooltip_text(Some(&tooltip));
+            }
+        });
+
+        state.stream = Some(stream);
+    }
+
+    if let Some(stream) = &state.stream {
+        // Start over once the end has been reached.
+        if stream.is_ended() {
+            stream.seek(0);
+        }
+        stream.set_playing(!stream.is_playing());
+    }
+}
+
+/// Draw the loudness bars of a voice message, the played ones in full color.
+fn draw_waveform(
+    area: &gtk::DrawingArea,
+    cr: &gtk::cairo::Context,
+    width: i32,
+    height: i32,
+    waveform: &[u8],
+    progress: f64,
+) {
+    if waveform.is_empty() {
+        return;
+    }
+
+    let (width, height) = (f64::from(width), f64::from(height));
+    let count = u32::try_from(waveform.len()).unwrap_or(u32::MAX);
+    let step = width / f64::from(count);
+    let bar_width = (step * 0.6).max(1.0);
+    let color = area.color();
+
+    // Fill all the played bars at once, then all the others, to keep it cheap.
+    for played in [true, false] {
+        for (index, &sample) in (0..count).zip(waveform) {
+            let x = f64::from(index) * step;
+            if (x + bar_width / 2.0 <= width * progress) != played {
+                continue;
+            }
+
+            // Keep silent parts visible as thin bars.
+            let bar_height = (f64::from(sample.min(100)) / 100.0).max(0.1) * height;
+            cr.rectangle(x, (height - bar_height) / 2.0, bar_width, bar_height);
+        }
+
+        let alpha = if played { 1.0 } else { 0.35 };
+        cr.set_source_rgba(
+            f64::from(color.red()),
+            f64::from(color.green()),
+            f64::from(color.blue()),
+            f64::from(color.alpha()) * alpha,
+        );
+        let _ = cr.fill();
+    }
+}
+
+/// Show the message quoted by a reply above its text.
+fn bind_quote(widgets: &ChatRowWidgets, message: &ChatMessage, quote: &Quote) {
+    widgets.quote_button.set_action_target_value(Some(
+        &(message.chat_jid.clone(), quote.id.clone()).to_variant(),
+    ));
+    widgets
+        .quote_sender_label
+        .set_label(&quote_sender_name(quote));
+    widgets
+        .quote_sender_label
+        .set_visible(quote.outgoing || !quote.sender_jid.is_empty());
+
+    let content = if quote.content.is_empty() {
+        i18n!("Message")
+    } else {
+        quote.content.replace('\n', " ")
+    };
+    widgets.quote_label.set_label(&content);
+    widgets.quote_button.set_visible(true);
+}
+
+/// Show a location in the location card.
+fn bind_location(widgets: &ChatRowWidgets, message: &ChatMessage, location: &Location) {
+    widgets
+        .location_button
+        .set_action_target_value(Some(&location.geo_uri().to_variant()));
+
+    widgets
+        .location_picture
+        .set_visible(location.thumbnail.is_some());
+    if let Some(ref data) = location.thumbnail {
+        bind_thumbnail(
+            widgets,
+            &widgets.location_picture,
+            widgets.location_picture.upcast_ref(),
+            thumbnails::key("location", message, data),
+            data,
+        );
+    }
+
+    let name = location
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("{:.5}, {:.5}", location.latitude, location.longitude));
+    widgets.location_label.set_label(&name);
+
+    widgets
+        .address_label
+        .set_label(location.address.as_deref().unwrap_or_default());
+    widgets
+        .address_label
+        .set_visible(location.address.is_some());
+
+    widgets.live_label.set_visible(location.live);
+}
+
+/// Show the preview of a link in the link preview card.
+fn bind_link_preview(widgets: &ChatRowWidgets, message: &ChatMessage, preview: &LinkPreview) {
+    widgets
+        .preview_button
+        .set_action_target_value(Some(&preview.url.to_variant()));
+    widgets.preview_button.set_tooltip_text(Some(&preview.url));
+
+    widgets
+        .preview_picture
+        .set_visible(preview.thumbnail.is_some());
+    if let Some(ref data) = preview.thumbnail {
+        bind_thumbnail(
+            widgets,
+            &widgets.preview_picture,
+            widgets.preview_picture.upcast_ref(),
+            thumbnails::key("preview", message, data),
+            data,
+        );
+    }
+
+    widgets
+        .preview_title_label
+        .set_label(preview.title.as_deref().unwrap_or_default());
+    widgets
+        .preview_title_label
+        .set_visible(preview.title.is_some());
+    widgets
+        .preview_description_label
+        .set_label(preview.description.as_deref().unwrap_or_default());
+    widgets
+        .preview_description_label
+        .set_visible(preview.description.is_some());
+    widgets.preview_domain_label.set_label(preview.domain());
+
+    widgets.preview_button.set_visible(true);
+}
+
+/// Fill the image preview of a message.
+fn bind_image(widgets: &ChatRowWidgets, message: &ChatMessage, media: &Media) {
+    widgets.image_button.set_visible(true);
+    bind_thumbnail(
+        widgets,
+        &widgets.image_picture,
+        widgets.image_button.upcast_ref(),
+        thumbnails::key("image", message, &media.data),
+        &media.data,
+    );
+
+    widgets.image_button.set_action_target_value(Some(
+        &(message.chat_jid.clone(), message.server_id.clone()).to_variant(),
+    ));
+}
+
+/// Show the button downloading the media of a message with its size, while
+/// it hasn't been fetched.
+fn bind_download(widgets: &ChatRowWidgets, message: &ChatMessage) {
+    let size = message
+        .media
+        .as_ref()
+        .filter(|_| !message.revoked && !message.server_id.is_empty())
+        .and_then(Media::download_size);
+    widgets.download_button.set_visible(size.is_some());
+    let Some(size) = size else {
+        return;
+    };
+
+    widgets
+        .download_button
+        .set_label(&i18n_f!("Download ({0})", glib::format_size(size)));
+    widgets.download_button.set_action_target_value(Some(
+        &(message.chat_jid.clone(), message.server_id.clone()).to_variant(),
+    ));
+}
+
+/// Get the thumbnails shown for a message, keyed like when its row is bound.
+fn message_thumbnails(message: &ChatMessage) -> Vec<(String, Arc<Vec<u8>>)> {
+    if message.revoked {
+        return Vec::new();
+    }
+
+    let mut thumbnails = Vec::new();
+    if let Some(ref media) = message.media
+        && matches!(media.r#type, MediaType::Image | MediaType::Sticker)
+        && media.has_data()
+    {
+        let key = thumbnails::key("image", message, &media.data);
+        thumbnails.push((key, Arc::clone(&media.data)));
+    }
+    if let Some(data) = message.preview.as_ref().and_then(|p| p.thumbnail.as_ref()) {
+        thumbnails.push((thumbnails::key("preview", message, data), Arc::clone(data)));
+    }
+    if let Some(data) = message.location.as_ref().and_then(|l| l.thumbnail.as_ref()) {
+        thumbnails.push((thumbnails::key("location", message, data), Arc::clone(data)));
+    }
+
+    thumbnails
+}
+
+/// Decode the thumbnails of the first messages ahead of their rows, given
+/// nearest to the view first.
+fn prefetch_thumbnails<'a>(messages: impl IntoIterator<Item = &'a ChatMessage>) {
+    thumbnails::prefetch(
+        messages
+            .into_iter()
+            .take(PREFETCH_COUNT)
+            .flat_map(message_thumbnails)
+            .collect(),
+    );
+}
+
+/// Show a thumbnail in a picture, decoding it in the background unless it's
+/// cached. `container` is hidden if the thumbnail can't be decoded.
+fn bind_thumbnail(
+    widgets: &ChatRowWidgets,
+    picture: &gtk::Picture,
+    container: &gtk::Widget,
+    key: String,
+    data: &Arc<Vec<u8>>,
+) {
+    if let Some(texture) = thumbnails::cached(&key) {
+        picture.set_paintable(Some(&texture));
+        return;
+    }
+
+    picture.set_paintable(None::<&gdk::Paintable>);
+    widgets.thumbnail_keys.borrow_mut().push(key.clone());
+
+    // The row may be bound to another message by the time it's decoded.
+    let keys = Rc::clone(&widgets.thumbnail_keys);
+    let (picture, container, data) = (picture.downgrade(), container.downgrade(), Arc::clone(data));
+    relm4::spawn_local(async move {
+        let texture = thumbnails::load(key.clone(), data).await;
+        if !keys.borrow().contains(&key) {
+            return;
+        }
+        if let (Some(picture), Some(container)) = (picture.upgrade(), container.upgrade()) {
+            picture.set_paintable(texture.as_ref());
+            container.set_visible(texture.is_some());
+        }
+    });
+}
+
+/// Build a contact card with buttons to message and save the contact.
+fn contact_card(card: &ContactCard) -> gtk::Box {
+    let card_box = gtk::Box::builder()
+        .spacing(6)
+        .orientation(gtk::Orientation::Vertical)
+        .css_classes(["contact-card"])
+        .build();
+
+    let info_box = gtk::Box::builder()
+        .spacing(12)
+        .orientation(gtk::Orientation::Horizontal)
+        .build();
+    info_box.append(
+        &adw::Avatar::builder()
+            .size(40)
+            .text(&card.name)
+            .show_initials(true)
+            .build(),
+    );
+
+    let text_box = gtk::Box::builder()
+        .valign(gtk::Align::Center)
+        .spacing(2)
+        .orientation(gtk::Orientation::Vertical)
+        .build();
+    text_box.append(
+        &gtk::Label::builder()
+            .label(&card.name)
+            .xalign(0.0)
+            .wrap(true)
+            .wrap_mode(pango::WrapMode::WordChar)
+            .css_classes(["heading"])
+            .build(),
+    );
+
+    let phone_number = card.phone_number();
+    if let Some(ref phone_number) = phone_number {
+        text_box.append(
+            &gtk::Label::builder()
+                .label(phone_number)
+                .xalign(0.0)
+                .css_classes(["dimmed", "caption", "numeric"])
+                .build(),
+        );
+    }
+    info_box.append(&text_box);
+    card_box.append(&info_box);
+
+    let buttons_box = gtk::Box::builder()
+        .spacing(6)
+        .homogeneous(true)
+        .orientation(gtk::Orientation::Horizontal)
+        .build();
+
+    let message_button = gtk::Button::builder()
+        .label(i18n!("Message"))
+        .css_classes(["flat"])
+        .build();
+    let save_button = gtk::Button::builder()
+        .label(i18n!("Save"))
+        .css_classes(["flat"])
+        .build();
+
+    // Without a phone number there's no chat to open.
+    if let Some(jid) = card.jid() {
+        message_button.set_action_name(Some("app.message-contact"));
+        message_button
+            .set_action_target_value(Some(&(jid.clone(), card.name.clone()).to_variant()));
+
+        save_button.set_action_name(Some("app.save-contact"));
+        save_button.set_action_target_value(Some(
+            &(jid, card.name.clone(), phone_number.unwrap_or_default()).to_variant(),
+        ));
+    } else {
+        message_button.set_sensitive(false);
+        save_button.set_sensitive(false);
+    }
+
+    buttons_box.append(&message_button);
+    buttons_box.append(&save_button);
+    card_box.append(&buttons_box);
+
+    card_box
+}
+
+/// Add a row per poll option, voting for it when clicked.
+/// Checks if pressing `key` with `modifiers` in the message entry sends the
+/// message, unless an input method is composing text.
+fn sends_message(
+    shortcut: SendShortcut,
+    key: gdk::Key,
+    modifiers: gdk::ModifierType,
+    composing: bool,
+) -> bool {
+    matches!(key, gdk::Key::Return | gdk::Key::KP_Enter) && !composing && shortcut.sends(modifiers)
+}
+
+fn fill_poll_box(poll_box: &gtk::Box, msg: &ChatMessage, poll: &Poll) {
+    let hint = if poll.multi_select {
+        i18n!("Select one or more")
+    } else {
+        i18n!("Select one")
+    };
+    poll_box.append(
+        &gtk::Label::builder()
+            .label(hint)
+            .halign(gtk::Align::Start)
+            .css_classes(["dimmed", "caption"])
+            .build(),
+    );
+
+    let voters = u32::try_from(poll.voter_count()).unwrap_or(u32::MAX);
+    for option in &poll.options {
+        let votes = u32::try_from(poll.vote_count(option)).unwrap_or(u32::MAX);
+
+        let option_box = gtk::Box::builder()
+            .spacing(4)
+            .orientation(gtk::Orientation::Vertical)
+            .build();
+
+        let label_box = gtk::Box::builder()
+            .spacing(12)
+            .orientation(gtk::Orientation::Horizontal)
+            .build();
+        label_box.append(
+            &gtk::Label::builder()
+                .label(option)
+                .xalign(0.0)
+                .hexpand(true)
+                .wrap(true)
+                .wrap_mode(pango::WrapMode::WordChar)
+                .build(),
+        );
+        label_box.append(
+            &gtk::Label::builder()
+                .label(votes.to_string())
+                .css_classes(["dimmed", "caption", "numeric"])
+                .build(),
+        );
+        option_box.append(&label_box);
+
+        let fraction = if voters == 0 {
+            0.0
+        } else {
+            f64::from(votes) / f64::from(voters)
+        };
+        option_box.append(&gtk::ProgressBar::builder().fraction(fraction).build());
+
+        let target = (msg.chat_jid.clone(), msg.server_id.clone(), option.clone());
+        let button = gtk::Button::builder()
+            .child(&option_box)
+            .action_name("app.vote-poll")
+            .action_target(&target.to_variant())
+            .css_classes(["flat", "poll-option"])
+            .build();
+        poll_box.append(&button);
+    }
+
+    let total = ni18n!("{0} vote", "{0} votes", voters).replacen("{0}", &voters.to_string(), 1);
+    poll_box.append(
+        &gtk::Label::builder()
+            .label(total)
+            .halign(gtk::Align::End)
+            .css_classes(["dimmed", "caption", "numeric"])
+            .build(),
+    );
+}
+
+/// Add a pill per reaction emoji, listing who reacted when clicked.
+fn fill_reactions_box(widgets: &ChatRowWidgets, msg: &ChatMessage) {
+    let user_jid = USER_JID.with_borrow(Clone::clone);
+    for (emoji, senders) in &msg.reactions {
+        let count = u32::try_from(senders.len()).unwrap_or(u32::MAX);
+        let pill = gtk::Button::builder()
+            .label(format!("{emoji} {count}"))
+            .tooltip_text(ni18n!("{0} reaction", "{0} reactions", count).replacen(
+                "{0}",
+                &count.to_string(),
+                1,
+            ))
+            .css_classes(["reaction-pill", "numeric"])
+            .build();
+        if user_jid.as_ref().is_some_and(|jid| senders.contains(jid)) {
+            pill.add_css_class("own");
+        }
+
+        let (message, emoji, popover) = (
+            msg.clone(),
+            emoji.clone(),
+            Rc::clone(&widgets.reactions_popover),
+        );
+        pill.connect_clicked(move |pill| {
+            show_reactions_popover(pill, &message, &emoji, &popover);
+        });
+        widgets.reactions_box.append(&pill);
+    }
+}
+
+/// Close the popover listing who reacted, if open.
+fn close_reactions_popover(popover: &Rc<RefCell<Option<gtk::Popover>>>) {
+    if let Some(popover) = popover.borrow_mut().take() {
+        popover.popdown();
+        popover.unparent();
+    }
+}
+
+/// Get the name a reactor is listed with.
+fn reactor_name(names: &HashMap<String, String>, user_jid: Option<&str>, jid: &str) -> String {
+    if user_jid == Some(jid) {
+        i18n!("You")
+    } else {
+        names.get(jid).cloned().unwrap_or_else(|| format_jid(jid))
+    }
+}
+
+/// Show who reacted to a message on a pill, with a tab per emoji starting at
+/// `emoji`. Rows are built as they're scrolled to, and our own reaction can
+/// be removed from its row.
+fn show_reactions_popover(
+    pill: &gtk::Button,
+    message: &ChatMessage,
+    emoji: &str,
+    slot: &Rc<RefCell<Option<gtk::Popover>>>,
+) {
+    close_reactions_popover(slot);
+
+    let user_jid = USER_JID.with_borrow(Clone::clone);
+    let names: Rc<RefCell<HashMap<String, String>>> = Rc::default();
+    let popover = gtk::Popover::new();
+
+    let search_entry = gtk::SearchEntry::builder()
+        .placeholder_text(i18n!("Search"))
+        .build();
+    let filter = gtk::CustomFilter::new({
+        let (names, user_jid, search_entry) = (
+            Rc::clone(&names),
+            user_jid.clone(),
+            search_entry.downgrade(),
+        );
+        move |item| {
+            let (Some(item), Some(search_entry)) = (
+                item.downcast_ref::<gtk::StringObject>(),
+                search_entry.upgrade(),
+            ) else {
+                return true;
+            };
+            let query = search_entry.text().to_lowercase();
+            query.is_empty()
+                || reactor_name(&names.borrow(), user_jid.as_deref(), &item.string())
+                    .to_lowercase()
+                    .contains(&query)
+        }
+    });
+    let changed_filter = filter.clone();
+    search_entry.connect_search_changed(move |_| {
+        changed_filter.changed(gtk::FilterChange::Different);
+    });
+
+    let stack = gtk::Stack::builder().vhomogeneous(false).build();
+    let mut lists = Vec::new();
+    for (reaction, senders) in &message.reactions {
+        let list = senders
+            .iter()
+            .map(String::as_str)
+            .collect::<gtk::StringList>();
+        let model = gtk::NoSelection::new(Some(gtk::FilterListModel::new(
+            Some(list.clone()),
+            Some(filter.clone()),
+        )));
+        lists.push(list);
+
+        let factory = gtk::SignalListItemFactory::new();
+        let weak_popover = popover.downgrade();
+        factory.connect_setup(move |_, item| {
+            let Some(item) = item.downcast_ref::<gtk::ListItem>() else {
+                return;
+            };
+            let row = gtk::Box::builder()
+                .spacing(12)
+                .orientation(gtk::Orientation::Horizontal)
+                .build();
+            row.append(&adw::Avatar::builder().size(32).show_initials(true).build());
+            row.append(
+                &gtk::Label::builder()
+                    .xalign(0.0)
+                    .hexpand(true)
+                    .ellipsize(pango::EllipsizeMode::End)
+                    .build(),
+            );
+            let remove_button = gtk::Button::builder()
+                .label(i18n!("Remove"))
+                .valign(gtk::Align::Center)
+                .action_name("app.react")
+                .css_classes(["flat"])
+                .build();
+
+            // Our reaction is gone once removed, so is the list.
+            let popover = weak_popover.clone();
+            remove_button.connect_clicked(move |_| {
+                if let Some(popover) = popover.upgrade() {
+                    popover.popdown();
+                }
+            });
+            row.append(&remove_button);
+            item.set_child(Some(&row));
+        });
+        factory.connect_bind({
+            let (names, user_jid) = (Rc::clone(&names), user_jid.clone());
+            let target = (
+                message.chat_jid.clone(),
+                message.server_id.clone(),
+                String::new(),
+            );
+            move |_, item| {
+                let Some(item) = item.downcast_ref::<gtk::ListItem>() else {
+                    return;
+                };
+                let (Some(jid), Some(row)) = (
+                    item.item().and_downcast::<gtk::StringObject>(),
+                    item.child(),
+                ) else {
+                    return;
+                };
+                let jid = jid.string();
+                let name = reactor_name(&names.borrow(), user_jid.as_deref(), &jid);
+
+                let mut child = row.first_child();
+                if let Some(avatar) = child.as_ref().and_then(|c| c.downcast_ref::<adw::Avatar>()) {
+                    avatar.set_text(Some(&name));
+                }
+                child = child.and_then(|c| c.next_sibling());
+                if let Some(label) = child.as_ref().and_then(|c| c.downcast_ref::<gtk::Label>()) {
+                    label.set_label(&name);
+                }
+                child = child.and_then(|c| c.next_sibling());
+                if let Some(button) = child.and_downcast::<gtk::Button>() {
+                    button.set_action_target_value(Some(&target.to_variant()));
+                    button.set_visible(user_jid.as_deref() == Some(jid.as_str()));
+                }
+            }
+        });
+
+        let view = gtk::ListView::builder()
+            .model(&model)
+            .factory(&factory)
+            .css_classes(["navigation-sidebar"])
+            .build();
+        let scrolled_window = gtk::ScrolledWindow::builder()
+            .child(&view)
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .propagate_natural_height(true)
+            .max_content_height(320)
+            .min_content_width(260)
+            .build();
+        let count = senders.len();
+        stack.add_titled(
+            &scrolled_window,
+            Some(reaction),
+            &format!("{reaction} {count}"),
+        );
+    }
+    stack.set_visible_child_name(emoji);
+
+    let content = gtk::Box::builder()
+        .spacing(6)
+        .orientation(gtk::Orientation::Vertical)
+        .build();
+    content.append(
+        &gtk::StackSwitcher::builder()
+            .stack(&stack)
+            .visible(message.reactions.len() > 1)
+            .build(),
+    );
+    content.append(&search_entry);
+    content.append(&stack);
+    popover.set_child(Some(&content));
+    popover.set_parent(pill);
+
+    // Unparent the popover once closed, unless a rebind did it already.
+    let closed_slot = Rc::clone(slot);
+    popover.connect_closed(move |popover| {
+        let popover = popover.clone();
+        let slot = Rc::clone(&closed_slot);
+        glib::idle_add_local_once(move || {
+            if slot.borrow().as_ref() == Some(&popover) {
+                slot.borrow_mut().take();
+                popover.unparent();
+            }
+        });
+    });
+    *slot.borrow_mut() = Some(popover.clone());
+    popover.popup();
+
+    // Name the reactors once looked up, rebuilding the rows shown.
+    let mut jids = message
+        .reactions
+        .values()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    jids.sort_unstable();
+    jids.dedup();
+    let db = Arc::clone(&message.db);
+    relm4::spawn_local(async move {
+        match db.get_contact_names(&jids).await {
+            Ok(found) => names.borrow_mut().extend(found),
+            Err(e) => tracing::error!("Failed to load the names of reactors: {e}"),
+        }
+        for list in lists {
+            let count = list.n_items();
+            list.items_changed(0, count, count);
+        }
+        filter.changed(gtk::FilterChange::Different);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{block_on, database, message};
+
+    fn image(data: &[u8]) -> Media {
+        Media {
+            r#type: MediaType::Image,
+            data: Arc::new(data.to_vec()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn enter_sends_unless_composing() {
+        let none = gdk::ModifierType::empty();
+        assert!(sends_message(SendShortcut::Enter, gdk::Key::Return, none, false));
+        assert!(sends_message(SendShortcut::Enter, gdk::Key::KP_Enter, none, false));
+        assert!(!sends_message(SendShortcut::Enter, gdk::Key::Return, none, true));
+        assert!(!sends_message(SendShortcut::Enter, gdk::Key::a, none, false));
+        assert!(!sends_message(
+            SendShortcut::Enter,
+            gdk::Key::Return,
+            gdk::ModifierType::SHIFT_MASK,
+            false
+        ));
+        assert!(!sends_message(
+            SendShortcut::CtrlEnter,
+            gdk::Key::Return,
+            gdk::ModifierType::CONTROL_MASK,
+            true
+        ));
+    }
+
+    #[test]
+    fn received_messages_are_placed_once_by_their_time() {
+        let db = block_on(database());
+        let (monday, tuesday) = (
+            NaiveDate::from_ymd_opt(2026, 10, 12).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 10, 13).unwrap(),
+        );
+        let received = |id: &str, timestamp: i64| ChatMessage {
+            timestamp: DateTime::from_timestamp(timestamp, 0).unwrap(),
+            ..message(&db, "chat", "alice", id)
+        };
+        let row = |id: &str, timestamp: i64| RowMetadata::Message {
+            timestamp,
+            id: id.to_string(),
+        };
+        let rows = VecDeque::from([
+            RowMetadata::Separator(monday),
+            row("a", 10),
+            row("c", 30),
+            RowMetadata::Separator(tuesday),
+            row("e", 100),
+        ]);
+        let place =
+            |id, timestamp, date| place_received(&rows, &received(id, timestamp), date, false);
+
+        // Already in the page loaded while it arrived.
+        assert_eq!(place("c", 30, monday), None);
+        assert_eq!(place("f", 110, tuesday), Some(Placement::End));
+        assert_eq!(place("b", 20, monday), Some(Placement::Before(2, false)));
+        assert_eq!(place("d", 50, tuesday), Some(Placement::Before(4, false)));
+        assert_eq!(place("0", 5, monday), Some(Placement::Before(1, false)));
+        let sunday = monday.pred_opt().unwrap();
+        assert_eq!(place("0", 5, sunday), Some(Placement::Before(0, true)));
+
+        // Older than the whole page: it's loaded along with older ones.
+        assert_eq!(place_received(&rows, &received("0", 5), sunday, true), None);
+    }
+
+    #[test]
+    fn thumbnails_are_keyed_like_bound_rows() {
+        let db = block_on(database());
+        let mut msg = message(&db, "chat", "alice", "1");
+        msg.media = Some(image(b"jpeg"));
+        msg.preview = Some(LinkPreview {
+            url: "https://example.com".into(),
+            title: None,
+            description: None,
+            thumbnail: Some(Arc::new(b"preview".to_vec())),
+        });
+
+        let keys = message_thumbnails(&msg)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                thumbnails::key("image", &msg, b"jpeg"),
+                thumbnails::key("preview", &msg, b"preview"),
+            ]
+        );
+    }
+
+    #[test]
+    fn thumbnail_key_changes_once_downloaded() {
+        let db = block_on(database());
+        let msg = message(&db, "chat", "alice", "1");
+
+        assert_ne!(
+            thumbnails::key("image", &msg, b""),
+            thumbnails::key("image", &msg, b"jpeg")
+        );
+    }
+
+    #[test]
+    fn no_thumbnails_for_revoked_or_missing_media() {
+        let db = block_on(database());
+        let mut revoked = message(&db, "chat", "alice", "1");
+        revoked.media = Some(image(b"jpeg"));
+        revoked.revoked = true;
+        let mut pending = message(&db, "chat", "alice", "2");
+        pending.media = Some(image(b""));
+
+        assert!(message_thumbnails(&revoked).is_empty());
+        assert!(message_thumbnails(&pending).is_empty());
+    }
+
+    #[test]
+    fn reactors_are_named_from_contacts() {
+        let names = HashMap::from([("bob@lid".to_string(), "Bob".to_string())]);
+
+        assert_eq!(reactor_name(&names, Some("me@lid"), "me@lid"), "You");
+        assert_eq!(reactor_name(&names, Some("me@lid"), "bob@lid"), "Bob");
+        assert_eq!(
+            reactor_name(&names, None, "5511999999999@s.whatsapp.net"),
+            format_jid("5511999999999@s.whatsapp.net")
+        );
+    }
+
+    #[test]
+    fn month_bounds_span_the_whole_month() {
+        let (start, end) = month_bounds(2024, 1).expect("February 2024 exists");
+
+        assert_eq!(local_date(start), NaiveDate::from_ymd_opt(2024, 2, 1));
+        assert_eq!(local_date(end - 1), NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert_eq!(local_date(end), NaiveDate::from_ymd_opt(2024, 3, 1));
+        assert!(month_bounds(2024, 12).is_none());
+    }
+}
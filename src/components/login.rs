@@ -9,18 +9,41 @@ use std::{
 use adw::prelude::*;
 use futures_util::FutureExt;
 use gtk::{gdk, glib, pango};
-use relm4::{RelmRemoveAllExt, component::Connector, prelude::*};
+use relm4::{
+    RelmRemoveAllExt,
+    actions::{RelmAction, RelmActionGroup},
+    component::Connector,
+    prelude::*,
+};
 use relm4_components::alert::{Alert, AlertMsg, AlertSettings};
-use rlibphonenumber::{PhoneNumber, PhoneNumberFormat};
+use rlibphonenumber::{PHONE_NUMBER_UTIL, PhoneNumber, PhoneNumberFormat};
 use strum::{AsRefStr, EnumString};
 use tokio::time::{self, Instant};
 
 use crate::{
     i18n,
-    utils::generate_qr_code,
+    session::{ClientError, PairFailure},
+    utils::{generate_qr_code, icon_or_fallback},
     widgets::{PairStep, PairingCell},
 };
 
+/// How long to keep requesting QR codes before asking to reset the session.
+const MAX_QR_WAIT: Duration = Duration::from_secs(15 * 60);
+
+/// How long to wait for the next QR code after one expires, before
+/// requesting a fresh one.
+const QR_REFRESH_GRACE: Duration = Duration::from_secs(5);
+
+/// Flag shown for numbers outside of any country, like satellite and
+/// toll-free ones.
+const NON_GEOGRAPHIC_FLAG: &str = "🇺🇳";
+
+/// Region code rlibphonenumber gives to non-geographic dialing codes.
+const NON_GEOGRAPHIC_REGION: &str = "001";
+
+relm4::new_action_group!(LoginActionGroup, "login");
+relm4::new_stateless_action!(CopyPairingDataAction, LoginActionGroup, "copy-pairing-data");
+
 #[derive(Debug)]
 pub struct Login {
     /// Page main stack is displaying.
@@ -29,6 +52,8 @@ pub struct Login {
     state: LoginState,
     /// Current QR code texture.
     qr_code: Option<gdk::Paintable>,
+    /// Countries to pick the dialing code from.
+    countries: Vec<Country>,
     /// Error dialog (TODO: use a custom alert dialog).
     error_dialog: Connector<Alert>,
     /// Pairing box containing all pair cells.
@@ -39,6 +64,8 @@ pub struct Login {
     phone_number_entry: adw::EntryRow,
     /// Current pair phone number view.
     phone_number_view: LoginPhoneNumberView,
+    /// Why the last pairing attempt failed, shown instead of the methods.
+    pair_failure: Option<PairFailure>,
 }
 
 #[derive(Clone, Copy, Debug, AsRefStr, PartialEq, EnumString)]
@@ -52,12 +79,18 @@ enum LoginPage {
 struct LoginState {
     /// 8-character pair code.
     code: Option<[char; 8]>,
+    /// Data encoded in the current QR code.
+    qr_data: Option<String>,
     /// Current pair state.
     pair_state: PairState,
-    /// QR code scan attempts.
-    scan_attempts: u8,
+    /// QR codes shown in this session, identifying the current one.
+    scan_attempts: u32,
+    /// Region of the country picked or typed.
+    selected_region: Option<String>,
     /// QR code expiration bar's progress.
     progress_fraction: f64,
+    /// When the first QR code of this session was shown.
+    qr_session_started: Option<Instant>,
     /// Whether the phone number is valid.
     valid_phone_number: Arc<AtomicBool>,
     /// Whether all QR codes from session has been expired.
@@ -66,6 +99,45 @@ struct LoginState {
     phone_number_country_emoji: Option<String>,
 }
 
+impl LoginState {
+    /// Pick the country of the dialing code typed, unless the one already
+    /// picked shares it.
+    fn update_country(&mut self, number: &str, countries: &[Country]) {
+        let Some((dial_code, _)) = split_dial_code(number, countries) else {
+            if is_non_geographic(number) {
+                self.selected_region = None;
+                self.phone_number_country_emoji = Some(NON_GEOGRAPHIC_FLAG.to_string());
+            }
+            return;
+        };
+
+        let keeps_selection = self.selected_region.as_ref().is_some_and(|region| {
+            countries
+                .iter()
+                .any(|c| c.region == *region && c.dial_code == dial_code)
+        });
+        if keeps_selection {
+            return;
+        }
+
+        let main_region = PHONE_NUMBER_UTIL.get_region_code_for_country_code(dial_code);
+        let country = countries.iter().find(|c| c.region == main_region);
+        self.selected_region = country.map(|c| c.region.clone());
+        self.phone_number_country_emoji = country.map(|c| c.flag.clone());
+    }
+}
+
+/// A country phone numbers can be linked from.
+#[derive(Clone, Debug)]
+struct Country {
+    /// Flag emoji.
+    flag: String,
+    /// Two-letter region code.
+    region: String,
+    /// International dialing code, without the `+`.
+    dial_code: i32,
+}
+
 #[derive(Clone, Debug, Default, PartialEq)]
 pub enum PairState {
     /// The client was paired successfully.
@@ -94,6 +166,12 @@ pub enum LoginInput {
     },
     /// Client has paired successfully.
     PairSuccess,
+    /// Use the dialing code of the country at this index.
+    SelectCountry(usize),
+    /// Copy the pair code to the clipboard.
+    CopyCode,
+    /// Copy the data encoded in the QR code to the clipboard.
+    CopyPairingData,
     /// Request the login to change the page to `QrCode`.
     PairWithQrCode,
     /// Request the login to change the page to `PhoneNumber`.
@@ -102,8 +180,13 @@ pub enum LoginInput {
         edit: bool,
     },
 
-    /// Error occurred.
-    Error { message: String },
+    /// Pairing was refused.
+    PairFailed(PairFailure),
+    /// Leave the failure page and start pairing again.
+    RetryPairing,
+
+    /// The client failed.
+    Error(ClientError),
 }
 
 #[derive(Debug)]
@@ -113,6 +196,9 @@ pub enum LoginOutput {
 
     /// Request the session to pair with a phone number.
     PairWithPhoneNumber { phone_number: String },
+
+    /// Show a notification in the window.
+    ShowToast(String),
 }
 
 #[derive(Debug)]
@@ -123,9 +209,11 @@ pub enum LoginCommand {
     /// Update the QR Code.
     UpdateQrCode { data: String, timeout: Duration },
     /// QR code expired by timeout.
-    QrCodeExpired,
+    QrCodeExpired(u32),
+    /// Request a fresh QR code if none came after the one expired.
+    RefreshQrCode(u32),
     /// Update the expiration bar.
-    UpdateExpirationBar(f32),
+    UpdateExpirationBar { attempt: u32, progress: f32 },
     /// Request the session to pair with a phone number.
     PairWithPhoneNumber { phone_number: String },
 
@@ -143,309 +231,389 @@ impl AsyncComponent for Login {
     type Output = LoginOutput;
     type CommandOutput = LoginCommand;
 
+    menu! {
+        qr_code_menu: {
+            section! {
+                &i18n!("_Copy Pairing Data") => CopyPairingDataAction,
+            }
+        }
+    }
+
     view! {
         &adw::ToolbarView {
             set_css_classes: &["login-page"],
 
             add_top_bar = &adw::HeaderBar {
                 pack_end = &gtk::Button {
-                    set_icon_name: "info-outline-symbolic",
+                    set_icon_name: icon_or_fallback("info-outline-symbolic", "help-about-symbolic"),
                     set_action_name: Some("win.about"),
                     set_tooltip_text: Some(&i18n!("About Papo")),
                 }
             },
 
             #[wrap(Some)]
-            set_content = &adw::StatusPage {
-                set_title: &i18n!("Link your phone"),
-                set_vexpand: true,
-
-                gtk::Stack {
-                    set_transition_type: gtk::StackTransitionType::SlideLeftRight,
-
-                    add_named[Some("qr-code")] = &gtk::Box {
-                        set_halign: gtk::Align::Center,
-                        set_valign: gtk::Align::Center,
-                        set_spacing: 5,
-                        set_orientation: gtk::Orientation::Vertical,
-
-                        gtk::Box {
-                            set_halign: gtk::Align::Center,
-                            set_valign: gtk::Align::Center,
-                            set_hexpand: true,
-                            set_vexpand: true,
-                            set_spacing: 10,
-                            #[watch]
-                            set_css_classes: if model.qr_code.is_none() { &["card", "view"] } else { &[] },
-                            set_orientation: gtk::Orientation::Vertical,
-                            set_width_request: 200,
-                            set_height_request: 200,
-
-                            gtk::Picture {
-                                set_halign: gtk::Align::Center,
-                                set_valign: gtk::Align::Center,
-                                set_hexpand: true,
-                                set_vexpand: true,
-                                #[watch]
-                                set_visible: model.qr_code.is_some(),
-                                #[watch]
-                                set_paintable: model.qr_code.as_ref(),
-                                set_css_classes: &["qr-code"]
-                            },
-
-                            gtk::Box {
-                                set_halign: gtk::Align::Center,
-                                set_valign: gtk::Align::Center,
-                                set_hexpand: true,
-                                set_vexpand: true,
-                                #[watch]
-                                set_visible: model.qr_code.is_none(),
-                                set_spacing: 20,
-                                set_orientation: gtk::Orientation::Vertical,
-
-                                gtk::Label {
-                                    #[watch]
-                                    set_label: &if model.state.session_scan_expired.load(Ordering::Acquire) {
-                                        i18n!("All QR codes for this session were expired.")
-                                    } else {
-                                        i18n!("Waiting QR code...")
-                                    },
-                                    set_justify: gtk::Justification::Center,
-                                    set_css_classes: &["title-4"],
-                                    set_max_width_chars: 14,
-
-                                    set_wrap: true,
-                                    set_wrap_mode: pango::WrapMode::WordChar,
-                                },
-
-                                adw::Spinner {
-                                    #[watch]
-                                    set_visible: !model.state.session_scan_expired.load(Ordering::Acquire),
-                                    set_width_request: 32,
-                                    set_height_request: 32
-                                },
-
-                                gtk::Button {
-                                    set_label: &i18n!("Reset Session"),
-                                    #[watch]
-                                    set_visible: model.state.session_scan_expired.load(Ordering::Acquire),
-                                    set_css_classes: &["pill", "suggested-action"],
+            #[name = "breakpoint_bin"]
+            set_content = &adw::BreakpointBin {
+                set_width_request: 360,
+                set_height_request: 400,
 
-                                    connect_clicked[sender] => move |_| {
-                                        sender.oneshot_command(async { LoginCommand::ResetSession });
-                                    }
-                                }
-                            }
-                        },
+                #[wrap(Some)]
+                set_child = &gtk::Stack {
+                    set_transition_type: gtk::StackTransitionType::Crossfade,
 
-                        gtk::Revealer {
-                            #[watch]
-                            set_visible: model.page == LoginPage::QrCode,
-                            #[watch]
-                            set_reveal_child: model.qr_code.is_some(),
-                            set_margin_bottom: 20,
-                            set_transition_type: gtk::RevealerTransitionType::SwingDown,
-                            set_transition_duration: 300,
-
-                            gtk::ProgressBar {
-                                set_halign: gtk::Align::Center,
-                                set_valign: gtk::Align::End,
-                                #[watch]
-                                set_fraction: model.state.progress_fraction,
-                                set_width_request: 200
-                            }
-                        },
+                    add_named[Some("pairing")] = &adw::StatusPage {
+                        set_title: &i18n!("Link your phone"),
+                        set_vexpand: true,
 
+                        #[name = "qr_layout"]
                         gtk::Box {
                             set_halign: gtk::Align::Center,
-                            set_hexpand: true,
-                            set_spacing: 10,
-                            set_orientation: gtk::Orientation::Vertical,
-                            set_margin_bottom: 15,
-
-                            PairStep::new(1, &i18n!("Open WhatsApp on your phone.")).main_box {},
-                            PairStep::new(2, &i18n!("Go to: <i>Menu &gt; Linked devices &gt; Link a device</i>")) .main_box {},
-                            PairStep::new(3, &i18n!("Scan this QR code.")).main_box {}
-                        },
-
-                        gtk::Label {
-                            set_label: &format!("<a href=\"link-with-phone-number\">{}</a>", i18n!("Link with Phone Number")),
-                            set_justify: gtk::Justification::Center,
-                            set_use_markup: true,
-                            set_css_classes: &["body"],
-
-                            connect_activate_link[sender] => move |_, uri| {
-                                if uri == "link-with-phone-number" {
-                                    sender.input(LoginInput::PairWithPhoneNumber { edit: false });
-                                }
+                            set_spacing: 36,
+                            set_orientation: gtk::Orientation::Horizontal,
 
-                                glib::Propagation::Stop
-                            }
-                        }
-                    },
+                            gtk::Stack {
+                                set_transition_type: gtk::StackTransitionType::SlideLeftRight,
 
-                    add_named[Some("phone-number")] = &gtk::Stack {
-                        set_transition_type: gtk::StackTransitionType::Crossfade,
-
-                        add_named[Some("enter-phone-number")] = &gtk::Box {
-                            set_halign: gtk::Align::Center,
-                            set_valign: gtk::Align::Center,
-                            set_spacing: 20,
-                            set_orientation: gtk::Orientation::Vertical,
-
-                            gtk::Box {
-                                set_halign: gtk::Align::Center,
-                                set_valign: gtk::Align::Center,
-                                set_hexpand: true,
-                                set_vexpand: true,
-                                set_css_classes: &["card"],
-                                set_width_request: 80,
-                                set_height_request: 80,
-
-                                gtk::Image {
+                                add_named[Some("qr-code")] = &gtk::Box {
                                     set_halign: gtk::Align::Center,
                                     set_valign: gtk::Align::Center,
-                                    set_hexpand: true,
-                                    set_vexpand: true,
-                                    set_icon_name: Some("phone-right-facing-symbolic"),
-                                    set_pixel_size: 50
-                                }
-                            },
-
-                            gtk::Box {
-                                set_halign: gtk::Align::Center,
-                                set_hexpand: true,
-
-                                gtk::Label {
-                                    set_label: &i18n!("Please enter your phone number."),
-                                    set_justify: gtk::Justification::Center,
-                                    set_css_classes: &["body", "dimmed"],
-                                },
-                            },
-
-                            gtk::Box {
-                                set_halign: gtk::Align::Center,
-                                set_hexpand: true,
-                                set_spacing: 15,
-                                set_orientation: gtk::Orientation::Vertical,
-
-                                adw::PreferencesGroup {
-                                    set_separate_rows: true,
-                                    set_width_request: 300,
-
-                                    #[local_ref]
-                                    add = &phone_number_entry -> adw::EntryRow {
-                                        set_title: &i18n!("Phone Number"),
-                                        set_max_length: 20,
-                                        set_input_hints: gtk::InputHints::PRIVATE,
-                                        set_input_purpose: gtk::InputPurpose::Phone,
+                                    set_spacing: 5,
+                                    set_orientation: gtk::Orientation::Vertical,
+
+                                    gtk::Box {
+                                        set_halign: gtk::Align::Center,
+                                        set_valign: gtk::Align::Center,
+                                        set_hexpand: true,
+                                        set_vexpand: true,
+                                        set_spacing: 10,
+                                        #[watch]
+                                        set_css_classes: if model.qr_code.is_none() { &["card", "view"] } else { &[] },
+                                        set_orientation: gtk::Orientation::Vertical,
                                         set_width_request: 200,
-
-                                        connect_changed[sender] => move |_| { sender.oneshot_command(async { LoginCommand::ValidatePhoneNumber }); },
-                                        connect_entry_activated[sender] => move |entry| {
-                                            let phone_number = entry.text().to_string();
-                                            sender.oneshot_command(async { LoginCommand::PairWithPhoneNumber { phone_number, } });
+                                        set_height_request: 200,
+
+                                        #[name = "qr_code_picture"]
+                                        gtk::Picture {
+                                            set_halign: gtk::Align::Center,
+                                            set_valign: gtk::Align::Center,
+                                            set_hexpand: true,
+                                            set_vexpand: true,
+                                            #[watch]
+                                            set_visible: model.qr_code.is_some(),
+                                            #[watch]
+                                            set_paintable: model.qr_code.as_ref(),
+                                            set_css_classes: &["qr-code"]
+                                        },
+
+                                        gtk::Box {
+                                            set_halign: gtk::Align::Center,
+                                            set_valign: gtk::Align::Center,
+                                            set_hexpand: true,
+                                            set_vexpand: true,
+                                            #[watch]
+                                            set_visible: model.qr_code.is_none(),
+                                            set_spacing: 20,
+                                            set_orientation: gtk::Orientation::Vertical,
+
+                                            gtk::Label {
+                                                #[watch]
+                                                set_label: &if model.state.session_scan_expired.load(Ordering::Acquire) {
+                                                    i18n!("All QR codes for this session were expired.")
+                                                } else if model.state.scan_attempts > 0 {
+                                                    i18n!("Refreshing QR code…")
+                                                } else {
+                                                    i18n!("Waiting QR code...")
+                                                },
+                                                set_justify: gtk::Justification::Center,
+                                                set_css_classes: &["title-4"],
+                                                set_max_width_chars: 14,
+
+                                                set_wrap: true,
+                                                set_wrap_mode: pango::WrapMode::WordChar,
+                                            },
+
+                                            adw::Spinner {
+                                                #[watch]
+                                                set_visible: !model.state.session_scan_expired.load(Ordering::Acquire),
+                                                set_width_request: 32,
+                                                set_height_request: 32
+                                            },
+
+                                            gtk::Button {
+                                                set_label: &i18n!("Reset Session"),
+                                                #[watch]
+                                                set_visible: model.state.session_scan_expired.load(Ordering::Acquire),
+                                                set_css_classes: &["pill", "suggested-action"],
+
+                                                connect_clicked[sender] => move |_| {
+                                                    sender.oneshot_command(async { LoginCommand::ResetSession });
+                                                }
+                                            }
                                         }
                                     },
 
-                                    add = &adw::ButtonRow{
-                                        set_title: &i18n!("Next"),
+                                    gtk::Revealer {
                                         #[watch]
-                                        set_css_classes: if model.state.valid_phone_number.load(Ordering::Acquire) { &["suggested-action"] } else { &[] },
-                                        set_end_icon_name: Some("go-next-symbolic"),
-                                        set_height_request: 40,
-
-                                        connect_activated[sender, phone_number_entry] => move |_| {
-                                            let phone_number = phone_number_entry.text().to_string();
-                                            sender.oneshot_command(async { LoginCommand::PairWithPhoneNumber { phone_number, } });
+                                        set_visible: model.page == LoginPage::QrCode,
+                                        #[watch]
+                                        set_reveal_child: model.qr_code.is_some(),
+                                        set_margin_bottom: 20,
+                                        set_transition_type: gtk::RevealerTransitionType::SwingDown,
+                                        set_transition_duration: 300,
+
+                                        gtk::ProgressBar {
+                                            set_halign: gtk::Align::Center,
+                                            set_valign: gtk::Align::End,
+                                            #[watch]
+                                            set_fraction: model.state.progress_fraction,
+                                            set_width_request: 200
                                         }
-                                    }
-                                }
-                            },
+                                    },
+
+                                    gtk::Label {
+                                        set_label: &format!("<a href=\"link-with-phone-number\">{}</a>", i18n!("Link with Phone Number")),
+                                        set_justify: gtk::Justification::Center,
+                                        set_use_markup: true,
+                                        set_css_classes: &["body"],
 
-                            gtk::Label {
-                                set_label: &format!("<a href=\"link-with-qr-code\">{}</a>", i18n!("Link with QR Code")),
-                                set_justify: gtk::Justification::Center,
-                                set_use_markup: true,
-                                set_css_classes: &["body"],
+                                        connect_activate_link[sender] => move |_, uri| {
+                                            if uri == "link-with-phone-number" {
+                                                sender.input(LoginInput::PairWithPhoneNumber { edit: false });
+                                            }
 
-                                connect_activate_link[sender] => move |_, uri| {
-                                    if uri == "link-with-qr-code" {
-                                        sender.input(LoginInput::PairWithQrCode);
+                                            glib::Propagation::Stop
+                                        }
                                     }
+                                },
 
-                                    glib::Propagation::Stop
-                                }
-                            }
-                        },
+                                add_named[Some("phone-number")] = &gtk::Stack {
+                                    set_transition_type: gtk::StackTransitionType::Crossfade,
+
+                                    add_named[Some("enter-phone-number")] = &gtk::Box {
+                                        set_halign: gtk::Align::Center,
+                                        set_valign: gtk::Align::Center,
+                                        set_spacing: 20,
+                                        set_orientation: gtk::Orientation::Vertical,
+
+                                        gtk::Box {
+                                            set_halign: gtk::Align::Center,
+                                            set_valign: gtk::Align::Center,
+                                            set_hexpand: true,
+                                            set_vexpand: true,
+                                            set_css_classes: &["card"],
+                                            set_width_request: 80,
+                                            set_height_request: 80,
+
+                                            gtk::Image {
+                                                set_halign: gtk::Align::Center,
+                                                set_valign: gtk::Align::Center,
+                                                set_hexpand: true,
+                                                set_vexpand: true,
+                                                set_icon_name: Some(icon_or_fallback("phone-right-facing-symbolic", "phone-symbolic")),
+                                                set_pixel_size: 50
+                                            }
+                                        },
+
+                                        gtk::Box {
+                                            set_halign: gtk::Align::Center,
+                                            set_hexpand: true,
+
+                                            gtk::Label {
+                                                set_label: &i18n!("Please enter your phone number."),
+                                                set_justify: gtk::Justification::Center,
+                                                set_css_classes: &["body", "dimmed"],
+                                            },
+                                        },
+
+                                        gtk::Box {
+                                            set_halign: gtk::Align::Center,
+                                            set_hexpand: true,
+                                            set_spacing: 15,
+                                            set_orientation: gtk::Orientation::Vertical,
+
+                                            adw::PreferencesGroup {
+                                                set_separate_rows: true,
+                                                set_width_request: 300,
+
+                                                #[local_ref]
+                                                add = &phone_number_entry -> adw::EntryRow {
+                                                    set_title: &i18n!("Phone Number"),
+                                                    set_max_length: 20,
+                                                    set_input_hints: gtk::InputHints::PRIVATE,
+                                                    set_input_purpose: gtk::InputPurpose::Phone,
+                                                    set_width_request: 200,
+
+                                                    #[name = "country_button"]
+                                                    add_prefix = &gtk::MenuButton {
+                                                        #[watch]
+                                                        set_label: model.state.phone_number_country_emoji.as_deref().unwrap_or("🌐"),
+                                                        set_valign: gtk::Align::Center,
+                                                        set_tooltip_text: Some(&i18n!("Country")),
+                                                        set_css_classes: &["flat"],
+                                                    },
+
+                                                    connect_changed[sender] => move |_| { sender.oneshot_command(async { LoginCommand::ValidatePhoneNumber }); },
+                                                    connect_entry_activated[sender] => move |entry| {
+                                                        let phone_number = entry.text().to_string();
+                                                        sender.oneshot_command(async { LoginCommand::PairWithPhoneNumber { phone_number, } });
+                                                    }
+                                                },
+
+                                                add = &adw::ButtonRow{
+                                                    set_title: &i18n!("Next"),
+                                                    #[watch]
+                                                    set_css_classes: if model.state.valid_phone_number.load(Ordering::Acquire) { &["suggested-action"] } else { &[] },
+                                                    set_end_icon_name: Some(icon_or_fallback("go-next-symbolic", "go-next")),
+                                                    set_height_request: 40,
+
+                                                    connect_activated[sender, phone_number_entry] => move |_| {
+                                                        let phone_number = phone_number_entry.text().to_string();
+                                                        sender.oneshot_command(async { LoginCommand::PairWithPhoneNumber { phone_number, } });
+                                                    }
+                                                }
+                                            }
+                                        },
+
+                                        gtk::Label {
+                                            set_label: &format!("<a href=\"link-with-qr-code\">{}</a>", i18n!("Link with QR Code")),
+                                            set_justify: gtk::Justification::Center,
+                                            set_use_markup: true,
+                                            set_css_classes: &["body"],
+
+                                            connect_activate_link[sender] => move |_, uri| {
+                                                if uri == "link-with-qr-code" {
+                                                    sender.input(LoginInput::PairWithQrCode);
+                                                }
+
+                                                glib::Propagation::Stop
+                                            }
+                                        }
+                                    },
 
-                        add_named[Some("confirm-code")] = &gtk::Box {
-                            set_halign: gtk::Align::Center,
-                            set_valign: gtk::Align::Center,
-                            set_spacing: 20,
-                            set_orientation: gtk::Orientation::Vertical,
+                                    add_named[Some("confirm-code")] = &gtk::Box {
+                                        set_halign: gtk::Align::Center,
+                                        set_valign: gtk::Align::Center,
+                                        set_spacing: 20,
+                                        set_orientation: gtk::Orientation::Vertical,
+
+                                        gtk::Box {
+                                            set_halign: gtk::Align::Center,
+                                            set_valign: gtk::Align::Center,
+                                            set_hexpand: true,
+                                            set_vexpand: true,
+                                            set_css_classes: &["card"],
+                                            set_width_request: 80,
+                                            set_height_request: 80,
+
+                                            gtk::Image {
+                                                set_halign: gtk::Align::Center,
+                                                set_valign: gtk::Align::Center,
+                                                set_hexpand: true,
+                                                set_vexpand: true,
+                                                set_icon_name: Some(icon_or_fallback("phonelink-setup-symbolic", "computer-symbolic")),
+                                                set_pixel_size: 50
+                                            }
+                                        },
+
+                                        gtk::Label {
+                                            #[watch]
+                                            set_label: &format!("{} (<a href=\"edit-phone-number\">{}</a>)", i18n!("Linking WhatsApp account <b>${phone-number}</b>"), i18n!("edit"))
+                                                .replace("${phone-number}", model.phone_number_entry.text().to_string().as_str()),
+                                            set_justify: gtk::Justification::Center,
+                                            set_use_markup: true,
+                                            set_css_classes: &["body"],
+
+                                            connect_activate_link[sender] => move |_, uri| {
+                                                if uri == "edit-phone-number" {
+                                                    sender.input(LoginInput::PairWithPhoneNumber { edit: true });
+                                                }
+
+                                                glib::Propagation::Stop
+                                            }
+                                        },
+
+                                        #[local_ref]
+                                        pairing_box -> gtk::Box {
+                                            set_halign: gtk::Align::Center,
+                                            set_hexpand: true,
+                                            set_homogeneous: true,
+                                        },
+
+                                        gtk::Button {
+                                            set_label: &i18n!("_Copy Code"),
+                                            set_use_underline: true,
+                                            set_halign: gtk::Align::Center,
+                                            set_css_classes: &["pill"],
+                                            #[watch]
+                                            set_sensitive: model.state.code.is_some(),
+
+                                            connect_clicked[sender] => move |_| {
+                                                sender.input(LoginInput::CopyCode);
+                                            }
+                                        },
+                                    },
 
-                            gtk::Box {
-                                set_halign: gtk::Align::Center,
-                                set_valign: gtk::Align::Center,
-                                set_hexpand: true,
-                                set_vexpand: true,
-                                set_css_classes: &["card"],
-                                set_width_request: 80,
-                                set_height_request: 80,
+                                    #[watch]
+                                    set_visible_child_name: model.phone_number_view.as_ref(),
+                                },
 
-                                gtk::Image {
-                                    set_halign: gtk::Align::Center,
-                                    set_valign: gtk::Align::Center,
-                                    set_hexpand: true,
-                                    set_vexpand: true,
-                                    set_icon_name: Some("phonelink-setup-symbolic"),
-                                    set_pixel_size: 50
-                                }
+                                #[watch]
+                                set_visible_child_name: model.page.as_ref(),
                             },
 
-                            gtk::Label {
+                            // Instructions for the pairing method shown.
+                            gtk::Stack {
+                                set_valign: gtk::Align::Center,
+                                set_transition_type: gtk::StackTransitionType::Crossfade,
                                 #[watch]
-                                set_label: &format!("{} (<a href=\"edit-phone-number\">{}</a>)", i18n!("Linking WhatsApp account <b>${phone-number}</b>"), i18n!("edit"))
-                                    .replace("${phone-number}", model.phone_number_entry.text().to_string().as_str()),
-                                set_justify: gtk::Justification::Center,
-                                set_use_markup: true,
-                                set_css_classes: &["body"],
-
-                                connect_activate_link[sender] => move |_, uri| {
-                                    if uri == "edit-phone-number" {
-                                        sender.input(LoginInput::PairWithPhoneNumber { edit: true });
-                                    }
+                                set_visible: model.page == LoginPage::QrCode
+                                    || matches!(model.phone_number_view, LoginPhoneNumberView::ConfirmCode),
 
-                                    glib::Propagation::Stop
-                                }
-                            },
+                                add_named[Some("qr-code")] = &gtk::Box {
+                                    set_spacing: 10,
+                                    set_orientation: gtk::Orientation::Vertical,
 
-                            #[local_ref]
-                            pairing_box -> gtk::Box {
-                                set_halign: gtk::Align::Center,
-                                set_hexpand: true,
-                                set_homogeneous: true,
-                            },
+                                    PairStep::new(1, &i18n!("Open WhatsApp on your phone.")).main_box {},
+                                    PairStep::new(2, &i18n!("Tap <i>Menu &gt; Linked devices</i>.")).main_box {},
+                                    PairStep::new(3, &i18n!("Tap <i>Link a device</i>.")).main_box {},
+                                    PairStep::new(4, &i18n!("Point your phone at this screen.")).main_box {}
+                                },
 
-                            gtk::Box {
-                                set_halign: gtk::Align::Center,
-                                set_hexpand: true,
-                                set_spacing: 10,
-                                set_orientation: gtk::Orientation::Vertical,
+                                add_named[Some("phone-number")] = &gtk::Box {
+                                    set_spacing: 10,
+                                    set_orientation: gtk::Orientation::Vertical,
 
-                                PairStep::new(1, &i18n!("Open WhatsApp on your phone.")).main_box {},
-                                PairStep::new(2, &i18n!("Go to: <i>Menu &gt; Linked devices &gt; Link a device</i>")) .main_box {},
-                                PairStep::new(3, &i18n!("Tap <i>Link with phone number</i> and enter this code on your phone.")).main_box {}
+                                    PairStep::new(1, &i18n!("Open WhatsApp on your phone.")).main_box {},
+                                    PairStep::new(2, &i18n!("Tap <i>Menu &gt; Linked devices</i>.")).main_box {},
+                                    PairStep::new(3, &i18n!("Tap <i>Link a device</i>, then <i>Link with phone number instead</i>.")).main_box {},
+                                    PairStep::new(4, &i18n!("Enter this code on your phone.")).main_box {}
+                                },
+
+                                #[watch]
+                                set_visible_child_name: model.page.as_ref(),
                             }
-                        },
+                        }
+                    },
 
+                    add_named[Some("failed")] = &adw::StatusPage {
+                        #[watch]
+                        set_icon_name: model.pair_failure.as_ref().map(PairFailure::icon_name),
+                        #[watch]
+                        set_title: &model.pair_failure.as_ref().map(PairFailure::title).unwrap_or_default(),
                         #[watch]
-                        set_visible_child_name: model.phone_number_view.as_ref(),
+                        set_description: model.pair_failure.as_ref().map(PairFailure::description).as_deref(),
+
+                        gtk::Button {
+                            set_label: &i18n!("_Try Again"),
+                            set_use_underline: true,
+                            set_halign: gtk::Align::Center,
+                            set_css_classes: &["pill", "suggested-action"],
+                            connect_clicked => LoginInput::RetryPairing,
+                        },
                     },
 
                     #[watch]
-                    set_visible_child_name: model.page.as_ref(),
+                    set_visible_child_name: if model.pair_failure.is_some() { "failed" } else { "pairing" },
                 }
             }
         }
@@ -474,17 +642,130 @@ impl AsyncComponent for Login {
             page: LoginPage::QrCode,
             state: LoginState::default(),
             qr_code: None,
+            countries: countries(),
             error_dialog,
             pairing_box,
             pairing_cells: None,
             phone_number_entry: phone_number_entry.clone(),
             phone_number_view: LoginPhoneNumberView::EnterPhoneNumber,
+            pair_failure: None,
         };
 
         let pairing_box = &model.pairing_box;
 
         let widgets = view_output!();
 
+        // Show the instructions under the pairing method on narrow windows.
+        let breakpoint = adw::Breakpoint::new(adw::BreakpointCondition::new_length(
+            adw::BreakpointConditionLengthType::MaxWidth,
+            600.0,
+            adw::LengthUnit::Sp,
+        ));
+        breakpoint.add_setters(&[(
+            &widgets.qr_layout,
+            "orientation",
+            gtk::Orientation::Vertical,
+        )]);
+        widgets.breakpoint_bin.add_breakpoint(breakpoint);
+
+        // Country picker, searchable by region or dialing code.
+        let country_list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .css_classes(["navigation-sidebar"])
+            .build();
+        for country in &model.countries {
+            let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+            row_box.append(&gtk::Label::new(Some(&country.flag)));
+            row_box.append(
+                &gtk::Label::builder()
+                    .label(&country.region)
+                    .hexpand(true)
+                    .xalign(0.0)
+                    .build(),
+            );
+            row_box.append(
+                &gtk::Label::builder()
+                    .label(format!("+{}", country.dial_code))
+                    .css_classes(["dimmed", "numeric"])
+                    .build(),
+            );
+
+            country_list.append(
+                &gtk::ListBoxRow::builder()
+                    .child(&row_box)
+                    .name(format!(
+                        "{} +{}",
+                        country.region.to_lowercase(),
+                        country.dial_code
+                    ))
+                    .build(),
+            );
+        }
+
+        let search_entry = gtk::SearchEntry::builder()
+            .placeholder_text(i18n!("Search countries"))
+            .build();
+        country_list.set_filter_func({
+            let search_entry = search_entry.clone();
+            move |row| {
+                let query = search_entry.text().trim().to_lowercase();
+                row.widget_name().contains(query.trim_start_matches('+'))
+            }
+        });
+        search_entry.connect_search_changed({
+            let country_list = country_list.clone();
+            move |_| country_list.invalidate_filter()
+        });
+
+        let country_popover = gtk::Popover::builder()
+            .child(&{
+                let popover_box = gtk::Box::new(gtk::Orientation::Vertical, 6);
+                popover_box.append(&search_entry);
+                popover_box.append(
+                    &gtk::ScrolledWindow::builder()
+                        .child(&country_list)
+                        .hscrollbar_policy(gtk::PolicyType::Never)
+                        .propagate_natural_height(true)
+                        .max_content_height(300)
+                        .build(),
+                );
+                popover_box
+            })
+            .build();
+        country_popover.connect_show(move |_| {
+            search_entry.grab_focus();
+        });
+
+        let input_sender = sender.input_sender().clone();
+        let popover = country_popover.clone();
+        country_list.connect_row_activated(move |_, row| {
+            popover.popdown();
+            if let Ok(index) = usize::try_from(row.index()) {
+                input_sender.emit(LoginInput::SelectCountry(index));
+            }
+        });
+        widgets.country_button.set_popover(Some(&country_popover));
+
+        // Copy the QR code data from a right click menu, for debugging.
+        let qr_code_popover = gtk::PopoverMenu::from_model(Some(&qr_code_menu));
+        qr_code_popover.set_has_arrow(false);
+        qr_code_popover.set_parent(&widgets.qr_code_picture);
+
+        let click_gesture = gtk::GestureClick::builder()
+            .button(gdk::BUTTON_SECONDARY)
+            .build();
+        click_gesture.connect_pressed(move |_, _, _, _| qr_code_popover.popup());
+        widgets.qr_code_picture.add_controller(click_gesture);
+
+        let input_sender = sender.input_sender().clone();
+        let copy_pairing_data_action =
+            RelmAction::<CopyPairingDataAction>::new_stateless(move |_| {
+                input_sender.emit(LoginInput::CopyPairingData);
+            });
+        let mut actions = RelmActionGroup::<LoginActionGroup>::new();
+        actions.add_action(copy_pairing_data_action);
+        actions.register_for_widget(&root);
+
         AsyncComponentParts { model, widgets }
     }
 
@@ -492,7 +773,7 @@ impl AsyncComponent for Login {
         &mut self,
         input: Self::Input,
         sender: AsyncComponentSender<Self>,
-        _root: &Self::Root,
+        root: &Self::Root,
     ) {
         match input {
             LoginInput::PairCode {
@@ -549,6 +830,39 @@ impl AsyncComponent for Login {
                     }
                 }
             }
+            LoginInput::SelectCountry(index) => {
+                let Some(country) = self.countries.get(index) else {
+                    return;
+                };
+
+                // Replace the dialing code typed, if any, keeping the rest of the number.
+                let text = self.phone_number_entry.text();
+                let rest = split_dial_code(&text, &self.countries)
+                    .map_or_else(|| text.trim_start_matches('+').trim(), |(_, rest)| rest);
+                let number = format!("+{} {rest}", country.dial_code);
+
+                self.state.selected_region = Some(country.region.clone());
+                self.state.phone_number_country_emoji = Some(country.flag.clone());
+
+                self.phone_number_entry.set_text(&number);
+                self.phone_number_entry.set_position(-1);
+                self.phone_number_entry.grab_focus();
+            }
+            LoginInput::CopyCode => {
+                // Not received yet, the button is insensitive until then.
+                let Some(code) = self.state.code else { return };
+
+                root.clipboard().set_text(&code.iter().collect::<String>());
+                let _ = sender.output(LoginOutput::ShowToast(i18n!("Code copied")));
+            }
+            LoginInput::CopyPairingData => {
+                let Some(ref data) = self.state.qr_data else {
+                    return;
+                };
+
+                root.clipboard().set_text(data);
+                let _ = sender.output(LoginOutput::ShowToast(i18n!("Pairing data copied")));
+            }
             LoginInput::PairWithQrCode => {
                 self.page = LoginPage::QrCode;
             }
@@ -561,7 +875,16 @@ impl AsyncComponent for Login {
                 self.phone_number_entry.grab_focus();
             }
 
-            LoginInput::Error { message } => {
+            LoginInput::PairFailed(failure) => {
+                self.state.pair_state = PairState::Pairing;
+                self.pair_failure = Some(failure);
+            }
+            LoginInput::RetryPairing => {
+                self.pair_failure = None;
+                sender.oneshot_command(async { LoginCommand::ResetSession });
+            }
+
+            LoginInput::Error(error) => {
                 if self.page == LoginPage::PhoneNumber {
                     // Reset session and start pair with phone number
                     sender.oneshot_command(async { LoginCommand::ResetSession });
@@ -573,7 +896,16 @@ impl AsyncComponent for Login {
                 } else {
                     self.state.pair_state = PairState::Pairing;
 
-                    self.error_dialog.widgets().gtk_label_2.set_text(&message);
+                    // Let the session be reset by hand.
+                    self.qr_code = None;
+                    self.state
+                        .session_scan_expired
+                        .store(true, Ordering::Release);
+
+                    self.error_dialog
+                        .widgets()
+                        .gtk_label_2
+                        .set_text(&error.message());
                     self.error_dialog.emit(AlertMsg::Show);
                 }
             }
@@ -592,9 +924,11 @@ impl AsyncComponent for Login {
                 // Reset the session.
                 self.qr_code = None;
                 self.state.code = None;
+                self.state.qr_data = None;
                 self.state.pair_state = PairState::Pairing;
                 self.phone_number_view = LoginPhoneNumberView::EnterPhoneNumber;
                 self.state.scan_attempts = 0;
+                self.state.qr_session_started = None;
                 self.state.progress_fraction = 1.0;
                 self.state
                     .session_scan_expired
@@ -607,19 +941,27 @@ impl AsyncComponent for Login {
                 // Reset the QR code and progress bar.
                 self.state.progress_fraction = 1.0;
 
-                if self.state.scan_attempts >= 5 {
+                // Keep requesting codes for a while, then ask to reset by hand.
+                let started = *self
+                    .state
+                    .qr_session_started
+                    .get_or_insert_with(Instant::now);
+                if started.elapsed() >= MAX_QR_WAIT {
+                    self.qr_code = None;
                     self.state
                         .session_scan_expired
                         .store(true, Ordering::Release);
                     return;
                 }
                 self.state.scan_attempts += 1;
+                let attempt = self.state.scan_attempts;
 
                 // Generate the QR code.
                 let texture = Box::pin(generate_qr_code(&data, 200))
                     .await
                     .expect("Failed to generate QR code");
                 self.qr_code = Some(texture.current_image());
+                self.state.qr_data = Some(data);
 
                 // Make sure to not reset the qr code after it refreshes.
                 let timeout = timeout.saturating_sub(Duration::from_secs(2));
@@ -635,27 +977,51 @@ impl AsyncComponent for Login {
                             interval.tick().await;
 
                             while elapsed < timeout {
-                                command_sender.emit(LoginCommand::UpdateExpirationBar(fraction));
+                                command_sender.emit(LoginCommand::UpdateExpirationBar {
+                                    attempt,
+                                    progress: fraction,
+                                });
 
                                 elapsed = start.elapsed();
                                 fraction = 1.0 - (elapsed.as_secs_f32() / timeout.as_secs_f32());
                                 interval.tick().await;
                             }
 
-                            command_sender.emit(LoginCommand::QrCodeExpired);
+                            command_sender.emit(LoginCommand::QrCodeExpired(attempt));
                         })
                         .drop_on_shutdown()
                         .boxed()
                 });
             }
-            LoginCommand::QrCodeExpired => {
+            // Timers of codes already replaced are ignored.
+            LoginCommand::QrCodeExpired(attempt) if attempt == self.state.scan_attempts => {
                 // Reset the QR code and progress bar.
                 self.qr_code = None;
+                self.state.qr_data = None;
                 self.state.progress_fraction = 1.0;
+
+                sender.oneshot_command(async move {
+                    time::sleep(QR_REFRESH_GRACE).await;
+                    LoginCommand::RefreshQrCode(attempt)
+                });
+            }
+            LoginCommand::RefreshQrCode(attempt)
+                if attempt == self.state.scan_attempts
+                    && self.page == LoginPage::QrCode
+                    && !self.state.session_scan_expired.load(Ordering::Acquire) =>
+            {
+                // The session ran out of codes, get a fresh one without starting over.
+                tracing::debug!("No QR code after the last one expired, requesting a new one");
+                let _ = sender.output(LoginOutput::ResetSession);
             }
-            LoginCommand::UpdateExpirationBar(progress) => {
+            LoginCommand::UpdateExpirationBar { attempt, progress }
+                if attempt == self.state.scan_attempts =>
+            {
                 self.state.progress_fraction = f64::from(progress);
             }
+            LoginCommand::QrCodeExpired(_)
+            | LoginCommand::RefreshQrCode(_)
+            | LoginCommand::UpdateExpirationBar { .. } => {}
             LoginCommand::PairWithPhoneNumber { phone_number } => {
                 if self.state.valid_phone_number.load(Ordering::Acquire) {
                     let _ = sender.output(LoginOutput::PairWithPhoneNumber { phone_number });
@@ -677,6 +1043,9 @@ impl AsyncComponent for Login {
                         sanitazed = format!("+{sanitazed}");
                     }
 
+                    // A dialing code typed overrides the country picked.
+                    self.state.update_country(&sanitazed, &self.countries);
+
                     if let Ok(number) = sanitazed.parse::<PhoneNumber>() {
                         if number.is_valid() {
                             if !self.state.valid_phone_number.load(Ordering::Acquire) {
@@ -713,3 +1082,97 @@ impl AsyncComponent for Login {
         }
     }
 }
+
+/// Get the countries with phone number metadata, sorted by region code.
+fn countries() -> Vec<Country> {
+    let mut countries = PHONE_NUMBER_UTIL
+        .get_supported_regions()
+        .into_iter()
+        .filter(|region| region.len() == 2)
+        .map(|region| Country {
+            flag: region_flag(&region),
+            region: region.to_string(),
+            dial_code: PHONE_NUMBER_UTIL.get_country_code_for_region(&region),
+        })
+        .collect::<Vec<_>>();
+    countries.sort_by(|a, b| a.region.cmp(&b.region));
+
+    countries
+}
+
+/// Get the flag emoji of a region, made of its regional indicator symbols.
+fn region_flag(region: &str) -> String {
+    region
+        .chars()
+        .filter_map(|c| {
+            let offset = u32::from(c.to_ascii_uppercase()).checked_sub(u32::from('A'))?;
+            char::from_u32(0x1F1E6 + offset)
+        })
+        .collect()
+}
+
+/// Check if a number starting with `+` has a non-geographic dialing code.
+fn is_non_geographic(number: &str) -> bool {
+    let Some(digits) = number.strip_prefix('+') else {
+        return false;
+    };
+
+    (1..=3).any(|len| {
+        digits
+            .get(..len)
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| {
+                PHONE_NUMBER_UTIL.get_region_code_for_country_code(code) == NON_GEOGRAPHIC_REGION
+            })
+    })
+}
+
+/// Split the dialing code from a number starting with `+`.
+/// Returns the code and the rest of the number.
+fn split_dial_code<'a>(number: &'a str, countries: &[Country]) -> Option<(i32, &'a str)> {
+    let digits = number.strip_prefix('+')?;
+
+    // Dialing codes have up to 3 digits and none is the prefix of another.
+    (1..=3).find_map(|len| {
+        let dial_code = digits.get(..len)?.parse::<i32>().ok()?;
+        countries
+            .iter()
+            .any(|c| c.dial_code == dial_code)
+            .then(|| (dial_code, digits[len..].trim_start_matches([' ', '-'])))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_geographic_numbers_get_the_generic_flag() {
+        assert!(is_non_geographic("+881 6 12345678"));
+        assert!(is_non_geographic("+80012345678"));
+        assert!(!is_non_geographic("+5511999990000"));
+        assert!(!is_non_geographic("+1 202 555 0100"));
+        assert!(!is_non_geographic("881612345678"));
+        assert!(!is_non_geographic("+"));
+
+        let countries = countries();
+        let mut state = LoginState::default();
+        state.update_country("+55 11 99999-0000", &countries);
+        assert_eq!(state.selected_region.as_deref(), Some("BR"));
+        assert_eq!(state.phone_number_country_emoji.as_deref(), Some("🇧🇷"));
+
+        state.update_country("+881 6 12345678", &countries);
+        assert_eq!(state.selected_region, None);
+        assert_eq!(
+            state.phone_number_country_emoji.as_deref(),
+            Some(NON_GEOGRAPHIC_FLAG)
+        );
+    }
+
+    #[test]
+    fn flags_are_made_of_regional_indicators() {
+        assert_eq!(region_flag("BR"), "🇧🇷");
+        assert_eq!(region_flag("pt"), "🇵🇹");
+        assert!(countries().iter().all(|c| c.flag.chars().count() == 2));
+    }
+}
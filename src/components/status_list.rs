@@ -0,0 +1,382 @@
+use adw::prelude::*;
+use chrono::{DateTime, Local, Utc};
+use gtk::{glib, pango};
+use indexmap::IndexMap;
+use relm4::{
+    prelude::*,
+    typed_view::list::{RelmListItem, TypedListView},
+};
+
+use crate::{
+    i18n,
+    modals::status_viewer::{StatusViewer, StatusViewerInit, StatusViewerOutput},
+    state::StatusUpdate,
+    utils::{format_date_label, format_lid_as_number, icon_or_fallback},
+};
+
+pub struct StatusList {
+    /// Status viewer, while it's open.
+    viewer: Option<Controller<StatusViewer>>,
+    /// Status updates grouped by poster JID, oldest first.
+    updates: IndexMap<String, Vec<StatusUpdate>>,
+    /// `ListView` widget wrapper containing a row per poster.
+    list_view_wrapper: TypedListView<StatusRow, gtk::NoSelection>,
+}
+
+#[derive(Debug)]
+pub enum StatusListInput {
+    /// Add a status update.
+    Add(StatusUpdate),
+    /// Open the status updates of a poster by its position.
+    OpenPosition(u32),
+    /// A status update has been viewed.
+    Viewed(String),
+    /// The status viewer has been closed.
+    ViewerClosed,
+    /// Drop the status updates that have expired.
+    Expire,
+}
+
+#[derive(Debug)]
+pub enum StatusListOutput {
+    /// A status update has been viewed.
+    Viewed { id: String, sender_jid: String },
+    /// Status updates posted before this time have expired.
+    Expired(DateTime<Utc>),
+}
+
+#[relm4::component(async, pub)]
+impl SimpleAsyncComponent for StatusList {
+    type Init = ();
+    type Input = StatusListInput;
+    type Output = StatusListOutput;
+
+    view! {
+        gtk::Stack {
+            set_transition_type: gtk::StackTransitionType::Crossfade,
+
+            add_named[Some("empty")] = &adw::StatusPage {
+                set_title: &i18n!("No Status Updates"),
+                set_vexpand: true,
+                set_icon_name: Some(icon_or_fallback("image-round-symbolic", "image-x-generic-symbolic")),
+                set_description: Some(&i18n!("Status updates from your contacts will show up here")),
+                set_css_classes: &["compact"],
+            },
+
+            add_named[Some("statuses")] = &gtk::ScrolledWindow {
+                set_vexpand: true,
+                set_hscrollbar_policy: gtk::PolicyType::Never,
+                set_overlay_scrolling: true,
+
+                #[local_ref]
+                list_view -> gtk::ListView {
+                    set_css_classes: &["navigation-sidebar"],
+                    set_single_click_activate: true,
+                },
+            },
+
+            #[watch]
+            set_visible_child_name: if model.updates.is_empty() { "empty" } else { "statuses" },
+        }
+    }
+
+    async fn init(
+        _init: Self::Init,
+        root: Self::Root,
+        sender: AsyncComponentSender<Self>,
+    ) -> AsyncComponentParts<Self> {
+        let model = Self {
+            viewer: None,
+            updates: IndexMap::new(),
+            list_view_wrapper: TypedListView::new(),
+        };
+
+        let list_view = &model.list_view_wrapper.view;
+
+        let widgets = view_output!();
+
+        let input_sender = sender.input_sender().clone();
+        list_view.connect_activate(move |_, position| {
+            input_sender.emit(StatusListInput::OpenPosition(position));
+        });
+
+        // Status updates stop being shown a while after being posted.
+        let input_sender = sender.input_sender().clone();
+        glib::timeout_add_seconds_local(60, move || {
+            input_sender.emit(StatusListInput::Expire);
+            glib::ControlFlow::Continue
+        });
+
+        AsyncComponentParts { model, widgets }
+    }
+
+    async fn update(&mut self, input: Self::Input, sender: AsyncComponentSender<Self>) {
+        match input {
+            StatusListInput::Add(status) => {
+                if status.is_expired(Utc::now()) {
+                    return;
+                }
+
+                let updates = self.updates.entry(status.sender_jid.clone()).or_default();
+                if updates.iter().any(|u| u.id == status.id) {
+                    return;
+                }
+
+                updates.push(status);
+                updates.sort_by_key(|u| u.timestamp);
+                self.rebuild();
+            }
+            StatusListInput::OpenPosition(position) => {
+                let Some(item) = self.list_view_wrapper.get_visible(position) else {
+                    return;
+                };
+                let (jid, name) = {
+                    let row = item.borrow();
+                    (row.sender_jid.clone(), row.name.clone())
+                };
+
+                let Some(updates) = self.updates.get(&jid) else {
+                    return;
+                };
+
+                // Start from the first update not yet viewed.
+                let start = updates.iter().position(|u| !u.viewed).unwrap_or_default();
+
+                self.viewer = Some(
+                    StatusViewer::builder()
+                        .launch(StatusViewerInit {
+                            name,
+                            start,
+                            updates: updates.clone(),
+                        })
+                        .forward(sender.input_sender(), |output| match output {
+                            StatusViewerOutput::Viewed(id) => StatusListInput::Viewed(id),
+                            StatusViewerOutput::Closed => StatusListInput::ViewerClosed,
+                        }),
+                );
+            }
+            StatusListInput::Viewed(id) => {
+                let Some(status) = self
+                    .updates
+                    .values_mut()
+                    .flatten()
+                    .find(|u| u.id == id && !u.viewed)
+                else {
+                    return;
+                };
+
+                status.viewed = true;
+                let sender_jid = status.sender_jid.clone();
+                let _ = sender.output(StatusListOutput::Viewed { id, sender_jid });
+
+                self.rebuild();
+            }
+            StatusListInput::ViewerClosed => {
+                self.viewer = None;
+            }
+            StatusListInput::Expire => {
+                let now = Utc::now();
+                if drop_expired(&mut self.updates, now) {
+                    self.rebuild();
+                    let _ = sender.output(StatusListOutput::Expired(now - StatusUpdate::LIFETIME));
+                }
+            }
+        }
+    }
+}
+
+impl StatusList {
+    /// Rebuild the rows, posters with unviewed updates first.
+    fn rebuild(&mut self) {
+        let mut rows = self
+            .updates
+            .iter()
+            .filter_map(|(jid, updates)| {
+                let last = updates.last()?;
+                let name = updates
+                    .iter()
+                    .rev()
+                    .find_map(|u| u.sender_name.clone())
+                    .filter(|n| !n.trim().is_empty())
+                    .unwrap_or_else(|| format_lid_as_number(jid));
+
+                Some(StatusRow {
+                    name,
+                    unviewed: updates.iter().filter(|u| !u.viewed).count(),
+                    sender_jid: jid.clone(),
+                    last_update: last.timestamp.timestamp(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        rows.sort_by(|a, b| {
+            (b.unviewed > 0)
+                .cmp(&(a.unviewed > 0))
+                .then_with(|| b.last_update.cmp(&a.last_update))
+        });
+
+        self.list_view_wrapper.clear();
+        self.list_view_wrapper.extend_from_iter(rows);
+    }
+}
+
+/// Drop the status updates expired by `now`, and posters left without any,
+/// telling whether any was dropped.
+fn drop_expired(updates: &mut IndexMap<String, Vec<StatusUpdate>>, now: DateTime<Utc>) -> bool {
+    let count = updates.values().map(Vec::len).sum::<usize>();
+
+    for poster_updates in updates.values_mut() {
+        poster_updates.retain(|u| !u.is_expired(now));
+    }
+    updates.retain(|_, poster_updates| !poster_updates.is_empty());
+
+    updates.values().map(Vec::len).sum::<usize>() < count
+}
+
+/// A single poster row in the status list.
+#[derive(Clone, Debug)]
+pub struct StatusRow {
+    /// Poster's display name.
+    name: String,
+    /// How many status updates are unviewed.
+    unviewed: usize,
+    /// Poster identifier.
+    sender_jid: String,
+    /// Unix timestamp of the latest status update.
+    last_update: i64,
+}
+
+pub struct StatusRowWidgets {
+    /// Poster avatar.
+    avatar: adw::Avatar,
+    /// Ring around the avatar.
+    avatar_ring: gtk::Box,
+    /// Poster name.
+    title_label: gtk::Label,
+    /// Time of the latest status update.
+    subtitle_label: gtk::Label,
+}
+
+impl RelmListItem for StatusRow {
+    type Root = gtk::Box;
+    type Widgets = StatusRowWidgets;
+
+    fn setup(_list_item: &gtk::ListItem) -> (Self::Root, Self::Widgets) {
+        let root = gtk::Box::builder()
+            .spacing(12)
+            .orientation(gtk::Orientation::Horizontal)
+            .margin_start(4)
+            .margin_end(4)
+            .margin_top(8)
+            .margin_bottom(8)
+            .build();
+
+        let avatar_ring = gtk::Box::builder()
+            .valign(gtk::Align::Center)
+            .css_classes(["status-ring"])
+            .build();
+        let avatar = adw::Avatar::builder().size(36).show_initials(true).build();
+        avatar_ring.append(&avatar);
+        root.append(&avatar_ring);
+
+        let text_box = gtk::Box::builder()
+            .valign(gtk::Align::Center)
+            .hexpand(true)
+            .spacing(2)
+            .orientation(gtk::Orientation::Vertical)
+            .build();
+        root.append(&text_box);
+
+        let title_label = gtk::Label::builder()
+            .lines(1)
+            .xalign(0.0)
+            .ellipsize(pango::EllipsizeMode::End)
+            .width_chars(1)
+            .build();
+        text_box.append(&title_label);
+
+        let subtitle_label = gtk::Label::builder()
+            .lines(1)
+            .xalign(0.0)
+            .css_classes(["dimmed", "caption", "numeric"])
+            .build();
+        text_box.append(&subtitle_label);
+
+        let widgets = StatusRowWidgets {
+            avatar,
+            avatar_ring,
+            title_label,
+            subtitle_label,
+        };
+
+        (root, widgets)
+    }
+
+    fn bind(&mut self, widgets: &mut Self::Widgets, root: &mut Self::Root) {
+        root.set_widget_name(&self.sender_jid);
+
+        widgets.avatar.set_text(Some(&self.name));
+        widgets.title_label.set_label(&self.name);
+
+        if self.unviewed > 0 {
+            widgets.avatar_ring.remove_css_class("viewed");
+        } else {
+            widgets.avatar_ring.add_css_class("viewed");
+        }
+
+        let subtitle = DateTime::from_timestamp(self.last_update, 0)
+            .map(|timestamp| {
+                let timestamp = timestamp.with_timezone(&Local);
+                format!(
+                    "{}, {}",
+                    format_date_label(timestamp.date_naive()),
+                    timestamp.format("%H:%M")
+                )
+            })
+            .unwrap_or_default();
+        widgets.subtitle_label.set_label(&subtitle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    fn status(id: &str, sender_jid: &str, age: TimeDelta) -> StatusUpdate {
+        StatusUpdate {
+            id: id.into(),
+            viewed: false,
+            content: String::new(),
+            timestamp: Utc::now() - age,
+            sender_jid: sender_jid.into(),
+            sender_name: None,
+            media: None,
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn expired_updates_and_empty_posters_are_dropped() {
+        let mut updates = IndexMap::new();
+        updates.insert(
+            "alice".to_string(),
+            vec![
+                status("old", "alice", TimeDelta::hours(25)),
+                status("new", "alice", TimeDelta::hours(1)),
+            ],
+        );
+        updates.insert(
+            "bob".to_string(),
+            vec![status("old", "bob", TimeDelta::hours(30))],
+        );
+
+        assert!(drop_expired(&mut updates, Utc::now()));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates["alice"].len(), 1);
+        assert_eq!(updates["alice"][0].id, "new");
+
+        assert!(!drop_expired(&mut updates, Utc::now()));
+    }
+}
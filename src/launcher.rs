@@ -0,0 +1,37 @@
+use gtk::{glib, prelude::*};
+use relm4::{gtk, main_application};
+
+use crate::config::APP_ID;
+
+/// Object the launcher entry updates are emitted from.
+const OBJECT_PATH: &str = "/com/amanoteam/Papo/LauncherEntry";
+/// Interface docks listen to for badges on app icons.
+const LAUNCHER_ENTRY_INTERFACE: &str = "com.canonical.Unity.LauncherEntry";
+
+/// Show `count` as a badge on the app icon, or hide it when it's 0.
+///
+/// Uses the Unity launcher API, which Dash to Dock, Plank and KDE follow;
+/// where nothing listens to it the update is just dropped.
+pub fn set_badge(count: usize) {
+    let Some(connection) = main_application().dbus_connection() else {
+        return;
+    };
+
+    let properties = glib::VariantDict::new(None);
+    properties.insert("count", i64::try_from(count).unwrap_or(i64::MAX));
+    properties.insert("count-visible", count > 0);
+
+    let parameters = glib::Variant::tuple_from_iter([
+        format!("application://{APP_ID}.desktop").to_variant(),
+        properties.end(),
+    ]);
+    if let Err(e) = connection.emit_signal(
+        None,
+        OBJECT_PATH,
+        LAUNCHER_ENTRY_INTERFACE,
+        "Update",
+        Some(&parameters),
+    ) {
+        tracing::warn!("Failed to update the launcher badge: {e}");
+    }
+}
@@ -36,11 +36,21 @@
 #[rustfmt::skip]
 mod config;
 mod application;
+mod background;
+mod cli;
 mod components;
+mod launcher;
 mod modals;
+mod report;
+mod screencast;
+mod search_provider;
 mod session;
+mod sound;
 mod state;
 mod store;
+#[cfg(test)]
+mod test_utils;
+mod thumbnails;
 mod utils;
 mod widgets;
 
@@ -50,7 +60,10 @@ mod icon_names {
     include!(concat!(env!("OUT_DIR"), "/icon_names.rs"));
 }
 
-use std::{fs::create_dir_all, path::PathBuf, sync::LazyLock};
+use std::{
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
 
 use gettextrs::LocaleCategory;
 use gtk::{gio, glib, prelude::ApplicationExt};
@@ -97,10 +110,19 @@ fn main() {
     // Default to the INFO level for this crate and WARN for everything else.
     // It can be overridden with the RUST_LOG environment variable.
     let env_filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("papo=info,warn"));
+        || EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("papo=info,warn"));
+
+    // Also log to a file, to be attached to problem reports.
+    let file_layer = report::open_log_file().ok().map(|file| {
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
+            .with_filter(env_filter())
+    });
 
     tracing_subscriber::registry()
-        .with(fmt::layer().with_filter(env_filter))
+        .with(fmt::layer().with_filter(env_filter()))
+        .with(file_layer)
         .init();
 
     // Prepare i18n.
@@ -108,11 +130,6 @@ fn main() {
     gettextrs::bindtextdomain(GETTEXT_PACKAGE, LOCALEDIR).expect("Unable to bind the text domain");
     gettextrs::textdomain(GETTEXT_PACKAGE).expect("Unable to switch to the text domain");
 
-    // Create datadir if it's missing.
-    if let Err(e) = create_dir_all(DATA_DIR.as_path()) {
-        tracing::error!("Failed to create data dir: {e}");
-    }
-
     // Tell `relm4` to use 4 threads for asynchronous background tasks.
     RELM_THREADS
         .set(4)
@@ -129,10 +146,14 @@ fn main() {
 
     // Initialize custom resources.
     let app = main_application();
-    app.set_flags(gio::ApplicationFlags::HANDLES_OPEN);
+    app.set_flags(
+        gio::ApplicationFlags::HANDLES_OPEN | gio::ApplicationFlags::HANDLES_COMMAND_LINE,
+    );
     app.set_resource_base_path(Some("/com/amanoteam/Papo/"));
+    cli::setup(&app);
+    app.connect_startup(|_| search_provider::register());
 
-    let app = RelmApp::from_app(app);
+    let app = RelmApp::from_app(app).with_args(std::env::args().collect());
 
     tracing::info!("Papo ({})", APP_ID);
     tracing::info!("Version: {} ({})", VERSION, PROFILE);
@@ -147,6 +168,9 @@ fn main() {
         .unwrap();
     relm4::set_global_css(&glib::GString::from_utf8_checked(data.to_vec()).unwrap());
     relm4_icons::initialize_icons(icon_names::GRESOURCE_BYTES, icon_names::RESOURCE_PREFIX);
+    if PROFILE == "Devel" {
+        utils::check_icons();
+    }
 
     app.visible_on_activate(false).run_async::<Application>(());
 }
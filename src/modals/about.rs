@@ -4,6 +4,7 @@ use relm4::prelude::*;
 use crate::{
     config::{APP_ID, VERSION},
     i18n,
+    report::{debug_info, issue_url},
 };
 
 pub struct AboutDialog;
@@ -21,7 +22,9 @@ impl SimpleComponent for AboutDialog {
             .application_icon(APP_ID)
             .license_type(gtk::License::Apache20)
             .website("https://github.com/AmanoTeam/Papo")
-            .issue_url("https://github.com/AmanoTeam/Papo/issues")
+            .issue_url(issue_url())
+            .debug_info(debug_info())
+            .debug_info_filename("papo-debug-info.txt")
             .version(VERSION)
             .copyright("© 2026 Andriel Ferreira")
             .developers([
@@ -0,0 +1,90 @@
+use adw::prelude::*;
+use gtk::gio;
+use relm4::prelude::*;
+
+use crate::{config::APP_ID, i18n, i18n_f, utils::risky_attachment_type};
+
+/// Open an attachment, warning first if it can run code. With `choose_app`,
+/// the app to open it with is asked for instead of using the default one.
+pub fn open_attachment(file: &gio::File, mime_type: &str, choose_app: bool) {
+    let file_name = file
+        .basename()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let trusted = gio::Settings::new(APP_ID).strv("trusted-attachment-types");
+    match risky_attachment_type(mime_type, &file_name) {
+        Some(file_type) if !trusted.iter().any(|t| t.eq_ignore_ascii_case(file_type)) => {
+            AttachmentWarningDialog::builder()
+                .launch(AttachmentWarningInit {
+                    file: file.clone(),
+                    file_type,
+                    choose_app,
+                })
+                .detach();
+        }
+        _ => launch(file, choose_app),
+    }
+}
+
+/// Open a file with its default application, or one picked for it.
+fn launch(file: &gio::File, choose_app: bool) {
+    let window = relm4::main_adw_application().windows().into_iter().next();
+    let launcher = gtk::FileLauncher::new(Some(file));
+    launcher.set_always_ask(choose_app);
+    launcher.launch(window.as_ref(), gio::Cancellable::NONE, |result| {
+        if let Err(e) = result {
+            tracing::error!("Failed to open attachment: {e}");
+        }
+    });
+}
+
+pub struct AttachmentWarningDialog;
+
+pub struct AttachmentWarningInit {
+    /// The attachment to open.
+    pub file: gio::File,
+    /// Type (extension) of the attachment.
+    pub file_type: &'static str,
+    /// Whether to ask for the app to open it with.
+    pub choose_app: bool,
+}
+
+impl SimpleComponent for AttachmentWarningDialog {
+    type Init = AttachmentWarningInit;
+    type Root = adw::AlertDialog;
+    type Input = ();
+    type Output = ();
+    type Widgets = adw::AlertDialog;
+
+    fn init_root() -> Self::Root {
+        adw::AlertDialog::builder()
+            .heading(i18n!("Open Risky File?"))
+            .default_response("cancel")
+            .close_response("cancel")
+            .build()
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        _sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {};
+        let widgets = root;
+
+        widgets.set_body(&i18n_f!(
+            "This is a {0} file, which can run code on your computer. Only open it if you trust the sender.",
+            init.file_type.to_uppercase()
+        ));
+        widgets.add_responses(&[("cancel", &i18n!("_Cancel")), ("open", &i18n!("_Open"))]);
+        widgets.set_response_appearance("open", adw::ResponseAppearance::Destructive);
+
+        let file = init.file;
+        widgets.connect_response(Some("open"), move |_, _| launch(&file, init.choose_app));
+
+        widgets.present(Some(&relm4::main_adw_application().windows()[0]));
+
+        ComponentParts { model, widgets }
+    }
+}
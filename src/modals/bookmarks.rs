@@ -0,0 +1,195 @@
+use std::{collections::HashMap, sync::Arc};
+
+use adw::prelude::*;
+use chrono::Local;
+use gtk::glib;
+use relm4::prelude::*;
+
+use crate::{
+    i18n,
+    store::{Bookmark, Database},
+    utils::icon_or_fallback,
+};
+
+/// Messages bookmarked on this device, grouped by chat.
+pub struct BookmarksDialog;
+
+pub struct BookmarksInit {
+    pub db: Arc<Database>,
+    /// Bookmarks to list, grouped by chat.
+    pub bookmarks: Vec<Bookmark>,
+    /// Names of the chats (JID -> name).
+    pub chat_names: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub enum BookmarksOutput {
+    /// Jump to a bookmarked message.
+    Open { chat_jid: String, msg_id: String },
+    /// Export the bookmarks to a file.
+    Export,
+    /// The dialog has been closed.
+    Closed,
+}
+
+impl SimpleComponent for BookmarksDialog {
+    type Init = BookmarksInit;
+    type Root = adw::Dialog;
+    type Input = ();
+    type Output = BookmarksOutput;
+    type Widgets = adw::Dialog;
+
+    fn init_root() -> Self::Root {
+        adw::Dialog::builder()
+            .title(i18n!("Bookmarks"))
+            .content_width(480)
+            .content_height(600)
+            .build()
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {};
+        let widgets = root;
+
+        let export_button = gtk::Button::builder()
+            .label(i18n!("_Export…"))
+            .use_underline(true)
+            .sensitive(!init.bookmarks.is_empty())
+            .build();
+        let output_sender = sender.clone();
+        export_button.connect_clicked(move |_| {
+            let _ = output_sender.output(BookmarksOutput::Export);
+        });
+
+        let header_bar = adw::HeaderBar::builder()
+            .title_widget(
+                &adw::WindowTitle::builder()
+                    .title(i18n!("Bookmarks"))
+                    .subtitle(i18n!("Kept on this device only"))
+                    .build(),
+            )
+            .build();
+        header_bar.pack_start(&export_button);
+
+        let page = adw::PreferencesPage::builder()
+            .description(i18n!(
+                "Bookmarks aren't starred messages and don't sync to your phone"
+            ))
+            .build();
+
+        let mut groups: HashMap<String, adw::PreferencesGroup> = HashMap::new();
+        for bookmark in &init.bookmarks {
+            let group = groups.entry(bookmark.chat_jid.clone()).or_insert_with(|| {
+                let group = adw::PreferencesGroup::builder()
+                    .title(
+                        init.chat_names
+                            .get(&bookmark.chat_jid)
+                            .map_or(bookmark.chat_jid.as_str(), String::as_str),
+                    )
+                    .build();
+                page.add(&group);
+                group
+            });
+            add_bookmark_row(group, &init.db, bookmark, &sender);
+        }
+
+        let content: gtk::Widget = if init.bookmarks.is_empty() {
+            adw::StatusPage::builder()
+                .title(i18n!("No Bookmarks"))
+                .description(i18n!(
+                    "Bookmark messages from their menu to find them here. Bookmarks stay on this device"
+                ))
+                .build()
+                .upcast()
+        } else {
+            page.upcast()
+        };
+
+        let toolbar_view = adw::ToolbarView::builder().content(&content).build();
+        toolbar_view.add_top_bar(&header_bar);
+        widgets.set_child(Some(&toolbar_view));
+
+        widgets.connect_closed(move |_| {
+            let _ = sender.output(BookmarksOutput::Closed);
+        });
+        widgets.present(Some(&relm4::main_adw_application().windows()[0]));
+
+        ComponentParts { model, widgets }
+    }
+}
+
+/// Add a row jumping to a bookmarked message, with a button to remove it.
+fn add_bookmark_row(
+    group: &adw::PreferencesGroup,
+    db: &Arc<Database>,
+    bookmark: &Bookmark,
+    sender: &ComponentSender<BookmarksDialog>,
+) {
+    let title = bookmark
+        .content
+        .as_deref()
+        .filter(|c| !c.is_empty())
+        .map_or_else(|| i18n!("Message not stored"), |c| c.replace('\n', " "));
+    let date = bookmark
+        .timestamp
+        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string());
+    let subtitle = [bookmark.note.clone().filter(|n| !n.is_empty()), date]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" · ");
+
+    let row = adw::ActionRow::builder()
+        .title(glib::markup_escape_text(&title))
+        .subtitle(glib::markup_escape_text(&subtitle))
+        .title_lines(2)
+        .activatable(true)
+        .build();
+
+    let remove_button = gtk::Button::builder()
+        .icon_name(icon_or_fallback(
+            "user-trash-symbolic",
+            "edit-delete-symbolic",
+        ))
+        .valign(gtk::Align::Center)
+        .tooltip_text(i18n!("Remove Bookmark"))
+        .css_classes(["flat"])
+        .build();
+    remove_button.connect_clicked({
+        let db = Arc::clone(db);
+        let group = group.clone();
+        let row = row.clone();
+        let chat_jid = bookmark.chat_jid.clone();
+        let msg_id = bookmark.message_id.clone();
+        move |_| {
+            group.remove(&row);
+
+            let db = Arc::clone(&db);
+            let (chat_jid, msg_id) = (chat_jid.clone(), msg_id.clone());
+            relm4::spawn(async move {
+                if let Err(e) = db.remove_bookmark(&chat_jid, &msg_id).await {
+                    tracing::error!("Failed to remove bookmark: {e}");
+                }
+            });
+        }
+    });
+    row.add_suffix(&remove_button);
+
+    row.connect_activated({
+        let sender = sender.clone();
+        let chat_jid = bookmark.chat_jid.clone();
+        let msg_id = bookmark.message_id.clone();
+        move |_| {
+            let _ = sender.output(BookmarksOutput::Open {
+                chat_jid: chat_jid.clone(),
+                msg_id: msg_id.clone(),
+            });
+        }
+    });
+
+    group.add(&row);
+}
@@ -0,0 +1,266 @@
+use std::time::{Duration, Instant};
+
+use adw::prelude::*;
+use gtk::glib;
+use relm4::prelude::*;
+
+use crate::{i18n, utils::icon_or_fallback};
+
+pub struct CallDialog {
+    /// Display name of the other side.
+    name: String,
+    /// Whether the call has ended, to hang up once.
+    ended: bool,
+    /// Whether our microphone is muted.
+    muted: bool,
+    /// Timer refreshing the elapsed time, while the call is ongoing.
+    timer: Option<glib::SourceId>,
+    /// The dialog itself.
+    dialog: adw::Dialog,
+    /// Call ID.
+    call_id: String,
+    /// Whether it's a video call.
+    is_video: bool,
+    /// When the call was answered.
+    answered_at: Option<Instant>,
+}
+
+pub struct CallDialogInit {
+    /// Display name of the other side.
+    pub name: String,
+    /// Call ID.
+    pub call_id: String,
+    /// Whether the call was already answered, as when accepting one.
+    pub answered: bool,
+    /// Whether it's a video call.
+    pub is_video: bool,
+}
+
+#[derive(Debug)]
+pub enum CallDialogInput {
+    /// The call has been answered.
+    Answered,
+    /// The call has ended, from either side.
+    Ended,
+    /// Mute or unmute our microphone.
+    ToggleMute,
+    /// Hang up the call.
+    HangUp,
+    /// A second has passed since the last refresh of the elapsed time.
+    Tick,
+    /// The dialog has been closed.
+    Closed,
+}
+
+#[derive(Debug)]
+pub enum CallDialogOutput {
+    /// Hang up the call.
+    HangUp(String),
+    /// The dialog has been closed.
+    Closed,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for CallDialog {
+    type Init = CallDialogInit;
+    type Input = CallDialogInput;
+    type Output = CallDialogOutput;
+
+    view! {
+        adw::Dialog {
+            set_content_width: 360,
+            set_title: &model.title(),
+            set_css_classes: &["call-dialog"],
+
+            connect_closed => CallDialogInput::Closed,
+
+            #[wrap(Some)]
+            set_child = &adw::ToolbarView {
+                add_top_bar = &adw::HeaderBar {
+                    set_show_end_title_buttons: false,
+                },
+
+                #[wrap(Some)]
+                set_content = &gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 12,
+                    set_margin_all: 24,
+
+                    adw::Avatar {
+                        set_size: 96,
+                        set_show_initials: true,
+                        set_text: Some(&model.name),
+                    },
+
+                    gtk::Label {
+                        set_label: &model.name,
+                        set_wrap: true,
+                        set_justify: gtk::Justification::Center,
+                        set_css_classes: &["title-2"],
+                    },
+
+                    gtk::Label {
+                        #[watch]
+                        set_label: &model.status(),
+                        set_css_classes: &["numeric", "dimmed"],
+                    },
+
+                    gtk::Box {
+                        set_halign: gtk::Align::Center,
+                        set_spacing: 24,
+                        set_margin_top: 12,
+
+                        gtk::ToggleButton {
+                            #[watch]
+                            set_active: model.muted,
+                            #[watch]
+                            set_icon_name: if model.muted {
+                                icon_or_fallback("microphone-disabled-symbolic", "audio-input-microphone-muted-symbolic")
+                            } else {
+                                icon_or_fallback("microphone-sensitive-symbolic", "audio-input-microphone-symbolic")
+                            },
+                            #[watch]
+                            set_tooltip_text: Some(&if model.muted { i18n!("Unmute") } else { i18n!("Mute") }),
+                            #[watch]
+                            set_sensitive: !model.ended,
+                            set_css_classes: &["circular"],
+
+                            connect_clicked => CallDialogInput::ToggleMute,
+                        },
+
+                        gtk::Button {
+                            set_icon_name: icon_or_fallback("call-stop-symbolic", "call-stop"),
+                            set_tooltip_text: Some(&i18n!("Hang Up")),
+                            #[watch]
+                            set_sensitive: !model.ended,
+                            set_css_classes: &["circular", "destructive-action"],
+
+                            connect_clicked => CallDialogInput::HangUp,
+                        },
+                    },
+                },
+            },
+        }
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let mut model = Self {
+            name: init.name,
+            ended: false,
+            muted: false,
+            timer: None,
+            dialog: root.clone(),
+            call_id: init.call_id,
+            is_video: init.is_video,
+            answered_at: None,
+        };
+
+        let widgets = view_output!();
+
+        if init.answered {
+            model.start_timer(&sender);
+        }
+        root.present(relm4::main_adw_application().active_window().as_ref());
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, input: Self::Input, sender: ComponentSender<Self>) {
+        match input {
+            CallDialogInput::Answered => {
+                if self.answered_at.is_none() && !self.ended {
+                    self.start_timer(&sender);
+                }
+            }
+            CallDialogInput::Ended => {
+                self.end();
+                self.dialog.force_close();
+            }
+            CallDialogInput::ToggleMute => self.muted = !self.muted,
+            CallDialogInput::HangUp => self.dialog.close(),
+            CallDialogInput::Tick => {}
+            CallDialogInput::Closed => {
+                // Closing the dialog hangs up, unless the call is already over.
+                if !self.ended {
+                    self.end();
+                    let _ = sender.output(CallDialogOutput::HangUp(self.call_id.clone()));
+                }
+                let _ = sender.output(CallDialogOutput::Closed);
+            }
+        }
+    }
+}
+
+impl CallDialog {
+    /// Get the ID of the call.
+    pub fn call_id(&self) -> &str {
+        &self.call_id
+    }
+
+    /// Start counting the elapsed time from now.
+    fn start_timer(&mut self, sender: &ComponentSender<Self>) {
+        self.answered_at = Some(Instant::now());
+
+        let input_sender = sender.input_sender().clone();
+        self.timer = Some(glib::timeout_add_seconds_local(1, move || {
+            input_sender.emit(CallDialogInput::Tick);
+            glib::ControlFlow::Continue
+        }));
+    }
+
+    /// Mark the call as over and stop the timer.
+    fn end(&mut self) {
+        self.ended = true;
+        if let Some(timer) = self.timer.take() {
+            timer.remove();
+        }
+    }
+
+    /// Get the title of the dialog, telling voice and video calls apart.
+    fn title(&self) -> String {
+        if self.is_video {
+            i18n!("Video Call")
+        } else {
+            i18n!("Voice Call")
+        }
+    }
+
+    /// Get the state of the call, or its elapsed time once answered.
+    fn status(&self) -> String {
+        match self.answered_at {
+            _ if self.ended => i18n!("Call Ended"),
+            Some(answered_at) => format_elapsed(answered_at.elapsed()),
+            None => i18n!("Ringing…"),
+        }
+    }
+}
+
+/// Format the elapsed time of a call as minutes and seconds, with the hours
+/// in front once it passes an hour.
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed_time_shows_hours_only_past_an_hour() {
+        assert_eq!(format_elapsed(Duration::from_secs(0)), "0:00");
+        assert_eq!(format_elapsed(Duration::from_secs(75)), "1:15");
+        assert_eq!(format_elapsed(Duration::from_secs(3599)), "59:59");
+        assert_eq!(format_elapsed(Duration::from_secs(3661)), "1:01:01");
+    }
+}
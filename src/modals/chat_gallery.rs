@@ -0,0 +1,462 @@
+use std::sync::Arc;
+
+use adw::prelude::*;
+use chrono::{DateTime, Local, Utc};
+use gtk::glib;
+use relm4::prelude::*;
+
+use crate::{
+    i18n,
+    state::{ChatDocument, ChatLink, ChatMessage, MediaType},
+    store::Database,
+    thumbnails,
+    utils::{format_date_label, icon_or_fallback},
+};
+
+/// Number of items loaded at once in each tab.
+const PAGE_SIZE: u32 = 60;
+
+/// Media, links and documents sent in a chat, in tabs loaded page by page.
+pub struct ChatGallery {
+    db: Arc<Database>,
+    /// Loading state of the links tab.
+    links: Paging,
+    /// Loading state of the media tab.
+    media: Paging,
+    /// JID of the chat.
+    chat_jid: String,
+    /// Loading state of the documents tab.
+    documents: Paging,
+    /// List of links.
+    links_list: gtk::ListBox,
+    /// Grid of image and video thumbnails.
+    media_grid: gtk::FlowBox,
+    /// List of documents.
+    documents_list: gtk::ListBox,
+}
+
+/// How far a tab has been loaded.
+#[derive(Debug, Default)]
+struct Paging {
+    /// Number of items loaded.
+    loaded: u32,
+    /// Whether a page is being loaded.
+    loading: bool,
+    /// Whether all items have been loaded.
+    exhausted: bool,
+}
+
+impl Paging {
+    /// Checks if the tab has nothing to show.
+    fn is_empty(&self) -> bool {
+        self.exhausted && self.loaded == 0
+    }
+
+    /// Count a page of `count` items as loaded.
+    fn page_loaded(&mut self, count: usize) {
+        let count = u32::try_from(count).unwrap_or(u32::MAX);
+        self.loaded += count;
+        self.loading = false;
+        self.exhausted = count < PAGE_SIZE;
+    }
+}
+
+pub struct ChatGalleryInit {
+    pub db: Arc<Database>,
+    /// JID of the chat.
+    pub chat_jid: String,
+    /// Name of the chat.
+    pub chat_name: String,
+}
+
+/// Tabs of the gallery.
+#[derive(Clone, Copy, Debug)]
+pub enum GalleryTab {
+    Media,
+    Links,
+    Documents,
+}
+
+#[derive(Debug)]
+pub enum ChatGalleryInput {
+    /// Load the next page of a tab.
+    LoadMore(GalleryTab),
+    /// The dialog has been closed.
+    Closed,
+}
+
+#[derive(Debug)]
+pub enum ChatGalleryOutput {
+    /// The dialog has been closed.
+    Closed,
+}
+
+#[derive(Debug)]
+pub enum ChatGalleryCommand {
+    /// A page of images and videos has been loaded.
+    MediaLoaded(Vec<ChatMessage>),
+    /// A page of links has been loaded.
+    LinksLoaded(Vec<ChatLink>),
+    /// A page of documents has been loaded.
+    DocumentsLoaded(Vec<ChatDocument>),
+}
+
+#[relm4::component(pub)]
+impl Component for ChatGallery {
+    type Init = ChatGalleryInit;
+    type Input = ChatGalleryInput;
+    type Output = ChatGalleryOutput;
+    type CommandOutput = ChatGalleryCommand;
+
+    view! {
+        adw::Dialog {
+            set_title: &i18n!("Media, Links and Docs"),
+            set_content_width: 560,
+            set_content_height: 640,
+
+            connect_closed => ChatGalleryInput::Closed,
+
+            #[wrap(Some)]
+            set_child = &adw::ToolbarView {
+                add_top_bar = &adw::HeaderBar {
+                    #[wrap(Some)]
+                    set_title_widget = &adw::WindowTitle {
+                        set_title: &i18n!("Media, Links and Docs"),
+                        set_subtitle: &init.chat_name,
+                    },
+                },
+
+                add_top_bar = &adw::ViewSwitcherBar {
+                    set_stack: Some(&view_stack),
+                    set_reveal: true,
+                },
+
+                #[name = "view_stack"]
+                #[wrap(Some)]
+                set_content = &adw::ViewStack {
+                    add_titled_with_icon[Some("media"), &i18n!("Media"), icon_or_fallback("image-round-symbolic", "image-x-generic-symbolic")] = &gtk::Stack {
+                        add_named[Some("empty")] = &adw::StatusPage {
+                            set_icon_name: Some(icon_or_fallback("image-round-symbolic", "image-x-generic-symbolic")),
+                            set_title: &i18n!("No Media"),
+                            set_description: Some(&i18n!("Photos and videos sent in this chat show up here")),
+                        },
+
+                        add_named[Some("items")] = &gtk::ScrolledWindow {
+                            set_hscrollbar_policy: gtk::PolicyType::Never,
+                            connect_edge_reached[sender] => move |_, position| {
+                                if position == gtk::PositionType::Bottom {
+                                    sender.input(ChatGalleryInput::LoadMore(GalleryTab::Media));
+                                }
+                            },
+
+                            #[local_ref]
+                            media_grid -> gtk::FlowBox {
+                                set_valign: gtk::Align::Start,
+                                set_margin_all: 12,
+                                set_row_spacing: 6,
+                                set_column_spacing: 6,
+                                set_homogeneous: true,
+                                set_max_children_per_line: 8,
+                                set_selection_mode: gtk::SelectionMode::None,
+                            },
+                        },
+
+                        #[watch]
+                        set_visible_child_name: if model.media.is_empty() { "empty" } else { "items" },
+                    },
+
+                    add_titled_with_icon[Some("links"), &i18n!("Links"), icon_or_fallback("chain-link-symbolic", "insert-link-symbolic")] = &gtk::Stack {
+                        add_named[Some("empty")] = &adw::StatusPage {
+                            set_icon_name: Some(icon_or_fallback("chain-link-symbolic", "insert-link-symbolic")),
+                            set_title: &i18n!("No Links"),
+                            set_description: Some(&i18n!("Links sent in this chat show up here")),
+                        },
+
+                        add_named[Some("items")] = &gtk::ScrolledWindow {
+                            set_hscrollbar_policy: gtk::PolicyType::Never,
+                            connect_edge_reached[sender] => move |_, position| {
+                                if position == gtk::PositionType::Bottom {
+                                    sender.input(ChatGalleryInput::LoadMore(GalleryTab::Links));
+                                }
+                            },
+
+                            adw::Clamp {
+                                #[local_ref]
+                                links_list -> gtk::ListBox {
+                                    set_valign: gtk::Align::Start,
+                                    set_margin_all: 12,
+                                    set_selection_mode: gtk::SelectionMode::None,
+                                    set_css_classes: &["boxed-list"],
+                                },
+                            },
+                        },
+
+                        #[watch]
+                        set_visible_child_name: if model.links.is_empty() { "empty" } else { "items" },
+                    },
+
+                    add_titled_with_icon[Some("documents"), &i18n!("Docs"), icon_or_fallback("paper-symbolic", "x-office-document-symbolic")] = &gtk::Stack {
+                        add_named[Some("empty")] = &adw::StatusPage {
+                            set_icon_name: Some(icon_or_fallback("paper-symbolic", "x-office-document-symbolic")),
+                            set_title: &i18n!("No Documents"),
+                            set_description: Some(&i18n!("Documents sent in this chat show up here")),
+                        },
+
+                        add_named[Some("items")] = &gtk::ScrolledWindow {
+                            set_hscrollbar_policy: gtk::PolicyType::Never,
+                            connect_edge_reached[sender] => move |_, position| {
+                                if position == gtk::PositionType::Bottom {
+                                    sender.input(ChatGalleryInput::LoadMore(GalleryTab::Documents));
+                                }
+                            },
+
+                            adw::Clamp {
+                                #[local_ref]
+                                documents_list -> gtk::ListBox {
+                                    set_valign: gtk::Align::Start,
+                                    set_margin_all: 12,
+                                    set_selection_mode: gtk::SelectionMode::None,
+                                    set_css_classes: &["boxed-list"],
+                                },
+                            },
+                        },
+
+                        #[watch]
+                        set_visible_child_name: if model.documents.is_empty() { "empty" } else { "items" },
+                    },
+                },
+            },
+        }
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {
+            db: Arc::clone(&init.db),
+            chat_jid: init.chat_jid.clone(),
+            media: Paging::default(),
+            links: Paging::default(),
+            documents: Paging::default(),
+            media_grid: gtk::FlowBox::new(),
+            links_list: gtk::ListBox::new(),
+            documents_list: gtk::ListBox::new(),
+        };
+
+        let media_grid = &model.media_grid;
+        let links_list = &model.links_list;
+        let documents_list = &model.documents_list;
+        let widgets = view_output!();
+
+        // Fill the first page of every tab.
+        for tab in [GalleryTab::Media, GalleryTab::Links, GalleryTab::Documents] {
+            sender.input(ChatGalleryInput::LoadMore(tab));
+        }
+
+        root.present(Some(&relm4::main_adw_application().windows()[0]));
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, input: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        match input {
+            ChatGalleryInput::LoadMore(tab) => {
+                let paging = self.paging_mut(tab);
+                if paging.loading || paging.exhausted {
+                    return;
+                }
+                paging.loading = true;
+                let offset = paging.loaded;
+
+                let db = Arc::clone(&self.db);
+                let chat_jid = self.chat_jid.clone();
+                sender.oneshot_command(async move {
+                    match tab {
+                        GalleryTab::Media => ChatGalleryCommand::MediaLoaded(
+                            db.load_chat_media(&chat_jid, offset, PAGE_SIZE)
+                                .await
+                                .unwrap_or_else(|e| {
+                                    tracing::error!("Failed to load media of {chat_jid}: {e}");
+                                    Vec::new()
+                                }),
+                        ),
+                        GalleryTab::Links => ChatGalleryCommand::LinksLoaded(
+                            db.load_chat_links(&chat_jid, offset, PAGE_SIZE)
+                                .await
+                                .unwrap_or_else(|e| {
+                                    tracing::error!("Failed to load links of {chat_jid}: {e}");
+                                    Vec::new()
+                                }),
+                        ),
+                        GalleryTab::Documents => ChatGalleryCommand::DocumentsLoaded(
+                            db.load_chat_documents(&chat_jid, offset, PAGE_SIZE)
+                                .await
+                                .unwrap_or_else(|e| {
+                                    tracing::error!("Failed to load documents of {chat_jid}: {e}");
+                                    Vec::new()
+                                }),
+                        ),
+                    }
+                });
+            }
+            ChatGalleryInput::Closed => {
+                let _ = sender.output(ChatGalleryOutput::Closed);
+            }
+        }
+    }
+
+    fn update_cmd(
+        &mut self,
+        message: Self::CommandOutput,
+        _sender: ComponentSender<Self>,
+        _root: &Self::Root,
+    ) {
+        match message {
+            ChatGalleryCommand::MediaLoaded(messages) => {
+                for message in &messages {
+                    self.media_grid.append(&media_tile(message));
+                }
+                self.media.page_loaded(messages.len());
+            }
+            ChatGalleryCommand::LinksLoaded(links) => {
+                for link in &links {
+                    self.links_list.append(&link_row(link));
+                }
+                self.links.page_loaded(links.len());
+            }
+            ChatGalleryCommand::DocumentsLoaded(documents) => {
+                for document in &documents {
+                    self.documents_list.append(&document_row(document));
+                }
+                self.documents.page_loaded(documents.len());
+            }
+        }
+    }
+}
+
+impl ChatGallery {
+    /// Get the loading state of a tab.
+    fn paging_mut(&mut self, tab: GalleryTab) -> &mut Paging {
+        match tab {
+            GalleryTab::Media => &mut self.media,
+            GalleryTab::Links => &mut self.links,
+            GalleryTab::Documents => &mut self.documents,
+        }
+    }
+}
+
+/// Get when something was sent, like "Yesterday, 14:32".
+fn format_sent_at(timestamp: DateTime<Utc>) -> String {
+    let timestamp = timestamp.with_timezone(&Local);
+    format!(
+        "{}, {}",
+        format_date_label(timestamp.date_naive()),
+        timestamp.format("%H:%M")
+    )
+}
+
+/// Build a thumbnail opening an image in the viewer, or a video in an app.
+/// Images are loaded and decoded off the main thread once the tile is built.
+fn media_tile(message: &ChatMessage) -> gtk::Button {
+    let is_video = message
+        .media
+        .as_ref()
+        .is_some_and(|media| matches!(media.r#type, MediaType::Video));
+
+    let picture = gtk::Picture::builder()
+        .width_request(112)
+        .height_request(112)
+        .content_fit(gtk::ContentFit::Cover)
+        .css_classes(["gallery-thumbnail"])
+        .build();
+
+    let placeholder = gtk::Image::builder()
+        .icon_name(if is_video {
+            icon_or_fallback("media-playback-start-symbolic", "media-playback-start")
+        } else {
+            icon_or_fallback("image-round-symbolic", "image-x-generic-symbolic")
+        })
+        .halign(gtk::Align::Center)
+        .valign(gtk::Align::Center)
+        .css_classes(["osd", "circular"])
+        .build();
+
+    let overlay = gtk::Overlay::builder().child(&picture).build();
+    overlay.add_overlay(&placeholder);
+
+    if !is_video {
+        let message = message.clone();
+        let picture = picture.downgrade();
+        let placeholder = placeholder.downgrade();
+        relm4::spawn_local(async move {
+            let Some(texture) = thumbnails::load_stored("image", &message).await else {
+                return;
+            };
+            if let (Some(picture), Some(placeholder)) = (picture.upgrade(), placeholder.upgrade()) {
+                picture.set_paintable(Some(&texture));
+                placeholder.set_visible(false);
+            }
+        });
+    }
+
+    let button = gtk::Button::builder()
+        .child(&overlay)
+        .action_name(if is_video {
+            "app.open-media-with"
+        } else {
+            "app.view-image"
+        })
+        .tooltip_text(format_sent_at(message.timestamp))
+        .css_classes(["flat", "gallery-tile"])
+        .build();
+    button.set_action_target_value(Some(
+        &(message.chat_jid.clone(), message.server_id.clone()).to_variant(),
+    ));
+    button
+}
+
+/// Build a row opening a link in the browser.
+fn link_row(link: &ChatLink) -> adw::ActionRow {
+    let row = adw::ActionRow::builder()
+        .title(glib::markup_escape_text(link.display_title()))
+        .subtitle(glib::markup_escape_text(&link.url))
+        .subtitle_lines(1)
+        .tooltip_text(format_sent_at(link.timestamp))
+        .activatable(true)
+        .action_name("app.open-link")
+        .build();
+    row.set_action_target_value(Some(&link.url.to_variant()));
+    row.add_suffix(&gtk::Image::from_icon_name(icon_or_fallback(
+        "adw-external-link-symbolic",
+        "go-next-symbolic",
+    )));
+    row
+}
+
+/// Build a row opening a document in an app.
+fn document_row(document: &ChatDocument) -> adw::ActionRow {
+    let title = if document.content.is_empty() {
+        i18n!("Document")
+    } else {
+        document.content.replace('\n', " ")
+    };
+    let subtitle = format!(
+        "{} · {}",
+        glib::format_size(document.size),
+        format_sent_at(document.timestamp)
+    );
+
+    let row = adw::ActionRow::builder()
+        .title(glib::markup_escape_text(&title))
+        .subtitle(subtitle)
+        .title_lines(1)
+        .activatable(true)
+        .action_name("app.open-media-with")
+        .build();
+    row.set_action_target_value(Some(
+        &(document.chat_jid.clone(), document.message_id.clone()).to_variant(),
+    ));
+    row
+}
@@ -0,0 +1,280 @@
+use adw::prelude::*;
+use gtk::{gdk, glib, pango};
+use relm4::prelude::*;
+use rlibphonenumber::PhoneNumber;
+
+use crate::{i18n, i18n_f};
+
+/// Name of the row to message the number typed, which can't be a JID.
+const NUMBER_ROW: &str = "message-number";
+
+/// Quick switcher to jump to a chat by typing its name.
+pub struct ChatSwitcher {
+    /// Row to message the number typed, while it's checked.
+    number_row: gtk::ListBoxRow,
+    /// Why the number typed can't be messaged.
+    error_label: gtk::Label,
+}
+
+pub struct ChatSwitcherInit {
+    /// Chats to switch to (JID, name), most recent first.
+    pub chats: Vec<(String, String)>,
+    /// Widget to show the switcher over.
+    pub parent: gtk::Widget,
+}
+
+#[derive(Debug)]
+pub enum ChatSwitcherInput {
+    /// The number typed can't be messaged.
+    NumberFailed(String),
+}
+
+#[derive(Debug)]
+pub enum ChatSwitcherOutput {
+    /// A chat has been chosen.
+    Selected(String),
+    /// Message a phone number, digits only, if it's on `WhatsApp`.
+    MessageNumber(String),
+    /// The switcher has been closed.
+    Closed,
+}
+
+impl SimpleComponent for ChatSwitcher {
+    type Init = ChatSwitcherInit;
+    type Root = gtk::Popover;
+    type Input = ChatSwitcherInput;
+    type Output = ChatSwitcherOutput;
+    type Widgets = gtk::Popover;
+
+    fn init_root() -> Self::Root {
+        gtk::Popover::builder()
+            .has_arrow(false)
+            .position(gtk::PositionType::Bottom)
+            .css_classes(["chat-switcher"])
+            .build()
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let widgets = root;
+
+        let search_entry = gtk::SearchEntry::builder()
+            .placeholder_text(i18n!("Go to chat or phone number…"))
+            .build();
+
+        let list_box = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::Browse)
+            .css_classes(["navigation-sidebar"])
+            .build();
+
+        // Shown first when the query looks like a phone number.
+        let number_label = gtk::Label::builder()
+            .xalign(0.0)
+            .ellipsize(pango::EllipsizeMode::End)
+            .build();
+        let number_row = gtk::ListBoxRow::builder()
+            .name(NUMBER_ROW)
+            .child(&number_label)
+            .build();
+        list_box.append(&number_row);
+        for (jid, name) in init.chats {
+            let label = gtk::Label::builder()
+                .label(name)
+                .xalign(0.0)
+                .ellipsize(pango::EllipsizeMode::End)
+                .build();
+            list_box.append(&gtk::ListBoxRow::builder().name(jid).child(&label).build());
+        }
+
+        let scrolled_window = gtk::ScrolledWindow::builder()
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .propagate_natural_height(true)
+            .min_content_width(320)
+            .max_content_height(360)
+            .child(&list_box)
+            .build();
+
+        let error_label = gtk::Label::builder()
+            .visible(false)
+            .wrap(true)
+            .xalign(0.0)
+            .margin_start(6)
+            .margin_end(6)
+            .css_classes(["error", "caption"])
+            .build();
+
+        let content = gtk::Box::builder()
+            .spacing(6)
+            .orientation(gtk::Orientation::Vertical)
+            .build();
+        content.append(&search_entry);
+        content.append(&scrolled_window);
+        content.append(&error_label);
+        widgets.set_child(Some(&content));
+
+        // Filter the chats by name as the user types.
+        let entry = search_entry.clone();
+        list_box.set_filter_func(move |row| {
+            if row.widget_name() == NUMBER_ROW {
+                return looks_like_number(&entry.text());
+            }
+
+            let name = row
+                .child()
+                .and_downcast::<gtk::Label>()
+                .map(|label| label.label())
+                .unwrap_or_default();
+            fuzzy_match(&entry.text(), &name)
+        });
+
+        let list = list_box.clone();
+        let row = number_row.clone();
+        let error = error_label.clone();
+        search_entry.connect_search_changed(move |entry| {
+            number_label.set_label(&i18n_f!("Message {0}", entry.text().trim()));
+            row.set_sensitive(true);
+            error.set_visible(false);
+
+            list.invalidate_filter();
+            select_adjacent(&list, None);
+        });
+
+        // `Enter` opens the selected chat.
+        let list = list_box.clone();
+        search_entry.connect_activate(move |_| {
+            if let Some(row) = list.selected_row() {
+                row.activate();
+            }
+        });
+
+        // Move through the chats without leaving the search entry.
+        let list = list_box.clone();
+        let key_event_controller = gtk::EventControllerKey::builder()
+            .propagation_phase(gtk::PropagationPhase::Capture)
+            .build();
+        key_event_controller.connect_key_pressed(move |_, key, _, _| match key {
+            gdk::Key::Down => {
+                select_adjacent(&list, Some(true));
+                glib::Propagation::Stop
+            }
+            gdk::Key::Up => {
+                select_adjacent(&list, Some(false));
+                glib::Propagation::Stop
+            }
+            _ => glib::Propagation::Proceed,
+        });
+        search_entry.add_controller(key_event_controller);
+
+        let popover = widgets.clone();
+        let output_sender = sender.clone();
+        let entry = search_entry.clone();
+        let error = error_label.clone();
+        list_box.connect_row_activated(move |_, row| {
+            if row.widget_name() != NUMBER_ROW {
+                let _ =
+                    output_sender.output(ChatSwitcherOutput::Selected(row.widget_name().into()));
+                popover.popdown();
+                return;
+            }
+
+            // Stay open while the number is checked, to show if it fails.
+            if let Some(phone_number) = parse_phone_number(&entry.text()) {
+                row.set_sensitive(false);
+                error.set_visible(false);
+                let _ = output_sender.output(ChatSwitcherOutput::MessageNumber(phone_number));
+            } else {
+                error.set_label(&i18n!(
+                    "Not a valid phone number, type it with the country code"
+                ));
+                error.set_visible(true);
+            }
+        });
+
+        widgets.connect_closed(move |popover| {
+            popover.unparent();
+            let _ = sender.output(ChatSwitcherOutput::Closed);
+        });
+
+        widgets.set_parent(&init.parent);
+        widgets.popup();
+        select_adjacent(&list_box, None);
+        search_entry.grab_focus();
+
+        let model = Self {
+            number_row,
+            error_label,
+        };
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, input: Self::Input, _sender: ComponentSender<Self>) {
+        match input {
+            ChatSwitcherInput::NumberFailed(message) => {
+                self.number_row.set_sensitive(true);
+                self.error_label.set_label(&message);
+                self.error_label.set_visible(true);
+            }
+        }
+    }
+}
+
+/// Select the next or previous visible row, or the first one if `forward` is `None`.
+fn select_adjacent(list_box: &gtk::ListBox, forward: Option<bool>) {
+    let current = list_box.selected_row().map(|row| row.index());
+    let rows = std::iter::successors(list_box.row_at_index(0), |row| {
+        list_box.row_at_index(row.index() + 1)
+    })
+    .filter(gtk::ListBoxRow::is_child_visible)
+    .collect::<Vec<_>>();
+
+    let position = rows.iter().position(|row| Some(row.index()) == current);
+    let row = match (forward, position) {
+        (Some(true), Some(position)) => rows.get(position + 1),
+        (Some(false), Some(position)) => position.checked_sub(1).and_then(|p| rows.get(p)),
+        _ => rows.first(),
+    };
+
+    if let Some(row) = row {
+        list_box.select_row(Some(row));
+    }
+}
+
+/// Check if `query` is made of what a typed phone number is, so it may be one.
+fn looks_like_number(query: &str) -> bool {
+    let query = query.trim();
+    query.chars().any(|c| c.is_ascii_digit())
+        && query
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '(' | ')' | '.'))
+}
+
+/// Get the digits of a valid phone number typed with its country code.
+pub fn parse_phone_number(query: &str) -> Option<String> {
+    if !looks_like_number(query) {
+        return None;
+    }
+
+    let digits = query
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>();
+    format!("+{digits}")
+        .parse::<PhoneNumber>()
+        .is_ok_and(|number| number.is_valid())
+        .then_some(digits)
+}
+
+/// Check if all the characters of `query` appear in order in `name`, ignoring case.
+fn fuzzy_match(query: &str, name: &str) -> bool {
+    let mut name = name.chars().flat_map(char::to_lowercase);
+
+    query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .all(|q| name.any(|c| c == q))
+}
@@ -0,0 +1,379 @@
+use adw::prelude::*;
+use chrono::Local;
+use gtk::{gdk, glib, pango};
+use num_traits::ToPrimitive;
+use relm4::prelude::*;
+
+use crate::{
+    i18n,
+    state::ChatMessage,
+    thumbnails,
+    utils::{format_date_label, format_jid, icon_or_fallback},
+};
+
+/// Furthest an image can be zoomed in, relative to its fitted size.
+const MAX_ZOOM: f64 = 8.0;
+/// How much a scroll wheel step zooms in or out.
+const SCROLL_ZOOM_STEP: f64 = 1.25;
+
+pub struct ImageViewer {
+    /// Zoom level, where 1.0 fits the image to the window.
+    zoom: f64,
+    /// Index of the displayed image.
+    index: usize,
+    /// Images to step through, oldest first.
+    images: Vec<ChatMessage>,
+    /// Picture displaying the image.
+    picture: gtk::Picture,
+    /// Scrolled area the zoomed picture is panned in.
+    scrolled: gtk::ScrolledWindow,
+    /// Scroll position when the drag started.
+    pan_origin: (f64, f64),
+    /// Zoom level when the pinch started.
+    pinch_origin: f64,
+}
+
+pub struct ImageViewerInit {
+    /// Index of the first image to display.
+    pub start: usize,
+    /// Images to step through, oldest first, without their data.
+    pub images: Vec<ChatMessage>,
+}
+
+#[derive(Debug)]
+pub enum ImageViewerInput {
+    /// Display the next image.
+    Next,
+    /// Display the previous image.
+    Previous,
+    /// The image at this index has been decoded.
+    Loaded(usize, Option<gdk::Texture>),
+    /// Multiply the zoom level by this factor.
+    ZoomBy(f64),
+    /// A pinch gesture has started.
+    PinchStarted,
+    /// A pinch gesture has scaled by this factor since it started.
+    Pinch(f64),
+    /// A drag gesture has started.
+    PanStarted,
+    /// A drag gesture has moved by this offset since it started.
+    Pan(f64, f64),
+    /// The dialog has been closed.
+    Closed,
+}
+
+#[derive(Debug)]
+pub enum ImageViewerOutput {
+    /// The dialog has been closed.
+    Closed,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for ImageViewer {
+    type Init = ImageViewerInit;
+    type Input = ImageViewerInput;
+    type Output = ImageViewerOutput;
+
+    view! {
+        adw::Dialog {
+            set_content_width: 960,
+            set_content_height: 720,
+            set_css_classes: &["image-viewer"],
+
+            connect_closed => ImageViewerInput::Closed,
+
+            #[wrap(Some)]
+            set_child = &adw::ToolbarView {
+                set_extend_content_to_top_edge: true,
+
+                add_top_bar = &adw::HeaderBar {
+                    #[wrap(Some)]
+                    set_title_widget = &adw::WindowTitle {
+                        #[watch]
+                        set_title: &model.title(),
+                        #[watch]
+                        set_subtitle: &model.subtitle(),
+                    },
+
+                    pack_end = &gtk::Button {
+                        set_icon_name: icon_or_fallback("send-to-symbolic", "document-send"),
+                        set_tooltip_text: Some(&i18n!("Open With…")),
+                        set_action_name: Some("app.open-media-with"),
+                        #[watch]
+                        set_action_target_value: Some(&model.target()),
+                    },
+
+                    pack_end = &gtk::Button {
+                        set_icon_name: icon_or_fallback("document-save-symbolic", "document-save"),
+                        set_tooltip_text: Some(&i18n!("Save As…")),
+                        set_action_name: Some("app.save-media"),
+                        #[watch]
+                        set_action_target_value: Some(&model.target()),
+                    },
+
+                    pack_end = &gtk::Button {
+                        set_icon_name: icon_or_fallback("edit-copy-symbolic", "edit-copy"),
+                        set_tooltip_text: Some(&i18n!("Copy Image")),
+                        set_action_name: Some("app.copy-image"),
+                        #[watch]
+                        set_action_target_value: Some(&model.target()),
+                    },
+                },
+
+                #[wrap(Some)]
+                set_content = &gtk::Overlay {
+                    #[local_ref]
+                    scrolled -> gtk::ScrolledWindow {
+                        set_hexpand: true,
+                        set_vexpand: true,
+
+                        #[local_ref]
+                        picture -> gtk::Picture {
+                            set_can_shrink: true,
+                            set_content_fit: gtk::ContentFit::Contain,
+                        },
+
+                        // Pinch to zoom.
+                        add_controller = gtk::GestureZoom {
+                            connect_begin[sender] => move |_, _| {
+                                sender.input(ImageViewerInput::PinchStarted);
+                            },
+                            connect_scale_changed[sender] => move |_, scale| {
+                                sender.input(ImageViewerInput::Pinch(scale));
+                            },
+                        },
+
+                        // Drag to pan around a zoomed image.
+                        add_controller = gtk::GestureDrag {
+                            connect_drag_begin[sender] => move |_, _, _| {
+                                sender.input(ImageViewerInput::PanStarted);
+                            },
+                            connect_drag_update[sender] => move |_, x, y| {
+                                sender.input(ImageViewerInput::Pan(x, y));
+                            },
+                        },
+
+                        // Scroll to zoom, instead of scrolling.
+                        add_controller = gtk::EventControllerScroll {
+                            set_flags: gtk::EventControllerScrollFlags::VERTICAL,
+                            connect_scroll[sender] => move |_, _, dy| {
+                                let factor = if dy < 0.0 { SCROLL_ZOOM_STEP } else { SCROLL_ZOOM_STEP.recip() };
+                                sender.input(ImageViewerInput::ZoomBy(factor));
+                                glib::Propagation::Stop
+                            },
+                        },
+                    },
+
+                    add_overlay = &gtk::Button {
+                        set_halign: gtk::Align::Start,
+                        set_valign: gtk::Align::Center,
+                        set_margin_start: 12,
+                        set_icon_name: icon_or_fallback("go-previous-symbolic", "go-previous"),
+                        set_tooltip_text: Some(&i18n!("Previous")),
+                        set_css_classes: &["circular", "osd"],
+                        #[watch]
+                        set_visible: model.index > 0,
+
+                        connect_clicked => ImageViewerInput::Previous,
+                    },
+
+                    add_overlay = &gtk::Button {
+                        set_halign: gtk::Align::End,
+                        set_valign: gtk::Align::Center,
+                        set_margin_end: 12,
+                        set_icon_name: icon_or_fallback("go-next-symbolic", "go-next"),
+                        set_tooltip_text: Some(&i18n!("Next")),
+                        set_css_classes: &["circular", "osd"],
+                        #[watch]
+                        set_visible: model.index + 1 < model.images.len(),
+
+                        connect_clicked => ImageViewerInput::Next,
+                    },
+
+                    add_overlay = &gtk::Label {
+                        set_halign: gtk::Align::Center,
+                        set_valign: gtk::Align::End,
+                        set_margin_all: 18,
+                        set_wrap: true,
+                        set_wrap_mode: pango::WrapMode::WordChar,
+                        set_selectable: true,
+                        set_css_classes: &["osd", "image-caption"],
+                        #[watch]
+                        set_label: model.caption(),
+                        #[watch]
+                        set_visible: !model.caption().is_empty(),
+                    },
+                },
+            },
+
+            // Step through the images with the arrow keys.
+            add_controller = gtk::EventControllerKey {
+                connect_key_pressed[sender] => move |_, key, _, _| match key {
+                    gdk::Key::Left => {
+                        sender.input(ImageViewerInput::Previous);
+                        glib::Propagation::Stop
+                    }
+                    gdk::Key::Right => {
+                        sender.input(ImageViewerInput::Next);
+                        glib::Propagation::Stop
+                    }
+                    _ => glib::Propagation::Proceed,
+                }
+            },
+        }
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let mut model = Self {
+            zoom: 1.0,
+            index: init.start.min(init.images.len().saturating_sub(1)),
+            images: init.images,
+            picture: gtk::Picture::new(),
+            scrolled: gtk::ScrolledWindow::new(),
+            pinch_origin: 1.0,
+            pan_origin: (0.0, 0.0),
+        };
+
+        let picture = &model.picture;
+        let scrolled = &model.scrolled;
+        let widgets = view_output!();
+
+        model.show(&sender);
+        root.present(Some(&relm4::main_adw_application().windows()[0]));
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, input: Self::Input, sender: ComponentSender<Self>) {
+        match input {
+            ImageViewerInput::Next => {
+                if self.index + 1 < self.images.len() {
+                    self.index += 1;
+                    self.show(&sender);
+                }
+            }
+            ImageViewerInput::Previous => {
+                if self.index > 0 {
+                    self.index -= 1;
+                    self.show(&sender);
+                }
+            }
+            ImageViewerInput::Loaded(index, texture) => {
+                // Drop images decoded after stepping past them.
+                if index == self.index {
+                    self.picture.set_paintable(texture.as_ref());
+                }
+            }
+            ImageViewerInput::ZoomBy(factor) => self.set_zoom(self.zoom * factor),
+            ImageViewerInput::PinchStarted => self.pinch_origin = self.zoom,
+            ImageViewerInput::Pinch(scale) => self.set_zoom(self.pinch_origin * scale),
+            ImageViewerInput::PanStarted => {
+                self.pan_origin = (
+                    self.scrolled.hadjustment().value(),
+                    self.scrolled.vadjustment().value(),
+                );
+            }
+            ImageViewerInput::Pan(x, y) => {
+                // Move the image along with the pointer.
+                self.scrolled.hadjustment().set_value(self.pan_origin.0 - x);
+                self.scrolled.vadjustment().set_value(self.pan_origin.1 - y);
+            }
+            ImageViewerInput::Closed => {
+                let _ = sender.output(ImageViewerOutput::Closed);
+            }
+        }
+    }
+}
+
+impl ImageViewer {
+    /// Display the current image, fitted to the window, once its data is
+    /// loaded and decoded off the main thread.
+    fn show(&mut self, sender: &ComponentSender<Self>) {
+        self.picture.set_paintable(None::<&gdk::Paintable>);
+        self.set_zoom(1.0);
+
+        let Some(message) = self.images.get(self.index).cloned() else {
+            return;
+        };
+        let index = self.index;
+        let sender = sender.clone();
+        relm4::spawn_local(async move {
+            let texture = thumbnails::load_stored("image", &message).await;
+            sender.input(ImageViewerInput::Loaded(index, texture));
+        });
+    }
+
+    /// Zoom the image, growing the picture past the window to pan around it.
+    fn set_zoom(&mut self, zoom: f64) {
+        self.zoom = zoom.clamp(1.0, MAX_ZOOM);
+
+        if self.zoom > 1.0 {
+            let zoomed = |length: i32| {
+                (f64::from(length) * self.zoom)
+                    .round()
+                    .to_i32()
+                    .unwrap_or(i32::MAX)
+            };
+            self.picture.set_size_request(
+                zoomed(self.scrolled.width()),
+                zoomed(self.scrolled.height()),
+            );
+        } else {
+            self.picture.set_size_request(-1, -1);
+        }
+    }
+
+    /// Get the message action target of the displayed image.
+    fn target(&self) -> glib::Variant {
+        self.images
+            .get(self.index)
+            .map(|message| (message.chat_jid.clone(), message.server_id.clone()))
+            .unwrap_or_default()
+            .to_variant()
+    }
+
+    /// Get the caption of the displayed image.
+    fn caption(&self) -> &str {
+        self.images
+            .get(self.index)
+            .map_or("", |message| message.content.as_str())
+    }
+
+    /// Get who sent the displayed image.
+    fn title(&self) -> String {
+        self.images
+            .get(self.index)
+            .map(|message| {
+                if message.outgoing {
+                    i18n!("You")
+                } else {
+                    message
+                        .sender_name
+                        .clone()
+                        .unwrap_or_else(|| format_jid(&message.sender_jid))
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get the position and time of the displayed image.
+    fn subtitle(&self) -> String {
+        self.images
+            .get(self.index)
+            .map(|message| {
+                let timestamp = message.timestamp.with_timezone(&Local);
+                format!(
+                    "{}/{} · {}, {}",
+                    self.index + 1,
+                    self.images.len(),
+                    format_date_label(timestamp.date_naive()),
+                    timestamp.format("%H:%M")
+                )
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use adw::prelude::*;
+use chrono::Local;
+use relm4::prelude::*;
+
+use crate::{
+    i18n, i18n_f,
+    store::JoinRequest,
+    utils::{format_date_label, format_lid_as_number, icon_or_fallback},
+};
+
+/// People asking to join a group, for its admins to approve or reject.
+pub struct JoinRequestsDialog {
+    /// Rows of the requests, by the JID of who asked.
+    rows: HashMap<String, adw::ActionRow>,
+    /// Group listing the requests.
+    group: adw::PreferencesGroup,
+    /// Names of who asked (JID -> name).
+    names: HashMap<String, String>,
+    /// Stack switching between the requests and the empty state.
+    stack: gtk::Stack,
+}
+
+pub struct JoinRequestsInit {
+    /// Name of the group.
+    pub name: String,
+    /// Names of who asked, when they're known (JID -> name).
+    pub names: HashMap<String, String>,
+    /// Requests to list, oldest first.
+    pub requests: Vec<JoinRequest>,
+}
+
+#[derive(Debug)]
+pub enum JoinRequestsInput {
+    /// Replace the listed requests, as fetched from the server, with the
+    /// names of who asked when they're known.
+    SetRequests(Vec<JoinRequest>, HashMap<String, String>),
+    /// Approve or reject the request of someone.
+    Resolve { jid: String, approve: bool },
+    /// Requests have been resolved or withdrawn, and are no longer listed.
+    Remove(Vec<String>),
+    /// Resolving requests failed, so they can be tried again.
+    Failed,
+}
+
+#[derive(Debug)]
+pub enum JoinRequestsOutput {
+    /// Approve or reject requests.
+    Resolve {
+        participants: Vec<String>,
+        approve: bool,
+    },
+    /// The dialog has been closed.
+    Closed,
+}
+
+impl SimpleComponent for JoinRequestsDialog {
+    type Init = JoinRequestsInit;
+    type Root = adw::Dialog;
+    type Input = JoinRequestsInput;
+    type Output = JoinRequestsOutput;
+    type Widgets = adw::Dialog;
+
+    fn init_root() -> Self::Root {
+        adw::Dialog::builder()
+            .content_width(420)
+            .content_height(520)
+            .build()
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let widgets = root;
+
+        let header_bar = adw::HeaderBar::builder()
+            .title_widget(
+                &adw::WindowTitle::builder()
+                    .title(i18n!("Join Requests"))
+                    .subtitle(&init.name)
+                    .build(),
+            )
+            .build();
+
+        let group = adw::PreferencesGroup::new();
+        let page = adw::PreferencesPage::new();
+        page.add(&group);
+
+        let empty = adw::StatusPage::builder()
+            .icon_name(icon_or_fallback("people-symbolic", "system-users-symbolic"))
+            .title(i18n!("No Pending Requests"))
+            .description(i18n!("People asking to join the group show up here"))
+            .build();
+
+        let stack = gtk::Stack::new();
+        stack.add_named(&page, Some("requests"));
+        stack.add_named(&empty, Some("empty"));
+
+        let toolbar_view = adw::ToolbarView::builder().content(&stack).build();
+        toolbar_view.add_top_bar(&header_bar);
+        widgets.set_child(Some(&toolbar_view));
+
+        let mut model = Self {
+            rows: HashMap::new(),
+            group,
+            names: init.names,
+            stack,
+        };
+        model.set_requests(&init.requests, &sender);
+
+        let output_sender = sender.clone();
+        widgets.connect_closed(move |_| {
+            let _ = output_sender.output(JoinRequestsOutput::Closed);
+        });
+        widgets.present(relm4::main_adw_application().active_window().as_ref());
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, input: Self::Input, sender: ComponentSender<Self>) {
+        match input {
+            JoinRequestsInput::SetRequests(requests, names) => {
+                self.names.extend(names);
+                self.set_requests(&requests, &sender);
+            }
+            JoinRequestsInput::Resolve { jid, approve } => {
+                // Held until the server answers, so it's not resolved twice.
+                if let Some(row) = self.rows.get(&jid) {
+                    row.set_sensitive(false);
+                }
+                let _ = sender.output(JoinRequestsOutput::Resolve {
+                    participants: vec![jid],
+                    approve,
+                });
+            }
+            JoinRequestsInput::Remove(jids) => {
+                for jid in jids {
+                    if let Some(row) = self.rows.remove(&jid) {
+                        self.group.remove(&row);
+                    }
+                }
+                self.update_title();
+            }
+            JoinRequestsInput::Failed => {
+                for row in self.rows.values() {
+                    row.set_sensitive(true);
+                }
+            }
+        }
+    }
+}
+
+impl JoinRequestsDialog {
+    /// List the requests, replacing the ones listed before.
+    fn set_requests(&mut self, requests: &[JoinRequest], sender: &ComponentSender<Self>) {
+        for row in self.rows.drain().map(|(_, row)| row) {
+            self.group.remove(&row);
+        }
+
+        for request in requests {
+            let name = self
+                .names
+                .get(&request.jid)
+                .cloned()
+                .unwrap_or_else(|| format_lid_as_number(&request.jid));
+            let requested_at = request.requested_at.with_timezone(&Local);
+            let row = adw::ActionRow::builder()
+                .title(&name)
+                .subtitle(format!(
+                    "{}, {}",
+                    format_date_label(requested_at.date_naive()),
+                    requested_at.format("%H:%M")
+                ))
+                .build();
+
+            for (approve, icon, fallback, tooltip) in [
+                (
+                    false,
+                    "cross-large-symbolic",
+                    "window-close-symbolic",
+                    i18n!("Reject"),
+                ),
+                (
+                    true,
+                    "check-plain-symbolic",
+                    "object-select-symbolic",
+                    i18n!("Approve"),
+                ),
+            ] {
+                let button = gtk::Button::builder()
+                    .icon_name(icon_or_fallback(icon, fallback))
+                    .tooltip_text(tooltip)
+                    .valign(gtk::Align::Center)
+                    .css_classes(if approve {
+                        ["circular", "suggested-action"]
+                    } else {
+                        ["circular", "flat"]
+                    })
+                    .build();
+                let jid = request.jid.clone();
+                let input_sender = sender.input_sender().clone();
+                button.connect_clicked(move |_| {
+                    input_sender.emit(JoinRequestsInput::Resolve {
+                        jid: jid.clone(),
+                        approve,
+                    });
+                });
+                row.add_suffix(&button);
+            }
+
+            self.group.add(&row);
+            self.rows.insert(request.jid.clone(), row);
+        }
+
+        self.update_title();
+    }
+
+    /// Show how many requests are pending, or the empty state without any.
+    fn update_title(&self) {
+        let count = self.rows.len();
+        self.group
+            .set_title(&i18n_f!("Pending requests ({0})", count));
+        self.stack
+            .set_visible_child_name(if count == 0 { "empty" } else { "requests" });
+    }
+}
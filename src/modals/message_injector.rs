@@ -0,0 +1,272 @@
+use std::sync::Arc;
+
+use adw::prelude::*;
+use chrono::{TimeDelta, Utc};
+use indexmap::IndexMap;
+use num_traits::ToPrimitive;
+use relm4::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    state::{ChatKind, ChatMessage, Media, MediaType, MessageKind, MessageStatus},
+    store::Database,
+};
+
+/// Participant sending the incoming samples in groups.
+const SAMPLE_SENDER_JID: &str = "10000000000@s.whatsapp.net";
+
+/// Kinds of samples, in the order they're listed.
+const SAMPLE_KINDS: [SampleKind; 11] = [
+    SampleKind::Text,
+    SampleKind::LongText,
+    SampleKind::EmojiOnly,
+    SampleKind::RightToLeft,
+    SampleKind::Media(MediaType::Image),
+    SampleKind::Media(MediaType::Audio),
+    SampleKind::Media(MediaType::Video),
+    SampleKind::Media(MediaType::Sticker),
+    SampleKind::Media(MediaType::Document),
+    SampleKind::Service,
+    SampleKind::Reactions,
+];
+
+/// Injects synthetic messages into the open chat, to test how they're shown
+/// without a phone sending each kind. Only built with the `message-injector`
+/// feature, which Devel builds enable.
+pub struct MessageInjector;
+
+#[derive(Debug)]
+pub enum MessageInjectorOutput {
+    /// Inject a sample into the open chat.
+    Inject(Sample),
+    /// The dialog has been closed.
+    Closed,
+}
+
+/// Kind of synthetic message.
+#[derive(Clone, Copy, Debug)]
+pub enum SampleKind {
+    /// A short text.
+    Text,
+    /// Text spanning several paragraphs.
+    LongText,
+    /// Only emoji, shown bigger.
+    EmojiOnly,
+    /// Text in a right-to-left script.
+    RightToLeft,
+    /// A media without caption.
+    Media(MediaType),
+    /// A service event.
+    Service,
+    /// A text with reactions.
+    Reactions,
+}
+
+impl SampleKind {
+    /// Get the name listed in the dialog.
+    fn label(self) -> String {
+        match self {
+            Self::Text => "Text".to_string(),
+            Self::LongText => "Long Text".to_string(),
+            Self::EmojiOnly => "Emoji Only".to_string(),
+            Self::RightToLeft => "Right-to-Left Text".to_string(),
+            Self::Media(media_type) => media_type.display_label(),
+            Self::Service => "Service Event".to_string(),
+            Self::Reactions => "Reactions".to_string(),
+        }
+    }
+}
+
+/// A synthetic message, as picked in the dialog.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    /// Kind of the message.
+    pub kind: SampleKind,
+    /// Whether it's sent by the connected user.
+    pub outgoing: bool,
+    /// How long before now it was sent.
+    pub minutes_ago: u32,
+}
+
+impl Sample {
+    /// Build the message, as if it was sent in `chat_jid`.
+    pub fn to_message(self, chat_jid: &str, user_jid: &str, db: Arc<Database>) -> ChatMessage {
+        let (sender_jid, sender_name) = if self.outgoing {
+            (user_jid.to_string(), None)
+        } else if ChatKind::from_jid(chat_jid) == ChatKind::Group {
+            (SAMPLE_SENDER_JID.to_string(), Some("Maria".to_string()))
+        } else {
+            (chat_jid.to_string(), Some("Maria".to_string()))
+        };
+
+        let mut kind = MessageKind::Regular;
+        let mut media = None;
+        let mut reactions = IndexMap::new();
+        let content = match self.kind {
+            SampleKind::Text => "Hello! How are you doing today?".to_string(),
+            SampleKind::LongText => [
+                "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.";
+                4
+            ]
+            .join("\n\n"),
+            SampleKind::EmojiOnly => "😂👍🎉".to_string(),
+            SampleKind::RightToLeft => "مرحبا! كيف حالك اليوم؟".to_string(),
+            SampleKind::Media(media_type) => {
+                media = Some(Media {
+                    r#type: media_type,
+                    mime_type: media_type.guess_mime_type(),
+                    ..Media::default()
+                });
+                String::new()
+            }
+            SampleKind::Service => {
+                kind = MessageKind::Service;
+                "Maria added Ana".to_string()
+            }
+            SampleKind::Reactions => {
+                reactions.insert("👍".to_string(), vec![chat_jid.to_string()]);
+                reactions.insert("❤️".to_string(), vec![user_jid.to_string()]);
+                "React to this message".to_string()
+            }
+        };
+
+        ChatMessage {
+            local_id: Uuid::new_v4(),
+            server_id: format!("DEVEL{}", Uuid::new_v4().simple()).to_uppercase(),
+            chat_jid: chat_jid.to_string(),
+            sender_jid,
+            sender_name,
+
+            kind,
+            poll: None,
+            media,
+            edited: false,
+            // Seen already, so opening the chat never sends receipts for them.
+            status: if self.outgoing {
+                MessageStatus::Sent
+            } else {
+                MessageStatus::Read
+            },
+            content,
+            revoked: false,
+            contacts: Vec::new(),
+            location: None,
+            quote: None,
+            preview: None,
+            outgoing: self.outgoing,
+            reactions,
+            timestamp: Utc::now() - TimeDelta::minutes(i64::from(self.minutes_ago)),
+
+            db,
+        }
+    }
+}
+
+impl SimpleComponent for MessageInjector {
+    type Init = ();
+    type Root = adw::Dialog;
+    type Input = ();
+    type Output = MessageInjectorOutput;
+    type Widgets = adw::Dialog;
+
+    fn init_root() -> Self::Root {
+        adw::Dialog::builder()
+            .title("Inject Message")
+            .content_width(400)
+            .build()
+    }
+
+    fn init(
+        _init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {};
+        let widgets = root;
+
+        let labels = SAMPLE_KINDS.map(SampleKind::label);
+        let kind_row = adw::ComboRow::builder()
+            .title("Kind")
+            .model(&gtk::StringList::new(
+                &labels.each_ref().map(String::as_str),
+            ))
+            .build();
+        let outgoing_row = adw::SwitchRow::builder().title("Outgoing").build();
+        let minutes_ago_row = adw::SpinRow::builder()
+            .title("Minutes Ago")
+            .subtitle("Sent before now, to test date separators")
+            .adjustment(&gtk::Adjustment::new(
+                0.0,
+                0.0,
+                60.0 * 24.0 * 30.0,
+                1.0,
+                60.0,
+                0.0,
+            ))
+            .build();
+
+        let group = adw::PreferencesGroup::builder()
+            .description("Add synthetic messages to the open chat, stored like read ones")
+            .build();
+        group.add(&kind_row);
+        group.add(&outgoing_row);
+        group.add(&minutes_ago_row);
+
+        let page = adw::PreferencesPage::new();
+        page.add(&group);
+
+        let inject_button = gtk::Button::builder()
+            .label("_Inject")
+            .use_underline(true)
+            .css_classes(["suggested-action"])
+            .build();
+        let output_sender = sender.clone();
+        inject_button.connect_clicked(move |_| {
+            let Some(&kind) = SAMPLE_KINDS.get(kind_row.selected() as usize) else {
+                return;
+            };
+            let minutes_ago = minutes_ago_row.value().to_u32().unwrap_or_default();
+            let _ = output_sender.output(MessageInjectorOutput::Inject(Sample {
+                kind,
+                outgoing: outgoing_row.is_active(),
+                minutes_ago,
+            }));
+        });
+
+        let header_bar = adw::HeaderBar::new();
+        header_bar.pack_end(&inject_button);
+
+        let toolbar_view = adw::ToolbarView::builder().content(&page).build();
+        toolbar_view.add_top_bar(&header_bar);
+        widgets.set_child(Some(&toolbar_view));
+
+        widgets.connect_closed(move |_| {
+            let _ = sender.output(MessageInjectorOutput::Closed);
+        });
+        widgets.present(Some(&relm4::main_adw_application().windows()[0]));
+
+        ComponentParts { model, widgets }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{block_on, database};
+
+    #[test]
+    fn incoming_samples_are_read() {
+        let db = block_on(database());
+        let sample = Sample {
+            kind: SampleKind::Text,
+            outgoing: false,
+            minutes_ago: 5,
+        };
+        let message = sample.to_message("group@g.us", "me@lid", db);
+
+        assert_eq!(message.status, MessageStatus::Read);
+        assert_eq!(message.sender_jid, SAMPLE_SENDER_JID);
+        assert!(message.server_id.starts_with("DEVEL"));
+        assert!(message.timestamp < Utc::now() - TimeDelta::minutes(4));
+    }
+}
@@ -1,2 +1,13 @@
 pub mod about;
+pub mod attachment_warning;
+pub mod bookmarks;
+pub mod call;
+pub mod chat_gallery;
+pub mod chat_switcher;
+pub mod image_viewer;
+pub mod join_requests;
+#[cfg(feature = "message-injector")]
+pub mod message_injector;
+pub mod preferences;
 pub mod shortcuts;
+pub mod status_viewer;
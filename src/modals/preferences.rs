@@ -0,0 +1,637 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use adw::prelude::*;
+use chrono::Utc;
+use gtk::gio;
+use relm4::prelude::*;
+
+use crate::{
+    DATA_DIR,
+    config::APP_ID,
+    i18n,
+    store::{Database, Snippet},
+    utils::{SendShortcut, Wallpaper, icon_or_fallback},
+};
+
+/// Wallpapers in the order they're listed in.
+const WALLPAPERS: [Wallpaper; 3] = [Wallpaper::Default, Wallpaper::Pattern, Wallpaper::Image];
+/// Send shortcuts in the order they're listed in.
+const SEND_SHORTCUTS: [SendShortcut; 2] = [SendShortcut::Enter, SendShortcut::CtrlEnter];
+
+pub struct PreferencesDialog;
+
+impl SimpleComponent for PreferencesDialog {
+    type Init = Arc<Database>;
+    type Root = adw::PreferencesDialog;
+    type Input = ();
+    type Output = ();
+    type Widgets = adw::PreferencesDialog;
+
+    fn init_root() -> Self::Root {
+        adw::PreferencesDialog::builder().build()
+    }
+
+    fn init(
+        db: Self::Init,
+        root: Self::Root,
+        _sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = Self {};
+        let widgets = root;
+        let settings = gio::Settings::new(APP_ID);
+
+        let general_page = adw::PreferencesPage::builder()
+            .title(i18n!("General"))
+            .build();
+
+        // Composer group
+        let composer = adw::PreferencesGroup::builder()
+            .title(i18n!("Composer"))
+            .build();
+
+        let composer_hints = adw::SwitchRow::builder()
+            .title(i18n!("Remember Input Hints"))
+            .subtitle(i18n!(
+                "Reopen each chat with the keyboard and emoji picker last used in it"
+            ))
+            .build();
+        settings
+            .bind("remember-composer-hints", &composer_hints, "active")
+            .build();
+        composer.add(&composer_hints);
+
+        let expand_snippets = adw::SwitchRow::builder()
+            .title(i18n!("Expand Snippets"))
+            .subtitle(i18n!(
+                "Replace a snippet trigger with its text when pressing Tab"
+            ))
+            .build();
+        settings
+            .bind("expand-snippets", &expand_snippets, "active")
+            .build();
+        composer.add(&expand_snippets);
+
+        let current = settings
+            .string("send-shortcut")
+            .parse::<SendShortcut>()
+            .unwrap_or_default();
+        let send_shortcut = adw::ComboRow::builder()
+            .title(i18n!("Send Messages With"))
+            .model(&gtk::StringList::new(&[
+                &i18n!("Enter"),
+                &i18n!("Ctrl+Enter"),
+            ]))
+            .selected(
+                SEND_SHORTCUTS
+                    .iter()
+                    .position(|s| *s == current)
+                    .and_then(|index| u32::try_from(index).ok())
+                    .unwrap_or_default(),
+            )
+            .build();
+        send_shortcut.connect_selected_notify({
+            let settings = settings.clone();
+            move |row| {
+                let shortcut = usize::try_from(row.selected())
+                    .ok()
+                    .and_then(|index| SEND_SHORTCUTS.get(index))
+                    .copied()
+                    .unwrap_or_default();
+                if let Err(e) = settings.set_string("send-shortcut", shortcut.as_ref()) {
+                    tracing::error!("Failed to save the send shortcut: {e}");
+                }
+            }
+        });
+        composer.add(&send_shortcut);
+
+        general_page.add(&composer);
+
+        // Snippets group
+        let snippets = adw::PreferencesGroup::builder()
+            .title(i18n!("Snippets"))
+            .description(i18n!(
+                "Type a trigger followed by its text, like “/addr 221B Baker Street”"
+            ))
+            .build();
+        settings
+            .bind("expand-snippets", &snippets, "sensitive")
+            .get()
+            .build();
+
+        let new_snippet = adw::EntryRow::builder()
+            .title(i18n!("New Snippet"))
+            .show_apply_button(true)
+            .build();
+        new_snippet.connect_apply({
+            let db = Arc::clone(&db);
+            let group = snippets.clone();
+            move |row| {
+                let text = row.text();
+                let Some((trigger, expansion)) = text.trim().split_once(char::is_whitespace) else {
+                    return;
+                };
+
+                let snippet = Snippet {
+                    trigger: trigger.to_string(),
+                    expansion: expansion.trim_start().to_string(),
+                };
+                add_snippet_row(&group, &db, &snippet);
+                row.set_text("");
+
+                let db = Arc::clone(&db);
+                relm4::spawn(async move {
+                    if let Err(e) = db.save_snippet(&snippet).await {
+                        tracing::error!("Failed to save snippet: {e}");
+                    }
+                });
+            }
+        });
+        snippets.add(&new_snippet);
+
+        relm4::spawn_local({
+            let db = Arc::clone(&db);
+            let group = snippets.clone();
+            async move {
+                match db.load_snippets().await {
+                    Ok(snippets) => {
+                        for snippet in snippets {
+                            add_snippet_row(&group, &db, &snippet);
+                        }
+                    }
+                    Err(e) => tracing::error!("Failed to load snippets: {e}"),
+                }
+            }
+        });
+
+        general_page.add(&snippets);
+
+        // Chats group
+        let chats = adw::PreferencesGroup::builder()
+            .title(i18n!("Chats"))
+            .build();
+
+        let open_at_first_unread = adw::SwitchRow::builder()
+            .title(i18n!("Open at First Unread Message"))
+            .subtitle(i18n!(
+                "Pick up where you left off in chats with many unread messages, instead of at the latest one"
+            ))
+            .build();
+        settings
+            .bind("open-at-first-unread", &open_at_first_unread, "active")
+            .build();
+        chats.add(&open_at_first_unread);
+
+        general_page.add(&chats);
+
+        // Wallpaper group
+        let wallpaper = adw::PreferencesGroup::builder()
+            .title(i18n!("Wallpaper"))
+            .description(i18n!(
+                "Chats can use their own wallpaper from the chat menu"
+            ))
+            .build();
+
+        let current = settings
+            .string("chat-wallpaper")
+            .parse::<Wallpaper>()
+            .unwrap_or_default();
+        let wallpaper_row = adw::ComboRow::builder()
+            .title(i18n!("Chat Background"))
+            .model(&gtk::StringList::new(&[
+                &i18n!("None"),
+                &i18n!("Pattern"),
+                &i18n!("Image"),
+            ]))
+            .selected(
+                WALLPAPERS
+                    .iter()
+                    .position(|w| *w == current)
+                    .and_then(|index| u32::try_from(index).ok())
+                    .unwrap_or_default(),
+            )
+            .build();
+        wallpaper_row.connect_selected_notify({
+            let settings = settings.clone();
+            move |row| {
+                let wallpaper = usize::try_from(row.selected())
+                    .ok()
+                    .and_then(|index| WALLPAPERS.get(index))
+                    .copied()
+                    .unwrap_or_default();
+                if let Err(e) = settings.set_string("chat-wallpaper", wallpaper.as_ref()) {
+                    tracing::error!("Failed to save the chat wallpaper: {e}");
+                }
+            }
+        });
+        wallpaper.add(&wallpaper_row);
+
+        let image_row = adw::ActionRow::builder()
+            .title(i18n!("Wallpaper Image"))
+            .subtitle(i18n!("Scaled to fill the chat, and dimmed in dark mode"))
+            .build();
+        let choose_button = gtk::Button::builder()
+            .label(i18n!("Choose…"))
+            .valign(gtk::Align::Center)
+            .build();
+        choose_button.connect_clicked({
+            let settings = settings.clone();
+            let wallpaper_row = wallpaper_row.clone();
+            move |button| {
+                let filter = gtk::FileFilter::new();
+                filter.set_name(Some(&i18n!("Images")));
+                filter.add_mime_type("image/*");
+                let filters = gio::ListStore::new::<gtk::FileFilter>();
+                filters.append(&filter);
+
+                let dialog = gtk::FileDialog::builder()
+                    .title(i18n!("Choose Wallpaper"))
+                    .filters(&filters)
+                    .build();
+                let window = button.root().and_downcast::<gtk::Window>();
+
+                let settings = settings.clone();
+                let wallpaper_row = wallpaper_row.clone();
+                dialog.open(window.as_ref(), gio::Cancellable::NONE, move |result| {
+                    let Some(source) = result.ok().and_then(|file| file.path()) else {
+                        return;
+                    };
+
+                    relm4::spawn_local(async move {
+                        let result = relm4::spawn_blocking(move || save_wallpaper_image(&source))
+                            .await
+                            .unwrap_or_else(|e| Err(io::Error::other(e)));
+                        match result {
+                            Ok(path) => {
+                                if let Err(e) = settings
+                                    .set_string("chat-wallpaper-image", &path.to_string_lossy())
+                                {
+                                    tracing::error!("Failed to save the wallpaper image: {e}");
+                                }
+                                // Switch to the image wallpaper.
+                                wallpaper_row.set_selected(2);
+                            }
+                            Err(e) => tracing::error!("Failed to copy the wallpaper image: {e}"),
+                        }
+                    });
+                });
+            }
+        });
+        image_row.add_suffix(&choose_button);
+        image_row.set_activatable_widget(Some(&choose_button));
+        wallpaper.add(&image_row);
+
+        general_page.add(&wallpaper);
+
+        // Privacy group
+        let privacy = adw::PreferencesGroup::builder()
+            .title(i18n!("Privacy"))
+            .build();
+
+        let read_receipts = adw::SwitchRow::builder()
+            .title(i18n!("Send Read Receipts"))
+            .subtitle(i18n!(
+                "Let contacts know you read their messages. Groups and status updates always get read receipts"
+            ))
+            .build();
+        settings
+            .bind("send-read-receipts", &read_receipts, "active")
+            .build();
+        privacy.add(&read_receipts);
+
+        let link_previews = adw::SwitchRow::builder()
+            .title(i18n!("Generate Link Previews"))
+            .subtitle(i18n!(
+                "Fetch the page of links you send to attach a preview. Linked sites can see your IP address"
+            ))
+            .build();
+        settings
+            .bind("link-previews", &link_previews, "active")
+            .build();
+        privacy.add(&link_previews);
+
+        general_page.add(&privacy);
+
+        // Background group
+        let background = adw::PreferencesGroup::builder()
+            .title(i18n!("Background"))
+            .build();
+
+        let run_in_background = adw::SwitchRow::builder()
+            .title(i18n!("Keep Running in the Background"))
+            .subtitle(i18n!(
+                "Receive messages and calls with the window closed, until quitting from the main menu"
+            ))
+            .build();
+        settings
+            .bind("run-in-background", &run_in_background, "active")
+            .build();
+        background.add(&run_in_background);
+
+        general_page.add(&background);
+
+        // Notifications group
+        let notifications = adw::PreferencesGroup::builder()
+            .title(i18n!("Notifications"))
+            .build();
+
+        let mute_during_screencast = adw::SwitchRow::builder()
+            .title(i18n!("Mute While Sharing the Screen"))
+            .subtitle(i18n!(
+                "Hide notifications while the screen is being shared or recorded"
+            ))
+            .build();
+        settings
+            .bind("mute-during-screencast", &mute_during_screencast, "active")
+            .build();
+        notifications.add(&mute_during_screencast);
+
+        let muted_group_mentions = adw::SwitchRow::builder()
+            .title(i18n!("Mentions in Muted Groups"))
+            .subtitle(i18n!(
+                "Notify messages that mention you even in groups that are muted"
+            ))
+            .build();
+        settings
+            .bind("muted-group-mentions", &muted_group_mentions, "active")
+            .build();
+        notifications.add(&muted_group_mentions);
+
+        let launcher_badge = adw::SwitchRow::builder()
+            .title(i18n!("Unread Count on the App Icon"))
+            .subtitle(i18n!(
+                "Show how many messages are unread in chats that aren't muted, in docks that support it"
+            ))
+            .build();
+        settings
+            .bind("launcher-badge", &launcher_badge, "active")
+            .build();
+        notifications.add(&launcher_badge);
+
+        general_page.add(&notifications);
+
+        // Sounds group
+        let sounds = adw::PreferencesGroup::builder()
+            .title(i18n!("Sounds"))
+            .build();
+
+        let message_received_sound = adw::SwitchRow::builder()
+            .title(i18n!("Message Received"))
+            .subtitle(i18n!(
+                "Play a sound when a message is received in a chat that isn't muted"
+            ))
+            .build();
+        settings
+            .bind("message-received-sound", &message_received_sound, "active")
+            .build();
+        sounds.add(&message_received_sound);
+
+        let sounds_while_focused = adw::SwitchRow::builder()
+            .title(i18n!("While Focused"))
+            .subtitle(i18n!(
+                "Also play the message received sound while the window is focused"
+            ))
+            .build();
+        settings
+            .bind("sounds-while-focused", &sounds_while_focused, "active")
+            .build();
+        settings
+            .bind("message-received-sound", &sounds_while_focused, "sensitive")
+            .get()
+            .build();
+        sounds.add(&sounds_while_focused);
+
+        let automated_message_sounds = adw::SwitchRow::builder()
+            .title(i18n!("Automated Messages"))
+            .subtitle(i18n!(
+                "Also play it for greeting and away messages businesses send automatically"
+            ))
+            .build();
+        settings
+            .bind(
+                "automated-message-sounds",
+                &automated_message_sounds,
+                "active",
+            )
+            .build();
+        settings
+            .bind(
+                "message-received-sound",
+                &automated_message_sounds,
+                "sensitive",
+            )
+            .get()
+            .build();
+        sounds.add(&automated_message_sounds);
+
+        let call_ringtone = adw::SwitchRow::builder()
+            .title(i18n!("Ringtone"))
+            .subtitle(i18n!("Ring while a call is incoming"))
+            .build();
+        settings
+            .bind("call-ringtone", &call_ringtone, "active")
+            .build();
+        sounds.add(&call_ringtone);
+
+        let message_sent_sound = adw::SwitchRow::builder()
+            .title(i18n!("Message Sent"))
+            .subtitle(i18n!("Play a sound when a message is sent"))
+            .build();
+        settings
+            .bind("message-sent-sound", &message_sent_sound, "active")
+            .build();
+        sounds.add(&message_sent_sound);
+
+        let message_failed_sound = adw::SwitchRow::builder()
+            .title(i18n!("Message Failed"))
+            .subtitle(i18n!("Play a sound when a message can't be sent"))
+            .build();
+        settings
+            .bind("message-failed-sound", &message_failed_sound, "active")
+            .build();
+        sounds.add(&message_failed_sound);
+
+        general_page.add(&sounds);
+
+        // Attachments group
+        let attachments = adw::PreferencesGroup::builder()
+            .title(i18n!("Attachments"))
+            .description(i18n!(
+                "Files that can run code ask for confirmation before opening, unless their type is trusted"
+            ))
+            .build();
+
+        let trusted_types = adw::EntryRow::builder()
+            .title(i18n!("Trusted File Types"))
+            .text(settings.strv("trusted-attachment-types").join(", "))
+            .show_apply_button(true)
+            .build();
+        trusted_types.connect_apply({
+            let settings = settings.clone();
+            move |row| {
+                let text = row.text().to_lowercase();
+                let types = text
+                    .split([',', ' '])
+                    .map(|t| t.trim().trim_start_matches('.'))
+                    .filter(|t| !t.is_empty())
+                    .collect::<Vec<_>>();
+
+                if let Err(e) = settings.set_strv("trusted-attachment-types", types) {
+                    tracing::error!("Failed to save trusted attachment types: {e}");
+                }
+            }
+        });
+        attachments.add(&trusted_types);
+
+        general_page.add(&attachments);
+
+        // Storage group
+        let storage = adw::PreferencesGroup::builder()
+            .title(i18n!("Storage"))
+            .build();
+
+        let low_space_threshold = adw::SpinRow::builder()
+            .title(i18n!("Low Disk Space Warning"))
+            .subtitle(i18n!(
+                "Free space in MiB below which history sync only keeps chats and avatars aren't downloaded. 0 turns it off"
+            ))
+            .adjustment(&gtk::Adjustment::new(0.0, 0.0, 102_400.0, 256.0, 1024.0, 0.0))
+            .build();
+        settings
+            .bind("low-disk-space-threshold", &low_space_threshold, "value")
+            .build();
+        storage.add(&low_space_threshold);
+
+        general_page.add(&storage);
+
+        // Automatic downloads group
+        let auto_download = adw::PreferencesGroup::builder()
+            .title(i18n!("Automatic Downloads"))
+            .description(i18n!("Videos and documents are only downloaded on request"))
+            .build();
+
+        let auto_download_images = adw::SwitchRow::builder().title(i18n!("Photos")).build();
+        settings
+            .bind("auto-download-images", &auto_download_images, "active")
+            .build();
+        auto_download.add(&auto_download_images);
+
+        let image_limit = adw::SpinRow::builder()
+            .title(i18n!("Photo Size Limit"))
+            .subtitle(i18n!(
+                "Size in MiB above which photos are only downloaded on request"
+            ))
+            .adjustment(&gtk::Adjustment::new(1.0, 1.0, 100.0, 1.0, 5.0, 0.0))
+            .build();
+        settings
+            .bind("auto-download-image-limit", &image_limit, "value")
+            .build();
+        auto_download_images
+            .bind_property("active", &image_limit, "sensitive")
+            .sync_create()
+            .build();
+        auto_download.add(&image_limit);
+
+        let auto_download_audio = adw::SwitchRow::builder()
+            .title(i18n!("Voice Messages and Audio"))
+            .build();
+        settings
+            .bind("auto-download-audio", &auto_download_audio, "active")
+            .build();
+        auto_download.add(&auto_download_audio);
+
+        let auto_download_metered = adw::SwitchRow::builder()
+            .title(i18n!("On Metered Connections"))
+            .subtitle(i18n!(
+                "Also download automatically on connections like mobile data"
+            ))
+            .build();
+        settings
+            .bind("auto-download-metered", &auto_download_metered, "active")
+            .build();
+        auto_download.add(&auto_download_metered);
+
+        general_page.add(&auto_download);
+        widgets.add(&general_page);
+
+        widgets.present(Some(&relm4::main_adw_application().windows()[0]));
+
+        ComponentParts { model, widgets }
+    }
+}
+
+/// Add an editable row for a snippet, saving its text when applied.
+fn add_snippet_row(group: &adw::PreferencesGroup, db: &Arc<Database>, snippet: &Snippet) {
+    let row = adw::EntryRow::builder()
+        .title(&snippet.trigger)
+        .text(&snippet.expansion)
+        .show_apply_button(true)
+        .build();
+    row.connect_apply({
+        let db = Arc::clone(db);
+        let trigger = snippet.trigger.clone();
+        move |row| {
+            let snippet = Snippet {
+                trigger: trigger.clone(),
+                expansion: row.text().to_string(),
+            };
+            let db = Arc::clone(&db);
+            relm4::spawn(async move {
+                if let Err(e) = db.save_snippet(&snippet).await {
+                    tracing::error!("Failed to save snippet: {e}");
+                }
+            });
+        }
+    });
+
+    let delete_button = gtk::Button::builder()
+        .icon_name(icon_or_fallback(
+            "user-trash-symbolic",
+            "edit-delete-symbolic",
+        ))
+        .tooltip_text(i18n!("Delete Snippet"))
+        .valign(gtk::Align::Center)
+        .css_classes(["flat"])
+        .build();
+    delete_button.connect_clicked({
+        let db = Arc::clone(db);
+        let group = group.clone();
+        let row = row.clone();
+        let trigger = snippet.trigger.clone();
+        move |_| {
+            group.remove(&row);
+
+            let db = Arc::clone(&db);
+            let trigger = trigger.clone();
+            relm4::spawn(async move {
+                if let Err(e) = db.delete_snippet(&trigger).await {
+                    tracing::error!("Failed to delete snippet: {e}");
+                }
+            });
+        }
+    });
+    row.add_suffix(&delete_button);
+
+    group.add(&row);
+}
+
+/// Copy an image to the data directory to use as the wallpaper, replacing
+/// the previous one. The copy gets a new name, so the change is noticed.
+fn save_wallpaper_image(source: &Path) -> io::Result<PathBuf> {
+    let dir = DATA_DIR.join("wallpapers");
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+
+    let extension = source
+        .extension()
+        .map_or_else(String::new, |ext| format!(".{}", ext.to_string_lossy()));
+    let path = dir.join(format!("{}{extension}", Utc::now().timestamp_millis()));
+    fs::copy(source, &path)?;
+
+    Ok(path)
+}
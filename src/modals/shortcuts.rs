@@ -1,7 +1,105 @@
 use adw::prelude::*;
-use relm4::prelude::*;
+use gtk::gio;
+use relm4::{actions::ActionName, prelude::*};
 
-use crate::i18n;
+use crate::{
+    application::{
+        ChatSwitcherAction, DoNotDisturbAction, FocusModeAction, NextChatAction, PreferencesAction,
+        PreviousChatAction, QuitAction, ShortcutsAction,
+    },
+    config::APP_ID,
+    i18n,
+    utils::SendShortcut,
+};
+
+/// A keyboard shortcut listed in the shortcuts dialog.
+pub struct Shortcut {
+    /// Description shown in the dialog.
+    pub title: String,
+    /// Detailed name of the action it activates, if any.
+    pub action: Option<String>,
+    /// Accelerators, the first one is shown in the dialog.
+    pub accelerators: &'static [&'static str],
+}
+
+impl Shortcut {
+    /// Create a shortcut that activates an action.
+    fn action<A: ActionName>(title: String, accelerators: &'static [&'static str]) -> Self {
+        Self {
+            title,
+            action: Some(A::action_name()),
+            accelerators,
+        }
+    }
+
+    /// Create a shortcut handled by a widget itself.
+    fn widget(title: String, accelerators: &'static [&'static str]) -> Self {
+        Self {
+            title,
+            action: None,
+            accelerators,
+        }
+    }
+}
+
+/// Get all the keyboard shortcuts by section. Used both to register the
+/// accelerators and to fill the dialog, so they never go out of sync.
+pub fn shortcut_sections() -> Vec<(String, Vec<Shortcut>)> {
+    let send_shortcut = gio::Settings::new(APP_ID)
+        .string("send-shortcut")
+        .parse::<SendShortcut>()
+        .unwrap_or_default();
+
+    vec![
+        (
+            i18n!("General"),
+            vec![
+                Shortcut::action::<PreferencesAction>(i18n!("Preferences"), &["<Control>comma"]),
+                Shortcut::action::<ShortcutsAction>(
+                    i18n!("Keyboard Shortcuts"),
+                    &["<Control>question"],
+                ),
+                Shortcut::action::<DoNotDisturbAction>(
+                    i18n!("Toggle Do Not Disturb"),
+                    &["<Control><Shift>d"],
+                ),
+                Shortcut::action::<FocusModeAction>(
+                    i18n!("Toggle Focus Mode"),
+                    &["<Control><Shift>f"],
+                ),
+                Shortcut::action::<QuitAction>(i18n!("Quit"), &["<Control>q"]),
+            ],
+        ),
+        (
+            i18n!("Chat List"),
+            vec![
+                Shortcut::action::<ChatSwitcherAction>(i18n!("Go to Chat"), &["<Control>k"]),
+                Shortcut::action::<NextChatAction>(
+                    i18n!("Next Chat"),
+                    &["<Control>Tab", "<Alt>Down"],
+                ),
+                Shortcut::action::<PreviousChatAction>(
+                    i18n!("Previous Chat"),
+                    &["<Control><Shift>Tab", "<Alt>Up"],
+                ),
+            ],
+        ),
+        (
+            i18n!("Conversation"),
+            vec![
+                Shortcut::widget(i18n!("Close Chat"), &["Escape"]),
+                Shortcut::widget(i18n!("Return to Message Entry"), &["Escape"]),
+            ],
+        ),
+        (
+            i18n!("Composer"),
+            vec![Shortcut::widget(
+                i18n!("Send Message"),
+                send_shortcut.accelerators(),
+            )],
+        ),
+    ]
+}
 
 pub struct ShortcutsDialog;
 
@@ -24,11 +122,16 @@ impl SimpleComponent for ShortcutsDialog {
         let model = Self {};
         let widgets = root;
 
-        // General section
-        let general = adw::ShortcutsSection::new(Some(&i18n!("General")));
-        general.add(adw::ShortcutsItem::new(&i18n!("Quit"), "<Control>q"));
-        // section.add(adw::ShortcutsItem::new("New Tab", "<Control>t"));
-        widgets.add(general);
+        for (title, shortcuts) in shortcut_sections() {
+            let section = adw::ShortcutsSection::new(Some(&title));
+            for shortcut in shortcuts {
+                section.add(adw::ShortcutsItem::new(
+                    &shortcut.title,
+                    &shortcut.accelerators.join(" "),
+                ));
+            }
+            widgets.add(section);
+        }
 
         widgets.present(Some(&relm4::main_adw_application().windows()[0]));
 
@@ -0,0 +1,306 @@
+use std::time::Duration;
+
+use adw::prelude::*;
+use chrono::Local;
+use gtk::{gdk, glib, pango};
+use relm4::prelude::*;
+
+use crate::{
+    i18n,
+    state::StatusUpdate,
+    thumbnails,
+    utils::{format_date_label, icon_or_fallback},
+};
+
+/// How long a status update is displayed before advancing to the next one.
+const STATUS_DURATION: Duration = Duration::from_secs(6);
+
+pub struct StatusViewer {
+    /// Poster's display name.
+    name: String,
+    /// Index of the displayed status update.
+    index: usize,
+    /// The window itself.
+    window: adw::Window,
+    /// Picture displaying the preview of an image or video.
+    picture: gtk::Picture,
+    /// Status updates to step through, oldest first.
+    updates: Vec<StatusUpdate>,
+    /// How many times a status update was displayed, to ignore stale timers.
+    generation: u64,
+}
+
+pub struct StatusViewerInit {
+    /// Poster's display name.
+    pub name: String,
+    /// Index of the first status update to display.
+    pub start: usize,
+    /// Status updates to step through, oldest first.
+    pub updates: Vec<StatusUpdate>,
+}
+
+#[derive(Debug)]
+pub enum StatusViewerInput {
+    /// Display the next status update.
+    Next,
+    /// Display the previous status update.
+    Previous,
+    /// Auto-advance timer of a status update has elapsed.
+    Elapsed(u64),
+    /// The preview of the status update at this index has been decoded.
+    Loaded(usize, Option<gdk::Texture>),
+    /// Close the viewer.
+    Close,
+    /// The window has been closed.
+    Closed,
+}
+
+#[derive(Debug)]
+pub enum StatusViewerOutput {
+    /// A status update has been viewed.
+    Viewed(String),
+    /// The dialog has been closed.
+    Closed,
+}
+
+#[relm4::component(pub)]
+impl SimpleComponent for StatusViewer {
+    type Init = StatusViewerInit;
+    type Input = StatusViewerInput;
+    type Output = StatusViewerOutput;
+
+    view! {
+        adw::Window {
+            set_modal: true,
+            set_default_width: 420,
+            set_default_height: 720,
+            set_css_classes: &["status-viewer"],
+
+            connect_close_request[sender] => move |_| {
+                sender.input(StatusViewerInput::Closed);
+                glib::Propagation::Proceed
+            },
+
+            #[wrap(Some)]
+            set_content = &adw::ToolbarView {
+                add_top_bar = &adw::HeaderBar {
+                    #[wrap(Some)]
+                    set_title_widget = &adw::WindowTitle {
+                        set_title: &model.name,
+                        #[watch]
+                        set_subtitle: &model.subtitle(),
+                    },
+                },
+
+                #[wrap(Some)]
+                set_content = &gtk::Overlay {
+                    #[wrap(Some)]
+                    set_child = &gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 18,
+                        set_margin_all: 48,
+
+                        #[local_ref]
+                        picture -> gtk::Picture {
+                            set_vexpand: true,
+                            set_can_shrink: true,
+                            set_content_fit: gtk::ContentFit::Contain,
+                            #[watch]
+                            set_visible: model.has_media(),
+                        },
+
+                        gtk::Label {
+                            set_hexpand: true,
+                            #[watch]
+                            set_vexpand: !model.has_media(),
+                            set_wrap: true,
+                            set_wrap_mode: pango::WrapMode::WordChar,
+                            set_justify: gtk::Justification::Center,
+                            set_selectable: false,
+                            #[watch]
+                            set_css_classes: if model.has_media() { &["body"] } else { &["title-2"] },
+                            #[watch]
+                            set_label: model.updates.get(model.index).map_or("", |u| u.content.as_str()),
+                            #[watch]
+                            set_visible: model.updates.get(model.index).is_some_and(|u| !u.content.is_empty()),
+                        },
+
+                        // Tap the left or right half to step through the updates.
+                        add_controller = gtk::GestureClick {
+                            connect_released[sender] => move |gesture, _, x, _| {
+                                let width = gesture.widget().map_or(0, |w| w.width());
+                                if x < f64::from(width) / 2.0 {
+                                    sender.input(StatusViewerInput::Previous);
+                                } else {
+                                    sender.input(StatusViewerInput::Next);
+                                }
+                            }
+                        },
+                    },
+
+                    add_overlay = &gtk::Button {
+                        set_halign: gtk::Align::Start,
+                        set_valign: gtk::Align::Center,
+                        set_margin_start: 12,
+                        set_icon_name: icon_or_fallback("go-previous-symbolic", "go-previous"),
+                        set_tooltip_text: Some(&i18n!("Previous")),
+                        set_css_classes: &["circular", "osd"],
+                        #[watch]
+                        set_visible: model.index > 0,
+
+                        connect_clicked => StatusViewerInput::Previous,
+                    },
+
+                    add_overlay = &gtk::Button {
+                        set_halign: gtk::Align::End,
+                        set_valign: gtk::Align::Center,
+                        set_margin_end: 12,
+                        set_icon_name: icon_or_fallback("go-next-symbolic", "go-next"),
+                        set_tooltip_text: Some(&i18n!("Next")),
+                        set_css_classes: &["circular", "osd"],
+                        #[watch]
+                        set_visible: model.index + 1 < model.updates.len(),
+
+                        connect_clicked => StatusViewerInput::Next,
+                    },
+                },
+            },
+
+            // Step through the updates with the arrow keys.
+            add_controller = gtk::EventControllerKey {
+                connect_key_pressed[sender] => move |_, key, _, _| match key {
+                    gdk::Key::Escape => {
+                        sender.input(StatusViewerInput::Close);
+                        glib::Propagation::Stop
+                    }
+                    gdk::Key::Left => {
+                        sender.input(StatusViewerInput::Previous);
+                        glib::Propagation::Stop
+                    }
+                    gdk::Key::Right => {
+                        sender.input(StatusViewerInput::Next);
+                        glib::Propagation::Stop
+                    }
+                    _ => glib::Propagation::Proceed,
+                }
+            },
+        }
+    }
+
+    fn init(
+        init: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let mut model = Self {
+            name: init.name,
+            index: init.start.min(init.updates.len().saturating_sub(1)),
+            window: root.clone(),
+            picture: gtk::Picture::new(),
+            updates: init.updates,
+            generation: 0,
+        };
+
+        let picture = &model.picture;
+        let widgets = view_output!();
+
+        model.show(&sender);
+        root.set_transient_for(relm4::main_adw_application().windows().first());
+        root.fullscreen();
+        root.present();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, input: Self::Input, sender: ComponentSender<Self>) {
+        match input {
+            StatusViewerInput::Next => {
+                if self.index + 1 < self.updates.len() {
+                    self.index += 1;
+                    self.show(&sender);
+                } else {
+                    self.window.close();
+                }
+            }
+            StatusViewerInput::Previous => {
+                if self.index > 0 {
+                    self.index -= 1;
+                    self.show(&sender);
+                }
+            }
+            StatusViewerInput::Elapsed(generation) => {
+                if generation == self.generation {
+                    sender.input(StatusViewerInput::Next);
+                }
+            }
+            StatusViewerInput::Loaded(index, texture) => {
+                // Drop previews decoded after stepping past them.
+                if index == self.index {
+                    self.picture.set_paintable(texture.as_ref());
+                }
+            }
+            StatusViewerInput::Close => self.window.close(),
+            StatusViewerInput::Closed => {
+                let _ = sender.output(StatusViewerOutput::Closed);
+            }
+        }
+    }
+}
+
+impl StatusViewer {
+    /// Display the current status update and restart the auto-advance timer.
+    /// Previews of images and videos are decoded off the main thread.
+    fn show(&mut self, sender: &ComponentSender<Self>) {
+        self.generation += 1;
+        self.picture.set_paintable(None::<&gdk::Paintable>);
+
+        let Some(status) = self.updates.get_mut(self.index) else {
+            return;
+        };
+
+        if !status.viewed {
+            status.viewed = true;
+            let _ = sender.output(StatusViewerOutput::Viewed(status.id.clone()));
+        }
+
+        if let Some(thumbnail) = status.thumbnail.clone() {
+            let key = format!("status:{}", status.id);
+            let index = self.index;
+            let sender = sender.clone();
+            relm4::spawn_local(async move {
+                let texture = thumbnails::load(key, thumbnail).await;
+                sender.input(StatusViewerInput::Loaded(index, texture));
+            });
+        }
+
+        let sender = sender.clone();
+        let generation = self.generation;
+        glib::timeout_add_local_once(STATUS_DURATION, move || {
+            sender.input(StatusViewerInput::Elapsed(generation));
+        });
+    }
+
+    /// Checks if the displayed status update is an image or a video.
+    fn has_media(&self) -> bool {
+        self.updates
+            .get(self.index)
+            .is_some_and(|status| status.media.is_some())
+    }
+
+    /// Get the position and time of the displayed status update.
+    fn subtitle(&self) -> String {
+        self.updates
+            .get(self.index)
+            .map(|status| {
+                let timestamp = status.timestamp.with_timezone(&Local);
+                format!(
+                    "{}/{} · {}, {}",
+                    self.index + 1,
+                    self.updates.len(),
+                    format_date_label(timestamp.date_naive()),
+                    timestamp.format("%H:%M")
+                )
+            })
+            .unwrap_or_default()
+    }
+}
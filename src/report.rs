@@ -0,0 +1,144 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use gtk::glib;
+use regex::Regex;
+use relm4::gtk;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    DATA_DIR,
+    config::{APP_ID, PROFILE, VERSION},
+    session::AvatarCache,
+};
+
+/// Log file of the current run, the previous one is kept next to it.
+pub static LOG_FILE: LazyLock<PathBuf> = LazyLock::new(|| DATA_DIR.join("papo.log"));
+
+/// Number of log lines included in a problem report.
+const REPORT_LOG_LINES: usize = 1000;
+
+/// Phone numbers, written out or as the user part of JIDs.
+static PHONE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\+\d[\d \-()]{5,}\d|\d{7,}").unwrap());
+
+/// Quoted strings, which hold message bodies, names and other free text.
+static QUOTED_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""(?:[^"\\]|\\.)*""#).unwrap());
+
+/// Move the log of the previous run aside and open a new one.
+pub fn open_log_file() -> io::Result<File> {
+    fs::create_dir_all(DATA_DIR.as_path())?;
+    if LOG_FILE.exists() {
+        fs::rename(LOG_FILE.as_path(), previous_log_file())?;
+    }
+
+    File::create(LOG_FILE.as_path())
+}
+
+/// Get the log file of the previous run.
+fn previous_log_file() -> PathBuf {
+    LOG_FILE.with_extension("log.1")
+}
+
+/// Describe the app and system, for bug reports.
+pub fn debug_info() -> String {
+    format!(
+        "Papo {VERSION} ({PROFILE})\nApp ID: {APP_ID}\nOS: {}\nGTK: {}.{}.{}\nlibadwaita: {}.{}.{}\n",
+        os_info::get(),
+        gtk::major_version(),
+        gtk::minor_version(),
+        gtk::micro_version(),
+        adw::major_version(),
+        adw::minor_version(),
+        adw::micro_version(),
+    )
+}
+
+/// Get the URL to open a new issue, prefilled with the app version.
+pub fn issue_url() -> String {
+    let body = format!("Papo {VERSION} ({PROFILE}), {}", os_info::get());
+
+    format!(
+        "https://github.com/AmanoTeam/Papo/issues/new?body={}",
+        glib::Uri::escape_string(&body, None, false)
+    )
+}
+
+/// Remove phone numbers and quoted text (such as message bodies) from `text`.
+pub fn redact(text: &str) -> String {
+    let text = QUOTED_REGEX.replace_all(text, "\"[redacted]\"");
+
+    PHONE_REGEX.replace_all(&text, "[phone]").into_owned()
+}
+
+/// Read the last `count` lines logged, including the previous run.
+fn read_log_tail(count: usize) -> String {
+    let read = |path: &Path| {
+        fs::read(path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default()
+    };
+    let log = read(&previous_log_file()) + &read(&LOG_FILE);
+
+    let skip = log.lines().count().saturating_sub(count);
+    log.lines().skip(skip).collect::<Vec<_>>().join("\n")
+}
+
+/// Write a problem report to `path` as a zip with the debug info, cache
+/// statistics and the redacted log tail. Nothing is uploaded.
+pub fn write_bundle(path: &Path, schema_version: i64) -> io::Result<()> {
+    let (avatar_count, avatar_size) = AvatarCache::new()?.get_stats()?;
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default();
+
+    zip.start_file("debug-info.txt", options)?;
+    zip.write_all(debug_info().as_bytes())?;
+    writeln!(zip, "Database schema version: {schema_version}")?;
+    writeln!(zip, "Cached avatars: {avatar_count} ({avatar_size} bytes)")?;
+
+    zip.start_file("papo.log", options)?;
+    zip.write_all(redact(&read_log_tail(REPORT_LOG_LINES)).as_bytes())?;
+
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phone_numbers_are_redacted() {
+        assert_eq!(
+            redact("Chat 5511999990000@s.whatsapp.net left"),
+            "Chat [phone]@s.whatsapp.net left"
+        );
+        assert_eq!(redact("Call +55 11 99999-0000 now"), "Call [phone] now");
+        assert_eq!(redact("Chat 12345678901234@lid"), "Chat [phone]@lid");
+    }
+
+    #[test]
+    fn quoted_text_is_redacted() {
+        assert_eq!(
+            redact(r#"Updated chat name for 120363@g.us to: "Alice \"Al\" Smith""#),
+            r#"Updated chat name for 120363@g.us to: "[redacted]""#
+        );
+        assert_eq!(
+            redact(r#"keeping local "Bob" over "Robert""#),
+            r#"keeping local "[redacted]" over "[redacted]""#
+        );
+    }
+
+    #[test]
+    fn other_text_is_kept() {
+        let line = "Synced 12 messages (of 40 received, 3 duplicates)";
+
+        assert_eq!(redact(line), line);
+    }
+}
@@ -0,0 +1,42 @@
+use gtk::{gio, glib, prelude::*};
+use relm4::gtk;
+
+/// GNOME Shell, which knows when the screen is being shared or recorded.
+const SHELL_BUS_NAME: &str = "org.gnome.Shell";
+/// Object of the GNOME Shell introspection interface.
+const INTROSPECT_PATH: &str = "/org/gnome/Shell/Introspect";
+/// GNOME Shell introspection interface.
+const INTROSPECT_INTERFACE: &str = "org.gnome.Shell.Introspect";
+/// Property set while the screen is being shared or recorded.
+const SCREENCAST_PROPERTY: &str = "ScreencastActive";
+
+/// Watch whether the screen is being shared or recorded, calling `callback`
+/// with the current state and again whenever it changes.
+///
+/// The portal doesn't expose the sessions of other apps, so this relies on
+/// GNOME Shell, and reports no screencast elsewhere. The returned proxy must
+/// be kept alive to keep watching.
+pub async fn watch(callback: impl Fn(bool) + 'static) -> Result<gio::DBusProxy, glib::Error> {
+    let proxy = gio::DBusProxy::for_bus_future(
+        gio::BusType::Session,
+        gio::DBusProxyFlags::DO_NOT_AUTO_START,
+        None,
+        SHELL_BUS_NAME,
+        INTROSPECT_PATH,
+        INTROSPECT_INTERFACE,
+    )
+    .await?;
+
+    callback(is_active(&proxy));
+    proxy.connect_g_properties_changed(move |proxy, _, _| callback(is_active(proxy)));
+
+    Ok(proxy)
+}
+
+/// Check if the screen is being shared or recorded.
+fn is_active(proxy: &gio::DBusProxy) -> bool {
+    proxy
+        .cached_property(SCREENCAST_PROPERTY)
+        .and_then(|value| value.get::<bool>())
+        .unwrap_or_default()
+}
@@ -0,0 +1,237 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use gtk::{gio, glib, prelude::*};
+use relm4::{gtk, main_application};
+
+use crate::{cli, i18n, session::AvatarCache, state::ChatKind, store::Database, utils::format_jid};
+
+/// Object path the search provider is exported at, as set in the
+/// search provider file installed for GNOME Shell.
+pub const OBJECT_PATH: &str = "/com/amanoteam/Papo/SearchProvider";
+
+/// Most results returned, as GNOME Shell only shows a handful anyway.
+const MAX_RESULTS: usize = 10;
+
+const INTERFACE_XML: &str = r#"
+<node>
+  <interface name="org.gnome.Shell.SearchProvider2">
+    <method name="GetInitialResultSet">
+      <arg type="as" name="terms" direction="in" />
+      <arg type="as" name="results" direction="out" />
+    </method>
+    <method name="GetSubsearchResultSet">
+      <arg type="as" name="previous_results" direction="in" />
+      <arg type="as" name="terms" direction="in" />
+      <arg type="as" name="results" direction="out" />
+    </method>
+    <method name="GetResultMetas">
+      <arg type="as" name="identifiers" direction="in" />
+      <arg type="aa{sv}" name="metas" direction="out" />
+    </method>
+    <method name="ActivateResult">
+      <arg type="s" name="identifier" direction="in" />
+      <arg type="as" name="terms" direction="in" />
+      <arg type="u" name="timestamp" direction="in" />
+    </method>
+    <method name="LaunchSearch">
+      <arg type="as" name="terms" direction="in" />
+      <arg type="u" name="timestamp" direction="in" />
+    </method>
+  </interface>
+</node>
+"#;
+
+/// Names of the chats and contacts found so far, by JID, to describe and
+/// open the results GNOME Shell asks about.
+type Names = Rc<RefCell<HashMap<String, String>>>;
+
+/// Export the search provider on the session bus connection of the app.
+///
+/// Called on startup, so it's also there when the app is only started as a
+/// D-Bus service by GNOME Shell, without a window.
+pub fn register() {
+    let Some(connection) = main_application().dbus_connection() else {
+        tracing::warn!("Not on the session bus, the search provider won't be available");
+        return;
+    };
+
+    let interface = match gio::DBusNodeInfo::for_xml(INTERFACE_XML) {
+        Ok(node) => node.lookup_interface("org.gnome.Shell.SearchProvider2"),
+        Err(e) => {
+            tracing::error!("Failed to parse the search provider interface: {e}");
+            return;
+        }
+    };
+    let Some(interface) = interface else {
+        return;
+    };
+
+    let names = Names::default();
+    let result = connection
+        .register_object(OBJECT_PATH, &interface)
+        .method_call(
+            move |_, _, _, _, method, parameters, invocation| match method {
+                "GetInitialResultSet" => {
+                    if let Some((terms,)) = parameters.get::<(Vec<String>,)>() {
+                        search(Rc::clone(&names), terms, None, invocation);
+                    }
+                }
+                "GetSubsearchResultSet" => {
+                    if let Some((previous, terms)) = parameters.get::<(Vec<String>, Vec<String>)>()
+                    {
+                        search(Rc::clone(&names), terms, Some(previous), invocation);
+                    }
+                }
+                "GetResultMetas" => {
+                    if let Some((identifiers,)) = parameters.get::<(Vec<String>,)>() {
+                        let metas = result_metas(&names.borrow(), &identifiers);
+                        invocation.return_value(Some(&glib::Variant::tuple_from_iter([metas])));
+                    }
+                }
+                "ActivateResult" => {
+                    if let Some((jid, _, _)) = parameters.get::<(String, Vec<String>, u32)>() {
+                        activate(Some(jid));
+                    }
+                    invocation.return_value(None);
+                }
+                "LaunchSearch" => {
+                    activate(None);
+                    invocation.return_value(None);
+                }
+                _ => invocation.return_error(
+                    gio::DBusError::UnknownMethod,
+                    &format!("Unknown method {method}"),
+                ),
+            },
+        )
+        .build();
+
+    if let Err(e) = result {
+        tracing::error!("Failed to export the search provider: {e}");
+    }
+}
+
+/// Look for chats and contacts matching all `terms`, within `previous`
+/// results if given, and reply to `invocation` with their JIDs.
+fn search(
+    names: Names,
+    terms: Vec<String>,
+    previous: Option<Vec<String>>,
+    invocation: gio::DBusMethodInvocation,
+) {
+    let terms: Vec<String> = terms.iter().map(|term| term.to_lowercase()).collect();
+    let matches = move |texts: &[&str]| {
+        let text = texts.join(" ").to_lowercase();
+        terms.iter().all(|term| text.contains(term.as_str()))
+    };
+
+    relm4::spawn_local(async move {
+        let mut results: Vec<(String, String)> = Vec::new();
+
+        let db = match Database::shared().await {
+            Ok(db) => db,
+            Err(e) => {
+                tracing::error!("Failed to open the database for the search provider: {e}");
+                invocation.return_value(Some(&(Vec::<String>::new(),).to_variant()));
+                return;
+            }
+        };
+
+        match db.load_chats().await {
+            Ok(chats) => {
+                for chat in chats {
+                    let name = chat.get_name_or_number();
+                    if matches(&[&name, &format_jid(&chat.jid)]) {
+                        results.push((chat.jid, name));
+                    }
+                }
+            }
+            Err(e) => tracing::error!("Failed to load chats for the search provider: {e}"),
+        }
+
+        // Contacts without a chat yet, which would be opened as a new one.
+        match db.get_all_contacts().await {
+            Ok(contacts) => {
+                for contact in contacts {
+                    let Some(name) = contact.name.or(contact.push_name) else {
+                        continue;
+                    };
+                    let phone_number = contact.phone_number.unwrap_or_default();
+                    if !results.iter().any(|(jid, _)| *jid == contact.jid)
+                        && matches(&[&name, &phone_number])
+                    {
+                        results.push((contact.jid, name));
+                    }
+                }
+            }
+            Err(e) => tracing::error!("Failed to load contacts for the search provider: {e}"),
+        }
+
+        if let Some(previous) = previous {
+            results.retain(|(jid, _)| previous.contains(jid));
+        }
+        results.truncate(MAX_RESULTS);
+
+        let mut names = names.borrow_mut();
+        let jids: Vec<String> = results
+            .into_iter()
+            .map(|(jid, name)| {
+                names.insert(jid.clone(), name);
+                jid
+            })
+            .collect();
+        invocation.return_value(Some(&(jids,).to_variant()));
+    });
+}
+
+/// Describe the results with the given JIDs, as `aa{sv}`.
+///
+/// Only the name and the phone number or kind of chat are shown, never
+/// message content: results show up in the overview for anyone looking at
+/// the screen, and there's no notification privacy level to allow previews.
+fn result_metas(names: &HashMap<String, String>, jids: &[String]) -> glib::Variant {
+    let avatars = AvatarCache::new().ok();
+
+    let metas = jids.iter().filter_map(|jid| {
+        let name = names.get(jid)?;
+
+        let meta = glib::VariantDict::new(None);
+        meta.insert("id", jid.as_str());
+        meta.insert("name", name.as_str());
+
+        let description = match ChatKind::from_jid(jid) {
+            kind if kind.is_private() => Some(format_jid(jid)),
+            ChatKind::Group => Some(i18n!("Group")),
+            ChatKind::Newsletter => Some(i18n!("Channel")),
+            _ => None,
+        };
+        if let Some(description) = description.filter(|d| d != name) {
+            meta.insert("description", description);
+        }
+
+        let icon = avatars
+            .as_ref()
+            .and_then(|avatars| avatars.get_cached_path(jid))
+            .map_or_else(
+                || gio::ThemedIcon::new("avatar-default-symbolic").upcast::<gio::Icon>(),
+                |path| gio::FileIcon::new(&gio::File::for_path(path)).upcast(),
+            );
+        if let Some(icon) = icon.serialize() {
+            meta.insert_value("icon", &icon);
+        }
+
+        Some(meta.end())
+    });
+
+    glib::Variant::array_from_iter_with_type(glib::VariantTy::VARDICT, metas)
+}
+
+/// Bring the window up, opening the chat or contact with the given JID if any.
+fn activate(jid: Option<String>) {
+    let app = main_application();
+
+    match jid {
+        Some(jid) => cli::open_chat(&app, jid),
+        None => app.activate(),
+    }
+}
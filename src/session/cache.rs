@@ -1,7 +1,14 @@
-use std::{fs, io, path::PathBuf};
+use std::{fs, io, path::PathBuf, time::Duration};
 
 use crate::DATA_DIR;
 
+/// Time to wait before fetching an avatar the server refused again,
+/// doubled on each refusal.
+const REFUSED_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+/// Longest time to wait before fetching a refused avatar again.
+const MAX_REFUSED_COOLDOWN: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
 /// Cache for chat avatars downloaded from `WhatsApp`.
 #[derive(Clone, Debug)]
 pub struct AvatarCache {
@@ -26,6 +33,47 @@ impl AvatarCache {
         self.cache_dir.join(format!("{safe_jid}.jpg"))
     }
 
+    /// Get the path of the file counting the refusals of an avatar.
+    fn get_refused_path(&self, jid: &str) -> PathBuf {
+        self.get_avatar_path(jid).with_extension("refused")
+    }
+
+    /// Note that the server refused to give the avatar of `jid`, such as when
+    /// hidden from us or when we're blocked, to back off from fetching it.
+    pub fn mark_refused(&self, jid: &str) -> Result<(), io::Error> {
+        let path = self.get_refused_path(jid);
+        let refusals = fs::read_to_string(&path)
+            .ok()
+            .and_then(|count| count.trim().parse::<u32>().ok())
+            .unwrap_or(0);
+
+        fs::write(path, refusals.saturating_add(1).to_string())
+    }
+
+    /// Check if fetching the avatar of `jid` should wait, as the server
+    /// refused it recently.
+    pub fn is_refused(&self, jid: &str) -> bool {
+        let path = self.get_refused_path(jid);
+        let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+            return false;
+        };
+        let refusals = fs::read_to_string(&path)
+            .ok()
+            .and_then(|count| count.trim().parse::<u32>().ok())
+            .unwrap_or(1);
+
+        let cooldown = REFUSED_COOLDOWN
+            .saturating_mul(2_u32.saturating_pow(refusals.saturating_sub(1)))
+            .min(MAX_REFUSED_COOLDOWN);
+        modified.elapsed().is_ok_and(|elapsed| elapsed < cooldown)
+    }
+
+    /// Check if the server ever refused the avatar of `jid` since it was
+    /// last downloaded.
+    pub fn was_refused(&self, jid: &str) -> bool {
+        self.get_refused_path(jid).exists()
+    }
+
     /// Check if an avatar is cached.
     pub fn is_cached(&self, jid: &str) -> bool {
         self.get_avatar_path(jid).exists()
@@ -41,6 +89,7 @@ impl AvatarCache {
     pub fn save_avatar(&self, jid: &str, data: &[u8]) -> Result<String, io::Error> {
         let path = self.get_avatar_path(jid);
         fs::write(&path, data)?;
+        let _ = fs::remove_file(self.get_refused_path(jid));
 
         Ok(path.to_string_lossy().into_owned())
     }
@@ -64,4 +113,19 @@ impl AvatarCache {
 
         Ok(())
     }
+
+    /// Get the number of cached avatars and their total size in bytes.
+    pub fn get_stats(&self) -> Result<(usize, u64), io::Error> {
+        let mut count = 0;
+        let mut size = 0;
+        for entry in fs::read_dir(&self.cache_dir)? {
+            let entry = entry?;
+            if entry.path().extension().is_some_and(|ext| ext == "jpg") {
+                count += 1;
+                size += entry.metadata()?.len();
+            }
+        }
+
+        Ok((count, size))
+    }
 }
@@ -1,5 +1,6 @@
 use std::{
     fs,
+    io::Read,
     path::Path,
     sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
@@ -7,6 +8,7 @@ use std::{
 
 use adw::prelude::*;
 use chrono::{DateTime, Utc};
+use gtk::glib;
 use relm4::prelude::*;
 use tokio::sync::Mutex;
 use uuid::Uuid;
@@ -22,12 +24,43 @@ use wacore::{
 use waproto::whatsapp::{
     Message,
     device_props::{AppVersion, PlatformType},
+    group_participant,
+};
+use whatsapp_rust::{
+    Jid, TokioRuntime, bot::Bot, calls::CallOptions, request::IqError, store::SqliteStore,
 };
-use whatsapp_rust::{Jid, TokioRuntime, bot::Bot, store::SqliteStore};
 use whatsapp_rust_tokio_transport::TokioWebSocketTransportFactory;
 use whatsapp_rust_ureq_http_client::UreqHttpClient;
 
-use crate::{DATA_DIR, i18n, i18n_f, session::AvatarCache, state::ChatMessage};
+use crate::{
+    DATA_DIR, i18n, i18n_f,
+    session::{AvatarCache, IngestionGate, IngestionTicket},
+    state::{ChatKind, ChatMessage, DownloadableMedia, GroupEvent, LinkPreview, MessageContent},
+    store::JoinRequest,
+    utils::{extract_links, icon_or_fallback, is_low_on_space},
+};
+
+/// Error code the server answers queries it refuses to answer with.
+const NOT_AUTHORIZED: u16 = 401;
+
+/// Error code the server answers pairing with a number it can't link with.
+const BAD_REQUEST: u16 = 400;
+
+/// Error code the server answers with after too many pairing attempts.
+const RATE_OVERLIMIT: u16 = 429;
+
+/// Maximum number of participants of a group passed along while syncing.
+const MAX_SYNCED_PARTICIPANTS: usize = 256;
+
+/// Number of messages asked from the phone when restoring cleared history.
+const HISTORY_REQUEST_COUNT: i32 = 500;
+
+/// How much of a linked page is read to build its preview, the metadata
+/// being in its head.
+const MAX_PREVIEW_PAGE_SIZE: usize = 512 * 1024;
+
+/// Longest wait for a linked page to build its preview.
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Shared client handle for accessing the `WhatsApp` client.
 pub type ClientHandle = Arc<Mutex<Option<Arc<whatsapp_rust::Client>>>>;
@@ -36,13 +69,14 @@ pub type ClientHandle = Arc<Mutex<Option<Arc<whatsapp_rust::Client>>>>;
 /// a clean interface for UI operations.
 #[derive(Clone)]
 pub struct Client {
+    /// Holds avatar downloads back while received messages are handled.
+    gate: IngestionGate,
     /// Client connection state.
     pub state: ClientState,
     /// Shared client reference.
     handle: ClientHandle,
     /// System OS type.
     os_type: String,
-
     /// Avatar cache for downloading and storing profile pictures.
     avatar_cache: Arc<Mutex<Option<AvatarCache>>>,
 }
@@ -74,6 +108,127 @@ pub enum ClientState {
     Error(String),
 }
 
+/// A failure of the client, told apart so the UI can pick how to show it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientError {
+    /// The session storage couldn't be opened.
+    Database(String),
+    /// The connection failed, and may succeed when retried.
+    Network(String),
+    /// Pairing was refused.
+    Pairing(PairFailure),
+}
+
+impl ClientError {
+    /// Describe the error to the user.
+    pub fn message(&self) -> String {
+        match self {
+            Self::Database(reason) => i18n_f!("Database error: {0}", reason),
+            Self::Network(reason) => i18n_f!("Connection failed: {0}", reason),
+            Self::Pairing(failure) => failure.title(),
+        }
+    }
+}
+
+/// Why read receipts couldn't be sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReceiptError {
+    /// There's no connection to send them on; they're sent once there is.
+    NotConnected,
+    /// Sending them failed, with the reason.
+    Failed(String),
+}
+
+impl std::fmt::Display for ReceiptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotConnected => f.write_str("Not connected"),
+            Self::Failed(reason) => f.write_str(reason),
+        }
+    }
+}
+
+/// Why pairing failed, told apart from the error the server answered with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PairFailure {
+    /// The account already has as many linked devices as allowed.
+    TooManyDevices,
+    /// Too many attempts in a short time.
+    RateLimited,
+    /// The phone number is malformed or not on `WhatsApp`.
+    InvalidNumber,
+    /// Any other failure, with its message.
+    Other(String),
+}
+
+impl PairFailure {
+    /// Tell the failure apart from the error pairing failed with, by the
+    /// status code the server answered with, or else by its message.
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        match error.downcast_ref::<IqError>() {
+            Some(IqError::ServerError {
+                code: RATE_OVERLIMIT,
+                ..
+            }) => Self::RateLimited,
+            Some(IqError::ServerError {
+                code: BAD_REQUEST, ..
+            }) => Self::InvalidNumber,
+            _ => Self::from_message(&error.to_string()),
+        }
+    }
+
+    /// Tell the failure apart from its error message, by the condition the
+    /// server answered with.
+    pub fn from_message(error: &str) -> Self {
+        let lowercase = error.to_lowercase();
+        let mentions = |patterns: &[&str]| patterns.iter().any(|p| lowercase.contains(p));
+
+        if mentions(&["too many devices", "device limit", "companion limit"]) {
+            Self::TooManyDevices
+        } else if mentions(&["rate-overlimit", "rate limit"]) {
+            Self::RateLimited
+        } else if mentions(&["bad-request", "invalid phone"]) {
+            Self::InvalidNumber
+        } else {
+            Self::Other(error.to_string())
+        }
+    }
+
+    /// Gets an icon for the failure.
+    pub fn icon_name(&self) -> &'static str {
+        match self {
+            Self::TooManyDevices => {
+                icon_or_fallback("phonelink-setup-symbolic", "computer-symbolic")
+            }
+            _ => icon_or_fallback("exclamation-mark-symbolic", "dialog-error-symbolic"),
+        }
+    }
+
+    /// Gets a title for the failure.
+    pub fn title(&self) -> String {
+        match self {
+            Self::TooManyDevices => i18n!("Too Many Linked Devices"),
+            Self::RateLimited => i18n!("Too Many Attempts"),
+            Self::InvalidNumber => i18n!("Couldn't Link With This Number"),
+            Self::Other(_) => i18n!("Couldn't Link Your Phone"),
+        }
+    }
+
+    /// Gets what to do about the failure, as markup.
+    pub fn description(&self) -> String {
+        match self {
+            Self::TooManyDevices => i18n!(
+                "WhatsApp only allows a few linked devices. On your phone, open <i>Menu &gt; Linked devices</i>, log out of one, then try again."
+            ),
+            Self::RateLimited => i18n!("Wait a few minutes before trying again."),
+            Self::InvalidNumber => {
+                i18n!("Check that the phone number includes its country code and is on WhatsApp.")
+            }
+            Self::Other(message) => glib::markup_escape_text(message).to_string(),
+        }
+    }
+}
+
 impl ClientState {
     /// Checks if the client is paired.
     pub fn is_paired(&self) -> bool {
@@ -99,25 +254,90 @@ pub enum ClientInput {
     AcceptCall { call_id: String },
     /// Decline an incoming call.
     DeclineCall { call_id: String },
+    /// Hang up a call, or cancel it while it's ringing.
+    EndCall { call_id: String },
 
     /// Send typing indicator.
     SendTyping { jid: String },
     /// Stop typing indicator.
     StopTyping { jid: String },
 
-    /// Mark messages as read.
+    /// Mark messages as read, reporting back with `id`.
     MarkRead {
+        id: Uuid,
         chat_jid: String,
         sender_jid: Option<String>,
         message_ids: Vec<String>,
     },
-    /// Send a message.
-    SendMessage { message: Box<ChatMessage> },
+    /// Send a message, as a reply if it quotes another.
+    SendMessage {
+        message: Box<ChatMessage>,
+        /// Whether to fetch the page of the first link in the text, to
+        /// attach its preview.
+        fetch_preview: bool,
+    },
+    /// Vote in a poll, replacing our previous vote.
+    VotePoll {
+        poll: Box<ChatMessage>,
+        voter_jid: String,
+        options: Vec<String>,
+    },
+    /// Pin or unpin a message in its chat for everyone.
+    PinMessage {
+        message: Box<ChatMessage>,
+        pinned: bool,
+    },
+    /// React to a message, replacing our previous reaction, or remove it
+    /// with an empty `emoji`.
+    React {
+        message: Box<ChatMessage>,
+        emoji: String,
+    },
+    /// Ask the phone for the messages of a chat sent before `oldest`.
+    RequestHistory { oldest: Box<ChatMessage> },
     /// Fetch avatar for a chat.
     FetchAvatar {
         /// Chat JID.
         jid: String,
     },
+    /// Ask to be told when a contact comes online or is typing.
+    SubscribePresence {
+        /// Contact JID.
+        jid: String,
+    },
+    /// Fetch all the participants of a group.
+    FetchParticipants {
+        /// Group JID.
+        jid: String,
+    },
+    /// Fetch the people asking to join a group the user is an admin of.
+    FetchJoinRequests {
+        /// Group JID.
+        jid: String,
+    },
+    /// Approve or reject requests to join a group.
+    ResolveJoinRequests {
+        /// Group JID.
+        jid: String,
+        /// JIDs of who asked to join.
+        participants: Vec<String>,
+        approve: bool,
+    },
+    /// Download the full media of a message.
+    DownloadMedia {
+        /// Chat JID.
+        chat_jid: String,
+        /// Message ID.
+        message_id: String,
+        media: DownloadableMedia,
+    },
+    /// Fetch what contacts see of the connected account.
+    FetchOwnProfile,
+    /// Check if a phone number is on `WhatsApp`.
+    CheckNumber {
+        /// Phone number, digits only.
+        phone_number: String,
+    },
 }
 
 #[derive(Debug)]
@@ -147,6 +367,7 @@ pub enum ClientOutput {
     },
     /// Client has paired successfully.
     PairSuccess,
+    /// Pairing has failed.
 
     /// Syncing in progress.
     Syncing,
@@ -157,7 +378,17 @@ pub enum ClientOutput {
         from_jid: String,
         is_video: bool,
     },
-    /// Call ended.
+    /// Outgoing call placed, ringing on the other side.
+    CallStarted {
+        call_id: String,
+        jid: String,
+        is_video: bool,
+    },
+    /// Outgoing call couldn't be placed.
+    CallFailed { jid: String },
+    /// Call answered, by the other side or by us.
+    CallAccepted { call_id: String },
+    /// Call ended, declined or cancelled.
     CallEnded { call_id: String },
 
     /// Message receipt updated.
@@ -174,13 +405,65 @@ pub enum ClientOutput {
     },
 
     /// Message was sent successfully.
-    MessageSent { chat_jid: String, msg_id: Uuid },
+    MessageSent {
+        chat_jid: String,
+        msg_id: Uuid,
+        /// Preview of its link attached while sending.
+        preview: Option<LinkPreview>,
+    },
     /// Message failed to send.
     MessageFailed { chat_jid: String, msg_id: Uuid },
     /// New message received.
     MessageReceived {
         info: Box<MessageInfo>,
         message: Box<Message>,
+        /// Held until the message is handled.
+        ticket: IngestionTicket,
+    },
+    /// Message received that couldn't be decrypted.
+    UndecryptableMessage { chat_jid: String, msg_id: String },
+    /// A group changed while connected (`w:gp2` notification).
+    GroupChanged {
+        chat_jid: String,
+        /// ID of the notification, unique to each change in it.
+        id: String,
+        timestamp: DateTime<Utc>,
+        event: GroupEvent,
+    },
+    /// People asked to join a group the user is an admin of.
+    JoinRequested {
+        chat_jid: String,
+        participants: Vec<String>,
+        timestamp: DateTime<Utc>,
+    },
+    /// Requests to join a group were withdrawn, or resolved by another admin.
+    JoinRequestsWithdrawn {
+        chat_jid: String,
+        participants: Vec<String>,
+    },
+    /// The people asking to join a group have been fetched.
+    JoinRequestsFetched {
+        chat_jid: String,
+        requests: Vec<JoinRequest>,
+    },
+    /// Requests to join a group have been approved or rejected.
+    JoinRequestsResolved {
+        chat_jid: String,
+        participants: Vec<String>,
+        approved: bool,
+    },
+    /// Requests to join a group couldn't be approved or rejected.
+    JoinRequestsFailed { chat_jid: String },
+    /// The full media of a message has been downloaded.
+    MediaDownloaded {
+        chat_jid: String,
+        message_id: String,
+        data: Vec<u8>,
+    },
+    /// The full media of a message couldn't be downloaded.
+    MediaDownloadFailed {
+        chat_jid: String,
+        message_id: String,
     },
 
     /// Chat synced from history (`JoinedGroup` event).
@@ -189,6 +472,8 @@ pub enum ClientOutput {
         jid: String,
         /// Display name.
         name: Option<String>,
+        /// Alternate (PN or LID) JID of the chat.
+        alt_jid: Option<String>,
         /// Whether chat is pinned.
         pinned: bool,
         /// Whether chat is archived.
@@ -197,10 +482,16 @@ pub enum ClientOutput {
         unread_count: Option<u32>,
         /// Group participants (for groups).
         participants: Vec<(String, Option<String>)>,
+        /// Group admins (for groups).
+        admins: Vec<String>,
         /// Mute end time (if muted).
         mute_end_time: Option<u64>,
         /// Last message timestamp.
         last_message_time: Option<u64>,
+        /// Number of participants (for groups).
+        participant_count: Option<u32>,
+        /// When the conversation was last modified on the server.
+        modified_at: Option<u64>,
     },
     /// Messages synced from history for a chat.
     MessagesSynced {
@@ -208,6 +499,8 @@ pub enum ClientOutput {
         chat_jid: String,
         /// Synced messages.
         messages: Vec<SyncedMessage>,
+        /// Held until the messages are handled.
+        ticket: IngestionTicket,
     },
 
     /// Chat property updated (pin, mute, archive).
@@ -218,6 +511,8 @@ pub enum ClientOutput {
         pinned: Option<bool>,
         /// Whether the chat is muted.
         muted: Option<bool>,
+        /// When the mute ends, as sent by WhatsApp.
+        mute_end: Option<i64>,
         /// Whether the chat is archived.
         archived: Option<bool>,
     },
@@ -245,9 +540,36 @@ pub enum ClientOutput {
         /// Phone number (from JID user part).
         phone_number: String,
     },
+    /// Profile of the connected account fetched.
+    OwnProfile {
+        /// Phone number JID of the account.
+        phone_jid: Option<String>,
+        /// About text, if set and visible.
+        about: Option<String>,
+    },
+    /// All the participants of a group have been fetched.
+    ParticipantsFetched {
+        /// Group JID.
+        jid: String,
+        /// Participant JIDs.
+        participants: Vec<String>,
+    },
+    /// A phone number has been checked.
+    NumberChecked {
+        /// Phone number, as asked.
+        phone_number: String,
+        /// JID of the number if it's on `WhatsApp`, or why it couldn't be checked.
+        result: Result<Option<String>, String>,
+    },
+
+    /// Messages have been marked as read, or failed to.
+    MarkedRead {
+        id: Uuid,
+        result: Result<(), ReceiptError>,
+    },
 
     /// Error occurred.
-    Error { message: String },
+    Error(ClientError),
 }
 
 /// A message synced from history.
@@ -257,18 +579,67 @@ pub struct SyncedMessage {
     pub id: String,
     /// Whether message is unread.
     pub unread: bool,
-    /// Message content (text).
-    pub content: Option<String>,
+    /// What the message carries, read like received ones.
+    pub content: MessageContent,
     /// Whether message was sent by current user.
     pub outgoing: bool,
     /// Message timestamp.
     pub timestamp: u64,
     /// Sender JID.
     pub sender_jid: String,
+    /// Group change this message stands for, if it's a service event.
+    pub group_event: Option<GroupEvent>,
     /// Sender push name.
     pub sender_name: Option<String>,
 }
 
+/// Fetch the page a link points to and build its preview from its metadata.
+async fn fetch_link_preview(url: String) -> Option<LinkPreview> {
+    let page = relm4::spawn_blocking({
+        let url = url.clone();
+        move || fetch_preview_page(&url)
+    })
+    .await
+    .ok()?
+    .inspect_err(|e| tracing::warn!("Failed to fetch link preview: {e}"))
+    .ok()??;
+
+    LinkPreview::from_html(url, &page)
+}
+
+/// Read the start of an HTML page, if `url` points to one.
+fn fetch_preview_page(url: &str) -> Result<Option<String>, ureq::Error> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(PREVIEW_TIMEOUT))
+        .build()
+        .into();
+    let mut response = agent.get(url).call()?;
+
+    if let Some(mime_type) = response.body().mime_type()
+        && !is_html_type(mime_type)
+    {
+        tracing::debug!("No link preview for a {mime_type} page");
+        return Ok(None);
+    }
+
+    let mut page = Vec::new();
+    response
+        .body_mut()
+        .as_reader()
+        .take(MAX_PREVIEW_PAGE_SIZE as u64)
+        .read_to_end(&mut page)?;
+
+    Ok(Some(String::from_utf8_lossy(&page).into_owned()))
+}
+
+/// Checks if a MIME type is of a page with metadata for a preview.
+fn is_html_type(mime_type: &str) -> bool {
+    let mime_type = mime_type.split(';').next().unwrap_or_default().trim();
+
+    mime_type.eq_ignore_ascii_case("text/html")
+        || mime_type.eq_ignore_ascii_case("application/xhtml+xml")
+}
+
 /// Delete the `WhatsApp` database files to clear stored credentials.
 fn clear_whatsapp_credentials() {
     let db_path = DATA_DIR.join("whatsapp.db");
@@ -288,6 +659,39 @@ fn clear_whatsapp_credentials() {
     }
 }
 
+/// Extract the first participants of a group conversation, with the total count.
+/// Large groups and communities can have thousands of them, so only the first
+/// page flows through sync; the rest are fetched when the group is opened.
+fn synced_participants(
+    conv: &waproto::whatsapp::Conversation,
+    is_group: bool,
+) -> (Vec<(String, Option<String>)>, Option<u32>) {
+    if !is_group {
+        return (Vec::new(), None);
+    }
+
+    let participants = conv
+        .participant
+        .iter()
+        .take(MAX_SYNCED_PARTICIPANTS)
+        .map(|p| (p.user_jid.clone(), None))
+        .collect();
+
+    (
+        participants,
+        Some(u32::try_from(conv.participant.len()).unwrap_or(u32::MAX)),
+    )
+}
+
+/// Extract the admins of a group conversation, among all its participants.
+fn synced_admins(conv: &waproto::whatsapp::Conversation) -> Vec<String> {
+    conv.participant
+        .iter()
+        .filter(|p| p.rank() != group_participant::Rank::Regular)
+        .map(|p| p.user_jid.clone())
+        .collect()
+}
+
 /// Extract synced messages from a conversation's message list.
 /// Shared between `ProcessJoinedGroup` and `ProcessHistorySync`.
 fn extract_synced_messages(
@@ -296,9 +700,7 @@ fn extract_synced_messages(
 ) -> Vec<SyncedMessage> {
     let mut synced_messages = Vec::new();
     for hist_msg in &conv.messages {
-        if let Some(web_msg) = &hist_msg.message
-            && let Some(msg) = &web_msg.message
-        {
+        if let Some(web_msg) = &hist_msg.message {
             let msg_id = web_msg.key.id.clone().unwrap_or_default();
             let sender_jid = web_msg
                 .key
@@ -313,30 +715,71 @@ fn extract_synced_messages(
                     .as_secs()
             });
 
-            let content = msg
-                .conversation
-                .clone()
-                .filter(|c| !c.is_empty())
-                .or_else(|| {
-                    msg.extended_text_message
-                        .as_ref()
-                        .and_then(|e| e.text.clone().filter(|t| !t.is_empty()))
-                });
+            // Group changes come as stubs, without a message.
+            let group_event = web_msg.message_stub_type.and_then(|stub_type| {
+                GroupEvent::from_stub(stub_type, &web_msg.message_stub_parameters, &sender_jid)
+            });
+            let Some(msg) = &web_msg.message else {
+                if group_event.is_some() {
+                    synced_messages.push(SyncedMessage {
+                        id: msg_id,
+                        unread: false,
+                        content: MessageContent::default(),
+                        outgoing,
+                        timestamp,
+                        sender_jid,
+                        group_event,
+                        sender_name: None,
+                    });
+                }
+                continue;
+            };
 
             synced_messages.push(SyncedMessage {
                 id: msg_id,
                 unread: false,
-                content,
+                content: MessageContent::from_message(msg),
                 outgoing,
                 timestamp,
                 sender_jid,
+                group_event: None,
                 sender_name: web_msg.push_name.clone().filter(|n| !n.is_empty()),
             });
         }
     }
+
+    // Only the newest incoming messages are unread, as many as the phone reports.
+    let unread_count = conv
+        .unread_count
+        .map_or(0, |c| usize::try_from(c).unwrap_or(usize::MAX));
+    mark_unread(&mut synced_messages, unread_count);
+
     synced_messages
 }
 
+/// Get the ID of a change in a notification, which shares the ID of the
+/// notification when it's the only or first change in it.
+fn notification_change_id(id: &str, index: usize) -> String {
+    if index == 0 || id.is_empty() {
+        id.to_string()
+    } else {
+        format!("{id}-{index}")
+    }
+}
+
+/// Mark the newest `unread_count` incoming messages as unread, keeping the rest read.
+fn mark_unread(messages: &mut [SyncedMessage], unread_count: usize) {
+    let mut incoming = messages
+        .iter_mut()
+        .filter(|m| !m.outgoing && m.group_event.is_none())
+        .collect::<Vec<_>>();
+    incoming.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+
+    for message in incoming.into_iter().take(unread_count) {
+        message.unread = true;
+    }
+}
+
 #[derive(Debug)]
 pub enum ClientCommand {
     /// Start the client connection.
@@ -376,6 +819,10 @@ pub enum ClientCommand {
         /// History sync protobuf.
         history_sync: Box<waproto::whatsapp::HistorySync>,
     },
+    /// A `HistorySync` event has been processed.
+    HistorySyncCompleted,
+    /// Messages received while offline have been delivered.
+    OfflineSyncCompleted,
 }
 
 impl Client {
@@ -383,6 +830,50 @@ impl Client {
     fn update_state(&mut self, state: ClientState) {
         self.state = state;
     }
+
+    /// Tell that chats started syncing, the first time they do since connecting.
+    fn start_syncing(&mut self, sender: &AsyncComponentSender<Self>) {
+        if self.state == ClientState::Connected {
+            self.update_state(ClientState::Syncing);
+            let _ = sender.output(ClientOutput::Syncing);
+        }
+    }
+
+    /// Go back to connected once a sync is over.
+    fn finish_syncing(&mut self) {
+        if self.state == ClientState::Syncing {
+            self.update_state(ClientState::Connected);
+        }
+    }
+
+    /// Send read receipts for messages from a sender in a chat.
+    async fn mark_read(
+        &self,
+        chat_jid: &str,
+        sender_jid: Option<&str>,
+        message_ids: Vec<String>,
+    ) -> Result<(), ReceiptError> {
+        if message_ids.is_empty() {
+            return Ok(());
+        }
+
+        let chat_jid = chat_jid
+            .parse::<Jid>()
+            .map_err(|e| ReceiptError::Failed(format!("Failed to parse JID {chat_jid}: {e}")))?;
+        let sender_jid = sender_jid
+            .map(|jid| {
+                jid.parse::<Jid>()
+                    .map_err(|e| ReceiptError::Failed(format!("Failed to parse JID {jid}: {e}")))
+            })
+            .transpose()?;
+
+        let handle = self.handle.lock().await;
+        let client = handle.as_ref().ok_or(ReceiptError::NotConnected)?;
+        client
+            .mark_as_read(&chat_jid, sender_jid.as_ref(), message_ids)
+            .await
+            .map_err(|e| ReceiptError::Failed(e.to_string()))
+    }
 }
 
 #[relm4::component(async, pub)]
@@ -423,6 +914,7 @@ impl AsyncComponent for Client {
             handle: Arc::new(Mutex::new(None)),
             os_type,
             avatar_cache: Arc::new(Mutex::new(avatar_cache)),
+            gate: IngestionGate::default(),
         };
 
         let widgets = view_output!();
@@ -469,45 +961,116 @@ impl AsyncComponent for Client {
                         })
                         .await
                     {
-                        let _ = sender.output(ClientOutput::Error {
-                            message: i18n_f!("Failed to pair with phone number: {0}", e),
-                        });
+                        tracing::error!("Failed to pair with phone number: {e}");
+                        let _ = sender.output(ClientOutput::Error(ClientError::Pairing(
+                            PairFailure::from_error(&e),
+                        )));
                     }
                 }
             }
 
             ClientInput::MarkRead {
+                id,
                 chat_jid,
                 sender_jid,
                 message_ids,
             } => {
-                if !message_ids.is_empty() {
-                    let handle = self.handle.lock().await;
-                    if let Some(client) = handle.as_ref() {
-                        let Ok(jid) = chat_jid.parse::<Jid>() else {
-                            tracing::error!("Failed to parse JID: {chat_jid}");
-                            return;
-                        };
-
-                        let s_jid = if let Some(sender_jid) = sender_jid {
-                            let Ok(jid) = sender_jid.parse::<Jid>() else {
-                                tracing::error!("Failed to parse JID: {sender_jid}");
-                                return;
-                            };
+                let result = self
+                    .mark_read(&chat_jid, sender_jid.as_deref(), message_ids)
+                    .await
+                    .inspect_err(|e| tracing::error!("Failed to mark messages as read: {e}"));
+                let _ = sender.output(ClientOutput::MarkedRead { id, result });
+            }
+            ClientInput::SendMessage {
+                mut message,
+                fetch_preview,
+            } => {
+                // Fetching the preview can take a while, so don't hold the lock.
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let Some(client) = client else {
+                    return;
+                };
+                let Ok(jid) = message.chat_jid.parse::<Jid>() else {
+                    tracing::error!("Failed to parse JID: {}", message.chat_jid);
+                    return;
+                };
 
-                            Some(jid)
-                        } else {
-                            None
-                        };
+                // Other devices show the preview the sender attaches.
+                if fetch_preview
+                    && message.preview.is_none()
+                    && let Some(url) = extract_links(&message.content).into_iter().next()
+                {
+                    message.preview = fetch_link_preview(url).await;
+                }
 
-                        if let Err(e) = client.mark_as_read(&jid, s_jid.as_ref(), message_ids).await
-                        {
-                            tracing::error!("Failed to mark messages as read: {e}");
+                let content = match message.quote {
+                    Some(ref quote) => message.to_reply_message(quote),
+                    None => (*message).clone().into(),
+                };
+                match Box::pin(client.send_message(jid, content)).await {
+                    Ok(msg_id) => {
+                        // Update the message server id in-place.
+                        message.server_id = msg_id;
+
+                        // Update the message in the database.
+                        if let Err(e) = message.save().await {
+                            tracing::error!("Failed to update message: {}", e);
                         }
+
+                        let _ = sender.output(ClientOutput::MessageSent {
+                            chat_jid: message.chat_jid,
+                            msg_id: message.local_id,
+                            preview: message.preview,
+                        });
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to send message: {e}");
+
+                        let _ = sender.output(ClientOutput::MessageFailed {
+                            chat_jid: message.chat_jid,
+                            msg_id: message.local_id,
+                        });
+                    }
+                }
+            }
+            ClientInput::VotePoll {
+                poll,
+                voter_jid,
+                options,
+            } => {
+                let Some(vote) = poll.to_poll_vote_message(&voter_jid, &options) else {
+                    tracing::error!("Failed to encrypt vote for poll {}", poll.server_id);
+                    return;
+                };
+
+                let handle = self.handle.lock().await;
+                if let Some(client) = handle.as_ref() {
+                    let Ok(jid) = poll.chat_jid.parse::<Jid>() else {
+                        tracing::error!("Failed to parse JID: {}", poll.chat_jid);
+                        return;
+                    };
+
+                    if let Err(e) = Box::pin(client.send_message(jid, vote)).await {
+                        tracing::error!("Failed to vote in poll {}: {e}", poll.server_id);
+                    }
+                }
+            }
+            ClientInput::PinMessage { message, pinned } => {
+                let handle = self.handle.lock().await;
+                if let Some(client) = handle.as_ref() {
+                    let Ok(jid) = message.chat_jid.parse::<Jid>() else {
+                        tracing::error!("Failed to parse JID: {}", message.chat_jid);
+                        return;
+                    };
+
+                    if let Err(e) =
+                        Box::pin(client.send_message(jid, message.to_pin_message(pinned))).await
+                    {
+                        tracing::error!("Failed to pin message {}: {e}", message.server_id);
                     }
                 }
             }
-            ClientInput::SendMessage { mut message } => {
+            ClientInput::React { message, emoji } => {
                 let handle = self.handle.lock().await;
                 if let Some(client) = handle.as_ref() {
                     let Ok(jid) = message.chat_jid.parse::<Jid>() else {
@@ -515,34 +1078,328 @@ impl AsyncComponent for Client {
                         return;
                     };
 
-                    match Box::pin(client.send_message(jid, (*message).clone().into())).await {
-                        Ok(msg_id) => {
-                            // Update the message server id in-place.
-                            message.server_id = msg_id;
+                    if let Err(e) =
+                        Box::pin(client.send_message(jid, message.to_reaction_message(&emoji)))
+                            .await
+                    {
+                        tracing::error!("Failed to react to message {}: {e}", message.server_id);
+                    }
+                }
+            }
+            ClientInput::RequestHistory { oldest } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let Some(client) = client else {
+                    return;
+                };
+
+                relm4::spawn(async move {
+                    // The request goes to our own phone, which answers with a
+                    // history sync of the chat.
+                    let Some(own_jid) = client.get_pn().await else {
+                        tracing::error!("Can't request history before being paired");
+                        return;
+                    };
+                    let request = oldest.to_history_request(HISTORY_REQUEST_COUNT);
+                    if let Err(e) = Box::pin(client.send_message(own_jid, request)).await {
+                        tracing::error!("Failed to request history of {}: {e}", oldest.chat_jid);
+                    }
+                });
+            }
+            ClientInput::FetchAvatar { jid } => {
+                sender.oneshot_command(async move { ClientCommand::FetchAvatar { jid } });
+            }
+            ClientInput::SubscribePresence { jid } => {
+                // The server refusing the contact's avatar suggests it'll
+                // refuse their presence as well.
+                let avatar_cache = Arc::clone(&self.avatar_cache);
+                if avatar_cache
+                    .lock()
+                    .await
+                    .as_ref()
+                    .is_some_and(|cache| cache.is_refused(&jid))
+                {
+                    tracing::debug!("Presence of {jid} refused recently, not subscribing");
+                    return;
+                }
+
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let (Some(client), Ok(contact)) = (client, jid.parse::<Jid>()) else {
+                    return;
+                };
 
-                            // Update the message in the database.
-                            if let Err(e) = message.save().await {
-                                tracing::error!("Failed to update message: {}", e);
+                relm4::spawn(async move {
+                    match client.presence().subscribe(&contact).await {
+                        Ok(()) => {}
+                        Err(e) if is_not_authorized(&e) => {
+                            tracing::info!("Presence refused for {jid}, backing off");
+                            if let Some(cache) = avatar_cache.lock().await.as_ref()
+                                && let Err(e) = cache.mark_refused(&jid)
+                            {
+                                tracing::error!("Failed to save presence refusal for {jid}: {e}");
                             }
+                        }
+                        Err(e) => tracing::error!("Failed to subscribe to presence of {jid}: {e}"),
+                    }
+                });
+            }
+            ClientInput::FetchOwnProfile => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let Some(client) = client else {
+                    return;
+                };
+
+                relm4::spawn(async move {
+                    let phone_jid = client.get_pn().await;
+                    let about = match &phone_jid {
+                        Some(jid) => client
+                            .contacts()
+                            .get_user_info(std::slice::from_ref(jid))
+                            .await
+                            .inspect_err(|e| tracing::error!("Failed to get own about text: {e}"))
+                            .ok()
+                            .and_then(|mut info| info.remove(jid))
+                            .and_then(|info| info.status)
+                            .filter(|about| !about.is_empty()),
+                        None => None,
+                    };
+
+                    let _ = sender.output(ClientOutput::OwnProfile {
+                        phone_jid: phone_jid.map(|jid| jid.to_string()),
+                        about,
+                    });
+                });
+            }
+            ClientInput::FetchParticipants { jid } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let (Some(client), Ok(group)) = (client, jid.parse::<Jid>()) else {
+                    return;
+                };
+
+                relm4::spawn(async move {
+                    match client.groups().get_metadata(&group).await {
+                        Ok(metadata) => {
+                            let participants = metadata
+                                .participants
+                                .iter()
+                                .map(|p| p.jid.to_string())
+                                .collect();
+                            let _ = sender
+                                .output(ClientOutput::ParticipantsFetched { jid, participants });
+                        }
+                        Err(e) => tracing::error!("Failed to fetch participants of {jid}: {e}"),
+                    }
+                });
+            }
+            ClientInput::FetchJoinRequests { jid } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let (Some(client), Ok(group)) = (client, jid.parse::<Jid>()) else {
+                    return;
+                };
 
-                            let _ = sender.output(ClientOutput::MessageSent {
-                                chat_jid: message.chat_jid,
-                                msg_id: message.local_id,
+                relm4::spawn(async move {
+                    match client.groups().get_membership_requests(&group).await {
+                        Ok(requests) => {
+                            let requests = requests
+                                .into_iter()
+                                .map(|request| JoinRequest {
+                                    jid: request.jid.to_string(),
+                                    requested_at: request
+                                        .request_time
+                                        .and_then(|t| DateTime::from_timestamp(t, 0))
+                                        .unwrap_or_else(Utc::now),
+                                })
+                                .collect();
+                            let _ = sender.output(ClientOutput::JoinRequestsFetched {
+                                chat_jid: jid,
+                                requests,
+                            });
+                        }
+                        Err(e) => tracing::error!("Failed to fetch join requests of {jid}: {e}"),
+                    }
+                });
+            }
+            ClientInput::ResolveJoinRequests {
+                jid,
+                participants,
+                approve,
+            } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let (Some(client), Ok(group)) = (client, jid.parse::<Jid>()) else {
+                    let _ = sender.output(ClientOutput::JoinRequestsFailed { chat_jid: jid });
+                    return;
+                };
+                let requesters = participants
+                    .iter()
+                    .filter_map(|p| p.parse::<Jid>().ok())
+                    .collect::<Vec<_>>();
+
+                relm4::spawn(async move {
+                    let groups = client.groups();
+                    let result = if approve {
+                        groups
+                            .approve_membership_requests(&group, &requesters)
+                            .await
+                    } else {
+                        groups.reject_membership_requests(&group, &requesters).await
+                    };
+
+                    match result {
+                        Ok(_) => {
+                            let _ = sender.output(ClientOutput::JoinRequestsResolved {
+                                chat_jid: jid,
+                                participants,
+                                approved: approve,
                             });
                         }
                         Err(e) => {
-                            tracing::error!("Failed to send message: {e}");
+                            tracing::error!("Failed to resolve join requests of {jid}: {e}");
+                            let _ =
+                                sender.output(ClientOutput::JoinRequestsFailed { chat_jid: jid });
+                        }
+                    }
+                });
+            }
+            ClientInput::DownloadMedia {
+                chat_jid,
+                message_id,
+                media,
+            } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let Some(client) = client else {
+                    let _ = sender.output(ClientOutput::MediaDownloadFailed {
+                        chat_jid,
+                        message_id,
+                    });
+                    return;
+                };
+
+                relm4::spawn(async move {
+                    let result = client
+                        .download_from_params(
+                            &media.direct_path,
+                            &media.media_key,
+                            &media.file_sha256,
+                            &media.file_enc_sha256,
+                            media.file_length,
+                            media.download_type,
+                        )
+                        .await;
 
-                            let _ = sender.output(ClientOutput::MessageFailed {
-                                chat_jid: message.chat_jid,
-                                msg_id: message.local_id,
+                    match result {
+                        Ok(data) => {
+                            let _ = sender.output(ClientOutput::MediaDownloaded {
+                                chat_jid,
+                                message_id,
+                                data,
+                            });
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to download media of {message_id}: {e}");
+                            let _ = sender.output(ClientOutput::MediaDownloadFailed {
+                                chat_jid,
+                                message_id,
                             });
                         }
                     }
-                }
+                });
             }
-            ClientInput::FetchAvatar { jid } => {
-                sender.oneshot_command(async move { ClientCommand::FetchAvatar { jid } });
+            ClientInput::CheckNumber { phone_number } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                relm4::spawn(async move {
+                    let result = match client {
+                        Some(client) => client
+                            .contacts()
+                            .is_on_whatsapp(&[phone_number.as_str()])
+                            .await
+                            .map(|results| {
+                                results
+                                    .into_iter()
+                                    .find(|r| r.is_registered)
+                                    .map(|r| r.jid.to_string())
+                            })
+                            .map_err(|e| {
+                                tracing::error!("Failed to check number {phone_number}: {e}");
+                                i18n!("Couldn't check the number, try again later")
+                            }),
+                        None => Err(i18n!("Not connected, try again later")),
+                    };
+
+                    let _ = sender.output(ClientOutput::NumberChecked {
+                        phone_number,
+                        result,
+                    });
+                });
+            }
+
+            ClientInput::StartCall { jid, is_video } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let (Some(client), Ok(peer)) = (client, jid.parse::<Jid>()) else {
+                    let _ = sender.output(ClientOutput::CallFailed { jid });
+                    return;
+                };
+
+                relm4::spawn(async move {
+                    let options = CallOptions { video: is_video };
+                    match client.calls().start_call(&peer, options).await {
+                        Ok(call_id) => {
+                            let _ = sender.output(ClientOutput::CallStarted {
+                                call_id,
+                                jid,
+                                is_video,
+                            });
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to call {jid}: {e}");
+                            let _ = sender.output(ClientOutput::CallFailed { jid });
+                        }
+                    }
+                });
+            }
+            ClientInput::AcceptCall { call_id } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let Some(client) = client else {
+                    let _ = sender.output(ClientOutput::CallEnded { call_id });
+                    return;
+                };
+
+                relm4::spawn(async move {
+                    match client.calls().accept_call(&call_id).await {
+                        Ok(()) => {
+                            let _ = sender.output(ClientOutput::CallAccepted { call_id });
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to accept call {call_id}: {e}");
+                            let _ = sender.output(ClientOutput::CallEnded { call_id });
+                        }
+                    }
+                });
+            }
+            ClientInput::DeclineCall { call_id } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let Some(client) = client else {
+                    return;
+                };
+
+                relm4::spawn(async move {
+                    if let Err(e) = client.calls().reject_call(&call_id).await {
+                        tracing::error!("Failed to decline call {call_id}: {e}");
+                    }
+                });
+            }
+            ClientInput::EndCall { call_id } => {
+                let client = self.handle.lock().await.as_ref().map(Arc::clone);
+                let Some(client) = client else {
+                    let _ = sender.output(ClientOutput::CallEnded { call_id });
+                    return;
+                };
+
+                relm4::spawn(async move {
+                    if let Err(e) = client.calls().end_call(&call_id).await {
+                        tracing::error!("Failed to hang up call {call_id}: {e}");
+                    }
+                    // The call is over for us even if the other side wasn't told.
+                    let _ = sender.output(ClientOutput::CallEnded { call_id });
+                });
             }
 
             _ => {}
@@ -568,9 +1425,8 @@ impl AsyncComponent for Client {
                         Ok(store) => Arc::new(store),
                         Err(e) => {
                             tracing::error!("Failed to initialize SQLite storage: {e}");
-                            let _ = sender.output(ClientOutput::Error {
-                                message: i18n_f!("Database error: {0}", e),
-                            });
+                            let _ = sender
+                                .output(ClientOutput::Error(ClientError::Database(e.to_string())));
 
                             return;
                         }
@@ -586,6 +1442,7 @@ impl AsyncComponent for Client {
 
                     // Create bot with event handler.
                     let sender_clone = sender.clone();
+                    let gate = self.gate.clone();
                     let mut bot = Bot::builder()
                         .with_backend(backend)
                         .with_runtime(TokioRuntime)
@@ -603,6 +1460,7 @@ impl AsyncComponent for Client {
                         .with_transport_factory(TokioWebSocketTransportFactory::new())
                         .on_event(move |event, _client| {
                             let sender = sender_clone.clone();
+                            let gate = gate.clone();
 
                             async move {
                                 match event {
@@ -641,6 +1499,14 @@ impl AsyncComponent for Client {
                                         sender
                                             .oneshot_command(async { ClientCommand::PairSuccess });
                                     }
+                                    Event::PairError(error) => {
+                                        tracing::error!("Pairing failed: {}", error.error);
+                                        let _ = sender.output(ClientOutput::Error(
+                                            ClientError::Pairing(PairFailure::from_message(
+                                                &error.error,
+                                            )),
+                                        ));
+                                    }
 
                                     Event::Receipt(receipt) => {
                                         let chat_jid = receipt.source.chat.to_string();
@@ -668,6 +1534,20 @@ impl AsyncComponent for Client {
                                         let _ = sender.output(ClientOutput::MessageReceived {
                                             info: Box::new(info),
                                             message,
+                                            ticket: gate.ingest(),
+                                        });
+                                    }
+
+                                    Event::UndecryptableMessage(undecryptable) => {
+                                        let info = undecryptable.info;
+                                        tracing::warn!(
+                                            "Failed to decrypt message {} from {}",
+                                            info.id,
+                                            info.source.sender
+                                        );
+                                        let _ = sender.output(ClientOutput::UndecryptableMessage {
+                                            chat_jid: info.source.chat.to_string(),
+                                            msg_id: info.id,
                                         });
                                     }
 
@@ -681,6 +1561,106 @@ impl AsyncComponent for Client {
                                         });
                                     }
 
+                                    // Changes to groups made while connected, such as
+                                    // the user leaving or being removed.
+                                    Event::Notification(node)
+                                        if node.attrs().optional_string("type")
+                                            == Some("w:gp2") =>
+                                    {
+                                        let mut attrs = node.attrs();
+                                        let chat_jid = attrs
+                                            .optional_string("from")
+                                            .unwrap_or_default()
+                                            .to_string();
+                                        let actor = attrs
+                                            .optional_string("participant")
+                                            .unwrap_or_default()
+                                            .to_string();
+                                        let id = attrs
+                                            .optional_string("id")
+                                            .unwrap_or_default()
+                                            .to_string();
+                                        let timestamp = attrs
+                                            .optional_string("t")
+                                            .and_then(|t| t.parse().ok())
+                                            .and_then(|t| DateTime::from_timestamp(t, 0))
+                                            .unwrap_or_else(Utc::now);
+
+                                        for (index, change) in
+                                            node.children().unwrap_or_default().iter().enumerate()
+                                        {
+                                            // Participants are listed in the change, or in
+                                            // the group it creates.
+                                            let participants = change
+                                                .children()
+                                                .unwrap_or_default()
+                                                .iter()
+                                                .flat_map(|child| {
+                                                    if child.tag == "group" {
+                                                        child.children().unwrap_or_default()
+                                                    } else {
+                                                        std::slice::from_ref(child)
+                                                    }
+                                                })
+                                                .filter(|child| {
+                                                    matches!(
+                                                        child.tag.as_str(),
+                                                        "participant" | "requested_user"
+                                                    )
+                                                })
+                                                .filter_map(|child| {
+                                                    child
+                                                        .attrs()
+                                                        .optional_string("jid")
+                                                        .map(ToString::to_string)
+                                                })
+                                                .collect();
+                                            let subject = change
+                                                .attrs()
+                                                .optional_string("subject")
+                                                .map(ToString::to_string);
+
+                                            // Requests to join only reach admins, and aren't
+                                            // kept in the history until resolved.
+                                            match change.tag.as_str() {
+                                                "created_membership_requests" => {
+                                                    let _ = sender.output(
+                                                        ClientOutput::JoinRequested {
+                                                            chat_jid: chat_jid.clone(),
+                                                            participants,
+                                                            timestamp,
+                                                        },
+                                                    );
+                                                    continue;
+                                                }
+                                                "revoked_membership_requests" => {
+                                                    let _ = sender.output(
+                                                        ClientOutput::JoinRequestsWithdrawn {
+                                                            chat_jid: chat_jid.clone(),
+                                                            participants,
+                                                        },
+                                                    );
+                                                    continue;
+                                                }
+                                                _ => {}
+                                            }
+
+                                            if let Some(event) = GroupEvent::from_notification(
+                                                &change.tag,
+                                                &actor,
+                                                participants,
+                                                subject,
+                                            ) {
+                                                let _ = sender.output(ClientOutput::GroupChanged {
+                                                    chat_jid: chat_jid.clone(),
+                                                    id: notification_change_id(&id, index),
+                                                    timestamp,
+                                                    event,
+                                                });
+                                            }
+                                        }
+                                    }
+
                                     Event::HistorySync(history_sync) => {
                                         sender.oneshot_command(async move {
                                             ClientCommand::ProcessHistorySync {
@@ -692,7 +1672,9 @@ impl AsyncComponent for Client {
                                         tracing::debug!("Offline sync preview received");
                                     }
                                     Event::OfflineSyncCompleted(_) => {
-                                        let _ = sender.output(ClientOutput::OfflineSyncCompleted);
+                                        sender.oneshot_command(async {
+                                            ClientCommand::OfflineSyncCompleted
+                                        });
                                     }
 
                                     Event::DeviceListUpdate(_) => {
@@ -705,6 +1687,7 @@ impl AsyncComponent for Client {
                                             jid: update.jid.to_string(),
                                             pinned: update.action.pinned,
                                             muted: None,
+                                            mute_end: None,
                                             archived: None,
                                         });
                                     }
@@ -713,6 +1696,7 @@ impl AsyncComponent for Client {
                                             jid: update.jid.to_string(),
                                             pinned: None,
                                             muted: update.action.muted,
+                                            mute_end: update.action.mute_end_timestamp,
                                             archived: None,
                                         });
                                     }
@@ -721,6 +1705,7 @@ impl AsyncComponent for Client {
                                             jid: update.jid.to_string(),
                                             pinned: None,
                                             muted: None,
+                                            mute_end: None,
                                             archived: update.action.archived,
                                         });
                                     }
@@ -747,6 +1732,30 @@ impl AsyncComponent for Client {
                                         });
                                     }
 
+                                    Event::CallOffer(offer) => {
+                                        let _ = sender.output(ClientOutput::CallOffer {
+                                            call_id: offer.call_id,
+                                            from_jid: offer.from.to_string(),
+                                            is_video: offer.is_video,
+                                        });
+                                    }
+                                    // Answers to calls we placed, and ends of any call.
+                                    Event::CallAccept(accept) => {
+                                        let _ = sender.output(ClientOutput::CallAccepted {
+                                            call_id: accept.call_id,
+                                        });
+                                    }
+                                    Event::CallReject(reject) => {
+                                        let _ = sender.output(ClientOutput::CallEnded {
+                                            call_id: reject.call_id,
+                                        });
+                                    }
+                                    Event::CallTerminate(terminate) => {
+                                        let _ = sender.output(ClientOutput::CallEnded {
+                                            call_id: terminate.call_id,
+                                        });
+                                    }
+
                                     Event::SelfPushNameUpdated(update) => {
                                         let _ = sender.output(ClientOutput::SelfPushNameUpdated {
                                             push_name: update.new_name,
@@ -778,9 +1787,9 @@ impl AsyncComponent for Client {
                         Err(e) => {
                             tracing::error!("Client failed to start: {e}");
 
-                            let message = i18n_f!("Connection failed: {0}", e);
-                            self.update_state(ClientState::Error(message.clone()));
-                            let _ = sender.output(ClientOutput::Error { message });
+                            self.update_state(ClientState::Error(e.to_string()));
+                            let _ = sender
+                                .output(ClientOutput::Error(ClientError::Network(e.to_string())));
                         }
                     }
                 }
@@ -910,8 +1919,13 @@ impl AsyncComponent for Client {
                 let avatar_cache = Arc::clone(&self.avatar_cache);
                 let client_handle = Arc::clone(&self.handle);
                 let sender_clone = sender.clone();
+                let low_on_space = is_low_on_space(&DATA_DIR);
+                let gate = self.gate.clone();
 
                 relm4::spawn(async move {
+                    // Let received messages be handled first.
+                    let _permit = gate.background().await;
+
                     // Check if already cached (release lock immediately after).
                     let cached_path = {
                         let cache_guard = avatar_cache.lock().await;
@@ -930,6 +1944,19 @@ impl AsyncComponent for Client {
                         let _ = sender_clone.output(ClientOutput::AvatarUpdate { jid, path });
                         return;
                     }
+                    if avatar_cache
+                        .lock()
+                        .await
+                        .as_ref()
+                        .is_some_and(|cache| cache.is_refused(&jid))
+                    {
+                        tracing::debug!("Avatar refused recently for {jid}, not fetching");
+                        return;
+                    }
+                    if low_on_space {
+                        tracing::debug!("Low on disk space, not fetching the avatar of {jid}");
+                        return;
+                    }
 
                     // Get the client handle (clone Arc to release lock).
                     let client = {
@@ -961,7 +1988,19 @@ impl AsyncComponent for Client {
                             return;
                         }
                         Err(e) => {
-                            tracing::error!("Failed to get profile picture for {jid}: {e}");
+                            // Hidden from us by privacy settings, or we're blocked:
+                            // asking again soon won't change the answer.
+                            if is_not_authorized(&e) {
+                                tracing::info!("Profile picture refused for {jid}, backing off");
+                                let cache_guard = avatar_cache.lock().await;
+                                if let Some(cache) = cache_guard.as_ref()
+                                    && let Err(e) = cache.mark_refused(&jid)
+                                {
+                                    tracing::error!("Failed to save avatar refusal for {jid}: {e}");
+                                }
+                            } else {
+                                tracing::error!("Failed to get profile picture for {jid}: {e}");
+                            }
                             return;
                         }
                     };
@@ -1008,33 +2047,41 @@ impl AsyncComponent for Client {
                 });
             }
             ClientCommand::ProcessJoinedGroup { lazy_conv } => {
+                self.start_syncing(&sender);
+
                 // Offload CPU-intensive protobuf parsing to blocking thread.
                 let sender_clone = sender.clone();
+                let gate = self.gate.clone();
                 relm4::spawn_blocking(move || {
                     // Parse the lazy conversation (this does protobuf decoding - CPU intensive).
                     // Use get_with_messages() because get() strips messages to save memory.
                     if let Some(conv) = lazy_conv.get_with_messages() {
                         let chat_jid = conv.new_jid.clone().unwrap_or_else(|| conv.id.clone());
-                        let is_group = chat_jid.ends_with("@g.us");
+                        let kind = ChatKind::from_jid(&chat_jid);
+                        let is_group = kind == ChatKind::Group;
+                        let alt_jid = if kind == ChatKind::Lid {
+                            conv.pn_jid.clone()
+                        } else {
+                            conv.lid_jid.clone()
+                        };
 
-                        // Extract participants for groups.
-                        let mut participants = Vec::new();
-                        if is_group {
-                            for p in &conv.participant {
-                                participants.push((p.user_jid.clone(), None::<String>));
-                            }
-                        }
+                        let (participants, participant_count) =
+                            synced_participants(&conv, is_group);
 
                         // Emit chat synced event.
                         let _ = sender_clone.output(ClientOutput::ChatSynced {
                             jid: chat_jid.clone(),
                             name: conv.name.clone(),
+                            alt_jid,
                             pinned: conv.pinned.is_some_and(|p| p > 0),
                             archived: conv.archived.unwrap_or(false),
                             unread_count: conv.unread_count,
                             participants,
+                            admins: synced_admins(&conv),
                             mute_end_time: conv.mute_end_time,
                             last_message_time: conv.last_msg_timestamp,
+                            participant_count,
+                            modified_at: conv.conversation_timestamp,
                         });
 
                         // Process messages from the conversation.
@@ -1045,50 +2092,502 @@ impl AsyncComponent for Client {
                             let _ = sender_clone.output(ClientOutput::MessagesSynced {
                                 chat_jid,
                                 messages: synced_messages,
+                                ticket: gate.ingest(),
                             });
                         }
                     }
                 });
             }
             ClientCommand::ProcessHistorySync { history_sync } => {
+                self.start_syncing(&sender);
+
+                // Storing thousands of messages could fill the disk up and corrupt
+                // the databases, so only keep the chats until space is freed.
+                let metadata_only = is_low_on_space(&DATA_DIR);
+                if metadata_only {
+                    tracing::warn!("Low on disk space, syncing chats without their messages");
+                }
+
                 let sender_clone = sender.clone();
+                let gate = self.gate.clone();
                 relm4::spawn_blocking(move || {
                     for conv in &history_sync.conversations {
                         let chat_jid = conv.new_jid.clone().unwrap_or_else(|| conv.id.clone());
-                        let is_group = chat_jid.ends_with("@g.us");
+                        let kind = ChatKind::from_jid(&chat_jid);
+                        let is_group = kind == ChatKind::Group;
+                        let alt_jid = if kind == ChatKind::Lid {
+                            conv.pn_jid.clone()
+                        } else {
+                            conv.lid_jid.clone()
+                        };
 
-                        // Extract participants for groups.
-                        let mut participants = Vec::new();
-                        if is_group {
-                            for p in &conv.participant {
-                                participants.push((p.user_jid.clone(), None::<String>));
-                            }
-                        }
+                        let (participants, participant_count) = synced_participants(conv, is_group);
 
                         let _ = sender_clone.output(ClientOutput::ChatSynced {
                             jid: chat_jid.clone(),
                             name: conv.name.clone(),
+                            alt_jid,
                             pinned: conv.pinned.is_some_and(|p| p > 0),
                             archived: conv.archived.unwrap_or(false),
                             unread_count: conv.unread_count,
                             participants,
+                            admins: synced_admins(conv),
                             mute_end_time: conv.mute_end_time,
                             last_message_time: conv.last_msg_timestamp,
+                            participant_count,
+                            modified_at: conv.conversation_timestamp,
                         });
 
+                        if metadata_only {
+                            continue;
+                        }
+
                         let synced_messages = extract_synced_messages(conv, &chat_jid);
 
                         if !synced_messages.is_empty() {
                             let _ = sender_clone.output(ClientOutput::MessagesSynced {
                                 chat_jid,
                                 messages: synced_messages,
+                                ticket: gate.ingest(),
                             });
                         }
                     }
 
-                    let _ = sender_clone.output(ClientOutput::HistorySyncCompleted);
+                    sender_clone.oneshot_command(async { ClientCommand::HistorySyncCompleted });
                 });
             }
+            ClientCommand::HistorySyncCompleted => {
+                self.finish_syncing();
+                let _ = sender.output(ClientOutput::HistorySyncCompleted);
+            }
+            ClientCommand::OfflineSyncCompleted => {
+                self.finish_syncing();
+                let _ = sender.output(ClientOutput::OfflineSyncCompleted);
+            }
+        }
+    }
+}
+
+/// Checks if the server refused a query as not authorized, as it does for
+/// profile pictures hidden from us or when we're blocked.
+fn is_not_authorized(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<IqError>(),
+        Some(IqError::ServerError {
+            code: NOT_AUTHORIZED,
+            ..
+        })
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use waproto::whatsapp as wa;
+
+    use super::*;
+    use crate::{
+        state::TextContent,
+        test_utils::{block_on, chat, database, message},
+    };
+
+    #[test]
+    fn only_html_pages_have_previews() {
+        assert!(is_html_type("text/html"));
+        assert!(is_html_type("text/html; charset=utf-8"));
+        assert!(is_html_type("Application/XHTML+XML"));
+        assert!(!is_html_type("application/pdf"));
+        assert!(!is_html_type("video/mp4"));
+        assert!(!is_html_type("text/plain"));
+    }
+
+    #[test]
+    fn error_messages_carry_their_reason() {
+        assert!(
+            ClientError::Network("connection reset".into())
+                .message()
+                .contains("connection reset")
+        );
+        assert!(
+            ClientError::Database("disk I/O error".into())
+                .message()
+                .contains("disk I/O error")
+        );
+        assert_eq!(
+            ClientError::Pairing(PairFailure::RateLimited).message(),
+            PairFailure::RateLimited.title()
+        );
+    }
+
+    #[test]
+    fn only_unauthorized_queries_are_refusals() {
+        let refused = anyhow::Error::new(IqError::ServerError {
+            code: NOT_AUTHORIZED,
+            text: "not-authorized".into(),
+        });
+        let missing = anyhow::Error::new(IqError::ServerError {
+            code: 404,
+            text: "item-not-found".into(),
+        });
+
+        assert!(is_not_authorized(&refused));
+        assert!(!is_not_authorized(&missing));
+        assert!(!is_not_authorized(&anyhow::anyhow!("401 in a message")));
+    }
+
+    #[test]
+    fn pairing_failures_are_told_apart_by_status_code() {
+        let server_error = |code, text: &str| {
+            anyhow::Error::new(IqError::ServerError {
+                code,
+                text: text.into(),
+            })
+        };
+
+        assert!(matches!(
+            PairFailure::from_error(&server_error(RATE_OVERLIMIT, "rate-overlimit")),
+            PairFailure::RateLimited
+        ));
+        assert!(matches!(
+            PairFailure::from_error(&server_error(BAD_REQUEST, "bad-request")),
+            PairFailure::InvalidNumber
+        ));
+        assert!(matches!(
+            PairFailure::from_error(&server_error(500, "internal-server-error")),
+            PairFailure::Other(_)
+        ));
+
+        // Numbers in the message, like the phone number, aren't codes.
+        assert!(matches!(
+            PairFailure::from_error(&anyhow::anyhow!("Failed to pair +1 400 555 0429")),
+            PairFailure::Other(_)
+        ));
+        assert!(matches!(
+            PairFailure::from_message("companion limit reached"),
+            PairFailure::TooManyDevices
+        ));
+        assert!(matches!(
+            PairFailure::from_message("rate-overlimit"),
+            PairFailure::RateLimited
+        ));
+    }
+
+    /// Build a read text message synced from the history.
+    fn synced(id: &str, outgoing: bool, timestamp: u64) -> SyncedMessage {
+        SyncedMessage {
+            id: id.to_string(),
+            unread: false,
+            content: MessageContent {
+                text: Some(TextContent {
+                    text: id.to_string(),
+                    ..TextContent::default()
+                }),
+                ..MessageContent::default()
+            },
+            outgoing,
+            timestamp,
+            sender_jid: String::new(),
+            group_event: None,
+            sender_name: None,
         }
     }
+
+    #[test]
+    fn only_the_newest_incoming_messages_are_unread() {
+        let mut left = synced("left", false, 4);
+        left.group_event = Some(GroupEvent::ParticipantLeft {
+            participant: "alice@s.whatsapp.net".into(),
+        });
+        let mut messages = vec![
+            synced("newest", false, 5),
+            synced("oldest", false, 1),
+            synced("sent", true, 6),
+            left,
+            synced("middle", false, 3),
+        ];
+
+        mark_unread(&mut messages, 2);
+        let unread: Vec<_> = messages
+            .iter()
+            .filter(|m| m.unread)
+            .map(|m| m.id.as_str())
+            .collect();
+        assert_eq!(unread, ["newest", "middle"]);
+
+        let mut messages = vec![synced("one", false, 1), synced("two", false, 2)];
+        mark_unread(&mut messages, usize::MAX);
+        assert!(messages.iter().all(|m| m.unread));
+
+        mark_unread(&mut [], 3);
+    }
+
+    /// Messages carrying each kind of content, as received.
+    fn content_fixture() -> Vec<(&'static str, wa::Message)> {
+        use wa::message::{
+            AudioMessage, ContactMessage, DocumentMessage, ExtendedTextMessage, ImageMessage,
+            LocationMessage, PollCreationMessage, VideoMessage, poll_creation_message,
+        };
+
+        vec![
+            (
+                "reply",
+                wa::Message {
+                    extended_text_message: Some(
+                        ExtendedTextMessage {
+                            text: Some("See https://example.com".to_string()),
+                            matched_text: Some("https://example.com".to_string()),
+                            title: Some("Example".to_string()),
+                            context_info: Some(
+                                wa::ContextInfo {
+                                    stanza_id: Some("quoted".to_string()),
+                                    participant: Some("alice@s.whatsapp.net".to_string()),
+                                    quoted_message: Some(
+                                        wa::Message {
+                                            conversation: Some("Where?".to_string()),
+                                            ..Default::default()
+                                        }
+                                        .into(),
+                                    ),
+                                    ..Default::default()
+                                }
+                                .into(),
+                            ),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    ..Default::default()
+                },
+            ),
+            (
+                "location",
+                wa::Message {
+                    location_message: Some(
+                        LocationMessage {
+                            name: Some("Park".to_string()),
+                            degrees_latitude: Some(-23.5),
+                            degrees_longitude: Some(-46.6),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    ..Default::default()
+                },
+            ),
+            (
+                "contact",
+                wa::Message {
+                    contact_message: Some(
+                        ContactMessage {
+                            display_name: Some("Bob".to_string()),
+                            vcard: Some("BEGIN:VCARD\nFN:Bob\nEND:VCARD".to_string()),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    ..Default::default()
+                },
+            ),
+            (
+                "poll",
+                wa::Message {
+                    poll_creation_message: Some(
+                        PollCreationMessage {
+                            name: Some("Lunch?".to_string()),
+                            options: ["Yes", "No"]
+                                .map(|name| poll_creation_message::Option {
+                                    option_name: Some(name.to_string()),
+                                    ..Default::default()
+                                })
+                                .to_vec(),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    ..Default::default()
+                },
+            ),
+            (
+                "image",
+                wa::Message {
+                    image_message: Some(
+                        ImageMessage {
+                            caption: Some("Sunset".to_string()),
+                            jpeg_thumbnail: Some(b"jpeg".to_vec()),
+                            media_key: Some(b"key".to_vec()),
+                            direct_path: Some("/v/image".to_string()),
+                            file_length: Some(300_000),
+                            file_enc_sha256: Some(b"enc".to_vec()),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    ..Default::default()
+                },
+            ),
+            (
+                "video",
+                wa::Message {
+                    video_message: Some(
+                        VideoMessage {
+                            seconds: Some(12),
+                            jpeg_thumbnail: Some(b"jpeg".to_vec()),
+                            media_key: Some(b"key".to_vec()),
+                            direct_path: Some("/v/video".to_string()),
+                            file_length: Some(5_000_000),
+                            file_enc_sha256: Some(b"enc".to_vec()),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    ..Default::default()
+                },
+            ),
+            (
+                "document",
+                wa::Message {
+                    document_message: Some(
+                        DocumentMessage {
+                            file_name: Some("report.pdf".to_string()),
+                            media_key: Some(b"key".to_vec()),
+                            direct_path: Some("/v/report".to_string()),
+                            file_length: Some(4096),
+                            file_enc_sha256: Some(b"enc".to_vec()),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    ..Default::default()
+                },
+            ),
+            (
+                "voice",
+                wa::Message {
+                    audio_message: Some(
+                        AudioMessage {
+                            seconds: Some(4),
+                            waveform: Some(vec![10, 50, 90]),
+                            media_key: Some(b"key".to_vec()),
+                            direct_path: Some("/v/voice".to_string()),
+                            file_length: Some(2048),
+                            file_enc_sha256: Some(b"enc".to_vec()),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    ..Default::default()
+                },
+            ),
+        ]
+    }
+
+    /// Describe what a stored message carries, to compare how it was saved.
+    fn stored_content(message: &ChatMessage) -> String {
+        format!(
+            "{:?}",
+            (
+                &message.content,
+                message.kind,
+                &message.poll,
+                message.media.as_ref().map(|media| (
+                    media.r#type,
+                    &media.data,
+                    &media.mime_type,
+                    media.durations_secs,
+                    &media.waveform,
+                    media.download_size(),
+                )),
+                &message.quote,
+                &message.preview,
+                &message.contacts,
+                &message.location,
+            )
+        )
+    }
+
+    #[test]
+    fn live_and_synced_messages_are_stored_alike() {
+        block_on(async {
+            let fixture = content_fixture();
+            let conversation = wa::Conversation {
+                id: "chat".to_string(),
+                messages: fixture
+                    .iter()
+                    .zip(1_700_000_000..)
+                    .map(|((id, msg), timestamp)| wa::HistorySyncMsg {
+                        message: Some(
+                            wa::WebMessageInfo {
+                                key: wa::MessageKey {
+                                    id: Some((*id).to_string()),
+                                    participant: Some("bob@s.whatsapp.net".to_string()),
+                                    ..Default::default()
+                                },
+                                message: Some(msg.clone().into()),
+                                message_timestamp: Some(timestamp),
+                                ..Default::default()
+                            }
+                            .into(),
+                        ),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            };
+            let synced = extract_synced_messages(&conversation, "chat");
+            assert_eq!(synced.len(), fixture.len());
+
+            let live_db = database().await;
+            let synced_db = database().await;
+            live_db.save_chat(&chat(&live_db, "chat")).await.unwrap();
+            for ((id, msg), synced) in fixture.iter().zip(synced) {
+                let timestamp =
+                    DateTime::from_timestamp(synced.timestamp.cast_signed(), 0).unwrap();
+
+                let content = MessageContent::from_message(msg);
+                let mut live = message(&live_db, "chat", "bob@s.whatsapp.net", id);
+                live.content = content.display_text().unwrap();
+                live.timestamp = timestamp;
+                content.apply(&mut live);
+                live_db.save_message("chat", &live).await.unwrap();
+
+                let mut from_history = message(&synced_db, "chat", &synced.sender_jid, &synced.id);
+                from_history.content = synced.content.display_text().unwrap();
+                from_history.timestamp = timestamp;
+                synced.content.apply(&mut from_history);
+                assert!(
+                    synced_db
+                        .save_synced_message("chat", &from_history)
+                        .await
+                        .unwrap()
+                );
+            }
+
+            let live = live_db.load_messages("chat", 10).await.unwrap();
+            let synced = synced_db.load_messages("chat", 10).await.unwrap();
+            assert_eq!(live.len(), fixture.len());
+            for (live, synced) in live.iter().zip(&synced) {
+                assert_eq!(live.server_id, synced.server_id);
+                assert_eq!(stored_content(live), stored_content(synced));
+            }
+            assert!(
+                live.iter()
+                    .any(|m| m.quote.is_some() && m.preview.is_some())
+            );
+            assert!(live.iter().any(|m| m.location.is_some()));
+            assert!(live.iter().any(|m| !m.contacts.is_empty()));
+            assert!(live.iter().any(|m| m.poll.is_some()));
+            assert!(
+                live.iter()
+                    .filter_map(|m| m.media.as_ref())
+                    .any(|m| m.waveform.is_some() && m.can_download())
+            );
+        });
+    }
+
+    #[test]
+    fn each_change_in_a_notification_gets_its_own_id() {
+        assert_eq!(notification_change_id("1234", 0), "1234");
+        assert_eq!(notification_change_id("1234", 1), "1234-1");
+        assert_eq!(notification_change_id("1234", 2), "1234-2");
+        assert_eq!(notification_change_id("", 1), "");
+    }
 }
@@ -1,5 +1,9 @@
 mod cache;
 mod client;
+mod priority;
 
 pub use cache::AvatarCache;
-pub use client::{Client, ClientInput, ClientOutput, SyncedMessage};
+pub use client::{
+    Client, ClientError, ClientInput, ClientOutput, PairFailure, ReceiptError, SyncedMessage,
+};
+pub use priority::{IngestionGate, IngestionTicket};
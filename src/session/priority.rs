@@ -0,0 +1,157 @@
+use std::{
+    pin::pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+/// Maximum number of background tasks, like avatar downloads, running at once.
+const MAX_BACKGROUND_TASKS: usize = 4;
+
+/// Gives incoming messages priority over background work, which waits for
+/// a free slot and for no message to be in flight before running.
+#[derive(Clone, Debug)]
+pub struct IngestionGate {
+    /// Woken when the last message in flight is handled.
+    idle: Arc<Notify>,
+    /// Number of messages received but not handled yet.
+    pending: Arc<AtomicUsize>,
+    /// Slots for background tasks.
+    background: Arc<Semaphore>,
+}
+
+impl Default for IngestionGate {
+    fn default() -> Self {
+        Self {
+            pending: Arc::new(AtomicUsize::new(0)),
+            idle: Arc::new(Notify::new()),
+            background: Arc::new(Semaphore::new(MAX_BACKGROUND_TASKS)),
+        }
+    }
+}
+
+impl IngestionGate {
+    /// Count a message as in flight until the returned ticket is dropped.
+    pub fn ingest(&self) -> IngestionTicket {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        IngestionTicket(self.clone())
+    }
+
+    /// Wait for a background slot, once no message is in flight.
+    pub async fn background(&self) -> OwnedSemaphorePermit {
+        let permit = Arc::clone(&self.background)
+            .acquire_owned()
+            .await
+            .expect("Background semaphore closed");
+        self.wait_idle().await;
+
+        permit
+    }
+
+    /// Wait until no message is in flight.
+    async fn wait_idle(&self) {
+        loop {
+            // Listen before checking, not to miss a wake up in between.
+            let mut notified = pin!(self.idle.notified());
+            notified.as_mut().enable();
+
+            if self.pending.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// A received message in flight, counted as handled once dropped.
+#[derive(Debug)]
+pub struct IngestionTicket(IngestionGate);
+
+impl Drop for IngestionTicket {
+    fn drop(&mut self) {
+        if self.0.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Let other tasks run for a while.
+    async fn yield_times(times: usize) {
+        for _ in 0..times {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .build()
+            .expect("Failed to build test runtime")
+    }
+
+    #[test]
+    fn background_work_waits_for_messages_in_flight() {
+        runtime().block_on(async {
+            let gate = IngestionGate::default();
+            let first = gate.ingest();
+            let second = gate.ingest();
+
+            let task = tokio::spawn({
+                let gate = gate.clone();
+                async move { drop(gate.background().await) }
+            });
+
+            yield_times(100).await;
+            assert!(!task.is_finished());
+
+            drop(first);
+            yield_times(100).await;
+            assert!(!task.is_finished());
+
+            drop(second);
+            task.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn background_work_runs_once_a_burst_of_messages_is_handled() {
+        runtime().block_on(async {
+            let gate = IngestionGate::default();
+            let running = Arc::new(AtomicUsize::new(0));
+
+            let tasks = (0..1000)
+                .map(|i| {
+                    let gate = gate.clone();
+                    let running = Arc::clone(&running);
+                    tokio::spawn(async move {
+                        if i % 3 == 0 {
+                            let _permit = gate.background().await;
+                            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            assert!(now <= MAX_BACKGROUND_TASKS);
+                            yield_times(5).await;
+                            running.fetch_sub(1, Ordering::SeqCst);
+                        } else {
+                            let _ticket = gate.ingest();
+                            yield_times(i % 7).await;
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            // Every task finishes, so no wake up was lost along the way.
+            for task in tasks {
+                task.await.unwrap();
+            }
+
+            assert_eq!(gate.pending.load(Ordering::SeqCst), 0);
+            assert_eq!(gate.background.available_permits(), MAX_BACKGROUND_TASKS);
+        });
+    }
+}
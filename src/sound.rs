@@ -0,0 +1,84 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use gtk::prelude::*;
+use relm4::gtk;
+
+/// Shortest time between two plays of the same sound, so quick bursts
+/// don't stack up.
+const MIN_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Volume sounds are played at, kept low as feedback should be subtle.
+const VOLUME: f64 = 0.5;
+
+thread_local! {
+    /// Sounds playing or last played, with when they started.
+    static PLAYING: RefCell<HashMap<Sound, (Instant, gtk::MediaFile)>> =
+        RefCell::new(HashMap::new());
+}
+
+/// A sound bundled in the resources.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sound {
+    /// A message has been received.
+    MessageReceived,
+    /// A message has been sent.
+    MessageSent,
+    /// A message could not be sent.
+    MessageFailed,
+    /// A call is incoming, looped until answered.
+    Ringtone,
+}
+
+impl Sound {
+    /// Get the resource path of the sound.
+    fn resource_path(self) -> &'static str {
+        match self {
+            Self::MessageReceived => "/com/amanoteam/Papo/sounds/message-received.wav",
+            Self::MessageSent => "/com/amanoteam/Papo/sounds/message-sent.wav",
+            Self::MessageFailed => "/com/amanoteam/Papo/sounds/message-failed.wav",
+            Self::Ringtone => "/com/amanoteam/Papo/sounds/ringtone.wav",
+        }
+    }
+}
+
+/// Play a sound, unless it was played too recently.
+pub fn play(sound: Sound) {
+    PLAYING.with_borrow_mut(|playing| {
+        if playing
+            .get(&sound)
+            .is_some_and(|(started, _)| started.elapsed() < MIN_INTERVAL)
+        {
+            return;
+        }
+
+        // The media stream stops once dropped, so keep it until replaced.
+        let media = gtk::MediaFile::for_resource(sound.resource_path());
+        media.set_volume(VOLUME);
+        media.play();
+        playing.insert(sound, (Instant::now(), media));
+    });
+}
+
+/// Play a sound over and over, until stopped.
+pub fn play_looping(sound: Sound) {
+    PLAYING.with_borrow_mut(|playing| {
+        let media = gtk::MediaFile::for_resource(sound.resource_path());
+        media.set_volume(VOLUME);
+        media.set_loop(true);
+        media.play();
+        playing.insert(sound, (Instant::now(), media));
+    });
+}
+
+/// Stop a sound, if it's playing.
+pub fn stop(sound: Sound) {
+    PLAYING.with_borrow_mut(|playing| {
+        if let Some((_, media)) = playing.remove(&sound) {
+            media.pause();
+        }
+    });
+}
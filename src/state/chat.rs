@@ -1,9 +1,87 @@
 use std::{collections::HashMap, sync::Arc};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use uuid::Uuid;
 
-use crate::{state::ChatMessage, store::Database, utils::format_lid_as_number};
+use crate::{
+    i18n, report,
+    state::ChatMessage,
+    store::{ChatSettings, Database},
+    utils::format_jid,
+};
+
+/// Mute end times above this are in milliseconds, as ones in seconds
+/// won't reach it for thousands of years.
+const MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// JID of the broadcast chat status updates are addressed to.
+pub const STATUS_JID: &str = "status@broadcast";
+
+/// Kind of a chat, derived from the domain of its JID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChatKind {
+    /// Private chat addressed by phone number (`@s.whatsapp.net`).
+    Private,
+    /// Private chat addressed by LID (`@lid`).
+    Lid,
+    /// Group chat (`@g.us`).
+    Group,
+    /// Broadcast list or status updates (`@broadcast`).
+    Broadcast,
+    /// Channel (`@newsletter`).
+    Newsletter,
+    /// Domain not known yet, with no assumptions made about it.
+    Unknown(String),
+}
+
+/// Check if a chat JID is the one status updates are addressed to,
+/// rather than a broadcast list.
+pub fn is_status_jid(jid: &str) -> bool {
+    jid == STATUS_JID
+}
+
+impl ChatKind {
+    /// Get the kind of chat a JID belongs to.
+    pub fn from_jid(jid: &str) -> Self {
+        match jid.rsplit_once('@').map_or("", |(_, domain)| domain) {
+            "s.whatsapp.net" => Self::Private,
+            "lid" => Self::Lid,
+            "g.us" => Self::Group,
+            "broadcast" => Self::Broadcast,
+            "newsletter" => Self::Newsletter,
+            domain => Self::Unknown(domain.to_string()),
+        }
+    }
+
+    /// Check if the chat is with a single user, who has a phone number
+    /// and a presence.
+    pub fn is_private(&self) -> bool {
+        matches!(self, Self::Private | Self::Lid)
+    }
+
+    /// Check if read receipts are sent in this kind of chat. WhatsApp always
+    /// sends them in groups, so the preference only turns them off in private chats.
+    pub fn sends_read_receipts(&self, enabled: bool) -> bool {
+        enabled || !self.is_private()
+    }
+}
+
+/// Whether the user is in a group.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Membership {
+    /// In the group, or the chat isn't one.
+    #[default]
+    Member,
+    /// Left the group, or was removed from it.
+    Left,
+}
+
+impl Membership {
+    /// Get the membership of a user who has `left` a group or not.
+    pub fn from_left(left: bool) -> Self {
+        if left { Self::Left } else { Self::Member }
+    }
+}
 
 /// Represents a chat/conversation.
 #[derive(Clone, Debug)]
@@ -19,16 +97,30 @@ pub struct Chat {
     pub pinned: bool,
     /// Whether this chat is archived.
     pub archived: bool,
+    /// Position in the list, raised above all others when the chat moves to the top.
+    pub sort_key: i64,
     /// Whether the user is currently online.
     pub available: Option<bool>,
     /// Last time the user has been seen.
     pub last_seen: Option<DateTime<Utc>>,
+    /// Manual position among the pinned chats.
+    pub pin_order: Option<u32>,
+    /// Whether the user is still in the group.
+    pub membership: Membership,
     /// Path to the cached avatar image.
     pub avatar_path: Option<String>,
+    /// When the mute ends, or `None` if it's muted until turned off.
+    pub muted_until: Option<DateTime<Utc>>,
     /// Participants names in groups (JID -> name).
     pub participants: HashMap<String, String>,
+    /// When the name was last changed locally.
+    pub name_modified_at: Option<DateTime<Utc>>,
+    /// When the flags (pinned, archived) were last changed locally.
+    pub flags_modified_at: Option<DateTime<Utc>>,
     /// Time of the last sent message.
     pub last_message_time: DateTime<Utc>,
+    /// Number of participants in groups, including those not loaded.
+    pub participant_count: Option<u32>,
 }
 
 impl Chat {
@@ -37,21 +129,79 @@ impl Chat {
         self.db.save_chat(self).await
     }
 
+    /// Whether the chat is muted right now, as a mute with an end
+    /// turns itself off once it's over.
+    pub fn is_muted(&self) -> bool {
+        self.is_muted_at(Utc::now())
+    }
+
+    /// Whether the chat is muted at a given time.
+    fn is_muted_at(&self, now: DateTime<Utc>) -> bool {
+        self.muted && self.muted_until.is_none_or(|until| until > now)
+    }
+
+    /// Set the mute from the end time WhatsApp sends, where zero means
+    /// not muted and a negative one muted until turned off.
+    pub fn set_mute_end(&mut self, end: i64) {
+        self.muted = end != 0;
+        self.muted_until = mute_end_time(end);
+    }
+
+    /// Check if only some of the participants of this group are loaded,
+    /// as syncing passes along the first ones of large groups.
+    pub fn has_missing_participants(&self) -> bool {
+        self.is_group()
+            && self.participant_count.is_some_and(|count| {
+                usize::try_from(count).unwrap_or(usize::MAX) > self.participants.len()
+            })
+    }
+
+    /// Merge the participants of this group fetched from the server, keeping
+    /// the names already known.
+    pub fn add_participants(&mut self, jids: Vec<String>) {
+        for jid in jids {
+            self.participants
+                .entry(jid)
+                .or_insert_with(|| i18n!("Unknown"));
+        }
+        self.participant_count = Some(u32::try_from(self.participants.len()).unwrap_or(u32::MAX));
+    }
+
+    /// Get the kind of this chat.
+    pub fn kind(&self) -> ChatKind {
+        ChatKind::from_jid(&self.jid)
+    }
+
     /// Check if the chat is a group.
     pub fn is_group(&self) -> bool {
-        self.jid.ends_with("@g.us")
+        self.kind() == ChatKind::Group
+    }
+
+    /// Check if the chat is a newsletter (channel).
+    pub fn is_newsletter(&self) -> bool {
+        self.kind() == ChatKind::Newsletter
+    }
+
+    /// Check if messages can't be sent to the chat, being a channel or a
+    /// group the user is no longer in.
+    pub fn is_read_only(&self) -> bool {
+        self.is_newsletter() || self.has_left()
+    }
+
+    /// Check if the user left the group, or was removed from it.
+    pub fn has_left(&self) -> bool {
+        self.membership == Membership::Left
+    }
+
+    /// Check if the chat is with a single user.
+    pub fn is_private(&self) -> bool {
+        self.kind().is_private()
     }
 
     /// Mark all messages in this chat as read.
     pub async fn mark_read(&self) -> Result<(), libsql::Error> {
         if self.get_unread_count().await.is_ok_and(|count| count > 0) {
-            self.db
-                .execute(
-                    "UPDATE messages SET status = 1 WHERE chat_jid = ?1 AND (status == 0 OR status == 5)",
-                    [self.jid.as_str()],
-                )
-                .await
-                .map(drop)
+            self.db.mark_chat_read(&self.jid).await
         } else {
             Ok(())
         }
@@ -60,12 +210,80 @@ impl Chat {
     /// Get the chat name or phone number if empty.
     pub fn get_name_or_number(&self) -> String {
         if self.name.is_empty() {
-            format_lid_as_number(&self.jid)
+            format_jid(&self.jid)
         } else {
             self.name.clone()
         }
     }
 
+    /// Merge the state from a history sync taken at `synced_at`, field group
+    /// by field group, keeping local changes newer than it.
+    /// Returns whether anything changed.
+    pub fn merge_synced(
+        &mut self,
+        name: Option<String>,
+        pinned: bool,
+        archived: bool,
+        mute_end: Option<u64>,
+        synced_at: DateTime<Utc>,
+    ) -> bool {
+        let mut changed = false;
+
+        if let Some(name) = name.filter(|n| !n.is_empty() && *n != self.name) {
+            if self.name_modified_at.is_some_and(|t| t > synced_at) {
+                tracing::debug!(
+                    "{}",
+                    report::redact(&format!(
+                        "Sync conflict on {} name: keeping local {:?} over {name:?}",
+                        self.jid, self.name
+                    ))
+                );
+            } else {
+                self.name = name;
+                changed = true;
+            }
+        }
+
+        if pinned != self.pinned || archived != self.archived {
+            if self.flags_modified_at.is_some_and(|t| t > synced_at) {
+                tracing::debug!(
+                    "Sync conflict on {} flags: keeping local (pinned: {}, archived: {}) over (pinned: {}, archived: {})",
+                    self.jid,
+                    self.pinned,
+                    self.archived,
+                    pinned,
+                    archived
+                );
+            } else {
+                if pinned != self.pinned {
+                    self.pin_order = None;
+                }
+                self.pinned = pinned;
+                self.archived = archived;
+                changed = true;
+            }
+        }
+
+        let muted = mute_end.is_some_and(|end| end != 0);
+        let muted_until = mute_end.and_then(|end| mute_end_time(end.cast_signed()));
+        if muted != self.muted || muted_until != self.muted_until {
+            if self.flags_modified_at.is_some_and(|t| t > synced_at) {
+                tracing::debug!(
+                    "Sync conflict on {} mute: keeping local (muted: {}) over (muted: {})",
+                    self.jid,
+                    self.muted,
+                    muted
+                );
+            } else {
+                self.muted = muted;
+                self.muted_until = muted_until;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
     /// Get the last sent message in this chat.
     pub async fn get_last_message(&self) -> Result<Option<ChatMessage>, libsql::Error> {
         self.load_messages(1).await.map(|mut m| m.pop())
@@ -98,6 +316,43 @@ impl Chat {
             .await
     }
 
+    /// Load messages around a given timestamp, oldest first.
+    pub async fn load_messages_around(
+        &self,
+        timestamp: i64,
+        limit: u32,
+    ) -> Result<Vec<ChatMessage>, libsql::Error> {
+        self.db
+            .load_messages_around(&self.jid, timestamp, limit)
+            .await
+    }
+
+    /// Get the timestamps of the oldest and newest messages in this chat.
+    pub async fn get_date_range(&self) -> Result<Option<(i64, i64)>, libsql::Error> {
+        self.db.get_chat_date_range(&self.jid).await
+    }
+
+    /// Get the local days with messages between `start` and `end`.
+    pub async fn get_message_days(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<NaiveDate>, libsql::Error> {
+        self.db.get_message_days(&self.jid, start, end).await
+    }
+
+    /// Get the timestamp of the first message between `start` and `end`,
+    /// or of the nearest earlier one.
+    pub async fn find_message_time_between(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> Result<Option<i64>, libsql::Error> {
+        self.db
+            .find_message_time_between(&self.jid, start, end)
+            .await
+    }
+
     /// Find a message in this chat by its server ID.
     pub async fn find_message(&self, msg_id: &str) -> Result<Option<ChatMessage>, libsql::Error> {
         self.db.load_message_by_server_id(&self.jid, msg_id).await
@@ -111,13 +366,276 @@ impl Chat {
         self.db.load_message_by_local_id(&self.jid, msg_id).await
     }
 
+    /// Load the pinned messages of this chat with when their pins end,
+    /// latest pin first. Pins that ended are dropped.
+    pub async fn load_pinned_messages(
+        &self,
+    ) -> Result<Vec<(ChatMessage, Option<DateTime<Utc>>)>, libsql::Error> {
+        let mut messages = Vec::new();
+        for (id, expires_at) in self
+            .db
+            .load_pinned_message_ids(&self.jid, Utc::now())
+            .await?
+        {
+            if let Some(message) = self.find_message(&id).await? {
+                messages.push((message, expires_at));
+            }
+        }
+
+        Ok(messages)
+    }
+
     /// Get the count of unread messages in this chat.
     pub async fn get_unread_count(&self) -> Result<usize, libsql::Error> {
         self.db.get_unread_count(&self.jid).await
     }
 
+    /// Get the number of people asking to join this group.
+    pub async fn get_join_request_count(&self) -> Result<usize, libsql::Error> {
+        self.db.count_join_requests(&self.jid).await
+    }
+
+    /// Get the time of the first unread message in this chat.
+    pub async fn get_first_unread_time(&self) -> Result<Option<i64>, libsql::Error> {
+        self.db.get_first_unread_time(&self.jid).await
+    }
+
     /// Get all unread messages in this chat.
     pub async fn get_unread_messages(&self) -> Result<Vec<ChatMessage>, libsql::Error> {
         self.db.get_unread_messages(&self.jid).await
     }
+
+    /// Load the local settings of this chat.
+    pub async fn load_settings(&self) -> Result<ChatSettings, libsql::Error> {
+        self.db.load_chat_settings(&self.jid).await
+    }
+
+    /// Save the local settings of this chat.
+    pub async fn save_settings(&self, settings: &ChatSettings) -> Result<(), libsql::Error> {
+        self.db.save_chat_settings(&self.jid, settings).await
+    }
+}
+
+/// Get when a mute ends from the end time WhatsApp sends, or `None` if it
+/// doesn't end (or there's no mute).
+fn mute_end_time(end: i64) -> Option<DateTime<Utc>> {
+    if end <= 0 {
+        None
+    } else if end > MILLIS_THRESHOLD {
+        // History sync sends it in seconds and app state in milliseconds.
+        DateTime::from_timestamp_millis(end)
+    } else {
+        DateTime::from_timestamp(end, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::test_utils::{block_on, chat, database, message};
+
+    #[test]
+    fn chat_kind_follows_the_jid_domain() {
+        assert_eq!(
+            ChatKind::from_jid("5511999990000@s.whatsapp.net"),
+            ChatKind::Private
+        );
+        assert_eq!(ChatKind::from_jid("123456789@lid"), ChatKind::Lid);
+        assert_eq!(ChatKind::from_jid("123-456@g.us"), ChatKind::Group);
+        assert_eq!(ChatKind::from_jid("123@broadcast"), ChatKind::Broadcast);
+        assert_eq!(ChatKind::from_jid("123@newsletter"), ChatKind::Newsletter);
+        assert_eq!(
+            ChatKind::from_jid("123@bot"),
+            ChatKind::Unknown("bot".into())
+        );
+        assert_eq!(ChatKind::from_jid("123"), ChatKind::Unknown(String::new()));
+
+        assert!(ChatKind::Private.is_private());
+        assert!(ChatKind::Lid.is_private());
+        assert!(!ChatKind::Group.is_private());
+        assert!(!ChatKind::Newsletter.is_private());
+        assert!(!ChatKind::Unknown("bot".into()).is_private());
+    }
+
+    #[test]
+    fn read_receipts_can_only_be_turned_off_in_private_chats() {
+        for kind in [ChatKind::Private, ChatKind::Lid] {
+            assert!(kind.sends_read_receipts(true));
+            assert!(!kind.sends_read_receipts(false));
+        }
+
+        for kind in [
+            ChatKind::Group,
+            ChatKind::Broadcast,
+            ChatKind::Newsletter,
+            ChatKind::Unknown("bot".into()),
+        ] {
+            assert!(kind.sends_read_receipts(true));
+            assert!(kind.sends_read_receipts(false));
+        }
+    }
+
+    #[test]
+    fn status_updates_are_told_apart_from_broadcast_lists() {
+        assert!(is_status_jid("status@broadcast"));
+        assert_eq!(ChatKind::from_jid(STATUS_JID), ChatKind::Broadcast);
+
+        assert!(!is_status_jid("1700000000@broadcast"));
+        assert!(!is_status_jid("status@s.whatsapp.net"));
+        assert!(!is_status_jid("status"));
+        assert!(!is_status_jid(""));
+    }
+
+    #[test]
+    fn large_groups_load_their_participants_on_demand() {
+        let db = block_on(database());
+        let mut group = chat(&db, "123@g.us");
+        group
+            .participants
+            .insert("alice@s.whatsapp.net".into(), "Alice".into());
+        assert!(!group.has_missing_participants());
+
+        group.participant_count = Some(3);
+        assert!(group.has_missing_participants());
+
+        group.add_participants(vec![
+            "alice@s.whatsapp.net".into(),
+            "bob@s.whatsapp.net".into(),
+            "carol@s.whatsapp.net".into(),
+        ]);
+        assert!(!group.has_missing_participants());
+        assert_eq!(group.participants["alice@s.whatsapp.net"], "Alice");
+        assert_eq!(group.participant_count, Some(3));
+
+        let mut private = chat(&db, "alice@s.whatsapp.net");
+        private.participant_count = Some(3);
+        assert!(!private.has_missing_participants());
+    }
+
+    #[test]
+    fn search_results_deleted_since_are_no_longer_found() {
+        block_on(async {
+            let db = database().await;
+            let chat = chat(&db, "5511999990000@s.whatsapp.net");
+            for id in ["deleted", "cleared"] {
+                db.save_message(&chat.jid, &message(&db, &chat.jid, &chat.jid, id))
+                    .await
+                    .unwrap();
+            }
+
+            let results = db.search_messages("Message", 10).await.unwrap();
+            assert_eq!(results.len(), 2);
+            for (_, result) in &results {
+                assert!(
+                    chat.find_message(&result.server_id)
+                        .await
+                        .unwrap()
+                        .is_some()
+                );
+            }
+
+            // Jumping to either now tells it's no longer available.
+            db.delete_message("deleted").await.unwrap();
+            db.clear_chat_history(&chat.jid, Utc::now() + TimeDelta::seconds(1))
+                .await
+                .unwrap();
+            for (_, result) in &results {
+                assert!(
+                    chat.find_message(&result.server_id)
+                        .await
+                        .unwrap()
+                        .is_none()
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn mute_end_time_units() {
+        assert_eq!(mute_end_time(0), None);
+        assert_eq!(mute_end_time(-1), None);
+        assert_eq!(
+            mute_end_time(1_700_000_000),
+            DateTime::from_timestamp(1_700_000_000, 0)
+        );
+        assert_eq!(
+            mute_end_time(1_700_000_000_000),
+            DateTime::from_timestamp(1_700_000_000, 0)
+        );
+    }
+
+    #[test]
+    fn mute_end_turns_mute_off() {
+        let db = block_on(database());
+        let mut chat = chat(&db, "5511999990000@s.whatsapp.net");
+        let now = Utc::now();
+
+        chat.set_mute_end(0);
+        assert!(!chat.is_muted_at(now));
+
+        chat.set_mute_end(-1);
+        assert!(chat.is_muted_at(now));
+        assert!(chat.is_muted_at(now + TimeDelta::days(365 * 100)));
+
+        let end = now + TimeDelta::hours(8);
+        chat.set_mute_end(end.timestamp_millis());
+        assert!(chat.is_muted_at(now));
+        assert!(!chat.is_muted_at(end));
+
+        chat.set_mute_end(end.timestamp());
+        assert!(chat.is_muted_at(now));
+        assert!(!chat.is_muted_at(end + TimeDelta::seconds(1)));
+    }
+
+    #[test]
+    fn sync_keeps_local_pins_and_names_changed_since() {
+        let db = block_on(database());
+        let mut chat = chat(&db, "5511999990000@s.whatsapp.net");
+        let synced_at = Utc::now();
+
+        // Pinned and renamed here after the sync was taken.
+        chat.name = "Local".to_string();
+        chat.pinned = true;
+        chat.pin_order = Some(1);
+        chat.name_modified_at = Some(synced_at + TimeDelta::seconds(1));
+        chat.flags_modified_at = Some(synced_at + TimeDelta::seconds(1));
+        assert!(!chat.merge_synced(Some("Synced".into()), false, false, None, synced_at));
+        assert_eq!(chat.name, "Local");
+        assert!(chat.pinned);
+        assert_eq!(chat.pin_order, Some(1));
+
+        // Changed here before a later sync, which wins.
+        let later = synced_at + TimeDelta::minutes(1);
+        assert!(chat.merge_synced(Some("Synced".into()), false, true, None, later));
+        assert_eq!(chat.name, "Synced");
+        assert!(!chat.pinned && chat.archived);
+        assert_eq!(chat.pin_order, None);
+    }
+
+    #[test]
+    fn sync_merges_mute_unless_changed_since() {
+        let db = block_on(database());
+        let mut chat = chat(&db, "5511999990000@s.whatsapp.net");
+        let synced_at = Utc::now();
+        let end = (synced_at + TimeDelta::hours(8)).timestamp();
+
+        assert!(chat.merge_synced(None, false, false, Some(end.cast_unsigned()), synced_at));
+        assert!(chat.muted);
+        assert_eq!(chat.muted_until, DateTime::from_timestamp(end, 0));
+        assert!(!chat.merge_synced(None, false, false, Some(end.cast_unsigned()), synced_at));
+
+        // A mute turned off live after the sync was taken stays off.
+        chat.muted = false;
+        chat.muted_until = None;
+        chat.flags_modified_at = Some(synced_at + TimeDelta::seconds(1));
+        assert!(!chat.merge_synced(None, false, false, Some(end.cast_unsigned()), synced_at));
+        assert!(!chat.muted);
+
+        let later = synced_at + TimeDelta::minutes(1);
+        assert!(!chat.merge_synced(None, false, false, None, later));
+        assert!(chat.merge_synced(None, false, false, Some(end.cast_unsigned()), later));
+        assert!(chat.muted);
+    }
 }
@@ -0,0 +1,95 @@
+use waproto::whatsapp as wa;
+
+use crate::{i18n, ni18n};
+
+/// A contact card (vCard) attached to a message.
+#[derive(Clone, Debug, Default)]
+pub struct ContactCard {
+    /// Contact display name.
+    pub name: String,
+    /// Raw vCard text.
+    pub vcard: String,
+}
+
+impl ContactCard {
+    /// Parse the contact cards from a contact message, if it's one.
+    pub fn from_message(message: &wa::Message) -> Vec<Self> {
+        let contacts = if let Some(ref contact) = message.contact_message {
+            vec![contact.as_ref()]
+        } else if let Some(ref array) = message.contacts_array_message {
+            array.contacts.iter().collect()
+        } else {
+            Vec::new()
+        };
+
+        contacts
+            .into_iter()
+            .filter_map(|contact| {
+                let vcard = contact.vcard.clone()?;
+                let name = contact
+                    .display_name
+                    .clone()
+                    .filter(|n| !n.is_empty())
+                    .or_else(|| find_property(&vcard, "FN").map(|(_, value)| value.to_string()))
+                    .unwrap_or_else(|| i18n!("Unknown"));
+
+                Some(Self { name, vcard })
+            })
+            .collect()
+    }
+
+    /// Gets a display label for a list of cards.
+    pub fn display_label(cards: &[Self]) -> String {
+        match cards {
+            [card] => format!("👤 {}", card.name),
+            cards => {
+                let count = u32::try_from(cards.len()).unwrap_or(u32::MAX);
+                format!(
+                    "👤 {}",
+                    ni18n!("{0} contact", "{0} contacts", count).replacen(
+                        "{0}",
+                        &count.to_string(),
+                        1
+                    )
+                )
+            }
+        }
+    }
+
+    /// Get the primary phone number.
+    pub fn phone_number(&self) -> Option<String> {
+        find_property(&self.vcard, "TEL")
+            .map(|(_, value)| value.to_string())
+            .filter(|n| !n.is_empty())
+    }
+
+    /// Get the JID of the primary phone number.
+    pub fn jid(&self) -> Option<String> {
+        let (params, value) = find_property(&self.vcard, "TEL")?;
+
+        // WhatsApp adds the account number as a `waid` parameter.
+        let number = params
+            .split(';')
+            .find_map(|param| param.strip_prefix("waid="))
+            .map_or_else(
+                || value.chars().filter(char::is_ascii_digit).collect(),
+                ToString::to_string,
+            );
+
+        (!number.is_empty()).then(|| format!("{number}@s.whatsapp.net"))
+    }
+}
+
+/// Find the first property of a vCard, returning its parameters and value.
+fn find_property<'a>(vcard: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+    vcard.lines().find_map(|line| {
+        let (key, value) = line.trim().split_once(':')?;
+        let (property, params) = key.split_once(';').unwrap_or((key, ""));
+
+        // Grouped properties are prefixed, e.g. "item1.TEL".
+        let property = property.rsplit('.').next().unwrap_or(property);
+        property
+            .eq_ignore_ascii_case(name)
+            .then(|| (params, value.trim()))
+    })
+}
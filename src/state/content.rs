@@ -0,0 +1,171 @@
+use waproto::whatsapp as wa;
+
+use crate::state::{
+    ChatMessage, ContactCard, Location, Media, MediaType, MessageKind, Poll, TextContent,
+};
+
+/// What a received message carries, read the same way whether it arrives
+/// live or is synced from the history.
+#[derive(Clone, Debug, Default)]
+pub struct MessageContent {
+    /// Kind of the message.
+    pub kind: MessageKind,
+    /// Poll created by the message.
+    pub poll: Option<Poll>,
+    /// Text, with the quote, mentions and link preview sent along.
+    pub text: Option<TextContent>,
+    /// Media attached to the message.
+    pub media: Option<Media>,
+    /// Shared contact cards.
+    pub contacts: Vec<ContactCard>,
+    /// Shared location.
+    pub location: Option<Location>,
+}
+
+impl MessageContent {
+    /// Read what a message carries.
+    pub fn from_message(message: &wa::Message) -> Self {
+        Self {
+            kind: MessageKind::from_received(message),
+            poll: Poll::from_message(message),
+            text: TextContent::from_message(message),
+            media: Media::from_message(message),
+            contacts: ContactCard::from_message(message),
+            location: Location::from_message(message),
+        }
+    }
+
+    /// Get the text shown for the message: its own, or a label for what it
+    /// carries instead. `None` if it carries nothing that can be shown.
+    pub fn display_text(&self) -> Option<String> {
+        self.text
+            .as_ref()
+            .map(|text| text.text.clone())
+            .or_else(|| self.poll.as_ref().map(|p| p.question.clone()))
+            .or_else(|| self.location.as_ref().map(Location::display_label))
+            .or_else(|| {
+                (!self.contacts.is_empty()).then(|| ContactCard::display_label(&self.contacts))
+            })
+            // Voice messages have no text, their bubble shows a player,
+            // stickers show their preview when they come with one, and
+            // images show above their caption.
+            .or_else(|| {
+                self.media
+                    .as_ref()
+                    .map(|media| match (&media.caption, media.r#type) {
+                        (Some(caption), _) => caption.clone(),
+                        (None, MediaType::Sticker) if !media.has_data() => {
+                            media.r#type.display_label()
+                        }
+                        (None, MediaType::Video | MediaType::Document) => {
+                            media.r#type.display_label()
+                        }
+                        (None, _) => String::new(),
+                    })
+            })
+    }
+
+    /// Fill a message with what it carries, besides its text.
+    pub fn apply(self, message: &mut ChatMessage) {
+        let (quote, preview) = self
+            .text
+            .map_or((None, None), |text| (text.quote, text.preview));
+
+        message.kind = self.kind;
+        message.poll = self.poll;
+        message.media = self.media;
+        message.quote = quote;
+        message.preview = preview;
+        message.contacts = self.contacts;
+        message.location = self.location;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_shown_before_what_the_message_carries() {
+        let content = MessageContent::from_message(&wa::Message {
+            extended_text_message: Some(
+                wa::message::ExtendedTextMessage {
+                    text: Some("Look here".to_string()),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            location_message: Some(
+                wa::message::LocationMessage {
+                    name: Some("Park".to_string()),
+                    degrees_latitude: Some(1.0),
+                    degrees_longitude: Some(2.0),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        });
+        assert_eq!(content.display_text().as_deref(), Some("Look here"));
+
+        let location = MessageContent {
+            text: None,
+            ..content
+        };
+        assert!(location.display_text().unwrap().contains("Park"));
+    }
+
+    #[test]
+    fn images_show_their_caption() {
+        let image = |caption: Option<&str>| {
+            MessageContent::from_message(&wa::Message {
+                image_message: Some(
+                    wa::message::ImageMessage {
+                        caption: caption.map(ToString::to_string),
+                        jpeg_thumbnail: Some(b"jpeg".to_vec()),
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            image(Some("Sunset")).display_text().as_deref(),
+            Some("Sunset")
+        );
+        assert_eq!(image(None).display_text().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn documents_show_their_file_name() {
+        let content = MessageContent::from_message(&wa::Message {
+            document_message: Some(
+                wa::message::DocumentMessage {
+                    file_name: Some("report.pdf".to_string()),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        });
+        assert_eq!(content.display_text().as_deref(), Some("report.pdf"));
+
+        let video = MessageContent::from_message(&wa::Message {
+            video_message: Some(wa::message::VideoMessage::default().into()),
+            ..Default::default()
+        });
+        assert_eq!(video.display_text(), Some(MediaType::Video.display_label()));
+    }
+
+    #[test]
+    fn voice_messages_show_no_text() {
+        let content = MessageContent::from_message(&wa::Message {
+            audio_message: Some(wa::message::AudioMessage::default().into()),
+            ..Default::default()
+        });
+        assert_eq!(content.display_text().as_deref(), Some(""));
+        assert!(MessageContent::default().display_text().is_none());
+    }
+}
@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+
+use crate::state::text::url_domain;
+
+/// A web link sent in a chat.
+#[derive(Clone, Debug)]
+pub struct ChatLink {
+    /// The link itself.
+    pub url: String,
+    /// Title of the linked page, from the preview sent along.
+    pub title: Option<String>,
+    /// When the message with the link was sent.
+    pub timestamp: DateTime<Utc>,
+    /// Server ID of the message with the link.
+    pub message_id: String,
+}
+
+impl ChatLink {
+    /// Get the title shown for the link, its site if it came without a preview.
+    pub fn display_title(&self) -> &str {
+        self.title
+            .as_deref()
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or_else(|| url_domain(&self.url))
+    }
+}
+
+/// A document sent in a chat.
+#[derive(Clone, Debug)]
+pub struct ChatDocument {
+    /// Size of the document in bytes, whether it was downloaded or not.
+    pub size: u64,
+    /// Caption or file name.
+    pub content: String,
+    /// JID of the chat the document is in.
+    pub chat_jid: String,
+    /// When the document was sent.
+    pub timestamp: DateTime<Utc>,
+    /// Server ID of the message with the document.
+    pub message_id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(url: &str, title: Option<&str>) -> ChatLink {
+        ChatLink {
+            url: url.into(),
+            title: title.map(Into::into),
+            timestamp: Utc::now(),
+            message_id: "id".into(),
+        }
+    }
+
+    #[test]
+    fn links_are_titled_by_their_preview() {
+        let link = link("https://www.gnome.org/news", Some("GNOME News"));
+        assert_eq!(link.display_title(), "GNOME News");
+    }
+
+    #[test]
+    fn links_without_preview_are_titled_by_their_site() {
+        assert_eq!(
+            link("https://www.gnome.org/news", None).display_title(),
+            "gnome.org"
+        );
+        assert_eq!(
+            link("https://gnome.org?q=1", Some(" ")).display_title(),
+            "gnome.org"
+        );
+    }
+}
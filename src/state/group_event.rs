@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+
+use waproto::whatsapp::web_message_info::StubType;
+
+use crate::{i18n, i18n_f, ni18n, utils::format_lid_as_number};
+
+/// A change made to a group, shown as a service event in its history.
+#[derive(Clone, Debug)]
+pub enum GroupEvent {
+    /// Participants were added (or joined) by `actor`.
+    ParticipantsAdded {
+        actor: String,
+        participants: Vec<String>,
+    },
+    /// Participants were removed by `actor`.
+    ParticipantsRemoved {
+        actor: String,
+        participants: Vec<String>,
+    },
+    /// A participant left the group.
+    ParticipantLeft { participant: String },
+    /// The group name was changed by `actor`.
+    SubjectChanged { actor: String, subject: String },
+    /// The group icon was changed by `actor`.
+    IconChanged { actor: String },
+    /// Participants were made admins.
+    ParticipantsPromoted { participants: Vec<String> },
+    /// Requests to join were approved by `actor`.
+    JoinRequestsApproved {
+        actor: String,
+        participants: Vec<String>,
+    },
+    /// Requests to join were rejected by `actor`.
+    JoinRequestsRejected {
+        actor: String,
+        participants: Vec<String>,
+    },
+}
+
+impl GroupEvent {
+    /// Parse the event from a message stub, if it's a supported group change.
+    pub fn from_stub(stub_type: i32, parameters: &[String], actor: &str) -> Option<Self> {
+        let actor = actor.to_string();
+        let participants = parameters.to_vec();
+
+        match StubType::try_from(stub_type).ok()? {
+            StubType::GroupParticipantAdd | StubType::GroupParticipantInvite => {
+                Some(Self::ParticipantsAdded {
+                    actor,
+                    participants,
+                })
+            }
+            StubType::GroupParticipantRemove => Some(Self::ParticipantsRemoved {
+                actor,
+                participants,
+            }),
+            StubType::GroupParticipantLeave => Some(Self::ParticipantLeft {
+                participant: parameters.first().cloned().unwrap_or(actor),
+            }),
+            StubType::GroupChangeSubject => Some(Self::SubjectChanged {
+                actor,
+                subject: parameters.first().cloned().unwrap_or_default(),
+            }),
+            StubType::GroupChangeIcon => Some(Self::IconChanged { actor }),
+            StubType::GroupParticipantPromote => Some(Self::ParticipantsPromoted { participants }),
+            _ => None,
+        }
+    }
+
+    /// Parse the event from a change in a live group notification, given by
+    /// the tag of the change, if it's a supported one.
+    pub fn from_notification(
+        tag: &str,
+        actor: &str,
+        participants: Vec<String>,
+        subject: Option<String>,
+    ) -> Option<Self> {
+        let actor = actor.to_string();
+
+        match tag {
+            // Joining through an invite link creates the group for the user.
+            "add" | "create" => Some(Self::ParticipantsAdded {
+                actor,
+                participants,
+            }),
+            // Leaving comes as the user removing themselves.
+            "remove"
+                if !participants.is_empty()
+                    && participants.iter().all(|p| same_user(p, &actor)) =>
+            {
+                Some(Self::ParticipantLeft { participant: actor })
+            }
+            "remove" => Some(Self::ParticipantsRemoved {
+                actor,
+                participants,
+            }),
+            "leave" => Some(Self::ParticipantLeft {
+                participant: participants.into_iter().next().unwrap_or(actor),
+            }),
+            "subject" => Some(Self::SubjectChanged {
+                actor,
+                subject: subject.unwrap_or_default(),
+            }),
+            "promote" => Some(Self::ParticipantsPromoted { participants }),
+            _ => None,
+        }
+    }
+
+    /// Gets the localized text, naming people from the group participants.
+    pub fn display_text(
+        &self,
+        user_jid: Option<&str>,
+        participants: &HashMap<String, String>,
+    ) -> String {
+        let name = |jid: &str| {
+            if user_jid.is_some_and(|u| same_user(u, jid)) {
+                i18n!("You")
+            } else {
+                participants
+                    .get(jid)
+                    .filter(|n| **n != i18n!("Unknown"))
+                    .cloned()
+                    .unwrap_or_else(|| format_lid_as_number(jid))
+            }
+        };
+        let names = |jids: &[String]| {
+            jids.iter()
+                .map(|jid| name(jid))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        match self {
+            Self::ParticipantsAdded {
+                actor,
+                participants,
+            } => {
+                if participants.iter().all(|p| same_user(p, actor)) {
+                    i18n_f!("{0} joined", name(actor))
+                } else {
+                    // Translators: {0} is who added, {1} are the added participants.
+                    i18n_f!("{0} added {1}", name(actor), names(participants))
+                }
+            }
+            Self::ParticipantsRemoved {
+                actor,
+                participants,
+            } => {
+                // Translators: {0} is who removed, {1} are the removed participants.
+                i18n_f!("{0} removed {1}", name(actor), names(participants))
+            }
+            Self::ParticipantLeft { participant } => i18n_f!("{0} left", name(participant)),
+            Self::SubjectChanged { actor, subject } => {
+                i18n_f!("{0} changed the group name to “{1}”", name(actor), subject)
+            }
+            Self::IconChanged { actor } => i18n_f!("{0} changed the group icon", name(actor)),
+            Self::ParticipantsPromoted { participants } => {
+                if participants
+                    .iter()
+                    .any(|p| user_jid.is_some_and(|u| same_user(u, p)))
+                {
+                    i18n!("You're now an admin")
+                } else {
+                    let count = u32::try_from(participants.len()).unwrap_or(u32::MAX);
+                    ni18n!("{0} is now an admin", "{0} are now admins", count).replacen(
+                        "{0}",
+                        &names(participants),
+                        1,
+                    )
+                }
+            }
+            Self::JoinRequestsApproved {
+                actor,
+                participants,
+            } => {
+                // Translators: {0} is who approved, {1} are who asked to join.
+                i18n_f!("{0} approved {1} to join", name(actor), names(participants))
+            }
+            Self::JoinRequestsRejected {
+                actor,
+                participants,
+            } => {
+                // Translators: {0} is who rejected, {1} are who asked to join.
+                i18n_f!(
+                    "{0} rejected the request of {1} to join",
+                    name(actor),
+                    names(participants)
+                )
+            }
+        }
+    }
+
+    /// Who made the change, if it's known.
+    pub fn actor(&self) -> Option<&str> {
+        match self {
+            Self::ParticipantsAdded { actor, .. }
+            | Self::ParticipantsRemoved { actor, .. }
+            | Self::SubjectChanged { actor, .. }
+            | Self::IconChanged { actor }
+            | Self::JoinRequestsApproved { actor, .. }
+            | Self::JoinRequestsRejected { actor, .. } => Some(actor.as_str()),
+            Self::ParticipantLeft { participant } => Some(participant.as_str()),
+            Self::ParticipantsPromoted { .. } => None,
+        }
+        .filter(|actor| !actor.is_empty())
+    }
+
+    /// Whether the event puts the user in the group (`Some(true)`) or takes
+    /// them out of it (`Some(false)`), if it changes their membership at all.
+    pub fn membership_change(&self, user_jids: &[String]) -> Option<bool> {
+        let is_user = |jid: &String| user_jids.iter().any(|user| same_user(user, jid));
+
+        match self {
+            Self::ParticipantsAdded { participants, .. } if participants.iter().any(is_user) => {
+                Some(true)
+            }
+            Self::ParticipantsRemoved { participants, .. } if participants.iter().any(is_user) => {
+                Some(false)
+            }
+            Self::ParticipantLeft { participant } if is_user(participant) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Check if two JIDs belong to the same user, ignoring the device part.
+fn same_user(a: &str, b: &str) -> bool {
+    let user = |jid: &str| {
+        let (user, server) = jid.split_once('@').unwrap_or((jid, ""));
+        (
+            user.split(':').next().unwrap_or(user).to_string(),
+            server.to_string(),
+        )
+    };
+
+    user(a) == user(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "5511999990000@s.whatsapp.net";
+    const OTHER: &str = "5511999990001@s.whatsapp.net";
+
+    fn user_jids() -> Vec<String> {
+        vec![USER.to_string(), "12345678901234@lid".to_string()]
+    }
+
+    fn notification(tag: &str, actor: &str, participants: &[&str]) -> Option<GroupEvent> {
+        let participants = participants.iter().map(ToString::to_string).collect();
+        GroupEvent::from_notification(tag, actor, participants, None)
+    }
+
+    #[test]
+    fn leaving_and_removal_take_the_user_out() {
+        let left = notification("remove", USER, &[USER]).unwrap();
+        assert!(matches!(left, GroupEvent::ParticipantLeft { .. }));
+        assert_eq!(left.membership_change(&user_jids()), Some(false));
+
+        let removed = notification("remove", OTHER, &["5511999990000:2@s.whatsapp.net"]).unwrap();
+        assert!(matches!(removed, GroupEvent::ParticipantsRemoved { .. }));
+        assert_eq!(removed.membership_change(&user_jids()), Some(false));
+
+        let leave = notification("leave", USER, &[USER]).unwrap();
+        assert_eq!(leave.membership_change(&user_jids()), Some(false));
+    }
+
+    #[test]
+    fn being_added_or_joining_by_invite_brings_the_user_back() {
+        let added = notification("add", OTHER, &[USER]).unwrap();
+        assert_eq!(added.membership_change(&user_jids()), Some(true));
+
+        let joined = notification("create", USER, &[OTHER, "12345678901234@lid"]).unwrap();
+        assert_eq!(joined.membership_change(&user_jids()), Some(true));
+    }
+
+    #[test]
+    fn changes_to_others_keep_the_membership() {
+        for event in [
+            notification("add", USER, &[OTHER]),
+            notification("remove", USER, &[OTHER]),
+            notification("leave", OTHER, &[OTHER]),
+            notification("promote", OTHER, &[USER]),
+            notification("subject", OTHER, &[]),
+        ] {
+            assert_eq!(event.unwrap().membership_change(&user_jids()), None);
+        }
+    }
+
+    #[test]
+    fn changes_are_made_by_their_actor() {
+        assert_eq!(
+            notification("add", OTHER, &[USER]).unwrap().actor(),
+            Some(OTHER)
+        );
+        assert_eq!(
+            notification("remove", USER, &[USER]).unwrap().actor(),
+            Some(USER)
+        );
+        assert_eq!(
+            notification("leave", OTHER, &[USER]).unwrap().actor(),
+            Some(USER)
+        );
+        assert_eq!(
+            notification("subject", OTHER, &[]).unwrap().actor(),
+            Some(OTHER)
+        );
+        assert_eq!(notification("add", "", &[USER]).unwrap().actor(), None);
+        assert_eq!(
+            notification("promote", OTHER, &[USER]).unwrap().actor(),
+            None
+        );
+    }
+
+    #[test]
+    fn resolved_join_requests_name_who_resolved_them() {
+        let approved = GroupEvent::JoinRequestsApproved {
+            actor: USER.to_string(),
+            participants: vec![OTHER.to_string()],
+        };
+        assert_eq!(approved.actor(), Some(USER));
+        assert_eq!(approved.membership_change(&user_jids()), None);
+
+        let participants = HashMap::from([(OTHER.to_string(), "Bob".to_string())]);
+        assert_eq!(
+            approved.display_text(Some(USER), &participants),
+            "You approved Bob to join"
+        );
+    }
+
+    #[test]
+    fn unknown_changes_are_skipped() {
+        assert!(notification("locked", OTHER, &[]).is_none());
+        assert!(notification("ephemeral", OTHER, &[]).is_none());
+    }
+}
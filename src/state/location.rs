@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use waproto::whatsapp as wa;
+
+use crate::i18n;
+
+/// A location attached to a message.
+#[derive(Clone, Debug, Default)]
+pub struct Location {
+    /// Whether this is a live location.
+    pub live: bool,
+    /// Place name.
+    pub name: Option<String>,
+    /// Place address.
+    pub address: Option<String>,
+    /// Latitude in degrees.
+    pub latitude: f64,
+    /// Longitude in degrees.
+    pub longitude: f64,
+    /// JPEG map thumbnail.
+    pub thumbnail: Option<Arc<Vec<u8>>>,
+}
+
+impl Location {
+    /// Parse the location from a location message, if it's one.
+    pub fn from_message(message: &wa::Message) -> Option<Self> {
+        if let Some(ref location) = message.location_message {
+            return Some(Self {
+                live: false,
+                name: location.name.clone().filter(|n| !n.is_empty()),
+                address: location.address.clone().filter(|a| !a.is_empty()),
+                latitude: location.degrees_latitude?,
+                longitude: location.degrees_longitude?,
+                thumbnail: location.jpeg_thumbnail.clone().map(Arc::new),
+            });
+        }
+
+        let location = message.live_location_message.as_ref()?;
+        Some(Self {
+            live: true,
+            name: location.caption.clone().filter(|c| !c.is_empty()),
+            address: None,
+            latitude: location.degrees_latitude?,
+            longitude: location.degrees_longitude?,
+            thumbnail: location.jpeg_thumbnail.clone().map(Arc::new),
+        })
+    }
+
+    /// Gets a display label.
+    pub fn display_label(&self) -> String {
+        let name = self.name.clone().unwrap_or_else(|| {
+            if self.live {
+                i18n!("Live location")
+            } else {
+                i18n!("Location")
+            }
+        });
+
+        format!("📍 {name}")
+    }
+
+    /// Gets a `geo:` URI pointing to the location.
+    pub fn geo_uri(&self) -> String {
+        format!("geo:{},{}", self.latitude, self.longitude)
+    }
+}
@@ -1,8 +1,36 @@
 use std::sync::Arc;
 
+use gtk::{gio, prelude::*};
 use wacore::download::MediaType as DownloadMediaType;
+use waproto::whatsapp as wa;
 
-use crate::i18n;
+use crate::{DATA_DIR, i18n, utils::is_low_on_space};
+
+/// Get what's needed to download the media of a message, from the fields
+/// all media messages share, if it has them.
+macro_rules! downloadable_media {
+    ($message:expr, $type:expr, $mime_type:expr, $duration_secs:expr) => {
+        match (
+            &$message.media_key,
+            &$message.direct_path,
+            &$message.file_enc_sha256,
+        ) {
+            (Some(media_key), Some(direct_path), Some(file_enc_sha256)) => {
+                Some(DownloadableMedia {
+                    media_key: media_key.clone(),
+                    mime_type: $mime_type.clone(),
+                    direct_path: direct_path.clone(),
+                    file_length: $message.file_length.unwrap_or_default(),
+                    file_sha256: $message.file_sha256.clone().unwrap_or_default(),
+                    download_type: $type.download_type(),
+                    duration_secs: $duration_secs,
+                    file_enc_sha256: file_enc_sha256.clone(),
+                })
+            }
+            _ => None,
+        }
+    };
+}
 
 /// A media attached to a message.
 #[derive(Clone, Debug, Default)]
@@ -19,6 +47,8 @@ pub struct Media {
     pub caption: Option<String>,
     /// Whether this is an animated sticker (WebP animation).
     pub animated: bool,
+    /// Loudness along a voice message, as samples from 0 to 100.
+    pub waveform: Option<Vec<u8>>,
     /// MIME type of the data.
     pub mime_type: String,
     /// Download info for fetching full media (videos, documents).
@@ -28,6 +58,144 @@ pub struct Media {
 }
 
 impl Media {
+    /// Get the media attached to a received message, if it carries any
+    /// that's shown.
+    pub fn from_message(message: &wa::Message) -> Option<Self> {
+        message
+            .audio_message
+            .as_ref()
+            .map(|audio| Self::from_audio_message(audio))
+            .or_else(|| {
+                message
+                    .sticker_message
+                    .as_ref()
+                    .map(|sticker| Self::from_sticker_message(sticker))
+            })
+            .or_else(|| {
+                message
+                    .image_message
+                    .as_ref()
+                    .map(|image| Self::from_image_message(image))
+            })
+            .or_else(|| {
+                message
+                    .video_message
+                    .as_ref()
+                    .map(|video| Self::from_video_message(video))
+            })
+            .or_else(|| {
+                message
+                    .document_message
+                    .as_ref()
+                    .map(|document| Self::from_document_message(document))
+            })
+    }
+
+    /// Get the image attached to a received message, shown by its
+    /// thumbnail until it's downloaded.
+    pub fn from_image_message(image: &wa::message::ImageMessage) -> Self {
+        let mime_type = image
+            .mimetype
+            .clone()
+            .unwrap_or_else(|| MediaType::Image.guess_mime_type());
+        let downloadable = downloadable_media!(image, MediaType::Image, mime_type, None);
+
+        Self {
+            data: Arc::new(image.jpeg_thumbnail.clone().unwrap_or_default()),
+            width: image.width,
+            height: image.height,
+            r#type: MediaType::Image,
+            caption: image.caption.clone().filter(|c| !c.is_empty()),
+            mime_type,
+            downloadable,
+            ..Self::default()
+        }
+    }
+
+    /// Get the audio attached to a received message, with its waveform.
+    pub fn from_audio_message(audio: &wa::message::AudioMessage) -> Self {
+        let mime_type = audio
+            .mimetype
+            .clone()
+            .unwrap_or_else(|| MediaType::Audio.guess_mime_type());
+        let downloadable = downloadable_media!(audio, MediaType::Audio, mime_type, audio.seconds);
+
+        Self {
+            r#type: MediaType::Audio,
+            mime_type,
+            downloadable,
+            durations_secs: audio.seconds,
+            waveform: audio
+                .waveform
+                .clone()
+                .filter(|waveform| !waveform.is_empty()),
+            ..Self::default()
+        }
+    }
+
+    /// Get the video attached to a received message. Its thumbnail isn't
+    /// kept as its data, which is only the video once downloaded.
+    pub fn from_video_message(video: &wa::message::VideoMessage) -> Self {
+        let mime_type = video
+            .mimetype
+            .clone()
+            .unwrap_or_else(|| MediaType::Video.guess_mime_type());
+        let downloadable = downloadable_media!(video, MediaType::Video, mime_type, video.seconds);
+
+        Self {
+            width: video.width,
+            height: video.height,
+            r#type: MediaType::Video,
+            caption: video.caption.clone().filter(|c| !c.is_empty()),
+            mime_type,
+            downloadable,
+            durations_secs: video.seconds,
+            ..Self::default()
+        }
+    }
+
+    /// Get the document attached to a received message, captioned with its
+    /// file name when it has no caption.
+    pub fn from_document_message(document: &wa::message::DocumentMessage) -> Self {
+        let mime_type = document
+            .mimetype
+            .clone()
+            .unwrap_or_else(|| MediaType::Document.guess_mime_type());
+        let downloadable = downloadable_media!(document, MediaType::Document, mime_type, None);
+
+        Self {
+            r#type: MediaType::Document,
+            caption: document
+                .caption
+                .clone()
+                .filter(|c| !c.is_empty())
+                .or_else(|| document.file_name.clone().filter(|n| !n.is_empty())),
+            mime_type,
+            downloadable,
+            ..Self::default()
+        }
+    }
+
+    /// Get the sticker attached to a received message, shown by its preview.
+    pub fn from_sticker_message(sticker: &wa::message::StickerMessage) -> Self {
+        let mime_type = sticker
+            .mimetype
+            .clone()
+            .unwrap_or_else(|| MediaType::Sticker.guess_mime_type());
+        let downloadable = downloadable_media!(sticker, MediaType::Sticker, mime_type, None);
+
+        Self {
+            data: Arc::new(sticker.png_thumbnail.clone().unwrap_or_default()),
+            width: sticker.width,
+            height: sticker.height,
+            r#type: MediaType::Sticker,
+            animated: sticker.is_animated.unwrap_or_default(),
+            mime_type,
+            downloadable,
+            ..Self::default()
+        }
+    }
+
     /// Checks if this media can be played.
     pub fn can_play(&self) -> bool {
         self.has_data() || self.can_download()
@@ -42,8 +210,120 @@ impl Media {
     pub fn can_download(&self) -> bool {
         self.downloadable.is_some()
     }
+
+    /// Gets the size of the full media, if it can be downloaded.
+    pub fn download_size(&self) -> Option<u64> {
+        self.downloadable.as_ref().map(|media| media.file_length)
+    }
+
+    /// Checks if the full media should be fetched without asking, following
+    /// `policy`. Videos and documents are only fetched on request.
+    pub fn should_auto_download(&self, policy: &AutoDownload) -> bool {
+        let Some(size) = self.download_size() else {
+            return false;
+        };
+        if policy.paused {
+            return false;
+        }
+
+        match self.r#type {
+            MediaType::Image | MediaType::Sticker => policy.images && size <= policy.image_limit,
+            MediaType::Audio => policy.audio,
+            MediaType::Video | MediaType::Document => false,
+        }
+    }
+
+    /// Gets the extension of files with the media, from its MIME type.
+    pub fn extension(&self) -> String {
+        let mime_type = self
+            .mime_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+
+        if let Some((_, extension)) = MEDIA_EXTENSIONS.iter().find(|(mime, _)| *mime == mime_type) {
+            return (*extension).to_string();
+        }
+
+        // Types like "image/avif" name their extension, unlike "x-" or
+        // "vnd." ones.
+        match mime_type.split_once('/') {
+            Some((_, subtype))
+                if !subtype.is_empty()
+                    && subtype.len() <= 5
+                    && subtype.chars().all(|c| c.is_ascii_alphanumeric()) =>
+            {
+                subtype.to_string()
+            }
+            _ => self.r#type.extension().to_string(),
+        }
+    }
 }
 
+/// What media is fetched without asking, as set in the preferences.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AutoDownload {
+    /// Whether voice messages and audio are fetched.
+    pub audio: bool,
+    /// Whether photos and stickers up to `image_limit` are fetched.
+    pub images: bool,
+    /// Whether nothing is fetched for now, on a metered connection or while
+    /// low on disk space.
+    pub paused: bool,
+    /// Size in bytes above which photos are only fetched on request.
+    pub image_limit: u64,
+}
+
+impl AutoDownload {
+    /// Read the policy from the preferences and the state of the network.
+    pub fn from_settings(settings: &gio::Settings) -> Self {
+        let metered = gio::NetworkMonitor::default().is_network_metered();
+
+        Self {
+            audio: settings.boolean("auto-download-audio"),
+            images: settings.boolean("auto-download-images"),
+            paused: (metered && !settings.boolean("auto-download-metered"))
+                || is_low_on_space(&DATA_DIR),
+            image_limit: u64::from(settings.uint("auto-download-image-limit")) * 1024 * 1024,
+        }
+    }
+}
+
+/// Extensions of MIME types whose subtype isn't one.
+const MEDIA_EXTENSIONS: &[(&str, &str)] = &[
+    ("image/jpeg", "jpg"),
+    ("image/svg+xml", "svg"),
+    ("audio/mpeg", "mp3"),
+    ("audio/mp4", "m4a"),
+    ("audio/x-m4a", "m4a"),
+    ("video/3gpp", "3gp"),
+    ("video/quicktime", "mov"),
+    ("text/plain", "txt"),
+    ("application/msword", "doc"),
+    ("application/vnd.ms-excel", "xls"),
+    ("application/vnd.ms-powerpoint", "ppt"),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pptx",
+    ),
+    ("application/vnd.oasis.opendocument.text", "odt"),
+    ("application/vnd.oasis.opendocument.spreadsheet", "ods"),
+    ("application/x-zip-compressed", "zip"),
+    ("application/x-7z-compressed", "7z"),
+    ("application/x-rar-compressed", "rar"),
+    ("application/vnd.android.package-archive", "apk"),
+];
+
 /// Type of media.
 #[derive(Clone, Copy, Debug, Default)]
 pub enum MediaType {
@@ -72,6 +352,28 @@ impl MediaType {
         }
     }
 
+    /// Gets the usual extension of files with the media.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Audio => "ogg",
+            Self::Image => "jpg",
+            Self::Video => "mp4",
+            Self::Sticker => "webp",
+            Self::Document => "pdf",
+        }
+    }
+
+    /// Gets the type the media is downloaded as, deriving its keys.
+    pub fn download_type(self) -> DownloadMediaType {
+        match self {
+            Self::Audio => DownloadMediaType::Audio,
+            Self::Image => DownloadMediaType::Image,
+            Self::Video => DownloadMediaType::Video,
+            Self::Sticker => DownloadMediaType::Sticker,
+            Self::Document => DownloadMediaType::Document,
+        }
+    }
+
     /// Guess the mime type from the media.
     pub fn guess_mime_type(self) -> String {
         match self {
@@ -114,6 +416,8 @@ pub struct DownloadableMedia {
     pub direct_path: String,
     /// Expected file size in bytes.
     pub file_length: u64,
+    /// SHA256 of the decrypted file, checked once downloaded.
+    pub file_sha256: Vec<u8>,
     /// Download media type (for key derivation).
     pub download_type: DownloadMediaType,
     /// Duration in seconds (for video/audio).
@@ -121,3 +425,204 @@ pub struct DownloadableMedia {
     /// SHA256 of encrypted file (used for URL token).
     pub file_enc_sha256: Vec<u8>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn media(r#type: MediaType, mime_type: &str) -> Media {
+        Media {
+            r#type,
+            mime_type: mime_type.to_string(),
+            ..Media::default()
+        }
+    }
+
+    #[test]
+    fn image_is_shown_by_its_thumbnail_until_downloaded() {
+        let image = wa::message::ImageMessage {
+            jpeg_thumbnail: Some(b"jpeg".to_vec()),
+            caption: Some("Sunset".to_string()),
+            width: Some(1280),
+            height: Some(720),
+            media_key: Some(b"key".to_vec()),
+            direct_path: Some("/v/image".to_string()),
+            file_enc_sha256: Some(b"enc".to_vec()),
+            file_length: Some(300_000),
+            ..Default::default()
+        };
+
+        let media = Media::from_message(&wa::Message {
+            image_message: Some(image.into()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(media.r#type, MediaType::Image));
+        assert_eq!(media.data.as_slice(), b"jpeg");
+        assert_eq!(media.caption.as_deref(), Some("Sunset"));
+        assert_eq!((media.width, media.height), (Some(1280), Some(720)));
+        assert_eq!(media.mime_type, "image/jpeg");
+        assert_eq!(media.download_size(), Some(300_000));
+        assert!(matches!(
+            media.downloadable.unwrap().download_type,
+            DownloadMediaType::Image
+        ));
+    }
+
+    #[test]
+    fn videos_and_documents_have_no_data_until_downloaded() {
+        let video = Media::from_message(&wa::Message {
+            video_message: Some(
+                wa::message::VideoMessage {
+                    jpeg_thumbnail: Some(b"jpeg".to_vec()),
+                    seconds: Some(12),
+                    mimetype: Some("video/quicktime".to_string()),
+                    media_key: Some(b"key".to_vec()),
+                    direct_path: Some("/v/video".to_string()),
+                    file_enc_sha256: Some(b"enc".to_vec()),
+                    file_length: Some(5_000_000),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(video.r#type, MediaType::Video));
+        assert!(!video.has_data());
+        assert_eq!(video.durations_secs, Some(12));
+        assert_eq!(video.extension(), "mov");
+        assert_eq!(video.download_size(), Some(5_000_000));
+
+        let document = Media::from_message(&wa::Message {
+            document_message: Some(
+                wa::message::DocumentMessage {
+                    file_name: Some("report.pdf".to_string()),
+                    jpeg_thumbnail: Some(b"jpeg".to_vec()),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(document.r#type, MediaType::Document));
+        assert!(!document.has_data());
+        assert!(!document.can_download());
+        assert_eq!(document.caption.as_deref(), Some("report.pdf"));
+    }
+
+    #[test]
+    fn sticker_is_shown_by_its_preview() {
+        let sticker = wa::message::StickerMessage {
+            png_thumbnail: Some(b"png".to_vec()),
+            is_animated: Some(true),
+            width: Some(512),
+            height: Some(512),
+            ..Default::default()
+        };
+
+        let media = Media::from_sticker_message(&sticker);
+        assert!(matches!(media.r#type, MediaType::Sticker));
+        assert_eq!(media.data.as_slice(), b"png");
+        assert_eq!(media.mime_type, "image/webp");
+        assert!(media.animated);
+        assert_eq!(media.width, Some(512));
+        assert!(!media.can_download());
+    }
+
+    fn downloadable(r#type: MediaType, file_length: u64) -> Media {
+        Media {
+            r#type,
+            downloadable: Some(DownloadableMedia {
+                media_key: Vec::new(),
+                mime_type: r#type.guess_mime_type(),
+                direct_path: "/path".to_string(),
+                file_length,
+                file_sha256: Vec::new(),
+                download_type: r#type.download_type(),
+                duration_secs: None,
+                file_enc_sha256: Vec::new(),
+            }),
+            ..Media::default()
+        }
+    }
+
+    const POLICY: AutoDownload = AutoDownload {
+        audio: true,
+        images: true,
+        paused: false,
+        image_limit: 5 * 1024 * 1024,
+    };
+
+    #[test]
+    fn photos_are_fetched_up_to_the_size_limit() {
+        assert!(downloadable(MediaType::Image, 5 * 1024 * 1024).should_auto_download(&POLICY));
+        assert!(downloadable(MediaType::Sticker, 1024).should_auto_download(&POLICY));
+        assert!(!downloadable(MediaType::Image, 5 * 1024 * 1024 + 1).should_auto_download(&POLICY));
+        assert!(
+            !downloadable(MediaType::Image, 1024).should_auto_download(&AutoDownload {
+                images: false,
+                ..POLICY
+            })
+        );
+    }
+
+    #[test]
+    fn videos_and_documents_are_only_fetched_on_request() {
+        assert!(downloadable(MediaType::Audio, 1024).should_auto_download(&POLICY));
+        assert!(!downloadable(MediaType::Video, 1024).should_auto_download(&POLICY));
+        assert!(!downloadable(MediaType::Document, 1024).should_auto_download(&POLICY));
+    }
+
+    #[test]
+    fn nothing_is_fetched_while_paused_or_already_there() {
+        let paused = AutoDownload {
+            paused: true,
+            ..POLICY
+        };
+        assert!(!downloadable(MediaType::Audio, 1024).should_auto_download(&paused));
+        assert!(!downloadable(MediaType::Image, 1024).should_auto_download(&paused));
+        assert!(!media(MediaType::Image, "image/jpeg").should_auto_download(&POLICY));
+    }
+
+    #[test]
+    fn extension_follows_the_mime_type() {
+        assert_eq!(media(MediaType::Image, "image/png").extension(), "png");
+        assert_eq!(media(MediaType::Image, "image/jpeg").extension(), "jpg");
+        assert_eq!(
+            media(MediaType::Video, "video/quicktime").extension(),
+            "mov"
+        );
+        assert_eq!(
+            media(
+                MediaType::Document,
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            )
+            .extension(),
+            "docx"
+        );
+    }
+
+    #[test]
+    fn extension_ignores_parameters_and_case() {
+        assert_eq!(
+            media(MediaType::Audio, "audio/ogg; codecs=opus").extension(),
+            "ogg"
+        );
+        assert_eq!(media(MediaType::Audio, "Audio/MPEG").extension(), "mp3");
+    }
+
+    #[test]
+    fn extension_falls_back_to_the_media_type() {
+        assert_eq!(media(MediaType::Image, "").extension(), "jpg");
+        assert_eq!(
+            media(MediaType::Document, "application/x-unknown-thing").extension(),
+            "pdf"
+        );
+        assert_eq!(
+            media(MediaType::Document, "application/octet-stream").extension(),
+            "pdf"
+        );
+    }
+}
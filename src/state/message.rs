@@ -1,16 +1,25 @@
-use std::sync::Arc;
+use std::{cmp::Ordering, sync::Arc};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use indexmap::IndexMap;
 use uuid::Uuid;
 use wacore::types::presence::ReceiptType;
 use waproto::whatsapp as wa;
 
 use crate::{
-    state::{Chat, Media},
+    i18n,
+    state::{Chat, ChatKind, ContactCard, LinkPreview, Location, Media, Poll, Quote},
     store::Database,
+    utils::icon_or_fallback,
 };
 
+/// How long pins last for everyone, the default of the official apps.
+const PIN_DURATION_SECS: u32 = 7 * 24 * 60 * 60;
+
+/// How long a sent message can stay undelivered before hinting that its
+/// recipient may be unreachable.
+const UNCONFIRMED_DELIVERY: TimeDelta = TimeDelta::days(1);
+
 /// Maximum number of unique emoji reactions per message to prevent spam.
 const MAX_REACTIONS_PER_MESSAGE: usize = 50;
 
@@ -18,16 +27,32 @@ const MAX_REACTIONS_PER_MESSAGE: usize = 50;
 #[derive(Clone, Debug)]
 pub struct Message {
     pub db: Arc<Database>,
+    /// Kind of the message.
+    pub kind: Kind,
+    /// Poll attached to this message.
+    pub poll: Option<Poll>,
     /// Media attached to this message.
     pub media: Option<Media>,
+    /// Message this one replies to.
+    pub quote: Option<Quote>,
+    /// Preview of the link in the text.
+    pub preview: Option<LinkPreview>,
+    /// Whether the message was edited by its sender.
+    pub edited: bool,
     /// Actual state of the message.
     pub status: Status,
     /// Message text.
     pub content: String,
+    /// Whether the message was deleted for everyone.
+    pub revoked: bool,
     /// JID (Jabbed ID) - unique chat identifier.
     pub chat_jid: String,
+    /// Contact cards attached to this message.
+    pub contacts: Vec<ContactCard>,
     /// Local unique message identifier.
     pub local_id: Uuid,
+    /// Location attached to this message.
+    pub location: Option<Location>,
     /// Whether the message was sent by the current user.
     pub outgoing: bool,
     /// Reactions on this message (emoji -> [sender JID]).
@@ -63,6 +88,200 @@ impl Message {
             .map(|c| c.expect("Failed to get chat attached to message"))
     }
 
+    /// Checks if delivery of this message has gone unconfirmed for so long
+    /// that its recipient may be unreachable, such as when they blocked the
+    /// user. Only a guess, backed by the server having refused the recipient's
+    /// profile picture (`recipient_refused`).
+    pub fn delivery_unconfirmed(&self, recipient_refused: bool, now: DateTime<Utc>) -> bool {
+        self.outgoing
+            && recipient_refused
+            && self.status == Status::Sent
+            && now - self.timestamp >= UNCONFIRMED_DELIVERY
+    }
+
+    /// Get when a pin made for everyone at `pinned_at` ends.
+    pub fn pin_expiry(pinned_at: DateTime<Utc>) -> DateTime<Utc> {
+        pinned_at + TimeDelta::seconds(PIN_DURATION_SECS.into())
+    }
+
+    /// Build the protocol message pinning (or unpinning) this message for everyone.
+    pub fn to_pin_message(&self, pinned: bool) -> wa::Message {
+        let kind = if pinned {
+            wa::message::pin_in_chat_message::Type::PinForAll
+        } else {
+            wa::message::pin_in_chat_message::Type::UnpinForAll
+        };
+
+        wa::Message {
+            pin_in_chat_message: Some(
+                wa::message::PinInChatMessage {
+                    key: Some(self.to_key()),
+                    r#type: Some(kind as i32),
+                    sender_timestamp_ms: Some(Utc::now().timestamp_millis()),
+                }
+                .into(),
+            ),
+            message_context_info: pinned.then(|| wa::MessageContextInfo {
+                message_add_on_duration_in_secs: Some(PIN_DURATION_SECS),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Build the peer message asking the phone for up to `count` messages
+    /// sent in this chat before this one.
+    pub fn to_history_request(&self, count: i32) -> wa::Message {
+        use wa::message::{
+            PeerDataOperationRequestMessage, PeerDataOperationRequestType, ProtocolMessage,
+            peer_data_operation_request_message::HistorySyncOnDemandRequest,
+            protocol_message::Type,
+        };
+
+        wa::Message {
+            protocol_message: Some(
+                ProtocolMessage {
+                    r#type: Some(Type::PeerDataOperationRequestMessage as i32),
+                    peer_data_operation_request_message: Some(PeerDataOperationRequestMessage {
+                        peer_data_operation_request_type: Some(
+                            PeerDataOperationRequestType::HistorySyncOnDemand as i32,
+                        ),
+                        history_sync_on_demand_request: Some(HistorySyncOnDemandRequest {
+                            chat_jid: Some(self.chat_jid.clone()),
+                            oldest_msg_id: Some(self.server_id.clone()),
+                            oldest_msg_from_me: Some(self.outgoing),
+                            on_demand_msg_count: Some(count),
+                            oldest_msg_timestamp_ms: Some(self.timestamp.timestamp_millis()),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    /// Build the protocol message voting for `options` in this poll as
+    /// `voter_jid`, or `None` if it isn't a poll that can be voted in.
+    pub fn to_poll_vote_message(&self, voter_jid: &str, options: &[String]) -> Option<wa::Message> {
+        let vote = self.poll.as_ref()?.encrypt_vote(
+            &self.server_id,
+            &self.sender_jid,
+            voter_jid,
+            options,
+        )?;
+
+        Some(wa::Message {
+            poll_update_message: Some(
+                wa::message::PollUpdateMessage {
+                    poll_creation_message_key: Some(self.to_key()),
+                    vote: Some(vote),
+                    sender_timestamp_ms: Some(Utc::now().timestamp_millis()),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        })
+    }
+
+    /// Build the message reacting to this one with `emoji`, or removing our
+    /// reaction if it's empty.
+    pub fn to_reaction_message(&self, emoji: &str) -> wa::Message {
+        wa::Message {
+            reaction_message: Some(
+                wa::message::ReactionMessage {
+                    key: Some(self.to_key()),
+                    text: Some(emoji.to_string()),
+                    sender_timestamp_ms: Some(Utc::now().timestamp_millis()),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    /// Set the reaction of `sender_jid`, replacing their previous one, or
+    /// remove it with `None`. New emoji are ignored past the limit per message.
+    pub fn set_reaction(&mut self, sender_jid: &str, emoji: Option<&str>) {
+        for senders in self.reactions.values_mut() {
+            senders.retain(|jid| jid != sender_jid);
+        }
+        self.reactions.retain(|_, senders| !senders.is_empty());
+
+        let Some(emoji) = emoji.filter(|emoji| !emoji.is_empty()) else {
+            return;
+        };
+        if !self.reactions.contains_key(emoji) && self.reactions.len() >= MAX_REACTIONS_PER_MESSAGE
+        {
+            return;
+        }
+        self.reactions
+            .entry(emoji.to_string())
+            .or_default()
+            .push(sender_jid.to_string());
+    }
+
+    /// Build the key other messages refer to this one by.
+    fn to_key(&self) -> wa::MessageKey {
+        wa::MessageKey {
+            remote_jid: Some(self.chat_jid.clone()),
+            from_me: Some(self.outgoing),
+            id: Some(self.server_id.clone()),
+            participant: (!self.outgoing && ChatKind::from_jid(&self.chat_jid) == ChatKind::Group)
+                .then(|| self.sender_jid.clone()),
+        }
+    }
+
+    /// Build the message sending this one as a reply to `quote`.
+    pub fn to_reply_message(&self, quote: &Quote) -> wa::Message {
+        wa::Message {
+            extended_text_message: Some(
+                wa::message::ExtendedTextMessage {
+                    context_info: Some(
+                        wa::ContextInfo {
+                            stanza_id: Some(quote.id.clone()),
+                            participant: Some(quote.sender_jid.clone())
+                                .filter(|jid| !jid.is_empty()),
+                            quoted_message: Some(
+                                wa::Message {
+                                    conversation: Some(quote.content.clone())
+                                        .filter(|content| !content.is_empty()),
+                                    ..Default::default()
+                                }
+                                .into(),
+                            ),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    ..self.to_extended_text()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    /// Build the extended text of the message, carrying its link preview.
+    fn to_extended_text(&self) -> wa::message::ExtendedTextMessage {
+        let preview = self.preview.as_ref();
+        wa::message::ExtendedTextMessage {
+            text: Some(self.content.clone()),
+            matched_text: preview.map(|preview| preview.url.clone()),
+            title: preview.and_then(|preview| preview.title.clone()),
+            description: preview.and_then(|preview| preview.description.clone()),
+            jpeg_thumbnail: preview
+                .and_then(|preview| preview.thumbnail.as_ref())
+                .map(|thumbnail| thumbnail.as_ref().clone()),
+            ..Default::default()
+        }
+    }
+
     /// Mark this message as read locally.
     pub async fn mark_read(&mut self) -> Result<(), libsql::Error> {
         if self.status == Status::Read {
@@ -76,6 +295,14 @@ impl Message {
 
 impl From<Message> for wa::Message {
     fn from(value: Message) -> Self {
+        // Only extended text carries a link preview.
+        if value.preview.is_some() {
+            return Self {
+                extended_text_message: Some(value.to_extended_text().into()),
+                ..Default::default()
+            };
+        }
+
         let conversation = if value.content.is_empty() {
             None
         } else {
@@ -95,9 +322,86 @@ impl From<&Message> for wa::Message {
     }
 }
 
-/// Represents a message status.
+/// Represents a message kind.
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 #[repr(u8)]
+pub enum Kind {
+    /// A message sent by a chat member.
+    #[default]
+    Regular,
+    /// A service event, e.g. someone joined the group.
+    Service,
+    /// A greeting or away message sent automatically by a business.
+    Automated,
+}
+
+impl Kind {
+    /// Get the kind of a received or synced message, telling apart the
+    /// replies businesses send automatically, whatever they carry.
+    pub fn from_received(message: &wa::Message) -> Self {
+        if is_automated_greeting(message) {
+            Self::Automated
+        } else {
+            Self::Regular
+        }
+    }
+}
+
+/// Checks if a message was sent as an automated greeting, from the context
+/// carried by its content, whatever it is.
+fn is_automated_greeting(message: &wa::Message) -> bool {
+    [
+        message
+            .extended_text_message
+            .as_ref()
+            .and_then(|content| content.context_info.as_ref()),
+        message
+            .image_message
+            .as_ref()
+            .and_then(|content| content.context_info.as_ref()),
+        message
+            .video_message
+            .as_ref()
+            .and_then(|content| content.context_info.as_ref()),
+        message
+            .audio_message
+            .as_ref()
+            .and_then(|content| content.context_info.as_ref()),
+        message
+            .document_message
+            .as_ref()
+            .and_then(|content| content.context_info.as_ref()),
+        message
+            .sticker_message
+            .as_ref()
+            .and_then(|content| content.context_info.as_ref()),
+        message
+            .location_message
+            .as_ref()
+            .and_then(|content| content.context_info.as_ref()),
+        message
+            .contact_message
+            .as_ref()
+            .and_then(|content| content.context_info.as_ref()),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|context| context.automated_greeting_message_shown == Some(true))
+}
+
+impl From<i32> for Kind {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => Self::Service,
+            2 => Self::Automated,
+            _ => Self::default(),
+        }
+    }
+}
+
+/// Represents a message status.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
 pub enum Status {
     /// The message was sent, but no one has received it yet.
     #[default]
@@ -115,17 +419,67 @@ pub enum Status {
 }
 
 impl Status {
+    /// Gets the status a message received in a chat is stored with. Stray
+    /// messages still reaching a group the user left aren't unread.
+    pub fn received(outgoing: bool, left: bool) -> Self {
+        if outgoing || left {
+            Self::Read
+        } else {
+            Self::Sent
+        }
+    }
+
+    /// Gets a display label.
+    pub fn display_label(self) -> String {
+        match self {
+            Self::Sending => i18n!("Sending"),
+            Self::Sent => i18n!("Sent"),
+            Self::Delivered => i18n!("Delivered"),
+            Self::Read => i18n!("Read"),
+            Self::Played => i18n!("Played"),
+            Self::Failed => i18n!("Not sent"),
+        }
+    }
+
     /// Get the corresponding status icon name.
     pub fn icon_name(&self) -> &str {
         match self {
-            Self::Sent => "check-round-outline-symbolic",
-            Self::Read | Self::Played | Self::Delivered => "check-round-outline2-symbolic",
-            Self::Failed => "exclamation-mark-symbolic",
-            Self::Sending => "clock-alt-symbolic",
+            Self::Sent => icon_or_fallback("check-round-outline-symbolic", "emblem-ok-symbolic"),
+            Self::Read | Self::Played | Self::Delivered => {
+                icon_or_fallback("check-round-outline2-symbolic", "emblem-default-symbolic")
+            }
+            Self::Failed => icon_or_fallback("exclamation-mark-symbolic", "dialog-error-symbolic"),
+            Self::Sending => icon_or_fallback("clock-alt-symbolic", "appointment-soon-symbolic"),
+        }
+    }
+
+    /// How far along the message is, failing being the least as a later
+    /// receipt proves it went through.
+    fn progress(self) -> u8 {
+        match self {
+            Self::Failed => 0,
+            Self::Sending => 1,
+            Self::Sent => 2,
+            Self::Delivered => 3,
+            Self::Read => 4,
+            Self::Played => 5,
         }
     }
 }
 
+// Ordered by progress, so receipts arriving out of order keep the furthest.
+impl Ord for Status {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.progress().cmp(&other.progress())
+    }
+}
+
+impl PartialOrd for Status {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl From<i32> for Status {
     fn from(value: i32) -> Self {
         match value {
@@ -140,6 +494,59 @@ impl From<i32> for Status {
     }
 }
 
+/// Who revokes or edits a message, to tell whether they may.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Actor {
+    /// The user, from this or another device, by any of their JIDs.
+    User(Vec<String>),
+    /// Someone else, by any of their JIDs.
+    Contact(Vec<String>),
+}
+
+impl Actor {
+    /// Get someone else by their JID and its alternate (PN or LID), which
+    /// are also kept without the device part as synced messages have none.
+    pub fn contact(jid: &str, alt_jid: Option<&str>) -> Self {
+        let mut jids = Vec::new();
+        for jid in [Some(jid), alt_jid].into_iter().flatten() {
+            let bare = match jid.split_once('@') {
+                Some((user, server)) => match user.split_once(':') {
+                    Some((user, _device)) => format!("{user}@{server}"),
+                    None => jid.to_string(),
+                },
+                None => jid.to_string(),
+            };
+            for jid in [jid.to_string(), bare] {
+                if !jid.is_empty() && !jids.contains(&jid) {
+                    jids.push(jid);
+                }
+            }
+        }
+
+        Self::Contact(jids)
+    }
+
+    /// JIDs the actor is known by.
+    pub fn jids(&self) -> &[String] {
+        match self {
+            Self::User(jids) | Self::Contact(jids) => jids,
+        }
+    }
+
+    /// Whether the message was sent by the actor. Only the other side can
+    /// send incoming messages in private chats, while groups go by sender.
+    pub fn sent(&self, message: &Message) -> bool {
+        match self {
+            Self::User(_) => message.outgoing,
+            Self::Contact(jids) => {
+                !message.outgoing
+                    && (ChatKind::from_jid(&message.chat_jid) != ChatKind::Group
+                        || jids.contains(&message.sender_jid))
+            }
+        }
+    }
+}
+
 impl TryFrom<ReceiptType> for Status {
     type Error = String;
 
@@ -155,3 +562,216 @@ impl TryFrom<ReceiptType> for Status {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{block_on, database, message};
+
+    fn automated_context() -> Option<wa::ContextInfo> {
+        Some(wa::ContextInfo {
+            automated_greeting_message_shown: Some(true),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn history_request_is_anchored_at_the_message() {
+        block_on(async {
+            let db = database().await;
+            let mut oldest = message(&db, "chat@s.whatsapp.net", "alice", "oldest");
+            oldest.outgoing = true;
+
+            let request = oldest.to_history_request(50);
+            let request = request
+                .protocol_message
+                .unwrap()
+                .peer_data_operation_request_message
+                .unwrap()
+                .history_sync_on_demand_request
+                .unwrap();
+            assert_eq!(request.chat_jid.as_deref(), Some("chat@s.whatsapp.net"));
+            assert_eq!(request.oldest_msg_id.as_deref(), Some("oldest"));
+            assert_eq!(request.oldest_msg_from_me, Some(true));
+            assert_eq!(request.on_demand_msg_count, Some(50));
+            assert_eq!(
+                request.oldest_msg_timestamp_ms,
+                Some(oldest.timestamp.timestamp_millis())
+            );
+        });
+    }
+
+    #[test]
+    fn automated_replies_are_told_apart_whatever_they_carry() {
+        let text = wa::Message {
+            extended_text_message: Some(
+                wa::message::ExtendedTextMessage {
+                    text: Some("Hi! We'll answer soon".into()),
+                    context_info: automated_context().map(Into::into),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        };
+        let image = wa::Message {
+            image_message: Some(
+                wa::message::ImageMessage {
+                    context_info: automated_context().map(Into::into),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        };
+        let document = wa::Message {
+            document_message: Some(
+                wa::message::DocumentMessage {
+                    context_info: automated_context().map(Into::into),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        };
+
+        assert_eq!(Kind::from_received(&text), Kind::Automated);
+        assert_eq!(Kind::from_received(&image), Kind::Automated);
+        assert_eq!(Kind::from_received(&document), Kind::Automated);
+    }
+
+    #[test]
+    fn other_messages_are_regular() {
+        let plain = wa::Message {
+            conversation: Some("Hello".into()),
+            ..Default::default()
+        };
+        let image = wa::Message {
+            image_message: Some(wa::message::ImageMessage::default().into()),
+            ..Default::default()
+        };
+
+        assert_eq!(Kind::from_received(&plain), Kind::Regular);
+        assert_eq!(Kind::from_received(&image), Kind::Regular);
+    }
+
+    #[test]
+    fn delivery_is_unconfirmed_after_a_day_sent_to_a_refusing_recipient() {
+        let db = block_on(database());
+        let now = Utc::now();
+        let mut msg = message(&db, "alice", "me", "1");
+        msg.outgoing = true;
+        msg.timestamp = now - TimeDelta::hours(25);
+
+        assert!(msg.delivery_unconfirmed(true, now));
+        // The avatar was never refused.
+        assert!(!msg.delivery_unconfirmed(false, now));
+
+        msg.status = Status::Delivered;
+        assert!(!msg.delivery_unconfirmed(true, now));
+
+        msg.status = Status::Sent;
+        msg.timestamp = now - TimeDelta::hours(2);
+        assert!(!msg.delivery_unconfirmed(true, now));
+
+        msg.timestamp = now - TimeDelta::hours(25);
+        msg.outgoing = false;
+        assert!(!msg.delivery_unconfirmed(true, now));
+    }
+
+    #[test]
+    fn reaction_replaces_the_previous_one() {
+        let db = block_on(database());
+        let mut msg = message(&db, "chat", "alice", "1");
+        msg.set_reaction("bob", Some("👍"));
+        msg.set_reaction("carol", Some("👍"));
+        msg.set_reaction("bob", Some("❤️"));
+
+        assert_eq!(msg.reactions["👍"], ["carol"]);
+        assert_eq!(msg.reactions["❤️"], ["bob"]);
+    }
+
+    #[test]
+    fn removed_reaction_drops_empty_emoji() {
+        let db = block_on(database());
+        let mut msg = message(&db, "chat", "alice", "1");
+        msg.set_reaction("bob", Some("👍"));
+        msg.set_reaction("bob", None);
+        msg.set_reaction("carol", Some("❤️"));
+        msg.set_reaction("carol", Some(""));
+
+        assert!(msg.reactions.is_empty());
+    }
+
+    #[test]
+    fn new_emoji_are_ignored_past_the_limit() {
+        let db = block_on(database());
+        let mut msg = message(&db, "chat", "alice", "1");
+        for i in 0..MAX_REACTIONS_PER_MESSAGE {
+            msg.set_reaction(&format!("user{i}"), Some(&format!("emoji{i}")));
+        }
+        msg.set_reaction("late", Some("new"));
+        msg.set_reaction("late", Some("emoji0"));
+
+        assert_eq!(msg.reactions.len(), MAX_REACTIONS_PER_MESSAGE);
+        assert_eq!(msg.reactions["emoji0"], ["user0", "late"]);
+    }
+
+    #[test]
+    fn reaction_message_refers_to_the_message() {
+        let db = block_on(database());
+        let msg = message(&db, "group@g.us", "alice@s.whatsapp.net", "1");
+        let reaction = msg.to_reaction_message("👍").reaction_message.unwrap();
+        let key = reaction.key.unwrap();
+
+        assert_eq!(reaction.text.as_deref(), Some("👍"));
+        assert_eq!(key.id.as_deref(), Some("1"));
+        assert_eq!(key.participant.as_deref(), Some("alice@s.whatsapp.net"));
+    }
+
+    #[test]
+    fn contact_is_known_without_the_device() {
+        let actor = Actor::contact("123:4@s.whatsapp.net", Some("456@lid"));
+
+        assert_eq!(
+            actor.jids(),
+            ["123:4@s.whatsapp.net", "123@s.whatsapp.net", "456@lid"]
+        );
+    }
+
+    #[test]
+    fn only_the_sender_sent_a_group_message() {
+        let db = block_on(database());
+        let msg = message(&db, "group@g.us", "alice@s.whatsapp.net", "1");
+
+        assert!(Actor::contact("alice@s.whatsapp.net", None).sent(&msg));
+        assert!(Actor::contact("alice:2@s.whatsapp.net", None).sent(&msg));
+        assert!(!Actor::contact("bob@s.whatsapp.net", None).sent(&msg));
+        assert!(!Actor::User(vec![String::from("alice@s.whatsapp.net")]).sent(&msg));
+    }
+
+    #[test]
+    fn other_side_sent_incoming_private_messages() {
+        let db = block_on(database());
+        let mut msg = message(&db, "alice@s.whatsapp.net", "alice@lid", "1");
+
+        assert!(Actor::contact("alice@s.whatsapp.net", None).sent(&msg));
+        assert!(!Actor::User(Vec::new()).sent(&msg));
+
+        msg.outgoing = true;
+        assert!(!Actor::contact("alice@s.whatsapp.net", None).sent(&msg));
+        assert!(Actor::User(Vec::new()).sent(&msg));
+    }
+
+    #[test]
+    fn messages_after_leaving_arent_unread_until_rejoining() {
+        assert_eq!(Status::received(false, false), Status::Sent);
+        assert_eq!(Status::received(true, false), Status::Read);
+
+        // Left the group.
+        assert_eq!(Status::received(false, true), Status::Read);
+
+        // Rejoined it.
+        assert_eq!(Status::received(false, false), Status::Sent);
+    }
+}
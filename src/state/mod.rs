@@ -1,7 +1,27 @@
 mod chat;
+mod contact_card;
+mod content;
+mod gallery;
+mod group_event;
+mod location;
 mod media;
 mod message;
+mod poll;
+mod quote;
+mod status;
+mod text;
 
-pub use chat::Chat;
-pub use media::{Media, MediaType};
-pub use message::{Message as ChatMessage, Status as MessageStatus};
+pub use chat::{Chat, ChatKind, Membership, STATUS_JID, is_status_jid};
+pub use contact_card::ContactCard;
+pub use content::MessageContent;
+pub use gallery::{ChatDocument, ChatLink};
+pub use group_event::GroupEvent;
+pub use location::Location;
+pub use media::{AutoDownload, DownloadableMedia, Media, MediaType};
+pub use message::{
+    Actor as MessageActor, Kind as MessageKind, Message as ChatMessage, Status as MessageStatus,
+};
+pub use poll::Poll;
+pub use quote::Quote;
+pub use status::StatusUpdate;
+pub use text::{LinkPreview, TextContent};
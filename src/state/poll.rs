@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use aes_gcm::{
+    Aes256Gcm, KeyInit,
+    aead::{Aead, Payload},
+};
+use hkdf::Hkdf;
+use prost::Message as _;
+use sha2::{Digest, Sha256};
+use waproto::whatsapp as wa;
+
+/// Use case the key of a vote is derived for from the poll's secret.
+const VOTE_USE_CASE: &str = "Poll Vote";
+/// Length of the nonce a vote is encrypted with.
+const VOTE_IV_LEN: usize = 12;
+
+/// A poll attached to a message.
+#[derive(Clone, Debug, Default)]
+pub struct Poll {
+    /// Selected options mapped by voter JID.
+    pub votes: HashMap<String, Vec<String>>,
+    /// Secret used to encrypt the votes.
+    pub secret: Option<Vec<u8>>,
+    /// Options to choose from, in order.
+    pub options: Vec<String>,
+    /// Question being asked.
+    pub question: String,
+    /// Whether more than one option can be selected.
+    pub multi_select: bool,
+}
+
+impl Poll {
+    /// Parse the poll from a poll creation message, if it's one.
+    pub fn from_message(message: &wa::Message) -> Option<Self> {
+        let creation = message
+            .poll_creation_message
+            .as_deref()
+            .or(message.poll_creation_message_v2.as_deref())
+            .or(message.poll_creation_message_v3.as_deref())?;
+
+        let options = creation
+            .options
+            .iter()
+            .filter_map(|o| o.option_name.clone())
+            .collect::<Vec<_>>();
+        if options.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            votes: HashMap::new(),
+            secret: message
+                .message_context_info
+                .as_ref()
+                .and_then(|c| c.message_secret.clone()),
+            options,
+            question: creation.name.clone().unwrap_or_default(),
+            // A count of 0 means any number of options can be selected.
+            multi_select: creation.selectable_options_count.unwrap_or_default() != 1,
+        })
+    }
+
+    /// Get how many voters selected an option.
+    pub fn vote_count(&self, option: &str) -> usize {
+        self.votes
+            .values()
+            .filter(|selected| selected.iter().any(|o| o == option))
+            .count()
+    }
+
+    /// Get how many voters selected at least one option.
+    pub fn voter_count(&self) -> usize {
+        self.votes.values().filter(|s| !s.is_empty()).count()
+    }
+
+    /// Toggle an option for a voter, returning their new selection.
+    pub fn toggle_vote(&mut self, voter_jid: &str, option: &str) -> Vec<String> {
+        let selected = self.votes.entry(voter_jid.to_string()).or_default();
+
+        if let Some(position) = selected.iter().position(|o| o == option) {
+            selected.remove(position);
+        } else if self.multi_select {
+            selected.push(option.to_string());
+        } else {
+            *selected = vec![option.to_string()];
+        }
+
+        selected.clone()
+    }
+
+    /// Encrypt the vote of `voter_jid` for `options` in this poll, sent with
+    /// ID `poll_id` by `creator_jid`, or `None` without the poll's secret.
+    pub fn encrypt_vote(
+        &self,
+        poll_id: &str,
+        creator_jid: &str,
+        voter_jid: &str,
+        options: &[String],
+    ) -> Option<wa::message::PollEncValue> {
+        let (key, aad) = self.vote_key(poll_id, creator_jid, voter_jid)?;
+        let vote = wa::message::PollVoteMessage {
+            selected_options: options.iter().map(|o| option_hash(o)).collect(),
+        };
+
+        let iv: [u8; VOTE_IV_LEN] = rand::random();
+        let payload = Aes256Gcm::new_from_slice(&key)
+            .ok()?
+            .encrypt(
+                (&iv[..]).into(),
+                Payload {
+                    msg: &vote.encode_to_vec(),
+                    aad: &aad,
+                },
+            )
+            .ok()?;
+
+        Some(wa::message::PollEncValue {
+            enc_payload: Some(payload),
+            enc_iv: Some(iv.to_vec()),
+        })
+    }
+
+    /// Decrypt the vote of `voter_jid` in this poll, sent with ID `poll_id`
+    /// by `creator_jid`, returning the selected options.
+    pub fn decrypt_vote(
+        &self,
+        poll_id: &str,
+        creator_jid: &str,
+        voter_jid: &str,
+        vote: &wa::message::PollEncValue,
+    ) -> Option<Vec<String>> {
+        let (key, aad) = self.vote_key(poll_id, creator_jid, voter_jid)?;
+        let iv = vote
+            .enc_iv
+            .as_deref()
+            .filter(|iv| iv.len() == VOTE_IV_LEN)?;
+
+        let plaintext = Aes256Gcm::new_from_slice(&key)
+            .ok()?
+            .decrypt(
+                iv.into(),
+                Payload {
+                    msg: vote.enc_payload.as_deref()?,
+                    aad: &aad,
+                },
+            )
+            .ok()?;
+        let vote = wa::message::PollVoteMessage::decode(plaintext.as_slice()).ok()?;
+
+        // Options are sent as their hashes, in the order of the poll.
+        Some(
+            self.options
+                .iter()
+                .filter(|o| vote.selected_options.contains(&option_hash(o)))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Derive the key and additional data of a vote from the poll's secret.
+    fn vote_key(
+        &self,
+        poll_id: &str,
+        creator_jid: &str,
+        voter_jid: &str,
+    ) -> Option<([u8; 32], Vec<u8>)> {
+        let secret = self.secret.as_deref()?;
+        let creator_jid = without_device(creator_jid);
+        let voter_jid = without_device(voter_jid);
+
+        let info = format!("{poll_id}{creator_jid}{voter_jid}{VOTE_USE_CASE}");
+        let mut key = [0; 32];
+        Hkdf::<Sha256>::new(None, secret)
+            .expand(info.as_bytes(), &mut key)
+            .ok()?;
+
+        Some((key, format!("{poll_id}\0{voter_jid}").into_bytes()))
+    }
+}
+
+/// Hash an option the way votes refer to it.
+fn option_hash(option: &str) -> Vec<u8> {
+    Sha256::digest(option.as_bytes()).to_vec()
+}
+
+/// Strip the device from a JID, as votes are keyed by user.
+fn without_device(jid: &str) -> String {
+    match jid.split_once('@') {
+        Some((user, server)) => {
+            let user = user.split_once(':').map_or(user, |(user, _)| user);
+            format!("{user}@{server}")
+        }
+        None => jid.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLL_ID: &str = "3EB0C767D26A1D8A";
+    const CREATOR: &str = "5511999990000@s.whatsapp.net";
+    const VOTER: &str = "5511888880000@s.whatsapp.net";
+
+    fn poll() -> Poll {
+        Poll {
+            secret: Some(vec![7; 32]),
+            options: vec![
+                String::from("Yes"),
+                String::from("No"),
+                String::from("Maybe"),
+            ],
+            question: String::from("Lunch?"),
+            multi_select: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn vote_round_trip() {
+        let poll = poll();
+        let selected = vec![String::from("Maybe"), String::from("Yes")];
+        let vote = poll
+            .encrypt_vote(POLL_ID, CREATOR, VOTER, &selected)
+            .expect("Failed to encrypt vote");
+
+        // Selected options come back in the order of the poll.
+        assert_eq!(
+            poll.decrypt_vote(POLL_ID, CREATOR, VOTER, &vote),
+            Some(vec![String::from("Yes"), String::from("Maybe")])
+        );
+    }
+
+    #[test]
+    fn empty_vote_round_trip() {
+        let poll = poll();
+        let vote = poll.encrypt_vote(POLL_ID, CREATOR, VOTER, &[]).unwrap();
+        assert_eq!(
+            poll.decrypt_vote(POLL_ID, CREATOR, VOTER, &vote),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn vote_key_ignores_devices() {
+        let poll = poll();
+        let vote = poll
+            .encrypt_vote(
+                POLL_ID,
+                CREATOR,
+                "5511888880000:12@s.whatsapp.net",
+                &[String::from("No")],
+            )
+            .unwrap();
+        assert_eq!(
+            poll.decrypt_vote(POLL_ID, "5511999990000:3@s.whatsapp.net", VOTER, &vote),
+            Some(vec![String::from("No")])
+        );
+    }
+
+    #[test]
+    fn vote_of_someone_else_fails() {
+        let poll = poll();
+        let vote = poll
+            .encrypt_vote(POLL_ID, CREATOR, VOTER, &[String::from("No")])
+            .unwrap();
+
+        assert_eq!(poll.decrypt_vote(POLL_ID, CREATOR, CREATOR, &vote), None);
+        assert_eq!(poll.decrypt_vote("OTHER", CREATOR, VOTER, &vote), None);
+        assert_eq!(
+            Poll {
+                secret: Some(vec![8; 32]),
+                ..poll()
+            }
+            .decrypt_vote(POLL_ID, CREATOR, VOTER, &vote),
+            None
+        );
+    }
+
+    #[test]
+    fn vote_needs_secret() {
+        let poll = Poll {
+            secret: None,
+            ..poll()
+        };
+        assert!(poll.encrypt_vote(POLL_ID, CREATOR, VOTER, &[]).is_none());
+    }
+
+    #[test]
+    fn toggle_single_and_multi_select() {
+        let mut poll = poll();
+        assert_eq!(poll.toggle_vote(VOTER, "Yes"), ["Yes"]);
+        assert_eq!(poll.toggle_vote(VOTER, "No"), ["Yes", "No"]);
+        assert_eq!(poll.toggle_vote(VOTER, "Yes"), ["No"]);
+
+        poll.multi_select = false;
+        assert_eq!(poll.toggle_vote(VOTER, "Maybe"), ["Maybe"]);
+        assert_eq!(poll.toggle_vote(VOTER, "Maybe"), Vec::<String>::new());
+        assert_eq!(poll.voter_count(), 0);
+    }
+}
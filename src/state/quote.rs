@@ -0,0 +1,62 @@
+use waproto::whatsapp as wa;
+
+use crate::state::ChatMessage;
+
+/// A message quoted by a reply.
+#[derive(Clone, Debug, Default)]
+pub struct Quote {
+    /// Server identifier of the quoted message.
+    pub id: String,
+    /// Text of the quoted message.
+    pub content: String,
+    /// Whether the quoted message was sent by the current user.
+    pub outgoing: bool,
+    /// Who sent the quoted message.
+    pub sender_jid: String,
+    /// Display name of who sent the quoted message, if known.
+    pub sender_name: Option<String>,
+}
+
+impl Quote {
+    /// Parse the message quoted by a reply, if it's one.
+    pub fn from_message(message: &wa::Message) -> Option<Self> {
+        let context = message
+            .extended_text_message
+            .as_ref()
+            .and_then(|text| text.context_info.as_ref())?;
+        let id = context.stanza_id.clone().filter(|id| !id.is_empty())?;
+
+        let content = context
+            .quoted_message
+            .as_ref()
+            .and_then(|quoted| {
+                quoted.conversation.clone().or_else(|| {
+                    quoted
+                        .extended_text_message
+                        .as_ref()
+                        .and_then(|text| text.text.clone())
+                })
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            id,
+            content,
+            outgoing: false,
+            sender_jid: context.participant.clone().unwrap_or_default(),
+            sender_name: None,
+        })
+    }
+}
+
+impl From<&ChatMessage> for Quote {
+    fn from(value: &ChatMessage) -> Self {
+        Self {
+            id: value.server_id.clone(),
+            content: value.content.clone(),
+            outgoing: value.outgoing,
+            sender_jid: value.sender_jid.clone(),
+            sender_name: value.sender_name.clone(),
+        }
+    }
+}
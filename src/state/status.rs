@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use waproto::whatsapp as wa;
+
+use crate::state::{MediaType, TextContent};
+
+/// A status update posted by a contact.
+#[derive(Clone, Debug)]
+pub struct StatusUpdate {
+    /// Server unique message identifier.
+    pub id: String,
+    /// Type of the posted media, if it isn't a text status.
+    pub media: Option<MediaType>,
+    /// Whether the status has been viewed.
+    pub viewed: bool,
+    /// Status text, or the caption of its media.
+    pub content: String,
+    /// JPEG preview of the posted image or video.
+    pub thumbnail: Option<Arc<Vec<u8>>>,
+    /// When the status was posted.
+    pub timestamp: DateTime<Utc>,
+    /// Poster identifier.
+    pub sender_jid: String,
+    /// Poster's display name (push name).
+    pub sender_name: Option<String>,
+}
+
+impl StatusUpdate {
+    /// How long a status update can be viewed after being posted.
+    pub const LIFETIME: TimeDelta = TimeDelta::days(1);
+
+    /// Parse a status update from a message posted to the status broadcast,
+    /// if it has text, an image or a video.
+    pub fn from_message(
+        id: String,
+        message: &wa::Message,
+        sender_jid: String,
+        sender_name: Option<String>,
+        timestamp: DateTime<Utc>,
+    ) -> Option<Self> {
+        let (content, media, thumbnail) = if let Some(text) = TextContent::from_message(message) {
+            (text.text, None, None)
+        } else if let Some(ref image) = message.image_message {
+            (
+                image.caption.clone(),
+                Some(MediaType::Image),
+                image.jpeg_thumbnail.clone(),
+            )
+        } else if let Some(ref video) = message.video_message {
+            (
+                video.caption.clone(),
+                Some(MediaType::Video),
+                video.jpeg_thumbnail.clone(),
+            )
+        } else {
+            return None;
+        };
+
+        Some(Self {
+            id,
+            viewed: false,
+            content: content.unwrap_or_default(),
+            timestamp,
+            sender_jid,
+            sender_name,
+            media,
+            thumbnail: thumbnail.filter(|t| !t.is_empty()).map(Arc::new),
+        })
+    }
+
+    /// Get when the status update stops being shown.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.timestamp + Self::LIFETIME
+    }
+
+    /// Checks if the status update has stopped being shown by `now`.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at() <= now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(message: &wa::Message) -> Option<StatusUpdate> {
+        StatusUpdate::from_message(
+            "id".into(),
+            message,
+            "alice@s.whatsapp.net".into(),
+            None,
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn text_status_has_no_media() {
+        let message = wa::Message {
+            conversation: Some("Hello".into()),
+            ..Default::default()
+        };
+
+        let status = parse(&message).unwrap();
+        assert_eq!(status.content, "Hello");
+        assert!(status.media.is_none());
+        assert!(status.thumbnail.is_none());
+    }
+
+    #[test]
+    fn image_status_keeps_its_caption_and_preview() {
+        let message = wa::Message {
+            image_message: Some(
+                wa::message::ImageMessage {
+                    caption: Some("Beach".into()),
+                    jpeg_thumbnail: Some(b"jpeg".to_vec()),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        };
+
+        let status = parse(&message).unwrap();
+        assert_eq!(status.content, "Beach");
+        assert!(matches!(status.media, Some(MediaType::Image)));
+        assert_eq!(status.thumbnail.as_deref(), Some(&b"jpeg".to_vec()));
+    }
+
+    #[test]
+    fn video_status_without_caption_or_preview() {
+        let message = wa::Message {
+            video_message: Some(
+                wa::message::VideoMessage {
+                    jpeg_thumbnail: Some(Vec::new()),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        };
+
+        let status = parse(&message).unwrap();
+        assert!(status.content.is_empty());
+        assert!(matches!(status.media, Some(MediaType::Video)));
+        assert!(status.thumbnail.is_none());
+    }
+
+    #[test]
+    fn other_messages_are_not_statuses() {
+        assert!(parse(&wa::Message::default()).is_none());
+    }
+
+    #[test]
+    fn status_expires_a_day_after_being_posted() {
+        let mut status = parse(&wa::Message {
+            conversation: Some("Hello".into()),
+            ..Default::default()
+        })
+        .unwrap();
+        let now = Utc::now();
+
+        status.timestamp = now - TimeDelta::hours(23);
+        assert!(!status.is_expired(now));
+
+        status.timestamp = now - TimeDelta::hours(24);
+        assert!(status.is_expired(now));
+    }
+}
@@ -0,0 +1,267 @@
+use std::sync::{Arc, LazyLock};
+
+use regex::Regex;
+use waproto::whatsapp as wa;
+
+use crate::state::Quote;
+
+/// Text of a message, sent either plain or extended with a reply, mentions
+/// or a link preview.
+#[derive(Clone, Debug, Default)]
+pub struct TextContent {
+    /// Text of the message.
+    pub text: String,
+    /// Message replied to, if it's a reply.
+    pub quote: Option<Quote>,
+    /// Preview of the link in the text, if the sender attached one.
+    pub preview: Option<LinkPreview>,
+    /// JIDs of who the message mentions.
+    pub mentions: Vec<String>,
+}
+
+/// Matches the `<meta>` tags of a page.
+static META_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<meta\s[^>]*>").unwrap());
+
+/// Matches the attributes of a tag, with their quoted values.
+static ATTRIBUTE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?s)([\w:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+/// Matches the `<title>` of a page.
+static TITLE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>([^<]*)</title>").unwrap());
+
+/// Preview of a link, as attached by the sender.
+#[derive(Clone, Debug, Default)]
+pub struct LinkPreview {
+    /// Link the preview is for.
+    pub url: String,
+    /// Page title.
+    pub title: Option<String>,
+    /// JPEG thumbnail of the page.
+    pub thumbnail: Option<Arc<Vec<u8>>>,
+    /// Page description.
+    pub description: Option<String>,
+}
+
+impl LinkPreview {
+    /// Build the preview of a link from the HTML of its page, reading the
+    /// Open Graph tags or else the page title and description.
+    pub fn from_html(url: String, html: &str) -> Option<Self> {
+        let mut og_title = None;
+        let mut description = None;
+        let mut og_description = None;
+
+        for tag in META_REGEX.find_iter(html) {
+            let mut key = None;
+            let mut content = None;
+            for attribute in ATTRIBUTE_REGEX.captures_iter(tag.as_str()) {
+                let value = attribute.get(2).or_else(|| attribute.get(3));
+                let value = value.map(|value| decode_entities(value.as_str()));
+                match attribute[1].to_lowercase().as_str() {
+                    "property" | "name" => key = value.map(|key| key.to_lowercase()),
+                    "content" => content = value.filter(|content| !content.is_empty()),
+                    _ => {}
+                }
+            }
+
+            match key.as_deref() {
+                Some("og:title") => og_title = og_title.or(content),
+                Some("og:description") => og_description = og_description.or(content),
+                Some("description") => description = description.or(content),
+                _ => {}
+            }
+        }
+        let title = og_title.or_else(|| {
+            TITLE_REGEX
+                .captures(html)
+                .map(|captures| decode_entities(&captures[1]))
+                .filter(|title| !title.is_empty())
+        });
+        let description = og_description.or(description);
+        (title.is_some() || description.is_some()).then_some(Self {
+            url,
+            title,
+            description,
+            thumbnail: None,
+        })
+    }
+
+    /// Domain of the link, shown under the preview.
+    pub fn domain(&self) -> &str {
+        url_domain(&self.url)
+    }
+}
+
+/// Get the domain of a link, without its `www.` prefix.
+pub fn url_domain(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    host.strip_prefix("www.").unwrap_or(host)
+}
+
+impl TextContent {
+    /// Parse the text of a message, if it has any.
+    pub fn from_message(message: &wa::Message) -> Option<Self> {
+        if let Some(text) = message.conversation.clone().filter(|c| !c.is_empty()) {
+            return Some(Self {
+                text,
+                ..Self::default()
+            });
+        }
+
+        let extended = message.extended_text_message.as_ref()?;
+        let text = extended.text.clone().filter(|t| !t.is_empty())?;
+        let mentions = extended
+            .context_info
+            .as_ref()
+            .map(|context| context.mentioned_jid.clone())
+            .unwrap_or_default();
+        let preview = extended
+            .matched_text
+            .clone()
+            .filter(|url| !url.is_empty())
+            .map(|url| LinkPreview {
+                url,
+                title: extended.title.clone().filter(|t| !t.is_empty()),
+                description: extended.description.clone().filter(|d| !d.is_empty()),
+                thumbnail: extended
+                    .jpeg_thumbnail
+                    .clone()
+                    .filter(|t| !t.is_empty())
+                    .map(Arc::new),
+            });
+
+        Some(Self {
+            text,
+            quote: Quote::from_message(message),
+            mentions,
+            preview,
+        })
+    }
+}
+
+/// Decode the most common HTML entities, and collapse the whitespace.
+fn decode_entities(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a message with extended text.
+    fn extended(text: wa::message::ExtendedTextMessage) -> wa::Message {
+        wa::Message {
+            extended_text_message: Some(text.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plain_text_is_read_from_the_conversation() {
+        let message = wa::Message {
+            conversation: Some("Hello".into()),
+            ..Default::default()
+        };
+        let text = TextContent::from_message(&message).unwrap();
+
+        assert_eq!(text.text, "Hello");
+        assert!(text.quote.is_none());
+        assert!(text.mentions.is_empty());
+        assert!(text.preview.is_none());
+    }
+
+    #[test]
+    fn extended_text_carries_its_reply_mentions_and_preview() {
+        let mut message = extended(wa::message::ExtendedTextMessage {
+            text: Some("@alice see https://www.example.com/page".into()),
+            matched_text: Some("https://www.example.com/page".into()),
+            title: Some("Example".into()),
+            description: Some(String::new()),
+            jpeg_thumbnail: Some(vec![0xFF, 0xD8]),
+            context_info: Some(
+                wa::ContextInfo {
+                    stanza_id: Some("QUOTED".into()),
+                    participant: Some("bob@s.whatsapp.net".into()),
+                    quoted_message: Some(
+                        wa::Message {
+                            conversation: Some("Original".into()),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    mentioned_jid: vec!["alice@s.whatsapp.net".into()],
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        });
+        // An empty conversation doesn't hide the extended text.
+        message.conversation = Some(String::new());
+        let text = TextContent::from_message(&message).unwrap();
+
+        assert_eq!(text.text, "@alice see https://www.example.com/page");
+        assert_eq!(text.mentions, ["alice@s.whatsapp.net"]);
+
+        let quote = text.quote.unwrap();
+        assert_eq!(quote.id, "QUOTED");
+        assert_eq!(quote.content, "Original");
+        assert_eq!(quote.sender_jid, "bob@s.whatsapp.net");
+
+        let preview = text.preview.unwrap();
+        assert_eq!(preview.domain(), "example.com");
+        assert_eq!(preview.title.as_deref(), Some("Example"));
+        assert_eq!(preview.description, None);
+        assert_eq!(preview.thumbnail.as_deref(), Some(&vec![0xFF, 0xD8]));
+    }
+
+    #[test]
+    fn messages_without_text_have_no_content() {
+        assert!(TextContent::from_message(&wa::Message::default()).is_none());
+        assert!(
+            TextContent::from_message(&wa::Message {
+                conversation: Some(String::new()),
+                ..Default::default()
+            })
+            .is_none()
+        );
+        assert!(
+            TextContent::from_message(&extended(wa::message::ExtendedTextMessage {
+                text: Some(String::new()),
+                matched_text: Some("https://example.com".into()),
+                ..Default::default()
+            }))
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn previews_are_read_from_the_page_metadata() {
+        let html = r#"
+            <title>Fallback</title>
+            <meta name="description" content="Plain  description">
+            <meta property="og:title" content="Tom &amp; Jerry">
+        "#;
+        let preview = LinkPreview::from_html("https://example.com".into(), html).unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Tom & Jerry"));
+        assert_eq!(preview.description.as_deref(), Some("Plain description"));
+
+        let preview =
+            LinkPreview::from_html("https://example.com".into(), "<title>Page</title>").unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Page"));
+
+        assert!(LinkPreview::from_html("https://example.com".into(), "<p>Nothing</p>").is_none());
+    }
+}
@@ -1,15 +1,70 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, fs, sync::Arc};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use indexmap::IndexMap;
-use libsql::{Builder, Cipher, Connection, EncryptionConfig, params::IntoParams};
+use libsql::{
+    Builder, Cipher, Connection, EncryptionConfig,
+    params::{IntoParams, Params},
+};
+use tokio::sync::OnceCell;
 use uuid::Uuid;
 
 use crate::{
     DATA_DIR,
-    state::{Chat, ChatMessage, Media, MediaType, MessageStatus},
+    state::{
+        Chat, ChatDocument, ChatLink, ChatMessage, ContactCard, DownloadableMedia, LinkPreview,
+        Location, Media, MediaType, Membership, MessageActor, MessageKind, MessageStatus, Poll,
+        Quote, StatusUpdate,
+    },
+    utils::{ComposerPurpose, Wallpaper, extract_links},
 };
 
+/// Most parameters bound to a single SQL statement.
+const MAX_QUERY_PARAMS: usize = 500;
+
+/// Tables of details kept for a message by its server ID.
+const MESSAGE_DETAIL_TABLES: &[&str] = &[
+    "polls",
+    "poll_options",
+    "poll_votes",
+    "locations",
+    "contact_cards",
+    "media_details",
+    "message_quotes",
+    "link_previews",
+    "reactions",
+    "message_links",
+];
+
+/// Read what's needed to download `media` from a row, starting at column
+/// `start`: its key, direct path, length and hashes. `None` once downloaded.
+fn downloadable_media(
+    row: &libsql::Row,
+    start: i32,
+    media: &Media,
+) -> Result<Option<DownloadableMedia>, libsql::Error> {
+    let (Some(media_key), Some(direct_path)) = (
+        row.get::<Option<Vec<u8>>>(start)?,
+        row.get::<Option<String>>(start + 1)?,
+    ) else {
+        return Ok(None);
+    };
+
+    Ok(Some(DownloadableMedia {
+        media_key,
+        mime_type: media.mime_type.clone(),
+        direct_path,
+        file_length: row
+            .get::<Option<i64>>(start + 2)?
+            .and_then(|length| u64::try_from(length).ok())
+            .unwrap_or_default(),
+        file_sha256: row.get::<Option<Vec<u8>>>(start + 3)?.unwrap_or_default(),
+        download_type: media.r#type.download_type(),
+        duration_secs: media.durations_secs,
+        file_enc_sha256: row.get::<Option<Vec<u8>>>(start + 4)?.unwrap_or_default(),
+    }))
+}
+
 /// Papo's own database for UI state persistence.
 /// Separate from whatsapp-rust's protocol database.
 #[derive(Clone, Debug)]
@@ -21,6 +76,13 @@ pub struct Database {
 impl Database {
     /// Create a new database.
     pub async fn new() -> Result<Self, libsql::Error> {
+        // Keep it next to the client database, creating the data dir on first run.
+        fs::create_dir_all(DATA_DIR.as_path()).map_err(|e| {
+            libsql::Error::ConnectionFailed(format!(
+                "Failed to create data dir {}: {e}",
+                DATA_DIR.display()
+            ))
+        })?;
         let path = DATA_DIR.join("papo.db");
 
         let db = Arc::new(
@@ -45,8 +107,31 @@ impl Database {
         Ok(this)
     }
 
+    /// Get the database shared by the window and the search provider,
+    /// opening it on first use.
+    pub async fn shared() -> Result<Arc<Self>, libsql::Error> {
+        static SHARED: OnceCell<Arc<Database>> = OnceCell::const_new();
+
+        SHARED
+            .get_or_try_init(|| async { Self::new().await.map(Arc::new) })
+            .await
+            .cloned()
+    }
+
+    /// Open an empty database kept in memory, for tests or when the one on
+    /// disk can't be opened.
+    pub async fn in_memory() -> Result<Self, libsql::Error> {
+        let db = Arc::new(Builder::new_local(":memory:").build().await?);
+        let conn = Arc::new(db.connect()?);
+
+        let this = Self { db, conn };
+        this.init_tables().await?;
+
+        Ok(this)
+    }
+
     /// Execute a SQL expression in the running connection.
-    pub async fn execute(&self, sql: &str, params: impl IntoParams) -> Result<u64, libsql::Error> {
+    async fn execute(&self, sql: &str, params: impl IntoParams) -> Result<u64, libsql::Error> {
         self.conn.execute(sql, params).await
     }
 
@@ -62,7 +147,14 @@ impl Database {
                 muted INTEGER DEFAULT 0,
                 pinned INTEGER DEFAULT 0,
                 last_message_time INTEGER,
-                archived INTEGER DEFAULT 0
+                archived INTEGER DEFAULT 0,
+                participant_count INTEGER,
+                name_modified_at INTEGER,
+                flags_modified_at INTEGER,
+                pin_order INTEGER,
+                sort_key INTEGER DEFAULT 0,
+                muted_until INTEGER,
+                left_group INTEGER DEFAULT 0
             )
             ",
                 (),
@@ -85,6 +177,8 @@ impl Database {
                 timestamp INTEGER NOT NULL,
                 media_type TEXT,
                 media_data BLOB,
+                revoked INTEGER DEFAULT 0,
+                edited INTEGER DEFAULT 0,
                 FOREIGN KEY (chat_jid) REFERENCES chats(jid) ON DELETE CASCADE
             )
             ",
@@ -110,571 +204,3048 @@ impl Database {
             )
             .await?;
 
-        self.conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_messages_chat ON messages(chat_jid, timestamp DESC)",
-            (),
-        )
-        .await?;
-        self.conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_chats_pinned ON chats(pinned DESC, last_message_time DESC)",
-            (),
-        ).await?;
+        // Chat settings (not cascade-deleted with chats, so they outlive them).
         self.conn
             .execute(
-                "CREATE INDEX IF NOT EXISTS idx_contacts_jid ON contacts(jid)",
+                r"
+            CREATE TABLE IF NOT EXISTS chat_settings (
+                jid TEXT PRIMARY KEY,
+                composer_emoji INTEGER DEFAULT 0,
+                undecryptable_count INTEGER DEFAULT 0
+            )
+            ",
                 (),
             )
             .await?;
 
-        Ok(())
-    }
-}
-
-/// Chat operations
-impl Database {
-    pub async fn save_chat(&self, chat: &Chat) -> Result<(), libsql::Error> {
-        let last_msg_time = chat
-            .get_last_message()
-            .await
-            .expect("Failed to get the last message of a chat")
-            .map_or(0, |m| m.timestamp.timestamp());
-
+        // Status updates.
         self.conn
             .execute(
                 r"
-            INSERT INTO chats (jid, name, muted, pinned, last_message_time, archived)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-            ON CONFLICT(jid) DO UPDATE SET
-                name = excluded.name,
-                muted = excluded.muted,
-                pinned = excluded.pinned,
-                last_message_time = excluded.last_message_time,
-                archived = excluded.archived
+            CREATE TABLE IF NOT EXISTS statuses (
+                id TEXT PRIMARY KEY,
+                sender_jid TEXT NOT NULL,
+                sender_name TEXT,
+                content TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                viewed INTEGER DEFAULT 0,
+                media_type TEXT,
+                thumbnail BLOB
+            )
             ",
-                libsql::params![
-                    chat.jid.clone(),
-                    chat.name.clone(),
-                    i32::from(chat.muted),
-                    i32::from(chat.pinned),
-                    last_msg_time,
-                    i32::from(chat.archived)
-                ],
+                (),
             )
             .await?;
 
-        Ok(())
-    }
-
-    /// Ensure a chat row exists in the database to satisfy foreign key constraints.
-    /// Uses `INSERT OR IGNORE` so it won't overwrite an existing chat with proper data.
-    pub async fn ensure_chat_exists(&self, jid: &str) -> Result<(), libsql::Error> {
+        // Polls.
         self.conn
             .execute(
-                "INSERT OR IGNORE INTO chats (jid, name, muted, pinned, last_message_time, archived) VALUES (?1, ?1, 0, 0, 0, 0)",
-                [jid],
+                r"
+            CREATE TABLE IF NOT EXISTS polls (
+                message_id TEXT PRIMARY KEY,
+                question TEXT NOT NULL,
+                multi_select INTEGER DEFAULT 0,
+                secret BLOB,
+                FOREIGN KEY (message_id) REFERENCES messages(server_id) ON DELETE CASCADE
+            )
+            ",
+                (),
             )
             .await?;
-        Ok(())
-    }
-
-    pub async fn load_chat(&self, jid: &str) -> Result<Option<Chat>, libsql::Error> {
-        let mut rows = self
-            .conn
-            .query(
+        self.conn
+            .execute(
                 r"
-            SELECT jid, name, muted, pinned, last_message_time, archived
-            FROM chats
-            WHERE jid = ?1 AND archived = 0
-            ORDER BY pinned DESC, last_message_time DESC
-            LIMIT 1
+            CREATE TABLE IF NOT EXISTS poll_options (
+                message_id TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                PRIMARY KEY (message_id, position),
+                FOREIGN KEY (message_id) REFERENCES polls(message_id) ON DELETE CASCADE
+            )
             ",
-                [jid],
+                (),
             )
             .await?;
-
-        if let Some(row) = rows.next().await? {
-            let jid: String = row.get(0)?;
-
-            Ok(Some(Chat {
-                jid,
-                name: row.get(1)?,
-                muted: row.get::<i32>(2)? != 0,
-                pinned: row.get::<i32>(3)? != 0,
-                archived: row.get::<i32>(5)? != 0,
-                available: None,
-                last_seen: None,
-                avatar_path: None,
-                participants: HashMap::new(),
-                last_message_time: DateTime::from_timestamp(row.get::<i64>(4)?, 0)
-                    .expect("Invalid timestamp"),
-
-                db: Arc::new(self.clone()),
-            }))
-        } else {
-            Ok(None)
-        }
-    }
-
-    pub async fn load_chats(&self) -> Result<Vec<Chat>, libsql::Error> {
-        let mut rows = self
-            .conn
-            .query(
+        self.conn
+            .execute(
                 r"
-            SELECT jid, name, muted, pinned, last_message_time, archived
-            FROM chats
-            WHERE archived = 0
-            ORDER BY pinned DESC, last_message_time DESC
+            CREATE TABLE IF NOT EXISTS poll_votes (
+                message_id TEXT NOT NULL,
+                voter_jid TEXT NOT NULL,
+                option_name TEXT NOT NULL,
+                PRIMARY KEY (message_id, voter_jid, option_name),
+                FOREIGN KEY (message_id) REFERENCES polls(message_id) ON DELETE CASCADE
+            )
             ",
                 (),
             )
             .await?;
 
-        let mut chats = Vec::new();
-        while let Some(row) = rows.next().await? {
-            let jid: String = row.get(0)?;
-
-            chats.push(Chat {
-                jid,
-                name: row.get(1)?,
-                muted: row.get::<i32>(2)? != 0,
-                pinned: row.get::<i32>(3)? != 0,
-                archived: row.get::<i32>(5)? != 0,
-                available: None,
-                last_seen: None,
-                avatar_path: None,
-                participants: HashMap::new(),
-                last_message_time: DateTime::from_timestamp(row.get::<i64>(4)?, 0)
-                    .expect("Invalid timestamp"),
-
-                db: Arc::new(self.clone()),
-            });
-        }
-
-        Ok(chats)
-    }
-
-    pub async fn delete_chat(&self, jid: &str) -> Result<(), libsql::Error> {
-        // Cascade delete will remove messages too.
+        // Locations.
         self.conn
-            .execute("DELETE FROM chats WHERE jid = ?1", [jid])
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS locations (
+                message_id TEXT PRIMARY KEY,
+                latitude REAL NOT NULL,
+                longitude REAL NOT NULL,
+                name TEXT,
+                address TEXT,
+                live INTEGER DEFAULT 0,
+                thumbnail BLOB,
+                FOREIGN KEY (message_id) REFERENCES messages(server_id) ON DELETE CASCADE
+            )
+            ",
+                (),
+            )
             .await?;
 
-        Ok(())
-    }
-}
-
-/// Message operations.
-impl Database {
-    pub async fn save_message(
-        &self,
-        chat_jid: &str,
-        msg: &ChatMessage,
-    ) -> Result<(), libsql::Error> {
-        let media_type = msg.media.as_ref().map(|m| format!("{:?}", m.r#type));
-        let media_data = msg.media.as_ref().map(|m| m.data.as_ref().clone());
-
+        // Contact cards.
         self.conn
             .execute(
                 r"
-            INSERT INTO messages (local_id, server_id, chat_jid, sender_jid, sender_name, content,
-                                  outgoing, status, timestamp, media_type, media_data)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
-            ON CONFLICT(local_id) DO UPDATE SET
-                status = excluded.status,
-                content = excluded.content,
-                server_id = excluded.server_id
+            CREATE TABLE IF NOT EXISTS contact_cards (
+                message_id TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                vcard TEXT NOT NULL,
+                PRIMARY KEY (message_id, position),
+                FOREIGN KEY (message_id) REFERENCES messages(server_id) ON DELETE CASCADE
+            )
             ",
-                libsql::params![
-                    msg.local_id.to_string(),
-                    msg.server_id.clone(),
-                    chat_jid,
-                    msg.sender_jid.clone(),
-                    msg.sender_name.clone(),
-                    msg.content.clone(),
-                    i32::from(msg.outgoing),
-                    msg.status as i32,
-                    msg.timestamp.timestamp(),
-                    media_type,
-                    media_data
-                ],
+                (),
             )
             .await?;
 
-        // Update chat's last_message_time.
+        // Duration and waveform of voice messages, and what's needed to
+        // download media not fetched yet.
         self.conn
             .execute(
-                "UPDATE chats SET last_message_time = ?1 WHERE jid = ?2",
-                libsql::params![msg.timestamp.timestamp(), chat_jid],
+                r"
+            CREATE TABLE IF NOT EXISTS media_details (
+                message_id TEXT PRIMARY KEY,
+                duration_secs INTEGER,
+                waveform BLOB,
+                mime_type TEXT,
+                media_key BLOB,
+                direct_path TEXT,
+                file_length INTEGER,
+                file_sha256 BLOB,
+                file_enc_sha256 BLOB,
+                FOREIGN KEY (message_id) REFERENCES messages(server_id) ON DELETE CASCADE
+            )
+            ",
+                (),
             )
             .await?;
 
-        Ok(())
-    }
+        // Messages quoted by replies.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS message_quotes (
+                message_id TEXT PRIMARY KEY,
+                quoted_id TEXT NOT NULL,
+                sender_jid TEXT NOT NULL,
+                sender_name TEXT,
+                outgoing INTEGER DEFAULT 0,
+                content TEXT NOT NULL,
+                FOREIGN KEY (message_id) REFERENCES messages(server_id) ON DELETE CASCADE
+            )
+            ",
+                (),
+            )
+            .await?;
 
-    /// Save a synced message, skipping duplicates on both `local_id` and `server_id`.
-    /// Also ensures the chat exists to satisfy the foreign key constraint.
-    /// Returns `true` if the message was inserted, `false` if it was a duplicate.
-    pub async fn save_synced_message(
-        &self,
-        chat_jid: &str,
-        msg: &ChatMessage,
-    ) -> Result<bool, libsql::Error> {
-        self.ensure_chat_exists(chat_jid).await?;
+        // Link previews attached to messages.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS link_previews (
+                message_id TEXT PRIMARY KEY,
+                url TEXT NOT NULL,
+                title TEXT,
+                description TEXT,
+                thumbnail BLOB,
+                FOREIGN KEY (message_id) REFERENCES messages(server_id) ON DELETE CASCADE
+            )
+            ",
+                (),
+            )
+            .await?;
 
-        let media_type = msg.media.as_ref().map(|m| format!("{:?}", m.r#type));
-        let media_data = msg.media.as_ref().map(|m| m.data.as_ref().clone());
+        // Reactions on messages, one per reactor.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS reactions (
+                message_id TEXT NOT NULL,
+                sender_jid TEXT NOT NULL,
+                emoji TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                PRIMARY KEY (message_id, sender_jid),
+                FOREIGN KEY (message_id) REFERENCES messages(server_id) ON DELETE CASCADE
+            )
+            ",
+                (),
+            )
+            .await?;
 
-        let rows = self
-            .conn
+        // Messages that couldn't be decrypted yet, waiting for a retry.
+        self.conn
             .execute(
                 r"
-            INSERT OR IGNORE INTO messages (local_id, server_id, chat_jid, sender_jid, sender_name,
-                                            content, outgoing, status, timestamp, media_type, media_data)
-            VALUES (?1, NULLIF(?2, ''), ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+            CREATE TABLE IF NOT EXISTS undecryptable_messages (
+                id TEXT PRIMARY KEY,
+                chat_jid TEXT NOT NULL
+            )
             ",
-                libsql::params![
-                    msg.local_id.to_string(),
-                    msg.server_id.clone(),
-                    chat_jid,
-                    msg.sender_jid.clone(),
-                    msg.sender_name.clone(),
-                    msg.content.clone(),
-                    i32::from(msg.outgoing),
-                    msg.status as i32,
-                    msg.timestamp.timestamp(),
-                    media_type,
-                    media_data
-                ],
+                (),
             )
             .await?;
 
+        // Pinned messages.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS pinned_messages (
+                chat_jid TEXT NOT NULL,
+                message_id TEXT NOT NULL,
+                pinned_at INTEGER NOT NULL,
+                PRIMARY KEY (chat_jid, message_id)
+            )
+            ",
+                (),
+            )
+            .await?;
+
+        // Admins of groups, who may delete messages of others.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS group_admins (
+                chat_jid TEXT NOT NULL,
+                jid TEXT NOT NULL,
+                PRIMARY KEY (chat_jid, jid)
+            )
+            ",
+                (),
+            )
+            .await?;
+
+        // People asking to join groups the user is an admin of.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS join_requests (
+                chat_jid TEXT NOT NULL,
+                jid TEXT NOT NULL,
+                requested_at INTEGER NOT NULL,
+                PRIMARY KEY (chat_jid, jid)
+            )
+            ",
+                (),
+            )
+            .await?;
+
+        // Web links in message text, for the chat gallery.
+        let links_indexed = self.table_exists("message_links").await?;
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS message_links (
+                message_id TEXT NOT NULL,
+                url TEXT NOT NULL,
+                chat_jid TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                PRIMARY KEY (message_id, url),
+                FOREIGN KEY (message_id) REFERENCES messages(server_id) ON DELETE CASCADE
+            )
+            ",
+                (),
+            )
+            .await?;
+
+        // Messages bookmarked on this device, never synced.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS bookmarks (
+                chat_jid TEXT NOT NULL,
+                message_id TEXT NOT NULL,
+                note TEXT,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (chat_jid, message_id)
+            )
+            ",
+                (),
+            )
+            .await?;
+
+        // Read receipts not sent yet, with the message IDs comma-separated.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS pending_receipts (
+                id TEXT PRIMARY KEY,
+                chat_jid TEXT NOT NULL,
+                sender_jid TEXT,
+                message_ids TEXT NOT NULL,
+                attempts INTEGER DEFAULT 0
+            )
+            ",
+                (),
+            )
+            .await?;
+
+        // Composer snippets.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS snippets (
+                shortcut TEXT PRIMARY KEY,
+                expansion TEXT NOT NULL
+            )
+            ",
+                (),
+            )
+            .await?;
+
+        // Alternate (PN or LID) JIDs of chats, to route messages addressed by them.
+        self.conn
+            .execute(
+                r"
+            CREATE TABLE IF NOT EXISTS jid_aliases (
+                alias TEXT PRIMARY KEY,
+                jid TEXT NOT NULL
+            )
+            ",
+                (),
+            )
+            .await?;
+
+        // Columns added after the tables were first created.
+        self.add_column_if_missing("chats", "participant_count", "INTEGER")
+            .await?;
+        self.add_column_if_missing("chats", "name_modified_at", "INTEGER")
+            .await?;
+        self.add_column_if_missing("chats", "flags_modified_at", "INTEGER")
+            .await?;
+        self.add_column_if_missing("chats", "pin_order", "INTEGER")
+            .await?;
+        if self
+            .add_column_if_missing("chats", "sort_key", "INTEGER DEFAULT 0")
+            .await?
+        {
+            // Keep the order chats had by their last message time.
+            self.conn
+                .execute(
+                    "UPDATE chats SET sort_key = COALESCE(last_message_time, 0) * 1000",
+                    (),
+                )
+                .await?;
+        }
+        self.add_column_if_missing("chats", "muted_until", "INTEGER")
+            .await?;
+        self.add_column_if_missing("chats", "left_group", "INTEGER DEFAULT 0")
+            .await?;
+        self.add_column_if_missing("messages", "revoked", "INTEGER DEFAULT 0")
+            .await?;
+        self.add_column_if_missing("messages", "edited", "INTEGER DEFAULT 0")
+            .await?;
+        self.add_column_if_missing("messages", "kind", "INTEGER DEFAULT 0")
+            .await?;
+        self.add_column_if_missing("chat_settings", "undecryptable_count", "INTEGER DEFAULT 0")
+            .await?;
+        self.add_column_if_missing("chat_settings", "wallpaper", "TEXT")
+            .await?;
+        self.add_column_if_missing("chat_settings", "cleared_before", "INTEGER")
+            .await?;
+        self.add_column_if_missing("chat_settings", "composer_purpose", "TEXT")
+            .await?;
+        self.add_column_if_missing("chat_settings", "composer_picker", "INTEGER DEFAULT 0")
+            .await?;
+        self.add_column_if_missing("pinned_messages", "expires_at", "INTEGER")
+            .await?;
+        self.add_column_if_missing("statuses", "media_type", "TEXT")
+            .await?;
+        self.add_column_if_missing("statuses", "thumbnail", "BLOB")
+            .await?;
+        for (column, definition) in [
+            ("mime_type", "TEXT"),
+            ("media_key", "BLOB"),
+            ("direct_path", "TEXT"),
+            ("file_length", "INTEGER"),
+            ("file_sha256", "BLOB"),
+            ("file_enc_sha256", "BLOB"),
+        ] {
+            self.add_column_if_missing("media_details", column, definition)
+                .await?;
+        }
+
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_messages_chat ON messages(chat_jid, timestamp DESC)",
+            (),
+        )
+        .await?;
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_chats_pinned ON chats(pinned DESC, last_message_time DESC)",
+            (),
+        ).await?;
+        self.conn
+            .execute(
+                "CREATE INDEX IF NOT EXISTS idx_chats_sort ON chats(pinned DESC, sort_key DESC)",
+                (),
+            )
+            .await?;
+        self.conn
+            .execute(
+                "CREATE INDEX IF NOT EXISTS idx_contacts_jid ON contacts(jid)",
+                (),
+            )
+            .await?;
+        self.conn
+            .execute(
+                "CREATE INDEX IF NOT EXISTS idx_message_links_chat ON message_links(chat_jid, timestamp DESC)",
+                (),
+            )
+            .await?;
+
+        // Index the links of messages stored before there was an index.
+        if !links_indexed {
+            self.index_stored_links().await?;
+        }
+
+        // Foreign keys aren't enforced, so delete what belongs to messages
+        // and chats on every path deleting them.
+        self.conn
+            .execute("DROP TRIGGER IF EXISTS messages_delete_links", ())
+            .await?;
+        let details = MESSAGE_DETAIL_TABLES
+            .iter()
+            .map(|table| format!("DELETE FROM {table} WHERE message_id = OLD.server_id;"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.conn
+            .execute(
+                &format!(
+                    r"
+            CREATE TRIGGER IF NOT EXISTS messages_delete_details
+            AFTER DELETE ON messages
+            BEGIN
+                {details}
+                DELETE FROM pinned_messages
+                WHERE chat_jid = OLD.chat_jid AND message_id = OLD.server_id;
+            END
+            "
+                ),
+                (),
+            )
+            .await?;
+        self.conn
+            .execute(
+                r"
+            CREATE TRIGGER IF NOT EXISTS chats_delete_messages
+            AFTER DELETE ON chats
+            BEGIN
+                DELETE FROM messages WHERE chat_jid = OLD.jid;
+                DELETE FROM group_admins WHERE chat_jid = OLD.jid;
+            END
+            ",
+                (),
+            )
+            .await?;
+        self.conn
+            .execute(
+                r"
+            CREATE TRIGGER IF NOT EXISTS chats_delete_join_requests
+            AFTER DELETE ON chats
+            BEGIN
+                DELETE FROM join_requests WHERE chat_jid = OLD.jid;
+            END
+            ",
+                (),
+            )
+            .await?;
+        self.drop_orphaned_rows().await?;
+
+        Ok(())
+    }
+
+    /// Delete the rows left behind by messages and chats deleted before
+    /// their deletes were cascaded.
+    async fn drop_orphaned_rows(&self) -> Result<(), libsql::Error> {
+        for table in MESSAGE_DETAIL_TABLES {
+            self.conn
+                .execute(
+                    &format!(
+                        r"
+            DELETE FROM {table} WHERE NOT EXISTS (
+                SELECT 1 FROM messages WHERE messages.server_id = {table}.message_id
+            )
+            "
+                    ),
+                    (),
+                )
+                .await?;
+        }
+        self.conn
+            .execute(
+                r"
+            DELETE FROM pinned_messages WHERE NOT EXISTS (
+                SELECT 1 FROM messages
+                WHERE messages.chat_jid = pinned_messages.chat_jid
+                    AND messages.server_id = pinned_messages.message_id
+            )
+            ",
+                (),
+            )
+            .await?;
+        self.conn
+            .execute(
+                r"
+            DELETE FROM group_admins WHERE NOT EXISTS (
+                SELECT 1 FROM chats WHERE chats.jid = group_admins.chat_jid
+            )
+            ",
+                (),
+            )
+            .await?;
+        self.conn
+            .execute(
+                r"
+            DELETE FROM join_requests WHERE NOT EXISTS (
+                SELECT 1 FROM chats WHERE chats.jid = join_requests.chat_jid
+            )
+            ",
+                (),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Check if a table exists.
+    async fn table_exists(&self, table: &str) -> Result<bool, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                [table],
+            )
+            .await?;
+
+        Ok(rows.next().await?.is_some())
+    }
+
+    /// Get the schema version, which changes every time the schema does.
+    pub async fn get_schema_version(&self) -> Result<i64, libsql::Error> {
+        let mut rows = self.conn.query("PRAGMA schema_version", ()).await?;
+
+        rows.next().await?.map_or(Ok(0), |row| row.get::<i64>(0))
+    }
+
+    /// Add a column to a table created by an older version, if it's missing,
+    /// telling whether it was.
+    async fn add_column_if_missing(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<bool, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(&format!("PRAGMA table_info({table})"), ())
+            .await?;
+
+        while let Some(row) = rows.next().await? {
+            if row.get_str(1)? == column {
+                return Ok(false);
+            }
+        }
+
+        self.conn
+            .execute(
+                &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+                (),
+            )
+            .await?;
+
+        Ok(true)
+    }
+}
+
+/// Chat operations
+impl Database {
+    pub async fn save_chat(&self, chat: &Chat) -> Result<(), libsql::Error> {
+        let last_msg_time = chat
+            .get_last_message()
+            .await
+            .expect("Failed to get the last message of a chat")
+            .map_or(0, |m| m.timestamp.timestamp());
+
+        self.conn
+            .execute(
+                r"
+            INSERT INTO chats (jid, name, muted, pinned, last_message_time, archived, participant_count,
+                               name_modified_at, flags_modified_at, pin_order, sort_key, muted_until,
+                               left_group)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+            ON CONFLICT(jid) DO UPDATE SET
+                name = excluded.name,
+                muted = excluded.muted,
+                muted_until = excluded.muted_until,
+                pinned = excluded.pinned,
+                last_message_time = excluded.last_message_time,
+                archived = excluded.archived,
+                left_group = excluded.left_group,
+                participant_count = COALESCE(excluded.participant_count, chats.participant_count),
+                name_modified_at = COALESCE(excluded.name_modified_at, chats.name_modified_at),
+                flags_modified_at = COALESCE(excluded.flags_modified_at, chats.flags_modified_at),
+                pin_order = excluded.pin_order,
+                sort_key = MAX(chats.sort_key, excluded.sort_key)
+            ",
+                libsql::params![
+                    chat.jid.clone(),
+                    chat.name.clone(),
+                    i32::from(chat.muted),
+                    i32::from(chat.pinned),
+                    last_msg_time,
+                    i32::from(chat.archived),
+                    chat.participant_count,
+                    chat.name_modified_at.map(|t| t.timestamp()),
+                    chat.flags_modified_at.map(|t| t.timestamp()),
+                    chat.pin_order,
+                    chat.sort_key,
+                    chat.muted_until.map(|t| t.timestamp()),
+                    i32::from(chat.has_left())
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Ensure a chat row exists in the database to satisfy foreign key constraints.
+    /// Uses `INSERT OR IGNORE` so it won't overwrite an existing chat with proper data.
+    pub async fn ensure_chat_exists(&self, jid: &str) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO chats (jid, name, muted, pinned, last_message_time, archived) VALUES (?1, ?1, 0, 0, 0, 0)",
+                [jid],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Get the highest sort key given to a chat, archived ones included.
+    pub async fn max_chat_sort_key(&self) -> Result<i64, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query("SELECT COALESCE(MAX(sort_key), 0) FROM chats", ())
+            .await?;
+
+        rows.next().await?.map_or(Ok(0), |row| row.get::<i64>(0))
+    }
+
+    /// Raise the sort key of a chat, never lowering it.
+    pub async fn raise_chat_sort_key(&self, jid: &str, sort_key: i64) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                "UPDATE chats SET sort_key = MAX(sort_key, ?2) WHERE jid = ?1",
+                libsql::params![jid, sort_key],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn load_chat(&self, jid: &str) -> Result<Option<Chat>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT jid, name, muted, pinned, last_message_time, archived, participant_count,
+                   name_modified_at, flags_modified_at, pin_order, sort_key, muted_until,
+                   left_group
+            FROM chats
+            WHERE jid = ?1
+            ",
+                [jid],
+            )
+            .await?;
+
+        if let Some(row) = rows.next().await? {
+            let jid: String = row.get(0)?;
+
+            Ok(Some(Chat {
+                jid,
+                name: row.get(1)?,
+                muted: row.get::<i32>(2)? != 0,
+                muted_until: row
+                    .get::<i64>(11)
+                    .ok()
+                    .and_then(|t| DateTime::from_timestamp(t, 0)),
+                pinned: row.get::<i32>(3)? != 0,
+                archived: row.get::<i32>(5)? != 0,
+                membership: Membership::from_left(row.get::<i32>(12)? != 0),
+                available: None,
+                last_seen: None,
+                avatar_path: None,
+                participants: HashMap::new(),
+                last_message_time: DateTime::from_timestamp(row.get::<i64>(4)?, 0)
+                    .expect("Invalid timestamp"),
+                participant_count: row.get(6).ok(),
+                name_modified_at: row
+                    .get::<i64>(7)
+                    .ok()
+                    .and_then(|t| DateTime::from_timestamp(t, 0)),
+                flags_modified_at: row
+                    .get::<i64>(8)
+                    .ok()
+                    .and_then(|t| DateTime::from_timestamp(t, 0)),
+                pin_order: row.get(9).ok(),
+                sort_key: row.get(10)?,
+
+                db: Arc::new(self.clone()),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn load_chats(&self) -> Result<Vec<Chat>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT jid, name, muted, pinned, last_message_time, archived, participant_count,
+                   name_modified_at, flags_modified_at, pin_order, sort_key, muted_until,
+                   left_group
+            FROM chats
+            ORDER BY pinned DESC, pin_order, sort_key DESC, jid
+            ",
+                (),
+            )
+            .await?;
+
+        let mut chats = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let jid: String = row.get(0)?;
+
+            chats.push(Chat {
+                jid,
+                name: row.get(1)?,
+                muted: row.get::<i32>(2)? != 0,
+                muted_until: row
+                    .get::<i64>(11)
+                    .ok()
+                    .and_then(|t| DateTime::from_timestamp(t, 0)),
+                pinned: row.get::<i32>(3)? != 0,
+                archived: row.get::<i32>(5)? != 0,
+                membership: Membership::from_left(row.get::<i32>(12)? != 0),
+                available: None,
+                last_seen: None,
+                avatar_path: None,
+                participants: HashMap::new(),
+                last_message_time: DateTime::from_timestamp(row.get::<i64>(4)?, 0)
+                    .expect("Invalid timestamp"),
+                participant_count: row.get(6).ok(),
+                name_modified_at: row
+                    .get::<i64>(7)
+                    .ok()
+                    .and_then(|t| DateTime::from_timestamp(t, 0)),
+                flags_modified_at: row
+                    .get::<i64>(8)
+                    .ok()
+                    .and_then(|t| DateTime::from_timestamp(t, 0)),
+                pin_order: row.get(9).ok(),
+                sort_key: row.get(10)?,
+
+                db: Arc::new(self.clone()),
+            });
+        }
+
+        Ok(chats)
+    }
+
+    pub async fn delete_chat(&self, jid: &str) -> Result<(), libsql::Error> {
+        // The delete trigger removes its messages and admins too.
+        self.conn
+            .execute("DELETE FROM chats WHERE jid = ?1", [jid])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Delete the data kept on this device only for a chat, which outlives it
+    /// to be there again if the chat comes back.
+    pub async fn delete_chat_local_data(&self, jid: &str) -> Result<(), libsql::Error> {
+        self.conn
+            .execute("DELETE FROM chat_settings WHERE jid = ?1", [jid])
+            .await?;
+        self.conn
+            .execute("DELETE FROM bookmarks WHERE chat_jid = ?1", [jid])
+            .await?;
+        self.conn
+            .execute(
+                "DELETE FROM undecryptable_messages WHERE chat_jid = ?1",
+                [jid],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Replace the admins of a group, as synced.
+    pub async fn set_group_admins(
+        &self,
+        chat_jid: &str,
+        admins: &[String],
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute("DELETE FROM group_admins WHERE chat_jid = ?1", [chat_jid])
+            .await?;
+
+        for jid in admins {
+            self.conn
+                .execute(
+                    "INSERT OR IGNORE INTO group_admins (chat_jid, jid) VALUES (?1, ?2)",
+                    [chat_jid, jid.as_str()],
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Check if any of `jids` is an admin of a group.
+    pub async fn is_group_admin(
+        &self,
+        chat_jid: &str,
+        jids: &[String],
+    ) -> Result<bool, libsql::Error> {
+        if jids.is_empty() {
+            return Ok(false);
+        }
+
+        let sql = format!(
+            "SELECT 1 FROM group_admins WHERE chat_jid = ?1 AND jid IN ({}) LIMIT 1",
+            placeholders(2, jids.len())
+        );
+        let mut params = vec![libsql::Value::Text(chat_jid.to_string())];
+        params.extend(jids.iter().cloned().map(libsql::Value::Text));
+
+        let mut rows = self.conn.query(&sql, Params::Positional(params)).await?;
+        Ok(rows.next().await?.is_some())
+    }
+}
+
+/// Someone asking to join a group the user is an admin of.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JoinRequest {
+    /// JID of who asked.
+    pub jid: String,
+    /// When they asked.
+    pub requested_at: DateTime<Utc>,
+}
+
+/// Join request operations.
+impl Database {
+    /// Keep requests to join a group, leaving the ones already kept as they are.
+    pub async fn add_join_requests(
+        &self,
+        chat_jid: &str,
+        requests: &[JoinRequest],
+    ) -> Result<(), libsql::Error> {
+        for request in requests {
+            self.conn
+                .execute(
+                    r"
+            INSERT OR IGNORE INTO join_requests (chat_jid, jid, requested_at)
+            VALUES (?1, ?2, ?3)
+            ",
+                    libsql::params![
+                        chat_jid,
+                        request.jid.as_str(),
+                        request.requested_at.timestamp()
+                    ],
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the requests to join a group, as fetched from the server.
+    pub async fn set_join_requests(
+        &self,
+        chat_jid: &str,
+        requests: &[JoinRequest],
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute("DELETE FROM join_requests WHERE chat_jid = ?1", [chat_jid])
+            .await?;
+
+        self.add_join_requests(chat_jid, requests).await
+    }
+
+    /// Forget requests to join a group, once they're resolved or withdrawn.
+    pub async fn remove_join_requests(
+        &self,
+        chat_jid: &str,
+        jids: &[String],
+    ) -> Result<(), libsql::Error> {
+        for jid in jids {
+            self.conn
+                .execute(
+                    "DELETE FROM join_requests WHERE chat_jid = ?1 AND jid = ?2",
+                    [chat_jid, jid.as_str()],
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Load the requests to join a group, oldest first.
+    pub async fn load_join_requests(
+        &self,
+        chat_jid: &str,
+    ) -> Result<Vec<JoinRequest>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT jid, requested_at FROM join_requests
+            WHERE chat_jid = ?1
+            ORDER BY requested_at, jid
+            ",
+                [chat_jid],
+            )
+            .await?;
+
+        let mut requests = Vec::new();
+        while let Some(row) = rows.next().await? {
+            requests.push(JoinRequest {
+                jid: row.get(0)?,
+                requested_at: DateTime::from_timestamp(row.get(1)?, 0).unwrap_or_default(),
+            });
+        }
+
+        Ok(requests)
+    }
+
+    /// Count the requests to join a group.
+    pub async fn count_join_requests(&self, chat_jid: &str) -> Result<usize, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT COUNT(*) FROM join_requests WHERE chat_jid = ?1",
+                [chat_jid],
+            )
+            .await?;
+
+        if let Some(row) = rows.next().await? {
+            Ok(usize::try_from(row.get::<u64>(0)?).unwrap_or(0))
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+/// Message operations.
+impl Database {
+    pub async fn save_message(
+        &self,
+        chat_jid: &str,
+        msg: &ChatMessage,
+    ) -> Result<(), libsql::Error> {
+        let media_type = msg.media.as_ref().map(|m| format!("{:?}", m.r#type));
+        let media_data = msg.media.as_ref().map(|m| m.data.as_ref().clone());
+
+        self.conn
+            .execute(
+                r"
+            INSERT INTO messages (local_id, server_id, chat_jid, sender_jid, sender_name, content,
+                                  outgoing, status, timestamp, media_type, media_data, revoked,
+                                  edited, kind)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+            ON CONFLICT(local_id) DO UPDATE SET
+                status = excluded.status,
+                content = excluded.content,
+                edited = excluded.edited,
+                revoked = excluded.revoked,
+                server_id = excluded.server_id
+            ON CONFLICT(server_id) DO NOTHING
+            ",
+                libsql::params![
+                    msg.local_id.to_string(),
+                    msg.server_id.clone(),
+                    chat_jid,
+                    msg.sender_jid.clone(),
+                    msg.sender_name.clone(),
+                    msg.content.clone(),
+                    i32::from(msg.outgoing),
+                    msg.status as i32,
+                    msg.timestamp.timestamp(),
+                    media_type,
+                    media_data,
+                    i32::from(msg.revoked),
+                    i32::from(msg.edited),
+                    msg.kind as i32
+                ],
+            )
+            .await?;
+        self.save_message_details(msg).await?;
+
+        // Update chat's last_message_time.
+        self.conn
+            .execute(
+                "UPDATE chats SET last_message_time = ?1 WHERE jid = ?2",
+                libsql::params![msg.timestamp.timestamp(), chat_jid],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Save what a message carries besides its text, and index its links,
+    /// the same way for live and synced messages.
+    async fn save_message_details(&self, msg: &ChatMessage) -> Result<(), libsql::Error> {
+        if let Some(ref poll) = msg.poll {
+            self.save_poll(&msg.server_id, poll).await?;
+        }
+        if let Some(ref location) = msg.location {
+            self.save_location(&msg.server_id, location).await?;
+        }
+        if !msg.contacts.is_empty() {
+            self.save_contact_cards(&msg.server_id, &msg.contacts)
+                .await?;
+        }
+        if let Some(ref media) = msg.media
+            && (media.durations_secs.is_some() || media.waveform.is_some() || media.can_download())
+        {
+            self.save_media_details(&msg.server_id, media).await?;
+        }
+        // Sent replies get their quote saved once the server acknowledges them.
+        if let Some(ref quote) = msg.quote
+            && !msg.server_id.is_empty()
+        {
+            self.save_quote(&msg.server_id, quote).await?;
+        }
+        if let Some(ref preview) = msg.preview
+            && !msg.server_id.is_empty()
+        {
+            self.save_link_preview(&msg.server_id, preview).await?;
+        }
+        self.index_links(&msg.server_id, &msg.content).await?;
+
+        Ok(())
+    }
+
+    /// Save a synced message, skipping duplicates on both `local_id` and `server_id`.
+    /// Also ensures the chat exists to satisfy the foreign key constraint.
+    /// Returns `true` if the message was inserted, `false` if it was a duplicate.
+    pub async fn save_synced_message(
+        &self,
+        chat_jid: &str,
+        msg: &ChatMessage,
+    ) -> Result<bool, libsql::Error> {
+        self.ensure_chat_exists(chat_jid).await?;
+
+        let media_type = msg.media.as_ref().map(|m| format!("{:?}", m.r#type));
+        let media_data = msg.media.as_ref().map(|m| m.data.as_ref().clone());
+
+        let rows = self
+            .conn
+            .execute(
+                r"
+            INSERT OR IGNORE INTO messages (local_id, server_id, chat_jid, sender_jid, sender_name,
+                                            content, outgoing, status, timestamp, media_type, media_data,
+                                            revoked, edited, kind)
+            VALUES (?1, NULLIF(?2, ''), ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+            ",
+                libsql::params![
+                    msg.local_id.to_string(),
+                    msg.server_id.clone(),
+                    chat_jid,
+                    msg.sender_jid.clone(),
+                    msg.sender_name.clone(),
+                    msg.content.clone(),
+                    i32::from(msg.outgoing),
+                    msg.status as i32,
+                    msg.timestamp.timestamp(),
+                    media_type,
+                    media_data,
+                    i32::from(msg.revoked),
+                    i32::from(msg.edited),
+                    msg.kind as i32
+                ],
+            )
+            .await?;
+
+        if rows > 0 {
+            self.conn
+                .execute(
+                    "UPDATE chats SET last_message_time = ?1 WHERE jid = ?2",
+                    libsql::params![msg.timestamp.timestamp(), chat_jid],
+                )
+                .await?;
+            self.save_message_details(msg).await?;
+        }
+
+        Ok(rows > 0)
+    }
+
+    pub async fn load_message_by_local_id(
+        &self,
+        chat_jid: &str,
+        msg_id: &Uuid,
+    ) -> Result<Option<ChatMessage>, libsql::Error> {
+        let mut rows = self.conn.query(
+            r"
+            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, media_data, revoked, edited, kind
+            FROM messages
+            WHERE chat_jid = ?1 AND local_id = ?2
+            ORDER BY timestamp DESC
+            LIMIT 1
+            ",
+            libsql::params![chat_jid, msg_id.to_string()],
+        ).await?;
+
+        if let Some(row) = rows.next().await? {
+            let media = row.get::<String>(9).map_or(None, |media_type| {
+                row.get::<Vec<u8>>(10).map_or(None, |data| {
+                    let media_type: MediaType = media_type.into();
+
+                    Some(Media {
+                        data: Arc::new(data),
+                        r#type: media_type,
+                        mime_type: media_type.guess_mime_type(),
+                        ..Default::default()
+                    })
+                })
+            });
+
+            Ok(Some(ChatMessage {
+                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
+                server_id: row.get(1)?,
+                chat_jid: row.get(2)?,
+                sender_jid: row.get(3)?,
+                sender_name: row.get(4).ok(),
+
+                kind: MessageKind::from(row.get::<i32>(13)?),
+                poll: None,
+                media,
+                edited: row.get::<i32>(12)? != 0,
+                status: MessageStatus::from(row.get::<i32>(7)?),
+                content: row.get(5)?,
+                revoked: row.get::<i32>(11)? != 0,
+                contacts: Vec::new(),
+                location: None,
+                quote: None,
+                preview: None,
+                outgoing: row.get::<i32>(6)? != 0,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
+                reactions: IndexMap::new(),
+
+                db: Arc::new(self.clone()),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn load_message_by_server_id(
+        &self,
+        chat_jid: &str,
+        msg_id: &str,
+    ) -> Result<Option<ChatMessage>, libsql::Error> {
+        let mut rows = self.conn.query(
+            r"
+            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, media_data, revoked, edited, kind
+            FROM messages
+            WHERE chat_jid = ?1 AND server_id = ?2
+            ORDER BY timestamp DESC
+            LIMIT 1
+            ",
+            libsql::params![chat_jid, msg_id],
+        ).await?;
+
+        if let Some(row) = rows.next().await? {
+            let media = row.get::<String>(9).map_or(None, |media_type| {
+                row.get::<Vec<u8>>(10).map_or(None, |data| {
+                    let media_type: MediaType = media_type.into();
+
+                    Some(Media {
+                        data: Arc::new(data),
+                        r#type: media_type,
+                        mime_type: media_type.guess_mime_type(),
+                        ..Default::default()
+                    })
+                })
+            });
+
+            Ok(Some(ChatMessage {
+                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
+                server_id: row.get(1)?,
+                chat_jid: row.get(2)?,
+                sender_jid: row.get(3)?,
+                sender_name: row.get(4).ok(),
+
+                kind: MessageKind::from(row.get::<i32>(13)?),
+                poll: None,
+                media,
+                edited: row.get::<i32>(12)? != 0,
+                status: MessageStatus::from(row.get::<i32>(7)?),
+                content: row.get(5)?,
+                revoked: row.get::<i32>(11)? != 0,
+                contacts: Vec::new(),
+                location: None,
+                quote: None,
+                preview: None,
+                outgoing: row.get::<i32>(6)? != 0,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
+                reactions: IndexMap::new(),
+
+                db: Arc::new(self.clone()),
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn load_messages(
+        &self,
+        chat_jid: &str,
+        limit: u32,
+    ) -> Result<Vec<ChatMessage>, libsql::Error> {
+        let mut rows = self.conn.query(
+            r"
+            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, media_data, revoked, edited, kind
+            FROM messages
+            WHERE chat_jid = ?1
+            ORDER BY timestamp DESC
+            LIMIT ?2
+            ",
+            libsql::params![chat_jid, limit],
+        ).await?;
+
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let media = row.get::<String>(9).map_or(None, |media_type| {
+                row.get::<Vec<u8>>(10).map_or(None, |data| {
+                    let media_type: MediaType = media_type.into();
+
+                    Some(Media {
+                        data: Arc::new(data),
+                        r#type: media_type,
+                        mime_type: media_type.guess_mime_type(),
+                        ..Default::default()
+                    })
+                })
+            });
+
+            messages.push(ChatMessage {
+                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
+                server_id: row.get(1)?,
+                chat_jid: row.get(2)?,
+                sender_jid: row.get(3)?,
+                sender_name: row.get(4).ok(),
+
+                kind: MessageKind::from(row.get::<i32>(13)?),
+                poll: None,
+                media,
+                edited: row.get::<i32>(12)? != 0,
+                status: MessageStatus::from(row.get::<i32>(7)?),
+                content: row.get(5)?,
+                revoked: row.get::<i32>(11)? != 0,
+                contacts: Vec::new(),
+                location: None,
+                quote: None,
+                preview: None,
+                outgoing: row.get::<i32>(6)? != 0,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
+                reactions: IndexMap::new(),
+
+                db: Arc::new(self.clone()),
+            });
+        }
+
+        self.attach_details(&mut messages).await?;
+
+        Ok(messages)
+    }
+
+    /// Load the downloaded images of a chat, oldest first, leaving out their
+    /// data to be loaded with [`Self::load_media_data`] when shown.
+    pub async fn load_media_messages(
+        &self,
+        chat_jid: &str,
+    ) -> Result<Vec<ChatMessage>, libsql::Error> {
+        let mut rows = self.conn.query(
+            r"
+            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, revoked, edited, kind
+            FROM messages
+            WHERE chat_jid = ?1 AND media_type = 'Image' AND LENGTH(media_data) > 0 AND revoked = 0
+            ORDER BY timestamp ASC
+            ",
+            libsql::params![chat_jid],
+        ).await?;
+
+        self.media_rows(&mut rows).await
+    }
+
+    /// Load the downloaded data of a media message.
+    pub async fn load_media_data(
+        &self,
+        chat_jid: &str,
+        msg_id: &str,
+    ) -> Result<Option<Vec<u8>>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT media_data FROM messages WHERE chat_jid = ?1 AND server_id = ?2 AND LENGTH(media_data) > 0 LIMIT 1",
+                libsql::params![chat_jid, msg_id],
+            )
+            .await?;
+
+        match rows.next().await? {
+            Some(row) => Ok(Some(row.get::<Vec<u8>>(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Read media messages selected without their data.
+    async fn media_rows(&self, rows: &mut libsql::Rows) -> Result<Vec<ChatMessage>, libsql::Error> {
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let media_type: MediaType = row.get::<String>(9)?.into();
+
+            messages.push(ChatMessage {
+                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
+                server_id: row.get(1)?,
+                chat_jid: row.get(2)?,
+                sender_jid: row.get(3)?,
+                sender_name: row.get(4).ok(),
+
+                kind: MessageKind::from(row.get::<i32>(12)?),
+                poll: None,
+                media: Some(Media {
+                    r#type: media_type,
+                    mime_type: media_type.guess_mime_type(),
+                    ..Default::default()
+                }),
+                edited: row.get::<i32>(11)? != 0,
+                status: MessageStatus::from(row.get::<i32>(7)?),
+                content: row.get(5)?,
+                revoked: false,
+                contacts: Vec::new(),
+                location: None,
+                quote: None,
+                preview: None,
+                outgoing: row.get::<i32>(6)? != 0,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
+                reactions: IndexMap::new(),
+
+                db: Arc::new(self.clone()),
+            });
+        }
+
+        Ok(messages)
+    }
+
+    /// Load messages after a specific time (newer messages).
+    pub async fn load_messages_after(
+        &self,
+        chat_jid: &str,
+        after_timestamp: i64,
+        limit: u32,
+    ) -> Result<Vec<ChatMessage>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, revoked, edited, kind
+            FROM messages
+            WHERE chat_jid = ?1 AND timestamp > ?2
+            ORDER BY timestamp ASC
+            LIMIT ?3
+            ",
+                libsql::params![chat_jid, after_timestamp, limit],
+            )
+            .await?;
+
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next().await? {
+            messages.push(ChatMessage {
+                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
+                server_id: row.get(1)?,
+                chat_jid: row.get(2)?,
+                sender_jid: row.get(3)?,
+                sender_name: row.get(4).ok(),
+
+                kind: MessageKind::from(row.get::<i32>(11)?),
+                poll: None,
+                media: None,
+                edited: row.get::<i32>(10)? != 0,
+                status: MessageStatus::from(row.get::<i32>(7)?),
+                content: row.get(5)?,
+                revoked: row.get::<i32>(9)? != 0,
+                contacts: Vec::new(),
+                location: None,
+                quote: None,
+                preview: None,
+                outgoing: row.get::<i32>(6)? != 0,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
+                reactions: IndexMap::new(),
+
+                db: Arc::new(self.clone()),
+            });
+        }
+
+        self.attach_details(&mut messages).await?;
+
+        Ok(messages)
+    }
+
+    /// Load messages around a specific time, oldest first, the ones from
+    /// that time on taking half of the `limit`.
+    pub async fn load_messages_around(
+        &self,
+        chat_jid: &str,
+        timestamp: i64,
+        limit: u32,
+    ) -> Result<Vec<ChatMessage>, libsql::Error> {
+        let mut messages = self
+            .load_messages_before(chat_jid, timestamp, limit / 2)
+            .await?;
+        messages.reverse();
+        messages.extend(
+            self.load_messages_after(chat_jid, timestamp - 1, limit - limit / 2)
+                .await?,
+        );
+
+        Ok(messages)
+    }
+
+    /// Get the times of the oldest and newest messages in a chat.
+    pub async fn get_chat_date_range(
+        &self,
+        chat_jid: &str,
+    ) -> Result<Option<(i64, i64)>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT MIN(timestamp), MAX(timestamp) FROM messages WHERE chat_jid = ?1",
+                [chat_jid],
+            )
+            .await?;
+
+        let Some(row) = rows.next().await? else {
+            return Ok(None);
+        };
+
+        Ok(row.get::<Option<i64>>(0)?.zip(row.get::<Option<i64>>(1)?))
+    }
+
+    /// Get the local days with messages in a chat between `start` and `end`,
+    /// oldest first.
+    pub async fn get_message_days(
+        &self,
+        chat_jid: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<NaiveDate>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT DISTINCT date(timestamp, 'unixepoch', 'localtime') AS day
+            FROM messages
+            WHERE chat_jid = ?1 AND timestamp >= ?2 AND timestamp < ?3
+            ORDER BY day
+            ",
+                libsql::params![chat_jid, start, end],
+            )
+            .await?;
+
+        let mut days = Vec::new();
+        while let Some(row) = rows.next().await? {
+            if let Ok(day) = row.get_str(0)?.parse() {
+                days.push(day);
+            }
+        }
+
+        Ok(days)
+    }
+
+    /// Get the time of the first message between `start` and `end`, or of
+    /// the nearest one before them if there's none.
+    pub async fn find_message_time_between(
+        &self,
+        chat_jid: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Option<i64>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT COALESCE(
+                (SELECT MIN(timestamp) FROM messages WHERE chat_jid = ?1 AND timestamp >= ?2 AND timestamp < ?3),
+                (SELECT MAX(timestamp) FROM messages WHERE chat_jid = ?1 AND timestamp < ?2),
+                (SELECT MIN(timestamp) FROM messages WHERE chat_jid = ?1)
+            )
+            ",
+                libsql::params![chat_jid, start, end],
+            )
+            .await?;
+
+        rows.next()
+            .await?
+            .map_or(Ok(None), |row| row.get::<Option<i64>>(0))
+    }
+
+    /// Load messages from before a specific time (older messages).
+    pub async fn load_messages_before(
+        &self,
+        chat_jid: &str,
+        before_timestamp: i64,
+        limit: u32,
+    ) -> Result<Vec<ChatMessage>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, revoked, edited, kind
+            FROM messages
+            WHERE chat_jid = ?1 AND timestamp < ?2
+            ORDER BY timestamp DESC
+            LIMIT ?3
+            ",
+                libsql::params![chat_jid, before_timestamp, limit],
+            )
+            .await?;
+
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next().await? {
+            messages.push(ChatMessage {
+                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
+                server_id: row.get(1)?,
+                chat_jid: row.get(2)?,
+                sender_jid: row.get(3)?,
+                sender_name: row.get(4).ok(),
+
+                kind: MessageKind::from(row.get::<i32>(11)?),
+                poll: None,
+                media: None,
+                edited: row.get::<i32>(10)? != 0,
+                status: MessageStatus::from(row.get::<i32>(7)?),
+                content: row.get(5)?,
+                revoked: row.get::<i32>(9)? != 0,
+                contacts: Vec::new(),
+                location: None,
+                quote: None,
+                preview: None,
+                outgoing: row.get::<i32>(6)? != 0,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
+                reactions: IndexMap::new(),
+
+                db: Arc::new(self.clone()),
+            });
+        }
+
+        self.attach_details(&mut messages).await?;
+
+        Ok(messages)
+    }
+
+    /// Replace a message deleted for everyone with a tombstone, if `actor`
+    /// sent it or is an admin of its group.
+    /// Returns `true` if the message was found.
+    pub async fn revoke_message(
+        &self,
+        chat_jid: &str,
+        message_id: &str,
+        actor: &MessageActor,
+    ) -> Result<bool, libsql::Error> {
+        let sql = format!(
+            r"
+            UPDATE messages
+            SET content = '', media_type = NULL, media_data = NULL, revoked = 1
+            WHERE chat_jid = ?1 AND server_id = ?2 AND ({} OR EXISTS (
+                SELECT 1 FROM group_admins
+                WHERE group_admins.chat_jid = messages.chat_jid AND group_admins.jid IN ({})
+            ))
+            ",
+            sent_by(actor, 3),
+            placeholders(3, actor.jids().len())
+        );
+        let mut params = vec![
+            libsql::Value::Text(chat_jid.to_string()),
+            libsql::Value::Text(message_id.to_string()),
+        ];
+        params.extend(actor.jids().iter().cloned().map(libsql::Value::Text));
+
+        let rows = self.conn.execute(&sql, Params::Positional(params)).await?;
+        if rows > 0 {
+            self.index_links(message_id, "").await?;
+        }
+
+        Ok(rows > 0)
+    }
+
+    /// Replace the text of a message, if `actor` sent it.
+    /// Returns `true` if the message was found.
+    pub async fn edit_message(
+        &self,
+        chat_jid: &str,
+        message_id: &str,
+        actor: &MessageActor,
+        content: &str,
+    ) -> Result<bool, libsql::Error> {
+        let sql = format!(
+            r"
+            UPDATE messages SET content = ?1, edited = 1
+            WHERE chat_jid = ?2 AND server_id = ?3 AND revoked = 0 AND {}
+            ",
+            sent_by(actor, 4)
+        );
+        let mut params = vec![
+            libsql::Value::Text(content.to_string()),
+            libsql::Value::Text(chat_jid.to_string()),
+            libsql::Value::Text(message_id.to_string()),
+        ];
+        if let MessageActor::Contact(jids) = actor {
+            params.extend(jids.iter().cloned().map(libsql::Value::Text));
+        }
+
+        let rows = self.conn.execute(&sql, Params::Positional(params)).await?;
         if rows > 0 {
+            self.index_links(message_id, content).await?;
+        }
+
+        Ok(rows > 0)
+    }
+
+    pub async fn delete_message(&self, message_id: &str) -> Result<(), libsql::Error> {
+        self.conn
+            .execute("DELETE FROM messages WHERE server_id = ?1", [message_id])
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_unread_count(&self, chat_jid: &str) -> Result<usize, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT COUNT(*) FROM messages WHERE chat_jid = ?1 AND status != 1 AND outgoing == 0",
+                [chat_jid],
+            )
+            .await?;
+
+        if let Some(row) = rows.next().await? {
+            Ok(usize::try_from(row.get::<u64>(0)?).unwrap_or(0))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Get the time of the oldest unread incoming message of a chat.
+    pub async fn get_first_unread_time(
+        &self,
+        chat_jid: &str,
+    ) -> Result<Option<i64>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT MIN(timestamp) FROM messages WHERE chat_jid = ?1 AND status != 1 AND outgoing == 0",
+                [chat_jid],
+            )
+            .await?;
+
+        rows.next()
+            .await?
+            .map_or(Ok(None), |row| row.get::<Option<i64>>(0))
+    }
+
+    /// Get the count of unread messages across all chats, leaving out those
+    /// of muted chats unless `include_muted` is set.
+    pub async fn get_total_unread_count(
+        &self,
+        include_muted: bool,
+    ) -> Result<usize, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT COUNT(*) FROM messages m
+            JOIN chats c ON c.jid = m.chat_jid
+            WHERE m.status != 1 AND m.outgoing == 0
+              AND (?1 OR c.muted == 0
+                   OR c.muted_until <= CAST(strftime('%s', 'now') AS INTEGER))
+            ",
+                [include_muted],
+            )
+            .await?;
+
+        if let Some(row) = rows.next().await? {
+            Ok(usize::try_from(row.get::<u64>(0)?).unwrap_or(0))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Get the time of the newest incoming message already read in a chat.
+    pub async fn get_last_read_time(&self, chat_jid: &str) -> Result<Option<i64>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT MAX(timestamp) FROM messages WHERE chat_jid = ?1 AND status = 1 AND outgoing == 0 AND kind != 1",
+                [chat_jid],
+            )
+            .await?;
+
+        rows.next()
+            .await?
+            .map_or(Ok(None), |row| row.get::<Option<i64>>(0))
+    }
+
+    /// Get the unread incoming messages of a chat, oldest first.
+    pub async fn get_unread_messages(
+        &self,
+        chat_jid: &str,
+    ) -> Result<Vec<ChatMessage>, libsql::Error> {
+        let mut rows = self.conn.query(
+            r"
+            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, media_data, revoked, edited, kind
+            FROM messages
+            WHERE chat_jid = ?1 AND status != 1 AND outgoing == 0
+            ORDER BY timestamp ASC
+            ",
+            libsql::params![chat_jid],
+        ).await?;
+
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let media = row.get::<String>(9).map_or(None, |media_type| {
+                row.get::<Vec<u8>>(10).map_or(None, |data| {
+                    let media_type: MediaType = media_type.into();
+
+                    Some(Media {
+                        data: Arc::new(data),
+                        r#type: media_type,
+                        mime_type: media_type.guess_mime_type(),
+                        ..Default::default()
+                    })
+                })
+            });
+
+            messages.push(ChatMessage {
+                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
+                server_id: row.get(1)?,
+                chat_jid: row.get(2)?,
+                sender_jid: row.get(3)?,
+                sender_name: row.get(4).ok(),
+
+                kind: MessageKind::from(row.get::<i32>(13)?),
+                poll: None,
+                media,
+                edited: row.get::<i32>(12)? != 0,
+                status: MessageStatus::from(row.get::<i32>(7)?),
+                content: row.get(5)?,
+                revoked: row.get::<i32>(11)? != 0,
+                contacts: Vec::new(),
+                location: None,
+                quote: None,
+                preview: None,
+                outgoing: row.get::<i32>(6)? != 0,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
+                reactions: IndexMap::new(),
+
+                db: Arc::new(self.clone()),
+            });
+        }
+
+        Ok(messages)
+    }
+
+    /// Mark every unread incoming message of a chat as read.
+    pub async fn mark_chat_read(&self, chat_jid: &str) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                "UPDATE messages SET status = 1 WHERE chat_jid = ?1 AND status != 1 AND outgoing == 0",
+                [chat_jid],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Gallery operations.
+impl Database {
+    /// Replace the indexed links of a message with those in its text.
+    async fn index_links(&self, message_id: &str, content: &str) -> Result<(), libsql::Error> {
+        // Messages are only referenced once the server acknowledges them.
+        if message_id.is_empty() {
+            return Ok(());
+        }
+
+        self.conn
+            .execute(
+                "DELETE FROM message_links WHERE message_id = ?1",
+                [message_id],
+            )
+            .await?;
+
+        for url in extract_links(content) {
+            self.conn
+                .execute(
+                    r"
+                INSERT OR IGNORE INTO message_links (message_id, url, chat_jid, timestamp)
+                SELECT server_id, ?2, chat_jid, timestamp FROM messages WHERE server_id = ?1
+                ",
+                    libsql::params![message_id, url],
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Index the links of all stored messages.
+    async fn index_stored_links(&self) -> Result<(), libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT server_id, content FROM messages
+            WHERE server_id IS NOT NULL AND revoked = 0
+              AND (content LIKE '%http%' OR content LIKE '%www.%')
+            ",
+                (),
+            )
+            .await?;
+
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next().await? {
+            messages.push((row.get::<String>(0)?, row.get::<String>(1)?));
+        }
+
+        for (message_id, content) in messages {
+            self.index_links(&message_id, &content).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Load a page of the images and videos of a chat, newest first,
+    /// leaving out their data to be loaded with [`Self::load_media_data`].
+    pub async fn load_chat_media(
+        &self,
+        chat_jid: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<ChatMessage>, libsql::Error> {
+        let mut rows = self.conn.query(
+            r"
+            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, revoked, edited, kind
+            FROM messages
+            WHERE chat_jid = ?1 AND media_type IN ('Image', 'Video') AND revoked = 0
+            ORDER BY timestamp DESC
+            LIMIT ?2 OFFSET ?3
+            ",
+            libsql::params![chat_jid, limit, offset],
+        ).await?;
+
+        self.media_rows(&mut rows).await
+    }
+
+    /// Load a page of the documents of a chat, newest first.
+    pub async fn load_chat_documents(
+        &self,
+        chat_jid: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<ChatDocument>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT m.server_id, m.chat_jid, m.content,
+                   COALESCE(d.file_length, LENGTH(m.media_data), 0), m.timestamp
+            FROM messages m
+            LEFT JOIN media_details d ON d.message_id = m.server_id
+            WHERE m.chat_jid = ?1 AND m.media_type = 'Document' AND m.revoked = 0
+            ORDER BY m.timestamp DESC
+            LIMIT ?2 OFFSET ?3
+            ",
+                libsql::params![chat_jid, limit, offset],
+            )
+            .await?;
+
+        let mut documents = Vec::new();
+        while let Some(row) = rows.next().await? {
+            documents.push(ChatDocument {
+                size: row.get::<u64>(3)?,
+                content: row.get::<String>(2).unwrap_or_default(),
+                chat_jid: row.get(1)?,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(4)?, 0).unwrap_or_else(Utc::now),
+                message_id: row.get::<String>(0).unwrap_or_default(),
+            });
+        }
+
+        Ok(documents)
+    }
+
+    /// Load a page of the links sent in a chat, newest first.
+    pub async fn load_chat_links(
+        &self,
+        chat_jid: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<ChatLink>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT l.url, l.timestamp, l.message_id, p.title
+            FROM message_links l
+            LEFT JOIN link_previews p ON p.message_id = l.message_id AND p.url = l.url
+            WHERE l.chat_jid = ?1
+            ORDER BY l.timestamp DESC, l.url
+            LIMIT ?2 OFFSET ?3
+            ",
+                libsql::params![chat_jid, limit, offset],
+            )
+            .await?;
+
+        let mut links = Vec::new();
+        while let Some(row) = rows.next().await? {
+            links.push(ChatLink {
+                url: row.get(0)?,
+                title: row.get(3)?,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(1)?, 0).unwrap_or_else(Utc::now),
+                message_id: row.get(2)?,
+            });
+        }
+
+        Ok(links)
+    }
+}
+
+/// Pinned message operations.
+impl Database {
+    /// Pin a message, until `expires_at` if it's set.
+    pub async fn pin_message(
+        &self,
+        chat_jid: &str,
+        message_id: &str,
+        pinned_at: DateTime<Utc>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT INTO pinned_messages (chat_jid, message_id, pinned_at, expires_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(chat_jid, message_id) DO UPDATE SET
+                pinned_at = excluded.pinned_at,
+                expires_at = excluded.expires_at
+            ",
+                libsql::params![
+                    chat_jid,
+                    message_id,
+                    pinned_at.timestamp(),
+                    expires_at.map(|t| t.timestamp())
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn unpin_message(
+        &self,
+        chat_jid: &str,
+        message_id: &str,
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                "DELETE FROM pinned_messages WHERE chat_jid = ?1 AND message_id = ?2",
+                [chat_jid, message_id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Load the IDs of the pinned messages of a chat with when their pins
+    /// end, latest pin first. Pins ended by `now` are dropped.
+    pub async fn load_pinned_message_ids(
+        &self,
+        chat_jid: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<(String, Option<DateTime<Utc>>)>, libsql::Error> {
+        self.conn
+            .execute(
+                "DELETE FROM pinned_messages WHERE chat_jid = ?1 AND expires_at <= ?2",
+                libsql::params![chat_jid, now.timestamp()],
+            )
+            .await?;
+
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT message_id, expires_at FROM pinned_messages
+            WHERE chat_jid = ?1
+            ORDER BY pinned_at DESC
+            ",
+                [chat_jid],
+            )
+            .await?;
+
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let expires_at = row
+                .get::<Option<i64>>(1)?
+                .and_then(|t| DateTime::from_timestamp(t, 0));
+            ids.push((row.get(0)?, expires_at));
+        }
+
+        Ok(ids)
+    }
+}
+
+/// A message bookmarked on this device only, unlike stars which sync.
+#[derive(Clone, Debug)]
+pub struct Bookmark {
+    /// Note about the bookmark.
+    pub note: Option<String>,
+    /// Text of the message, if it's still stored.
+    pub content: Option<String>,
+    /// JID of the chat the message is in.
+    pub chat_jid: String,
+    /// When the message was sent, if it's still stored.
+    pub timestamp: Option<DateTime<Utc>>,
+    /// When the message was bookmarked.
+    pub created_at: DateTime<Utc>,
+    /// Server ID of the message.
+    pub message_id: String,
+}
+
+/// Bookmark operations.
+impl Database {
+    pub async fn add_bookmark(
+        &self,
+        chat_jid: &str,
+        message_id: &str,
+        note: Option<&str>,
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT INTO bookmarks (chat_jid, message_id, note, created_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(chat_jid, message_id) DO UPDATE SET
+                note = excluded.note
+            ",
+                libsql::params![chat_jid, message_id, note, Utc::now().timestamp()],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_bookmark(
+        &self,
+        chat_jid: &str,
+        message_id: &str,
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                "DELETE FROM bookmarks WHERE chat_jid = ?1 AND message_id = ?2",
+                [chat_jid, message_id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Count the bookmarks in a chat.
+    pub async fn count_chat_bookmarks(&self, chat_jid: &str) -> Result<usize, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT COUNT(*) FROM bookmarks WHERE chat_jid = ?1",
+                [chat_jid],
+            )
+            .await?;
+
+        if let Some(row) = rows.next().await? {
+            Ok(usize::try_from(row.get::<u64>(0)?).unwrap_or(0))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Load all bookmarks, grouped by chat and oldest message first.
+    pub async fn load_bookmarks(&self) -> Result<Vec<Bookmark>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT b.chat_jid, b.message_id, b.note, b.created_at, m.content, m.timestamp
+            FROM bookmarks b
+            LEFT JOIN messages m ON m.chat_jid = b.chat_jid AND m.server_id = b.message_id
+            ORDER BY b.chat_jid, m.timestamp, b.created_at
+            ",
+                (),
+            )
+            .await?;
+
+        let mut bookmarks = Vec::new();
+        while let Some(row) = rows.next().await? {
+            bookmarks.push(Bookmark {
+                note: row.get(2).ok(),
+                content: row.get(4).ok(),
+                chat_jid: row.get(0)?,
+                timestamp: row
+                    .get::<i64>(5)
+                    .ok()
+                    .and_then(|t| DateTime::from_timestamp(t, 0)),
+                created_at: DateTime::from_timestamp(row.get::<i64>(3)?, 0).unwrap_or_default(),
+                message_id: row.get(1)?,
+            });
+        }
+
+        Ok(bookmarks)
+    }
+}
+
+/// Read receipts for a sender's messages, kept until they're sent.
+#[derive(Clone, Debug)]
+pub struct PendingReceipt {
+    /// ID to match the result of sending them.
+    pub id: Uuid,
+    /// Times sending them failed.
+    pub attempts: u32,
+    /// JID of the chat the messages are in.
+    pub chat_jid: String,
+    /// JID of who sent the messages, in groups.
+    pub sender_jid: Option<String>,
+    /// Server IDs of the messages.
+    pub message_ids: Vec<String>,
+}
+
+/// Pending read receipt operations.
+impl Database {
+    pub async fn add_pending_receipt(&self, receipt: &PendingReceipt) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT INTO pending_receipts (id, chat_jid, sender_jid, message_ids, attempts)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ",
+                libsql::params![
+                    receipt.id.to_string(),
+                    receipt.chat_jid.as_str(),
+                    receipt.sender_jid.as_deref(),
+                    receipt.message_ids.join(","),
+                    receipt.attempts,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_pending_receipt(&self, id: Uuid) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                "DELETE FROM pending_receipts WHERE id = ?1",
+                [id.to_string()],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Count a failure to send receipts, returning them with the new count.
+    pub async fn fail_pending_receipt(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<PendingReceipt>, libsql::Error> {
+        self.conn
+            .execute(
+                "UPDATE pending_receipts SET attempts = attempts + 1 WHERE id = ?1",
+                [id.to_string()],
+            )
+            .await?;
+
+        Ok(self
+            .query_pending_receipts("WHERE id = ?1", [id.to_string()])
+            .await?
+            .pop())
+    }
+
+    /// Load all the receipts still to be sent, oldest first.
+    pub async fn load_pending_receipts(&self) -> Result<Vec<PendingReceipt>, libsql::Error> {
+        self.query_pending_receipts("ORDER BY rowid", ()).await
+    }
+
+    async fn query_pending_receipts(
+        &self,
+        clause: &str,
+        params: impl IntoParams,
+    ) -> Result<Vec<PendingReceipt>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                &format!(
+                    "SELECT id, chat_jid, sender_jid, message_ids, attempts FROM pending_receipts {clause}"
+                ),
+                params,
+            )
+            .await?;
+
+        let mut receipts = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let Ok(id) = Uuid::parse_str(row.get_str(0)?) else {
+                continue;
+            };
+
+            receipts.push(PendingReceipt {
+                id,
+                attempts: row.get(4)?,
+                chat_jid: row.get(1)?,
+                sender_jid: row.get(2).ok(),
+                message_ids: row.get_str(3)?.split(',').map(str::to_string).collect(),
+            });
+        }
+
+        Ok(receipts)
+    }
+}
+
+/// Poll operations.
+impl Database {
+    /// Save a poll attached to a message, keeping its votes.
+    pub async fn save_poll(&self, message_id: &str, poll: &Poll) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT OR IGNORE INTO polls (message_id, question, multi_select, secret)
+            VALUES (?1, ?2, ?3, ?4)
+            ",
+                libsql::params![
+                    message_id,
+                    poll.question.clone(),
+                    i32::from(poll.multi_select),
+                    poll.secret.clone()
+                ],
+            )
+            .await?;
+
+        for (position, name) in (0_i64..).zip(&poll.options) {
             self.conn
                 .execute(
-                    "UPDATE chats SET last_message_time = ?1 WHERE jid = ?2",
-                    libsql::params![msg.timestamp.timestamp(), chat_jid],
+                    r"
+                INSERT OR IGNORE INTO poll_options (message_id, position, name)
+                VALUES (?1, ?2, ?3)
+                ",
+                    libsql::params![message_id, position, name.clone()],
                 )
                 .await?;
         }
 
-        Ok(rows > 0)
+        Ok(())
     }
 
-    pub async fn load_message_by_local_id(
-        &self,
-        chat_jid: &str,
-        msg_id: &Uuid,
-    ) -> Result<Option<ChatMessage>, libsql::Error> {
-        let mut rows = self.conn.query(
-            r"
-            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, media_data
-            FROM messages
-            WHERE chat_jid = ?1 AND local_id = ?2
-            ORDER BY timestamp DESC
-            LIMIT 1
-            ",
-            libsql::params![chat_jid, msg_id.to_string()],
-        ).await?;
+    /// Attach their polls, locations, contact cards, media details, quotes,
+    /// link previews and reactions to loaded messages, with a query per table.
+    async fn attach_details(&self, messages: &mut [ChatMessage]) -> Result<(), libsql::Error> {
+        let index: HashMap<String, usize> = messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| !m.server_id.is_empty())
+            .map(|(i, m)| (m.server_id.clone(), i))
+            .collect();
+        if index.is_empty() {
+            return Ok(());
+        }
+        let ids = index.keys().cloned().collect::<Vec<_>>();
 
-        if let Some(row) = rows.next().await? {
-            let media = row.get::<String>(9).map_or(None, |media_type| {
-                row.get::<Vec<u8>>(10).map_or(None, |data| {
-                    let media_type: MediaType = media_type.into();
+        let mut polls: HashMap<String, Poll> = HashMap::new();
+        for row in self
+            .query_with_ids(
+                "SELECT message_id, question, multi_select, secret FROM polls WHERE message_id IN ({ids})",
+                &ids,
+            )
+            .await?
+        {
+            polls.insert(
+                row.get(0)?,
+                Poll {
+                    secret: row.get(3).ok(),
+                    question: row.get(1)?,
+                    multi_select: row.get::<i32>(2)? != 0,
+                    ..Default::default()
+                },
+            );
+        }
+        if !polls.is_empty() {
+            for row in self
+                .query_with_ids(
+                    "SELECT message_id, name FROM poll_options WHERE message_id IN ({ids}) ORDER BY position",
+                    &ids,
+                )
+                .await?
+            {
+                if let Some(poll) = polls.get_mut(row.get_str(0)?) {
+                    poll.options.push(row.get(1)?);
+                }
+            }
+            for row in self
+                .query_with_ids(
+                    "SELECT message_id, voter_jid, option_name FROM poll_votes WHERE message_id IN ({ids})",
+                    &ids,
+                )
+                .await?
+            {
+                if let Some(poll) = polls.get_mut(row.get_str(0)?) {
+                    poll.votes.entry(row.get(1)?).or_default().push(row.get(2)?);
+                }
+            }
+        }
+        for (id, poll) in polls {
+            messages[index[&id]].poll = Some(poll);
+        }
 
-                    Some(Media {
-                        data: Arc::new(data),
-                        r#type: media_type,
-                        mime_type: media_type.guess_mime_type(),
-                        ..Default::default()
-                    })
-                })
+        for row in self
+            .query_with_ids(
+                r"
+            SELECT message_id, latitude, longitude, name, address, live, thumbnail
+            FROM locations WHERE message_id IN ({ids})
+            ",
+                &ids,
+            )
+            .await?
+        {
+            messages[index[row.get_str(0)?]].location = Some(Location {
+                live: row.get::<i32>(5)? != 0,
+                name: row.get(3).ok(),
+                address: row.get(4).ok(),
+                latitude: row.get(1)?,
+                longitude: row.get(2)?,
+                thumbnail: row.get::<Vec<u8>>(6).ok().map(Arc::new),
             });
+        }
 
-            Ok(Some(ChatMessage {
-                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
-                server_id: row.get(1)?,
-                chat_jid: row.get(2)?,
-                sender_jid: row.get(3)?,
-                sender_name: row.get(4).ok(),
+        for row in self
+            .query_with_ids(
+                "SELECT message_id, name, vcard FROM contact_cards WHERE message_id IN ({ids}) ORDER BY position",
+                &ids,
+            )
+            .await?
+        {
+            messages[index[row.get_str(0)?]].contacts.push(ContactCard {
+                name: row.get(1)?,
+                vcard: row.get(2)?,
+            });
+        }
 
-                media,
-                status: MessageStatus::from(row.get::<i32>(7)?),
+        for row in self
+            .query_with_ids(
+                r"
+            SELECT message_id, duration_secs, waveform, mime_type, media_key, direct_path,
+                   file_length, file_sha256, file_enc_sha256
+            FROM media_details WHERE message_id IN ({ids})
+            ",
+                &ids,
+            )
+            .await?
+        {
+            if let Some(ref mut media) = messages[index[row.get_str(0)?]].media {
+                media.durations_secs = row
+                    .get::<Option<i64>>(1)?
+                    .and_then(|secs| u32::try_from(secs).ok());
+                media.waveform = row.get::<Option<Vec<u8>>>(2)?;
+                if let Some(mime_type) = row.get::<Option<String>>(3)? {
+                    media.mime_type = mime_type;
+                }
+                media.downloadable = downloadable_media(&row, 4, media)?;
+            }
+        }
+
+        for row in self
+            .query_with_ids(
+                r"
+            SELECT message_id, quoted_id, sender_jid, sender_name, outgoing, content
+            FROM message_quotes WHERE message_id IN ({ids})
+            ",
+                &ids,
+            )
+            .await?
+        {
+            messages[index[row.get_str(0)?]].quote = Some(Quote {
+                id: row.get(1)?,
+                sender_jid: row.get(2)?,
+                sender_name: row.get(3).ok(),
+                outgoing: row.get::<i32>(4)? != 0,
                 content: row.get(5)?,
-                outgoing: row.get::<i32>(6)? != 0,
-                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
-                reactions: IndexMap::new(),
+            });
+        }
 
-                db: Arc::new(self.clone()),
-            }))
-        } else {
-            Ok(None)
+        for row in self
+            .query_with_ids(
+                r"
+            SELECT message_id, url, title, description, thumbnail
+            FROM link_previews WHERE message_id IN ({ids})
+            ",
+                &ids,
+            )
+            .await?
+        {
+            messages[index[row.get_str(0)?]].preview = Some(LinkPreview {
+                url: row.get(1)?,
+                title: row.get(2).ok(),
+                description: row.get(3).ok(),
+                thumbnail: row.get::<Vec<u8>>(4).ok().map(Arc::new),
+            });
+        }
+
+        for row in self
+            .query_with_ids(
+                r"
+            SELECT message_id, sender_jid, emoji
+            FROM reactions WHERE message_id IN ({ids})
+            ORDER BY timestamp
+            ",
+                &ids,
+            )
+            .await?
+        {
+            messages[index[row.get_str(0)?]]
+                .reactions
+                .entry(row.get(2)?)
+                .or_default()
+                .push(row.get(1)?);
         }
+
+        Ok(())
     }
 
-    pub async fn load_message_by_server_id(
+    /// Run a query for the given IDs, which are put in place of `{ids}` in
+    /// batches under the limit of SQL parameters.
+    async fn query_with_ids(
         &self,
-        chat_jid: &str,
-        msg_id: &str,
-    ) -> Result<Option<ChatMessage>, libsql::Error> {
-        let mut rows = self.conn.query(
-            r"
-            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, media_data
-            FROM messages
-            WHERE chat_jid = ?1 AND server_id = ?2
-            ORDER BY timestamp DESC
-            LIMIT 1
-            ",
-            libsql::params![chat_jid, msg_id],
-        ).await?;
+        sql: &str,
+        ids: &[String],
+    ) -> Result<Vec<libsql::Row>, libsql::Error> {
+        let mut result = Vec::new();
+        for batch in ids.chunks(MAX_QUERY_PARAMS) {
+            let params = batch
+                .iter()
+                .map(|id| libsql::Value::Text(id.clone()))
+                .collect::<Vec<_>>();
 
-        if let Some(row) = rows.next().await? {
-            let media = row.get::<String>(9).map_or(None, |media_type| {
-                row.get::<Vec<u8>>(10).map_or(None, |data| {
-                    let media_type: MediaType = media_type.into();
+            let mut rows = self
+                .conn
+                .query(
+                    &sql.replace("{ids}", &placeholders(1, batch.len())),
+                    Params::Positional(params),
+                )
+                .await?;
+            while let Some(row) = rows.next().await? {
+                result.push(row);
+            }
+        }
 
-                    Some(Media {
-                        data: Arc::new(data),
-                        r#type: media_type,
-                        mime_type: media_type.guess_mime_type(),
-                        ..Default::default()
-                    })
-                })
-            });
+        Ok(result)
+    }
 
-            Ok(Some(ChatMessage {
-                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
-                server_id: row.get(1)?,
-                chat_jid: row.get(2)?,
-                sender_jid: row.get(3)?,
-                sender_name: row.get(4).ok(),
+    /// Replace the options selected by a voter in a poll.
+    pub async fn save_poll_vote(
+        &self,
+        message_id: &str,
+        voter_jid: &str,
+        options: &[String],
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                "DELETE FROM poll_votes WHERE message_id = ?1 AND voter_jid = ?2",
+                [message_id, voter_jid],
+            )
+            .await?;
 
-                media,
-                status: MessageStatus::from(row.get::<i32>(7)?),
-                content: row.get(5)?,
-                outgoing: row.get::<i32>(6)? != 0,
-                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
-                reactions: IndexMap::new(),
+        for option in options {
+            self.conn
+                .execute(
+                    r"
+                INSERT OR IGNORE INTO poll_votes (message_id, voter_jid, option_name)
+                VALUES (?1, ?2, ?3)
+                ",
+                    [message_id, voter_jid, option.as_str()],
+                )
+                .await?;
+        }
 
-                db: Arc::new(self.clone()),
-            }))
+        Ok(())
+    }
+}
+
+/// Reaction operations.
+impl Database {
+    /// Set the reaction of `sender_jid` to a message, replacing their
+    /// previous one, or remove it with `None`.
+    pub async fn save_reaction(
+        &self,
+        message_id: &str,
+        sender_jid: &str,
+        emoji: Option<&str>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), libsql::Error> {
+        if let Some(emoji) = emoji {
+            self.conn
+                .execute(
+                    r"
+                INSERT OR REPLACE INTO reactions (message_id, sender_jid, emoji, timestamp)
+                VALUES (?1, ?2, ?3, ?4)
+                ",
+                    libsql::params![message_id, sender_jid, emoji, timestamp.timestamp()],
+                )
+                .await?;
         } else {
-            Ok(None)
+            self.conn
+                .execute(
+                    "DELETE FROM reactions WHERE message_id = ?1 AND sender_jid = ?2",
+                    [message_id, sender_jid],
+                )
+                .await?;
         }
+
+        Ok(())
     }
+}
 
-    pub async fn load_messages(
+/// Media details operations.
+impl Database {
+    pub async fn save_media_details(
+        &self,
+        message_id: &str,
+        media: &Media,
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT OR REPLACE INTO media_details (message_id, duration_secs, waveform, mime_type,
+                                                  media_key, direct_path, file_length, file_sha256,
+                                                  file_enc_sha256)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ",
+                libsql::params![
+                    message_id,
+                    media.durations_secs.map(i64::from),
+                    media.waveform.clone(),
+                    media.downloadable.as_ref().map(|d| d.mime_type.clone()),
+                    media.downloadable.as_ref().map(|d| d.media_key.clone()),
+                    media.downloadable.as_ref().map(|d| d.direct_path.clone()),
+                    media
+                        .downloadable
+                        .as_ref()
+                        .and_then(|d| i64::try_from(d.file_length).ok()),
+                    media.downloadable.as_ref().map(|d| d.file_sha256.clone()),
+                    media
+                        .downloadable
+                        .as_ref()
+                        .map(|d| d.file_enc_sha256.clone())
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Load what's needed to download the media of a message not fetched yet.
+    pub async fn load_downloadable_media(
         &self,
         chat_jid: &str,
-        limit: u32,
-    ) -> Result<Vec<ChatMessage>, libsql::Error> {
-        let mut rows = self.conn.query(
-            r"
-            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, media_data
-            FROM messages
-            WHERE chat_jid = ?1
-            ORDER BY timestamp DESC
-            LIMIT ?2
+        message_id: &str,
+    ) -> Result<Option<Media>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT m.media_type, d.duration_secs, d.media_key, d.direct_path, d.file_length,
+                   d.file_sha256, d.file_enc_sha256, d.mime_type
+            FROM messages m
+            JOIN media_details d ON d.message_id = m.server_id
+            WHERE m.chat_jid = ?1 AND m.server_id = ?2
             ",
-            libsql::params![chat_jid, limit],
-        ).await?;
+                libsql::params![chat_jid, message_id],
+            )
+            .await?;
 
-        let mut messages = Vec::new();
-        while let Some(row) = rows.next().await? {
-            let media = row.get::<String>(9).map_or(None, |media_type| {
-                row.get::<Vec<u8>>(10).map_or(None, |data| {
-                    let media_type: MediaType = media_type.into();
+        let Some(row) = rows.next().await? else {
+            return Ok(None);
+        };
+        let Some(media_type) = row.get::<Option<String>>(0)? else {
+            return Ok(None);
+        };
+        let r#type = MediaType::from(media_type);
+        let mut media = Media {
+            r#type,
+            mime_type: row
+                .get::<Option<String>>(7)?
+                .unwrap_or_else(|| r#type.guess_mime_type()),
+            durations_secs: row
+                .get::<Option<i64>>(1)?
+                .and_then(|secs| u32::try_from(secs).ok()),
+            ..Default::default()
+        };
+        media.downloadable = downloadable_media(&row, 2, &media)?;
 
-                    Some(Media {
-                        data: Arc::new(data),
-                        r#type: media_type,
-                        mime_type: media_type.guess_mime_type(),
-                        ..Default::default()
-                    })
-                })
-            });
+        Ok(media.downloadable.is_some().then_some(media))
+    }
 
-            messages.push(ChatMessage {
-                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
-                server_id: row.get(1)?,
-                chat_jid: row.get(2)?,
-                sender_jid: row.get(3)?,
-                sender_name: row.get(4).ok(),
+    /// Keep the full media of a message once downloaded, forgetting how to
+    /// download it.
+    pub async fn save_downloaded_media(
+        &self,
+        chat_jid: &str,
+        message_id: &str,
+        data: &[u8],
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                "UPDATE messages SET media_data = ?1 WHERE chat_jid = ?2 AND server_id = ?3",
+                libsql::params![data.to_vec(), chat_jid, message_id],
+            )
+            .await?;
+        self.conn
+            .execute(
+                r"
+            UPDATE media_details
+            SET media_key = NULL, direct_path = NULL, file_length = NULL, file_sha256 = NULL,
+                file_enc_sha256 = NULL
+            WHERE message_id = ?1
+            ",
+                libsql::params![message_id],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Quote operations.
+impl Database {
+    pub async fn save_quote(&self, message_id: &str, quote: &Quote) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT OR REPLACE INTO message_quotes (message_id, quoted_id, sender_jid, sender_name, outgoing, content)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ",
+                libsql::params![
+                    message_id,
+                    quote.id.clone(),
+                    quote.sender_jid.clone(),
+                    quote.sender_name.clone(),
+                    i32::from(quote.outgoing),
+                    quote.content.clone()
+                ],
+            )
+            .await?;
 
-                media,
-                status: MessageStatus::from(row.get::<i32>(7)?),
-                content: row.get(5)?,
-                outgoing: row.get::<i32>(6)? != 0,
-                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
-                reactions: IndexMap::new(),
+        Ok(())
+    }
+}
 
-                db: Arc::new(self.clone()),
-            });
-        }
+/// Link preview operations.
+impl Database {
+    pub async fn save_link_preview(
+        &self,
+        message_id: &str,
+        preview: &LinkPreview,
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT OR REPLACE INTO link_previews (message_id, url, title, description, thumbnail)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ",
+                libsql::params![
+                    message_id,
+                    preview.url.clone(),
+                    preview.title.clone(),
+                    preview.description.clone(),
+                    preview.thumbnail.as_ref().map(|t| t.as_ref().clone())
+                ],
+            )
+            .await?;
 
-        Ok(messages)
+        Ok(())
     }
+}
 
-    /// Load messages after a specific time (newer messages).
-    pub async fn load_messages_after(
+/// Location operations.
+impl Database {
+    pub async fn save_location(
         &self,
-        chat_jid: &str,
-        after_timestamp: i64,
-        limit: u32,
-    ) -> Result<Vec<ChatMessage>, libsql::Error> {
-        let mut rows = self
-            .conn
-            .query(
+        message_id: &str,
+        location: &Location,
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
                 r"
-            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp
-            FROM messages
-            WHERE chat_jid = ?1 AND timestamp > ?2
-            ORDER BY timestamp ASC
-            LIMIT ?3
+            INSERT OR REPLACE INTO locations (message_id, latitude, longitude, name, address, live, thumbnail)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
             ",
-                libsql::params![chat_jid, after_timestamp, limit],
+                libsql::params![
+                    message_id,
+                    location.latitude,
+                    location.longitude,
+                    location.name.clone(),
+                    location.address.clone(),
+                    i32::from(location.live),
+                    location.thumbnail.as_ref().map(|t| t.as_ref().clone())
+                ],
             )
             .await?;
 
-        let mut messages = Vec::new();
-        while let Some(row) = rows.next().await? {
-            messages.push(ChatMessage {
-                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
-                server_id: row.get(1)?,
-                chat_jid: row.get(2)?,
-                sender_jid: row.get(3)?,
-                sender_name: row.get(4).ok(),
-
-                media: None,
-                status: MessageStatus::from(row.get::<i32>(7)?),
-                content: row.get(5)?,
-                outgoing: row.get::<i32>(6)? != 0,
-                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
-                reactions: IndexMap::new(),
+        Ok(())
+    }
+}
 
-                db: Arc::new(self.clone()),
-            });
+/// Contact card operations.
+impl Database {
+    pub async fn save_contact_cards(
+        &self,
+        message_id: &str,
+        cards: &[ContactCard],
+    ) -> Result<(), libsql::Error> {
+        for (position, card) in (0_i64..).zip(cards) {
+            self.conn
+                .execute(
+                    r"
+                INSERT OR REPLACE INTO contact_cards (message_id, position, name, vcard)
+                VALUES (?1, ?2, ?3, ?4)
+                ",
+                    libsql::params![message_id, position, card.name.clone(), card.vcard.clone()],
+                )
+                .await?;
         }
 
-        Ok(messages)
+        Ok(())
     }
+}
 
-    /// Load messages from before a specific time (older messages).
-    pub async fn load_messages_before(
-        &self,
-        chat_jid: &str,
-        before_timestamp: i64,
-        limit: u32,
-    ) -> Result<Vec<ChatMessage>, libsql::Error> {
+/// Input hints of the composer, as last used in a chat.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ComposerHints {
+    /// Whether the emoji picker was used for the last message.
+    pub picker: bool,
+    /// What the last message was typed as.
+    pub purpose: ComposerPurpose,
+}
+
+/// Local per-chat settings.
+#[derive(Clone, Debug, Default)]
+pub struct ChatSettings {
+    /// Wallpaper of the chat, instead of the one set in the preferences.
+    pub wallpaper: Option<Wallpaper>,
+    /// When the history was cleared, older messages aren't synced again.
+    pub cleared_before: Option<DateTime<Utc>>,
+    /// Input hints the composer was last used with.
+    pub composer_hints: ComposerHints,
+    /// Number of messages that couldn't be decrypted yet.
+    pub undecryptable_count: u32,
+}
+
+/// Chat settings operations.
+impl Database {
+    pub async fn load_chat_settings(&self, jid: &str) -> Result<ChatSettings, libsql::Error> {
         let mut rows = self
             .conn
             .query(
                 r"
-            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp
-            FROM messages
-            WHERE chat_jid = ?1 AND timestamp < ?2
-            ORDER BY timestamp DESC
-            LIMIT ?3
+            SELECT composer_emoji, undecryptable_count, wallpaper, cleared_before,
+                   composer_purpose, composer_picker
+            FROM chat_settings
+            WHERE jid = ?1
             ",
-                libsql::params![chat_jid, before_timestamp, limit],
+                [jid],
             )
             .await?;
 
-        let mut messages = Vec::new();
-        while let Some(row) = rows.next().await? {
-            messages.push(ChatMessage {
-                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
-                server_id: row.get(1)?,
-                chat_jid: row.get(2)?,
-                sender_jid: row.get(3)?,
-                sender_name: row.get(4).ok(),
+        if let Some(row) = rows.next().await? {
+            Ok(ChatSettings {
+                composer_hints: ComposerHints {
+                    picker: row.get::<i32>(5)? != 0,
+                    // Only whether it was used for emoji was kept before.
+                    purpose: row
+                        .get::<String>(4)
+                        .ok()
+                        .and_then(|purpose| purpose.parse().ok())
+                        .unwrap_or(if row.get::<i32>(0)? == 0 {
+                            ComposerPurpose::Text
+                        } else {
+                            ComposerPurpose::Emoji
+                        }),
+                },
+                wallpaper: row
+                    .get::<String>(2)
+                    .ok()
+                    .and_then(|wallpaper| wallpaper.parse().ok()),
+                undecryptable_count: row.get::<u32>(1)?,
+                cleared_before: row
+                    .get::<Option<i64>>(3)?
+                    .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0)),
+            })
+        } else {
+            Ok(ChatSettings::default())
+        }
+    }
 
-                media: None,
-                status: MessageStatus::from(row.get::<i32>(7)?),
-                content: row.get(5)?,
-                outgoing: row.get::<i32>(6)? != 0,
-                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
-                reactions: IndexMap::new(),
+    pub async fn save_chat_settings(
+        &self,
+        jid: &str,
+        settings: &ChatSettings,
+    ) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT INTO chat_settings (jid, composer_purpose, composer_picker, wallpaper)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(jid) DO UPDATE SET
+                composer_purpose = excluded.composer_purpose,
+                composer_picker = excluded.composer_picker,
+                wallpaper = excluded.wallpaper
+            ",
+                libsql::params![
+                    jid,
+                    settings.composer_hints.purpose.as_ref(),
+                    i32::from(settings.composer_hints.picker),
+                    settings.wallpaper.as_ref().map(AsRef::<str>::as_ref)
+                ],
+            )
+            .await?;
 
-                db: Arc::new(self.clone()),
-            });
-        }
+        Ok(())
+    }
 
-        Ok(messages)
+    /// Delete the messages of a chat sent up to `before`, and remember it so
+    /// they aren't synced again.
+    pub async fn clear_chat_history(
+        &self,
+        jid: &str,
+        before: DateTime<Utc>,
+    ) -> Result<(), libsql::Error> {
+        // Bookmarks are kept on this device only, so they'd point to nothing
+        // once their messages are gone.
+        self.conn
+            .execute(
+                r"
+            DELETE FROM bookmarks WHERE chat_jid = ?1 AND message_id IN (
+                SELECT server_id FROM messages WHERE chat_jid = ?1 AND timestamp <= ?2
+            )
+            ",
+                libsql::params![jid, before.timestamp()],
+            )
+            .await?;
+        self.conn
+            .execute(
+                "DELETE FROM messages WHERE chat_jid = ?1 AND timestamp <= ?2",
+                libsql::params![jid, before.timestamp()],
+            )
+            .await?;
+        // Messages waiting to be decrypted were sent before the history was
+        // cleared, and would be skipped once they are.
+        self.conn
+            .execute(
+                "DELETE FROM undecryptable_messages WHERE chat_jid = ?1",
+                [jid],
+            )
+            .await?;
+        self.conn
+            .execute(
+                r"
+            INSERT INTO chat_settings (jid, cleared_before)
+            VALUES (?1, ?2)
+            ON CONFLICT(jid) DO UPDATE SET
+                cleared_before = excluded.cleared_before,
+                undecryptable_count = 0
+            ",
+                libsql::params![jid, before.timestamp()],
+            )
+            .await?;
+
+        Ok(())
     }
 
-    pub async fn delete_message(&self, message_id: &str) -> Result<(), libsql::Error> {
+    /// Let the cleared messages of a chat be synced again.
+    pub async fn forget_cleared_history(&self, jid: &str) -> Result<(), libsql::Error> {
         self.conn
-            .execute("DELETE FROM messages WHERE server_id = ?1", [message_id])
+            .execute(
+                "UPDATE chat_settings SET cleared_before = NULL WHERE jid = ?1",
+                [jid],
+            )
             .await?;
 
         Ok(())
     }
 
-    pub async fn get_unread_count(&self, chat_jid: &str) -> Result<usize, libsql::Error> {
-        let mut rows = self
+    /// Count a message that couldn't be decrypted in its chat's settings.
+    /// Returns whether it wasn't counted before.
+    pub async fn add_undecryptable_message(
+        &self,
+        chat_jid: &str,
+        msg_id: &str,
+    ) -> Result<bool, libsql::Error> {
+        let inserted = self
             .conn
-            .query(
-                "SELECT COUNT(*) FROM messages WHERE chat_jid = ?1 AND status != 1 AND outgoing == 0",
-                [chat_jid],
+            .execute(
+                "INSERT OR IGNORE INTO undecryptable_messages (id, chat_jid) VALUES (?1, ?2)",
+                [msg_id, chat_jid],
             )
-            .await?;
+            .await?
+            > 0;
 
-        if let Some(row) = rows.next().await? {
-            Ok(usize::try_from(row.get::<u64>(0)?).unwrap_or(0))
-        } else {
-            Ok(0)
+        if inserted {
+            self.conn
+                .execute(
+                    r"
+                INSERT INTO chat_settings (jid, undecryptable_count)
+                VALUES (?1, 1)
+                ON CONFLICT(jid) DO UPDATE SET
+                    undecryptable_count = undecryptable_count + 1
+                ",
+                    [chat_jid],
+                )
+                .await?;
         }
+
+        Ok(inserted)
     }
 
-    pub async fn get_unread_messages(
+    /// Stop counting a message once it has been decrypted.
+    /// Returns the JID of its chat, if it was counted.
+    pub async fn resolve_undecryptable_message(
         &self,
-        chat_jid: &str,
-    ) -> Result<Vec<ChatMessage>, libsql::Error> {
-        let mut rows = self.conn.query(
-            r"
-            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, media_type, media_data
-            FROM messages
-            WHERE chat_jid = ?1 AND status != 1 AND outgoing == 0
-            ORDER BY timestamp DESC
-            LIMIT ?2
-            ",
-            libsql::params![chat_jid],
-        ).await?;
+        msg_id: &str,
+    ) -> Result<Option<String>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "DELETE FROM undecryptable_messages WHERE id = ?1 RETURNING chat_jid",
+                [msg_id],
+            )
+            .await?;
+        let Some(chat_jid) = rows
+            .next()
+            .await?
+            .map(|row| row.get::<String>(0))
+            .transpose()?
+        else {
+            return Ok(None);
+        };
 
-        let mut messages = Vec::new();
-        while let Some(row) = rows.next().await? {
-            let media = row.get::<String>(9).map_or(None, |media_type| {
-                row.get::<Vec<u8>>(10).map_or(None, |data| {
-                    let media_type: MediaType = media_type.into();
+        self.conn
+            .execute(
+                "UPDATE chat_settings SET undecryptable_count = MAX(undecryptable_count - 1, 0) WHERE jid = ?1",
+                [chat_jid.as_str()],
+            )
+            .await?;
 
-                    Some(Media {
-                        data: Arc::new(data),
-                        r#type: media_type,
-                        mime_type: media_type.guess_mime_type(),
-                        ..Default::default()
-                    })
-                })
-            });
+        Ok(Some(chat_jid))
+    }
+}
 
-            messages.push(ChatMessage {
-                local_id: Uuid::parse_str(row.get_str(0)?).unwrap(),
-                server_id: row.get(1)?,
-                chat_jid: row.get(2)?,
-                sender_jid: row.get(3)?,
-                sender_name: row.get(4).ok(),
+/// Status operations.
+impl Database {
+    pub async fn save_status_update(&self, status: &StatusUpdate) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT OR IGNORE INTO statuses (id, sender_jid, sender_name, content, timestamp, viewed,
+                                            media_type, thumbnail)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ",
+                libsql::params![
+                    status.id.clone(),
+                    status.sender_jid.clone(),
+                    status.sender_name.clone(),
+                    status.content.clone(),
+                    status.timestamp.timestamp(),
+                    i32::from(status.viewed),
+                    status.media.map(|media| format!("{media:?}")),
+                    status.thumbnail.as_deref().cloned()
+                ],
+            )
+            .await?;
 
-                media,
-                status: MessageStatus::from(row.get::<i32>(7)?),
-                content: row.get(5)?,
-                outgoing: row.get::<i32>(6)? != 0,
-                timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
-                reactions: IndexMap::new(),
+        Ok(())
+    }
 
-                db: Arc::new(self.clone()),
+    /// Load the status updates posted after `since`, oldest first.
+    pub async fn load_status_updates(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<StatusUpdate>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                r"
+            SELECT id, sender_jid, sender_name, content, timestamp, viewed, media_type, thumbnail
+            FROM statuses
+            WHERE timestamp > ?1
+            ORDER BY timestamp ASC
+            ",
+                [since.timestamp()],
+            )
+            .await?;
+
+        let mut statuses = Vec::new();
+        while let Some(row) = rows.next().await? {
+            statuses.push(StatusUpdate {
+                id: row.get(0)?,
+                viewed: row.get::<i32>(5)? != 0,
+                content: row.get(3)?,
+                timestamp: DateTime::from_timestamp(row.get::<i64>(4)?, 0).unwrap_or_else(Utc::now),
+                sender_jid: row.get(1)?,
+                sender_name: row.get(2).ok(),
+                media: row.get::<String>(6).ok().map(MediaType::from),
+                thumbnail: row.get::<Vec<u8>>(7).ok().map(Arc::new),
             });
         }
 
-        Ok(messages)
+        Ok(statuses)
+    }
+
+    pub async fn mark_status_update_viewed(&self, id: &str) -> Result<(), libsql::Error> {
+        self.conn
+            .execute("UPDATE statuses SET viewed = 1 WHERE id = ?1", [id])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Delete the status updates posted before `before`.
+    pub async fn delete_status_updates(&self, before: DateTime<Utc>) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                "DELETE FROM statuses WHERE timestamp <= ?1",
+                [before.timestamp()],
+            )
+            .await?;
+
+        Ok(())
     }
 }
 
@@ -735,6 +3306,30 @@ impl Database {
         }
     }
 
+    /// Get the names of the saved contacts among `jids`, or their push names.
+    pub async fn get_contact_names(
+        &self,
+        jids: &[String],
+    ) -> Result<HashMap<String, String>, libsql::Error> {
+        let mut names = HashMap::new();
+        for row in self
+            .query_with_ids(
+                r"
+            SELECT jid, COALESCE(NULLIF(name, ''), NULLIF(push_name, ''))
+            FROM contacts WHERE jid IN ({ids})
+            ",
+                jids,
+            )
+            .await?
+        {
+            if let Ok(name) = row.get::<String>(1) {
+                names.insert(row.get(0)?, name);
+            }
+        }
+
+        Ok(names)
+    }
+
     pub async fn get_all_contacts(&self) -> Result<Vec<Contact>, libsql::Error> {
         let mut rows = self.conn.query(
             "SELECT jid, phone_number, name, push_name, is_registered FROM contacts ORDER BY name",
@@ -756,6 +3351,97 @@ impl Database {
     }
 }
 
+/// A composer snippet, expanded when its trigger is followed by `Tab`.
+#[derive(Clone, Debug)]
+pub struct Snippet {
+    /// Word that expands to the snippet, like `/addr`.
+    pub trigger: String,
+    /// Text the trigger expands to.
+    pub expansion: String,
+}
+
+/// Snippet operations.
+impl Database {
+    pub async fn save_snippet(&self, snippet: &Snippet) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT INTO snippets (shortcut, expansion)
+            VALUES (?1, ?2)
+            ON CONFLICT(shortcut) DO UPDATE SET
+                expansion = excluded.expansion
+            ",
+                [snippet.trigger.as_str(), snippet.expansion.as_str()],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_snippet(&self, trigger: &str) -> Result<(), libsql::Error> {
+        self.conn
+            .execute("DELETE FROM snippets WHERE shortcut = ?1", [trigger])
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn load_snippets(&self) -> Result<Vec<Snippet>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query(
+                "SELECT shortcut, expansion FROM snippets ORDER BY shortcut",
+                (),
+            )
+            .await?;
+
+        let mut snippets = Vec::new();
+        while let Some(row) = rows.next().await? {
+            snippets.push(Snippet {
+                trigger: row.get(0)?,
+                expansion: row.get(1)?,
+            });
+        }
+
+        Ok(snippets)
+    }
+}
+
+/// JID alias operations.
+impl Database {
+    /// Remember that messages addressed to `alias` belong to the chat `jid`.
+    pub async fn save_jid_alias(&self, alias: &str, jid: &str) -> Result<(), libsql::Error> {
+        self.conn
+            .execute(
+                r"
+            INSERT INTO jid_aliases (alias, jid)
+            VALUES (?1, ?2)
+            ON CONFLICT(alias) DO UPDATE SET
+                jid = excluded.jid
+            ",
+                [alias, jid],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Load the alternate JIDs of chats, mapped to the JID of their chat.
+    pub async fn load_jid_aliases(&self) -> Result<HashMap<String, String>, libsql::Error> {
+        let mut rows = self
+            .conn
+            .query("SELECT alias, jid FROM jid_aliases", ())
+            .await?;
+
+        let mut aliases = HashMap::new();
+        while let Some(row) = rows.next().await? {
+            aliases.insert(row.get(0)?, row.get(1)?);
+        }
+
+        Ok(aliases)
+    }
+}
+
 /// Search operations.
 impl Database {
     pub async fn search_contacts(&self, query: &str) -> Result<Vec<Contact>, libsql::Error> {
@@ -799,9 +3485,9 @@ impl Database {
             .conn
             .query(
                 r"
-            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp
+            SELECT local_id, server_id, chat_jid, sender_jid, sender_name, content, outgoing, status, timestamp, revoked, edited, kind
             FROM messages
-            WHERE content LIKE ?1
+            WHERE content LIKE ?1 AND kind != 1
             ORDER BY timestamp DESC
             LIMIT ?2
             ",
@@ -819,9 +3505,17 @@ impl Database {
                 sender_jid: row.get(3)?,
                 sender_name: row.get(4).ok(),
 
+                kind: MessageKind::from(row.get::<i32>(11)?),
+                poll: None,
                 media: None,
+                edited: row.get::<i32>(10)? != 0,
                 status: row.get::<i32>(7)?.into(),
                 content: row.get(5)?,
+                revoked: row.get::<i32>(9)? != 0,
+                contacts: Vec::new(),
+                location: None,
+                quote: None,
+                preview: None,
                 outgoing: row.get::<i32>(6)? != 0,
                 timestamp: DateTime::from_timestamp(row.get::<i64>(8)?, 0).unwrap_or_else(Utc::now),
                 reactions: IndexMap::new(),
@@ -834,3 +3528,866 @@ impl Database {
         Ok(results)
     }
 }
+
+/// Get `count` SQL parameters numbered from `from`, comma-separated.
+fn placeholders(from: usize, count: usize) -> String {
+    (from..from + count)
+        .map(|i| format!("?{i}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Get the SQL condition matching the messages sent by `actor`, with the
+/// JIDs of someone else numbered from `from`.
+fn sent_by(actor: &MessageActor, from: usize) -> String {
+    match actor {
+        MessageActor::User(_) => String::from("outgoing = 1"),
+        MessageActor::Contact(jids) => format!(
+            "(outgoing = 0 AND (chat_jid NOT LIKE '%@g.us' OR sender_jid IN ({})))",
+            placeholders(from, jids.len())
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{block_on, chat, database, message};
+
+    #[test]
+    fn muted_chats_are_left_out_of_the_total_unread_count() {
+        block_on(async {
+            let db = database().await;
+            let mut muted = chat(&db, "muted");
+            muted.muted = true;
+            db.save_chat(&chat(&db, "open")).await.unwrap();
+            db.save_chat(&muted).await.unwrap();
+            db.save_message("open", &message(&db, "open", "alice", "1"))
+                .await
+                .unwrap();
+            db.save_message("muted", &message(&db, "muted", "bob", "2"))
+                .await
+                .unwrap();
+
+            assert_eq!(db.get_total_unread_count(true).await.unwrap(), 2);
+            assert_eq!(db.get_total_unread_count(false).await.unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn clearing_history_drops_what_points_to_its_messages() {
+        block_on(async {
+            let db = database().await;
+            let mut old = message(&db, "chat", "alice", "old");
+            old.timestamp -= chrono::Duration::hours(1);
+            db.save_message("chat", &old).await.unwrap();
+            db.add_bookmark("chat", "old", None).await.unwrap();
+            db.add_undecryptable_message("chat", "pending")
+                .await
+                .unwrap();
+
+            let cleared_at = Utc::now() - chrono::Duration::minutes(1);
+            let new = message(&db, "chat", "alice", "new");
+            db.save_message("chat", &new).await.unwrap();
+            db.add_bookmark("chat", "new", None).await.unwrap();
+
+            db.clear_chat_history("chat", cleared_at).await.unwrap();
+
+            let messages = db.load_messages("chat", 10).await.unwrap();
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].server_id, "new");
+            assert_eq!(db.count_chat_bookmarks("chat").await.unwrap(), 1);
+            let settings = db.load_chat_settings("chat").await.unwrap();
+            assert_eq!(settings.undecryptable_count, 0);
+            assert!(settings.cleared_before.is_some());
+        });
+    }
+
+    #[test]
+    fn links_carry_the_title_of_their_preview() {
+        block_on(async {
+            let db = database().await;
+            let mut msg = message(&db, "chat", "alice", "1");
+            msg.content = "https://gnome.org and https://kde.org".into();
+            msg.preview = Some(LinkPreview {
+                url: "https://gnome.org".into(),
+                title: Some("GNOME".into()),
+                ..Default::default()
+            });
+            db.save_message("chat", &msg).await.unwrap();
+
+            let links = db.load_chat_links("chat", 0, 10).await.unwrap();
+            let title = |url: &str| {
+                links
+                    .iter()
+                    .find(|link| link.url == url)
+                    .and_then(|link| link.title.clone())
+            };
+            assert_eq!(title("https://gnome.org").as_deref(), Some("GNOME"));
+            assert_eq!(title("https://kde.org"), None);
+        });
+    }
+
+    #[test]
+    fn jid_aliases_are_kept_and_replaced() {
+        block_on(async {
+            let db = database().await;
+            db.save_jid_alias("123@lid", "old@s.whatsapp.net")
+                .await
+                .unwrap();
+            db.save_jid_alias("123@lid", "5511999999999@s.whatsapp.net")
+                .await
+                .unwrap();
+            db.save_jid_alias("456@lid", "group@g.us").await.unwrap();
+
+            let aliases = db.load_jid_aliases().await.unwrap();
+            assert_eq!(aliases.len(), 2);
+            assert_eq!(aliases["123@lid"], "5511999999999@s.whatsapp.net");
+            assert_eq!(aliases["456@lid"], "group@g.us");
+        });
+    }
+
+    #[test]
+    fn unread_messages_are_incoming_oldest_first_until_marked_read() {
+        block_on(async {
+            let db = database().await;
+            let mut older = message(&db, "chat", "alice", "older");
+            older.timestamp -= chrono::Duration::minutes(5);
+            let newer = message(&db, "chat", "alice", "newer");
+            let mut outgoing = message(&db, "chat", "me", "outgoing");
+            outgoing.outgoing = true;
+            db.save_message("chat", &newer).await.unwrap();
+            db.save_message("chat", &older).await.unwrap();
+            db.save_message("chat", &outgoing).await.unwrap();
+            db.save_message("other", &message(&db, "other", "bob", "other"))
+                .await
+                .unwrap();
+
+            let unread = db.get_unread_messages("chat").await.unwrap();
+            let ids: Vec<_> = unread.iter().map(|m| m.server_id.as_str()).collect();
+            assert_eq!(ids, ["older", "newer"]);
+
+            db.mark_chat_read("chat").await.unwrap();
+            assert!(db.get_unread_messages("chat").await.unwrap().is_empty());
+            assert_eq!(db.get_unread_count("chat").await.unwrap(), 0);
+            assert_eq!(db.get_unread_count("other").await.unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn messages_are_stored_once_whether_live_or_synced_first() {
+        block_on(async {
+            let db = database().await;
+            let mut older = message(&db, "chat", "alice", "older");
+            older.timestamp -= chrono::Duration::minutes(5);
+            let newer = message(&db, "chat", "alice", "newer");
+            // The copy from the other path has a local ID of its own.
+            let copy = |msg: &ChatMessage| ChatMessage {
+                local_id: Uuid::new_v4(),
+                ..msg.clone()
+            };
+
+            // Received live, then in a history page.
+            db.save_message("chat", &newer).await.unwrap();
+            assert!(!db.save_synced_message("chat", &copy(&newer)).await.unwrap());
+
+            // In a history page, then received live.
+            assert!(db.save_synced_message("chat", &older).await.unwrap());
+            db.save_message("chat", &copy(&older)).await.unwrap();
+
+            let loaded = db.load_messages("chat", 10).await.unwrap();
+            let ids: Vec<_> = loaded.iter().map(|m| m.server_id.as_str()).collect();
+            assert_eq!(ids, ["newer", "older"]);
+            assert_eq!(loaded[1].local_id, older.local_id);
+        });
+    }
+
+    #[test]
+    fn last_read_time_is_that_of_the_newest_read_incoming_message() {
+        block_on(async {
+            let db = database().await;
+            assert_eq!(db.get_last_read_time("chat").await.unwrap(), None);
+
+            let mut read = message(&db, "chat", "alice", "read");
+            read.status = MessageStatus::Read;
+            read.timestamp -= chrono::Duration::minutes(5);
+            let mut sent = message(&db, "chat", "me", "sent");
+            sent.status = MessageStatus::Read;
+            sent.outgoing = true;
+            db.save_message("chat", &read).await.unwrap();
+            db.save_message("chat", &sent).await.unwrap();
+            db.save_message("chat", &message(&db, "chat", "alice", "unread"))
+                .await
+                .unwrap();
+
+            assert_eq!(
+                db.get_last_read_time("chat").await.unwrap(),
+                Some(read.timestamp.timestamp())
+            );
+        });
+    }
+
+    #[test]
+    fn details_are_attached_to_their_messages() {
+        block_on(async {
+            let db = database().await;
+            let mut plain = message(&db, "chat", "alice", "plain");
+            plain.timestamp -= chrono::Duration::seconds(1);
+            let mut reply = message(&db, "chat", "alice", "reply");
+            reply.quote = Some(Quote {
+                id: "plain".into(),
+                sender_jid: "alice".into(),
+                sender_name: None,
+                outgoing: false,
+                content: "Message plain".into(),
+            });
+            reply.poll = Some(Poll {
+                question: "Lunch?".into(),
+                options: vec!["Yes".into(), "No".into()],
+                ..Default::default()
+            });
+            reply.contacts = vec![
+                ContactCard {
+                    name: "Bob".into(),
+                    vcard: "BEGIN:VCARD".into(),
+                },
+                ContactCard {
+                    name: "Carol".into(),
+                    vcard: "BEGIN:VCARD".into(),
+                },
+            ];
+            db.save_message("chat", &plain).await.unwrap();
+            db.save_message("chat", &reply).await.unwrap();
+            db.save_poll_vote("reply", "bob", &["Yes".into()])
+                .await
+                .unwrap();
+
+            let messages = db.load_messages("chat", 10).await.unwrap();
+            let plain = messages.iter().find(|m| m.server_id == "plain").unwrap();
+            let reply = messages.iter().find(|m| m.server_id == "reply").unwrap();
+
+            assert!(plain.quote.is_none() && plain.poll.is_none() && plain.contacts.is_empty());
+            assert_eq!(reply.quote.as_ref().unwrap().id, "plain");
+            let poll = reply.poll.as_ref().unwrap();
+            assert_eq!(poll.options, ["Yes", "No"]);
+            assert_eq!(poll.votes["bob"], ["Yes"]);
+            let names = reply
+                .contacts
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(names, ["Bob", "Carol"]);
+        });
+    }
+
+    #[test]
+    fn details_are_loaded_past_the_parameter_limit() {
+        block_on(async {
+            let db = database().await;
+            let count = MAX_QUERY_PARAMS + 10;
+            for i in 0..count {
+                let mut msg = message(&db, "chat", "alice", &format!("m{i}"));
+                msg.contacts = vec![ContactCard {
+                    name: format!("Contact {i}"),
+                    vcard: String::new(),
+                }];
+                db.save_message("chat", &msg).await.unwrap();
+            }
+
+            let messages = db
+                .load_messages("chat", u32::try_from(count).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(messages.len(), count);
+            assert!(messages.iter().all(|m| m.contacts.len() == 1));
+        });
+    }
+
+    #[test]
+    fn reactions_replace_and_remove() {
+        block_on(async {
+            let db = database().await;
+            db.save_message("chat", &message(&db, "chat", "alice", "1"))
+                .await
+                .unwrap();
+
+            let now = Utc::now();
+            db.save_reaction("1", "bob", Some("👍"), now).await.unwrap();
+            db.save_reaction("1", "carol", Some("👍"), now)
+                .await
+                .unwrap();
+            db.save_reaction("1", "bob", Some("❤️"), now).await.unwrap();
+            let messages = db.load_messages("chat", 10).await.unwrap();
+            assert_eq!(messages[0].reactions["👍"], ["carol"]);
+            assert_eq!(messages[0].reactions["❤️"], ["bob"]);
+
+            db.save_reaction("1", "bob", None, now).await.unwrap();
+            let messages = db.load_messages("chat", 10).await.unwrap();
+            assert!(!messages[0].reactions.contains_key("❤️"));
+        });
+    }
+
+    #[test]
+    fn contact_names_fall_back_to_push_names() {
+        block_on(async {
+            let db = database().await;
+            for (jid, name, push_name) in [
+                ("alice", Some("Alice"), Some("Ali")),
+                ("bob", None, Some("Bobby")),
+                ("carol", None, None),
+            ] {
+                db.save_contact(&Contact {
+                    jid: jid.into(),
+                    name: name.map(Into::into),
+                    push_name: push_name.map(Into::into),
+                    phone_number: None,
+                    is_registered: true,
+                })
+                .await
+                .unwrap();
+            }
+
+            let jids = ["alice", "bob", "carol", "dave"].map(String::from);
+            let names = db.get_contact_names(&jids).await.unwrap();
+            assert_eq!(names.len(), 2);
+            assert_eq!(names["alice"], "Alice");
+            assert_eq!(names["bob"], "Bobby");
+        });
+    }
+
+    #[test]
+    fn revokes_only_apply_to_messages_of_the_revoker() {
+        block_on(async {
+            let db = database().await;
+            let alice = MessageActor::contact("alice@lid", None);
+            let mut own = message(&db, "group@g.us", "me@lid", "own");
+            own.outgoing = true;
+            for msg in [message(&db, "group@g.us", "bob@lid", "bob"), own] {
+                db.save_message("group@g.us", &msg).await.unwrap();
+            }
+            db.save_message("dm@lid", &message(&db, "dm@lid", "dm@lid", "dm"))
+                .await
+                .unwrap();
+
+            let bob = MessageActor::contact("bob:3@lid", None);
+            let other_side = MessageActor::contact("dm@lid", None);
+            let user = MessageActor::User(Vec::new());
+            for (chat_jid, id, actor, allowed) in [
+                ("group@g.us", "bob", &alice, false),
+                ("group@g.us", "own", &alice, false),
+                ("group@g.us", "dm", &other_side, false),
+                ("dm@lid", "dm", &user, false),
+                ("dm@lid", "dm", &other_side, true),
+                ("group@g.us", "bob", &bob, true),
+            ] {
+                let revoked = db.revoke_message(chat_jid, id, actor).await.unwrap();
+                assert_eq!(revoked, allowed, "{id} in {chat_jid}");
+            }
+            let revoked = db
+                .load_message_by_server_id("group@g.us", "bob")
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(revoked.revoked);
+        });
+    }
+
+    #[test]
+    fn group_admins_revoke_messages_of_others() {
+        block_on(async {
+            let db = database().await;
+            let mut own = message(&db, "group@g.us", "me@lid", "own");
+            own.outgoing = true;
+            for msg in [message(&db, "group@g.us", "bob@lid", "bob"), own] {
+                db.save_message("group@g.us", &msg).await.unwrap();
+            }
+            db.set_group_admins("group@g.us", &["alice@lid".into(), "me@lid".into()])
+                .await
+                .unwrap();
+
+            let alice = MessageActor::contact("alice@lid", None);
+            assert!(
+                db.revoke_message("group@g.us", "own", &alice)
+                    .await
+                    .unwrap()
+            );
+            let user = MessageActor::User(vec!["me@lid".into()]);
+            assert!(db.revoke_message("group@g.us", "bob", &user).await.unwrap());
+
+            db.set_group_admins("group@g.us", &[]).await.unwrap();
+            let msg = message(&db, "group@g.us", "bob@lid", "later");
+            db.save_message("group@g.us", &msg).await.unwrap();
+            assert!(
+                !db.revoke_message("group@g.us", "later", &alice)
+                    .await
+                    .unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn join_requests_are_kept_until_resolved_and_go_with_their_chat() {
+        block_on(async {
+            let db = database().await;
+            db.save_chat(&chat(&db, "group@g.us")).await.unwrap();
+            let request = |jid: &str, t: i64| JoinRequest {
+                jid: jid.to_string(),
+                requested_at: DateTime::from_timestamp(t, 0).unwrap(),
+            };
+
+            db.add_join_requests(
+                "group@g.us",
+                &[request("bob@lid", 20), request("eve@lid", 10)],
+            )
+            .await
+            .unwrap();
+            // Asking again keeps the first time they asked.
+            db.add_join_requests("group@g.us", &[request("bob@lid", 30)])
+                .await
+                .unwrap();
+            let requests = db.load_join_requests("group@g.us").await.unwrap();
+            assert_eq!(requests, [request("eve@lid", 10), request("bob@lid", 20)]);
+
+            db.remove_join_requests("group@g.us", &["eve@lid".into()])
+                .await
+                .unwrap();
+            assert_eq!(db.count_join_requests("group@g.us").await.unwrap(), 1);
+
+            db.delete_chat("group@g.us").await.unwrap();
+            assert_eq!(db.count_join_requests("group@g.us").await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn only_listed_admins_are_group_admins() {
+        block_on(async {
+            let db = database().await;
+            db.set_group_admins("group@g.us", &["me@lid".into()])
+                .await
+                .unwrap();
+
+            let user = ["me@s.whatsapp.net".to_string(), "me@lid".to_string()];
+            assert!(db.is_group_admin("group@g.us", &user).await.unwrap());
+            assert!(!db.is_group_admin("other@g.us", &user).await.unwrap());
+            assert!(!db.is_group_admin("group@g.us", &[]).await.unwrap());
+        });
+    }
+
+    #[test]
+    fn edits_only_apply_to_messages_of_the_sender() {
+        block_on(async {
+            let db = database().await;
+            let mut own = message(&db, "group@g.us", "me@lid", "own");
+            own.outgoing = true;
+            for msg in [message(&db, "group@g.us", "bob@lid", "bob"), own] {
+                db.save_message("group@g.us", &msg).await.unwrap();
+            }
+            // Admins may delete messages of others, but never edit them.
+            db.set_group_admins("group@g.us", &["alice@lid".into()])
+                .await
+                .unwrap();
+
+            let alice = MessageActor::contact("alice@lid", None);
+            let bob = MessageActor::contact("bob@lid", None);
+            let user = MessageActor::User(vec!["me@lid".into()]);
+            for (chat_jid, id, actor, allowed) in [
+                ("group@g.us", "bob", &alice, false),
+                ("group@g.us", "bob", &user, false),
+                ("group@g.us", "own", &bob, false),
+                ("other@g.us", "bob", &bob, false),
+                ("group@g.us", "bob", &bob, true),
+                ("group@g.us", "own", &user, true),
+            ] {
+                let edited = db
+                    .edit_message(chat_jid, id, actor, "Edited")
+                    .await
+                    .unwrap();
+                assert_eq!(edited, allowed, "{id} in {chat_jid}");
+            }
+        });
+    }
+
+    #[test]
+    fn message_days_are_local_and_distinct() {
+        block_on(async {
+            let db = database().await;
+            let day = chrono::Duration::days(1);
+            let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+            let times = [start, start + chrono::Duration::minutes(1), start + day * 3];
+            for (i, timestamp) in times.into_iter().enumerate() {
+                let mut msg = message(&db, "chat", "alice", &i.to_string());
+                msg.timestamp = timestamp;
+                db.save_message("chat", &msg).await.unwrap();
+            }
+            let mut later = message(&db, "chat", "alice", "later");
+            later.timestamp = start + day * 10;
+            db.save_message("chat", &later).await.unwrap();
+
+            let end = (start + day * 5).timestamp();
+            let days = db
+                .get_message_days("chat", start.timestamp(), end)
+                .await
+                .unwrap();
+            let local = |t: DateTime<Utc>| t.with_timezone(&chrono::Local).date_naive();
+            assert_eq!(days, [local(start), local(start + day * 3)]);
+        });
+    }
+
+    #[test]
+    fn composer_hints_are_kept_per_chat() {
+        block_on(async {
+            let db = database().await;
+            let hints = ComposerHints {
+                picker: true,
+                purpose: ComposerPurpose::Url,
+            };
+            let settings = ChatSettings {
+                composer_hints: hints,
+                ..Default::default()
+            };
+            db.save_chat_settings("chat", &settings).await.unwrap();
+            let loaded = db.load_chat_settings("chat").await.unwrap();
+            assert_eq!(loaded.composer_hints, hints);
+            let other = db.load_chat_settings("other").await.unwrap();
+            assert_eq!(other.composer_hints, ComposerHints::default());
+
+            // Chats only remembered for emoji keep them.
+            db.conn
+                .execute(
+                    "INSERT INTO chat_settings (jid, composer_emoji) VALUES ('old', 1)",
+                    (),
+                )
+                .await
+                .unwrap();
+            let loaded = db.load_chat_settings("old").await.unwrap();
+            assert_eq!(loaded.composer_hints.purpose, ComposerPurpose::Emoji);
+            assert!(!loaded.composer_hints.picker);
+        });
+    }
+
+    #[test]
+    fn archived_chats_are_loaded_with_their_state() {
+        block_on(async {
+            let db = database().await;
+            let mut archived = chat(&db, "group@g.us");
+            archived.archived = true;
+            archived.muted = true;
+            archived.membership = Membership::Left;
+            db.save_chat(&archived).await.unwrap();
+            db.save_chat(&chat(&db, "alice@lid")).await.unwrap();
+
+            let chats = db.load_chats().await.unwrap();
+            let loaded = chats.iter().find(|c| c.jid == "group@g.us").unwrap();
+            assert_eq!(chats.len(), 2);
+            assert!(loaded.archived && loaded.muted && loaded.has_left());
+
+            let loaded = db.load_chat("group@g.us").await.unwrap().unwrap();
+            assert!(loaded.archived && loaded.muted && loaded.has_left());
+        });
+    }
+
+    #[test]
+    fn expired_pins_are_dropped() {
+        block_on(async {
+            let db = database().await;
+            let now = Utc::now();
+            let hour = chrono::Duration::hours(1);
+            db.pin_message("chat", "forever", now - hour * 3, None)
+                .await
+                .unwrap();
+            db.pin_message("chat", "ended", now - hour * 2, Some(now - hour))
+                .await
+                .unwrap();
+            db.pin_message("chat", "current", now - hour, Some(now + hour))
+                .await
+                .unwrap();
+
+            let pins = db.load_pinned_message_ids("chat", now).await.unwrap();
+            let ids = pins.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>();
+            assert_eq!(ids, ["current", "forever"]);
+            assert_eq!(
+                pins[0].1.map(|t| t.timestamp()),
+                Some((now + hour).timestamp())
+            );
+
+            // Pins ending later are dropped once their time comes.
+            let pins = db
+                .load_pinned_message_ids("chat", now + hour)
+                .await
+                .unwrap();
+            assert_eq!(pins.len(), 1);
+        });
+    }
+
+    /// Count the rows of a table kept for a message.
+    async fn count_rows(db: &Database, table: &str, message_id: &str) -> i64 {
+        let mut rows = db
+            .conn
+            .query(
+                &format!("SELECT COUNT(*) FROM {table} WHERE message_id = ?1"),
+                [message_id],
+            )
+            .await
+            .unwrap();
+
+        rows.next().await.unwrap().unwrap().get(0).unwrap()
+    }
+
+    /// Store a reaction, a link preview and a pin for a message.
+    async fn add_details(db: &Database, chat_jid: &str, message_id: &str) {
+        db.conn
+            .execute(
+                "INSERT INTO reactions (message_id, sender_jid, emoji, timestamp) VALUES (?1, 'bob@lid', '👍', 0)",
+                [message_id],
+            )
+            .await
+            .unwrap();
+        db.save_link_preview(message_id, &LinkPreview::default())
+            .await
+            .unwrap();
+        db.pin_message(chat_jid, message_id, Utc::now(), None)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn deleting_messages_deletes_their_details() {
+        block_on(async {
+            let db = database().await;
+            for id in ["gone", "kept"] {
+                db.save_message("dm@lid", &message(&db, "dm@lid", "dm@lid", id))
+                    .await
+                    .unwrap();
+                add_details(&db, "dm@lid", id).await;
+            }
+
+            db.delete_message("gone").await.unwrap();
+
+            for table in ["reactions", "link_previews", "pinned_messages"] {
+                assert_eq!(count_rows(&db, table, "gone").await, 0, "{table}");
+                assert_eq!(count_rows(&db, table, "kept").await, 1, "{table}");
+            }
+        });
+    }
+
+    #[test]
+    fn deleting_a_chat_deletes_its_messages_and_admins() {
+        block_on(async {
+            let db = database().await;
+            db.save_chat(&chat(&db, "group@g.us")).await.unwrap();
+            db.save_message("group@g.us", &message(&db, "group@g.us", "bob@lid", "1"))
+                .await
+                .unwrap();
+            add_details(&db, "group@g.us", "1").await;
+            db.set_group_admins("group@g.us", &[String::from("bob@lid")])
+                .await
+                .unwrap();
+
+            db.delete_chat("group@g.us").await.unwrap();
+
+            let message = db.load_message_by_server_id("group@g.us", "1").await;
+            assert!(message.unwrap().is_none());
+            assert_eq!(count_rows(&db, "reactions", "1").await, 0);
+            assert_eq!(count_rows(&db, "pinned_messages", "1").await, 0);
+            let mut admins = db
+                .conn
+                .query("SELECT 1 FROM group_admins", ())
+                .await
+                .unwrap();
+            assert!(admins.next().await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn orphans_are_dropped_despite_unsent_messages() {
+        block_on(async {
+            let db = database().await;
+            db.save_chat(&chat(&db, "dm@lid")).await.unwrap();
+            db.save_message("dm@lid", &message(&db, "dm@lid", "dm@lid", "kept"))
+                .await
+                .unwrap();
+            // Messages not sent yet have no server ID, which `NOT IN` can't
+            // tell apart from any other.
+            db.conn
+                .execute(
+                    "INSERT INTO messages (local_id, chat_jid, sender_jid, timestamp) VALUES ('unsent', 'dm@lid', '', 0)",
+                    (),
+                )
+                .await
+                .unwrap();
+            for id in ["kept", "orphan"] {
+                add_details(&db, "dm@lid", id).await;
+            }
+            db.set_group_admins("left@g.us", &[String::from("bob@lid")])
+                .await
+                .unwrap();
+
+            db.drop_orphaned_rows().await.unwrap();
+
+            for table in ["reactions", "link_previews", "pinned_messages"] {
+                assert_eq!(count_rows(&db, table, "orphan").await, 0, "{table}");
+                assert_eq!(count_rows(&db, table, "kept").await, 1, "{table}");
+            }
+            let mut admins = db
+                .conn
+                .query("SELECT 1 FROM group_admins", ())
+                .await
+                .unwrap();
+            assert!(admins.next().await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn images_are_listed_without_their_data() {
+        block_on(async {
+            let db = database().await;
+            let image = |server_id: &str, data: &[u8], age: i64| {
+                let mut msg = message(&db, "chat", "alice", server_id);
+                msg.timestamp -= chrono::Duration::seconds(age);
+                msg.media = Some(Media {
+                    data: Arc::new(data.to_vec()),
+                    r#type: MediaType::Image,
+                    ..Default::default()
+                });
+                msg
+            };
+            for msg in [
+                image("newer", b"png", 1),
+                image("older", b"jpeg", 2),
+                image("pending", b"", 0),
+            ] {
+                db.save_message("chat", &msg).await.unwrap();
+            }
+
+            let images = db.load_media_messages("chat").await.unwrap();
+            let ids: Vec<_> = images.iter().map(|m| m.server_id.as_str()).collect();
+            assert_eq!(ids, ["older", "newer"]);
+            assert!(images.iter().flat_map(|m| &m.media).all(|m| !m.has_data()));
+
+            assert_eq!(
+                db.load_media_data("chat", "older").await.unwrap(),
+                Some(b"jpeg".to_vec())
+            );
+            assert_eq!(db.load_media_data("chat", "pending").await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn media_can_be_downloaded_until_it_has_been() {
+        block_on(async {
+            let db = database().await;
+            let mut msg = message(&db, "chat", "alice", "voice");
+            msg.media = Some(Media {
+                r#type: MediaType::Audio,
+                mime_type: "audio/ogg; codecs=opus".to_string(),
+                durations_secs: Some(7),
+                downloadable: Some(DownloadableMedia {
+                    media_key: b"key".to_vec(),
+                    mime_type: "audio/ogg; codecs=opus".to_string(),
+                    direct_path: "/v/voice".to_string(),
+                    file_length: 2048,
+                    file_sha256: b"sha".to_vec(),
+                    download_type: MediaType::Audio.download_type(),
+                    duration_secs: Some(7),
+                    file_enc_sha256: b"enc".to_vec(),
+                }),
+                ..Default::default()
+            });
+            db.save_message("chat", &msg).await.unwrap();
+
+            let media = db
+                .load_downloadable_media("chat", "voice")
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(media.mime_type, "audio/ogg; codecs=opus");
+            assert_eq!(media.download_size(), Some(2048));
+            let downloadable = media.downloadable.unwrap();
+            assert_eq!(downloadable.direct_path, "/v/voice");
+            assert_eq!(downloadable.file_sha256, b"sha");
+            assert_eq!(downloadable.duration_secs, Some(7));
+
+            let loaded = db.load_messages("chat", 10).await.unwrap();
+            assert!(loaded[0].media.as_ref().unwrap().can_download());
+
+            db.save_downloaded_media("chat", "voice", b"ogg")
+                .await
+                .unwrap();
+            assert!(
+                db.load_downloadable_media("chat", "voice")
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+            let loaded = db.load_messages("chat", 10).await.unwrap();
+            let media = loaded[0].media.as_ref().unwrap();
+            assert!(!media.can_download());
+            assert_eq!(media.data.as_slice(), b"ogg");
+            assert_eq!(media.durations_secs, Some(7));
+        });
+    }
+
+    #[test]
+    fn documents_are_listed_with_their_full_size() {
+        block_on(async {
+            let db = database().await;
+            let mut msg = message(&db, "chat", "alice", "report");
+            msg.content = "report.pdf".to_string();
+            msg.media = Some(Media::from_document_message(
+                &waproto::whatsapp::message::DocumentMessage {
+                    file_name: Some("report.pdf".to_string()),
+                    media_key: Some(b"key".to_vec()),
+                    direct_path: Some("/v/report".to_string()),
+                    file_enc_sha256: Some(b"enc".to_vec()),
+                    file_length: Some(4096),
+                    ..Default::default()
+                },
+            ));
+            db.save_message("chat", &msg).await.unwrap();
+
+            let documents = db.load_chat_documents("chat", 0, 10).await.unwrap();
+            assert_eq!(documents.len(), 1);
+            assert_eq!(documents[0].content, "report.pdf");
+            assert_eq!(documents[0].size, 4096);
+
+            db.save_downloaded_media("chat", "report", b"%PDF")
+                .await
+                .unwrap();
+            let documents = db.load_chat_documents("chat", 0, 10).await.unwrap();
+            assert_eq!(documents[0].size, 4);
+        });
+    }
+
+    #[test]
+    fn status_updates_keep_their_media_until_they_expire() {
+        block_on(async {
+            let db = database().await;
+            let now = Utc::now();
+            let status = |id: &str, age: i64| StatusUpdate {
+                id: id.into(),
+                viewed: false,
+                content: "Beach".into(),
+                timestamp: now - chrono::Duration::hours(age),
+                sender_jid: "alice".into(),
+                sender_name: None,
+                media: Some(MediaType::Image),
+                thumbnail: Some(Arc::new(b"jpeg".to_vec())),
+            };
+            db.save_status_update(&status("old", 30)).await.unwrap();
+            db.save_status_update(&status("new", 1)).await.unwrap();
+
+            db.delete_status_updates(now - StatusUpdate::LIFETIME)
+                .await
+                .unwrap();
+            let statuses = db
+                .load_status_updates(now - chrono::Duration::days(7))
+                .await
+                .unwrap();
+
+            assert_eq!(statuses.len(), 1);
+            assert_eq!(statuses[0].id, "new");
+            assert!(matches!(statuses[0].media, Some(MediaType::Image)));
+            assert_eq!(statuses[0].thumbnail.as_deref(), Some(&b"jpeg".to_vec()));
+        });
+    }
+}
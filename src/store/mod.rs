@@ -1,3 +1,5 @@
 mod database;
 
-pub use database::{Contact, Database};
+pub use database::{
+    Bookmark, ChatSettings, ComposerHints, Contact, Database, JoinRequest, PendingReceipt, Snippet,
+};
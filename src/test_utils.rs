@@ -0,0 +1,85 @@
+//! Helpers shared by the unit tests.
+
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
+use uuid::Uuid;
+
+use crate::{
+    state::{Chat, ChatMessage, Membership, MessageKind, MessageStatus},
+    store::Database,
+};
+
+/// Run a future to completion on a fresh single-threaded runtime.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build test runtime")
+        .block_on(future)
+}
+
+/// Open an empty database kept in memory.
+pub async fn database() -> Arc<Database> {
+    Arc::new(
+        Database::in_memory()
+            .await
+            .expect("Failed to open in-memory database"),
+    )
+}
+
+/// Build a chat with no state.
+pub fn chat(db: &Arc<Database>, jid: &str) -> Chat {
+    Chat {
+        db: Arc::clone(db),
+        jid: jid.to_string(),
+        name: String::new(),
+        muted: false,
+        muted_until: None,
+        pinned: false,
+        archived: false,
+        membership: Membership::Member,
+        available: None,
+        pin_order: None,
+        sort_key: 0,
+        last_seen: None,
+        avatar_path: None,
+        participants: HashMap::new(),
+        name_modified_at: None,
+        flags_modified_at: None,
+        last_message_time: DateTime::UNIX_EPOCH,
+        participant_count: None,
+    }
+}
+
+/// Build a text message received in `chat_jid` from `sender_jid`.
+pub fn message(
+    db: &Arc<Database>,
+    chat_jid: &str,
+    sender_jid: &str,
+    server_id: &str,
+) -> ChatMessage {
+    ChatMessage {
+        db: Arc::clone(db),
+        kind: MessageKind::Regular,
+        poll: None,
+        media: None,
+        quote: None,
+        preview: None,
+        edited: false,
+        status: MessageStatus::Sent,
+        content: format!("Message {server_id}"),
+        revoked: false,
+        chat_jid: chat_jid.to_string(),
+        contacts: Vec::new(),
+        local_id: Uuid::new_v4(),
+        location: None,
+        outgoing: false,
+        reactions: IndexMap::new(),
+        sender_jid: sender_jid.to_string(),
+        server_id: server_id.to_string(),
+        timestamp: Utc::now(),
+        sender_name: None,
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    cell::{Cell, RefCell},
+    sync::Arc,
+};
+
+use glycin::Loader;
+use gtk::{gdk, glib, prelude::*};
+use indexmap::IndexMap;
+use relm4::gtk;
+
+use crate::state::ChatMessage;
+
+/// Most bytes of decoded thumbnails kept in memory.
+const MAX_CACHED_BYTES: usize = 128 * 1024 * 1024;
+
+/// Most bytes decoded by a single prefetch, leaving room in the cache for
+/// the thumbnails on screen.
+const MAX_PREFETCH_BYTES: usize = MAX_CACHED_BYTES / 2;
+
+thread_local! {
+    /// Decoded thumbnails, by the key they were loaded with.
+    static TEXTURES: RefCell<SizedLru<gdk::Texture>> =
+        RefCell::new(SizedLru::new(MAX_CACHED_BYTES));
+    /// Bumped to stop the running prefetch.
+    static PREFETCH_GENERATION: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Values dropped least recently used first once their total size goes
+/// over a budget.
+#[derive(Debug)]
+struct SizedLru<V> {
+    /// Total size of the values.
+    size: usize,
+    /// Largest total size kept.
+    budget: usize,
+    /// Values with their size, least recently used first.
+    entries: IndexMap<String, (V, usize)>,
+}
+
+impl<V: Clone> SizedLru<V> {
+    fn new(budget: usize) -> Self {
+        Self {
+            size: 0,
+            budget,
+            entries: IndexMap::new(),
+        }
+    }
+
+    /// Get a value, marking it as the most recently used.
+    fn get(&mut self, key: &str) -> Option<V> {
+        let index = self.entries.get_index_of(key)?;
+        let last = self.entries.len() - 1;
+        self.entries.move_index(index, last);
+
+        self.entries
+            .get_index(last)
+            .map(|(_, (value, _))| value.clone())
+    }
+
+    /// Insert a value, dropping the least recently used ones over the budget.
+    /// The value just inserted is kept even if it's over the budget alone.
+    fn insert(&mut self, key: String, value: V, size: usize) {
+        if let Some((_, old_size)) = self.entries.shift_remove(&key) {
+            self.size -= old_size;
+        }
+        self.size += size;
+        self.entries.insert(key, (value, size));
+
+        while self.size > self.budget && self.entries.len() > 1 {
+            if let Some((_, (_, size))) = self.entries.shift_remove_index(0) {
+                self.size -= size;
+            }
+        }
+    }
+}
+
+/// Get the key a thumbnail of a message is cached under, changing with its
+/// data as media gets downloaded.
+pub fn key(kind: &str, message: &ChatMessage, data: &[u8]) -> String {
+    format!("{kind}:{}:{}", message.local_id, data.len())
+}
+
+/// Get a thumbnail decoded before, if it's still cached.
+pub fn cached(key: &str) -> Option<gdk::Texture> {
+    TEXTURES.with_borrow_mut(|textures| textures.get(key))
+}
+
+/// Decode a thumbnail off the main thread, caching it under `key`.
+pub async fn load(key: String, data: Arc<Vec<u8>>) -> Option<gdk::Texture> {
+    if let Some(texture) = cached(&key) {
+        return Some(texture);
+    }
+
+    let loader = Loader::new_bytes(glib::Bytes::from(data.as_slice()));
+    let texture = match loader.load().await {
+        Ok(image) => image.next_frame().await.map(|frame| frame.texture()),
+        Err(e) => Err(e),
+    };
+    let texture = texture
+        .inspect_err(|e| tracing::debug!("Failed to decode thumbnail {key}: {e}"))
+        .ok()?;
+
+    let size = texture_size(&texture);
+    TEXTURES.with_borrow_mut(|textures| textures.insert(key, texture.clone(), size));
+
+    Some(texture)
+}
+
+/// Load the stored data of a media message and decode it off the main
+/// thread, for messages loaded without their data.
+pub async fn load_stored(kind: &str, message: &ChatMessage) -> Option<gdk::Texture> {
+    let data = message
+        .db
+        .load_media_data(&message.chat_jid, &message.server_id)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to load media {}: {e}", message.server_id))
+        .ok()??;
+
+    load(key(kind, message, &data), Arc::new(data)).await
+}
+
+/// Decode thumbnails ahead of their rows being shown, in the given order.
+/// Replaces the running prefetch, if any.
+pub fn prefetch(thumbnails: Vec<(String, Arc<Vec<u8>>)>) {
+    cancel_prefetch();
+    let generation = PREFETCH_GENERATION.get();
+
+    relm4::spawn_local(async move {
+        let mut decoded = 0;
+        for (key, data) in thumbnails {
+            if PREFETCH_GENERATION.get() != generation || decoded >= MAX_PREFETCH_BYTES {
+                return;
+            }
+            if cached(&key).is_none()
+                && let Some(texture) = load(key, data).await
+            {
+                decoded += texture_size(&texture);
+            }
+        }
+    });
+}
+
+/// Stop the running prefetch, such as when its chat is closed.
+pub fn cancel_prefetch() {
+    PREFETCH_GENERATION.set(PREFETCH_GENERATION.get().wrapping_add(1));
+}
+
+/// Get roughly how many bytes a texture takes in memory.
+fn texture_size(texture: &gdk::Texture) -> usize {
+    usize::try_from(texture.width()).unwrap_or(0)
+        * usize::try_from(texture.height()).unwrap_or(0)
+        * 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_is_dropped_first() {
+        let mut cache = SizedLru::new(30);
+        cache.insert("a".into(), 1, 10);
+        cache.insert("b".into(), 2, 10);
+        cache.insert("c".into(), 3, 10);
+
+        // Reading "a" makes "b" the least recently used.
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("d".into(), 4, 10);
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+        assert_eq!(cache.get("d"), Some(4));
+        assert_eq!(cache.size, 30);
+    }
+
+    #[test]
+    fn replacing_a_value_updates_the_size() {
+        let mut cache = SizedLru::new(30);
+        cache.insert("a".into(), 1, 10);
+        cache.insert("a".into(), 2, 25);
+
+        assert_eq!(cache.get("a"), Some(2));
+        assert_eq!(cache.size, 25);
+    }
+
+    #[test]
+    fn value_over_the_budget_alone_is_kept() {
+        let mut cache = SizedLru::new(30);
+        cache.insert("a".into(), 1, 10);
+        cache.insert("b".into(), 2, 50);
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(2));
+        assert_eq!(cache.size, 50);
+    }
+}
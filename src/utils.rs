@@ -1,17 +1,25 @@
-use std::error::Error;
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::{Duration, SystemTime},
+};
 
-use chrono::{Datelike, Local, NaiveDate};
+use chrono::{DateTime, Datelike, Local, NaiveDate, SecondsFormat, TimeZone, Utc};
 use fast_qr::{
     ECL, QRBuilder,
     convert::{Builder, Shape, image::ImageBuilder},
 };
 use glib::Bytes;
 use glycin::Loader;
-use gtk::{gdk, glib};
+use gtk::{gdk, gio, glib, pango};
+use regex::Regex;
 use relm4::prelude::*;
 use rlibphonenumber::{PhoneNumber, PhoneNumberFormat};
+use strum::{AsRefStr, EnumString};
 
-use crate::i18n;
+use crate::{config::APP_ID, i18n, state::ChatKind, store::Snippet};
 
 /// Gets only the first name from a full name.
 pub fn get_first_name(name: &str) -> String {
@@ -53,6 +61,213 @@ pub async fn generate_qr_code(data: &str, size: u32) -> Result<gdk::Texture, Box
     Ok(texture)
 }
 
+/// Highest unread count shown in full, on chat badges and in the window title.
+pub const MAX_UNREAD_COUNT: u32 = 999;
+
+/// Formats a count for a badge, capping it at `max` (e.g. "999+").
+pub fn format_badge_count(count: u32, max: u32) -> String {
+    if count > max {
+        format!("{max}+")
+    } else {
+        count.to_string()
+    }
+}
+
+/// Step of the checklist shown to new users above the chats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, AsRefStr, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum OnboardingStep {
+    /// Allow the app to show notifications.
+    AllowNotifications,
+    /// Keep receiving messages with the window closed.
+    EnableBackground,
+    /// Start a chat with a contact.
+    StartChat,
+}
+
+impl OnboardingStep {
+    /// All the steps, in the order they're listed.
+    pub const ALL: [Self; 3] = [
+        Self::AllowNotifications,
+        Self::EnableBackground,
+        Self::StartChat,
+    ];
+
+    /// Check if the step has been completed.
+    pub fn is_completed(self, settings: &gio::Settings) -> bool {
+        settings
+            .strv("onboarding-completed")
+            .iter()
+            .any(|step| step.as_str() == self.as_ref())
+    }
+
+    /// Check off the step, once it's done.
+    pub fn complete(self, settings: &gio::Settings) {
+        if self.is_completed(settings) {
+            return;
+        }
+
+        let mut steps = settings.strv("onboarding-completed");
+        steps.push(self.as_ref().into());
+        if let Err(e) = settings.set_strv("onboarding-completed", steps) {
+            tracing::error!("Failed to check off onboarding step {}: {e}", self.as_ref());
+        }
+    }
+}
+
+/// Keep the checklist shown to new users from those who linked their phone
+/// before it existed. Decided once, on the first start with the checklist.
+pub fn skip_onboarding_if_linked(settings: &gio::Settings, linked: bool) {
+    // Writing the setting, even to its default, marks it as decided.
+    if settings.user_value("onboarding-dismissed").is_some() {
+        return;
+    }
+
+    if linked {
+        tracing::info!("Skipping the onboarding of a phone linked before it");
+    }
+    if let Err(e) = settings.set_boolean("onboarding-dismissed", linked) {
+        tracing::error!("Failed to save whether to show the onboarding: {e}");
+    }
+}
+
+/// How timestamps are written in exports and copies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AsRefStr, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum TimestampMode {
+    /// Local time, with its UTC offset.
+    #[default]
+    Local,
+    /// UTC, as ISO 8601.
+    Utc,
+}
+
+/// Key that sends the message in the composer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AsRefStr, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum SendShortcut {
+    /// `Enter` sends, `Shift+Enter` is left for a new line.
+    #[default]
+    Enter,
+    /// `Ctrl+Enter` sends, `Enter` is left for a new line.
+    CtrlEnter,
+}
+
+impl SendShortcut {
+    /// Checks if `Enter` pressed with `modifiers` sends the message.
+    pub fn sends(self, modifiers: gdk::ModifierType) -> bool {
+        match self {
+            Self::Enter => !modifiers.contains(gdk::ModifierType::SHIFT_MASK),
+            Self::CtrlEnter => modifiers.contains(gdk::ModifierType::CONTROL_MASK),
+        }
+    }
+
+    /// Get the accelerators of the shortcut, as listed in the shortcuts dialog.
+    pub fn accelerators(self) -> &'static [&'static str] {
+        match self {
+            Self::Enter => &["Return"],
+            Self::CtrlEnter => &["<Control>Return"],
+        }
+    }
+
+    /// Get the tooltip of the send button, naming the shortcut.
+    pub fn tooltip(self) -> String {
+        match self {
+            Self::Enter => i18n!("Send (Enter)"),
+            Self::CtrlEnter => i18n!("Send (Ctrl+Enter)"),
+        }
+    }
+}
+
+/// Background of the chat history.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AsRefStr, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Wallpaper {
+    /// Plain background.
+    #[default]
+    Default,
+    /// Subtle pattern drawn over the background.
+    Pattern,
+    /// Image picked in the preferences.
+    Image,
+}
+
+impl Wallpaper {
+    /// Get the style class of the chat view for this wallpaper.
+    pub fn css_class(self) -> &'static str {
+        match self {
+            Self::Default => "",
+            Self::Pattern => "wallpaper-pattern",
+            Self::Image => "wallpaper-image",
+        }
+    }
+}
+
+/// What the composer was last used to type in a chat, to ask input methods
+/// for a matching keyboard.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AsRefStr, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ComposerPurpose {
+    /// Any text.
+    #[default]
+    Text,
+    /// Only emoji.
+    Emoji,
+    /// A link on its own.
+    Url,
+    /// A number on its own.
+    Number,
+}
+
+impl ComposerPurpose {
+    /// Guess what a sent message was typed as.
+    pub fn from_text(text: &str) -> Self {
+        let text = text.trim();
+        if is_emoji_only(text) {
+            Self::Emoji
+        } else if extract_links(text).len() == 1 && !text.contains(char::is_whitespace) {
+            Self::Url
+        } else if text.chars().any(|c| c.is_ascii_digit())
+            && text
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, ' ' | '+' | '-' | '.' | ','))
+        {
+            Self::Number
+        } else {
+            Self::Text
+        }
+    }
+
+    /// Get the input purpose and hints of the composer.
+    pub fn input_hints(self) -> (gtk::InputPurpose, gtk::InputHints) {
+        match self {
+            Self::Text => (gtk::InputPurpose::FreeForm, gtk::InputHints::NONE),
+            Self::Emoji => (gtk::InputPurpose::FreeForm, gtk::InputHints::EMOJI),
+            Self::Url => (gtk::InputPurpose::Url, gtk::InputHints::NO_SPELLCHECK),
+            Self::Number => (gtk::InputPurpose::Number, gtk::InputHints::NONE),
+        }
+    }
+}
+
+/// Formats a timestamp for exports and copies.
+///
+/// Local times keep their UTC offset, so times repeated when clocks go back
+/// stay unambiguous.
+pub fn format_export_timestamp(dt: DateTime<Utc>, mode: TimestampMode) -> String {
+    match mode {
+        TimestampMode::Local => format_zoned_timestamp(&dt.with_timezone(&Local)),
+        TimestampMode::Utc => dt.to_rfc3339_opts(SecondsFormat::Secs, true),
+    }
+}
+
+/// Formats a timestamp in the time zone it's in, followed by its UTC offset.
+fn format_zoned_timestamp<Tz: TimeZone>(dt: &DateTime<Tz>) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    dt.format("%Y-%m-%d %H:%M:%S %:z").to_string()
+}
+
 /// Formats a date into a human-readable label for date separators.
 pub fn format_date_label(date: NaiveDate) -> String {
     let today = Local::now().date_naive();
@@ -67,12 +282,36 @@ pub fn format_date_label(date: NaiveDate) -> String {
         return i18n!("Yesterday");
     }
 
+    format_date(date)
+}
+
+/// Formats a date with its month name, and its year if it isn't this one.
+pub fn format_date(date: NaiveDate) -> String {
+    let today = Local::now().date_naive();
+
     // Same year: "February 23", different year: "February 23, 2024".
-    if date.year() == today.year() {
-        date.format("%B %-e").to_string()
+    // Formatted by GLib so month names and day order follow the user's locale.
+    let format = if date.year() == today.year() {
+        // Translators: date separator format for this year, see `g_date_time_format`.
+        i18n!("%B %-e")
     } else {
-        date.format("%B %-e, %Y").to_string()
-    }
+        // Translators: date separator format for other years, see `g_date_time_format`.
+        i18n!("%B %-e, %Y")
+    };
+
+    glib::DateTime::from_local(
+        date.year(),
+        date.month().cast_signed(),
+        date.day().cast_signed(),
+        0,
+        0,
+        0.0,
+    )
+    .and_then(|d| d.format(&format))
+    .map_or_else(
+        |_| date.format("%B %-e, %Y").to_string(),
+        |label| label.to_string(),
+    )
 }
 
 /// Formats a LID as international phone number.
@@ -86,7 +325,593 @@ pub fn format_lid_as_number(lid: &str) -> String {
     })
 }
 
+/// Formats a chat JID for display: as phone number for private chats, and
+/// as is for any other kind, which has no phone number.
+pub fn format_jid(jid: &str) -> String {
+    if ChatKind::from_jid(jid).is_private() {
+        format_lid_as_number(jid)
+    } else {
+        jid.to_string()
+    }
+}
+
 /// Extracts phone number from JID/LID.
 pub fn extract_phone_from_jid(jid: &str) -> String {
     format!("+{}", jid.split('@').next().unwrap_or(jid))
 }
+
+/// Expands the snippet whose trigger is the whole word ending at `cursor` (in chars).
+/// Returns the expanded text and the cursor position after the expansion.
+pub fn expand_snippet(text: &str, cursor: usize, snippets: &[Snippet]) -> Option<(String, usize)> {
+    let split = text
+        .char_indices()
+        .nth(cursor)
+        .map_or(text.len(), |(index, _)| index);
+    let (before, after) = text.split_at(split);
+
+    // The trigger must be followed by a word boundary too.
+    if after.starts_with(|c: char| !c.is_whitespace()) {
+        return None;
+    }
+
+    let word = before.rsplit(char::is_whitespace).next()?;
+    let snippet = snippets
+        .iter()
+        .find(|s| !word.is_empty() && s.trigger == word)?;
+    let prefix = &before[..before.len() - word.len()];
+
+    Some((
+        format!("{prefix}{}{after}", snippet.expansion),
+        prefix.chars().count() + snippet.expansion.chars().count(),
+    ))
+}
+
+/// Matches web links in message text, with or without a scheme.
+static LINK_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\b(?:https?://|www\.)[^\s<>"]+"#).unwrap());
+
+/// Extracts the web links in a text, with `https://` added to those without a scheme.
+pub fn extract_links(text: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    for found in LINK_REGEX.find_iter(text) {
+        // Punctuation ending a sentence isn't part of the link.
+        let link = found
+            .as_str()
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'', '"']);
+        let link = if link.to_lowercase().starts_with("www.") {
+            format!("https://{link}")
+        } else {
+            link.to_string()
+        };
+
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+/// Most emoji a message can have to be shown large, as the official apps do.
+const MAX_JUMBO_EMOJI: usize = 3;
+
+/// Checks if a text consists only of emoji (and whitespace).
+pub fn is_emoji_only(text: &str) -> bool {
+    emoji_count(text).is_some()
+}
+
+/// Checks if a text is made of few enough emoji to show them large.
+pub fn is_jumbo_emoji(text: &str) -> bool {
+    emoji_count(text).is_some_and(|count| count <= MAX_JUMBO_EMOJI)
+}
+
+/// Counts the emoji of a text made only of emoji (and whitespace), or `None`
+/// if it has anything else. Sequences joined by ZWJ, with a skin tone, flags
+/// and keycaps count as a single emoji.
+fn emoji_count(text: &str) -> Option<usize> {
+    let mut chars = text.trim().chars().peekable();
+    chars.peek()?;
+
+    let mut count = 0;
+    // Whether the next emoji is joined to the previous one by a ZWJ.
+    let mut joined = false;
+    // Whether a flag is missing its second regional indicator.
+    let mut open_flag = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{200D}' if count > 0 => {
+                joined = true;
+                continue;
+            }
+            // Variation selectors, skin tones and tags modify the emoji before them.
+            '\u{FE0E}' | '\u{FE0F}' | '\u{1F3FB}'..='\u{1F3FF}' | '\u{E0020}'..='\u{E007F}'
+                if count > 0 => {}
+            // Flags are pairs of regional indicators.
+            '\u{1F1E6}'..='\u{1F1FF}' => {
+                if !open_flag && !joined {
+                    count += 1;
+                }
+                open_flag = !open_flag;
+                joined = false;
+                continue;
+            }
+            // Keycaps are a digit, `#` or `*` with the keycap mark.
+            '0'..='9' | '#' | '*' => {
+                chars.next_if_eq(&'\u{FE0F}');
+                chars.next_if_eq(&'\u{20E3}')?;
+                if !joined {
+                    count += 1;
+                }
+            }
+            c if c.is_whitespace() => {}
+            c if is_emoji(c) => {
+                // Symbols shown as text by default, like © or ↔, are only
+                // emoji when asked to be, or with a skin tone.
+                let presented = has_emoji_presentation(c)
+                    || chars
+                        .peek()
+                        .is_some_and(|next| matches!(next, '\u{FE0F}' | '\u{1F3FB}'..='\u{1F3FF}'));
+                if chars.peek() == Some(&'\u{FE0E}') || !(presented || joined) {
+                    return None;
+                }
+                if !joined {
+                    count += 1;
+                }
+            }
+            _ => return None,
+        }
+        joined = false;
+        open_flag = false;
+    }
+
+    Some(count)
+}
+
+/// Checks if a character is an emoji, by the Unicode blocks holding them.
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00A9}'
+            | '\u{00AE}'
+            | '\u{203C}'
+            | '\u{2049}'
+            | '\u{2122}'
+            | '\u{2139}'
+            | '\u{2194}'..='\u{21AA}'
+            | '\u{231A}'..='\u{23FF}'
+            | '\u{24C2}'
+            | '\u{25AA}'..='\u{25FE}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2934}'..='\u{2935}'
+            | '\u{2B05}'..='\u{2B55}'
+            | '\u{3030}'
+            | '\u{303D}'
+            | '\u{3297}'
+            | '\u{3299}'
+            | '\u{1F000}'..='\u{1FAFF}'
+    )
+}
+
+/// Checks if an emoji is shown as such by default, rather than as text. Those
+/// outside the Basic Multilingual Plane are taken as all being so.
+fn has_emoji_presentation(c: char) -> bool {
+    matches!(
+        c,
+        '\u{231A}'..='\u{231B}'
+            | '\u{23E9}'..='\u{23EC}'
+            | '\u{23F0}'
+            | '\u{23F3}'
+            | '\u{25FD}'..='\u{25FE}'
+            | '\u{2614}'..='\u{2615}'
+            | '\u{2648}'..='\u{2653}'
+            | '\u{267F}'
+            | '\u{2693}'
+            | '\u{26A1}'
+            | '\u{26AA}'..='\u{26AB}'
+            | '\u{26BD}'..='\u{26BE}'
+            | '\u{26C4}'..='\u{26C5}'
+            | '\u{26CE}'
+            | '\u{26D4}'
+            | '\u{26EA}'
+            | '\u{26F2}'..='\u{26F3}'
+            | '\u{26F5}'
+            | '\u{26FA}'
+            | '\u{26FD}'
+            | '\u{2705}'
+            | '\u{270A}'..='\u{270B}'
+            | '\u{2728}'
+            | '\u{274C}'
+            | '\u{274E}'
+            | '\u{2753}'..='\u{2755}'
+            | '\u{2757}'
+            | '\u{2795}'..='\u{2797}'
+            | '\u{27B0}'
+            | '\u{27BF}'
+            | '\u{2B1B}'..='\u{2B1C}'
+            | '\u{2B50}'
+            | '\u{2B55}'
+            | '\u{1F000}'..='\u{1FAFF}'
+    )
+}
+
+/// Gets the direction of a text from its first strong character, falling back
+/// to the default one for text without any (like digits or emoji).
+pub fn text_direction(text: &str) -> gtk::TextDirection {
+    match pango::find_base_dir(text) {
+        pango::Direction::Rtl => gtk::TextDirection::Rtl,
+        pango::Direction::Ltr => gtk::TextDirection::Ltr,
+        _ => gtk::TextDirection::None,
+    }
+}
+
+/// Gets how many bytes are free on the filesystem containing `path`.
+pub fn available_space(path: &Path) -> Option<u64> {
+    gio::File::for_path(path)
+        .query_filesystem_info(gio::FILE_ATTRIBUTE_FILESYSTEM_FREE, gio::Cancellable::NONE)
+        .inspect_err(|e| tracing::warn!("Failed to get free space of {}: {e}", path.display()))
+        .ok()
+        .map(|info| info.attribute_uint64(gio::FILE_ATTRIBUTE_FILESYSTEM_FREE))
+}
+
+/// Checks if the filesystem containing `path` has less free space than
+/// the threshold set in the preferences, which 0 turns off.
+pub fn is_low_on_space(path: &Path) -> bool {
+    let threshold_mb = gio::Settings::new(APP_ID).uint("low-disk-space-threshold");
+    let threshold = u64::from(threshold_mb) * 1024 * 1024;
+
+    threshold > 0 && available_space(path).is_some_and(|free| free < threshold)
+}
+
+/// Gets the directory media is written to for other apps to open it.
+pub fn media_cache_dir() -> PathBuf {
+    glib::user_cache_dir().join("papo").join("media")
+}
+
+/// Delete the files in `dir` last changed more than `max_age` ago, giving
+/// how many were deleted.
+pub fn remove_stale_files(dir: &Path, max_age: Duration) -> io::Result<usize> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .unwrap_or_default();
+
+        if metadata.is_file() && age > max_age {
+            fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Attachment types that can run code when opened, as (extension, MIME type) pairs.
+const RISKY_ATTACHMENT_TYPES: &[(&str, &str)] = &[
+    ("apk", "application/vnd.android.package-archive"),
+    ("appimage", "application/vnd.appimage"),
+    ("bat", "application/x-bat"),
+    ("cmd", "application/x-cmd"),
+    ("deb", "application/vnd.debian.binary-package"),
+    ("desktop", "application/x-desktop"),
+    ("exe", "application/x-msdownload"),
+    ("exe", "application/vnd.microsoft.portable-executable"),
+    ("flatpakref", "application/vnd.flatpak.ref"),
+    ("jar", "application/java-archive"),
+    ("js", "text/javascript"),
+    ("js", "application/javascript"),
+    ("msi", "application/x-msi"),
+    ("ps1", "application/x-powershell"),
+    ("py", "text/x-python"),
+    ("rpm", "application/x-rpm"),
+    ("scr", "application/x-msdownload"),
+    ("sh", "application/x-shellscript"),
+    ("vbs", "text/vbscript"),
+];
+
+/// Gets the type (extension) of an attachment that can run code when opened, if it's one.
+pub fn risky_attachment_type(mime_type: &str, file_name: &str) -> Option<&'static str> {
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase());
+
+    RISKY_ATTACHMENT_TYPES
+        .iter()
+        .find(|(ext, mime)| {
+            mime_type.eq_ignore_ascii_case(mime) || extension.as_deref() == Some(ext)
+        })
+        .map(|(ext, _)| *ext)
+}
+
+/// Icons set by name in the code, checked at startup in development builds.
+const USED_ICONS: &[&str] = &[
+    "pin-symbolic",
+    "menu-symbolic",
+    "paper-symbolic",
+    "reply-symbolic",
+    "go-next-symbolic",
+    "send-to-symbolic",
+    "starred-symbolic",
+    "clock-alt-symbolic",
+    "edit-copy-symbolic",
+    "speaker-0-symbolic",
+    "view-more-symbolic",
+    "chain-link-symbolic",
+    "down-small-symbolic",
+    "face-smile-symbolic",
+    "user-trash-symbolic",
+    "go-previous-symbolic",
+    "image-round-symbolic",
+    "paper-plane-symbolic",
+    "info-outline-symbolic",
+    "mail-forward-symbolic",
+    "window-close-symbolic",
+    "document-save-symbolic",
+    "eye-not-looking-symbolic",
+    "phonelink-setup-symbolic",
+    "exclamation-mark-symbolic",
+    "adw-external-link-symbolic",
+    "chat-bubbles-text-symbolic",
+    "chat-bubbles-empty-symbolic",
+    "phone-right-facing-symbolic",
+    "check-round-outline-symbolic",
+    "check-round-outline2-symbolic",
+    "media-playback-pause-symbolic",
+    "media-playback-start-symbolic",
+    "notifications-disabled-symbolic",
+];
+
+/// Checks if the icon theme can resolve an icon.
+fn has_icon(name: &str) -> bool {
+    gdk::Display::default()
+        .is_some_and(|display| gtk::IconTheme::for_display(&display).has_icon(name))
+}
+
+/// Gets `name` if the icon theme has it, or `fallback` otherwise.
+pub fn icon_or_fallback<'a>(name: &'a str, fallback: &'a str) -> &'a str {
+    if has_icon(name) {
+        name
+    } else {
+        tracing::warn!("Icon {name} not found, using {fallback}");
+        fallback
+    }
+}
+
+/// Logs the icons used in the code that the icon theme can't resolve,
+/// meaning they are missing from the bundle in `build.rs`.
+pub fn check_icons() {
+    for name in USED_ICONS.iter().filter(|name| !has_icon(name)) {
+        tracing::error!("Icon {name} can't be resolved, bundle it in build.rs or drop it");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+
+    use super::*;
+
+    /// Get the source files of the app, nested ones included.
+    fn source_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(source_files(&path));
+            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn icons_are_set_through_the_fallback_and_listed() {
+        let icon = Regex::new(r#"icon_or_fallback\(\s*"([a-z0-9-]+)""#).unwrap();
+        let literal = Regex::new(r#"icon_name(: |\()(Some\()?""#).unwrap();
+
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for path in source_files(&src) {
+            let code = fs::read_to_string(&path).unwrap();
+            assert!(
+                !literal.is_match(&code),
+                "{} sets an icon without a fallback",
+                path.display()
+            );
+            for name in icon.captures_iter(&code).map(|c| c[1].to_string()) {
+                assert!(
+                    USED_ICONS.contains(&name.as_str()),
+                    "{name} in {} is missing from USED_ICONS",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn emoji_sequences_count_once() {
+        assert_eq!(emoji_count("😀"), Some(1));
+        assert_eq!(emoji_count(" 😀 😀  "), Some(2));
+        // Flags, keycaps, skin tones and ZWJ sequences.
+        assert_eq!(emoji_count("🇧🇷🇵🇹"), Some(2));
+        assert_eq!(emoji_count("1️⃣#⃣"), Some(2));
+        assert_eq!(emoji_count("👍🏽"), Some(1));
+        assert_eq!(emoji_count("👩‍👩‍👧‍👦"), Some(1));
+        assert_eq!(emoji_count("🏳️‍🌈❤️"), Some(2));
+        assert_eq!(emoji_count("👩‍⚕️"), Some(1));
+    }
+
+    #[test]
+    fn emoji_count_rejects_other_text() {
+        assert_eq!(emoji_count(""), None);
+        assert_eq!(emoji_count("   "), None);
+        assert_eq!(emoji_count("hi 😀"), None);
+        assert_eq!(emoji_count("😀!"), None);
+        assert_eq!(emoji_count("1"), None);
+        assert_eq!(emoji_count("12"), None);
+    }
+
+    #[test]
+    fn text_symbols_are_emoji_only_when_asked() {
+        assert_eq!(emoji_count("©"), None);
+        assert_eq!(emoji_count("™"), None);
+        assert_eq!(emoji_count("↔"), None);
+        assert_eq!(emoji_count("©️"), Some(1));
+        assert_eq!(emoji_count("↔️"), Some(1));
+        assert_eq!(emoji_count("❤"), None);
+        assert_eq!(emoji_count("❤️"), Some(1));
+        assert_eq!(emoji_count("☝🏻"), Some(1));
+        // Emoji shown as such by default, unless asked to be text.
+        assert_eq!(emoji_count("⭐"), Some(1));
+        assert_eq!(emoji_count("⌚\u{FE0E}"), None);
+    }
+
+    #[test]
+    fn composer_purpose_follows_what_was_sent() {
+        assert_eq!(ComposerPurpose::from_text("😀 👍🏽"), ComposerPurpose::Emoji);
+        assert_eq!(
+            ComposerPurpose::from_text(" www.example.com/a "),
+            ComposerPurpose::Url
+        );
+        assert_eq!(
+            ComposerPurpose::from_text("+55 11 99999-0000"),
+            ComposerPurpose::Number
+        );
+        assert_eq!(
+            ComposerPurpose::from_text("see www.example.com"),
+            ComposerPurpose::Text
+        );
+        assert_eq!(ComposerPurpose::from_text("1st"), ComposerPurpose::Text);
+        assert_eq!(ComposerPurpose::from_text("- ,"), ComposerPurpose::Text);
+    }
+
+    #[test]
+    fn few_emoji_are_shown_large() {
+        assert!(is_jumbo_emoji("😀😀😀"));
+        assert!(is_jumbo_emoji("🇧🇷👩‍👩‍👧‍👦1️⃣"));
+        assert!(!is_jumbo_emoji("😀😀😀😀"));
+        assert!(!is_jumbo_emoji("ok 😀"));
+        assert!(is_emoji_only("😀😀😀😀"));
+    }
+
+    #[test]
+    fn only_stale_files_are_removed() {
+        let dir = std::env::temp_dir().join(format!("papo-stale-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let old = dir.join("old.jpg");
+        let new = dir.join("new.jpg");
+        fs::write(&old, b"old").unwrap();
+        fs::write(&new, b"new").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60))
+            .unwrap();
+
+        let removed = remove_stale_files(&dir, Duration::from_secs(24 * 60 * 60)).unwrap();
+
+        assert_eq!(removed, 1);
+        assert!(!old.exists());
+        assert!(new.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_directory_has_nothing_to_remove() {
+        let dir = std::env::temp_dir().join("papo-stale-missing");
+
+        assert_eq!(remove_stale_files(&dir, Duration::ZERO).unwrap(), 0);
+    }
+
+    #[test]
+    fn risky_attachments_are_caught_by_type_or_extension() {
+        assert_eq!(
+            risky_attachment_type("application/vnd.android.package-archive", "app"),
+            Some("apk")
+        );
+        assert_eq!(
+            risky_attachment_type("APPLICATION/X-SHELLSCRIPT", "run"),
+            Some("sh")
+        );
+        assert_eq!(
+            risky_attachment_type("application/octet-stream", "Setup.EXE"),
+            Some("exe")
+        );
+        assert_eq!(
+            risky_attachment_type("application/pdf", "invoice.pdf.js"),
+            Some("js")
+        );
+
+        assert_eq!(
+            risky_attachment_type("application/pdf", "invoice.pdf"),
+            None
+        );
+        assert_eq!(risky_attachment_type("image/jpeg", "photo.jpg"), None);
+        assert_eq!(risky_attachment_type("text/plain", "sh"), None);
+        assert_eq!(risky_attachment_type("", ""), None);
+    }
+
+    #[test]
+    fn only_private_jids_are_shown_as_numbers() {
+        assert!(format_jid("5511999990000@s.whatsapp.net").starts_with("+55"));
+        assert!(format_jid("5511999990000@lid").starts_with('+'));
+        assert_eq!(format_jid("123-456@g.us"), "123-456@g.us");
+        assert_eq!(format_jid("123@newsletter"), "123@newsletter");
+        assert_eq!(format_jid("123@bot"), "123@bot");
+    }
+
+    #[test]
+    fn export_timestamps_stay_unambiguous_across_dst() {
+        // Clocks in Central Europe went back from 03:00 to 02:00 that night,
+        // so 02:30 happened twice, an hour apart.
+        let summer = DateTime::parse_from_rfc3339("2024-10-27T00:30:00Z").unwrap();
+        let winter = DateTime::parse_from_rfc3339("2024-10-27T01:30:00Z").unwrap();
+        let cest = FixedOffset::east_opt(2 * 3600).unwrap();
+        let cet = FixedOffset::east_opt(3600).unwrap();
+
+        assert_eq!(
+            format_zoned_timestamp(&summer.with_timezone(&cest)),
+            "2024-10-27 02:30:00 +02:00"
+        );
+        assert_eq!(
+            format_zoned_timestamp(&winter.with_timezone(&cet)),
+            "2024-10-27 02:30:00 +01:00"
+        );
+
+        let summer = summer.with_timezone(&Utc);
+        let winter = winter.with_timezone(&Utc);
+        assert_eq!(
+            format_export_timestamp(summer, TimestampMode::Utc),
+            "2024-10-27T00:30:00Z"
+        );
+        assert_eq!(
+            format_export_timestamp(winter, TimestampMode::Utc),
+            "2024-10-27T01:30:00Z"
+        );
+        assert_ne!(
+            format_export_timestamp(summer, TimestampMode::Local),
+            format_export_timestamp(winter, TimestampMode::Local)
+        );
+    }
+
+    #[test]
+    fn text_follows_its_first_strong_character() {
+        assert_eq!(text_direction("مرحبا"), gtk::TextDirection::Rtl);
+        assert_eq!(text_direction("שלום, hello"), gtk::TextDirection::Rtl);
+        assert_eq!(text_direction("123 مرحبا"), gtk::TextDirection::Rtl);
+        assert_eq!(text_direction("Hello, שלום"), gtk::TextDirection::Ltr);
+        assert_eq!(text_direction("😀 12:30"), gtk::TextDirection::None);
+        assert_eq!(text_direction(""), gtk::TextDirection::None);
+    }
+}